@@ -0,0 +1,55 @@
+// Meters the log-average luminance of a viewport's accumulated radiance for auto exposure. Each
+// workgroup averages the log2 luminance of its pixels in shared memory, then adds its mean to
+// the metering buffer in fixed point, as floats can't be added atomically.
+
+const WORKGROUP_SIZE: u32 = 256u;
+const PIXELS_PER_INVOCATION: u32 = 16u;
+// Match METERING_LOG_OFFSET and METERING_FIXED_POINT in exposure.rs. Luminances are metered
+// between 2^-16 and 2^16, which keeps the sum of a 4K view's workgroups well within a u32.
+const LOG_OFFSET: f32 = 16.0;
+const FIXED_POINT: f32 = 1024.0;
+
+struct Metering {
+    sum: atomic<u32>,
+    count: atomic<u32>,
+}
+
+@group(0) @binding(0) var<storage, read> accumulation: array<vec4<f32>>;
+@group(0) @binding(1) var<storage, read_write> metering: Metering;
+
+var<workgroup> sums: array<f32, WORKGROUP_SIZE>;
+var<workgroup> counts: array<u32, WORKGROUP_SIZE>;
+
+@compute @workgroup_size(256, 1, 1)
+fn meter_main(@builtin(workgroup_id) group: vec3<u32>, @builtin(local_invocation_index) local: u32) {
+    let pixels: u32 = arrayLength(&accumulation);
+    let first: u32 = group.x * WORKGROUP_SIZE * PIXELS_PER_INVOCATION;
+
+    // Neighbouring invocations read neighbouring pixels
+    var sum: f32 = 0.0;
+    var count: u32 = 0u;
+    for (var i: u32 = 0u; i < PIXELS_PER_INVOCATION; i++) {
+        let index: u32 = first + i * WORKGROUP_SIZE + local;
+        if (index < pixels) {
+            let luminance: f32 = dot(accumulation[index].xyz, vec3<f32>(0.2126, 0.7152, 0.0722));
+            sum += clamp(log2(max(luminance, 1e-6)) + LOG_OFFSET, 0.0, 2.0 * LOG_OFFSET);
+            count += 1u;
+        }
+    }
+    sums[local] = sum;
+    counts[local] = count;
+    workgroupBarrier();
+
+    for (var stride: u32 = WORKGROUP_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if (local < stride) {
+            sums[local] += sums[local + stride];
+            counts[local] += counts[local + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if (local == 0u && counts[0] > 0u) {
+        atomicAdd(&metering.sum, u32(sums[0] / f32(counts[0]) * FIXED_POINT));
+        atomicAdd(&metering.count, 1u);
+    }
+}
@@ -0,0 +1,57 @@
+// Blends the main window's last converged frame, reprojected to the current camera, over the
+// samples traced since the camera moved, so the view fades into the new render instead of
+// popping to a single noisy sample. Runs after the kernel and writes the color buffer again.
+
+// Laid out like fade_uniform_data in frame_fade.rs
+struct Fade {
+    historyOrigin: vec3<f32>,
+    weight: f32, // Of the kept frame, falls to 0 over the fade
+    historyLowerLeftCorner: vec3<f32>,
+    exposure: f32,
+    historyHorizontal: vec3<f32>,
+    historyVertical: vec3<f32>,
+    cameraOrigin: vec3<f32>,
+    cameraLowerLeftCorner: vec3<f32>,
+    cameraHorizontal: vec3<f32>,
+    cameraVertical: vec3<f32>,
+}
+
+@group(0) @binding(0) var<storage, read> history: array<vec4<f32>>;
+@group(0) @binding(1) var<storage, read> accumulation: array<vec4<f32>>;
+@group(0) @binding(2) var color_buffer: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3) var<uniform> fade: Fade;
+
+@compute @workgroup_size(8, 8, 1)
+fn fade_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size: vec2<u32> = textureDimensions(color_buffer);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    var color: vec3<f32> = accumulation[id.y * size.x + id.x].xyz;
+
+    // Pixels the kept frame doesn't show, like those turned into view, only have the new samples
+    let uv: vec2<f32> = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size);
+    let direction: vec3<f32> = fade.cameraLowerLeftCorner + uv.x * fade.cameraHorizontal + uv.y * fade.cameraVertical - fade.cameraOrigin;
+    let historyUv: vec2<f32> = reproject(direction);
+    if (all(historyUv >= vec2<f32>(0.0)) && all(historyUv < vec2<f32>(1.0))) {
+        let pixel: vec2<u32> = min(vec2<u32>(historyUv * vec2<f32>(size)), size - 1u);
+        color = mix(color, history[pixel.y * size.x + pixel.x].xyz, fade.weight);
+    }
+    textureStore(color_buffer, vec2<i32>(id.xy), vec4<f32>(color * fade.exposure, 1.0));
+}
+
+// Where a direction from the camera crosses the kept frame's image plane, as that frame's uv.
+// Directions it doesn't show get -1.
+fn reproject(direction: vec3<f32>) -> vec2<f32> {
+    let toPlane: vec3<f32> = fade.historyLowerLeftCorner - fade.historyOrigin;
+    let normal: vec3<f32> = cross(fade.historyHorizontal, fade.historyVertical);
+    let facing: f32 = dot(direction, normal);
+    if (facing * dot(toPlane, normal) <= 0.0) {
+        return vec2<f32>(-1.0);
+    }
+    let onPlane: vec3<f32> = direction * (dot(toPlane, normal) / facing) - toPlane;
+    return vec2<f32>(
+        dot(onPlane, fade.historyHorizontal) / dot(fade.historyHorizontal, fade.historyHorizontal),
+        dot(onPlane, fade.historyVertical) / dot(fade.historyVertical, fade.historyVertical)
+    );
+}
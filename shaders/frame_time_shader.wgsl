@@ -0,0 +1,66 @@
+// Scrolling graph of the last frames' CPU and GPU times, drawn in the bottom left corner
+
+const HISTORY: u32 = 240u; // Matches FRAME_TIME_HISTORY
+
+struct FrameTimes {
+    cpu: array<vec4<f32>, 60>, // Milliseconds per frame, four frames a vector, oldest first
+    gpu: array<vec4<f32>, 60>, // Negative for frames the GPU hasn't reported yet
+    count: f32, // Frames recorded, up to HISTORY
+    scale: f32, // Milliseconds at the top of the graph
+    padding: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> times: FrameTimes;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle covering the screen, with uv running from the top left corner
+@vertex
+fn vert_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    output.uv = uv;
+    return output;
+}
+
+const GRAPH_MIN: vec2<f32> = vec2<f32>(0.01, 0.76);
+const GRAPH_MAX: vec2<f32> = vec2<f32>(0.41, 0.98);
+const BUDGET_60_FPS: f32 = 16.667;
+const BUDGET_30_FPS: f32 = 33.333;
+
+@fragment
+fn frag_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    // Milliseconds a pixel of the graph spans, taken before discarding so the derivative is defined
+    let pixel_ms = fwidth(input.uv.y) / (GRAPH_MAX.y - GRAPH_MIN.y) * times.scale;
+    if (any(input.uv < GRAPH_MIN) || any(input.uv > GRAPH_MAX)) {
+        discard;
+    }
+
+    let local = (input.uv - GRAPH_MIN) / (GRAPH_MAX - GRAPH_MIN);
+    let ms = (1.0 - local.y) * times.scale;
+    var color = vec3<f32>(0.05, 0.05, 0.05);
+    if (abs(ms - BUDGET_60_FPS) < pixel_ms * 0.5 || abs(ms - BUDGET_30_FPS) < pixel_ms * 0.5) {
+        color = vec3<f32>(0.35, 0.35, 0.35);
+    }
+
+    // The newest frame is at the right edge, older ones scroll off to the left
+    let column = min(u32(local.x * f32(HISTORY)), HISTORY - 1u);
+    let first = HISTORY - u32(times.count);
+    if (column >= first) {
+        let i = column - first;
+        let cpu = times.cpu[i / 4u][i % 4u];
+        let gpu = times.gpu[i / 4u][i % 4u];
+        if (ms <= cpu) {
+            color = vec3<f32>(0.9, 0.55, 0.15);
+        }
+        if (gpu >= 0.0 && abs(ms - gpu) < pixel_ms) {
+            color = vec3<f32>(0.3, 0.8, 1.0);
+        }
+    }
+    return vec4<f32>(color, 1.0);
+}
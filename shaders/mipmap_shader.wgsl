@@ -0,0 +1,29 @@
+// Draws each mip level by averaging 2x2 texels of the level above it across a fullscreen triangle
+@group(0) @binding(0) var source_level : texture_2d<f32>;
+
+@vertex
+fn vert_main(@builtin(vertex_index) VertexIndex : u32) -> @builtin(position) vec4<f32> {
+
+    // One triangle covering the whole target, the parts outside it are clipped
+    var positions = array<vec2<f32>, 3>(
+        vec2<f32>(-1.0,  1.0),
+        vec2<f32>( 3.0,  1.0),
+        vec2<f32>(-1.0, -3.0)
+    );
+
+    return vec4<f32>(positions[VertexIndex], 0.0, 1.0);
+}
+
+@fragment
+fn frag_main(@builtin(position) Position : vec4<f32>) -> @location(0) vec4<f32> {
+    // Odd sized levels repeat their last row or column
+    let last: vec2<i32> = vec2<i32>(textureDimensions(source_level)) - 1;
+    let corner: vec2<i32> = 2 * vec2<i32>(Position.xy);
+
+    var sum: vec4<f32> = vec4<f32>(0.0);
+    for (var i: i32 = 0; i < 4; i++) {
+        let texel: vec2<i32> = min(corner + vec2<i32>(i % 2, i / 2), last);
+        sum += textureLoad(source_level, texel, 0);
+    }
+    return sum * 0.25;
+}
@@ -0,0 +1,37 @@
+// Progress bar drawn over the placeholder frame while a scene loads in the background
+
+struct Progress {
+    fraction: f32, // Share of the loading that is done, from 0 to 1
+    padding: vec3<f32>,
+}
+
+@group(0) @binding(0) var<uniform> progress: Progress;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle covering the screen, with uv running from the top left corner
+@vertex
+fn vert_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    output.uv = uv;
+    return output;
+}
+
+const BAR_MIN: vec2<f32> = vec2<f32>(0.25, 0.48);
+const BAR_MAX: vec2<f32> = vec2<f32>(0.75, 0.52);
+
+@fragment
+fn frag_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    if (any(input.uv < BAR_MIN) || any(input.uv > BAR_MAX)) {
+        discard;
+    }
+
+    let filled = (input.uv.x - BAR_MIN.x) / (BAR_MAX.x - BAR_MIN.x) < progress.fraction;
+    return select(vec4<f32>(0.1, 0.1, 0.1, 1.0), vec4<f32>(0.8, 0.8, 0.8, 1.0), filled);
+}
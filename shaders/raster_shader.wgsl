@@ -0,0 +1,67 @@
+// Draws the scene's triangles straight from the object buffer, one triangle per three vertices,
+// writing the index of the one in front at each pixel. The kernel starts its camera rays from
+// that triangle instead of walking the BVH for them.
+
+// Matches GeometricPrimitive and Material in raytracer_kernel.wgsl
+struct GeometricPrimitive {
+    data_type: f32,
+    material: f32,
+    data: array<f32, 16>,
+    layers: u32,
+}
+
+struct Material {
+    flags: f32,
+    filmThickness: f32,
+    filmIor: f32,
+    alphaCutoff: f32,
+    albedoTexture: f32,
+    roughness: f32,
+    metalness: f32,
+    transparency: f32,
+    ior: f32,
+    dispersion: f32,
+    padding_a: f32,
+    padding_b: f32,
+}
+
+// Laid out like raster_uniform_data in raster.rs
+struct Raster {
+    clip: mat4x4<f32>,
+    renderMask: u32,
+}
+
+struct Fragment {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) visible: u32, // Index of the triangle plus 1, 0 is left where none is
+}
+
+@group(0) @binding(0) var<storage, read> objects: array<GeometricPrimitive>;
+@group(0) @binding(1) var<storage, read> materials: array<Material>;
+@group(0) @binding(2) var<uniform> raster: Raster;
+
+@vertex
+fn raster_vertex(@builtin(vertex_index) vertex: u32) -> Fragment {
+    let index: u32 = vertex / 3u;
+    var fragment: Fragment;
+    fragment.visible = index + 1u;
+
+    // Other primitives, hidden triangles and see-through ones all land outside the view. The
+    // kernel finds those by walking the BVH as before.
+    let primitive: ptr<storage, GeometricPrimitive, read> = &objects[index];
+    if ((*primitive).data_type != 1.0 || ((*primitive).layers & raster.renderMask) == 0u
+        || materials[u32((*primitive).material)].transparency > 0.0) {
+        fragment.position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        return fragment;
+    }
+    // The corners follow the corner normals and colors, see decode_triangle in the kernel
+    let first: u32 = 7u + 3u * (vertex % 3u);
+    let corner: vec3<f32> = vec3<f32>((*primitive).data[first], (*primitive).data[first + 1u], (*primitive).data[first + 2u]);
+    fragment.position = raster.clip * vec4<f32>(corner, 1.0);
+    return fragment;
+}
+
+@fragment
+fn raster_fragment(fragment: Fragment) -> @location(0) u32 {
+    return fragment.visible;
+}
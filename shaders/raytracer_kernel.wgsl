@@ -2,6 +2,10 @@ struct Sphere {
     center: vec3<f32>,
     radius: f32,
     color: vec3<f32>,
+    textureKind: f32, // 0 solid, 1 image, 2 checker
+    texture: f32, // Index into textureRegions
+    checkerColor: vec3<f32>,
+    checkerScale: f32,
 }
 
 struct Triangle {
@@ -9,11 +13,75 @@ struct Triangle {
     corner_b: vec3<f32>,
     corner_c: vec3<f32>,
     color: vec3<f32>,
+    vertex_colors: vec3<f32>, // sRGB of each corner packed as r + 256 g + 65536 b, negative without them
+    normals: f32, // Index of the first of the corner normals in points, negative for flat shading
+}
+
+struct Quad {
+    corner: vec3<f32>,
+    edge_u: vec3<f32>,
+    edge_v: vec3<f32>,
+    color: vec3<f32>,
+}
+
+struct Billboard {
+    center: vec3<f32>,
+    halfWidth: f32,
+    color: vec3<f32>,
+    halfHeight: f32,
+    texture: f32, // Index into textureRegions
+    uvMin: vec2<f32>,
+    uvMax: vec2<f32>,
+}
+
+// Neighbouring points of a point cloud, stored in the point buffer
+struct PointCluster {
+    first: u32, // Index of the first point in points
+    count: u32,
+    radius: f32,
+    color: vec3<f32>, // Tint multiplied with each point's color
+}
+
+// Cubic Bezier hair strand swept by a circle
+struct Curve {
+    p0: vec3<f32>,
+    p1: vec3<f32>,
+    p2: vec3<f32>,
+    p3: vec3<f32>,
+    radius: f32,
+    color: vec3<f32>,
 }
 
 struct GeometricPrimitive {
-    data_type: f32, // 0 for sphere, 1 for triangle
+    data_type: f32, // 0 for sphere, 1 for triangle, 2 for quad, 3 for billboard, 4 for point cluster, 5 for curve, 6 for instance
+    material: f32, // Index into the material buffer
     data: array<f32, 16>, // Encoded data for both types
+    layers: u32, // Bit per layer the object is on, 0 when hidden
+}
+
+struct Material {
+    flags: f32, // Bit 0: cull backfaces, bit 1: two-sided shading, bit 2: diffuse, bit 3: hair
+    filmThickness: f32, // Thin film thickness in nanometers, 0 without a film
+    filmIor: f32,
+    alphaCutoff: f32, // Hits where the albedo alpha is lower are skipped
+    albedoTexture: f32, // Index into textureRegions, -1 without one
+    roughness: f32, // Radius of the jitter added to mirror reflections
+    metalness: f32, // Chance a diffuse material reflects like a mirror instead
+    transparency: f32, // Chance a ray passes through as if it missed
+    ior: f32, // Index of refraction of glass, 0 for opaque surfaces
+    dispersion: f32, // Cauchy coefficient in square micrometers, used in spectral mode
+    padding_a: f32,
+    padding_b: f32,
+}
+
+// Where a texture sits in objectTextures
+struct TextureRegion {
+    offset: vec2<f32>, // UV of the top left corner within the layer
+    scale: vec2<f32>, // UV size, 1 when the texture fills the layer
+    layer: f32,
+    maxLod: f32, // Deeper mip levels would mix in neighbouring textures
+    padding_a: f32,
+    padding_b: f32,
 }
 
 struct Node {
@@ -21,6 +89,8 @@ struct Node {
     leftChild: f32,
     maxCorner: vec3<f32>,
     objectCount: f32,
+    skip: f32, // Node to continue with once this one is missed or done, -1 past the last
+    splitAxis: f32,
 }
 
 struct BVH {
@@ -38,11 +108,72 @@ struct Ray {
 
 struct SceneData {
     cameraOrigin: vec3<f32>,
+    projection: f32, // How pixels map to camera rays, see FrameConstants
     lowerLeftCorner: vec3<f32>,
+    projectionA: f32,
     horizontal: vec3<f32>,
+    projectionB: f32,
     vertical: vec3<f32>,
-    maxBounces: f32,
+    maxSpecularBounces: f32,
     objectCount: f32,
+    writeAovs: f32,
+    frameIndex: f32,
+    stacklessTraversal: f32,
+    renderMask: u32, // Layers to draw
+    maxDiffuseBounces: f32,
+    skyYaw: f32, // Radians around the y axis
+    skyIntensity: f32,
+    portalCount: f32,
+    spectral: f32, // Trace wavelengths instead of RGB
+    maxRadiance: f32, // Brightest a sample may be, 0 without a limit
+    roughnessRegularization: f32, // Least roughness of reflections after a diffuse bounce
+    outlierRejection: f32,
+    caustics: f32, // Gather the photon map at matte surfaces
+    photonRadius: f32, // Size of a photon grid cell
+    bidirectional: f32, // Connect matte hits to a light subpath from the sky
+    blockSize: f32, // Pixels along each side sharing one sample, more than 1 while the view is changing
+    bvhHeatmap: f32, // Color pixels by how expensive their camera ray is to trace instead of shading them
+    exposure: f32, // Scales the radiance shown in the color buffer, the accumulation stays as traced
+    rasterPrimary: f32, // Start camera rays from the visibility texture, see trace_rasterized
+    portals: array<Portal, 4>, // Matches MAX_PORTALS in scene.rs
+    probeOrigin: vec3<f32>, // First probe of the grid, see ProbeGrid::uniform_data
+    probeSpacing: vec3<f32>,
+    probeCounts: vec3<f32>, // Probes along each axis, 0 without a grid
+}
+
+// Camera and sample of a dispatch. The renderer pushes them as push constants where the device
+// has them, which leaves the scene uniform unchanged between frames; otherwise they are copied
+// from the scene at the start of each entry point.
+struct FrameConstants {
+    cameraOrigin: vec3<f32>,
+    frameIndex: f32,
+    lowerLeftCorner: vec3<f32>,
+    projection: f32, // Matches Projection::uniform_data in camera.rs, as do the next two
+    horizontal: vec3<f32>,
+    projectionA: f32, // Eye separation of the stereo projection
+    vertical: vec3<f32>,
+    projectionB: f32, // Convergence distance of the stereo projection
+}
+
+// Opening the sky shines through, spanned by two edges from a corner
+struct Portal {
+    corner: vec3<f32>,
+    edge_u: vec3<f32>,
+    edge_v: vec3<f32>,
+}
+
+struct AovSample {
+    albedo: vec3<f32>,
+    objectId: f32,
+    normal: vec3<f32>,
+    depth: f32,
+}
+
+// Matte surface a light subpath reached, carrying the light that arrived there
+struct LightVertex {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    power: vec3<f32>, // Reflected light, with the area of the emitting disk folded in
 }
 
 struct RenderState {
@@ -52,6 +183,9 @@ struct RenderState {
     position: vec3<f32>,
     normal: vec3<f32>,
     front_face: bool,
+    objectId: f32,
+    material: f32,
+    tangent: vec3<f32>, // Direction of a hit curve, zero on other surfaces
 }
 
 @group(0) @binding(0) var color_buffer: texture_storage_2d<rgba8unorm, write>;
@@ -61,25 +195,579 @@ struct RenderState {
 @group(0) @binding(4) var<storage, read> objectLookup: ObjectIndices;
 @group(0) @binding(5) var skyMaterial: texture_cube<f32>;
 @group(0) @binding(6) var skySampler: sampler;
+@group(0) @binding(7) var<storage, read_write> aovs: array<AovSample>;
+@group(0) @binding(8) var<storage, read_write> accumulation: array<vec4<f32>>;
+@group(0) @binding(9) var objectTextures: texture_2d_array<f32>;
+@group(0) @binding(10) var objectTextureSampler: sampler;
+@group(0) @binding(11) var<storage, read> materials: array<Material>;
+@group(0) @binding(12) var<storage, read> textureRegions: array<TextureRegion>;
+// Position bits and the packed sRGB color of each point
+@group(0) @binding(13) var<storage, read> points: array<vec4<u32>>;
+// Hash grid of caustic photons, four words per cell: red, green and blue in fixed point and a count
+@group(0) @binding(14) var<storage, read_write> photons: array<atomic<u32>>;
+// Work done since the renderer last read the counters, a low and a high word per counter:
+// primary, secondary and shadow rays, BVH node visits and triangle tests
+@group(0) @binding(15) var<storage, read_write> stats: array<atomic<u32>>;
+// Light of the probe grid as linear spherical harmonics, PROBE_TEXELS texels side by side per probe
+// and a row per y and z. Renderers without probes bind a single texel.
+@group(0) @binding(16) var probes: texture_2d<f32>;
+// Index plus 1 of the triangle the raster pass found in front at each pixel, 0 where it found none.
+// Renderers without the raster pass bind a single texel.
+@group(0) @binding(17) var visibility: texture_2d<u32>;
+// Written by the probe pass, then copied over probes
+@group(1) @binding(0) var probeTarget: texture_storage_2d<rgba16float, write>;
+
+// Random number generator state for the current invocation
+var<private> rngState: u32;
+
+var<private> frame: FrameConstants;
+
+// Pixel of the visibility texture the next camera ray starts from, negative to trace it as usual
+var<private> rasterTexel: vec2<i32> = vec2<i32>(-1);
+
+// Counted by each invocation, then summed over its workgroup before going to the stats buffer
+const STAT_COUNTERS: u32 = 5; // Matches STAT_COUNTERS in ray_stats.rs
+var<private> tracedRays: u32;
+var<private> shadowRays: u32;
+var<private> nodeVisits: u32;
+var<private> triangleTests: u32;
+var<workgroup> workgroupStats: array<atomic<u32>, STAT_COUNTERS>;
+
+// Ray cone used to pick texture mip levels: the footprint of a pixel grows by coneSpread per
+// unit travelled, starting at coneWidth where the current ray leaves its origin
+var<private> coneSpread: f32;
+var<private> coneWidth: f32;
+
+// Frames averaged before outlier rejection trusts the average, and how much brighter a sample may be
+const OUTLIER_WARMUP_FRAMES: f32 = 8.0;
+const OUTLIER_RATIO: f32 = 4.0;
+
+// Photons traced each frame, matches PHOTON_WORKGROUPS in renderer.rs
+const PHOTONS_PER_FRAME: f32 = 16384.0;
+// Photon power is summed in fixed point with this many steps per unit
+const PHOTON_SCALE: f32 = 256.0;
+
+// Matte vertices a light subpath keeps for bidirectional connections
+const MAX_LIGHT_VERTICES: u32 = 3;
+// Connections closer than this are treated as this far apart, the inverse square would blow up
+const MIN_CONNECTION_DISTANCE: f32 = 0.1;
+var<private> lightVertices: array<LightVertex, MAX_LIGHT_VERTICES>;
+var<private> lightVertexCount: u32;
+
+// The BVH heat map has the photon pass trace camera rays on a grid over the image, this many
+// along each side, and count how many cost each number of node visits and triangle tests in the
+// photon buffer. Costs from HEATMAP_BINS - 1 up share the last count.
+const HEATMAP_GRID: u32 = 128u; // Invocations of the photon pass along each side
+const HEATMAP_BINS: u32 = 1024u;
 
+// Probe atlas layout and updates, see probes.rs
+const PROBE_TEXELS: u32 = 4u; // Matches PROBE_TEXELS in probes.rs
+const PROBE_RAYS: u32 = 32u; // Paths each probe traces per frame
+const PROBE_HYSTERESIS: f32 = 0.97; // Share of its light a settled probe keeps per frame
+const SH_CONSTANT: f32 = 0.282095;
+const SH_LINEAR: f32 = 0.488603;
+
+// Straight pieces a curve is cut into for intersection
+const CURVE_SEGMENTS: u32 = 8;
+
+// Secondary rays start off the surface, so only hits this close to the origin are treated as self-hits
+const RAY_T_MIN: f32 = 0.00001;
+
+// The renderer swaps this workgroup size for the one benchmarked fastest on the adapter
 @compute @workgroup_size(8,8,1)
-fn main(@builtin(global_invocation_id) GlobalInvocationID : vec3<u32>) {
+fn main(@builtin(global_invocation_id) GlobalInvocationID : vec3<u32>, @builtin(local_invocation_index) localIndex: u32) {
+    frame = frame_from_scene();
     let screen_size: vec2<i32> = vec2<i32>(textureDimensions(color_buffer));
-    let screen_pos : vec2<i32> = vec2<i32>(i32(GlobalInvocationID.x), i32(GlobalInvocationID.y));
+    // Each invocation traces one sample for a block of pixels, a single pixel unless the view is changing
+    let block: i32 = i32(scene.blockSize);
+    let block_pos : vec2<i32> = block * vec2<i32>(i32(GlobalInvocationID.x), i32(GlobalInvocationID.y));
 
-    // Calculate screen position
+    // Calculate screen position, at the middle of the block
     let uv: vec2<f32> = vec2<f32>(
-        (f32(GlobalInvocationID.x) + 0.5) / f32(screen_size.x),
-        (f32(GlobalInvocationID.y) + 0.5) / f32(screen_size.y)
+        (f32(block_pos.x) + 0.5 * f32(block)) / f32(screen_size.x),
+        (f32(block_pos.y) + 0.5 * f32(block)) / f32(screen_size.y)
     );
     
-    var myRay: Ray;
-    myRay.direction = normalize((scene.lowerLeftCorner + uv.x * scene.horizontal + uv.y * scene.vertical) - scene.cameraOrigin);
-    myRay.origin = scene.cameraOrigin;
+    // Seeded per pixel and frame, so accumulated frames average different diffuse paths
+    rngState = GlobalInvocationID.x * 1973u + GlobalInvocationID.y * 9277u + u32(frame.frameIndex) * 26699u;
+
+    // Angle one pixel covers, the image plane sits one unit in front of the camera or spans angles.
+    // Orthographic rays run parallel, so their cone doesn't widen.
+    coneSpread = select(length(frame.vertical) / f32(screen_size.y), 0.0, u32(frame.projection) == 4u);
+
+    let myRay: Ray = camera_ray(uv);
+    // The raster pass only draws through perspective cameras, at the size of the view
+    let center: vec2<i32> = min(block_pos + block / 2, screen_size - 1);
+    if (scene.rasterPrimary > 0.0 && u32(frame.projection) == 0u && all(vec2<i32>(textureDimensions(visibility)) == screen_size)) {
+        rasterTexel = center;
+    }
+
+    var pixel_color : vec3<f32>;
+    if (all(myRay.direction == vec3<f32>(0.0))) {
+        // Outside the circle a fisheye sees
+        pixel_color = vec3<f32>(0.0);
+    } else if (scene.bvhHeatmap > 0.0) {
+        pixel_color = heat_color(cost_percentile(traversal_cost(myRay)));
+    } else {
+        pixel_color = rayColor(myRay);
+        if (scene.maxRadiance > 0.0 && luminance(pixel_color) > scene.maxRadiance) {
+            pixel_color *= scene.maxRadiance / luminance(pixel_color);
+        }
+    }
+
+    // The sample is copied to every pixel of the block, which upscales the image by repeating it
+    for (var dy: i32 = 0; dy < block; dy++) {
+        for (var dx: i32 = 0; dx < block; dx++) {
+            let screen_pos: vec2<i32> = block_pos + vec2<i32>(dx, dy);
+            if (screen_pos.x >= screen_size.x || screen_pos.y >= screen_size.y) {
+                continue;
+            }
+            let pixel_index: u32 = u32(screen_pos.y * screen_size.x + screen_pos.x);
+            accumulate_sample(screen_pos, pixel_index, pixel_color);
+
+            if (scene.writeAovs > 0.0) {
+                write_aovs(myRay, pixel_index);
+            }
+        }
+    }
+    flush_stats(localIndex, 1u);
+}
+
+// Adds the invocation's counts to the stats buffer, with one atomic per counter and workgroup
+// as every invocation adding to the same words would serialize them
+fn flush_stats(localIndex: u32, primaryRays: u32) {
+    atomicAdd(&workgroupStats[0], primaryRays);
+    atomicAdd(&workgroupStats[1], tracedRays - primaryRays - shadowRays);
+    atomicAdd(&workgroupStats[2], shadowRays);
+    atomicAdd(&workgroupStats[3], nodeVisits);
+    atomicAdd(&workgroupStats[4], triangleTests);
+    workgroupBarrier();
+    if (localIndex == 0u) {
+        for (var i: u32 = 0u; i < STAT_COUNTERS; i++) {
+            add_stat(i, atomicLoad(&workgroupStats[i]));
+        }
+    }
+}
+
+// Adds to a 64-bit counter, carrying into the high word when the low one wraps
+fn add_stat(counter: u32, amount: u32) {
+    let low: u32 = atomicAdd(&stats[2u * counter], amount);
+    if (low > 0xffffffffu - amount) {
+        atomicAdd(&stats[2u * counter + 1u], 1u);
+    }
+}
+
+// The camera's ray through a point of the image, uv going from 0 to 1 across it. The panoramic
+// projections' image planes span angles around the camera, see Camera::update_camera. Points a
+// fisheye doesn't see get a ray without a direction.
+fn camera_ray(uv: vec2<f32>) -> Ray {
+    let onPlane: vec3<f32> = frame.lowerLeftCorner + uv.x * frame.horizontal + uv.y * frame.vertical - frame.cameraOrigin;
+    let right: vec3<f32> = normalize(frame.horizontal);
+    let up: vec3<f32> = normalize(frame.vertical);
+    let forward: vec3<f32> = cross(up, right);
+    let x: f32 = dot(onPlane, right);
+    let y: f32 = dot(onPlane, up);
+
+    var ray: Ray;
+    ray.origin = frame.cameraOrigin;
+    switch (u32(frame.projection)) {
+        // Equirectangular, x is the longitude and y the latitude
+        case 1u: {
+            ray.direction = cos(y) * (sin(x) * right + cos(x) * forward) + sin(y) * up;
+        }
+        // Fisheye, the distance from the center is the angle from the view direction
+        case 2u: {
+            let angle: f32 = length(vec2<f32>(x, y));
+            ray.direction = select((x * right + y * up) * sin(angle) / max(angle, 1e-6) + cos(angle) * forward, vec3<f32>(0.0), angle > 3.14159265);
+        }
+        // Stereo, each half of the image plane is moved to the middle for its eye, which looks at
+        // where the middle ray hits the plane of convergence
+        case 3u: {
+            let eye: f32 = select(-1.0, 1.0, x > 0.0);
+            let eyeX: f32 = x - eye * length(frame.horizontal) * 0.25;
+            let converged: vec3<f32> = (eyeX * right + y * up + forward) * frame.projectionB;
+            let offset: vec3<f32> = right * eye * frame.projectionA * 0.5;
+            ray.origin += offset;
+            ray.direction = normalize(converged - offset);
+        }
+        // Orthographic, the image plane sits on the camera and every ray leaves it straight ahead
+        case 4u: {
+            ray.origin += onPlane;
+            ray.direction = forward;
+        }
+        default: {
+            ray.direction = normalize(onPlane);
+        }
+    }
+    return ray;
+}
+
+fn frame_from_scene() -> FrameConstants {
+    return FrameConstants(scene.cameraOrigin, scene.frameIndex, scene.lowerLeftCorner, scene.projection, scene.horizontal, scene.projectionA, scene.vertical, scene.projectionB);
+}
+
+// Adds a sample to a pixel's running average and shows the average so far
+fn accumulate_sample(screen_pos: vec2<i32>, pixel_index: u32, sample: vec3<f32>) {
+    var pixel_color: vec3<f32> = sample;
+
+    // Outliers are samples several times brighter than the average of the first frames, scaled back to that bound
+    if (scene.outlierRejection > 0.0 && frame.frameIndex >= OUTLIER_WARMUP_FRAMES) {
+        let bound: f32 = OUTLIER_RATIO * max(luminance(accumulation[pixel_index].xyz), 0.05);
+        if (luminance(pixel_color) > bound) {
+            pixel_color *= bound / luminance(pixel_color);
+        }
+    }
+
+    // Keep a running average of the radiance since the last reset
+    var accumulated: vec4<f32> = vec4<f32>(pixel_color, 1.0);
+    if (frame.frameIndex > 0.0) {
+        accumulated = mix(accumulation[pixel_index], accumulated, 1.0 / (frame.frameIndex + 1.0));
+    }
+    accumulation[pixel_index] = accumulated;
+
+    // The heat map's colors are shown as they are
+    let exposure: f32 = select(scene.exposure, 1.0, scene.bvhHeatmap > 0.0);
+    textureStore(color_buffer, screen_pos, vec4<f32>(accumulated.xyz * exposure, 1.0));
+}
+
+// Sends photons from the sky towards the scene. Those bounced or bent by mirrors and glass are
+// stored where they land on a matte surface, lighting caustics the camera paths rarely find.
+@compute @workgroup_size(8,8,1)
+fn photon_main(@builtin(global_invocation_id) GlobalInvocationID : vec3<u32>, @builtin(local_invocation_index) localIndex: u32) {
+    frame = frame_from_scene();
+    rngState = GlobalInvocationID.x * 7919u + GlobalInvocationID.y * 104729u + u32(frame.frameIndex) * 15485863u + 1u;
+    coneSpread = 0.0;
+    coneWidth = 0.0;
+
+    // The heat map takes over the photon buffer, which the renderer empties every frame for it
+    if (scene.bvhHeatmap > 0.0) {
+        let uv: vec2<f32> = (vec2<f32>(GlobalInvocationID.xy) + 0.5) / f32(HEATMAP_GRID);
+        let sample: Ray = camera_ray(uv);
+        atomicAdd(&photons[traversal_cost(sample)], 1u);
+        flush_stats(localIndex, 0u);
+        return;
+    }
+
+    var photon: Ray = sky_photon();
+    var power: vec3<f32> = sky_color(-photon.direction);
+    var specularBounces: u32 = 0;
+
+    loop {
+        let result: RenderState = trace(photon);
+        if (!result.hit) {
+            break;
+        }
+        let material: Material = materials[u32(result.material)];
+        let flags: u32 = u32(material.flags);
+        if ((flags & 4u) != 0u) {
+            //light reaching a matte surface straight from the sky is found well enough by camera paths
+            if (specularBounces > 0u) {
+                deposit_photon(result.position, power);
+            }
+            break;
+        }
+        if (specularBounces >= u32(scene.maxSpecularBounces)) {
+            break;
+        }
+        specularBounces++;
+        power *= result.color;
+        if (material.ior > 0.0) {
+            photon.direction = scatter_dielectric(photon.direction, result.normal, result.front_face, material.ior);
+        } else {
+            photon.direction = normalize(reflect(photon.direction, result.normal) + material.roughness * random_unit_vector());
+        }
+        photon.origin = offset_ray_origin(result.position, result.normal, photon.direction);
+    }
+    flush_stats(localIndex, 0u);
+}
+
+// Traces PROBE_RAYS paths from each probe of the grid and blends their light into its spherical
+// harmonics. The workgroup size matches PROBE_WORKGROUP_SIZE in probes.rs.
+@compute @workgroup_size(64,1,1)
+fn probe_main(@builtin(global_invocation_id) GlobalInvocationID : vec3<u32>, @builtin(local_invocation_index) localIndex: u32) {
+    frame = frame_from_scene();
+    coneSpread = 0.0;
+    let counts: vec3<u32> = vec3<u32>(scene.probeCounts);
+    // Invocations past the last probe still take part in flush_stats' barrier
+    if (GlobalInvocationID.x < counts.x * counts.y * counts.z) {
+        update_probe(GlobalInvocationID.x, counts);
+    }
+    flush_stats(localIndex, 0u);
+}
+
+fn update_probe(probe: u32, counts: vec3<u32>) {
+    let cell: vec3<u32> = vec3<u32>(probe % counts.x, probe / counts.x % counts.y, probe / (counts.x * counts.y));
+    let texel: vec2<i32> = probe_texel(cell);
+    // The first texel's alpha counts the updates, seeding the paths so each update traces new ones
+    let updates: f32 = textureLoad(probes, texel, 0).w;
+    rngState = probe * 9781u + u32(updates) * 26699u + 1u;
+
+    var coefficients: array<vec3<f32>, 4>;
+    for (var i: u32 = 0u; i < PROBE_RAYS; i++) {
+        var ray: Ray;
+        ray.origin = scene.probeOrigin + vec3<f32>(cell) * scene.probeSpacing;
+        ray.direction = random_unit_vector();
+        var radiance: vec3<f32> = rayColor(ray);
+        if (scene.maxRadiance > 0.0 && luminance(radiance) > scene.maxRadiance) {
+            radiance *= scene.maxRadiance / luminance(radiance);
+        }
+        coefficients[0] += radiance * SH_CONSTANT;
+        coefficients[1] += radiance * SH_LINEAR * ray.direction.x;
+        coefficients[2] += radiance * SH_LINEAR * ray.direction.y;
+        coefficients[3] += radiance * SH_LINEAR * ray.direction.z;
+    }
+
+    // Every update weighs the same until the hysteresis takes over, so new probes settle quickly
+    // and settled ones follow changes in the scene without flickering
+    let blend: f32 = max(1.0 - PROBE_HYSTERESIS, 1.0 / (updates + 1.0));
+    for (var k: u32 = 0u; k < PROBE_TEXELS; k++) {
+        let at: vec2<i32> = texel + vec2<i32>(i32(k), 0);
+        let updated: vec3<f32> = mix(textureLoad(probes, at, 0).xyz, coefficients[k] * (12.566371 / f32(PROBE_RAYS)), blend);
+        textureStore(probeTarget, at, vec4<f32>(updated, select(0.0, min(updates + 1.0, 1000.0), k == 0u)));
+    }
+}
+
+fn probe_texel(cell: vec3<u32>) -> vec2<i32> {
+    return vec2<i32>(i32(cell.x * PROBE_TEXELS), i32(cell.y + cell.z * u32(scene.probeCounts.y)));
+}
+
+// Whether the scene has a probe grid and the atlas bound is the grid's, the single texel other
+// renderers bind has them trace every bounce instead
+fn probes_bound() -> bool {
+    let counts: vec3<u32> = vec3<u32>(scene.probeCounts);
+    return counts.x > 0u && all(textureDimensions(probes) == vec2<u32>(counts.x * PROBE_TEXELS, counts.y * counts.z));
+}
+
+// Light a white matte surface facing the normal reflects under a probe's light. Mirrors
+// sh_irradiance in probes.rs.
+fn probe_irradiance(cell: vec3<u32>, normal: vec3<f32>) -> vec3<f32> {
+    let texel: vec2<i32> = probe_texel(cell);
+    let linear: vec3<f32> = textureLoad(probes, texel + vec2<i32>(1, 0), 0).xyz * normal.x
+        + textureLoad(probes, texel + vec2<i32>(2, 0), 0).xyz * normal.y
+        + textureLoad(probes, texel + vec2<i32>(3, 0), 0).xyz * normal.z;
+    return max(textureLoad(probes, texel, 0).xyz * SH_CONSTANT + linear * (SH_LINEAR * 2.0 / 3.0), vec3<f32>(0.0));
+}
+
+// Blends the light of the eight probes around a point. Probes behind the surface weigh less, as
+// they see its back side or what lies behind it.
+fn probe_light(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let counts: vec3<u32> = vec3<u32>(scene.probeCounts);
+    let grid: vec3<f32> = clamp((position - scene.probeOrigin) / max(scene.probeSpacing, vec3<f32>(1e-6)), vec3<f32>(0.0), vec3<f32>(counts - 1u));
+    let base: vec3<u32> = vec3<u32>(floor(grid));
+    let fraction: vec3<f32> = grid - vec3<f32>(base);
+
+    var light: vec3<f32> = vec3<f32>(0.0);
+    var total: f32 = 0.0;
+    for (var corner: u32 = 0u; corner < 8u; corner++) {
+        let offset: vec3<u32> = vec3<u32>(corner & 1u, (corner >> 1u) & 1u, (corner >> 2u) & 1u);
+        let cell: vec3<u32> = min(base + offset, counts - 1u);
+        let trilinear: vec3<f32> = select(1.0 - fraction, fraction, offset == vec3<u32>(1u));
+        let toProbe: vec3<f32> = scene.probeOrigin + vec3<f32>(cell) * scene.probeSpacing - position;
+        var facing: f32 = 1.0;
+        if (dot(toProbe, toProbe) > 1e-8) {
+            facing = 0.5 * (dot(normalize(toProbe), normal) + 1.0);
+        }
+        let weight: f32 = trilinear.x * trilinear.y * trilinear.z * (facing * facing + 0.05);
+        light += weight * probe_irradiance(cell, normal);
+        total += weight;
+    }
+    return light / max(total, 1e-6);
+}
+
+// Node visits and triangle tests finding the ray's first hit, up to the heat map's last bin
+fn traversal_cost(ray: Ray) -> u32 {
+    coneWidth = 0.0;
+    let before: u32 = nodeVisits + triangleTests;
+    _ = trace(ray);
+    return min(nodeVisits + triangleTests - before, HEATMAP_BINS - 1u);
+}
+
+// Share of the heat map's sampled rays cheaper than `cost`, counting half of those costing the same
+fn cost_percentile(cost: u32) -> f32 {
+    var below: u32 = 0u;
+    for (var i: u32 = 0u; i < cost; i++) {
+        below += atomicLoad(&photons[i]);
+    }
+    let same: u32 = atomicLoad(&photons[cost]);
+    return (f32(below) + 0.5 * f32(same)) / f32(HEATMAP_GRID * HEATMAP_GRID);
+}
+
+// Blue for the cheapest rays, through cyan, green and yellow to red for the most expensive
+fn heat_color(percentile: f32) -> vec3<f32> {
+    let t: f32 = 4.0 * percentile;
+    return clamp(vec3(t - 2.0, 2.0 - abs(t - 2.0), 2.0 - t), vec3(0.0), vec3(1.0));
+}
+
+// Ray entering the scene from a random sky direction, starting on a disk facing the scene's
+// bounds just outside them
+fn sky_photon() -> Ray {
+    let bounds: Node = tree.nodes[0];
+    let center: vec3<f32> = 0.5 * (bounds.minCorner + bounds.maxCorner);
+    let radius: f32 = 0.5 * length(bounds.maxCorner - bounds.minCorner);
+    let direction: vec3<f32> = random_unit_vector();
+    var side: vec3<f32> = cross(direction, vec3(0.0, 1.0, 0.0));
+    if (dot(side, side) < 1e-6) {
+        side = vec3(1.0, 0.0, 0.0);
+    }
+    side = normalize(side);
+    let up: vec3<f32> = cross(direction, side);
+    let spread: f32 = radius * sqrt(random_float());
+    let angle: f32 = 6.2831853 * random_float();
+
+    var photon: Ray;
+    photon.direction = direction;
+    photon.origin = center - direction * radius + spread * (cos(angle) * side + sin(angle) * up);
+    return photon;
+}
+
+// Follows one photon from the sky and keeps the matte surfaces it bounces off as light vertices.
+// Matte bounces scatter like the camera paths do, mirrors and glass pass the photon on.
+fn trace_light_subpath() {
+    lightVertexCount = 0u;
+    coneWidth = 0.0;
+    var photon: Ray = sky_photon();
+    let bounds: Node = tree.nodes[0];
+    let radius: f32 = 0.5 * length(bounds.maxCorner - bounds.minCorner);
+    var power: vec3<f32> = sky_color(-photon.direction) * 3.14159265 * radius * radius;
+    var specularBounces: u32 = 0;
+
+    loop {
+        let result: RenderState = trace(photon);
+        if (!result.hit) {
+            break;
+        }
+        let material: Material = materials[u32(result.material)];
+        power *= result.color;
+        if ((u32(material.flags) & 4u) != 0u) {
+            var vertex: LightVertex;
+            vertex.position = result.position;
+            vertex.normal = result.normal;
+            vertex.power = power;
+            lightVertices[lightVertexCount] = vertex;
+            lightVertexCount++;
+            if (lightVertexCount >= MAX_LIGHT_VERTICES) {
+                break;
+            }
+            let scattered: vec3<f32> = result.normal + random_unit_vector();
+            photon.direction = select(normalize(scattered), result.normal, dot(scattered, scattered) < 1e-8);
+        } else {
+            if (specularBounces >= u32(scene.maxSpecularBounces)) {
+                break;
+            }
+            specularBounces++;
+            if (material.ior > 0.0) {
+                photon.direction = scatter_dielectric(photon.direction, result.normal, result.front_face, material.ior);
+            } else {
+                photon.direction = normalize(reflect(photon.direction, result.normal) + material.roughness * random_unit_vector());
+            }
+        }
+        photon.origin = offset_ray_origin(result.position, result.normal, photon.direction);
+    }
+}
+
+// Light arriving at a matte point from every vertex of the light subpath it can see
+fn connect_light_vertices(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    //shadow rays must not widen the camera path's texture cone
+    let pathConeWidth: f32 = coneWidth;
+    var gathered: vec3<f32> = vec3(0.0);
+    for (var i: u32 = 0u; i < lightVertexCount; i++) {
+        let vertex: LightVertex = lightVertices[i];
+        let offset: vec3<f32> = vertex.position - position;
+        let distance: f32 = length(offset);
+        let direction: vec3<f32> = offset / max(distance, 1e-6);
+        let cosHere: f32 = dot(normal, direction);
+        let cosThere: f32 = -dot(vertex.normal, direction);
+        if (cosHere <= 0.0 || cosThere <= 0.0) {
+            continue;
+        }
+
+        var shadowRay: Ray;
+        shadowRay.direction = direction;
+        shadowRay.origin = offset_ray_origin(position, normal, direction);
+        coneWidth = 0.0;
+        shadowRays += 1u;
+        let blocker: RenderState = trace(shadowRay);
+        if (blocker.hit && blocker.t < distance * 0.999) {
+            continue;
+        }
+
+        let clamped: f32 = max(distance, MIN_CONNECTION_DISTANCE);
+        //the camera path's own bounce could have made this segment too, see connection_ratio
+        var ratio: f32 = connection_ratio(cosHere, cosThere, distance);
+        if (scene.portalCount > 0.0) {
+            ratio /= portal_weight(position, normal, direction);
+        }
+        gathered += vertex.power * cosHere * cosThere / (3.14159265 * clamped * clamped) / (1.0 + ratio);
+    }
+    coneWidth = pathConeWidth;
+    return gathered;
+}
+
+// How much likelier a camera path is to make the segment between two matte points by bouncing off
+// the first than a light subpath is to hit the second, for balancing the two. A light subpath
+// reaches its first vertex with density cos / (4π · disk area), and past it both ways scatter by
+// the cosine, so only the camera's bounce into the segment is left in the ratio.
+fn connection_ratio(cosHere: f32, cosThere: f32, distance: f32) -> f32 {
+    let bounds: Node = tree.nodes[0];
+    let radius: f32 = 0.5 * length(bounds.maxCorner - bounds.minCorner);
+    let clamped: f32 = max(distance, MIN_CONNECTION_DISTANCE);
+    return 4.0 * radius * radius * cosHere * cosThere / (clamped * clamped);
+}
+
+// First of the four words of the grid cell around a position
+fn photon_cell(position: vec3<f32>) -> u32 {
+    let cell: vec3<i32> = vec3<i32>(floor(position / scene.photonRadius));
+    let hash: u32 = (u32(cell.x) * 73856093u) ^ (u32(cell.y) * 19349663u) ^ (u32(cell.z) * 83492791u);
+    return 4u * (hash % (arrayLength(&photons) / 4u));
+}
+
+fn deposit_photon(position: vec3<f32>, power: vec3<f32>) {
+    let cell: u32 = photon_cell(position);
+    atomicAdd(&photons[cell], u32(power.r * PHOTON_SCALE));
+    atomicAdd(&photons[cell + 1u], u32(power.g * PHOTON_SCALE));
+    atomicAdd(&photons[cell + 2u], u32(power.b * PHOTON_SCALE));
+    atomicAdd(&photons[cell + 3u], 1u);
+}
+
+// Caustic light per unit area around a position, from the photons of every frame since the last reset.
+// Each photon carries its share of the sky's light through the emitting disk.
+fn caustic_irradiance(position: vec3<f32>) -> vec3<f32> {
+    let cell: u32 = photon_cell(position);
+    let summed: vec3<f32> = vec3(
+        f32(atomicLoad(&photons[cell])),
+        f32(atomicLoad(&photons[cell + 1u])),
+        f32(atomicLoad(&photons[cell + 2u])),
+    ) / PHOTON_SCALE;
+    let bounds: Node = tree.nodes[0];
+    let radius: f32 = 0.5 * length(bounds.maxCorner - bounds.minCorner);
+    let emitted: f32 = PHOTONS_PER_FRAME * (frame.frameIndex + 1.0);
+    return summed * 3.14159265 * radius * radius / (emitted * scene.photonRadius * scene.photonRadius);
+}
+
+// Rec. 709 brightness of a linear color
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
 
-    let pixel_color : vec3<f32> = rayColor(myRay);
+// Writes the first hit's albedo, normal, depth and object id for compositing.
+// Misses store the sky color as albedo, a zero normal and depth, and an object id of -1.
+fn write_aovs(ray: Ray, pixel_index: u32) {
+    coneWidth = 0.0;
+    let primary: RenderState = trace(ray);
 
-    textureStore(color_buffer, screen_pos, vec4<f32>(pixel_color, 1.0));
+    var aov: AovSample;
+    aov.albedo = primary.color;
+    if (primary.hit) {
+        aov.objectId = primary.objectId;
+        aov.normal = primary.normal;
+        aov.depth = primary.t;
+    } else {
+        aov.objectId = -1.0;
+        aov.normal = vec3(0.0);
+        aov.depth = 0.0;
+    }
+
+    aovs[pixel_index] = aov;
 }
 
 fn rayColor (ray: Ray) -> vec3<f32> {
@@ -90,105 +778,508 @@ fn rayColor (ray: Ray) -> vec3<f32> {
     temp_ray.origin = ray.origin;
     temp_ray.direction = ray.direction;
     
-    let bounces: u32 = u32(scene.maxBounces);
-    for (var bounce: u32 = 0; bounce < bounces; bounce++) {
-        result = trace(temp_ray);
+    //Diffuse and specular bounces are counted apart, so mirrors can go deep while matte paths stay short
+    var diffuseBounces: u32 = 0;
+    var specularBounces: u32 = 0;
+    coneWidth = 0.0;
+    //scales the path's color for bounces whose direction wasn't drawn by the cosine alone, see portal_weight
+    var pathWeight: f32 = 1.0;
+    //1 after a matte bounce, 2 once mirrors or glass and nothing else followed it. The sky such a
+    //path ends in is the caustic light the photon map already added at the matte surface.
+    var causticPath: u32 = 0u;
+    //in bidirectional mode, the share of the light from here on that the connections don't already
+    //count. bounceCos is the cosine of the last matte bounce, 0 when the last bounce was anything
+    //else, and bounceMixture its portal_weight.
+    var lightWeight: f32 = 1.0;
+    var bounceCos: f32 = 0.0;
+    var bounceMixture: f32 = 1.0;
 
-        //unpack color
-        color = 0.5 * (result.color + color);
+    //spectral mode follows a random hero wavelength and three companions spread evenly over the
+    //visible range, until glass bends each of them differently and only the hero goes on
+    var wavelengths: vec4<f32> = vec4(0.0);
+    var dispersed: bool = false;
+    //bidirectional mode pairs each camera path with one light subpath, traced before the camera's cone starts
+    if (scene.bidirectional > 0.0) {
+        trace_light_subpath();
+        coneWidth = 0.0;
+    }
+    if (scene.spectral > 0.0) {
+        let hero: f32 = random_float();
+        wavelengths = 380.0 + 340.0 * fract(vec4(hero, hero + 0.25, hero + 0.5, hero + 0.75));
+    }
+    loop {
+        if (all(rasterTexel >= vec2<i32>(0))) {
+            result = trace_rasterized(temp_ray);
+            rasterTexel = vec2<i32>(-1);
+        } else {
+            result = trace(temp_ray);
+        }
+        //reflections keep widening the same cone
+        coneWidth += result.t * coneSpread;
 
         //early exit
         if (!result.hit) {
+            if (scene.caustics > 0.0 && causticPath == 2u) {
+                color = 0.5 * color;
+            } else {
+                color = 0.5 * (lightWeight * result.color + color);
+            }
             break;
         }
 
+        //unpack color, tinted by the material's thin film
+        let material: Material = materials[u32(result.material)];
+        var surfaceColor: vec3<f32> = result.color;
+        if (material.filmThickness > 0.0) {
+            let cosTheta: f32 = abs(dot(temp_ray.direction, result.normal));
+            surfaceColor *= thin_film_tint(cosTheta, material.filmThickness, material.filmIor);
+        }
+        //matte surfaces brighten by the caustic light photons left around them
+        if (scene.caustics > 0.0 && (u32(material.flags) & 4u) != 0u) {
+            surfaceColor *= 1.0 + caustic_irradiance(result.position);
+        }
+        //and by the sky light the light subpath carried to places the camera path can see
+        if (scene.bidirectional > 0.0 && (u32(material.flags) & 4u) != 0u) {
+            //a matte bounce landing here is the other way to the same light, weighted by the balance heuristic
+            let cosThere: f32 = -dot(result.normal, temp_ray.direction);
+            if (bounceCos > 0.0 && cosThere > 0.0) {
+                let ratio: f32 = connection_ratio(bounceCos, cosThere, result.t) / bounceMixture;
+                lightWeight *= ratio / (1.0 + ratio);
+            }
+            surfaceColor *= 1.0 + connect_light_vertices(result.position, result.normal);
+        }
+        bounceCos = 0.0;
+        color = 0.5 * (lightWeight * surfaceColor + color);
+
         //Set up for next trace
-        temp_ray.origin = result.position;
-        temp_ray.direction = normalize(reflect(temp_ray.direction, result.normal));
+        let flags: u32 = u32(material.flags);
+        if ((flags & 4u) != 0u) {
+            causticPath = 0u;
+        } else if (causticPath > 0u) {
+            causticPath = 2u;
+        }
+        let specular: bool = (flags & 4u) == 0u || random_float() < material.metalness;
+        let hair: bool = (flags & 8u) != 0u && dot(result.tangent, result.tangent) > 0.0;
+        if (material.ior > 0.0) {
+            if (specularBounces >= u32(scene.maxSpecularBounces)) {
+                break;
+            }
+            specularBounces++;
+            var ior: f32 = material.ior;
+            if (scene.spectral > 0.0 && material.dispersion > 0.0) {
+                //Cauchy's equation, with the material's index taken at the sodium d line
+                let micrometers: f32 = wavelengths.x / 1000.0;
+                ior += material.dispersion * (1.0 / (micrometers * micrometers) - 1.0 / (0.5876 * 0.5876));
+                dispersed = true;
+            }
+            temp_ray.direction = scatter_dielectric(temp_ray.direction, result.normal, result.front_face, ior);
+        } else if (hair) {
+            if (diffuseBounces >= u32(scene.maxDiffuseBounces)) {
+                break;
+            }
+            diffuseBounces++;
+            temp_ray.direction = sample_hair(temp_ray.direction, result.normal, result.tangent, material.roughness);
+        } else if (!specular) {
+            if (diffuseBounces >= u32(scene.maxDiffuseBounces)) {
+                break;
+            }
+            //with a probe grid the bounce's light is looked up instead of traced
+            if (probes_bound()) {
+                color = 0.5 * (lightWeight * probe_light(result.position, result.normal) + color);
+                break;
+            }
+            diffuseBounces++;
+            causticPath = 1u;
+            let scattered: vec3<f32> = result.normal + random_unit_vector();
+            //a sample opposite the normal would cancel it out
+            if (dot(scattered, scattered) < 1e-8) {
+                temp_ray.direction = result.normal;
+            } else {
+                temp_ray.direction = normalize(scattered);
+            }
+            //half the bounces head for a random point on a portal, where the sky comes in. The cosine
+            //never picks one behind the surface, so such a path counts for nothing.
+            if (scene.portalCount > 0.0) {
+                if (random_float() < 0.5) {
+                    temp_ray.direction = sample_portal(result.position);
+                    if (dot(temp_ray.direction, result.normal) <= 0.0) {
+                        pathWeight = 0.0;
+                        break;
+                    }
+                }
+                bounceMixture = portal_weight(result.position, result.normal, temp_ray.direction);
+                pathWeight *= bounceMixture;
+            }
+            bounceCos = dot(temp_ray.direction, result.normal);
+        } else {
+            if (specularBounces >= u32(scene.maxSpecularBounces)) {
+                break;
+            }
+            specularBounces++;
+            //rough surfaces blur the reflection, keeping the mirror direction when the jitter points into the surface.
+            //Past a diffuse bounce the blur is kept from dropping below the regularization, which tames fireflies
+            var roughness: f32 = material.roughness;
+            if (diffuseBounces > 0u) {
+                roughness = max(roughness, scene.roughnessRegularization);
+            }
+            var reflected: vec3<f32> = reflect(temp_ray.direction, result.normal);
+            let jittered: vec3<f32> = reflected + roughness * random_unit_vector();
+            if (dot(jittered, result.normal) > 0.0) {
+                reflected = jittered;
+            }
+            temp_ray.direction = normalize(reflected);
+        }
+        temp_ray.origin = offset_ray_origin(result.position, result.normal, temp_ray.direction);
+    }
+    color *= pathWeight;
+
+    if (scene.spectral > 0.0) {
+        var response: vec3<f32> = wavelength_rgb(wavelengths.x);
+        if (!dispersed) {
+            response = 0.25 * (response + wavelength_rgb(wavelengths.y) + wavelength_rgb(wavelengths.z) + wavelength_rgb(wavelengths.w));
+        }
+        color *= response;
     }
 
     return color;
 }
 
+// Reflects or refracts into glass with the given index, picking by the Fresnel reflectance
+fn scatter_dielectric(direction: vec3<f32>, normal: vec3<f32>, frontFace: bool, ior: f32) -> vec3<f32> {
+    var facing: vec3<f32> = normal;
+    if (dot(direction, normal) > 0.0) {
+        facing = -normal;
+    }
+    var eta: f32 = ior;
+    if (frontFace) {
+        eta = 1.0 / ior;
+    }
+    let cosTheta: f32 = min(dot(-direction, facing), 1.0);
+    let r0: f32 = pow((1.0 - ior) / (1.0 + ior), 2.0);
+    let reflectance: f32 = r0 + (1.0 - r0) * pow(1.0 - cosTheta, 5.0);
+    //past the critical angle everything reflects
+    if (eta * eta * (1.0 - cosTheta * cosTheta) > 1.0 || random_float() < reflectance) {
+        return reflect(direction, facing);
+    }
+    return normalize(refract(direction, facing, eta));
+}
+
+// Linear sRGB response to a wavelength in nanometers, from analytic fits of the CIE 1931 matching
+// functions (Wyman et al. 2013), scaled so wavelengths drawn evenly from 380 to 720 average to white
+fn wavelength_rgb(lambda: f32) -> vec3<f32> {
+    let x: f32 = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7) - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y: f32 = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z: f32 = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    let rgb: vec3<f32> = vec3(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    );
+    return rgb * vec3(2.6488, 3.3485, 3.5033);
+}
+
+// Gaussian with different widths below and above its peak
+fn lobe(lambda: f32, peak: f32, below: f32, above: f32) -> f32 {
+    var width: f32 = above;
+    if (lambda < peak) {
+        width = below;
+    }
+    let t: f32 = (lambda - peak) / width;
+    return exp(-0.5 * t * t);
+}
+
+// Direction from a point towards a random spot on a random portal
+fn sample_portal(position: vec3<f32>) -> vec3<f32> {
+    let index: u32 = min(u32(random_float() * scene.portalCount), u32(scene.portalCount) - 1u);
+    let portal: Portal = scene.portals[index];
+    let spot: vec3<f32> = portal.corner + random_float() * portal.edge_u + random_float() * portal.edge_v;
+    return normalize(spot - position);
+}
+
+// Density of sample_portal's directions from a point per unit solid angle, zero for directions
+// that miss every portal
+fn portal_pdf(position: vec3<f32>, direction: vec3<f32>) -> f32 {
+    var pdf: f32 = 0.0;
+    for (var i: u32 = 0u; i < u32(scene.portalCount); i++) {
+        let portal: Portal = scene.portals[i];
+        //the normal's length is the portal's area
+        let normal: vec3<f32> = cross(portal.edge_u, portal.edge_v);
+        let facing: f32 = dot(direction, normal);
+        let t: f32 = dot(portal.corner - position, normal) / facing;
+        if (abs(facing) < 1e-12 || t <= 0.0) {
+            continue;
+        }
+        //coordinates of the crossing along the edges, which needn't be perpendicular
+        let offset: vec3<f32> = position + t * direction - portal.corner;
+        let uu: f32 = dot(portal.edge_u, portal.edge_u);
+        let uv: f32 = dot(portal.edge_u, portal.edge_v);
+        let vv: f32 = dot(portal.edge_v, portal.edge_v);
+        let ou: f32 = dot(offset, portal.edge_u);
+        let ov: f32 = dot(offset, portal.edge_v);
+        let det: f32 = uu * vv - uv * uv;
+        let u: f32 = (ou * vv - ov * uv) / det;
+        let v: f32 = (ov * uu - ou * uv) / det;
+        if (u >= 0.0 && u <= 1.0 && v >= 0.0 && v <= 1.0) {
+            //a point density of one over the area, seen at distance t and at an angle
+            pdf += t * t / abs(facing);
+        }
+    }
+    return pdf / scene.portalCount;
+}
+
+// Weight of a matte bounce drawn half the time towards the portals and half the time by the cosine,
+// the cosine density over the density of both together. Directions that could only come from the
+// portals are rare under the cosine and count little, the ones missing the portals count twice.
+fn portal_weight(position: vec3<f32>, normal: vec3<f32>, direction: vec3<f32>) -> f32 {
+    let cosinePdf: f32 = max(dot(direction, normal), 0.0) / 3.14159265;
+    let mixturePdf: f32 = 0.5 * cosinePdf + 0.5 * portal_pdf(position, direction);
+    if (mixturePdf <= 0.0) {
+        return 0.0;
+    }
+    return cosinePdf / mixturePdf;
+}
+
+// Light leaving a fiber keeps the angle it made with the fiber's direction, so reflections spread
+// into a cone around it. A random point on the lit half of the cone is picked, and roughness
+// widens the cone's rim along the fiber.
+fn sample_hair(direction: vec3<f32>, normal: vec3<f32>, tangent: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let along: f32 = clamp(dot(direction, tangent) + roughness * (2.0 * random_float() - 1.0), -1.0, 1.0);
+    let side: vec3<f32> = normalize(normal - dot(normal, tangent) * tangent);
+    let binormal: vec3<f32> = cross(tangent, side);
+    let azimuth: f32 = (random_float() - 0.5) * 3.14159265;
+    let around: vec3<f32> = cos(azimuth) * side + sin(azimuth) * binormal;
+    return normalize(along * tangent + sqrt(1.0 - along * along) * around);
+}
+
+// Hue of the light reflected by a film surrounded by air, from the Airy formula averaged over
+// both polarizations at red, green and blue wavelengths. Scaled so the strongest channel is 1,
+// which keeps the surface's brightness and only shifts its color with angle and thickness.
+fn thin_film_tint(cosTheta: f32, thickness: f32, ior: f32) -> vec3<f32> {
+    let sinFilm: f32 = sqrt(max(0.0, 1.0 - cosTheta * cosTheta)) / ior;
+    let cosFilm: f32 = sqrt(max(0.0, 1.0 - sinFilm * sinFilm));
+
+    //Fresnel amplitudes entering the film, leaving it on the far side flips their sign
+    let rs: f32 = (cosTheta - ior * cosFilm) / (cosTheta + ior * cosFilm);
+    let rp: f32 = (ior * cosTheta - cosFilm) / (ior * cosTheta + cosFilm);
+
+    let wavelengths: vec3<f32> = vec3<f32>(650.0, 510.0, 475.0);
+    let phase: vec3<f32> = 4.0 * 3.14159265 * ior * thickness * cosFilm / wavelengths;
+    let interference: vec3<f32> = cos(phase);
+
+    let reflectance: vec3<f32> = 0.5 * (airy_reflectance(rs, interference) + airy_reflectance(rp, interference));
+    return reflectance / max(max(reflectance.x, max(reflectance.y, reflectance.z)), 1e-6);
+}
+
+// Reflectance of a film whose two interfaces reflect with amplitudes r and -r
+fn airy_reflectance(r: f32, interference: vec3<f32>) -> vec3<f32> {
+    let r2: f32 = r * r;
+    return (2.0 * r2 - 2.0 * r2 * interference) / (1.0 + r2 * r2 - 2.0 * r2 * interference);
+}
+
+// PCG hash step, returning a float in [0, 1)
+fn random_float() -> f32 {
+    rngState = rngState * 747796405u + 2891336453u;
+    var word: u32 = ((rngState >> ((rngState >> 28u) + 4u)) ^ rngState) * 277803737u;
+    word = (word >> 22u) ^ word;
+    return f32(word) / 4294967296.0;
+}
+
+// Uniform direction on the unit sphere, added to a normal it gives a cosine-weighted bounce
+fn random_unit_vector() -> vec3<f32> {
+    let z: f32 = 2.0 * random_float() - 1.0;
+    let angle: f32 = 6.2831853 * random_float();
+    let radius: f32 = sqrt(max(0.0, 1.0 - z * z));
+    return vec3<f32>(radius * cos(angle), radius * sin(angle), z);
+}
+
+// Pushes a hit point off the surface along the normal, on the side the new ray leaves towards.
+// The offset grows with the position's magnitude to stay above f32 rounding error far from the origin.
+fn offset_ray_origin(position: vec3<f32>, normal: vec3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    let scale: f32 = max(1.0, max(abs(position.x), max(abs(position.y), abs(position.z))));
+    var offset: vec3<f32> = normal * scale * 0.0001;
+    if (dot(direction, normal) < 0.0) {
+        offset = -offset;
+    }
+    return position + offset;
+}
+
+// Samples the sky turned by skyYaw, so turning it moves the sky the same way in every direction
+fn sky_color(direction: vec3<f32>) -> vec3<f32> {
+    let c: f32 = cos(scene.skyYaw);
+    let s: f32 = sin(scene.skyYaw);
+    let rotated: vec3<f32> = vec3<f32>(c * direction.x - s * direction.z, direction.y, s * direction.x + c * direction.z);
+    // The sky is infinitely far away, so only the cone's spread matters. A face spans 90
+    // degrees, which near its center gives faceSize / 2 texels per radian.
+    let faceSize: f32 = f32(textureDimensions(skyMaterial).x);
+    let lod: f32 = max(0.0, log2(max(coneSpread * faceSize * 0.5, 1e-8)));
+    return scene.skyIntensity * textureSampleLevel(skyMaterial, skySampler, rotated, lod).xyz;
+}
+
 fn trace(ray: Ray) -> RenderState {
+    tracedRays += 1u;
     // Set up the render state 
     var renderState: RenderState;
+    renderState.hit = false;
+    renderState = traverse(ray, renderState);
+
+    if (!renderState.hit) {
+        // Sky color 
+        renderState.color = sky_color(ray.direction);
+    }
+    
+    return renderState;
+}
 
-    // Set up the start
+// A camera ray's first hit, starting from the triangle the raster pass found in front at its
+// pixel. Only where other primitives or see-through triangles could be closer is the BVH walked,
+// and then only up to the triangle. Pixels without one, and rays missing theirs like where its
+// alpha cuts it away, are traced as usual.
+fn trace_rasterized(ray: Ray) -> RenderState {
+    let visible: u32 = textureLoad(visibility, rasterTexel, 0).x;
+    if (visible == 0u) {
+        return trace(ray);
+    }
+    var renderState: RenderState;
     renderState.hit = false;
-    var nearestHit: f32 = 9999.0;
+    let index: u32 = visible - 1u;
+    renderState = hit_geometric_primitive(ray, objects[index], RAY_T_MIN, 9999.0, renderState);
+    if (!renderState.hit) {
+        return trace(ray);
+    }
+    tracedRays += 1u;
+    renderState.objectId = f32(index);
+    renderState.material = objects[index].material;
+    if (scene.rasterPrimary < 2.0) {
+        renderState = traverse(ray, renderState);
+    }
+    return renderState;
+}
+
+// Nearest hit of the ray closer than the one it starts from, if that hit anything
+fn traverse(ray: Ray, start: RenderState) -> RenderState {
+    if (scene.stacklessTraversal > 0.0) {
+        return traverse_stackless(ray, start);
+    }
+    return traverse_stack(ray, start);
+}
 
-    // Set up for BVH traversal
-    var node: Node = tree.nodes[0];
-    var stack: array<Node, 15>;
+// Walks the BVH with a stack, visiting the child on the near side of the split first
+fn traverse_stack(ray: Ray, start: RenderState) -> RenderState {
+    var renderState: RenderState = start;
+    var nearestHit: f32 = select(9999.0, start.t, start.hit);
+
+    var nodeIndex: u32 = 0;
+    var stack: array<u32, 32>; // Matches MAX_BVH_DEPTH in scene.rs
     var stackLocation: i32 = 0;
 
     while (true) {
-        var objectCount: u32 = u32(node.objectCount);
-        var contents: u32 = u32(node.leftChild);
+        nodeVisits += 1u;
+        let node: Node = tree.nodes[nodeIndex];
+        let objectCount: u32 = u32(node.objectCount);
+        let contents: u32 = u32(node.leftChild);
 
         if (objectCount == 0) {
-            var child1: Node = tree.nodes[contents];
-            var child2: Node = tree.nodes[contents + 1];
-
-            var distance1: f32 = hit_aabb(ray, child1);
-            var distance2: f32 = hit_aabb(ray, child2);
-            if (distance1 > distance2) {
-                var tempDist: f32 = distance1;
-                distance1 = distance2;
-                distance2 = tempDist;
-
-                var tempChild: Node = child1;
-                child1 = child2;
-                child2 = tempChild;
+            // The left child holds the lower half along the split axis
+            var near: u32 = contents;
+            var far: u32 = contents + 1;
+            if (ray.direction[u32(node.splitAxis)] < 0.0) {
+                near = contents + 1;
+                far = contents;
             }
 
-            if (distance1 > nearestHit) {
-                if (stackLocation == 0) {
-                    break;
-                }
-                else {
-                    stackLocation -= 1;
-                    node = stack[stackLocation];
-                }
-            }
-            else {
-                node = child1;
-                if (distance2 < nearestHit) {
-                    stack[stackLocation] = child2;
+            let nearDistance: f32 = hit_aabb(ray, tree.nodes[near]);
+            let farDistance: f32 = hit_aabb(ray, tree.nodes[far]);
+            if (nearDistance < nearestHit) {
+                if (farDistance < nearestHit) {
+                    stack[stackLocation] = far;
                     stackLocation += 1;
                 }
+                nodeIndex = near;
+                continue;
+            }
+            if (farDistance < nearestHit) {
+                nodeIndex = far;
+                continue;
             }
         }
         else {
-            for (var i: u32 = 0; i < objectCount; i++) {
-                var newRenderState: RenderState = hit_geometric_primitive(
-                    ray, 
-                    objects[u32(objectLookup.indices[i + contents])], 
-                    0.001, nearestHit, renderState
-                );
-
-                if (newRenderState.hit) {
-                    nearestHit = newRenderState.t;
-                    renderState = newRenderState;
-                }
+            renderState = hit_leaf(ray, node, nearestHit, renderState);
+            if (renderState.hit) {
+                nearestHit = renderState.t;
             }
+        }
 
-            if (stackLocation == 0) {
-                break;
-            }
-            else {
-                stackLocation -= 1;
-                node = stack[stackLocation];
+        if (stackLocation == 0) {
+            break;
+        }
+        stackLocation -= 1;
+        nodeIndex = stack[stackLocation];
+    }
+
+    return renderState;
+}
+
+// Walks the BVH in a fixed order following each node's skip link when it is missed or done,
+// trading front-to-back ordering for no per-ray stack
+fn traverse_stackless(ray: Ray, start: RenderState) -> RenderState {
+    var renderState: RenderState = start;
+    var nearestHit: f32 = select(9999.0, start.t, start.hit);
+
+    var nodeIndex: i32 = 0;
+    while (nodeIndex >= 0) {
+        nodeVisits += 1u;
+        let node: Node = tree.nodes[nodeIndex];
+
+        if (hit_aabb(ray, node) >= nearestHit) {
+            nodeIndex = i32(node.skip);
+        }
+        else if (node.objectCount == 0.0) {
+            nodeIndex = i32(node.leftChild);
+        }
+        else {
+            renderState = hit_leaf(ray, node, nearestHit, renderState);
+            if (renderState.hit) {
+                nearestHit = renderState.t;
             }
+            nodeIndex = i32(node.skip);
         }
     }
 
-    if (!renderState.hit) {
-        // Sky color 
-        renderState.color = textureSampleLevel(skyMaterial, skySampler, ray.direction, 0.0).xyz;
+    return renderState;
+}
+
+// Tests every object in a leaf, keeping the nearest hit
+fn hit_leaf(ray: Ray, node: Node, tMax: f32, oldRenderState: RenderState) -> RenderState {
+    var renderState: RenderState = oldRenderState;
+    var nearestHit: f32 = tMax;
+    let contents: u32 = u32(node.leftChild);
+
+    for (var i: u32 = 0; i < u32(node.objectCount); i++) {
+        let objectIndex: f32 = objectLookup.indices[i + contents];
+        //skip objects that are hidden or on no rendered layer
+        if ((objects[u32(objectIndex)].layers & scene.renderMask) == 0u) {
+            continue;
+        }
+        var newRenderState: RenderState = hit_geometric_primitive(
+            ray, 
+            objects[u32(objectIndex)], 
+            RAY_T_MIN, nearestHit, renderState
+        );
+
+        //see-through surfaces are skipped at random, so layers blend over many samples without sorting
+        let material: Material = materials[u32(objects[u32(objectIndex)].material)];
+        if (newRenderState.hit && material.transparency > 0.0 && random_float() < material.transparency) {
+            continue;
+        }
+
+        if (newRenderState.hit) {
+            nearestHit = newRenderState.t;
+            renderState = newRenderState;
+            renderState.objectId = objectIndex;
+            renderState.material = objects[u32(objectIndex)].material;
+        }
     }
-    
+
     return renderState;
 }
 
@@ -198,6 +1289,10 @@ fn decode_sphere(data: array<f32, 16>) -> Sphere {
         vec3(data[0], data[1], data[2]), // Center
         data[3], // Radius
         vec3(data[4], data[5], data[6]), // Color
+        data[7], // Texture kind
+        data[8], // Texture layer
+        vec3(data[9], data[10], data[11]), // Checker color
+        data[12], // Checker scale
     );
 }
 
@@ -207,10 +1302,58 @@ fn decode_triangle(data: array<f32, 16>) -> Triangle {
         vec3(data[7], data[8], data[9]), // corner_a
         vec3(data[10], data[11], data[12]), // corner_b
         vec3(data[13], data[14], data[15]), // corner_c
+        vec3(data[4], data[5], data[6]), // color
+        vec3(data[0], data[1], data[2]), // vertex_colors
+        data[3] // normals
+    );
+}
+
+// Function to decode a Quad from the GeometricPrimitive data array
+fn decode_quad(data: array<f32, 16>) -> Quad {
+    return Quad(
+        vec3(data[7], data[8], data[9]), // corner
+        vec3(data[10], data[11], data[12]), // edge_u
+        vec3(data[13], data[14], data[15]), // edge_v
         vec3(data[4], data[5], data[6]) // color
     );
 }
 
+// Function to decode a Billboard from the GeometricPrimitive data array
+fn decode_billboard(data: array<f32, 16>) -> Billboard {
+    return Billboard(
+        vec3(data[0], data[1], data[2]), // Center
+        data[3], // Half width
+        vec3(data[4], data[5], data[6]), // Color
+        data[7], // Half height
+        data[8], // Texture layer
+        vec2(data[9], data[10]), // UV min
+        vec2(data[11], data[12]), // UV max
+    );
+}
+
+// Function to decode a PointCluster from the GeometricPrimitive data array
+fn decode_point_cluster(data: array<f32, 16>) -> PointCluster {
+    return PointCluster(
+        u32(data[0]), // First point
+        u32(data[1]), // Count
+        data[2], // Radius
+        vec3(data[4], data[5], data[6]), // Color
+    );
+}
+
+// Function to decode a Curve from the GeometricPrimitive data array
+fn decode_curve(primitive: GeometricPrimitive) -> Curve {
+    let data = primitive.data;
+    return Curve(
+        vec3(data[0], data[1], data[2]), // Root
+        vec3(data[7], data[8], data[9]), // Control points
+        vec3(data[10], data[11], data[12]),
+        vec3(data[13], data[14], data[15]), // Tip
+        data[3], // Radius
+        vec3(data[4], data[5], data[6]), // Color
+    );
+}
+
 // Function to interpret the GeometricPrimitive and perform collision detection
 fn hit_geometric_primitive(ray: Ray, primitive: GeometricPrimitive, tMin: f32, tMax: f32, renderState: RenderState) -> RenderState {
     var state: RenderState;
@@ -221,11 +1364,172 @@ fn hit_geometric_primitive(ray: Ray, primitive: GeometricPrimitive, tMin: f32, t
     } else if (primitive.data_type == 1.0) {
         // Triangle
         let triangle: Triangle = decode_triangle(primitive.data);
-        state = hit_triangle(ray, triangle, tMin, tMax, renderState);
+        state = hit_triangle(ray, triangle, materials[u32(primitive.material)], tMin, tMax, renderState);
+    } else if (primitive.data_type == 2.0) {
+        // Quad
+        let quad: Quad = decode_quad(primitive.data);
+        state = hit_quad(ray, quad, materials[u32(primitive.material)], tMin, tMax, renderState);
+    } else if (primitive.data_type == 3.0) {
+        // Billboard
+        let billboard: Billboard = decode_billboard(primitive.data);
+        state = hit_billboard(ray, billboard, tMin, tMax, renderState);
+    } else if (primitive.data_type == 4.0) {
+        // Point cluster
+        let cluster: PointCluster = decode_point_cluster(primitive.data);
+        state = hit_point_cluster(ray, cluster, tMin, tMax, renderState);
+    } else if (primitive.data_type == 5.0) {
+        // Curve
+        let curve: Curve = decode_curve(primitive);
+        state = hit_curve(ray, curve, tMin, tMax, renderState);
+    } else if (primitive.data_type == 6.0) {
+        // Instance
+        state = hit_instance(ray, primitive, tMin, tMax, renderState);
     }
     return state;
 }
 
+// Takes the ray into the space of the instance's prototype and walks the prototype's BVH, whose
+// leaves hold triangles. The direction isn't normalized there, so distances match the scene's.
+fn hit_instance(ray: Ray, primitive: GeometricPrimitive, tMin: f32, tMax: f32, oldRenderState: RenderState) -> RenderState {
+    let data = primitive.data;
+    let toPrototype: mat3x3<f32> = mat3x3<f32>(
+        vec3(data[0], data[1], data[2]),
+        vec3(data[3], data[4], data[5]),
+        vec3(data[6], data[7], data[8]),
+    );
+    let localRay: Ray = Ray(toPrototype * ray.direction, toPrototype * ray.origin + vec3(data[9], data[10], data[11]));
+    let material: Material = materials[u32(primitive.material)];
+
+    var renderState: RenderState;
+    renderState.color = oldRenderState.color;
+    renderState.hit = false;
+    var nearestHit: f32 = tMax;
+
+    // Right children wait on the stack while the left ones are walked
+    var nodeIndex: u32 = u32(data[12]);
+    var stack: array<u32, 32>; // Matches MAX_BVH_DEPTH in scene.rs
+    var stackLocation: i32 = 0;
+    while (true) {
+        nodeVisits += 1u;
+        let node: Node = tree.nodes[nodeIndex];
+        let contents: u32 = u32(node.leftChild);
+        if (hit_aabb(localRay, node) < nearestHit) {
+            if (node.objectCount == 0.0) {
+                stack[stackLocation] = contents + 1;
+                stackLocation += 1;
+                nodeIndex = contents;
+                continue;
+            }
+            for (var i: u32 = 0; i < u32(node.objectCount); i++) {
+                let triangle: Triangle = decode_triangle(objects[u32(objectLookup.indices[i + contents])].data);
+                let newRenderState: RenderState = hit_triangle(localRay, triangle, material, tMin, nearestHit, oldRenderState);
+                if (newRenderState.hit) {
+                    nearestHit = newRenderState.t;
+                    renderState = newRenderState;
+                }
+            }
+        }
+
+        if (stackLocation == 0) {
+            break;
+        }
+        stackLocation -= 1;
+        nodeIndex = stack[stackLocation];
+    }
+
+    if (renderState.hit) {
+        renderState.position = ray.origin + renderState.t * ray.direction;
+        renderState.normal = normalize(transpose(toPrototype) * renderState.normal);
+        renderState.color *= vec3(data[13], data[14], data[15]);
+    }
+    return renderState;
+}
+
+fn curve_point(curve: Curve, t: f32) -> vec3<f32> {
+    let s: f32 = 1.0 - t;
+    return s * s * s * curve.p0 + 3.0 * s * s * t * curve.p1 + 3.0 * s * t * t * curve.p2 + t * t * t * curve.p3;
+}
+
+// Cuts the curve into straight pieces and tests each as a cylinder around its axis, taking the
+// surface to face the ray, which is close enough for strands a few pixels wide
+fn hit_curve(ray: Ray, curve: Curve, tMin: f32, tMax: f32, oldRenderState: RenderState) -> RenderState {
+    var renderState: RenderState;
+    renderState.color = oldRenderState.color;
+    renderState.hit = false;
+    var nearestHit: f32 = tMax;
+    let direction: vec3<f32> = normalize(ray.direction);
+    let scale: f32 = length(ray.direction);
+
+    var start: vec3<f32> = curve.p0;
+    for (var i: u32 = 1; i <= CURVE_SEGMENTS; i++) {
+        let end: vec3<f32> = curve_point(curve, f32(i) / f32(CURVE_SEGMENTS));
+        let axis: vec3<f32> = end - start;
+        let axisLength2: f32 = dot(axis, axis);
+        let toStart: vec3<f32> = ray.origin - start;
+
+        // Closest points between the ray and the piece's axis
+        let b: f32 = dot(direction, axis);
+        let denominator: f32 = axisLength2 - b * b;
+        var u: f32 = 0.0;
+        if (denominator > 1e-12) {
+            u = (dot(axis, toStart) - b * dot(direction, toStart)) / denominator;
+        }
+        u = clamp(u, 0.0, 1.0);
+        let onAxis: vec3<f32> = start + u * axis;
+        let along: f32 = dot(onAxis - ray.origin, direction);
+        let offset: vec3<f32> = ray.origin + along * direction - onAxis;
+        let distance2: f32 = dot(offset, offset);
+
+        if (distance2 < curve.radius * curve.radius) {
+            let t: f32 = (along - sqrt(curve.radius * curve.radius - distance2)) / scale;
+            if (t > tMin && t < nearestHit) {
+                nearestHit = t;
+                renderState.t = t;
+                renderState.position = ray.origin + t * ray.direction;
+                let closest: vec3<f32> = start + clamp(dot(renderState.position - start, axis) / axisLength2, 0.0, 1.0) * axis;
+                var outward_normal: vec3<f32> = renderState.position - closest;
+                if (dot(outward_normal, outward_normal) < 1e-12) {
+                    outward_normal = -direction;
+                }
+                renderState.normal = set_face_normal(ray, normalize(outward_normal));
+                renderState.front_face = dot(ray.direction, outward_normal) < 0.0;
+                renderState.tangent = normalize(axis);
+                renderState.color = curve.color;
+                renderState.hit = true;
+            }
+        }
+        start = end;
+    }
+
+    return renderState;
+}
+
+// Tests each point of the cluster as a sphere, keeping the nearest hit
+fn hit_point_cluster(ray: Ray, cluster: PointCluster, tMin: f32, tMax: f32, oldRenderState: RenderState) -> RenderState {
+    var renderState: RenderState;
+    renderState.color = oldRenderState.color;
+    renderState.hit = false;
+    var nearestHit: f32 = tMax;
+
+    for (var i: u32 = 0; i < cluster.count; i++) {
+        let point: vec4<u32> = points[cluster.first + i];
+        let srgb: vec3<f32> = unpack4x8unorm(point.w).xyz;
+        let sphere: Sphere = Sphere(
+            bitcast<vec3<f32>>(point.xyz),
+            cluster.radius,
+            cluster.color * pow(srgb, vec3<f32>(2.2)),
+            0.0, 0.0, vec3<f32>(0.0), 0.0, // Solid color, no texture
+        );
+        let state: RenderState = hit_sphere(ray, sphere, tMin, nearestHit, renderState);
+        if (state.hit) {
+            nearestHit = state.t;
+            renderState = state;
+        }
+    }
+
+    return renderState;
+}
+
 fn hit_sphere(ray: Ray, sphere: Sphere, tMin: f32, tMax: f32, oldRenderState: RenderState) -> RenderState {
     
     let oc: vec3<f32> = ray.origin - sphere.center;
@@ -239,7 +1543,11 @@ fn hit_sphere(ray: Ray, sphere: Sphere, tMin: f32, tMax: f32, oldRenderState: Re
 
     if (discriminant > 0.0) {
 
-        let t: f32 = (-half_b - sqrt(discriminant)) / a;
+        //the far side is hit from inside, like a ray leaving a glass ball
+        var t: f32 = (-half_b - sqrt(discriminant)) / a;
+        if (t <= tMin) {
+            t = (-half_b + sqrt(discriminant)) / a;
+        }
 
         if (t > tMin && t < tMax) {
             // First set the position of the ray using the ray formular
@@ -247,8 +1555,10 @@ fn hit_sphere(ray: Ray, sphere: Sphere, tMin: f32, tMax: f32, oldRenderState: Re
             // Get the normal
             let outward_normal: vec3<f32> = (renderState.position - sphere.center) / sphere.radius;
             renderState.normal = set_face_normal(ray, outward_normal);
+            renderState.front_face = dot(ray.direction, outward_normal) < 0.0;
             renderState.t = t;
-            renderState.color = sphere.color;
+            //one turn around the sphere spans the texture's width
+            renderState.color = sphere_color(sphere, sphere_uv(outward_normal), t, 6.2831853 * sphere.radius);
             renderState.hit = true;
             return renderState;
         }
@@ -259,7 +1569,30 @@ fn hit_sphere(ray: Ray, sphere: Sphere, tMin: f32, tMax: f32, oldRenderState: Re
     
 }
 
-fn hit_triangle(ray: Ray, tri: Triangle, tMin: f32, tMax: f32, oldRenderState: RenderState) -> RenderState {
+// Latitude/longitude UVs with v = 0 at the north pole, matching image row order
+fn sphere_uv(outward_normal: vec3<f32>) -> vec2<f32> {
+    let pi: f32 = 3.14159265;
+    let u: f32 = 0.5 + atan2(-outward_normal.z, outward_normal.x) / (2.0 * pi);
+    let v: f32 = 0.5 - asin(clamp(outward_normal.y, -1.0, 1.0)) / pi;
+    return vec2<f32>(u, v);
+}
+
+fn sphere_color(sphere: Sphere, uv: vec2<f32>, t: f32, span: f32) -> vec3<f32> {
+    if (sphere.textureKind == 1.0) {
+        let texel: vec4<f32> = sample_texture(sphere.texture, uv, t, span);
+        return sphere.color * texel.xyz;
+    } else if (sphere.textureKind == 2.0) {
+        let cell: vec2<i32> = vec2<i32>(floor(uv * vec2<f32>(2.0, 1.0) * sphere.checkerScale));
+        if ((cell.x + cell.y) % 2 != 0) {
+            return sphere.checkerColor;
+        }
+    }
+    return sphere.color;
+}
+
+fn hit_triangle(ray: Ray, tri: Triangle, material: Material, tMin: f32, tMax: f32, oldRenderState: RenderState) -> RenderState {
+    triangleTests += 1u;
+    let materialFlags: u32 = u32(material.flags);
     //Set up a blank renderstate,
     //right now this hasn't hit anything
     var renderState: RenderState;
@@ -269,31 +1602,64 @@ fn hit_triangle(ray: Ray, tri: Triangle, tMin: f32, tMax: f32, oldRenderState: R
     //Direction vectors
     let edge_ab: vec3<f32> = tri.corner_b - tri.corner_a;
     let edge_ac: vec3<f32> = tri.corner_c - tri.corner_a;
+    let normal: vec3<f32> = normalize(cross(edge_ab, edge_ac));
 
-    let h: vec3<f32> = cross(ray.direction, edge_ac);
-    let a: f32 =  dot(edge_ab, h);
-
-    //early exit, ray parallel with triangle surface
-    if (a > -0.0001 && a < 0.0001) {
+    //backface culling, the ray travels along the triangle's normal
+    if ((materialFlags & 1u) != 0u && dot(ray.direction, normal) >= 0.0) {
         return renderState;
     }
 
-    let f: f32 = 1.0 / a;
-    let s: vec3<f32> = ray.origin - tri.corner_a;
-    let u: f32 = f * dot(s,h);
-    
-    if (u < 0.0 || u > 1.0) {
-        return renderState;
+    //Watertight intersection (Woop, Benthin, Wald 2013): shear the triangle into ray space
+    //so edges shared by neighbouring triangles are evaluated identically and never leave cracks.
+    //kz is the dominant axis of the ray direction, kx and ky keep the winding order
+    let absDir: vec3<f32> = abs(ray.direction);
+    var kz: u32 = 2u;
+    if (absDir.x > absDir.y && absDir.x > absDir.z) {
+        kz = 0u;
+    } else if (absDir.y > absDir.z) {
+        kz = 1u;
     }
+    var kx: u32 = (kz + 1u) % 3u;
+    var ky: u32 = (kx + 1u) % 3u;
+    if (ray.direction[kz] < 0.0) {
+        let temp: u32 = kx;
+        kx = ky;
+        ky = temp;
+    }
+
+    //Shear constants
+    let shear_x: f32 = ray.direction[kx] / ray.direction[kz];
+    let shear_y: f32 = ray.direction[ky] / ray.direction[kz];
+    let shear_z: f32 = 1.0 / ray.direction[kz];
 
-    let q: vec3<f32> = cross(s, edge_ab);
-    let v: f32 = f * dot(ray.direction, q);
+    //Corners relative to the ray origin, sheared so the ray points down +z
+    let a: vec3<f32> = tri.corner_a - ray.origin;
+    let b: vec3<f32> = tri.corner_b - ray.origin;
+    let c: vec3<f32> = tri.corner_c - ray.origin;
+    let ax: f32 = a[kx] - shear_x * a[kz];
+    let ay: f32 = a[ky] - shear_y * a[kz];
+    let bx: f32 = b[kx] - shear_x * b[kz];
+    let by: f32 = b[ky] - shear_y * b[kz];
+    let cx: f32 = c[kx] - shear_x * c[kz];
+    let cy: f32 = c[ky] - shear_y * c[kz];
 
-    if (v < 0.0 || u + v > 1.0) {
+    //Scaled barycentric coordinates, the ray misses unless all share a sign
+    let u: f32 = cx * by - cy * bx;
+    let v: f32 = ax * cy - ay * cx;
+    let w: f32 = bx * ay - by * ax;
+    if ((u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0)) {
         return renderState;
     }
 
-    let t: f32 = f * dot(edge_ac, q);
+    //early exit, ray parallel with triangle surface
+    let det: f32 = u + v + w;
+    if (det == 0.0) {
+        return renderState;
+    }
+
+    //Hit distance from the barycentric-weighted sheared depths
+    let scaled_t: f32 = u * shear_z * a[kz] + v * shear_z * b[kz] + w * shear_z * c[kz];
+    let t: f32 = scaled_t / det;
 
     // //Normal of the triangle
     // var n: vec3<f32> = normalize(cross(edge_ab, edge_ac));
@@ -347,10 +1713,38 @@ fn hit_triangle(ray: Ray, tri: Triangle, tMin: f32, tMax: f32, oldRenderState: R
     // let t: f32 = determinant(system_matrix) / denominator;
 
     if (t > tMin && t < tMax) {
+        //alpha cutout, corners b and c sit at u = 1 and v = 1
+        let texel: vec4<f32> = albedo_texel(material, vec2<f32>(v, w) / det, t, length(edge_ab));
+        if (texel.a < material.alphaCutoff) {
+            return renderState;
+        }
 
         renderState.position = ray.origin + t * ray.direction;
-        renderState.normal = normalize(cross(edge_ab, edge_ac));
-        renderState.color = tri.color;
+        renderState.normal = normal;
+        renderState.front_face = dot(ray.direction, normal) < 0.0;
+        //smooth shading, blending the corner normals kept on the side of the face
+        if (tri.normals >= 0.0) {
+            let first: u32 = u32(tri.normals);
+            let normal_a: vec3<f32> = bitcast<vec3<f32>>(points[first].xyz);
+            let normal_b: vec3<f32> = bitcast<vec3<f32>>(points[first + 1u].xyz);
+            let normal_c: vec3<f32> = bitcast<vec3<f32>>(points[first + 2u].xyz);
+            let shading: vec3<f32> = (u * normal_a + v * normal_b + w * normal_c) / det;
+            if (dot(shading, shading) > 0.0) {
+                let smooth_normal: vec3<f32> = normalize(shading);
+                renderState.normal = select(-smooth_normal, smooth_normal, dot(smooth_normal, normal) >= 0.0);
+            }
+        }
+        //two-sided shading, face the normal towards the ray
+        if ((materialFlags & 2u) != 0u) {
+            renderState.normal = set_face_normal(ray, renderState.normal);
+        }
+        renderState.color = tri.color * texel.xyz;
+        if (tri.vertex_colors.x >= 0.0) {
+            let color_a: vec3<f32> = pow(unpack4x8unorm(u32(tri.vertex_colors.x)).xyz, vec3(2.2));
+            let color_b: vec3<f32> = pow(unpack4x8unorm(u32(tri.vertex_colors.y)).xyz, vec3(2.2));
+            let color_c: vec3<f32> = pow(unpack4x8unorm(u32(tri.vertex_colors.z)).xyz, vec3(2.2));
+            renderState.color *= (u * color_a + v * color_b + w * color_c) / det;
+        }
         renderState.t = t;
         renderState.hit = true;
         return renderState;
@@ -359,6 +1753,144 @@ fn hit_triangle(ray: Ray, tri: Triangle, tMin: f32, tMax: f32, oldRenderState: R
     return renderState;
 }
 
+fn hit_quad(ray: Ray, quad: Quad, material: Material, tMin: f32, tMax: f32, oldRenderState: RenderState) -> RenderState {
+    let materialFlags: u32 = u32(material.flags);
+    var renderState: RenderState;
+    renderState.color = oldRenderState.color;
+    renderState.hit = false;
+
+    let n: vec3<f32> = cross(quad.edge_u, quad.edge_v);
+    let denominator: f32 = dot(n, ray.direction);
+
+    //early exit, ray parallel with the quad's plane
+    if (abs(denominator) < 1e-8) {
+        return renderState;
+    }
+
+    //backface culling, the ray travels along the quad's normal
+    if ((materialFlags & 1u) != 0u && denominator > 0.0) {
+        return renderState;
+    }
+
+    let t: f32 = dot(n, quad.corner - ray.origin) / denominator;
+    if (t <= tMin || t >= tMax) {
+        return renderState;
+    }
+
+    //Coordinates of the hit point along both edges, inside when both lie in [0, 1]
+    let position: vec3<f32> = ray.origin + t * ray.direction;
+    let planar: vec3<f32> = position - quad.corner;
+    let w: vec3<f32> = n / dot(n, n);
+    let alpha: f32 = dot(w, cross(planar, quad.edge_v));
+    let beta: f32 = dot(w, cross(quad.edge_u, planar));
+    if (alpha < 0.0 || alpha > 1.0 || beta < 0.0 || beta > 1.0) {
+        return renderState;
+    }
+
+    //alpha cutout, traversal keeps looking for hits behind transparent texels
+    let texel: vec4<f32> = albedo_texel(material, vec2<f32>(alpha, beta), t, length(quad.edge_u));
+    if (texel.a < material.alphaCutoff) {
+        return renderState;
+    }
+
+    renderState.position = position;
+    renderState.normal = normalize(n);
+    renderState.front_face = denominator < 0.0;
+    //two-sided shading, face the normal towards the ray
+    if ((materialFlags & 2u) != 0u) {
+        renderState.normal = set_face_normal(ray, renderState.normal);
+    }
+    renderState.color = quad.color * texel.xyz;
+    renderState.t = t;
+    renderState.hit = true;
+    return renderState;
+}
+
+// Rectangle turned towards the camera origin, so reflections see it facing the same way as the camera.
+// Transparent texels of the label are skipped, leaving just the text.
+fn hit_billboard(ray: Ray, billboard: Billboard, tMin: f32, tMax: f32, oldRenderState: RenderState) -> RenderState {
+    var renderState: RenderState;
+    renderState.color = oldRenderState.color;
+    renderState.hit = false;
+
+    let toCamera: vec3<f32> = frame.cameraOrigin - billboard.center;
+    if (dot(toCamera, toCamera) < 1e-12) {
+        return renderState;
+    }
+    let n: vec3<f32> = normalize(toCamera);
+    //keep the text upright, falling back to another axis when looking straight down
+    var right: vec3<f32> = cross(vec3(0.0, 1.0, 0.0), n);
+    if (dot(right, right) < 1e-6) {
+        right = vec3(1.0, 0.0, 0.0);
+    }
+    right = normalize(right);
+    let up: vec3<f32> = cross(n, right);
+
+    let denominator: f32 = dot(n, ray.direction);
+    if (abs(denominator) < 1e-8) {
+        return renderState;
+    }
+    let t: f32 = dot(n, billboard.center - ray.origin) / denominator;
+    if (t <= tMin || t >= tMax) {
+        return renderState;
+    }
+
+    //Coordinates of the hit point from -1 to 1 across the rectangle
+    let position: vec3<f32> = ray.origin + t * ray.direction;
+    let x: f32 = dot(position - billboard.center, right) / billboard.halfWidth;
+    let y: f32 = dot(position - billboard.center, up) / billboard.halfHeight;
+    if (abs(x) > 1.0 || abs(y) > 1.0) {
+        return renderState;
+    }
+
+    let uv: vec2<f32> = mix(billboard.uvMin, billboard.uvMax, vec2(0.5 + 0.5 * x, 0.5 - 0.5 * y));
+    let texel: vec4<f32> = sample_region(billboard.texture, uv, 0.0);
+    if (texel.a < 0.5) {
+        return renderState;
+    }
+
+    renderState.position = position;
+    renderState.normal = set_face_normal(ray, n);
+    renderState.front_face = true;
+    renderState.color = billboard.color * texel.xyz;
+    renderState.t = t;
+    renderState.hit = true;
+    return renderState;
+}
+
+// The material's albedo texture at the given UV, opaque white when it has none
+fn albedo_texel(material: Material, uv: vec2<f32>, t: f32, span: f32) -> vec4<f32> {
+    if (material.albedoTexture < 0.0) {
+        return vec4<f32>(1.0);
+    }
+    return sample_texture(material.albedoTexture, uv, t, span);
+}
+
+// Samples a texture hit at distance t, where one unit of u covers span units of the surface
+fn sample_texture(texture: f32, uv: vec2<f32>, t: f32, span: f32) -> vec4<f32> {
+    let texels: f32 = f32(textureDimensions(objectTextures).x) * textureRegions[u32(texture)].scale.x;
+    return sample_region(texture, uv, texture_lod(t, texels / span));
+}
+
+// Looks the texture up in the region table, wrapping UVs inside packed regions.
+// Textures filling a layer leave the wrapping to the sampler.
+fn sample_region(texture: f32, uv: vec2<f32>, lod: f32) -> vec4<f32> {
+    let region: TextureRegion = textureRegions[u32(texture)];
+    var regionUv: vec2<f32> = uv;
+    if (region.scale.x < 1.0 || region.scale.y < 1.0) {
+        regionUv = fract(uv);
+    }
+    regionUv = region.offset + regionUv * region.scale;
+    return textureSampleLevel(objectTextures, objectTextureSampler, regionUv, i32(region.layer), min(lod, region.maxLod));
+}
+
+// Mip level whose texels match the ray cone's width at distance t along the current ray,
+// given how many texels of the base level cover one unit of the surface
+fn texture_lod(t: f32, texelsPerUnit: f32) -> f32 {
+    let footprint: f32 = (coneWidth + t * coneSpread) * texelsPerUnit;
+    return max(0.0, log2(max(footprint, 1e-8)));
+}
+
 fn hit_aabb(ray: Ray, node: Node) -> f32 {
     var inverseDir: vec3<f32> = vec3(1.0) / ray.direction;
     var t1: vec3<f32> = (node.minCorner - ray.origin) * inverseDir;
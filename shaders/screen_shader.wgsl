@@ -1,5 +1,14 @@
 @group(0) @binding(0) var screen_sampler : sampler;
 @group(0) @binding(1) var color_buffer : texture_2d<f32>;
+@group(0) @binding(2) var<uniform> post : PostProcess;
+
+// Stylization of the view, each effect is off at 0. Laid out like PostProcess::uniform_data.
+struct PostProcess {
+    chromaticAberration: f32, // Pixels the red and blue channels are pulled apart by at the edges
+    vignette: f32, // How much the corners darken
+    grain: f32, // Strength of the film grain
+    seed: u32, // Changes every frame, so the grain does too
+}
 
 struct VertexOutput {
     @builtin(position) Position : vec4<f32>,
@@ -34,6 +43,29 @@ fn vert_main(@builtin(vertex_index) VertexIndex : u32) -> VertexOutput {
 }
 
 @fragment
-fn frag_main(@location(0) TexCoord : vec2<f32>) -> @location(0) vec4<f32> {
-    return textureSample(color_buffer, screen_sampler, TexCoord);
-}
\ No newline at end of file
+fn frag_main(@builtin(position) Position : vec4<f32>, @location(0) TexCoord : vec2<f32>) -> @location(0) vec4<f32> {
+    var color: vec3<f32> = textureSample(color_buffer, screen_sampler, TexCoord).rgb;
+    let centered: vec2<f32> = TexCoord - 0.5;
+
+    // Red is pushed outwards and blue inwards, more the further from the center
+    let shift: vec2<f32> = centered * 2.0 * post.chromaticAberration / vec2<f32>(textureDimensions(color_buffer));
+    color.r = textureSample(color_buffer, screen_sampler, TexCoord + shift).r;
+    color.b = textureSample(color_buffer, screen_sampler, TexCoord - shift).b;
+
+    // Falls off with the square of the distance, reaching its full strength in the corners
+    let falloff: f32 = dot(centered, centered) * 2.0;
+    color *= 1.0 - post.vignette * falloff * falloff;
+
+    let pixel: vec2<u32> = vec2<u32>(Position.xy);
+    let noise: f32 = f32(hash(pixel.x ^ hash(pixel.y ^ hash(post.seed)))) / 4294967295.0 - 0.5;
+    color = max(color + noise * post.grain, vec3<f32>(0.0));
+
+    return vec4<f32>(color, 1.0);
+}
+
+// PCG hash of one word
+fn hash(value: u32) -> u32 {
+    let state: u32 = value * 747796405u + 2891336453u;
+    let word: u32 = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
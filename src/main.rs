@@ -1,97 +1,1081 @@
-mod raytracer;
-use raytracer::{Scene, State, Vec3};
-use winit::{event::{ElementState, Event, KeyEvent, WindowEvent}, event_loop::EventLoopBuilder, keyboard::{KeyCode, PhysicalKey}, window::WindowBuilder};
+use rust_raytracing_wgpu::raytracer::{append_results_csv, atlas_obj, job_camera, parse_batch_jobs, bake_ambient_occlusion, bake_lightmap, AoBake, LightmapBake, Object, ObjectId, results_json, run_benchmark, BenchRun, BenchScene, enumerate_adapters, parse_command, Asset, Camera, Command, FileWatcher, find_adapter, is_adapter_supported, placeholder_scene, init_logging, print_adapters, render_distributed, render_fingerprint, resume_cpu, resume_offline, Checkpoint, request_device, serve_worker, encode_jpeg, RemoteInput, RemoteView, Renderer, Replay, ReplayRecorder, save_radiance, CaptureFormat, Projection, Scene, SceneLoader, SkySource, State, TextureFiltering, Vec3};
+#[cfg(feature = "scripting")]
+use rust_raytracing_wgpu::raytracer::SceneScript;
+#[cfg(feature = "denoise")]
+use rust_raytracing_wgpu::raytracer::denoise_render;
+#[cfg(feature = "scripting")]
+use rhai::EvalAltResult;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+use winit::{event::{ElementState, Event, KeyEvent, WindowEvent}, event_loop::{ControlFlow, EventLoop}, keyboard::{KeyCode, ModifiersState, PhysicalKey}, window::{Fullscreen, Icon, WindowBuilder}};
 
-#[derive(Debug, Clone, Copy)]
-enum CustomEvent {
-    Timer,
-}
+const WINDOW_TITLE: &str = "Ray Tracer";
 
 pub async fn run() {
-    env_logger::init();
+    // `--log-level debug` shows messages down to that level, like RUST_LOG, which is used without it.
+    // `--chrome-trace trace.json` writes the spans of loading and of every frame there, for
+    // chrome://tracing or Perfetto, when built with the chrome-trace feature.
+    let _logging = init_logging(arg_value("--log-level").as_deref(), arg_value("--chrome-trace").as_deref());
+
+    // `--list-adapters` prints the GPUs `--adapter` can pick from and exits
+    if std::env::args().any(|arg| arg == "--list-adapters") {
+        print_adapters(&wgpu::Instance::new(wgpu::InstanceDescriptor::default()));
+        return;
+    }
+    // `--worker ADDRESS` listens there for offline renders sent with `--workers` and traces their tiles
+    if let Some(address) = arg_value("--worker") {
+        let listener = std::net::TcpListener::bind(&address).expect("Failed to listen for render coordinators");
+        info!("Render worker listening on {}", listener.local_addr().unwrap());
+        serve_worker(listener).expect("Render worker failed");
+        return;
+    }
+    // `--bench results.json` times the benchmark scenes and exits, see run_bench
+    if let Some(path) = arg_value("--bench") {
+        run_bench(&path);
+        return;
+    }
+    // `--remote ADDRESS` renders without a window and serves the view to browsers, see run_remote
+    if let Some(address) = arg_value("--remote") {
+        run_remote(&address);
+        return;
+    }
+    // `--offline out.png` renders without a window and exits, see run_offline
+    if let Some(path) = arg_value("--offline") {
+        run_offline(&path);
+        return;
+    }
+    // `--batch jobs.txt` renders every job of the list one after another and exits, see run_batch
+    if let Some(path) = arg_value("--batch") {
+        run_batch(&path);
+        return;
+    }
+    // `--bake-ao out.png` bakes the ambient occlusion of a mesh into a texture and exits, see run_bake_ao
+    if let Some(path) = arg_value("--bake-ao") {
+        run_bake_ao(&path);
+        return;
+    }
+    // `--bake-lightmaps FOLDER` bakes the lightmaps of meshes into a folder and exits, see run_bake_lightmaps
+    if let Some(folder) = arg_value("--bake-lightmaps") {
+        run_bake_lightmaps(&folder);
+        return;
+    }
+    // Without an adapter there is nothing to present with, so the CPU renders to a file instead
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    if cpu_backend() || pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).is_none() {
+        info!("Rendering on the CPU to render.png");
+        run_offline("render.png");
+        return;
+    }
 
-    let event_loop = EventLoopBuilder::<CustomEvent>::with_user_event()
-        .build()
+    let event_loop = EventLoop::new().unwrap();
+    // Below the minimum the editor's panels and the progress bar no longer fit
+    let window = WindowBuilder::new()
+        .with_title(WINDOW_TITLE)
+        .with_window_icon(load_window_icon("assets/gfx/icon.png"))
+        .with_min_inner_size(winit::dpi::LogicalSize::new(320.0, 240.0))
+        .build(&event_loop)
         .unwrap();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
-    let event_loop_proxy = event_loop.create_proxy();
+    // `--debug-view` opens a second window looking down on the scene from above, traced with the same
+    // device and scene buffers as the main one
+    let debug_window = std::env::args().any(|arg| arg == "--debug-view").then(|| WindowBuilder::new()
+        .with_title(format!("{} - Top-down", WINDOW_TITLE))
+        .with_window_icon(load_window_icon("assets/gfx/icon.png"))
+        .with_min_inner_size(winit::dpi::LogicalSize::new(320.0, 240.0))
+        .build(&event_loop)
+        .unwrap());
+    let debug_window_id = debug_window.as_ref().map(|window| window.id());
 
-    std::thread::spawn(move || loop {
-        std::thread::sleep(std::time::Duration::from_millis(17));
-        event_loop_proxy.send_event(CustomEvent::Timer).ok();
-    });
+    // `--fps 30` caps the frame rate, 60 by default, and `--fps 0` renders as fast as the present mode allows
+    let fps: u32 = arg_value("--fps").map_or(60, |fps| fps.parse().expect("Frame rate is not a number"));
+    let frame_interval = (fps > 0).then(|| Duration::from_secs_f64(1.0 / fps as f64));
+    let mut next_frame = Instant::now();
 
     // make the scene
-    let mut scene = Scene::new(40, window.outer_size().width as f32, window.outer_size().height as f32);
-    // scene.add_square(Vec3(0.0, 0.5, 0.0), 10.0, 10.0, Vec3(0.0, 1.0, 0.0), 0.0);
-    scene.add_sphere(Vec3(0.0, 0.0, -1.0), Vec3(1.0, 0.0, 0.0), 0.5);
-    // scene.add_object_mesh("assets/models/statue.obj");
-    // scene.add_sphere(Vec3(1.5, 0.0, -1.0), Vec3(0.0, 1.0, 0.0), 0.5);
-    // scene.add_sphere(Vec3(-1.5, 0.0, -1.0), Vec3(0.0, 0.0, 1.0), 0.5);
-    scene.make_scene();
-    
+    let mut scene = Scene::new(40, window.inner_size().width as f32, window.inner_size().height as f32);
+
+    // A script passed with `--script scene.rhai` builds the scene instead of the default one
+    #[cfg(feature = "scripting")]
+    let mut script = arg_value("--script").map(|path| run_script(&path, &mut scene).expect("Scene script failed"));
 
-    let mut program_state: State<'_> = State::new(&window, scene).await;
+    apply_scene_options(&mut scene);
+    // `--bvh-cache scene.bvh` reuses the BVH from an earlier run with the same geometry
+    let bvh_cache = arg_value("--bvh-cache");
+    // `--stats` logs object counts, BVH quality and GPU memory once the scene is built
+    let show_stats = std::env::args().any(|arg| arg == "--stats");
+    // `--probes SPACING` spreads irradiance probes that far apart over the loaded scene, lighting
+    // matte bounces from them instead of tracing further
+    let probe_spacing: Option<f32> = arg_value("--probes").map(|spacing| spacing.parse().expect("Probe spacing is not a number"));
+    // `--watch` reads the script, meshes, textures and skies again when their files change
+    let mut watcher = std::env::args().any(|arg| arg == "--watch").then(FileWatcher::default);
+    #[cfg(feature = "scripting")]
+    if let (Some(watcher), Some(path)) = (&mut watcher, arg_value("--script")) {
+        watcher.watch(&path, Asset::Script);
+    }
+    // `--commands demo.txt` runs a console command per line once the scene has loaded, to set up
+    // and capture renders for tests and demos. The editor's console, opened with the backtick
+    // key, queues the commands typed into it after them.
+    let mut commands = arg_value("--commands").map_or(VecDeque::new(), |path| read_commands(&path));
+    // `--record-replay replay.bin` writes where the camera and the objects are every frame, and
+    // `--replay replay.bin` puts them there again frame by frame instead of following the keys,
+    // the camera path and the script, then exits. Performance traces and bug reports of a replay
+    // see the same frames every time.
+    let record_path = arg_value("--record-replay");
+    let mut recorder: Option<ReplayRecorder> = None;
+    let mut replay = arg_value("--replay").map(|path| Replay::load(&path).unwrap_or_else(|e| panic!("Failed to read replay {}: {}", path, e)));
 
-    event_loop.run(move | event, elwt | match event {
-        Event::UserEvent(..) => {
-            program_state.window.request_redraw();
-            program_state.scene.update();
+    // The BVH is built and the sky and textures decoded in the background, a placeholder
+    // with a progress bar is shown until they are ready
+    let mut loader = Some(SceneLoader::spawn(scene, bvh_cache.clone()));
+    let placeholder = placeholder_scene(window.inner_size().width as f32, window.inner_size().height as f32);
+    // `--adapter 1|discrete|nvidia` renders on the adapter with that index, kind or name instead of the default
+    let adapter_choice = arg_value("--adapter");
+    let mut program_state: State<'_> = match State::new(&window, placeholder, adapter_choice.as_deref()).await {
+        Ok(state) => state,
+        Err(e) => {
+            error!("{}", e);
+            return;
         },
+    };
+    program_state.set_loading_progress(Some(0.0));
+    if let Some(debug_window) = &debug_window {
+        program_state.add_viewport(debug_window, top_down_camera(&program_state.scene));
+    }
+    // `--present-mode mailbox|fifo|immediate` picks how frames are presented, V cycles through them
+    if let Some(name) = arg_value("--present-mode") {
+        let mode = match name.as_str() {
+            "mailbox" => wgpu::PresentMode::Mailbox,
+            "fifo" => wgpu::PresentMode::Fifo,
+            "immediate" => wgpu::PresentMode::Immediate,
+            _ => panic!("Unknown present mode \"{}\"", name),
+        };
+        if !program_state.set_present_mode(mode) {
+            warn!("The window doesn't support the {:?} present mode", mode);
+        }
+    }
+    // `--idle-samples 512` stops tracing a static scene once it has that many samples per pixel,
+    // 0 keeps tracing. Input and window events still draw frames while it idles.
+    if let Some(samples) = arg_value("--idle-samples") {
+        program_state.idle_samples = samples.parse().expect("Idle sample count is not a number");
+    }
+    // `--frame-times` starts with the frame time graph shown, F3 shows or hides it
+    program_state.show_frame_times = std::env::args().any(|arg| arg == "--frame-times");
+    let mut window_changed = false;
+    // Frames drawn since the title last showed the frame rate
+    let mut title_frames = 0u32;
+    let mut title_updated = Instant::now();
+    #[cfg(feature = "scripting")]
+    let mut last_update = Instant::now();
+    let mut modifiers = ModifiersState::empty();
+    #[cfg(feature = "editor")]
+    let mut cursor_position = winit::dpi::PhysicalPosition::new(0.0, 0.0);
 
-        Event::WindowEvent { window_id, ref event } if window_id == program_state.window.id() => match event {
-            WindowEvent::Resized(physical_size) => program_state.resize(*physical_size),
-
-            WindowEvent::CloseRequested 
-            | WindowEvent::KeyboardInput { 
-                event: 
-                    KeyEvent { 
-                        physical_key: PhysicalKey::Code(KeyCode::Escape), 
-                        state: ElementState::Pressed, repeat: false, .. }, .. } => {
-                println!("Goodbye see you!");
-                elwt.exit();
-            }
+    event_loop.run(move | event, elwt | {
+        // Input and window changes are drawn even while the render idles, e.g. for the editor's panels
+        if matches!(event, Event::WindowEvent { ref event, .. } if !matches!(event, WindowEvent::RedrawRequested)) {
+            window_changed = true;
+        }
+        match event {
+            // Frames are paced here: once the next one is due the scene takes a step and a redraw is
+            // requested, then the loop sleeps until the one after
+            Event::AboutToWait => {
+                let now = Instant::now();
+                if now >= next_frame {
+                    next_frame = frame_interval.map_or(now, |interval| (next_frame + interval).max(now));
 
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        physical_key,
-                        state,
-                        ..
-                    },
-                ..
-            } => {
-                let key_code = match physical_key {
-                    PhysicalKey::Code(code) => Some(code),
-                    _ => None,
-                };
-                match state {
-                    ElementState::Pressed => {
-                        if let Some(code) = key_code {
-                            program_state.scene.keys_pressed.insert(*code);
+                    if let Some(active) = loader.as_mut() {
+                        match active.poll() {
+                            Some(loaded) => {
+                                if show_stats {
+                                    info!("Scene stats:\n{}", loaded.scene.stats());
+                                }
+                                program_state.set_loading_progress(None);
+                                // The placeholder stays up when the scene doesn't fit on the device
+                                if let Err(e) = program_state.replace_scene(loaded) {
+                                    error!("{}", e);
+                                } else {
+                                    if let Some(window_id) = debug_window_id {
+                                        let camera = top_down_camera(&program_state.scene);
+                                        program_state.set_viewport_camera(window_id, camera);
+                                    }
+                                    if let Some(watcher) = &mut watcher {
+                                        watcher.watch_scene(&program_state.scene);
+                                    }
+                                    if let Some(spacing) = probe_spacing {
+                                        program_state.scene.fit_probe_grid(spacing);
+                                    }
+                                    if let Some(Err(e)) = replay.as_ref().map(|replay| replay.check(&program_state.scene)) {
+                                        warn!("The replay may not match the scene: {}", e);
+                                    }
+                                    // A scene loaded again starts the recording over
+                                    if let Some(path) = &record_path {
+                                        recorder = ReplayRecorder::create(path, &program_state.scene)
+                                            .map_err(|e| error!("Failed to record replay {}: {}", path, e))
+                                            .ok();
+                                    }
+                                }
+                                loader = None;
+                                // Time spent loading isn't a step of the script's animation
+                                #[cfg(feature = "scripting")]
+                                {
+                                    last_update = Instant::now();
+                                }
+                            },
+                            None => program_state.set_loading_progress(Some(active.fraction)),
                         }
-                    },
-                    ElementState::Released => {
-                        if let Some(code) = key_code {
-                            program_state.scene.keys_pressed.remove(&code);
+                    } else {
+                        let moved = match &mut replay {
+                            Some(active) => active.step(&mut program_state.scene).unwrap_or_else(|| {
+                                info!("Replayed {} frames", active.frames.len());
+                                elwt.exit();
+                                false
+                            }),
+                            None => program_state.scene.update(),
+                        };
+                        if moved {
+                            program_state.reset_accumulation();
+                        }
+
+                        #[cfg(feature = "scripting")]
+                        if let Some(script) = script.as_mut().filter(|_| replay.is_none()) {
+                            let dt = last_update.elapsed().as_secs_f32();
+                            last_update = Instant::now();
+                            if let Err(e) = script.on_update(&mut program_state.scene, dt) {
+                                error!("on_update failed: {}", e);
+                            }
+                        }
+
+                        // Meshes, textures and skies are swapped in place, a changed script builds
+                        // the scene again in the background while the current one stays up
+                        for asset in watcher.as_mut().map_or(Vec::new(), FileWatcher::changed) {
+                            let reloaded = match asset {
+                                Asset::Mesh(id) => program_state.scene.reload_mesh(id).map(|_| ()).map_err(|e| e.to_string()),
+                                Asset::Textures => program_state.reload_textures().map_err(|e| e.to_string()),
+                                Asset::Sky(index) => program_state.reload_sky(index).map_err(|e| e.to_string()),
+                                #[cfg(feature = "scripting")]
+                                Asset::Script => {
+                                    script_scene(&arg_value("--script").unwrap(), program_state.window.inner_size()).map(|(reloaded, scene)| {
+                                        script = Some(reloaded);
+                                        program_state.set_loading_progress(Some(0.0));
+                                        loader = Some(SceneLoader::spawn(scene, bvh_cache.clone()));
+                                    }).map_err(|e| e.to_string())
+                                },
+                                #[cfg(not(feature = "scripting"))]
+                                Asset::Script => Ok(()),
+                            };
+                            if let Err(e) = reloaded {
+                                warn!("Failed to reload {:?}: {}", asset, e);
+                            }
+                        }
+
+                        #[cfg(feature = "editor")]
+                        commands.extend(program_state.take_console_commands());
+                        while let Some(command) = commands.pop_front() {
+                            let result = match command {
+                                // The commands after a wait see the view once it has the samples
+                                Command::Wait(samples) if program_state.sample_count() < samples => {
+                                    commands.push_front(command);
+                                    break;
+                                },
+                                // Scripts build a new scene, loaded in the background like at startup
+                                #[cfg(feature = "scripting")]
+                                Command::Load(ref path) if path.ends_with(".rhai") => {
+                                    script_scene(path, program_state.window.inner_size()).map(|(loaded, scene)| {
+                                        script = Some(loaded);
+                                        program_state.set_loading_progress(Some(0.0));
+                                        loader = Some(SceneLoader::spawn(scene, bvh_cache.clone()));
+                                        format!("Loading {}", path)
+                                    }).map_err(|e| e.to_string())
+                                },
+                                _ => program_state.run_command(&command),
+                            };
+                            match result {
+                                Ok(message) => program_state.console_print(&message),
+                                Err(e) => program_state.console_print(&format!("Error: {}", e)),
+                            }
+                            // The rest run on the new scene once it has loaded
+                            if loader.is_some() {
+                                break;
+                            }
+                        }
+                    }
+
+                    if let Some(active) = &mut recorder {
+                        if let Err(e) = active.record(&program_state.scene) {
+                            error!("Failed to record the frame, stopping the replay recording: {}", e);
+                            recorder = None;
+                        }
+                    }
+
+                    // The step is uploaded while the GPU still traces the last frame, the redraw only records
+                    program_state.update();
+
+                    // A converged render needs no new frames until something changes, which saves power
+                    if !program_state.is_idle() || window_changed {
+                        program_state.window.request_redraw();
+                        window_changed = false;
+                    }
+                }
+                elwt.set_control_flow(match frame_interval {
+                    Some(_) => ControlFlow::WaitUntil(next_frame),
+                    None => ControlFlow::Poll,
+                });
+            },
+
+            Event::WindowEvent { window_id, ref event } if window_id == program_state.window.id() && !editor_consumes(&mut program_state, event) => match event {
+                WindowEvent::Resized(physical_size) => program_state.resize(*physical_size),
+                // The window keeps the size the OS suggests for the new scale factor, the color buffer
+                // follows it in physical pixels when the next frame is drawn
+                WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                    debug!("Scale factor: {}", scale_factor);
+                    program_state.window.request_redraw();
+                },
+
+                WindowEvent::CloseRequested 
+                | WindowEvent::KeyboardInput { 
+                    event: 
+                        KeyEvent { 
+                            physical_key: PhysicalKey::Code(KeyCode::Escape), 
+                            state: ElementState::Pressed, repeat: false, .. }, .. } => {
+                    info!("Goodbye see you!");
+                    elwt.exit();
+                }
+
+                WindowEvent::ModifiersChanged(new_modifiers) => modifiers = new_modifiers.state(),
+
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key,
+                            state,
+                            repeat,
+                            ..
+                        },
+                    ..
+                } => {
+                    let key_code = match physical_key {
+                        PhysicalKey::Code(code) => Some(code),
+                        _ => None,
+                    };
+                    match state {
+                        ElementState::Pressed => {
+                            if let Some(code) = key_code {
+                                // Shortcuts like Ctrl+D shouldn't also move the camera
+                                if !modifiers.control_key() {
+                                    program_state.scene.keys_pressed.insert(*code);
+                                }
+
+                                if *code == KeyCode::KeyN && !repeat {
+                                    program_state.scene.next_sky();
+                                }
+                                if *code == KeyCode::KeyV && !repeat && !modifiers.control_key() {
+                                    info!("Present mode: {:?}", program_state.cycle_present_mode());
+                                }
+                                if *code == KeyCode::KeyF && !repeat {
+                                    program_state.scene.texture_filtering = program_state.scene.texture_filtering.next();
+                                }
+                                if *code == KeyCode::F3 && !repeat {
+                                    program_state.show_frame_times = !program_state.show_frame_times;
+                                }
+                                // F11 switches between a window and borderless fullscreen, Resized follows either way
+                                if *code == KeyCode::F11 && !repeat {
+                                    let fullscreen = program_state.window.fullscreen().is_none().then_some(Fullscreen::Borderless(None));
+                                    program_state.window.set_fullscreen(fullscreen);
+                                }
+                                // Ctrl+Z undoes the last edit, Ctrl+Y or Ctrl+Shift+Z redoes it. With the editor,
+                                // Ctrl+C and Ctrl+V copy and paste the selected objects and Ctrl+D duplicates them.
+                                if modifiers.control_key() && !repeat {
+                                    match code {
+                                        KeyCode::KeyZ if modifiers.shift_key() => { program_state.scene.redo(); },
+                                        KeyCode::KeyZ => { program_state.scene.undo(); },
+                                        KeyCode::KeyY => { program_state.scene.redo(); },
+                                        #[cfg(feature = "editor")]
+                                        KeyCode::KeyC => program_state.copy_selected(),
+                                        #[cfg(feature = "editor")]
+                                        KeyCode::KeyV => program_state.paste(),
+                                        #[cfg(feature = "editor")]
+                                        KeyCode::KeyD => program_state.duplicate_selected(),
+                                        _ => {},
+                                    }
+                                }
+
+                                // Number keys show or hide the matching layer
+                                if let Some(layer) = layer_for_key(*code).filter(|_| !repeat) {
+                                    program_state.scene.camera.toggle_layer(layer);
+                                    program_state.reset_accumulation();
+                                    let shown = program_state.scene.camera.render_mask & (1 << layer) != 0;
+                                    info!("Layer {} {}", layer + 1, if shown { "shown" } else { "hidden" });
+                                }
+                            }
+                        },
+                        ElementState::Released => {
+                            if let Some(code) = key_code {
+                                program_state.scene.keys_pressed.remove(code);
+                            }
+                        },
+                    }
+                },                    
+
+                // Clicking an object opens its material in the editor, Ctrl+click adds it to the selection
+                #[cfg(feature = "editor")]
+                WindowEvent::CursorMoved { position, .. } => cursor_position = *position,
+                #[cfg(feature = "editor")]
+                WindowEvent::MouseInput { state: ElementState::Pressed, button: winit::event::MouseButton::Left, .. } => {
+                    program_state.select_at(cursor_position.x as u32, cursor_position.y as u32, modifiers.control_key());
+                },
+
+                WindowEvent::RedrawRequested => match program_state.record() {
+                    Ok(_) => {
+                        // The frame rate, samples per pixel so far and rays traced per second, refreshed about once a second
+                        title_frames += 1;
+                        let elapsed = title_updated.elapsed();
+                        if elapsed >= Duration::from_secs(1) {
+                            let fps = title_frames as f64 / elapsed.as_secs_f64();
+                            let mut title = format!("{} - {:.0} fps, {} samples", WINDOW_TITLE, fps, program_state.sample_count());
+                            if let Some(stats) = program_state.ray_stats() {
+                                title += &format!(", {:.1} Mrays/s", stats.mrays_per_second());
+                            }
+                            program_state.window.set_title(&title);
+                            title_frames = 0;
+                            title_updated = Instant::now();
                         }
                     },
+                    // The surface has to be configured again, e.g. after the window moved to another monitor
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => program_state.resize(program_state.window.inner_size()),
+                    Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
+                    Err(e) => warn!("{:?}", e),
+                }
+
+                _ => (),
+
+            },
+
+            // Added viewports only follow their window's size, their frames are drawn with the main window's
+            Event::WindowEvent { window_id, ref event } if program_state.has_viewport(window_id) => match event {
+                WindowEvent::Resized(physical_size) => program_state.resize_viewport(window_id, *physical_size),
+                WindowEvent::CloseRequested => program_state.remove_viewport(window_id),
+                _ => (),
+            },
+
+            // The window title only shows the last second, so the whole run's rays go to the log
+            Event::LoopExiting => {
+                let (mrays, seconds) = program_state.ray_totals();
+                if seconds > 0.0 {
+                    info!("Traced {:.1} million rays over {:.1} s, {:.1} Mrays/s", mrays, seconds, mrays / seconds);
                 }
-            },                    
+            },
+
+            _ => {},
+        }
+    }).expect("Error!");
+}
+
+// Looks down on the whole scene as a plan without perspective, or on the origin while it has no objects
+fn top_down_camera(scene: &Scene) -> Camera {
+    let (min_corner, max_corner) = scene.bounds().unwrap_or((Vec3(-1.0, -1.0, -1.0), Vec3(1.0, 1.0, 1.0)));
+    Camera::top_down_orthographic(min_corner, max_corner, 1.0)
+}
+
+// The icon is optional, without the file the window gets the platform's default one
+fn load_window_icon(path: &str) -> Option<Icon> {
+    let image = match image::open(path) {
+        Ok(image) => image.into_rgba8(),
+        Err(e) => {
+            warn!("Failed to load the window icon {}: {}", path, e);
+            return None;
+        },
+    };
+    let (width, height) = image.dimensions();
+    Icon::from_rgba(image.into_raw(), width, height).ok()
+}
+
+// Renders the scene without a window at `--size 1280x720` with `--samples 256` per pixel and saves
+// it, as OpenEXR for `.exr` paths and PNG otherwise. `--adapters 0,1` splits the image into one
+// band per listed adapter, picked like `--adapter`, to render on several GPUs at once.
+// `--backend cpu`, or finding no usable adapter, traces the image on the CPU instead. `--workers`
+// sends the built scene to render workers on other machines, which trace it on their CPUs.
+// `--checkpoint render.ckpt` saves the samples so far every `--checkpoint-every 32` samples, and
+// `--resume render.ckpt` goes on from them, checkpointing to the same file.
+// `--denoise` cleans up the image with Open Image Denoise before saving it, when built with the
+// denoise feature.
+fn run_offline(path: &str) {
+    let (width, height) = image_size();
+    let samples: u32 = arg_value("--samples").map_or(256, |samples| samples.parse().expect("Sample count is not a number"));
+
+    let mut scene = Scene::new(40, width as f32, height as f32);
+    #[cfg(feature = "scripting")]
+    if let Some(script_path) = arg_value("--script") {
+        run_script(&script_path, &mut scene).expect("Scene script failed");
+    }
+    apply_scene_options(&mut scene);
+    match arg_value("--bvh-cache") {
+        Some(cache) => scene.make_scene_cached(&cache),
+        None => scene.make_scene(),
+    }
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapters: Vec<wgpu::Adapter> = match arg_value("--adapters") {
+        _ if cpu_backend() => Vec::new(),
+        Some(choices) => choices.split(',').map(|choice| {
+            find_adapter(enumerate_adapters(&instance), choice.trim())
+                .unwrap_or_else(|| panic!("No adapter matches \"{}\", --list-adapters shows them", choice))
+        }).collect(),
+        None => pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .filter(is_adapter_supported)
+            .into_iter()
+            .collect(),
+    };
+
+    let start_time = Instant::now();
+    // `--workers HOST:PORT,...` splits the render into tiles traced by `--worker` processes
+    let workers: Vec<String> = arg_value("--workers").map_or(Vec::new(), |list| list.split(',').map(|worker| worker.trim().to_string()).collect());
+    let checkpoint_path = arg_value("--checkpoint").or_else(|| arg_value("--resume"));
+    let fingerprint = render_fingerprint(&scene);
+    let radiance = if !workers.is_empty() {
+        assert!(checkpoint_path.is_none(), "Renders on workers can't be checkpointed");
+        let radiance = render_distributed(&mut scene, &workers, width, height, samples);
+        info!("Rendered {} samples on {} worker(s) in {:?}", samples, workers.len(), start_time.elapsed());
+        radiance
+    } else if adapters.is_empty() {
+        let radiance = render_checkpointed(fingerprint, width, height, samples, checkpoint_path.as_deref(), |frames, accumulation| {
+            resume_cpu(&scene, width, height, frames, accumulation)
+        });
+        info!("Rendered {} samples on the CPU in {:?}", samples, start_time.elapsed());
+        radiance
+    } else {
+        let radiance = render_checkpointed(fingerprint, width, height, samples, checkpoint_path.as_deref(), |frames, accumulation| {
+            // A scene too large for the adapters' buffers still renders, only slower
+            resume_offline(&mut scene, &adapters, width, height, frames.clone(), accumulation).unwrap_or_else(|e| {
+                warn!("{}, tracing on the CPU instead", e);
+                resume_cpu(&scene, width, height, frames, accumulation)
+            })
+        });
+        info!("Rendered {} samples on {} adapter(s) in {:?}", samples, adapters.len(), start_time.elapsed());
+        radiance
+    };
+    let radiance = denoise_output(&scene, width, height, radiance);
+    save_radiance(path, CaptureFormat::from_path(path), width, height, &radiance).expect("Failed to save the render");
+}
+
+// `--denoise` runs headless renders through Open Image Denoise before they are saved
+#[cfg(feature = "denoise")]
+fn denoise_output(scene: &Scene, width: u32, height: u32, radiance: Vec<f32>) -> Vec<f32> {
+    if !std::env::args().any(|arg| arg == "--denoise") {
+        return radiance;
+    }
+    let start_time = Instant::now();
+    let denoised = denoise_render(scene, width, height, &radiance);
+    info!("Denoised in {:?}", start_time.elapsed());
+    denoised
+}
+
+#[cfg(not(feature = "denoise"))]
+fn denoise_output(_scene: &Scene, _width: u32, _height: u32, radiance: Vec<f32>) -> Vec<f32> {
+    if std::env::args().any(|arg| arg == "--denoise") {
+        warn!("Denoising needs the denoise feature, saving the render as traced");
+    }
+    radiance
+}
+
+// Traces the samples of an offline render with `render`, which goes on from the radiance of the
+// samples before its frames. Without a checkpoint path they are traced at once, with one they are
+// traced a chunk at a time and saved after each chunk, going on from `--resume` when it is given.
+// The fingerprint is the scene's, see render_fingerprint.
+fn render_checkpointed(fingerprint: u64, width: u32, height: u32, samples: u32, checkpoint_path: Option<&str>, mut render: impl FnMut(std::ops::Range<u32>, Option<&[f32]>) -> Vec<f32>) -> Vec<f32> {
+    let Some(checkpoint_path) = checkpoint_path else {
+        return render(0..samples, None);
+    };
+    let mut checkpoint = Checkpoint { scene: fingerprint, width, height, samples: 0, accumulation: vec![0.0; (width * height * 4) as usize] };
+    if let Some(resume_path) = arg_value("--resume") {
+        checkpoint = Checkpoint::load(&resume_path).unwrap_or_else(|e| panic!("Failed to read checkpoint {}: {}", resume_path, e));
+        checkpoint.check(fingerprint, width, height).unwrap_or_else(|e| panic!("Can't resume from {}: {}", resume_path, e));
+        info!("Resuming from {} samples per pixel", checkpoint.samples);
+    }
+    let every: u32 = arg_value("--checkpoint-every").map_or(32, |every| every.parse().expect("Checkpoint interval is not a number of samples"));
+    while checkpoint.samples < samples {
+        let frames = checkpoint.samples..(checkpoint.samples + every.max(1)).min(samples);
+        let accumulation = (checkpoint.samples > 0).then_some(checkpoint.accumulation.as_slice());
+        checkpoint.accumulation = render(frames.clone(), accumulation);
+        checkpoint.samples = frames.end;
+        // A render that can't be checkpointed goes on, it just can't be resumed from here
+        match checkpoint.save(checkpoint_path) {
+            Ok(()) => info!("Saved checkpoint {} at {} samples per pixel", checkpoint_path, checkpoint.samples),
+            Err(e) => warn!("Failed to save checkpoint {}: {}", checkpoint_path, e),
+        }
+    }
+    checkpoint.accumulation
+}
 
-            WindowEvent::RedrawRequested => match program_state.render() {
-                Ok(_) => {},
-                Err(wgpu::SurfaceError::Lost) => program_state.resize(program_state.size),
-                Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
-                Err(e) => eprintln!("{:?}", e),
+// Renders the jobs of a list, see parse_batch_jobs, one after another on the adapter `--adapter`
+// picks. The device is made once for all of them, and jobs following one of the same scene
+// render it without building it, its BVH and its textures again, so lists sorted by scene
+// render fastest. The render options of the command line apply to every job. A job whose scene
+// or camera can't be loaded is skipped, the rest of the list still renders.
+fn run_batch(path: &str) {
+    let contents = std::fs::read_to_string(path).expect("Failed to read the job list");
+    let jobs = parse_batch_jobs(&contents).unwrap_or_else(|e| panic!("{} {}", path, e));
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = match arg_value("--adapter") {
+        Some(choice) => find_adapter(enumerate_adapters(&instance), &choice)
+            .unwrap_or_else(|| panic!("No adapter matches \"{}\", --list-adapters shows them", choice)),
+        None => pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .filter(is_adapter_supported)
+            .expect("Batch rendering needs a usable adapter"),
+    };
+    let (device, queue) = request_device(&adapter);
+    let (device, queue) = (std::sync::Arc::new(device), std::sync::Arc::new(queue));
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    let batch_start = Instant::now();
+    let mut failed = 0;
+    // The renderer of the last job's scene, with the path of its script
+    let mut loaded: Option<(String, Renderer)> = None;
+    for (i, job) in jobs.iter().enumerate() {
+        info!("Job {}/{}: {} at {}x{}, {} samples", i + 1, jobs.len(), job.scene, job.width, job.height, job.samples);
+        let start_time = Instant::now();
+        if loaded.as_ref().map(|(scene, _)| scene) != Some(&job.scene) {
+            // Dropped first so two scenes are never on the device at once
+            loaded = None;
+            match batch_scene(&job.scene).and_then(|scene| Renderer::new(device.clone(), queue.clone(), scene, format, job.width, job.height).map_err(|e| e.to_string())) {
+                Ok(renderer) => loaded = Some((job.scene.clone(), renderer)),
+                Err(e) => {
+                    error!("Skipping job {}, failed to build {}: {}", i + 1, job.scene, e);
+                    failed += 1;
+                    continue;
+                },
             }
+        }
+        let renderer = &mut loaded.as_mut().unwrap().1;
+        let camera = match job_camera(&renderer.scene, job) {
+            Ok(camera) => camera,
+            Err(e) => {
+                error!("Skipping job {}: {}", i + 1, e);
+                failed += 1;
+                continue;
+            },
+        };
+
+        renderer.resize(job.width, job.height);
+        renderer.reset_accumulation();
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Batch Target"),
+            size: wgpu::Extent3d { width: job.width, height: job.height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        }).create_view(&wgpu::TextureViewDescriptor::default());
+        let trace_start = Instant::now();
+        for _ in 0..job.samples {
+            renderer.render_to_texture(&target, &camera);
+            // Waiting on every sample keeps long renders from queueing up thousands of submissions
+            device.poll(wgpu::Maintain::Wait);
+        }
+        let ray_stats = renderer.take_ray_stats(trace_start.elapsed().as_secs_f64());
+        info!("Traced {:.1} Mrays/s", ray_stats.mrays_per_second());
+        let radiance = denoise_output(&renderer.scene, job.width, job.height, renderer.radiance());
+        match save_radiance(&job.output, CaptureFormat::from_path(&job.output), job.width, job.height, &radiance) {
+            Ok(()) => info!("Saved {} in {:?}", job.output, start_time.elapsed()),
+            Err(e) => {
+                error!("Failed to save {}: {}", job.output, e);
+                failed += 1;
+            },
+        }
+    }
+    info!("Rendered {} of {} job(s) in {:?}", jobs.len() - failed, jobs.len(), batch_start.elapsed());
+}
+
+// Builds the scene of a batch job with its script and the command line's render options
+#[cfg(feature = "scripting")]
+fn batch_scene(path: &str) -> Result<Scene, String> {
+    let mut scene = Scene::new(40, 1.0, 1.0);
+    run_script(path, &mut scene).map_err(|e| e.to_string())?;
+    apply_scene_options(&mut scene);
+    scene.make_scene();
+    Ok(scene)
+}
+
+#[cfg(not(feature = "scripting"))]
+fn batch_scene(path: &str) -> Result<Scene, String> {
+    Err(format!("{} is a scene script, which needs the scripting feature", path))
+}
+
+// Bakes the ambient occlusion of a mesh into a `--bake-size 512` texture laid out by its texture
+// coordinates and saves it as PNG. The mesh is the OBJ file given with `--bake-mesh model.obj`,
+// the object of the scene named by `--bake-object NAME`, or else the first one with texture
+// coordinates. Each texel casts `--samples 64` rays on the CPU, which surfaces further than
+// `--ao-distance 1` don't block. The scene is built like for run_offline.
+fn run_bake_ao(path: &str) {
+    let mut scene = Scene::new(40, 1.0, 1.0);
+    #[cfg(feature = "scripting")]
+    if let Some(script_path) = arg_value("--script") {
+        run_script(&script_path, &mut scene).expect("Scene script failed");
+    }
+    let mesh = arg_value("--bake-mesh").map(|mesh_path| scene.add_object_mesh(&mesh_path));
+    apply_scene_options(&mut scene);
+    scene.make_scene();
+
+    let id = match (mesh, arg_value("--bake-object")) {
+        (Some(id), _) => id,
+        (None, Some(name)) => scene.find(&name).unwrap_or_else(|| panic!("No object is named \"{}\"", name)),
+        (None, None) => scene.entries.keys().copied()
+            .find(|&id| scene.primitives(id).is_some_and(|primitives| primitives.iter().any(|primitive| matches!(primitive, Object::Triangle(triangle) if triangle.uvs.is_some()))))
+            .expect("No object has texture coordinates to bake into"),
+    };
+    let defaults = AoBake::default();
+    let bake = AoBake {
+        size: arg_value("--bake-size").map_or(defaults.size, |size| size.parse().expect("Bake size is not a number")),
+        samples: arg_value("--samples").map_or(defaults.samples, |samples| samples.parse().expect("Sample count is not a number")),
+        distance: arg_value("--ao-distance").map_or(defaults.distance, |distance| distance.parse().expect("AO distance is not a number")),
+    };
+
+    let start_time = Instant::now();
+    let texture = bake_ambient_occlusion(&scene, id, &bake).unwrap_or_else(|e| panic!("Failed to bake: {}", e));
+    info!("Baked {} samples per texel in {:?}", bake.samples, start_time.elapsed());
+    texture.save(path).expect("Failed to save the baked texture");
+}
+
+// Bakes the lightmaps of meshes into a folder for content pipelines, one `--bake-size 512` EXR per
+// mesh named after it, or PNG with `--bake-format png`. The meshes are the OBJ files given with
+// `--bake-mesh model.obj` and the objects of the scene named by `--bake-object NAME`, both as
+// often as needed, or else every object with triangles. Meshes without texture coordinates get a
+// generated atlas, saved next to the lightmap as an OBJ to load the mesh with. Each texel traces
+// `--samples 256` paths on the CPU and charts are dilated by `--dilation 4` texels.
+fn run_bake_lightmaps(folder: &str) {
+    let mut scene = Scene::new(40, 1.0, 1.0);
+    #[cfg(feature = "scripting")]
+    if let Some(script_path) = arg_value("--script") {
+        run_script(&script_path, &mut scene).expect("Scene script failed");
+    }
+    let mut ids: Vec<(ObjectId, String)> = arg_values("--bake-mesh").into_iter().map(|mesh_path| {
+        let name = std::path::Path::new(&mesh_path).file_stem().map_or(mesh_path.clone(), |stem| stem.to_string_lossy().into_owned());
+        (scene.add_object_mesh(&mesh_path), name)
+    }).collect();
+    apply_scene_options(&mut scene);
+    scene.make_scene();
 
-            _ => (),
+    for name in arg_values("--bake-object") {
+        ids.push((scene.find(&name).unwrap_or_else(|| panic!("No object is named \"{}\"", name)), name));
+    }
+    if ids.is_empty() {
+        ids = scene.entries.keys().copied()
+            .filter(|&id| scene.primitives(id).is_some_and(|primitives| primitives.iter().any(|primitive| matches!(primitive, Object::Triangle(_)))))
+            .map(|id| (id, scene.name(id).map_or(format!("object-{}", id.0), str::to_string)))
+            .collect();
+    }
+    if ids.is_empty() {
+        panic!("No object has triangles to bake");
+    }
+    let extension = match arg_value("--bake-format").as_deref() {
+        None | Some("exr") => "exr",
+        Some("png") => "png",
+        Some(format) => panic!("Unknown bake format {}, expected exr or png", format),
+    };
+    let defaults = LightmapBake::default();
+    let bake = LightmapBake {
+        size: arg_value("--bake-size").map_or(defaults.size, |size| size.parse().expect("Bake size is not a number")),
+        samples: arg_value("--samples").map_or(defaults.samples, |samples| samples.parse().expect("Sample count is not a number")),
+        dilation: arg_value("--dilation").map_or(defaults.dilation, |texels| texels.parse().expect("Dilation is not a number")),
+    };
 
+    std::fs::create_dir_all(folder).expect("Failed to create the lightmap folder");
+    for (id, name) in ids {
+        let start_time = Instant::now();
+        let lightmap = bake_lightmap(&scene, id, &bake, |done| info!("Baking {}: {:.0}%", name, done * 100.0))
+            .unwrap_or_else(|e| panic!("Failed to bake {}: {}", name, e));
+        info!("Baked {} in {:?}", name, start_time.elapsed());
+        let path = format!("{}/{}.{}", folder, name, extension);
+        save_radiance(&path, CaptureFormat::from_path(&path), lightmap.size, lightmap.size, &lightmap.radiance).expect("Failed to save the lightmap");
+        if let Some(atlas) = &lightmap.atlas {
+            std::fs::write(format!("{}/{}.obj", folder, name), atlas_obj(atlas)).expect("Failed to save the mesh with its atlas");
+        }
+    }
+}
+
+// Renders at `--size` like run_offline, but for as long as the process runs, serving the view at
+// http://ADDRESS/ with the browser's keys moving the camera like the window's and mouse drags
+// turning it. Frames are only encoded while someone watches, ten times a second at most, and
+// tracing stops at `--idle-samples` (2048 by default) until the view changes.
+fn run_remote(address: &str) {
+    let (width, height) = image_size();
+    let idle_samples: u32 = arg_value("--idle-samples").map_or(2048, |samples| samples.parse().expect("Idle sample count is not a number"));
+
+    let mut scene = Scene::new(40, width as f32, height as f32);
+    #[cfg(feature = "scripting")]
+    if let Some(script_path) = arg_value("--script") {
+        run_script(&script_path, &mut scene).expect("Scene script failed");
+    }
+    apply_scene_options(&mut scene);
+    scene.make_scene();
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+        .filter(is_adapter_supported)
+        .expect("Remote viewing needs a usable adapter");
+    let (device, queue) = request_device(&adapter);
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    // The embedded renderer draws the image over a target, which isn't read as the radiance is
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Remote Target"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    }).create_view(&wgpu::TextureViewDescriptor::default());
+    let device = std::sync::Arc::new(device);
+    let mut renderer = match Renderer::new(device.clone(), std::sync::Arc::new(queue), scene, format, width, height) {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            error!("{}", e);
+            return;
         },
+    };
 
-        _ => {},
-    }).expect("Error!");
+    let listener = std::net::TcpListener::bind(address).expect("Failed to listen for viewers");
+    info!("Serving the view at http://{}/", listener.local_addr().unwrap());
+    let view = RemoteView::listen(listener);
+    let frame_interval = Duration::from_millis(100);
+    let mut last_sent: Option<(Instant, u32)> = None;
+    loop {
+        for input in view.take_input() {
+            let scene = &mut renderer.scene;
+            match input {
+                RemoteInput::KeyDown(code) => { scene.keys_pressed.insert(code); },
+                RemoteInput::KeyUp(code) => { scene.keys_pressed.remove(&code); },
+                RemoteInput::Release => scene.keys_pressed.clear(),
+                // A fifth of a degree per pixel dragged
+                RemoteInput::Look { dx, dy } => {
+                    scene.camera.rotate_yaw(-dx * 0.2);
+                    scene.camera.rotate_pitch(-dy * 0.2);
+                },
+            }
+        }
+        if renderer.scene.update() {
+            renderer.reset_accumulation();
+        }
+
+        let camera = renderer.scene.camera;
+        if renderer.sample_count() < idle_samples {
+            renderer.render_to_texture(&target, &camera);
+            // Waiting on every frame keeps the queue from filling up while nobody reads the frames back
+            device.poll(wgpu::Maintain::Wait);
+        } else {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // A frame when someone watches, the interval has passed and there are new samples to show
+        let stale = last_sent.is_none_or(|(time, samples)| time.elapsed() >= frame_interval && samples != renderer.sample_count());
+        if view.viewer_count() > 0 && stale {
+            view.publish(encode_jpeg(width, height, &renderer.radiance()));
+            last_sent = Some((Instant::now(), renderer.sample_count()));
+        }
+    }
+}
+
+// Renders each of `--bench-scenes cornell,statue,spheres` (all by default) for `--bench-frames 200`
+// frames at `--size` on the adapter `--adapter` picks and prints the timings. They are saved to the
+// path as JSON, or appended as CSV rows for `.csv` paths, tagged with `--bench-label`, e.g. a commit.
+fn run_bench(path: &str) {
+    let (width, height) = image_size();
+    let frames: u32 = arg_value("--bench-frames").map_or(200, |frames| frames.parse().expect("Frame count is not a number"));
+    let scenes: Vec<BenchScene> = arg_value("--bench-scenes").map_or(BenchScene::ALL.to_vec(), |names| names.split(',').map(|name| {
+        BenchScene::from_name(name.trim()).unwrap_or_else(|| panic!("No benchmark scene \"{}\", pick from cornell, statue and spheres", name))
+    }).collect());
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = match arg_value("--adapter") {
+        Some(choice) => find_adapter(enumerate_adapters(&instance), &choice)
+            .unwrap_or_else(|| panic!("No adapter matches \"{}\", --list-adapters shows them", choice)),
+        None => pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .filter(is_adapter_supported)
+            .expect("Benchmarking needs a usable adapter"),
+    };
+    let (device, queue) = request_device(&adapter);
+    let (device, queue) = (std::sync::Arc::new(device), std::sync::Arc::new(queue));
+    let info = adapter.get_info();
+    let run = BenchRun {
+        label: arg_value("--bench-label").unwrap_or_default(),
+        adapter: info.name,
+        backend: format!("{:?}", info.backend),
+        width,
+        height,
+    };
+
+    println!("Benchmarking on {} ({}) at {}x{}, {} frames per scene", run.adapter, run.backend, width, height, frames);
+    let results: Vec<_> = scenes.into_iter().map(|scene| {
+        let result = run_benchmark(device.clone(), queue.clone(), scene, width, height, frames)
+            .unwrap_or_else(|e| panic!("Failed to build the {} scene: {}", scene.name(), e));
+        println!("{:>8}: {:>6} objects, BVH {:>8.2} ms, SAH cost {:>6.2}, {:>7.3} ms/frame, {:>8.1} Mrays/s",
+            scene.name(), result.objects, result.bvh_build_ms, result.sah_cost, result.ms_per_frame, result.mrays_per_second);
+        result
+    }).collect();
+
+    if path.to_lowercase().ends_with(".csv") {
+        append_results_csv(path, &run, &results).expect("Failed to write the benchmark results");
+    } else {
+        std::fs::write(path, results_json(&run, &results)).expect("Failed to write the benchmark results");
+    }
+}
+
+// Loads the script and runs its top level on the scene, which builds it
+#[cfg(feature = "scripting")]
+fn run_script(path: &str, scene: &mut Scene) -> Result<SceneScript, Box<EvalAltResult>> {
+    let mut script = SceneScript::load(path)?;
+    script.run_setup(scene)?;
+    Ok(script)
+}
+
+// Builds a scene with the script like at startup, to be loaded in the background
+#[cfg(feature = "scripting")]
+fn script_scene(path: &str, size: winit::dpi::PhysicalSize<u32>) -> Result<(SceneScript, Scene), Box<EvalAltResult>> {
+    let mut scene = Scene::new(40, size.width as f32, size.height as f32);
+    let script = run_script(path, &mut scene)?;
+    apply_scene_options(&mut scene);
+    Ok((script, scene))
+}
+
+// Console commands from a file, one per line. Empty lines and lines starting with # are skipped.
+fn read_commands(path: &str) -> VecDeque<Command> {
+    let contents = std::fs::read_to_string(path).expect("Failed to read the commands");
+    contents.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| parse_command(line).unwrap_or_else(|e| panic!("{} line {}: {}", path, i + 1, e)))
+        .collect()
+}
+
+// `--size 1280x720` of offline and remote renders
+fn image_size() -> (u32, u32) {
+    arg_value("--size").map_or((1280, 720), |size| {
+        let (width, height) = size.split_once('x').expect("--size takes WIDTHxHEIGHT");
+        (width.parse().expect("Width is not a number"), height.parse().expect("Height is not a number"))
+    })
+}
+
+// `--backend cpu` traces on the CPU even when there is an adapter
+fn cpu_backend() -> bool {
+    arg_value("--backend").is_some_and(|backend| backend == "cpu")
+}
+
+// Fills in a default scene when nothing was added, then applies the sky and render options
+fn apply_scene_options(scene: &mut Scene) {
+    if scene.objects.is_empty() {
+        // scene.add_square(Vec3(0.0, 0.5, 0.0), 10.0, 10.0, Vec3(0.0, 1.0, 0.0), 0.0);
+        scene.add_sphere(Vec3(0.0, 0.0, -1.0), Vec3(1.0, 0.0, 0.0), 0.5);
+        // scene.add_object_mesh("assets/models/statue.obj");
+        // scene.add_sphere(Vec3(1.5, 0.0, -1.0), Vec3(0.0, 1.0, 0.0), 0.5);
+        // scene.add_sphere(Vec3(-1.5, 0.0, -1.0), Vec3(0.0, 0.0, 1.0), 0.5);
+    }
+    // Each `--sky sky.ktx2` adds a cube map container or a cross or strip image to switch between
+    // with N, starting on the first one given
+    for (i, path) in arg_values("--sky").into_iter().enumerate() {
+        let index = scene.add_sky(SkySource::File(path));
+        if i == 0 {
+            scene.active_sky = index;
+        }
+    }
+    // `--projection perspective|equirectangular|fisheye|stereo|orthographic` picks how the camera maps the
+    // window to rays, for 360 and VR content
+    if let Some(name) = arg_value("--projection") {
+        scene.camera.set_projection(Projection::from_name(&name).expect("Unknown projection"));
+    }
+    // `--filtering nearest|bilinear|trilinear` picks the starting texture filtering, F cycles it
+    if let Some(name) = arg_value("--filtering") {
+        scene.texture_filtering = TextureFiltering::from_name(&name).expect("Unknown texture filtering");
+    }
+    // `--spectral` traces wavelengths instead of RGB, for dispersion in glass
+    if std::env::args().any(|arg| arg == "--spectral") {
+        scene.spectral = true;
+    }
+    // `--bdpt` connects camera paths to light subpaths from the sky, for interiors
+    if std::env::args().any(|arg| arg == "--bdpt") {
+        scene.bidirectional = true;
+    }
+    // `--hybrid` rasterizes the triangles camera rays hit first instead of tracing them, for dense meshes
+    if std::env::args().any(|arg| arg == "--hybrid") {
+        scene.raster_primary = true;
+    }
+    // `--frustum-culling` uploads only the part of the BVH inside the view each frame, for huge scenes
+    if std::env::args().any(|arg| arg == "--frustum-culling") {
+        scene.frustum_culling = true;
+    }
+    // `--stackless` walks the BVH with skip links instead of a per-ray stack
+    if std::env::args().any(|arg| arg == "--stackless") {
+        scene.stackless_traversal = true;
+    }
+    // `--fast-preview` traces at quarter resolution while the camera moves or the scene is edited
+    if std::env::args().any(|arg| arg == "--fast-preview") {
+        scene.interaction_mode = true;
+    }
+    // `--bvh-heatmap` colors pixels by how expensive their camera ray is to trace instead of shading them
+    if std::env::args().any(|arg| arg == "--bvh-heatmap") {
+        scene.bvh_heatmap = true;
+    }
+    // `--exposure STOPS` brightens the view, or darkens it for negative stops
+    if let Some(stops) = arg_value("--exposure") {
+        scene.exposure = stops.parse().expect("Exposure is not a number of stops");
+    }
+    // `--auto-exposure` adapts the exposure to the brightness of the view over time
+    if std::env::args().any(|arg| arg == "--auto-exposure") {
+        scene.auto_exposure = true;
+    }
+    // `--post-effects` draws the view with chromatic aberration, a vignette and film grain
+    if std::env::args().any(|arg| arg == "--post-effects") {
+        scene.post_process.enabled = true;
+    }
+    // `--fade SECONDS` sets how long the view fades from the last converged frame after the camera moves, 0 to pop
+    if let Some(seconds) = arg_value("--fade") {
+        scene.fade_duration = seconds.parse().expect("Fade is not a number of seconds");
+    }
+}
+
+// Digit keys 1 to 9 stand for layers 0 to 8
+fn layer_for_key(code: KeyCode) -> Option<u32> {
+    let layer = match code {
+        KeyCode::Digit1 => 0,
+        KeyCode::Digit2 => 1,
+        KeyCode::Digit3 => 2,
+        KeyCode::Digit4 => 3,
+        KeyCode::Digit5 => 4,
+        KeyCode::Digit6 => 5,
+        KeyCode::Digit7 => 6,
+        KeyCode::Digit8 => 7,
+        KeyCode::Digit9 => 8,
+        _ => return None,
+    };
+    Some(layer)
+}
+
+// Events used by the editor panels, like clicks on a slider, don't reach the scene
+#[cfg(feature = "editor")]
+fn editor_consumes(state: &mut State, event: &WindowEvent) -> bool {
+    state.editor_event(event)
+}
+
+#[cfg(not(feature = "editor"))]
+fn editor_consumes(_state: &mut State, _event: &WindowEvent) -> bool {
+    false
+}
+
+fn arg_value(name: &str) -> Option<String> {
+    arg_values(name).into_iter().next()
+}
+
+// Values following every occurrence of the option
+fn arg_values(name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == name {
+            values.extend(args.next());
+        }
+    }
+    values
 }
 
 fn main() {
@@ -0,0 +1,64 @@
+use tracing::{debug, info};
+
+use super::required_limits;
+
+/// Every adapter wgpu finds on any backend, in the order `--adapter` indices refer to
+pub fn enumerate_adapters(instance: &wgpu::Instance) -> Vec<wgpu::Adapter> {
+    instance.enumerate_adapters(wgpu::Backends::all())
+}
+
+/// Picks an adapter by its index in the enumeration, its kind (`discrete`, `integrated`,
+/// `virtual` or `cpu`) or a part of its name, ignoring case
+pub fn find_adapter(adapters: Vec<wgpu::Adapter>, choice: &str) -> Option<wgpu::Adapter> {
+    if let Ok(index) = choice.parse::<usize>() {
+        return adapters.into_iter().nth(index);
+    }
+    let device_type = match choice.to_lowercase().as_str() {
+        "discrete" => Some(wgpu::DeviceType::DiscreteGpu),
+        "integrated" => Some(wgpu::DeviceType::IntegratedGpu),
+        "virtual" => Some(wgpu::DeviceType::VirtualGpu),
+        "cpu" => Some(wgpu::DeviceType::Cpu),
+        _ => None,
+    };
+    let name = choice.to_lowercase();
+    adapters.into_iter().find(|adapter| {
+        let info = adapter.get_info();
+        match device_type {
+            Some(device_type) => info.device_type == device_type,
+            None => info.name.to_lowercase().contains(&name),
+        }
+    })
+}
+
+/// Name, backend and kind of the adapter on one line
+pub fn describe_adapter(adapter: &wgpu::Adapter) -> String {
+    let info = adapter.get_info();
+    format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type)
+}
+
+/// Prints the adapters `--adapter` can pick from with their index
+pub fn print_adapters(instance: &wgpu::Instance) {
+    for (i, adapter) in enumerate_adapters(instance).iter().enumerate() {
+        println!("{}: {}", i, describe_adapter(adapter));
+    }
+}
+
+/// Logs the adapter in use, and at debug level the limits the renderer runs into first
+pub fn log_adapter_limits(adapter: &wgpu::Adapter) {
+    let limits = adapter.limits();
+    info!("Adapter: {}", describe_adapter(adapter));
+    debug!("Max storage buffers per stage: {}", limits.max_storage_buffers_per_shader_stage);
+    debug!("Max storage buffer binding size: {} MiB", limits.max_storage_buffer_binding_size >> 20);
+    debug!("Max buffer size: {} MiB", limits.max_buffer_size >> 20);
+    debug!("Max texture array layers: {}", limits.max_texture_array_layers);
+    debug!("Max compute invocations per workgroup: {}", limits.max_compute_invocations_per_workgroup);
+}
+
+/// Whether the renderer can run on the adapter: compute shaders, the sky's extra view format and
+/// the storage buffers the photon map and the ray counters take past the default limit. GL
+/// adapters in particular often lack some of them.
+pub fn is_adapter_supported(adapter: &wgpu::Adapter) -> bool {
+    let flags = adapter.get_downlevel_capabilities().flags;
+    flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::VIEW_FORMATS)
+        && adapter.limits().max_storage_buffers_per_shader_stage >= required_limits().max_storage_buffers_per_shader_stage
+}
@@ -0,0 +1,287 @@
+use std::f32::consts::PI;
+
+use image::GrayImage;
+use rayon::prelude::*;
+
+use super::{CpuRenderer, Object, ObjectId, Scene, Triangle, Vec2, Vec3};
+
+// Distance surfaces start their rays off themselves at, so they don't hit where they start
+const SURFACE_OFFSET: f32 = 1e-4;
+/// Samples per texel each pass of a lightmap bake traces before reporting its progress
+pub const LIGHTMAP_PASS_SAMPLES: u32 = 16;
+
+/// How ambient occlusion is baked, see `bake_ambient_occlusion`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AoBake {
+    /// Texels along each side of the square texture
+    pub size: u32,
+    /// Rays cast from each texel
+    pub samples: u32,
+    /// Surfaces further away than this don't occlude, so open rooms aren't darkened by walls
+    /// across them
+    pub distance: f32,
+}
+
+impl Default for AoBake {
+    fn default() -> Self {
+        Self { size: 512, samples: 64, distance: 1.0 }
+    }
+}
+
+/// Bakes how open to the sky each point of an object is into a texture laid out by the texture
+/// coordinates of its triangles, white where nothing is in the way and black where every ray is
+/// blocked, to be multiplied into the object's color in a game engine. Every object of the built
+/// scene occludes, texels no triangle covers stay white.
+pub fn bake_ambient_occlusion(scene: &Scene, id: ObjectId, bake: &AoBake) -> Result<GrayImage, String> {
+    let primitives = scene.primitives(id).ok_or_else(|| format!("no object {}", id.0))?;
+    let triangles: Vec<&Triangle> = primitives.iter()
+        .filter_map(|primitive| match primitive {
+            Object::Triangle(triangle) if triangle.uvs.is_some() => Some(triangle),
+            _ => None,
+        })
+        .collect();
+    if triangles.is_empty() {
+        return Err(format!("object {} has no triangles with texture coordinates to bake into", id.0));
+    }
+
+    let texels = texel_surfaces(&triangles, bake.size);
+    let renderer = CpuRenderer::new(scene, 1, 1);
+    let cast = renderer.ray_caster(&scene.flatten_scene_data(0));
+    let occlusion: Vec<u8> = texels.par_iter().enumerate().map(|(i, texel)| {
+        let Some((position, normal)) = *texel else { return 255 };
+        let mut rng = (i as u32).wrapping_mul(9781).wrapping_add(1);
+        let origin = position + normal * SURFACE_OFFSET;
+        let open = (0..bake.samples)
+            .filter(|_| cast(origin, cosine_direction(normal, &mut rng), bake.distance, &mut rng).is_none())
+            .count();
+        (open as f32 / bake.samples.max(1) as f32 * 255.0).round() as u8
+    }).collect();
+
+    Ok(GrayImage::from_raw(bake.size, bake.size, occlusion).unwrap())
+}
+
+/// How a lightmap is baked, see `bake_lightmap`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightmapBake {
+    /// Texels along each side of the square lightmap
+    pub size: u32,
+    /// Paths traced from each texel
+    pub samples: u32,
+    /// Texels the baked charts are grown by into the empty space around them, so filtering and
+    /// mipmaps don't blend in black along seams
+    pub dilation: u32,
+}
+
+impl Default for LightmapBake {
+    fn default() -> Self {
+        Self { size: 512, samples: 256, dilation: 4 }
+    }
+}
+
+/// A baked lightmap, see `bake_lightmap`
+pub struct Lightmap {
+    pub size: u32,
+    /// Linear RGBA, four floats per texel row by row from the top like `save_radiance` takes.
+    /// Alpha is 1 where the object covers the texel or dilation filled it, 0 elsewhere.
+    pub radiance: Vec<f32>,
+    /// The object's triangles with the texture coordinates the lightmap was laid out by, when
+    /// they had none of their own and an atlas was generated for them
+    pub atlas: Option<Vec<Triangle>>,
+}
+
+/// Bakes the light arriving at each point of an object from every direction above it into a
+/// lightmap laid out by the texture coordinates of its triangles, generating an atlas with
+/// `generate_atlas` if any has none. Texels hold the irradiance divided by pi, the light a white
+/// matte surface gives off there, so a game engine multiplies them into the object's color.
+/// Paths are traced on the CPU through the built scene in passes of `LIGHTMAP_PASS_SAMPLES`,
+/// `progress` getting the share done after each.
+pub fn bake_lightmap(scene: &Scene, id: ObjectId, bake: &LightmapBake, mut progress: impl FnMut(f32)) -> Result<Lightmap, String> {
+    let primitives = scene.primitives(id).ok_or_else(|| format!("no object {}", id.0))?;
+    let mut triangles: Vec<Triangle> = primitives.iter()
+        .filter_map(|primitive| match primitive {
+            Object::Triangle(triangle) => Some(triangle.clone()),
+            _ => None,
+        })
+        .collect();
+    if triangles.is_empty() {
+        return Err(format!("object {} has no triangles to bake", id.0));
+    }
+    let generated = triangles.iter().any(|triangle| triangle.uvs.is_none());
+    if generated {
+        generate_atlas(&mut triangles, bake.size, bake.dilation);
+    }
+
+    let texels = texel_surfaces(&triangles.iter().collect::<Vec<_>>(), bake.size);
+    let renderer = CpuRenderer::new(scene, 1, 1);
+    let trace = renderer.path_tracer(&scene.flatten_scene_data(0));
+    let mut sums = vec![Vec3(0.0, 0.0, 0.0); texels.len()];
+    let passes = bake.samples.div_ceil(LIGHTMAP_PASS_SAMPLES);
+    for pass in 0..passes {
+        let samples = LIGHTMAP_PASS_SAMPLES.min(bake.samples - pass * LIGHTMAP_PASS_SAMPLES);
+        sums.par_iter_mut().zip(&texels).enumerate().for_each(|(i, (sum, texel))| {
+            let Some((position, normal)) = *texel else { return };
+            let mut rng = (i as u32).wrapping_mul(9781).wrapping_add(pass.wrapping_mul(26699)).wrapping_add(1);
+            let origin = position + normal * SURFACE_OFFSET;
+            for _ in 0..samples {
+                *sum += trace(origin, cosine_direction(normal, &mut rng), &mut rng);
+            }
+        });
+        progress((pass + 1) as f32 / passes as f32);
+    }
+
+    let mut radiance: Vec<f32> = sums.iter().zip(&texels)
+        .flat_map(|(sum, texel)| match texel {
+            Some(_) => {
+                let mean = *sum / bake.samples as f32;
+                [mean.0, mean.1, mean.2, 1.0]
+            },
+            None => [0.0; 4],
+        })
+        .collect();
+    dilate(&mut radiance, bake.size, bake.dilation);
+    Ok(Lightmap { size: bake.size, radiance, atlas: generated.then_some(triangles) })
+}
+
+/// Gives triangles texture coordinates laying them out in a texture of `size` texels, each in its
+/// own cell of a square grid with a margin of `margin` texels around it to dilate into. Triangles
+/// keep their shape but are scaled to fill their cell, so small ones get as many texels as large ones.
+pub fn generate_atlas(triangles: &mut [Triangle], size: u32, margin: u32) {
+    let columns = (triangles.len() as f32).sqrt().ceil().max(1.0) as usize;
+    let cell = 1.0 / columns as f32;
+    let margin = (margin as f32 / size as f32).min(cell * 0.25);
+    for (i, triangle) in triangles.iter_mut().enumerate() {
+        let corner = Vec2((i % columns) as f32 * cell + margin, (i / columns) as f32 * cell + margin);
+        // Flattened into the triangle's own plane with its first edge along u
+        let [a, b, c] = triangle.corners;
+        let normal = (b - a).cross(c - a);
+        if normal.magnitude() == 0.0 {
+            triangle.uvs = Some([corner; 3]);
+            continue;
+        }
+        let u_axis = (b - a).normalize();
+        let v_axis = normal.cross(u_axis).normalize();
+        let flat = [a, b, c].map(|point| ((point - a).dot(u_axis), (point - a).dot(v_axis)));
+        let min_u = flat.iter().map(|point| point.0).fold(f32::MAX, f32::min);
+        let max_u = flat.iter().map(|point| point.0).fold(f32::MIN, f32::max);
+        let min_v = flat.iter().map(|point| point.1).fold(f32::MAX, f32::min);
+        let max_v = flat.iter().map(|point| point.1).fold(f32::MIN, f32::max);
+        let scale = (cell - 2.0 * margin) / (max_u - min_u).max(max_v - min_v);
+        triangle.uvs = Some(flat.map(|point| Vec2(corner.0 + (point.0 - min_u) * scale, corner.1 + (point.1 - min_v) * scale)));
+    }
+}
+
+/// Grows the covered texels of a square RGBA texture, those with an alpha above 0, by `texels`
+/// into the uncovered ones around them, each taking the average of its covered neighbours
+pub fn dilate(radiance: &mut [f32], size: u32, texels: u32) {
+    let size = size as i64;
+    for _ in 0..texels {
+        let previous = radiance.to_vec();
+        for y in 0..size {
+            for x in 0..size {
+                let index = ((y * size + x) * 4) as usize;
+                if previous[index + 3] > 0.0 {
+                    continue;
+                }
+                let mut sum = [0.0; 3];
+                let mut count = 0;
+                for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))) {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= size || ny >= size {
+                        continue;
+                    }
+                    let neighbour = ((ny * size + nx) * 4) as usize;
+                    if previous[neighbour + 3] > 0.0 {
+                        (0..3).for_each(|c| sum[c] += previous[neighbour + c]);
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    radiance[index..index + 4].copy_from_slice(&[sum[0] / count as f32, sum[1] / count as f32, sum[2] / count as f32, 1.0]);
+                }
+            }
+        }
+    }
+}
+
+/// Triangles as an OBJ file with their texture coordinates and normals, for meshes whose
+/// lightmap atlas was generated to be loaded with it. Corners aren't shared between triangles.
+pub fn atlas_obj(triangles: &[Triangle]) -> String {
+    let mut obj = String::new();
+    for triangle in triangles {
+        let uvs = triangle.uvs.unwrap_or([Vec2(0.0, 0.0); 3]);
+        let face_normal = (triangle.corners[1] - triangle.corners[0]).cross(triangle.corners[2] - triangle.corners[0]).normalize();
+        let normals = triangle.normals.unwrap_or([face_normal; 3]);
+        for i in 0..3 {
+            let (corner, normal) = (triangle.corners[i], normals[i]);
+            obj += &format!("v {} {} {}\nvt {} {}\nvn {} {} {}\n", corner.0, corner.1, corner.2, uvs[i].0, uvs[i].1, normal.0, normal.1, normal.2);
+        }
+    }
+    for face in 0..triangles.len() {
+        let first = face * 3 + 1;
+        obj += &format!("f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}\n", first, first + 1, first + 2);
+    }
+    obj
+}
+
+/// Point and normal of the surface at the middle of each texel of a square texture, row by row
+/// from the top, None where no triangle's texture coordinates cover it. Texture coordinates go
+/// up from the bottom of the texture, parts of triangles outside of 0 to 1 are left out.
+pub fn texel_surfaces(triangles: &[&Triangle], size: u32) -> Vec<Option<(Vec3, Vec3)>> {
+    let mut texels = vec![None; (size * size) as usize];
+    for triangle in triangles {
+        let Some(uvs) = triangle.uvs else { continue };
+        let points = uvs.map(|uv| (uv.0 * size as f32, (1.0 - uv.1) * size as f32));
+        let area = edge(points[0], points[1], points[2]);
+        if area == 0.0 {
+            continue;
+        }
+        let face_normal = (triangle.corners[1] - triangle.corners[0]).cross(triangle.corners[2] - triangle.corners[0]).normalize();
+
+        // Texels whose middle lies within the triangle, found in its bounds
+        let min_x = points.iter().map(|point| point.0).fold(f32::MAX, f32::min).floor().clamp(0.0, size as f32) as u32;
+        let max_x = points.iter().map(|point| point.0).fold(f32::MIN, f32::max).ceil().clamp(0.0, size as f32) as u32;
+        let min_y = points.iter().map(|point| point.1).fold(f32::MAX, f32::min).floor().clamp(0.0, size as f32) as u32;
+        let max_y = points.iter().map(|point| point.1).fold(f32::MIN, f32::max).ceil().clamp(0.0, size as f32) as u32;
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let middle = (x as f32 + 0.5, y as f32 + 0.5);
+                let weights = [
+                    edge(points[1], points[2], middle) / area,
+                    edge(points[2], points[0], middle) / area,
+                    edge(points[0], points[1], middle) / area,
+                ];
+                if weights.iter().any(|&weight| weight < 0.0) {
+                    continue;
+                }
+                let blend = |values: [Vec3; 3]| values[0] * weights[0] + values[1] * weights[1] + values[2] * weights[2];
+                let normal = triangle.normals.map_or(face_normal, |normals| blend(normals).normalize());
+                texels[(y * size + x) as usize] = Some((blend(triangle.corners), normal));
+            }
+        }
+    }
+    texels
+}
+
+// Twice the signed area of the triangle abc, positive when it turns counterclockwise
+fn edge(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+// Direction off a surface with the normal, more likely the closer it is to the normal like light
+// arriving at a matte surface
+fn cosine_direction(normal: Vec3, rng: &mut u32) -> Vec3 {
+    let z = random_float(rng) * 2.0 - 1.0;
+    let angle = random_float(rng) * 2.0 * PI;
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let direction = normal + Vec3(radius * angle.cos(), radius * angle.sin(), z);
+    // Opposite the normal the sum vanishes, any direction off the surface does then
+    if direction.magnitude() < 1e-6 { normal } else { direction.normalize() }
+}
+
+// PCG hash step, like the renderers draw their random numbers with
+fn random_float(state: &mut u32) -> f32 {
+    *state = state.wrapping_mul(747796405).wrapping_add(2891336453);
+    let mut word = ((*state >> ((*state >> 28) + 4)) ^ *state).wrapping_mul(277803737);
+    word ^= word >> 22;
+    word as f32 / 4294967296.0
+}
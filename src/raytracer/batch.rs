@@ -0,0 +1,64 @@
+use super::{Camera, Scene};
+
+/// One render of a batch: a scene, where it is seen from and how, and the file it is saved to
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchJob {
+    /// Script building the scene
+    pub scene: String,
+    /// One of the scene's named cameras, None for the camera the scene starts with
+    pub camera: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    /// Image the render is saved to, its extension picks the format
+    pub output: String,
+}
+
+/// Reads a job list, one job per line of the scene, the camera name or `-` for the scene's own
+/// camera, the size as WIDTHxHEIGHT, the samples per pixel and the output path, e.g.
+///
+/// ```text
+/// scenes/room.rhai window 1920x1080 1024 renders/room-window.exr
+/// ```
+///
+/// Empty lines and lines starting with # are skipped. The error names the first line that is wrong.
+pub fn parse_batch_jobs(contents: &str) -> Result<Vec<BatchJob>, String> {
+    contents.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| parse_batch_job(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+fn parse_batch_job(line: &str) -> Result<BatchJob, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let [scene, camera, size, samples, output] = words[..] else {
+        return Err(format!("expected a scene, camera, size, samples and output, found {} words", words.len()));
+    };
+    let (width, height) = size.split_once('x')
+        .and_then(|(width, height)| Some((width.parse::<u32>().ok()?, height.parse::<u32>().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| format!("size \"{}\" is not WIDTHxHEIGHT", size))?;
+    let samples = samples.parse::<u32>().ok().filter(|&samples| samples > 0)
+        .ok_or_else(|| format!("samples \"{}\" is not a positive number", samples))?;
+    Ok(BatchJob {
+        scene: scene.to_string(),
+        camera: (camera != "-").then(|| camera.to_string()),
+        width,
+        height,
+        samples,
+        output: output.to_string(),
+    })
+}
+
+/// The camera a job renders the scene through, fitted to the job's size
+pub fn job_camera(scene: &Scene, job: &BatchJob) -> Result<Camera, String> {
+    let mut camera = match &job.camera {
+        None => scene.camera,
+        Some(name) => *scene.cameras.get(name).ok_or_else(|| {
+            let names: Vec<&str> = scene.cameras.keys().map(String::as_str).collect();
+            format!("{} has no camera \"{}\", it has {}", job.scene, name, if names.is_empty() { "none".to_string() } else { names.join(", ") })
+        })?,
+    };
+    camera.set_aspect_ratio(job.width as f32 / job.height as f32);
+    Ok(camera)
+}
@@ -0,0 +1,217 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::{Camera, Material, MaterialId, ObjMesh, Renderer, Scene, SkySource, Vec3};
+
+// Frames traced before timing starts, while the driver settles
+const WARMUP_FRAMES: u32 = 8;
+const RANDOM_SPHERES: usize = 10_000;
+const STATUE_PATH: &str = "assets/models/statue.obj";
+const CSV_HEADER: &str = "label,adapter,backend,width,height,scene,objects,bvh_build_ms,sah_cost,frames,ms_per_frame,mrays_per_second";
+
+/// Scenes the benchmark renders, the same on every run so results compare across commits and GPUs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchScene {
+    /// A box open towards the camera with red and green walls, a matte and a glass ball
+    Cornell,
+    /// The statue model, about 31k triangles
+    Statue,
+    /// Ten thousand spheres of random sizes and materials, from a fixed seed
+    Spheres,
+}
+
+impl BenchScene {
+    pub const ALL: [BenchScene; 3] = [BenchScene::Cornell, BenchScene::Statue, BenchScene::Spheres];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BenchScene::Cornell => "cornell",
+            BenchScene::Statue => "statue",
+            BenchScene::Spheres => "spheres",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scene| scene.name() == name)
+    }
+
+    /// The scene with its objects and camera, but without its BVH, which the benchmark times building
+    pub fn build(self, width: u32, height: u32) -> io::Result<Scene> {
+        let aspect_ratio = width as f32 / height as f32;
+        let mut scene = Scene::new(4, width as f32, height as f32);
+        scene.active_sky = scene.add_sky(SkySource::Solid([200, 210, 230]));
+        let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+
+        match self {
+            BenchScene::Cornell => {
+                scene.camera = Camera::new(Vec3(0.0, 0.0, -3.5), Vec3(0.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), 40.0, aspect_ratio);
+                let (white, red, green) = (Vec3(0.75, 0.75, 0.75), Vec3(0.75, 0.15, 0.15), Vec3(0.15, 0.75, 0.15));
+                // Corners of the box, x then y then z from -1 to 1
+                let corner = |i: usize| Vec3([-1.0, 1.0][i & 1], [-1.0, 1.0][i >> 1 & 1], [-1.0, 1.0][i >> 2 & 1]);
+                for (corners, color) in [([0, 1, 5, 4], white), ([2, 6, 7, 3], white), ([4, 5, 7, 6], white), ([0, 4, 6, 2], green), ([1, 3, 7, 5], red)] {
+                    add_wall(&mut scene, corners.map(corner), color, matte);
+                }
+                let ball = scene.add_sphere(Vec3(-0.4, -0.6, 0.3), Vec3(0.9, 0.9, 0.9), 0.4);
+                scene.set_material(ball, matte);
+                let glass = scene.add_material(Material { ior: 1.5, ..Default::default() });
+                let marble = scene.add_sphere(Vec3(0.45, -0.65, -0.3), Vec3(1.0, 1.0, 1.0), 0.35);
+                scene.set_material(marble, glass);
+            },
+            BenchScene::Statue => {
+                // The model stands along z
+                scene.camera = Camera::new(Vec3(0.0, -2.6, 0.9), Vec3(0.0, 0.0, 0.8), Vec3(0.0, 0.0, 1.0), 40.0, aspect_ratio);
+                let statue = scene.add_obj_mesh(ObjMesh::new(Vec3(0.8, 0.8, 0.8), STATUE_PATH)?);
+                scene.set_material(statue, matte);
+            },
+            BenchScene::Spheres => {
+                scene.camera = Camera::new(Vec3(0.0, 1.0, -24.0), Vec3(0.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), 40.0, aspect_ratio);
+                let mirror = scene.add_material(Material { roughness: 0.2, ..Default::default() });
+                let glass = scene.add_material(Material { ior: 1.5, ..Default::default() });
+                let mut rng = StdRng::seed_from_u64(0);
+                for _ in 0..RANDOM_SPHERES {
+                    let center = Vec3(rng.gen_range(-20.0..20.0), rng.gen_range(-5.0..5.0), rng.gen_range(-20.0..20.0));
+                    let color = Vec3(rng.gen(), rng.gen(), rng.gen());
+                    let sphere = scene.add_sphere(center, color, rng.gen_range(0.05..0.4));
+                    scene.set_material(sphere, [matte, matte, mirror, glass][rng.gen_range(0..4)]);
+                }
+            },
+        }
+        Ok(scene)
+    }
+}
+
+// A wall of the box as two triangles, with corners in order around its edge
+fn add_wall(scene: &mut Scene, corners: [Vec3; 4], color: Vec3, material: MaterialId) {
+    for triangle in [[corners[0], corners[1], corners[2]], [corners[0], corners[2], corners[3]]] {
+        let id = scene.add_triangle(triangle, color);
+        scene.set_material(id, material);
+    }
+}
+
+/// Timings of one scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchResult {
+    pub scene: BenchScene,
+    /// Primitives in the scene, each triangle of a mesh counting once
+    pub objects: usize,
+    pub bvh_build_ms: f64,
+    /// SAH cost of the BVH built, see `Scene::bvh_cost`
+    pub sah_cost: f32,
+    pub frames: u32,
+    pub ms_per_frame: f64,
+    pub mrays_per_second: f64,
+}
+
+/// Builds the scene's BVH and traces `frames` frames of it at the given size with the embedded
+/// renderer, waiting on each so the time covers the GPU's work. Like any embedded render, each
+/// frame also uploads the scene's buffers.
+pub fn run_benchmark(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, bench_scene: BenchScene, width: u32, height: u32, frames: u32) -> io::Result<BenchResult> {
+    let mut scene = bench_scene.build(width, height)?;
+    let objects = scene.objects.len();
+    let start_time = Instant::now();
+    scene.make_scene();
+    let bvh_build_ms = start_time.elapsed().as_secs_f64() * 1000.0;
+    let sah_cost = scene.bvh_cost().sah_cost;
+
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Benchmark Target"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    }).create_view(&wgpu::TextureViewDescriptor::default());
+    let camera = scene.camera;
+    let mut renderer = Renderer::new(device.clone(), queue, scene, format, width, height).map_err(io::Error::other)?;
+
+    for _ in 0..WARMUP_FRAMES {
+        renderer.render_to_texture(&target, &camera);
+    }
+    renderer.take_ray_stats(1.0);
+
+    let start_time = Instant::now();
+    for _ in 0..frames {
+        renderer.render_to_texture(&target, &camera);
+        device.poll(wgpu::Maintain::Wait);
+    }
+    let seconds = start_time.elapsed().as_secs_f64();
+    let stats = renderer.take_ray_stats(seconds);
+
+    Ok(BenchResult {
+        scene: bench_scene,
+        objects,
+        bvh_build_ms,
+        sah_cost,
+        frames,
+        ms_per_frame: seconds * 1000.0 / frames.max(1) as f64,
+        mrays_per_second: stats.mrays_per_second(),
+    })
+}
+
+/// Where and how a benchmark ran, written next to its results
+pub struct BenchRun {
+    /// Anything telling runs apart, like the commit
+    pub label: String,
+    pub adapter: String,
+    pub backend: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The run and its results as one JSON object
+pub fn results_json(run: &BenchRun, results: &[BenchResult]) -> String {
+    let results: Vec<String> = results.iter().map(|result| format!(
+        "    {{ \"scene\": \"{}\", \"objects\": {}, \"bvh_build_ms\": {:.3}, \"sah_cost\": {:.3}, \"frames\": {}, \"ms_per_frame\": {:.3}, \"mrays_per_second\": {:.2} }}",
+        result.scene.name(), result.objects, result.bvh_build_ms, result.sah_cost, result.frames, result.ms_per_frame, result.mrays_per_second,
+    )).collect();
+    format!(
+        "{{\n  \"label\": {},\n  \"adapter\": {},\n  \"backend\": {},\n  \"width\": {},\n  \"height\": {},\n  \"results\": [\n{}\n  ]\n}}\n",
+        json_string(&run.label), json_string(&run.adapter), json_string(&run.backend), run.width, run.height, results.join(",\n"),
+    )
+}
+
+/// Adds a row per result to a CSV file, writing the header first when the file is new, so one
+/// file collects runs across commits and machines
+pub fn append_results_csv(path: &str, run: &BenchRun, results: &[BenchResult]) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        writeln!(file, "{}", CSV_HEADER)?;
+    }
+    for result in results {
+        writeln!(file, "{},{},{},{},{},{},{},{:.3},{:.3},{},{:.3},{:.2}",
+            csv_field(&run.label), csv_field(&run.adapter), csv_field(&run.backend), run.width, run.height,
+            result.scene.name(), result.objects, result.bvh_build_ms, result.sah_cost, result.frames, result.ms_per_frame, result.mrays_per_second)?;
+    }
+    Ok(())
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Quoted when it holds a comma, a quote or a line break, with quotes doubled
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
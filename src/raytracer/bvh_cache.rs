@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use tracing::{debug, warn};
+
+use super::{Node, Object, Scene, Vec3, BVH_BUILDER_VERSION};
+
+// Identifies cache files, bumped whenever the layout below changes
+const MAGIC: &[u8; 8] = b"RTBVH001";
+
+// Layout, all little-endian:
+// magic, u64 hash of the scene and the builder version, u32 node count, u32 index count,
+// nodes as (min xyz f32, left_child i32, max xyz f32, object_count u32, split_axis u32),
+// object indices as u32
+const NODE_BYTES: u64 = 36;
+impl Scene {
+    /// Writes the built BVH and object indices, tagged with a hash of the geometry they were built from
+    pub fn save_bvh(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.bvh_hash().to_le_bytes())?;
+        writer.write_all(&(self.nodes_used as u32).to_le_bytes())?;
+        writer.write_all(&(self.object_indices.len() as u32).to_le_bytes())?;
+
+        for node in &self.nodes[..self.nodes_used] {
+            write_vec3(&mut writer, node.min_corner)?;
+            writer.write_all(&node.left_child.to_le_bytes())?;
+            write_vec3(&mut writer, node.max_corner)?;
+            writer.write_all(&(node.object_count as u32).to_le_bytes())?;
+            writer.write_all(&(node.split_axis as u32).to_le_bytes())?;
+        }
+        for &index in &self.object_indices {
+            writer.write_all(&(index as u32).to_le_bytes())?;
+        }
+
+        writer.flush()
+    }
+
+    /// Loads a BVH written by `save_bvh`. Returns false and leaves the scene untouched
+    /// when the file was built from different geometry, build settings or builder version, and
+    /// fails on a tree whose ranges don't fit the scene.
+    pub fn load_bvh(&mut self, path: &str) -> io::Result<bool> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a BVH cache file"));
+        }
+        if read_u64(&mut reader)? != self.bvh_hash() {
+            return Ok(false);
+        }
+
+        let node_count = read_u32(&mut reader)? as usize;
+        let index_count = read_u32(&mut reader)? as usize;
+
+        // The counts are only trusted as far as the file has room for them
+        let mut nodes = Vec::with_capacity(node_count.min((file_len / NODE_BYTES) as usize));
+        for _ in 0..node_count {
+            let min_corner = read_vec3(&mut reader)?;
+            let left_child = read_u32(&mut reader)? as i32;
+            let max_corner = read_vec3(&mut reader)?;
+            let object_count = read_u32(&mut reader)? as usize;
+            let split_axis = read_u32(&mut reader)? as usize;
+            nodes.push(Node { min_corner, left_child, max_corner, object_count, split_axis });
+        }
+        let mut object_indices = Vec::with_capacity(index_count.min((file_len / 4) as usize));
+        for _ in 0..index_count {
+            object_indices.push(read_u32(&mut reader)? as usize);
+        }
+        check_bvh(&nodes, &object_indices, self.objects.len())
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))?;
+
+        self.nodes = nodes;
+        self.nodes_used = node_count;
+        self.object_indices = object_indices;
+        self.dirty = false;
+        Ok(true)
+    }
+
+    /// Loads the BVH from the cache when it matches the scene, otherwise builds it and refreshes the cache
+    pub fn make_scene_cached(&mut self, path: &str) {
+        match self.load_bvh(path) {
+            Ok(true) => {
+                debug!("Read the BVH from {}", path);
+                return;
+            },
+            Ok(false) => {},
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => warn!("Rebuilding the BVH, cache {} is unusable: {}", path, e),
+        }
+
+        self.make_scene();
+        if let Err(e) = self.save_bvh(path) {
+            warn!("Failed to write BVH cache {}: {}", path, e);
+        }
+    }
+
+    // FNV-1a over everything the BVH depends on: object shapes, the build settings and the
+    // builder. Colors and textures don't change the tree, so editing them keeps the cache valid.
+    fn bvh_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+
+        feed(&BVH_BUILDER_VERSION.to_le_bytes());
+        feed(&(self.max_leaf_size as u64).to_le_bytes());
+        for object in &self.objects {
+            match object {
+                Object::Sphere(sphere) => {
+                    feed(&[0]);
+                    feed(bytemuck::cast_slice(&[sphere.center.0, sphere.center.1, sphere.center.2, sphere.radius]));
+                },
+                Object::Triangle(triangle) => {
+                    feed(&[1]);
+                    for corner in &triangle.corners {
+                        feed(bytemuck::cast_slice(&[corner.0, corner.1, corner.2]));
+                    }
+                },
+                Object::Quad(quad) => {
+                    feed(&[2]);
+                    for corner in quad.corners() {
+                        feed(bytemuck::cast_slice(&[corner.0, corner.1, corner.2]));
+                    }
+                },
+                Object::Billboard(billboard) => {
+                    feed(&[3]);
+                    feed(bytemuck::cast_slice(&[billboard.center.0, billboard.center.1, billboard.center.2, billboard.radius()]));
+                },
+                Object::Curve(curve) => {
+                    feed(&[5]);
+                    for point in &curve.control_points {
+                        feed(bytemuck::cast_slice(&[point.0, point.1, point.2]));
+                    }
+                    feed(&curve.radius.to_le_bytes());
+                },
+                Object::Points(cluster) => {
+                    feed(&[4]);
+                    let (min_corner, max_corner) = cluster.bounds();
+                    feed(bytemuck::cast_slice(&[min_corner.0, min_corner.1, min_corner.2, max_corner.0, max_corner.1, max_corner.2]));
+                },
+                Object::Instance(instance) => {
+                    feed(&[6]);
+                    let (min_corner, max_corner) = instance.bounds(&self.prototypes[instance.prototype]);
+                    feed(bytemuck::cast_slice(&[min_corner.0, min_corner.1, min_corner.2, max_corner.0, max_corner.1, max_corner.2]));
+                },
+            }
+        }
+
+        hash
+    }
+}
+
+// Whether the tree only points inside itself and the scene: inner nodes at two children stored
+// after them, leaves at a run of object indices, and those at objects. Only the root of an empty
+// scene has neither.
+fn check_bvh(nodes: &[Node], object_indices: &[usize], objects: usize) -> Result<(), &'static str> {
+    if nodes.is_empty() {
+        return Err("the BVH has no root");
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        if node.split_axis > 2 {
+            return Err("a node splits along an unknown axis");
+        }
+        if node.left_child < 0 {
+            if !(i == 0 && nodes.len() == 1 && node.object_count == 0 && objects == 0) {
+                return Err("a node has neither children nor objects");
+            }
+        } else if node.object_count == 0 {
+            if !(i + 1..nodes.len() - 1).contains(&(node.left_child as usize)) {
+                return Err("a node's children are out of range");
+            }
+        } else if node.left_child as usize + node.object_count > object_indices.len() {
+            return Err("a leaf's objects are out of range");
+        }
+    }
+    if object_indices.iter().any(|&index| index >= objects) {
+        return Err("an object index is out of range");
+    }
+    Ok(())
+}
+
+fn write_vec3(writer: &mut impl Write, value: Vec3) -> io::Result<()> {
+    writer.write_all(&value.0.to_le_bytes())?;
+    writer.write_all(&value.1.to_le_bytes())?;
+    writer.write_all(&value.2.to_le_bytes())
+}
+
+fn read_vec3(reader: &mut impl Read) -> io::Result<Vec3> {
+    Ok(Vec3(read_f32(reader)?, read_f32(reader)?, read_f32(reader)?))
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
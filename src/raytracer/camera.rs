@@ -1,46 +1,171 @@
+use std::f32::consts::PI;
+
 use super::{rotate_vector_around_axis, Vec3};
 
+/// How the camera turns points of the image into rays. The panoramic projections lay the image
+/// plane out in angles around the camera instead of at a distance in front of it, which the
+/// kernel turns into directions, so splitting the image into bands keeps working like it does
+/// for the perspective one.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    /// Through an image plane in front of the camera, spanning its field of view
+    #[default]
+    Perspective,
+    /// All the way around the camera, longitude across and latitude up the image, for 360 content
+    Equirectangular,
+    /// Equidistant fisheye, the angle from the view direction growing with the distance from the
+    /// image's center up to `fov` degrees across its height. Up to 360 degrees are shown.
+    Fisheye { fov: f32 },
+    /// The left eye's view on the left half and the right eye's on the right, `eye_separation`
+    /// apart. Objects `convergence` away from the camera appear at the depth of the screen.
+    Stereo { eye_separation: f32, convergence: f32 },
+    /// Parallel rays from an image plane `height` units tall on the camera, without perspective,
+    /// for technical drawings and top-down views
+    Orthographic { height: f32 },
+}
+
+impl Projection {
+    /// The projection with its default settings: a 180 degree fisheye, eyes 6.5 centimeters apart
+    /// converging at 2 meters for a scene in meters, or a 10 units tall orthographic view
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "equirectangular" => Some(Projection::Equirectangular),
+            "fisheye" => Some(Projection::Fisheye { fov: 180.0 }),
+            "stereo" => Some(Projection::Stereo { eye_separation: 0.065, convergence: 2.0 }),
+            "orthographic" => Some(Projection::Orthographic { height: 10.0 }),
+            _ => None,
+        }
+    }
+
+    /// Kind and settings as the kernel reads them, in the padding of the camera's vectors
+    pub fn uniform_data(self) -> [f32; 3] {
+        match self {
+            Projection::Perspective => [0.0, 0.0, 0.0],
+            Projection::Equirectangular => [1.0, 0.0, 0.0],
+            Projection::Fisheye { .. } => [2.0, 0.0, 0.0],
+            Projection::Stereo { eye_separation, convergence } => [3.0, eye_separation, convergence],
+            Projection::Orthographic { .. } => [4.0, 0.0, 0.0],
+        }
+    }
+}
+
+/// Floats of `Camera::to_floats`
+pub const CAMERA_FLOATS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Camera {
     pub origin: Vec3,
     pub lower_left_corner: Vec3,
     pub horizontal: Vec3,
     pub vertical: Vec3,
     pub lens_radius: f32,
+    /// Bit per layer to render, objects on none of these layers are skipped
+    pub render_mask: u32,
     aspect_ratio: f32,
     vfov: f32, // vertical field of view in degrees
     lookfrom: Vec3,
     lookat: Vec3,
     vup: Vec3, // up vector
+    projection: Projection,
 }
 
 impl Camera {
     pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, vfov: f32, aspect_ratio: f32) -> Self {
-        let theta = vfov.to_radians();
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h;
-        let viewport_width = aspect_ratio * viewport_height;
-
-        let w = (lookfrom - lookat).normalize();
-        let u = vup.cross(w).normalize();
-        let v = w.cross(u);
-
-        let origin = lookfrom;
-        let horizontal = u * viewport_width;
-        let vertical = v * viewport_height;
-        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w;
-
-        Camera {
-            origin,
-            lower_left_corner,
-            horizontal,
-            vertical,
+        let mut camera = Camera {
+            origin: lookfrom,
+            lower_left_corner: lookfrom,
+            horizontal: Vec3(0.0, 0.0, 0.0),
+            vertical: Vec3(0.0, 0.0, 0.0),
             lens_radius: 0.0, // Placeholder, assuming no lens distortion
+            render_mask: u32::MAX,
             aspect_ratio,
             vfov,
             lookfrom,
             lookat,
             vup,
-        }
+            projection: Projection::Perspective,
+        };
+        camera.update_camera();
+        camera
+    }
+
+    /// Looks straight down on the box from above, far enough up to see all of it, with +z up in the image
+    pub fn top_down(min_corner: Vec3, max_corner: Vec3, aspect_ratio: f32) -> Self {
+        const VFOV: f32 = 60.0;
+        let center = (min_corner + max_corner) * 0.5;
+        let radius = ((max_corner.0 - min_corner.0).powi(2) + (max_corner.2 - min_corner.2).powi(2)).sqrt() * 0.5;
+        let height = radius.max(0.5) / (VFOV.to_radians() / 2.0).tan();
+        let lookfrom = Vec3(center.0, max_corner.1 + height, center.2);
+        Camera::new(lookfrom, center, Vec3(0.0, 0.0, 1.0), VFOV, aspect_ratio)
+    }
+
+    /// Plan view of the box from above without perspective, showing all of it at the same scale
+    pub fn top_down_orthographic(min_corner: Vec3, max_corner: Vec3, aspect_ratio: f32) -> Self {
+        let mut camera = Camera::top_down(min_corner, max_corner, aspect_ratio);
+        let radius = ((max_corner.0 - min_corner.0).powi(2) + (max_corner.2 - min_corner.2).powi(2)).sqrt() * 0.5;
+        camera.set_projection(Projection::Orthographic { height: radius.max(0.5) * 2.0 });
+        camera
+    }
+
+    /// Moves the camera to `lookfrom` and turns it towards `lookat`, keeping its up direction
+    pub fn look_at(&mut self, lookfrom: Vec3, lookat: Vec3) {
+        self.lookfrom = lookfrom;
+        self.lookat = lookat;
+        self.update_camera();
+    }
+
+    /// Stretches the image plane to a new width over height, e.g. when the window is resized
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+        self.update_camera();
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// Switches how the image is mapped to rays, keeping where the camera is and looks
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.update_camera();
+    }
+
+    /// Where the camera is, where it looks and everything else it is made from, for files. The
+    /// image plane isn't included, `from_floats` works it out again the same way.
+    pub fn to_floats(&self) -> [f32; CAMERA_FLOATS] {
+        let (kind, [first, second]) = match self.projection {
+            Projection::Perspective => (0.0, [0.0, 0.0]),
+            Projection::Equirectangular => (1.0, [0.0, 0.0]),
+            Projection::Fisheye { fov } => (2.0, [fov, 0.0]),
+            Projection::Stereo { eye_separation, convergence } => (3.0, [eye_separation, convergence]),
+            Projection::Orthographic { height } => (4.0, [height, 0.0]),
+        };
+        let [from, at, up] = [self.lookfrom, self.lookat, self.vup];
+        [from.0, from.1, from.2, at.0, at.1, at.2, up.0, up.1, up.2,
+            self.vfov, self.aspect_ratio, self.lens_radius, f32::from_bits(self.render_mask), kind, first, second]
+    }
+
+    /// The camera `to_floats` was given, None when the projection isn't one
+    pub fn from_floats(data: [f32; CAMERA_FLOATS]) -> Option<Self> {
+        let projection = match data[13] as i32 {
+            0 => Projection::Perspective,
+            1 => Projection::Equirectangular,
+            2 => Projection::Fisheye { fov: data[14] },
+            3 => Projection::Stereo { eye_separation: data[14], convergence: data[15] },
+            4 => Projection::Orthographic { height: data[14] },
+            _ => return None,
+        };
+        let mut camera = Camera::new(Vec3(data[0], data[1], data[2]), Vec3(data[3], data[4], data[5]), Vec3(data[6], data[7], data[8]), data[9], data[10]);
+        camera.lens_radius = data[11];
+        camera.render_mask = data[12].to_bits();
+        camera.set_projection(projection);
+        Some(camera)
+    }
+
+    /// Shows the layer if it was hidden from the render, hides it otherwise
+    pub fn toggle_layer(&mut self, layer: u32) {
+        self.render_mask ^= 1 << layer;
     }
 
     // Correctly moves the camera forwards or backwards along the viewing direction
@@ -77,13 +202,49 @@ impl Camera {
         let u = self.vup.cross(w).normalize();
         let v = w.cross(u);
 
-        self.horizontal = u * viewport_width;
-        self.vertical = v * viewport_height;
-        self.lower_left_corner = self.lookfrom - self.horizontal / 2.0 - self.vertical / 2.0 - w;
+        // The panoramic image planes span angles in radians and sit on the camera
+        let (width, height, distance) = match self.projection {
+            // Each eye sees one half of the stereo image plane, the kernel moves it to the middle
+            Projection::Perspective | Projection::Stereo { .. } => (viewport_width, viewport_height, 1.0),
+            Projection::Equirectangular => (2.0 * PI, PI, 0.0),
+            Projection::Fisheye { fov } => (self.aspect_ratio * fov.to_radians(), fov.to_radians(), 0.0),
+            Projection::Orthographic { height } => (self.aspect_ratio * height, height, 0.0),
+        };
+        self.horizontal = u * width;
+        self.vertical = v * height;
+        self.lower_left_corner = self.lookfrom - self.horizontal / 2.0 - self.vertical / 2.0 - w * distance;
 
         self.origin = self.lookfrom;
     }
 
+    // Whether any part of a sphere lies inside the view pyramid through the image plane's corners
+    pub fn sees_sphere(&self, center: Vec3, radius: f32) -> bool {
+        // Panoramas see too much for a pyramid, and each eye has a pyramid of its own
+        if self.projection != Projection::Perspective {
+            return true;
+        }
+        let lower_left = self.lower_left_corner - self.origin;
+        let corners = [
+            lower_left,
+            lower_left + self.horizontal,
+            lower_left + self.horizontal + self.vertical,
+            lower_left + self.vertical,
+        ];
+        let forward = lower_left + (self.horizontal + self.vertical) * 0.5;
+        let offset = center - self.origin;
+
+        for i in 0..4 {
+            let mut normal = corners[i].cross(corners[(i + 1) % 4]).normalize();
+            if normal.dot(forward) < 0.0 {
+                normal = normal * -1.0; // Point the side plane's normal into the view
+            }
+            if normal.dot(offset) < -radius {
+                return false;
+            }
+        }
+        true
+    }
+
     // Rotates the camera left or right
     pub fn rotate_yaw(&mut self, angle_deg: f32) {
         let angle_rad = angle_deg.to_radians();
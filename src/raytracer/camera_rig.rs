@@ -0,0 +1,153 @@
+use std::time::Instant;
+
+use super::Vec3;
+
+// Steps each path segment is measured in, for moving along it at an even speed
+const SEGMENT_STEPS: usize = 32;
+// Seconds ahead on the path the camera looks at, so it turns into bends before reaching them
+const LOOK_AHEAD: f32 = 0.5;
+
+/// Wobble added to the view of a camera rig, like a handheld camera or a bumpy ride
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraShake {
+    /// Degrees the view turns off the path at most
+    pub amplitude: f32,
+    /// Roughly how many times a second the shake changes direction
+    pub frequency: f32,
+}
+
+/// Flies the camera through control points along a Catmull-Rom spline at a steady speed, facing
+/// where it is headed, so flythroughs are smooth and the same every time they're recorded
+#[derive(Debug, Clone)]
+pub struct CameraRig {
+    /// Points the path passes through, in order
+    pub points: Vec<Vec3>,
+    /// World units a second the camera moves along the path
+    pub speed: f32,
+    /// Starts over from the first point after reaching the last, otherwise stays at the end
+    pub looping: bool,
+    pub shake: Option<CameraShake>,
+    // Distance along the path at the end of each step of each segment
+    distances: Vec<f32>,
+    started: Option<Instant>, // Flying starts on the first update rather than when defined
+}
+
+impl CameraRig {
+    pub fn new(points: Vec<Vec3>, speed: f32, looping: bool) -> Self {
+        assert!(points.len() >= 2, "Camera paths need at least two points");
+        assert!(speed > 0.0, "Camera paths need a positive speed");
+        let mut rig = Self { points, speed, looping, shake: None, distances: Vec::new(), started: None };
+
+        let mut distance = 0.0;
+        let mut previous = rig.points[0];
+        for segment in 0..rig.points.len() - 1 {
+            for step in 1..=SEGMENT_STEPS {
+                let position = rig.spline(segment, step as f32 / SEGMENT_STEPS as f32);
+                distance += (position - previous).magnitude();
+                rig.distances.push(distance);
+                previous = position;
+            }
+        }
+        rig
+    }
+
+    /// Length of the whole path
+    pub fn length(&self) -> f32 {
+        *self.distances.last().unwrap()
+    }
+
+    /// Seconds it takes to fly the path once
+    pub fn duration(&self) -> f32 {
+        self.length() / self.speed
+    }
+
+    /// Where the camera is and looks at `seconds` into the flight
+    pub fn pose_at(&self, seconds: f32) -> (Vec3, Vec3) {
+        let position = self.position_at(seconds);
+        let ahead = self.position_at(seconds + LOOK_AHEAD);
+        // Near the end of a path that doesn't loop there's nothing ahead, so keep the last heading
+        let direction = if (ahead - position).magnitude() > 1e-4 {
+            (ahead - position).normalize()
+        } else {
+            (position - self.position_at(self.duration() - LOOK_AHEAD)).normalize()
+        };
+
+        let direction = match self.shake {
+            Some(shake) => {
+                let time = seconds * shake.frequency;
+                // Unrelated stretches of the same noise turn the view sideways and up or down
+                let yaw = (shake.amplitude * perlin(time)).to_radians();
+                let pitch = (shake.amplitude * perlin(time + 101.3)).to_radians();
+                let right = direction.cross(Vec3(0.0, 1.0, 0.0)).normalize();
+                let up = right.cross(direction);
+                (direction + right * yaw.tan() + up * pitch.tan()).normalize()
+            },
+            None => direction,
+        };
+        (position, position + direction)
+    }
+
+    /// Pose the camera should have by now
+    pub fn current_pose(&mut self) -> (Vec3, Vec3) {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        self.pose_at(started.elapsed().as_secs_f32())
+    }
+
+    /// Flies the path again from the start on the next update
+    pub fn restart(&mut self) {
+        self.started = None;
+    }
+
+    // Point on the path after flying for the given time
+    fn position_at(&self, seconds: f32) -> Vec3 {
+        let mut distance = seconds * self.speed;
+        distance = if self.looping { distance.rem_euclid(self.length()) } else { distance.clamp(0.0, self.length()) };
+
+        // Step the distance falls in, and how far through it
+        let index = self.distances.partition_point(|&end| end < distance).min(self.distances.len() - 1);
+        let start = if index == 0 { 0.0 } else { self.distances[index - 1] };
+        let through = if self.distances[index] > start { (distance - start) / (self.distances[index] - start) } else { 0.0 };
+
+        let segment = index / SEGMENT_STEPS;
+        let t = ((index % SEGMENT_STEPS) as f32 + through) / SEGMENT_STEPS as f32;
+        self.spline(segment, t)
+    }
+
+    // Point t of the way through the segment between two control points. The ends are repeated
+    // so the path starts and stops on them.
+    fn spline(&self, segment: usize, t: f32) -> Vec3 {
+        let last = self.points.len() - 1;
+        let p0 = self.points[segment.saturating_sub(1)];
+        let p1 = self.points[segment];
+        let p2 = self.points[(segment + 1).min(last)];
+        let p3 = self.points[(segment + 2).min(last)];
+
+        let t2 = t * t;
+        let t3 = t2 * t;
+        (p1 * 2.0
+            + (p2 - p0) * t
+            + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+            + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+            * 0.5
+    }
+}
+
+/// Smooth noise between -1 and 1 that varies about once per unit, 0 at every whole number
+pub fn perlin(x: f32) -> f32 {
+    let cell = x.floor();
+    let t = x - cell;
+    // Gradients at the two surrounding whole numbers, picked by hashing them
+    let gradient = |corner: f32| (hash(corner as i32 as u32) as f32 / u32::MAX as f32) * 2.0 - 1.0;
+    let left = gradient(cell) * t;
+    let right = gradient(cell + 1.0) * (t - 1.0);
+    let fade = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    // A single octave only reaches half of its range
+    (left + (right - left) * fade) * 2.0
+}
+
+// PCG hash of one word, like the one the screen shader's grain uses
+fn hash(value: u32) -> u32 {
+    let state = value.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
@@ -0,0 +1,95 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use super::Scene;
+
+// Identifies checkpoint files, bumped whenever the layout below changes
+const MAGIC: &[u8; 8] = b"RTCKP001";
+
+// Layout, all little-endian:
+// magic, u64 scene fingerprint, u32 width, u32 height, u32 samples,
+// then width * height * 4 f32 of accumulated radiance, row by row
+/// An offline render stopped partway: the running average of its samples so far and how many
+/// there were. Samples are seeded by their pixel and frame index, so the count is all the random
+/// state there is, and a render resumed from it traces the frames it would have gone on with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// Identifies the scene and camera rendered, see `render_fingerprint`
+    pub scene: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Samples per pixel in the accumulation, the frame the render goes on from
+    pub samples: u32,
+    /// Linear RGBA radiance laid out like `render_offline`'s
+    pub accumulation: Vec<f32>,
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint next to the path and then moves it over the last one, so a render
+    /// stopped while writing keeps the checkpoint before
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let partial = format!("{}.partial", path);
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.scene.to_le_bytes())?;
+        for value in [self.width, self.height, self.samples] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(bytemuck::cast_slice(&self.accumulation))?;
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&partial, path)
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a render checkpoint"));
+        }
+        let mut scene = [0; 8];
+        reader.read_exact(&mut scene)?;
+        let [width, height, samples] = [read_u32(&mut reader)?, read_u32(&mut reader)?, read_u32(&mut reader)?];
+
+        // Read through `take` so a broken size fails at the end of the file instead of allocating it
+        let len = width as u64 * height as u64 * 16;
+        let mut bytes = Vec::new();
+        reader.take(len).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "checkpoint ends early"));
+        }
+        let accumulation = bytes.chunks_exact(4).map(|value| f32::from_le_bytes(value.try_into().unwrap())).collect();
+        Ok(Self { scene: u64::from_le_bytes(scene), width, height, samples, accumulation })
+    }
+
+    /// Whether a render of the scene with the fingerprint at the size can go on from the checkpoint
+    pub fn check(&self, scene: u64, width: u32, height: u32) -> Result<(), String> {
+        if (self.width, self.height) != (width, height) {
+            return Err(format!("it is of a {}x{} render, not {}x{}", self.width, self.height, width, height));
+        }
+        if self.scene != scene {
+            return Err("it is of a different scene, camera or render options".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// FNV-1a over the scene's objects, materials and the parameters of its first frame, which hold
+/// the camera and the render options. Textures and the sky aren't included.
+pub fn render_fingerprint(scene: &Scene) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for bytes in [scene.flatten_scene_data(0), scene.flatten_object_data(), scene.flatten_material_data()] {
+        for byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
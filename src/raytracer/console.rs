@@ -0,0 +1,194 @@
+use super::Vec3;
+
+// Lines the console keeps before dropping the oldest
+const MAX_LOG_LINES: usize = 200;
+
+/// What `help` prints, one command per line
+pub const COMMAND_HELP: &str = "\
+spawn sphere|square X Y Z [SIZE]  adds a shape, undone like other edits
+set SETTING VALUE                 bounces, diffuse-bounces, specular-bounces, sky-intensity, sky-yaw,
+                                  max-radiance, idle-samples, exposure, fade, probes, or spectral, caustics,
+                                  bidirectional, bvh-heatmap, auto-exposure, post-effects, hybrid,
+                                  frustum-culling, stackless on|off
+load PATH                         adds an OBJ, STL or PLY mesh, or builds the scene from a .rhai script
+screenshot [PATH]                 saves the view as PNG, or OpenEXR for .exr paths
+wait SAMPLES                      holds the following commands until the view has that many samples
+undo, redo, help";
+
+/// A line typed into the console or read from a `--commands` file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Spawn { shape: Shape, position: Vec3, size: f32 },
+    Set { setting: Setting, value: f32 },
+    Load(String),
+    Screenshot(Option<String>),
+    Wait(u32),
+    Undo,
+    Redo,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    Sphere,
+    Square,
+}
+
+/// Scene and renderer options the console can change. Switches take 1 for on and 0 for off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    /// Both the diffuse and the specular bounces
+    Bounces,
+    DiffuseBounces,
+    SpecularBounces,
+    SkyIntensity,
+    SkyYaw,
+    MaxRadiance,
+    IdleSamples,
+    Spectral,
+    Caustics,
+    Bidirectional,
+    BvhHeatmap,
+    /// In stops
+    Exposure,
+    AutoExposure,
+    /// Chromatic aberration, vignette and film grain
+    PostEffects,
+    /// Seconds the view fades from the last converged frame after the camera moves
+    Fade,
+    /// Spacing of irradiance probes spread over the scene, 0 removes them
+    Probes,
+    /// Rasterize the triangles camera rays hit first
+    Hybrid,
+    /// Upload only the part of the BVH inside the camera's view
+    FrustumCulling,
+    /// Walk the BVH with skip links instead of a per-ray stack
+    Stackless,
+}
+
+impl Setting {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bounces" => Some(Setting::Bounces),
+            "diffuse-bounces" => Some(Setting::DiffuseBounces),
+            "specular-bounces" => Some(Setting::SpecularBounces),
+            "sky-intensity" => Some(Setting::SkyIntensity),
+            "sky-yaw" => Some(Setting::SkyYaw),
+            "max-radiance" => Some(Setting::MaxRadiance),
+            "idle-samples" => Some(Setting::IdleSamples),
+            "spectral" => Some(Setting::Spectral),
+            "caustics" => Some(Setting::Caustics),
+            "bidirectional" => Some(Setting::Bidirectional),
+            "bvh-heatmap" => Some(Setting::BvhHeatmap),
+            "exposure" => Some(Setting::Exposure),
+            "auto-exposure" => Some(Setting::AutoExposure),
+            "post-effects" => Some(Setting::PostEffects),
+            "fade" => Some(Setting::Fade),
+            "probes" => Some(Setting::Probes),
+            "hybrid" => Some(Setting::Hybrid),
+            "frustum-culling" => Some(Setting::FrustumCulling),
+            "stackless" => Some(Setting::Stackless),
+            _ => None,
+        }
+    }
+
+    fn is_switch(self) -> bool {
+        matches!(self, Setting::Spectral | Setting::Caustics | Setting::Bidirectional | Setting::BvhHeatmap | Setting::AutoExposure | Setting::PostEffects | Setting::Hybrid | Setting::FrustumCulling | Setting::Stackless)
+    }
+}
+
+/// Reads one command, with an error saying what was wrong with the line
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let number = |i: usize, what: &str| -> Result<f32, String> {
+        let word = words.get(i).ok_or_else(|| format!("missing {}", what))?;
+        word.parse::<f32>().ok().filter(|value| value.is_finite()).ok_or_else(|| format!("{} \"{}\" is not a number", what, word))
+    };
+
+    let command = match words.first().copied() {
+        Some("spawn") => {
+            let shape = match words.get(1).copied() {
+                Some("sphere") => Shape::Sphere,
+                Some("square") => Shape::Square,
+                Some(other) => return Err(format!("can't spawn \"{}\", only sphere or square", other)),
+                None => return Err("missing the shape to spawn".to_string()),
+            };
+            let position = Vec3(number(2, "x")?, number(3, "y")?, number(4, "z")?);
+            let size = if words.len() > 5 { number(5, "size")? } else { 0.5 };
+            if size <= 0.0 {
+                return Err("size has to be positive".to_string());
+            }
+            Command::Spawn { shape, position, size }
+        },
+        Some("set") => {
+            let name = words.get(1).ok_or("missing the setting")?;
+            let setting = Setting::from_name(name).ok_or_else(|| format!("unknown setting \"{}\"", name))?;
+            let value = match words.get(2).copied() {
+                Some("on" | "true") if setting.is_switch() => 1.0,
+                Some("off" | "false") if setting.is_switch() => 0.0,
+                _ => number(2, "value")?,
+            };
+            Command::Set { setting, value }
+        },
+        // Paths may contain spaces, so everything after the command is the path
+        Some("load") => match line.trim().strip_prefix("load").map(str::trim) {
+            Some(path) if !path.is_empty() => Command::Load(path.to_string()),
+            _ => return Err("missing the path to load".to_string()),
+        },
+        Some("screenshot") => {
+            let path = line.trim().strip_prefix("screenshot").map(str::trim).filter(|path| !path.is_empty());
+            Command::Screenshot(path.map(str::to_string))
+        },
+        Some("wait") => {
+            let samples = number(1, "sample count")?;
+            if samples < 0.0 {
+                return Err("sample count can't be negative".to_string());
+            }
+            Command::Wait(samples as u32)
+        },
+        Some("undo") => Command::Undo,
+        Some("redo") => Command::Redo,
+        Some("help") => Command::Help,
+        Some(other) => return Err(format!("unknown command \"{}\", try help", other)),
+        None => return Err("empty command".to_string()),
+    };
+    Ok(command)
+}
+
+/// What the console shows and what was typed into it, dropped down over the view with the
+/// backtick key
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    /// The line being typed
+    pub input: String,
+    /// Commands entered and what they printed, oldest first
+    pub log: Vec<String>,
+    queued: Vec<Command>,
+}
+
+impl Console {
+    /// Reads the typed line, queuing the command for `take_commands` or logging why it couldn't be read
+    pub fn submit(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        if line.trim().is_empty() {
+            return;
+        }
+        self.print(&format!("> {}", line.trim()));
+        match parse_command(&line) {
+            Ok(command) => self.queued.push(command),
+            Err(e) => self.print(&e),
+        }
+    }
+
+    pub fn print(&mut self, message: &str) {
+        self.log.extend(message.lines().map(str::to_string));
+        let excess = self.log.len().saturating_sub(MAX_LOG_LINES);
+        self.log.drain(..excess);
+    }
+
+    /// Commands entered since the last call, in order
+    pub fn take_commands(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.queued)
+    }
+}
@@ -0,0 +1,1337 @@
+use image::DynamicImage;
+use rayon::prelude::*;
+use tracing::warn;
+
+use super::{is_packed, scene_images, Scene, SkyFaces, Vec3, MATERIAL_FLOATS, MAX_PORTALS, NODE_FLOATS, OBJECT_STRIDE, POINT_WORDS};
+
+// Matches the constants of the same name in the kernel
+const OUTLIER_WARMUP_FRAMES: f32 = 8.0;
+const OUTLIER_RATIO: f32 = 4.0;
+const CURVE_SEGMENTS: u32 = 8;
+const RAY_T_MIN: f32 = 0.00001;
+const MAX_BVH_DEPTH: usize = 32;
+
+/// The flattened buffers and active sky of a built scene, everything the CPU renderer reads of it.
+/// Render workers are sent these in place of the scene.
+#[derive(Clone)]
+pub struct SceneBuffers {
+    pub objects: Vec<u8>,
+    pub nodes: Vec<u8>,
+    pub object_indices: Vec<u8>,
+    pub materials: Vec<u8>,
+    pub points: Vec<u8>,
+    /// The six cube map faces
+    pub sky: Vec<DynamicImage>,
+    /// The scene's images followed by the label atlas, see `scene_images`
+    pub textures: Vec<DynamicImage>,
+}
+
+impl SceneBuffers {
+    pub fn new(scene: &Scene) -> Self {
+        let sky = match scene.skies[scene.active_sky].load().expect("Failed to load sky") {
+            SkyFaces::Images(images) => images,
+            SkyFaces::Compressed(cube) => cube.decompress().expect("Failed to load sky"),
+        };
+        Self {
+            objects: scene.flatten_object_data(),
+            nodes: scene.flatten_node_data(),
+            object_indices: scene.flatten_object_index_data(),
+            materials: scene.flatten_material_data(),
+            points: scene.flatten_point_data(),
+            sky,
+            textures: scene_images(scene, |_| {}),
+        }
+    }
+
+    /// Whether the buffers can be rendered without reading past one of them, for buffers that
+    /// came from elsewhere. Every node's children and leaf range, every object index, and every
+    /// object's material, points and texture, and every material's texture have to be in range, the nodes have to form a tree no deeper
+    /// than the traversal stack, and the skip links have to be the ones built for that tree.
+    pub fn check(&self) -> Result<(), &'static str> {
+        let objects = words(&self.objects);
+        let objects: Vec<&[u32]> = objects.chunks_exact(OBJECT_STRIDE as usize / 4).collect();
+        let nodes = floats(&self.nodes);
+        let nodes: Vec<&[f32]> = nodes.chunks_exact(NODE_FLOATS).collect();
+        let object_indices = floats(&self.object_indices);
+        let materials = floats(&self.materials);
+        let materials: Vec<&[f32]> = materials.chunks_exact(MATERIAL_FLOATS).collect();
+        let points = self.points.len() / 4 / POINT_WORDS;
+        let textures = self.textures.len() as f32;
+
+        if nodes.is_empty() {
+            return Err("scene has no BVH");
+        }
+        // Children come after their parent, so one pass finds every node's depth and the skip link
+        // `skip_links` gives it. A node with two parents or a skip link other than that one could
+        // send the traversal round in a loop.
+        let (mut depths, mut skips, mut parented) = (vec![0; nodes.len()], vec![-1.0; nodes.len()], vec![false; nodes.len()]);
+        for (i, node) in nodes.iter().enumerate() {
+            let (left_child, object_count, skip) = (node[3], node[7], node[8]);
+            if skip != skips[i] {
+                return Err("BVH skip link doesn't match the tree");
+            }
+            if object_count == 0.0 {
+                if !(i as f32 + 1.0..nodes.len() as f32 - 1.0).contains(&left_child) {
+                    return Err("BVH children out of range");
+                }
+                if depths[i] >= MAX_BVH_DEPTH {
+                    return Err("BVH is deeper than the traversal stack");
+                }
+                let left_child = left_child as usize;
+                if parented[left_child] || parented[left_child + 1] {
+                    return Err("BVH node has two parents");
+                }
+                (parented[left_child], parented[left_child + 1]) = (true, true);
+                (depths[left_child], depths[left_child + 1]) = (depths[i] + 1, depths[i] + 1);
+                (skips[left_child], skips[left_child + 1]) = (left_child as f32 + 1.0, skips[i]);
+            } else if !(object_count > 0.0 && (0.0..=object_indices.len() as f32 - object_count).contains(&left_child)) {
+                return Err("BVH leaf out of range");
+            }
+        }
+        if !object_indices.iter().all(|index| (0.0..objects.len() as f32).contains(index)) {
+            return Err("object index out of range");
+        }
+
+        if !materials.iter().all(|material| material[4] < 0.0 || (0.0..textures).contains(&material[4])) {
+            return Err("material texture out of range");
+        }
+        for object in objects {
+            let [kind, material, data @ ..] = [0, 1, 2, 3, 4].map(|i| f32::from_bits(object[i]));
+            if !(0.0..materials.len() as f32).contains(&material) {
+                return Err("object material out of range");
+            }
+            // Image textured spheres and billboards keep their texture after the color
+            let (texture_kind, texture) = (f32::from_bits(object[9]), f32::from_bits(object[10]));
+            if (kind == 3.0 || (kind == 0.0 && texture_kind == 1.0)) && !(0.0..textures).contains(&texture) {
+                return Err("object texture out of range");
+            }
+            let in_points = |first: f32, count: f32| (0.0..=points as f32 - count).contains(&first);
+            let points_fit = match kind as u32 {
+                // Corner normals, when the triangle has them
+                1 => data[1] < 0.0 || in_points(data[1], 3.0),
+                4 => data[1] >= 0.0 && in_points(data[0], data[1]),
+                _ => true,
+            };
+            if !points_fit {
+                return Err("object points out of range");
+            }
+            // Instances keep the root of their prototype's BVH after the matrix
+            if kind == 6.0 && !(0.0..nodes.len() as f32).contains(&f32::from_bits(object[14])) {
+                return Err("instance BVH out of range");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Path tracer running the kernel's traversal and shading on the CPU, for machines without a
+/// usable adapter and for checking the GPU's output. It reads the same flattened buffers the
+/// kernel does and draws the same random numbers per pixel and frame, so both converge to the
+/// same image. Textures are sampled at their full resolution where the kernel picks a mip level
+/// by the ray's footprint. Caustics, bidirectional connections, probe grids, AOVs and the BVH
+/// heat map are left out, see `warn_unsupported`.
+pub struct CpuRenderer {
+    width: u32,
+    height: u32,
+    first_row: u32,
+    scene: CpuScene,
+    /// Running average of the radiance, four floats per pixel like the accumulation buffer
+    pub accumulation: Vec<f32>,
+}
+
+impl CpuRenderer {
+    /// Copies out a built scene's buffers and loads its active sky
+    pub fn new(scene: &Scene, width: u32, height: u32) -> Self {
+        Self::from_buffers(&SceneBuffers::new(scene), width, height, 0)
+    }
+
+    /// Renders the rows of a larger image starting at `first_row`, drawing the random numbers that
+    /// image's pixels would, so tiles traced apart add up to the image traced whole. The scene
+    /// parameters have to narrow the camera to the tile's rows, see `band_frames`.
+    pub fn from_buffers(buffers: &SceneBuffers, width: u32, height: u32, first_row: u32) -> Self {
+        let object_words = words(&buffers.objects);
+        let objects = object_words.chunks_exact(OBJECT_STRIDE as usize / 4).map(|object| Primitive {
+            kind: f32::from_bits(object[0]),
+            material: f32::from_bits(object[1]) as usize,
+            data: std::array::from_fn(|i| f32::from_bits(object[2 + i])),
+            layers: object[18],
+        }).collect();
+        let nodes = floats(&buffers.nodes).chunks_exact(NODE_FLOATS).map(|node| CpuNode {
+            min_corner: Vec3(node[0], node[1], node[2]),
+            left_child: node[3] as usize,
+            max_corner: Vec3(node[4], node[5], node[6]),
+            object_count: node[7] as usize,
+            skip: node[8] as i32,
+            split_axis: node[9] as usize,
+        }).collect();
+        let materials = floats(&buffers.materials).chunks_exact(MATERIAL_FLOATS).map(|material| CpuMaterial {
+            flags: material[0] as u32,
+            film_thickness: material[1],
+            film_ior: material[2],
+            alpha_cutoff: material[3],
+            albedo_texture: material[4],
+            roughness: material[5],
+            metalness: material[6],
+            transparency: material[7],
+            ior: material[8],
+            dispersion: material[9],
+        }).collect();
+        let points = words(&buffers.points).chunks_exact(POINT_WORDS).map(|point| [point[0], point[1], point[2], point[3]]).collect();
+
+        Self {
+            width,
+            height,
+            first_row,
+            scene: CpuScene {
+                objects,
+                nodes,
+                object_indices: floats(&buffers.object_indices).into_iter().map(|index| index as usize).collect(),
+                materials,
+                points,
+                sky: CpuSky::new(buffers.sky.clone()),
+                textures: buffers.textures.iter().map(CpuTexture::new).collect(),
+            },
+            accumulation: vec![0.0; (width * height * 4) as usize],
+        }
+    }
+
+    /// Starts over on another tile of the same scene, keeping the converted buffers and sky
+    pub fn set_tile(&mut self, width: u32, height: u32, first_row: u32) {
+        (self.width, self.height, self.first_row) = (width, height, first_row);
+        self.accumulation = vec![0.0; (width * height * 4) as usize];
+    }
+
+    /// Casts single rays into the scene for baking. The returned function gives the distance to
+    /// the nearest surface a ray with a unit direction hits before `max_distance`, None when
+    /// nothing is in the way. Surfaces are found as camera paths find them, with the scene
+    /// parameters of `Scene::flatten_scene_data`.
+    pub fn ray_caster(&self, scene_data: &[u8]) -> impl Fn(Vec3, Vec3, f32, &mut u32) -> Option<f32> + Sync + '_ {
+        let parameters = Parameters::new(&floats(scene_data));
+        move |origin, direction, max_distance, rng| {
+            self.scene.trace(&parameters, Ray { origin, direction }, rng).map(|hit| hit.t).filter(|&t| t < max_distance)
+        }
+    }
+
+    /// Light arriving along rays starting anywhere in the scene, traced like the camera's samples
+    /// with the parameters of `Scene::flatten_scene_data`, e.g. for lightmap baking
+    pub fn path_tracer(&self, scene_data: &[u8]) -> impl Fn(Vec3, Vec3, &mut u32) -> Vec3 + Sync + '_ {
+        let parameters = Parameters::new(&floats(scene_data));
+        move |origin, direction, rng| {
+            let sample = self.scene.ray_color(&parameters, Ray { origin, direction }, rng);
+            if parameters.max_radiance > 0.0 && luminance(sample) > parameters.max_radiance {
+                return sample * (parameters.max_radiance / luminance(sample));
+            }
+            sample
+        }
+    }
+
+    /// Albedo and normal of the first surface the camera ray through each pixel's center hits,
+    /// three floats each per pixel, for guiding the denoiser. Misses get the sky's color and a
+    /// zero normal like the kernel's AOVs.
+    pub fn denoise_guides(&self, scene_data: &[u8]) -> (Vec<f32>, Vec<f32>) {
+        let parameters = Parameters::new(&floats(scene_data));
+        let (width, height) = (self.width, self.height);
+        let guides: Vec<(Vec3, Vec3)> = (0..width * height).into_par_iter().map(|i| {
+            let uv = (((i % width) as f32 + 0.5) / width as f32, ((i / width) as f32 + 0.5) / height as f32);
+            let mut rng = i;
+            let Some(ray) = camera_ray(&parameters, uv) else {
+                return (Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0));
+            };
+            match self.scene.trace(&parameters, ray, &mut rng) {
+                Some(hit) => (hit.color, hit.normal),
+                None => (self.scene.sky_color(&parameters, ray.direction), Vec3(0.0, 0.0, 0.0)),
+            }
+        }).collect();
+        let albedo = guides.iter().flat_map(|(albedo, _)| [albedo.0, albedo.1, albedo.2]).collect();
+        let normal = guides.iter().flat_map(|(_, normal)| [normal.0, normal.1, normal.2]).collect();
+        (albedo, normal)
+    }
+
+    /// Traces one sample per pixel, or per block of pixels while the view is changing, with the
+    /// scene parameters of `Scene::flatten_scene_data` and adds it to the running average.
+    /// Rows of blocks are traced in parallel.
+    pub fn render_frame(&mut self, scene_data: &[u8]) {
+        let parameters = Parameters::new(&floats(scene_data));
+        let scene = &self.scene;
+        let (width, height, first_row) = (self.width, self.height, self.first_row);
+        let block = parameters.block_size.max(1);
+        let block_row_len = (width * block * 4) as usize;
+
+        self.accumulation.par_chunks_mut(block_row_len).enumerate().for_each(|(block_y, rows)| {
+            for block_x in 0..width.div_ceil(block) {
+                let (x, y) = (block_x * block, block_y as u32 * block);
+                let uv = (
+                    (x as f32 + 0.5 * block as f32) / width as f32,
+                    (y as f32 + 0.5 * block as f32) / height as f32,
+                );
+                let mut rng = block_x.wrapping_mul(1973)
+                    .wrapping_add((block_y as u32 + first_row / block).wrapping_mul(9277))
+                    .wrapping_add((parameters.frame_index as u32).wrapping_mul(26699));
+                let mut sample = match camera_ray(&parameters, uv) {
+                    Some(ray) => scene.ray_color(&parameters, ray, &mut rng),
+                    None => Vec3(0.0, 0.0, 0.0),
+                };
+                if parameters.max_radiance > 0.0 && luminance(sample) > parameters.max_radiance {
+                    sample = sample * (parameters.max_radiance / luminance(sample));
+                }
+
+                for dy in 0..block.min(height - y) {
+                    for dx in 0..block.min(width - x) {
+                        let offset = ((dy * width + x + dx) * 4) as usize;
+                        accumulate_sample(&parameters, &mut rows[offset..offset + 4], sample);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Renders `samples` samples per pixel of a built scene on the CPU and returns the linear RGBA
+/// radiance, laid out like `render_offline`'s
+pub fn render_cpu(scene: &Scene, width: u32, height: u32, samples: u32) -> Vec<f32> {
+    resume_cpu(scene, width, height, 0..samples, None)
+}
+
+/// Traces the samples `frames` like `render_cpu`, going on from the radiance of the samples before
+/// `frames.start`, None when it starts there
+pub fn resume_cpu(scene: &Scene, width: u32, height: u32, frames: std::ops::Range<u32>, accumulation: Option<&[f32]>) -> Vec<f32> {
+    warn_unsupported(scene);
+    let mut renderer = CpuRenderer::new(scene, width, height);
+    if let Some(accumulation) = accumulation {
+        renderer.accumulation.copy_from_slice(accumulation);
+    }
+    for frame in frames {
+        renderer.render_frame(&scene.flatten_scene_data(frame));
+    }
+    renderer.accumulation
+}
+
+/// Warns about the parts of the scene `CpuRenderer` leaves out, which the kernel would have drawn
+pub fn warn_unsupported(scene: &Scene) {
+    let unsupported: Vec<&str> = [
+        (scene.caustics, "caustics"),
+        (scene.bidirectional, "bidirectional connections"),
+        (scene.probe_grid.is_some(), "the probe grid"),
+        (scene.write_aovs, "AOVs"),
+        (scene.bvh_heatmap, "the BVH heat map"),
+    ].into_iter().filter_map(|(used, feature)| used.then_some(feature)).collect();
+    if !unsupported.is_empty() {
+        warn!("The CPU renderer leaves out {} of this scene, its image will differ from the GPU's", unsupported.join(", "));
+    }
+}
+
+// The camera's ray through a point of the image like the kernel's camera_ray, None outside the
+// circle a fisheye sees
+fn camera_ray(parameters: &Parameters, uv: (f32, f32)) -> Option<Ray> {
+    let on_plane = parameters.lower_left_corner + parameters.horizontal * uv.0 + parameters.vertical * uv.1 - parameters.camera_origin;
+    let right = parameters.horizontal.normalize();
+    let up = parameters.vertical.normalize();
+    let forward = up.cross(right);
+    let (x, y) = (on_plane.dot(right), on_plane.dot(up));
+
+    let origin = parameters.camera_origin;
+    let ray = match parameters.projection as u32 {
+        1 => Ray { origin, direction: (right * x.sin() + forward * x.cos()) * y.cos() + up * y.sin() },
+        2 => {
+            let angle = x.hypot(y);
+            if angle > std::f32::consts::PI {
+                return None;
+            }
+            Ray { origin, direction: (right * x + up * y) * (angle.sin() / angle.max(1e-6)) + forward * angle.cos() }
+        },
+        3 => {
+            let eye = if x > 0.0 { 1.0 } else { -1.0 };
+            let eye_x = x - eye * parameters.horizontal.magnitude() * 0.25;
+            let converged = (right * eye_x + up * y + forward) * parameters.projection_b;
+            let offset = right * (eye * parameters.projection_a * 0.5);
+            Ray { origin: origin + offset, direction: (converged - offset).normalize() }
+        },
+        4 => Ray { origin: origin + on_plane, direction: forward },
+        _ => Ray { origin, direction: on_plane.normalize() },
+    };
+    Some(ray)
+}
+
+// Adds a sample to a pixel's running average, with the kernel's outlier rejection
+fn accumulate_sample(parameters: &Parameters, pixel: &mut [f32], sample: Vec3) {
+    let mut sample = sample;
+    if parameters.outlier_rejection && parameters.frame_index >= OUTLIER_WARMUP_FRAMES {
+        let bound = OUTLIER_RATIO * luminance(Vec3(pixel[0], pixel[1], pixel[2])).max(0.05);
+        if luminance(sample) > bound {
+            sample = sample * (bound / luminance(sample));
+        }
+    }
+
+    let accumulated = [sample.0, sample.1, sample.2, 1.0];
+    let weight = 1.0 / (parameters.frame_index + 1.0);
+    for (channel, value) in pixel.iter_mut().zip(accumulated) {
+        *channel = if parameters.frame_index > 0.0 { *channel * (1.0 - weight) + value * weight } else { value };
+    }
+}
+
+// Fields of the scene uniform, see `Scene::flatten_scene_data`
+struct Parameters {
+    camera_origin: Vec3,
+    lower_left_corner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    projection: f32,
+    projection_a: f32,
+    projection_b: f32,
+    max_specular_bounces: u32,
+    frame_index: f32,
+    stackless_traversal: bool,
+    render_mask: u32,
+    max_diffuse_bounces: u32,
+    sky_yaw: f32,
+    sky_intensity: f32,
+    spectral: bool,
+    max_radiance: f32,
+    roughness_regularization: f32,
+    outlier_rejection: bool,
+    block_size: u32,
+    portals: Vec<[Vec3; 3]>,
+}
+
+impl Parameters {
+    fn new(data: &[f32]) -> Self {
+        let vector = |i: usize| Vec3(data[i], data[i + 1], data[i + 2]);
+        let portal_count = (data[24] as usize).min(MAX_PORTALS);
+        Self {
+            camera_origin: vector(0),
+            lower_left_corner: vector(4),
+            horizontal: vector(8),
+            vertical: vector(12),
+            projection: data[3],
+            projection_a: data[7],
+            projection_b: data[11],
+            max_specular_bounces: data[15] as u32,
+            frame_index: data[18],
+            stackless_traversal: data[19] > 0.0,
+            render_mask: data[20].to_bits(),
+            max_diffuse_bounces: data[21] as u32,
+            sky_yaw: data[22],
+            sky_intensity: data[23],
+            spectral: data[25] > 0.0,
+            max_radiance: data[26],
+            roughness_regularization: data[27],
+            outlier_rejection: data[28] > 0.0,
+            block_size: data[32] as u32,
+            portals: (0..portal_count).map(|i| [vector(36 + i * 12), vector(40 + i * 12), vector(44 + i * 12)]).collect(),
+        }
+    }
+}
+
+struct Primitive {
+    kind: f32,
+    material: usize,
+    data: [f32; 16],
+    layers: u32,
+}
+
+struct CpuNode {
+    min_corner: Vec3,
+    left_child: usize,
+    max_corner: Vec3,
+    object_count: usize,
+    skip: i32,
+    split_axis: usize,
+}
+
+struct CpuMaterial {
+    flags: u32,
+    film_thickness: f32,
+    film_ior: f32,
+    alpha_cutoff: f32,
+    albedo_texture: f32, // Index into textures, negative without one
+    roughness: f32,
+    metalness: f32,
+    transparency: f32,
+    ior: f32,
+    dispersion: f32,
+}
+
+#[derive(Clone, Copy)]
+struct Ray {
+    origin: Vec3,
+    direction: Vec3,
+}
+
+#[derive(Clone, Copy)]
+struct Hit {
+    t: f32,
+    color: Vec3,
+    position: Vec3,
+    normal: Vec3,
+    front_face: bool,
+    material: usize,
+    tangent: Vec3, // Direction of a hit curve, zero on other surfaces
+}
+
+// Buffers of a built scene, decoded from the bytes uploaded to the GPU
+struct CpuScene {
+    objects: Vec<Primitive>,
+    nodes: Vec<CpuNode>,
+    object_indices: Vec<usize>,
+    materials: Vec<CpuMaterial>,
+    points: Vec<[u32; 4]>,
+    sky: CpuSky,
+    textures: Vec<CpuTexture>,
+}
+
+impl CpuScene {
+    fn ray_color(&self, parameters: &Parameters, ray: Ray, rng: &mut u32) -> Vec3 {
+        let mut color = Vec3(1.0, 1.0, 1.0);
+        let mut ray = ray;
+        let mut diffuse_bounces = 0;
+        let mut specular_bounces = 0;
+        let mut path_weight = 1.0;
+
+        let mut wavelengths = [0.0; 4];
+        let mut dispersed = false;
+        if parameters.spectral {
+            let hero = random_float(rng);
+            wavelengths = [0.0, 0.25, 0.5, 0.75].map(|offset| 380.0 + 340.0 * (hero + offset).fract());
+        }
+        loop {
+            let Some(hit) = self.trace(parameters, ray, rng) else {
+                color = (self.sky_color(parameters, ray.direction) + color) * 0.5;
+                break;
+            };
+
+            let material = &self.materials[hit.material];
+            let mut surface_color = hit.color;
+            if material.film_thickness > 0.0 {
+                let cos_theta = ray.direction.dot(hit.normal).abs();
+                surface_color = times(surface_color, thin_film_tint(cos_theta, material.film_thickness, material.film_ior));
+            }
+            color = (surface_color + color) * 0.5;
+
+            let specular = material.flags & 4 == 0 || random_float(rng) < material.metalness;
+            let hair = material.flags & 8 != 0 && hit.tangent.dot(hit.tangent) > 0.0;
+            if material.ior > 0.0 {
+                if specular_bounces >= parameters.max_specular_bounces {
+                    break;
+                }
+                specular_bounces += 1;
+                let mut ior = material.ior;
+                if parameters.spectral && material.dispersion > 0.0 {
+                    let micrometers = wavelengths[0] / 1000.0;
+                    ior += material.dispersion * (1.0 / (micrometers * micrometers) - 1.0 / (0.5876 * 0.5876));
+                    dispersed = true;
+                }
+                ray.direction = scatter_dielectric(ray.direction, hit.normal, hit.front_face, ior, rng);
+            } else if hair {
+                if diffuse_bounces >= parameters.max_diffuse_bounces {
+                    break;
+                }
+                diffuse_bounces += 1;
+                ray.direction = sample_hair(ray.direction, hit.normal, hit.tangent, material.roughness, rng);
+            } else if !specular {
+                if diffuse_bounces >= parameters.max_diffuse_bounces {
+                    break;
+                }
+                diffuse_bounces += 1;
+                let scattered = hit.normal + random_unit_vector(rng);
+                ray.direction = if scattered.dot(scattered) < 1e-8 { hit.normal } else { scattered.normalize() };
+                if !parameters.portals.is_empty() {
+                    if random_float(rng) < 0.5 {
+                        ray.direction = sample_portal(parameters, hit.position, rng);
+                        if ray.direction.dot(hit.normal) <= 0.0 {
+                            path_weight = 0.0;
+                            break;
+                        }
+                    }
+                    path_weight *= portal_weight(parameters, hit.position, hit.normal, ray.direction);
+                }
+            } else {
+                if specular_bounces >= parameters.max_specular_bounces {
+                    break;
+                }
+                specular_bounces += 1;
+                let mut roughness = material.roughness;
+                if diffuse_bounces > 0 {
+                    roughness = roughness.max(parameters.roughness_regularization);
+                }
+                let mut reflected = reflect(ray.direction, hit.normal);
+                let jittered = reflected + random_unit_vector(rng) * roughness;
+                if jittered.dot(hit.normal) > 0.0 {
+                    reflected = jittered;
+                }
+                ray.direction = reflected.normalize();
+            }
+            ray.origin = offset_ray_origin(hit.position, hit.normal, ray.direction);
+        }
+        color = color * path_weight;
+
+        if parameters.spectral {
+            let mut response = wavelength_rgb(wavelengths[0]);
+            if !dispersed {
+                response = (response + wavelength_rgb(wavelengths[1]) + wavelength_rgb(wavelengths[2]) + wavelength_rgb(wavelengths[3])) * 0.25;
+            }
+            color = times(color, response);
+        }
+        color
+    }
+
+    fn sky_color(&self, parameters: &Parameters, direction: Vec3) -> Vec3 {
+        let (c, s) = (parameters.sky_yaw.cos(), parameters.sky_yaw.sin());
+        let rotated = Vec3(c * direction.0 - s * direction.2, direction.1, s * direction.0 + c * direction.2);
+        self.sky.sample(rotated) * parameters.sky_intensity
+    }
+
+    fn trace(&self, parameters: &Parameters, ray: Ray, rng: &mut u32) -> Option<Hit> {
+        if parameters.stackless_traversal {
+            self.traverse_stackless(parameters, ray, rng)
+        } else {
+            self.traverse_stack(parameters, ray, rng)
+        }
+    }
+
+    fn traverse_stack(&self, parameters: &Parameters, ray: Ray, rng: &mut u32) -> Option<Hit> {
+        let mut hit = None;
+        let mut nearest_hit = 9999.0;
+        let mut node_index = 0;
+        let mut stack = [0; MAX_BVH_DEPTH];
+        let mut stack_location = 0;
+
+        // A tree is walked in as many steps as it has nodes, more would mean the links loop
+        for _ in 0..self.nodes.len() {
+            let node = &self.nodes[node_index];
+            if node.object_count == 0 {
+                let (mut near, mut far) = (node.left_child, node.left_child + 1);
+                if ray.direction.axis(node.split_axis) < 0.0 {
+                    (near, far) = (far, near);
+                }
+                let near_distance = hit_aabb(ray, &self.nodes[near]);
+                let far_distance = hit_aabb(ray, &self.nodes[far]);
+                if near_distance < nearest_hit {
+                    if far_distance < nearest_hit {
+                        stack[stack_location] = far;
+                        stack_location += 1;
+                    }
+                    node_index = near;
+                    continue;
+                }
+                if far_distance < nearest_hit {
+                    node_index = far;
+                    continue;
+                }
+            } else {
+                hit = self.hit_leaf(parameters, ray, node, nearest_hit, hit, rng);
+                if let Some(hit) = hit {
+                    nearest_hit = hit.t;
+                }
+            }
+
+            if stack_location == 0 {
+                break;
+            }
+            stack_location -= 1;
+            node_index = stack[stack_location];
+        }
+        hit
+    }
+
+    fn traverse_stackless(&self, parameters: &Parameters, ray: Ray, rng: &mut u32) -> Option<Hit> {
+        let mut hit = None;
+        let mut nearest_hit = 9999.0;
+        let mut node_index = 0;
+
+        for _ in 0..self.nodes.len() {
+            if node_index < 0 {
+                break;
+            }
+            let node = &self.nodes[node_index as usize];
+            if hit_aabb(ray, node) >= nearest_hit {
+                node_index = node.skip;
+            } else if node.object_count == 0 {
+                node_index = node.left_child as i32;
+            } else {
+                hit = self.hit_leaf(parameters, ray, node, nearest_hit, hit, rng);
+                if let Some(hit) = hit {
+                    nearest_hit = hit.t;
+                }
+                node_index = node.skip;
+            }
+        }
+        hit
+    }
+
+    // Tests every object in a leaf, keeping the nearest hit
+    fn hit_leaf(&self, parameters: &Parameters, ray: Ray, node: &CpuNode, t_max: f32, old_hit: Option<Hit>, rng: &mut u32) -> Option<Hit> {
+        let mut hit = old_hit;
+        let mut nearest_hit = t_max;
+        for &object_index in &self.object_indices[node.left_child..node.left_child + node.object_count] {
+            let object = &self.objects[object_index];
+            if object.layers & parameters.render_mask == 0 {
+                continue;
+            }
+            let Some(mut new_hit) = self.hit_primitive(parameters, ray, object, RAY_T_MIN, nearest_hit) else {
+                continue;
+            };
+            let material = &self.materials[object.material];
+            if material.transparency > 0.0 && random_float(rng) < material.transparency {
+                continue;
+            }
+            nearest_hit = new_hit.t;
+            new_hit.material = object.material;
+            hit = Some(new_hit);
+        }
+        hit
+    }
+
+    fn hit_primitive(&self, parameters: &Parameters, ray: Ray, object: &Primitive, t_min: f32, t_max: f32) -> Option<Hit> {
+        let data = &object.data;
+        let vector = |i: usize| Vec3(data[i], data[i + 1], data[i + 2]);
+        let material = &self.materials[object.material];
+        match object.kind as u32 {
+            0 => {
+                let mut hit = hit_sphere(ray, &Sphere {
+                    center: vector(0),
+                    radius: data[3],
+                    color: vector(4),
+                    checker: (data[7] == 2.0).then(|| (vector(9), data[12])),
+                }, t_min, t_max)?;
+                if data[7] == 1.0 {
+                    let [r, g, b, _] = self.textures[data[8] as usize].sample(sphere_uv((hit.position - vector(0)) / data[3]));
+                    hit.color = times(hit.color, Vec3(r, g, b));
+                }
+                Some(hit)
+            },
+            1 => self.hit_triangle(ray, data, material, t_min, t_max),
+            2 => hit_quad(ray, vector(7), vector(10), vector(13), vector(4), material, &self.textures, t_min, t_max),
+            3 => self.hit_billboard(ray, parameters.camera_origin, data, t_min, t_max),
+            4 => self.hit_point_cluster(ray, data[0] as usize, data[1] as usize, data[2], vector(4), t_min, t_max),
+            5 => hit_curve(ray, [vector(0), vector(7), vector(10), vector(13)], data[3], vector(4), t_min, t_max),
+            6 => self.hit_instance(ray, data, material, t_min, t_max),
+            _ => None,
+        }
+    }
+
+    fn hit_triangle(&self, ray: Ray, data: &[f32; 16], material: &CpuMaterial, t_min: f32, t_max: f32) -> Option<Hit> {
+        let vector = |i: usize| Vec3(data[i], data[i + 1], data[i + 2]);
+        // Corner normals are kept in the point buffer like points, as three float bit patterns
+        let normals = (data[3] >= 0.0).then(|| [0, 1, 2].map(|i| {
+            let [x, y, z, _] = self.points[data[3] as usize + i];
+            Vec3(f32::from_bits(x), f32::from_bits(y), f32::from_bits(z))
+        }));
+        hit_triangle(ray, [vector(7), vector(10), vector(13)], vector(4), vector(0), normals, material, &self.textures, t_min, t_max)
+    }
+
+    // Takes the ray into the space of the instance's prototype and walks the prototype's BVH like
+    // the kernel's hit_instance. Only triangles are tested in its leaves.
+    fn hit_instance(&self, ray: Ray, data: &[f32; 16], material: &CpuMaterial, t_min: f32, t_max: f32) -> Option<Hit> {
+        let vector = |i: usize| Vec3(data[i], data[i + 1], data[i + 2]);
+        let [x, y, z] = [vector(0), vector(3), vector(6)];
+        let to_prototype = |v: Vec3| x * v.0 + y * v.1 + z * v.2;
+        let local_ray = Ray { origin: to_prototype(ray.origin) + vector(9), direction: to_prototype(ray.direction) };
+
+        let mut hit: Option<Hit> = None;
+        let mut nearest_hit = t_max;
+        let mut node_index = data[12] as usize;
+        let mut stack = [0; MAX_BVH_DEPTH];
+        let mut stack_location = 0;
+        for _ in 0..self.nodes.len() {
+            let node = &self.nodes[node_index];
+            if hit_aabb(local_ray, node) < nearest_hit {
+                if node.object_count == 0 {
+                    stack[stack_location] = node.left_child + 1;
+                    stack_location += 1;
+                    node_index = node.left_child;
+                    continue;
+                }
+                for &object_index in &self.object_indices[node.left_child..node.left_child + node.object_count] {
+                    let object = &self.objects[object_index];
+                    if object.kind != 1.0 {
+                        continue;
+                    }
+                    if let Some(new_hit) = self.hit_triangle(local_ray, &object.data, material, t_min, nearest_hit) {
+                        nearest_hit = new_hit.t;
+                        hit = Some(new_hit);
+                    }
+                }
+            }
+
+            if stack_location == 0 {
+                break;
+            }
+            stack_location -= 1;
+            node_index = stack[stack_location];
+        }
+
+        hit.map(|mut hit| {
+            hit.position = ray.origin + ray.direction * hit.t;
+            // Normals go back through the transpose of the matrix
+            hit.normal = Vec3(x.dot(hit.normal), y.dot(hit.normal), z.dot(hit.normal)).normalize();
+            hit.color = times(hit.color, vector(13));
+            hit
+        })
+    }
+
+    // Rectangle turned towards the camera origin like the kernel's hit_billboard, skipping the
+    // label's transparent texels
+    fn hit_billboard(&self, ray: Ray, camera_origin: Vec3, data: &[f32; 16], t_min: f32, t_max: f32) -> Option<Hit> {
+        let center = Vec3(data[0], data[1], data[2]);
+        let to_camera = camera_origin - center;
+        if to_camera.dot(to_camera) < 1e-12 {
+            return None;
+        }
+        let n = to_camera.normalize();
+        let mut right = Vec3(0.0, 1.0, 0.0).cross(n);
+        if right.dot(right) < 1e-6 {
+            right = Vec3(1.0, 0.0, 0.0);
+        }
+        let right = right.normalize();
+        let up = n.cross(right);
+
+        let denominator = n.dot(ray.direction);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+        let t = n.dot(center - ray.origin) / denominator;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let position = ray.origin + ray.direction * t;
+        let x = (position - center).dot(right) / data[3];
+        let y = (position - center).dot(up) / data[7];
+        if x.abs() > 1.0 || y.abs() > 1.0 {
+            return None;
+        }
+        let (s, r) = (0.5 + 0.5 * x, 0.5 - 0.5 * y);
+        let uv = (data[9] + (data[11] - data[9]) * s, data[10] + (data[12] - data[10]) * r);
+        let [red, green, blue, alpha] = self.textures[data[8] as usize].sample(uv);
+        if alpha < 0.5 {
+            return None;
+        }
+
+        Some(Hit {
+            t,
+            color: times(Vec3(data[4], data[5], data[6]), Vec3(red, green, blue)),
+            position,
+            normal: set_face_normal(ray, n),
+            front_face: true,
+            material: 0,
+            tangent: Vec3(0.0, 0.0, 0.0),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn hit_point_cluster(&self, ray: Ray, first: usize, count: usize, radius: f32, color: Vec3, t_min: f32, t_max: f32) -> Option<Hit> {
+        let mut hit = None;
+        let mut nearest_hit = t_max;
+        for point in &self.points[first..first + count] {
+            let srgb = unpack_unorm(point[3]);
+            let sphere = Sphere {
+                center: Vec3(f32::from_bits(point[0]), f32::from_bits(point[1]), f32::from_bits(point[2])),
+                radius,
+                color: times(color, Vec3(srgb.0.powf(2.2), srgb.1.powf(2.2), srgb.2.powf(2.2))),
+                checker: None,
+            };
+            if let Some(new_hit) = hit_sphere(ray, &sphere, t_min, nearest_hit) {
+                nearest_hit = new_hit.t;
+                hit = Some(new_hit);
+            }
+        }
+        hit
+    }
+}
+
+struct Sphere {
+    center: Vec3,
+    radius: f32,
+    color: Vec3,
+    checker: Option<(Vec3, f32)>, // Second color and scale of a checker pattern
+}
+
+fn hit_sphere(ray: Ray, sphere: &Sphere, t_min: f32, t_max: f32) -> Option<Hit> {
+    let oc = ray.origin - sphere.center;
+    let a = ray.direction.dot(ray.direction);
+    let half_b = ray.direction.dot(oc);
+    let c = oc.dot(oc) - sphere.radius * sphere.radius;
+    let discriminant = half_b * half_b - a * c;
+    // Written like the kernel's tests, so NaNs miss the same way
+    let mut t = f32::NAN;
+    if discriminant > 0.0 {
+        t = (-half_b - discriminant.sqrt()) / a;
+        if t <= t_min {
+            t = (-half_b + discriminant.sqrt()) / a;
+        }
+    }
+    if !(t > t_min && t < t_max) {
+        return None;
+    }
+
+    let position = ray.origin + ray.direction * t;
+    let outward_normal = (position - sphere.center) / sphere.radius;
+    let mut color = sphere.color;
+    if let Some((checker_color, scale)) = sphere.checker {
+        let (u, v) = sphere_uv(outward_normal);
+        let cell = ((u * 2.0 * scale).floor() as i32, (v * scale).floor() as i32);
+        if (cell.0 + cell.1) % 2 != 0 {
+            color = checker_color;
+        }
+    }
+    Some(Hit {
+        t,
+        color,
+        position,
+        normal: set_face_normal(ray, outward_normal),
+        front_face: ray.direction.dot(outward_normal) < 0.0,
+        material: 0,
+        tangent: Vec3(0.0, 0.0, 0.0),
+    })
+}
+
+// Latitude/longitude UVs with v = 0 at the north pole
+fn sphere_uv(outward_normal: Vec3) -> (f32, f32) {
+    let pi = std::f32::consts::PI;
+    (0.5 + (-outward_normal.2).atan2(outward_normal.0) / (2.0 * pi), 0.5 - outward_normal.1.clamp(-1.0, 1.0).asin() / pi)
+}
+
+// Watertight intersection, the same shear into ray space as the kernel
+#[allow(clippy::too_many_arguments)]
+fn hit_triangle(ray: Ray, corners: [Vec3; 3], color: Vec3, vertex_colors: Vec3, normals: Option<[Vec3; 3]>, material: &CpuMaterial, textures: &[CpuTexture], t_min: f32, t_max: f32) -> Option<Hit> {
+    let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]).normalize();
+    if material.flags & 1 != 0 && ray.direction.dot(normal) >= 0.0 {
+        return None;
+    }
+
+    let direction = ray.direction;
+    let abs_direction = Vec3(direction.0.abs(), direction.1.abs(), direction.2.abs());
+    let kz = if abs_direction.0 > abs_direction.1 && abs_direction.0 > abs_direction.2 {
+        0
+    } else if abs_direction.1 > abs_direction.2 {
+        1
+    } else {
+        2
+    };
+    let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+    if direction.axis(kz) < 0.0 {
+        (kx, ky) = (ky, kx);
+    }
+
+    let shear_x = direction.axis(kx) / direction.axis(kz);
+    let shear_y = direction.axis(ky) / direction.axis(kz);
+    let shear_z = 1.0 / direction.axis(kz);
+    let [a, b, c] = corners.map(|corner| corner - ray.origin);
+    let sheared = |p: Vec3| (p.axis(kx) - shear_x * p.axis(kz), p.axis(ky) - shear_y * p.axis(kz));
+    let ((ax, ay), (bx, by), (cx, cy)) = (sheared(a), sheared(b), sheared(c));
+
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+    let t = (u * shear_z * a.axis(kz) + v * shear_z * b.axis(kz) + w * shear_z * c.axis(kz)) / det;
+    if !(t > t_min && t < t_max) {
+        return None;
+    }
+    // Alpha cutout, corners b and c sit at u = 1 and v = 1
+    let [r, g, b, alpha] = albedo_texel(textures, material, (v / det, w / det));
+    if alpha < material.alpha_cutoff {
+        return None;
+    }
+
+    let mut hit_color = times(color, Vec3(r, g, b));
+    if vertex_colors.0 >= 0.0 {
+        let linear = |packed: f32| {
+            let srgb = unpack_unorm(packed as u32);
+            Vec3(srgb.0.powf(2.2), srgb.1.powf(2.2), srgb.2.powf(2.2))
+        };
+        let blended = (linear(vertex_colors.0) * u + linear(vertex_colors.1) * v + linear(vertex_colors.2) * w) / det;
+        hit_color = times(hit_color, blended);
+    }
+    // Smooth shading, blending the corner normals kept on the side of the face
+    let mut shading_normal = normal;
+    if let Some([normal_a, normal_b, normal_c]) = normals {
+        let blended = (normal_a * u + normal_b * v + normal_c * w) / det;
+        if blended.dot(blended) > 0.0 {
+            let smooth_normal = blended.normalize();
+            shading_normal = if smooth_normal.dot(normal) >= 0.0 { smooth_normal } else { smooth_normal * -1.0 };
+        }
+    }
+    Some(Hit {
+        t,
+        color: hit_color,
+        position: ray.origin + ray.direction * t,
+        normal: if material.flags & 2 != 0 { set_face_normal(ray, shading_normal) } else { shading_normal },
+        front_face: ray.direction.dot(normal) < 0.0,
+        material: 0,
+        tangent: Vec3(0.0, 0.0, 0.0),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn hit_quad(ray: Ray, corner: Vec3, edge_u: Vec3, edge_v: Vec3, color: Vec3, material: &CpuMaterial, textures: &[CpuTexture], t_min: f32, t_max: f32) -> Option<Hit> {
+    let n = edge_u.cross(edge_v);
+    let denominator = n.dot(ray.direction);
+    if denominator.abs() < 1e-8 || (material.flags & 1 != 0 && denominator > 0.0) {
+        return None;
+    }
+    let t = n.dot(corner - ray.origin) / denominator;
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+
+    let position = ray.origin + ray.direction * t;
+    let planar = position - corner;
+    let w = n / n.dot(n);
+    let alpha = w.dot(planar.cross(edge_v));
+    let beta = w.dot(edge_u.cross(planar));
+    if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+        return None;
+    }
+    let [r, g, b, opacity] = albedo_texel(textures, material, (alpha, beta));
+    if opacity < material.alpha_cutoff {
+        return None;
+    }
+
+    let normal = n.normalize();
+    Some(Hit {
+        t,
+        color: times(color, Vec3(r, g, b)),
+        position,
+        normal: if material.flags & 2 != 0 { set_face_normal(ray, normal) } else { normal },
+        front_face: denominator < 0.0,
+        material: 0,
+        tangent: Vec3(0.0, 0.0, 0.0),
+    })
+}
+
+// Chain of short cylinders along the curve, like the kernel's hit_curve
+fn hit_curve(ray: Ray, control_points: [Vec3; 4], radius: f32, color: Vec3, t_min: f32, t_max: f32) -> Option<Hit> {
+    let [p0, p1, p2, p3] = control_points;
+    let curve_point = |t: f32| {
+        let s = 1.0 - t;
+        p0 * (s * s * s) + p1 * (3.0 * s * s * t) + p2 * (3.0 * s * t * t) + p3 * (t * t * t)
+    };
+    let direction = ray.direction.normalize();
+    let scale = ray.direction.magnitude();
+
+    let mut hit = None;
+    let mut nearest_hit = t_max;
+    let mut start = p0;
+    for i in 1..=CURVE_SEGMENTS {
+        let end = curve_point(i as f32 / CURVE_SEGMENTS as f32);
+        let axis = end - start;
+        let axis_length2 = axis.dot(axis);
+        let to_start = ray.origin - start;
+
+        let b = direction.dot(axis);
+        let denominator = axis_length2 - b * b;
+        let mut u = 0.0;
+        if denominator > 1e-12 {
+            u = (axis.dot(to_start) - b * direction.dot(to_start)) / denominator;
+        }
+        let on_axis = start + axis * u.clamp(0.0, 1.0);
+        let along = (on_axis - ray.origin).dot(direction);
+        let offset = ray.origin + direction * along - on_axis;
+        let distance2 = offset.dot(offset);
+
+        if distance2 < radius * radius {
+            let t = (along - (radius * radius - distance2).sqrt()) / scale;
+            if t > t_min && t < nearest_hit {
+                nearest_hit = t;
+                let position = ray.origin + ray.direction * t;
+                let closest = start + axis * ((position - start).dot(axis) / axis_length2).clamp(0.0, 1.0);
+                let mut outward_normal = position - closest;
+                if outward_normal.dot(outward_normal) < 1e-12 {
+                    outward_normal = direction * -1.0;
+                }
+                hit = Some(Hit {
+                    t,
+                    color,
+                    position,
+                    normal: set_face_normal(ray, outward_normal.normalize()),
+                    front_face: ray.direction.dot(outward_normal) < 0.0,
+                    material: 0,
+                    tangent: axis.normalize(),
+                });
+            }
+        }
+        start = end;
+    }
+    hit
+}
+
+// The material's albedo texture at the given UV, opaque white when it has none
+fn albedo_texel(textures: &[CpuTexture], material: &CpuMaterial, uv: (f32, f32)) -> [f32; 4] {
+    if material.albedo_texture < 0.0 {
+        return [1.0; 4];
+    }
+    textures[material.albedo_texture as usize].sample(uv)
+}
+
+fn hit_aabb(ray: Ray, node: &CpuNode) -> f32 {
+    let inverse = Vec3(1.0 / ray.direction.0, 1.0 / ray.direction.1, 1.0 / ray.direction.2);
+    let t1 = times(node.min_corner - ray.origin, inverse);
+    let t2 = times(node.max_corner - ray.origin, inverse);
+    let (near, far) = (t1.min(t2), t1.max(t2));
+    let t_min = near.0.max(near.1).max(near.2);
+    let t_max = far.0.min(far.1).min(far.2);
+    if t_min > t_max || t_max < 0.0 {
+        99999.0
+    } else {
+        t_min
+    }
+}
+
+fn set_face_normal(ray: Ray, outward_normal: Vec3) -> Vec3 {
+    if ray.direction.dot(outward_normal) < 0.0 {
+        outward_normal
+    } else {
+        outward_normal * -1.0
+    }
+}
+
+fn scatter_dielectric(direction: Vec3, normal: Vec3, front_face: bool, ior: f32, rng: &mut u32) -> Vec3 {
+    let facing = if direction.dot(normal) > 0.0 { normal * -1.0 } else { normal };
+    let eta = if front_face { 1.0 / ior } else { ior };
+    let cos_theta = (direction * -1.0).dot(facing).min(1.0);
+    let r0 = ((1.0 - ior) / (1.0 + ior)).powi(2);
+    let reflectance = r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5);
+    if eta * eta * (1.0 - cos_theta * cos_theta) > 1.0 || random_float(rng) < reflectance {
+        return reflect(direction, facing);
+    }
+    refract(direction, facing, eta).normalize()
+}
+
+fn sample_portal(parameters: &Parameters, position: Vec3, rng: &mut u32) -> Vec3 {
+    let count = parameters.portals.len();
+    let index = ((random_float(rng) * count as f32) as usize).min(count - 1);
+    let [corner, edge_u, edge_v] = parameters.portals[index];
+    let spot = corner + edge_u * random_float(rng) + edge_v * random_float(rng);
+    (spot - position).normalize()
+}
+
+fn portal_pdf(parameters: &Parameters, position: Vec3, direction: Vec3) -> f32 {
+    let mut pdf = 0.0;
+    for &[corner, edge_u, edge_v] in &parameters.portals {
+        let normal = edge_u.cross(edge_v);
+        let facing = direction.dot(normal);
+        let t = (corner - position).dot(normal) / facing;
+        if facing.abs() < 1e-12 || t <= 0.0 {
+            continue;
+        }
+        let offset = position + direction * t - corner;
+        let (uu, uv, vv) = (edge_u.dot(edge_u), edge_u.dot(edge_v), edge_v.dot(edge_v));
+        let (ou, ov) = (offset.dot(edge_u), offset.dot(edge_v));
+        let det = uu * vv - uv * uv;
+        let (u, v) = ((ou * vv - ov * uv) / det, (ov * uu - ou * uv) / det);
+        if (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v) {
+            pdf += t * t / facing.abs();
+        }
+    }
+    pdf / parameters.portals.len() as f32
+}
+
+fn portal_weight(parameters: &Parameters, position: Vec3, normal: Vec3, direction: Vec3) -> f32 {
+    let cosine_pdf = direction.dot(normal).max(0.0) / std::f32::consts::PI;
+    let mixture_pdf = 0.5 * cosine_pdf + 0.5 * portal_pdf(parameters, position, direction);
+    if mixture_pdf <= 0.0 {
+        return 0.0;
+    }
+    cosine_pdf / mixture_pdf
+}
+
+fn sample_hair(direction: Vec3, normal: Vec3, tangent: Vec3, roughness: f32, rng: &mut u32) -> Vec3 {
+    let along = (direction.dot(tangent) + roughness * (2.0 * random_float(rng) - 1.0)).clamp(-1.0, 1.0);
+    let side = (normal - tangent * normal.dot(tangent)).normalize();
+    let binormal = tangent.cross(side);
+    let azimuth = (random_float(rng) - 0.5) * std::f32::consts::PI;
+    let around = side * azimuth.cos() + binormal * azimuth.sin();
+    (tangent * along + around * (1.0 - along * along).sqrt()).normalize()
+}
+
+fn thin_film_tint(cos_theta: f32, thickness: f32, ior: f32) -> Vec3 {
+    let sin_film = (1.0 - cos_theta * cos_theta).max(0.0).sqrt() / ior;
+    let cos_film = (1.0 - sin_film * sin_film).max(0.0).sqrt();
+    let rs = (cos_theta - ior * cos_film) / (cos_theta + ior * cos_film);
+    let rp = (ior * cos_theta - cos_film) / (ior * cos_theta + cos_film);
+
+    let reflectance = [650.0, 510.0, 475.0].map(|wavelength: f32| {
+        let interference = (4.0 * std::f32::consts::PI * ior * thickness * cos_film / wavelength).cos();
+        let airy = |r: f32| {
+            let r2 = r * r;
+            (2.0 * r2 - 2.0 * r2 * interference) / (1.0 + r2 * r2 - 2.0 * r2 * interference)
+        };
+        0.5 * (airy(rs) + airy(rp))
+    });
+    let strongest = reflectance[0].max(reflectance[1]).max(reflectance[2]).max(1e-6);
+    Vec3(reflectance[0], reflectance[1], reflectance[2]) / strongest
+}
+
+fn wavelength_rgb(lambda: f32) -> Vec3 {
+    let lobe = |peak: f32, below: f32, above: f32| {
+        let t = (lambda - peak) / if lambda < peak { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    let x = 1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7) - 0.065 * lobe(501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8);
+    Vec3(
+        (3.2406 * x - 1.5372 * y - 0.4986 * z) * 2.6488,
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z) * 3.3485,
+        (0.0557 * x - 0.2040 * y + 1.0570 * z) * 3.5033,
+    )
+}
+
+// The kernel's PCG hash step, returning a float in [0, 1)
+fn random_float(state: &mut u32) -> f32 {
+    *state = state.wrapping_mul(747796405).wrapping_add(2891336453);
+    let mut word = ((*state >> ((*state >> 28) + 4)) ^ *state).wrapping_mul(277803737);
+    word ^= word >> 22;
+    word as f32 / 4294967296.0
+}
+
+fn random_unit_vector(rng: &mut u32) -> Vec3 {
+    let z = 2.0 * random_float(rng) - 1.0;
+    let angle = std::f32::consts::TAU * random_float(rng);
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    Vec3(radius * angle.cos(), radius * angle.sin(), z)
+}
+
+fn offset_ray_origin(position: Vec3, normal: Vec3, direction: Vec3) -> Vec3 {
+    let scale = position.0.abs().max(position.1.abs()).max(position.2.abs()).max(1.0);
+    let offset = normal * (scale * 0.0001);
+    if direction.dot(normal) < 0.0 {
+        position - offset
+    } else {
+        position + offset
+    }
+}
+
+fn reflect(direction: Vec3, normal: Vec3) -> Vec3 {
+    direction - normal * (2.0 * normal.dot(direction))
+}
+
+fn refract(direction: Vec3, normal: Vec3, eta: f32) -> Vec3 {
+    let cos_incident = normal.dot(direction);
+    let k = 1.0 - eta * eta * (1.0 - cos_incident * cos_incident);
+    if k < 0.0 {
+        return Vec3(0.0, 0.0, 0.0);
+    }
+    direction * eta - normal * (eta * cos_incident + k.sqrt())
+}
+
+fn luminance(color: Vec3) -> f32 {
+    color.dot(Vec3(0.2126, 0.7152, 0.0722))
+}
+
+// Component-wise product
+fn times(a: Vec3, b: Vec3) -> Vec3 {
+    Vec3(a.0 * b.0, a.1 * b.1, a.2 * b.2)
+}
+
+// Red, green and blue bytes of a packed color as 0 to 1, like unpack4x8unorm
+fn unpack_unorm(packed: u32) -> Vec3 {
+    let channel = |shift: u32| ((packed >> shift) & 0xff) as f32 / 255.0;
+    Vec3(channel(0), channel(8), channel(16))
+}
+
+fn words(bytes: &[u8]) -> Vec<u32> {
+    bytes.chunks_exact(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect()
+}
+
+fn floats(bytes: &[u8]) -> Vec<f32> {
+    words(bytes).into_iter().map(f32::from_bits).collect()
+}
+
+// Sky faces in linear color, in the cube map's layer order +x, -x, +y, -y, +z, -z
+struct CpuSky {
+    width: u32,
+    height: u32,
+    faces: Vec<Vec<Vec3>>,
+}
+
+impl CpuSky {
+    fn new(images: Vec<DynamicImage>) -> Self {
+        assert_eq!(images.len(), 6, "There must be exactly 6 images for a cube map");
+        let (width, height) = (images[0].width(), images[0].height());
+        let faces = images.into_iter().map(|image| {
+            image.to_rgb8().pixels().map(|pixel| Vec3(srgb_to_linear(pixel[0]), srgb_to_linear(pixel[1]), srgb_to_linear(pixel[2]))).collect()
+        }).collect();
+        Self { width, height, faces }
+    }
+
+    // Bilinear lookup of the top mip level, clamped at the face's edges
+    fn sample(&self, direction: Vec3) -> Vec3 {
+        let Vec3(x, y, z) = direction;
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+        let (face, sc, tc, major) = if ax >= ay && ax >= az {
+            if x > 0.0 { (0, -z, -y, ax) } else { (1, z, -y, ax) }
+        } else if ay >= az {
+            if y > 0.0 { (2, x, z, ay) } else { (3, x, -z, ay) }
+        } else if z > 0.0 {
+            (4, x, -y, az)
+        } else {
+            (5, -x, -y, az)
+        };
+
+        let u = (sc / major + 1.0) * 0.5 * self.width as f32 - 0.5;
+        let v = (tc / major + 1.0) * 0.5 * self.height as f32 - 0.5;
+        let (u0, v0) = (u.floor(), v.floor());
+        let (fu, fv) = (u - u0, v - v0);
+        let texel = |du: f32, dv: f32| {
+            let px = ((u0 + du) as i64).clamp(0, self.width as i64 - 1) as usize;
+            let py = ((v0 + dv) as i64).clamp(0, self.height as i64 - 1) as usize;
+            self.faces[face][py * self.width as usize + px]
+        };
+        let top = texel(0.0, 0.0) * (1.0 - fu) + texel(1.0, 0.0) * fu;
+        let bottom = texel(0.0, 1.0) * (1.0 - fu) + texel(1.0, 1.0) * fu;
+        top * (1.0 - fv) + bottom * fv
+    }
+}
+
+// One of the scene's textures in linear color, with alpha left as it is
+struct CpuTexture {
+    width: u32,
+    height: u32,
+    packed: bool, // Shares a layer of the texture array with others, see `is_packed`
+    texels: Vec<[f32; 4]>,
+}
+
+impl CpuTexture {
+    fn new(image: &DynamicImage) -> Self {
+        let texels = image.to_rgba8().pixels().map(|pixel| {
+            [srgb_to_linear(pixel[0]), srgb_to_linear(pixel[1]), srgb_to_linear(pixel[2]), pixel[3] as f32 / 255.0]
+        }).collect();
+        Self { width: image.width(), height: image.height(), packed: is_packed(image), texels }
+    }
+
+    // Bilinear lookup of the top mip level. Like the texture array's sampler, u wraps around and v
+    // is clamped at the edges, and packed textures repeat with their border clamping both.
+    fn sample(&self, uv: (f32, f32)) -> [f32; 4] {
+        let (u, v) = if self.packed { (uv.0.rem_euclid(1.0), uv.1.rem_euclid(1.0)) } else { uv };
+        let u = u * self.width as f32 - 0.5;
+        let v = v * self.height as f32 - 0.5;
+        let (u0, v0) = (u.floor(), v.floor());
+        let (fu, fv) = (u - u0, v - v0);
+        let (width, height) = (self.width as i64, self.height as i64);
+        let texel = |du: f32, dv: f32| {
+            let px = (u0 + du) as i64;
+            let px = if self.packed { px.clamp(0, width - 1) } else { px.rem_euclid(width) };
+            let py = ((v0 + dv) as i64).clamp(0, height - 1);
+            self.texels[(py * width + px) as usize]
+        };
+        let blend = |a: [f32; 4], b: [f32; 4], f: f32| std::array::from_fn(|i| a[i] * (1.0 - f) + b[i] * f);
+        blend(blend(texel(0.0, 0.0), texel(1.0, 0.0), fu), blend(texel(0.0, 1.0), texel(1.0, 1.0), fu), fv)
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,39 @@
+use super::{CpuRenderer, Scene};
+
+/// Denoises a linear RGB image (3 floats per pixel) with Open Image Denoise's ray tracing
+/// filter, using the albedo and world normal AOVs as auxiliary guides. When the filter can't
+/// run the noisy image is returned, so a long render still gets saved.
+pub fn denoise(width: u32, height: u32, color: &[f32], albedo: &[f32], normal: &[f32]) -> Vec<f32> {
+    let device = oidn::Device::new();
+    let mut output = vec![0.0; color.len()];
+
+    let filtered = oidn::RayTracing::new(&device)
+        .srgb(false)
+        .hdr(true)
+        .image_dimensions(width as usize, height as usize)
+        .albedo_normal(albedo, normal)
+        .filter(color, &mut output);
+    if let Err(e) = filtered {
+        tracing::error!("Invalid denoise filter configuration: {:?}", e);
+        return color.to_vec();
+    }
+
+    if let Err((_, message)) = device.get_error() {
+        tracing::error!("Open Image Denoise failed: {}", message);
+        return color.to_vec();
+    }
+
+    output
+}
+
+/// Denoises the linear RGBA radiance of a headless render of the built scene, laid out like
+/// `render_offline`'s. The albedo and normal guides are traced on the CPU through the scene's
+/// camera, one ray per pixel, so renders from every backend are denoised the same way.
+pub fn denoise_render(scene: &Scene, width: u32, height: u32, radiance: &[f32]) -> Vec<f32> {
+    let (albedo, normal) = CpuRenderer::new(scene, width, height).denoise_guides(&scene.flatten_scene_data(0));
+    let color: Vec<f32> = radiance.chunks_exact(4).flat_map(|rgba| [rgba[0], rgba[1], rgba[2]]).collect();
+    let denoised = denoise(width, height, &color, &albedo, &normal);
+    denoised.chunks_exact(3).zip(radiance.chunks_exact(4))
+        .flat_map(|(rgb, rgba)| [rgb[0], rgb[1], rgb[2], rgba[3]])
+        .collect()
+}
@@ -0,0 +1,583 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use egui::load::SizedTexture;
+use egui::{Button, ComboBox, ScrollArea, Slider, TextEdit};
+use winit::event::{ElementState, KeyEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::Window;
+
+use super::{format_bytes, show_gizmo, Console, Edit, GizmoMode, Material, Object, ObjectId, RayStats, RemovedObject, Scene, SceneStats, ThinFilm, Transform, Vec3};
+
+/// Width and height in pixels of the material preview
+pub const PREVIEW_SIZE: u32 = 160;
+// How often the stats panel measures the scene again while it is open, as walking a large BVH
+// every frame would slow the editor down
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// egui panels drawn over the render: an outliner listing every object, and an inspector for
+/// the color and material of the selected one next to a small ray traced preview sphere.
+/// Objects are selected in the outliner or by clicking on them, Ctrl+click adding to the
+/// selection, and moved with a gizmo. The backtick key drops down a console for typed commands.
+pub struct Editor {
+    context: egui::Context,
+    input: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    preview_texture: egui::TextureId,
+    renaming: Option<(ObjectId, String)>, // Object whose name is being edited in the outliner
+    gizmo_hovered: bool, // Pointer was over a gizmo handle last frame
+    pending_edit: Option<PendingEdit>,
+    clipboard: Vec<RemovedObject>, // Last copied objects, pasted with their state at the time
+    focus_console: bool, // Console was just opened and its input should take the keyboard
+    stats: Option<(Instant, SceneStats)>, // Last measured for the stats panel, and when
+    /// Commands typed in and what they printed, run by the state with `take_console_commands`
+    pub console: Console,
+    /// How far copies made by duplicating or pasting are moved from the original
+    pub duplicate_offset: Vec3,
+    /// Transform the gizmo on the selected object edits
+    pub gizmo_mode: GizmoMode,
+    /// Object the inspector and gizmo edit, the last one selected
+    pub selected: Option<ObjectId>,
+    /// Objects copied, duplicated and deleted together, including `selected`
+    pub selection: BTreeSet<ObjectId>,
+}
+
+impl Editor {
+    pub fn new(device: &wgpu::Device, window: &Window, format: wgpu::TextureFormat, preview: &wgpu::TextureView) -> Self {
+        let context = egui::Context::default();
+        let input = egui_winit::State::new(context.clone(), egui::ViewportId::ROOT, window, Some(window.scale_factor() as f32), None);
+        let mut renderer = egui_wgpu::Renderer::new(device, format, None, 1);
+        let preview_texture = renderer.register_native_texture(device, preview, wgpu::FilterMode::Linear);
+
+        Self { context, input, renderer, preview_texture, renaming: None, gizmo_hovered: false, pending_edit: None, clipboard: Vec::new(), focus_console: false, stats: None, console: Console::default(), duplicate_offset: Vec3(0.5, 0.0, 0.0), gizmo_mode: GizmoMode::default(), selected: None, selection: BTreeSet::new() }
+    }
+
+    /// Makes the panels' renderer again on a new device, keeping the panels' state
+    pub fn recreate_renderer(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, preview: &wgpu::TextureView) {
+        self.renderer = egui_wgpu::Renderer::new(device, format, None, 1);
+        self.preview_texture = self.renderer.register_native_texture(device, preview, wgpu::FilterMode::Linear);
+    }
+
+    /// Passes a window event to egui, returning true when a panel or the gizmo used it and the scene shouldn't
+    pub fn on_window_event(&mut self, window: &Window, event: &winit::event::WindowEvent) -> bool {
+        // The backtick only opens and closes the console, it never reaches egui as typed text
+        if let winit::event::WindowEvent::KeyboardInput { event: KeyEvent { physical_key: PhysicalKey::Code(KeyCode::Backquote), state: ElementState::Pressed, repeat, .. }, .. } = event {
+            if !repeat {
+                self.console.open = !self.console.open;
+                self.focus_console = self.console.open;
+            }
+            return true;
+        }
+        let consumed = self.input.on_window_event(window, event).consumed;
+        // egui doesn't count the gizmo as a panel, clicks on its handles have to be caught here
+        consumed || (self.gizmo_hovered && matches!(event, winit::event::WindowEvent::MouseInput { .. }))
+    }
+
+    /// Whether the pointer is over a panel or the gizmo, so clicks there don't pick objects behind it
+    pub fn wants_pointer(&self) -> bool {
+        self.context.wants_pointer_input() || self.gizmo_hovered
+    }
+
+    /// Selects the object alone, or with `extend` adds it to the selection, taking it out again
+    /// when it was in it already. None clears the selection unless extending.
+    pub fn select(&mut self, id: Option<ObjectId>, extend: bool) {
+        match id {
+            Some(id) if extend => {
+                if !self.selection.remove(&id) {
+                    self.selection.insert(id);
+                    self.selected = Some(id);
+                } else if self.selected == Some(id) {
+                    self.selected = self.selection.last().copied();
+                }
+            },
+            None if extend => {},
+            id => {
+                self.selection = id.into_iter().collect();
+                self.selected = id;
+            },
+        }
+    }
+
+    /// Remembers the selected objects as they are now, for `paste`
+    pub fn copy(&mut self, scene: &Scene) {
+        let copied: Vec<RemovedObject> = self.selection.iter().filter_map(|&id| scene.snapshot(id)).collect();
+        if !copied.is_empty() {
+            self.clipboard = copied;
+        }
+    }
+
+    /// Adds the copied objects again, one duplicate offset away from where they were copied, and selects them
+    pub fn paste(&mut self, scene: &mut Scene) {
+        let copies: Vec<ObjectId> = self.clipboard.iter().map(|object| scene.add_snapshot(object)).collect();
+        self.place_copies(scene, &copies, Vec::new());
+        // Pasting again lands another offset further along
+        self.clipboard = copies.iter().filter_map(|&id| scene.snapshot(id)).collect();
+    }
+
+    /// Copies the selected objects one duplicate offset away and selects the copies. Meshes are
+    /// made instances first, so their copies share the triangles.
+    pub fn duplicate_selected(&mut self, scene: &mut Scene) {
+        let selection: Vec<ObjectId> = self.selection.iter().copied().collect();
+        let instanced = selection.iter().filter_map(|&id| scene.make_instance(id)).collect();
+        let copies: Vec<ObjectId> = selection.iter().filter_map(|&id| scene.duplicate(id)).collect();
+        self.place_copies(scene, &copies, instanced);
+    }
+
+    // Moves the copies one offset along and records them, after the edits made for them, as one edit
+    fn place_copies(&mut self, scene: &mut Scene, copies: &[ObjectId], mut edits: Vec<Edit>) {
+        for &copy in copies {
+            if let Some(position) = scene.position(copy) {
+                scene.set_position(copy, position + self.duplicate_offset);
+            }
+            edits.push(Edit::Added(scene.snapshot(copy).unwrap()));
+        }
+        push_edits(scene, edits);
+        if !copies.is_empty() {
+            self.selection = copies.iter().copied().collect();
+            self.selected = copies.last().copied();
+            self.renaming = None;
+        }
+    }
+
+    /// Material and color of the selected object, for the preview sphere
+    pub fn previewed_material(&self, scene: &Scene) -> Option<(Material, Vec3)> {
+        let id = self.selected?;
+        let material = scene.material(id)?;
+        Some((scene.materials[material.0], scene.color(id)?))
+    }
+
+    /// Runs the panels against the scene and draws them on top of the view, with the renderer's
+    /// last ray counts in the stats panel
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, window: &Window, scene: &mut Scene, ray_stats: Option<RayStats>) {
+        let raw_input = self.input.take_egui_input(window);
+        let context = self.context.clone();
+        let output = context.run(raw_input, |context| {
+            // Everything done to the selected object between pressing and releasing the pointer,
+            // like dragging a gizmo or a slider, is undone as one edit
+            if context.input(|input| input.pointer.primary_pressed()) {
+                self.pending_edit = self.selected.and_then(|id| PendingEdit::new(scene, id));
+            }
+
+            self.console(context);
+            self.gizmo(context, scene);
+            self.outliner(context, scene);
+            self.material_inspector(context, scene);
+            render_settings(context, scene);
+            self.scene_stats(context, scene, ray_stats);
+
+            if context.input(|input| input.pointer.primary_released()) {
+                if let Some(edit) = self.pending_edit.take().and_then(|pending| pending.finish(scene)) {
+                    scene.history.push(edit);
+                }
+            }
+        });
+        self.input.handle_platform_output(window, output.platform_output);
+
+        let paint_jobs = context.tessellate(output.shapes, output.pixels_per_point);
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [window.inner_size().width, window.inner_size().height],
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let callback_buffers = self.renderer.update_buffers(device, queue, encoder, &paint_jobs, &screen);
+        queue.submit(callback_buffers);
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Editor Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load, // Draw over the rendered frame
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.renderer.render(&mut render_pass, &paint_jobs, &screen);
+        }
+
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+
+    // Object and triangle counts, the BVH's size, the GPU buffers the scene takes and the rays traced
+    fn scene_stats(&mut self, context: &egui::Context, scene: &Scene, ray_stats: Option<RayStats>) {
+        egui::Window::new("Stats").default_open(false).show(context, |ui| {
+            if self.stats.as_ref().is_none_or(|(measured, _)| measured.elapsed() >= STATS_INTERVAL) {
+                self.stats = Some((Instant::now(), scene.stats()));
+            }
+            let stats = &self.stats.as_ref().unwrap().1;
+            ui.label(format!("{} objects, {} triangles", scene.entries.len(), stats.triangles));
+            ui.label(format!("{} spheres, {} quads, {} billboards, {} curves, {} instances", stats.spheres, stats.quads, stats.billboards, stats.curves, stats.instances));
+            ui.label(format!("{} points in {} clusters", stats.points, stats.point_clusters));
+            ui.separator();
+            ui.label(format!("BVH: {} nodes, {} leaves, depth {}", stats.nodes, stats.leaves, stats.bvh_depth));
+            ui.label(format!("SAH cost {:.2}, largest leaf {}", stats.bvh_cost.sah_cost, stats.largest_leaf));
+            ui.separator();
+            let buffers = [
+                ("Objects", stats.object_buffer_bytes),
+                ("Nodes", stats.node_buffer_bytes),
+                ("Object indices", stats.object_index_buffer_bytes),
+                ("Materials", stats.material_buffer_bytes),
+                ("Points", stats.point_buffer_bytes),
+                ("Textures", stats.texture_array_bytes),
+                ("Total", stats.total_gpu_bytes()),
+            ];
+            egui::Grid::new("buffer sizes").show(ui, |ui| {
+                for (name, bytes) in buffers {
+                    ui.label(name);
+                    ui.label(format_bytes(bytes));
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            match ray_stats {
+                Some(rays) => {
+                    ui.label(format!("{:.1} Mrays/s", rays.mrays_per_second()));
+                    ui.label(format!("{:.1} M camera, {:.1} M bounce, {:.1} M shadow rays/s", rays.primary_rays / 1e6, rays.secondary_rays / 1e6, rays.shadow_rays / 1e6));
+                },
+                None => {
+                    ui.label("Counting rays");
+                },
+            }
+        });
+    }
+
+    // Dropped down over the top of the view, the log above the line being typed
+    fn console(&mut self, context: &egui::Context) {
+        if !self.console.open {
+            return;
+        }
+        egui::TopBottomPanel::top("console").show(context, |ui| {
+            ScrollArea::vertical().max_height(200.0).stick_to_bottom(true).show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                for line in &self.console.log {
+                    ui.monospace(line);
+                }
+            });
+            let input = ui.add(TextEdit::singleline(&mut self.console.input)
+                .font(egui::TextStyle::Monospace)
+                .desired_width(f32::INFINITY)
+                .hint_text("help lists the commands"));
+            // Enter runs the line and keeps the keyboard for the next one
+            if input.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                self.console.submit();
+                self.focus_console = true;
+            }
+            if std::mem::take(&mut self.focus_console) {
+                input.request_focus();
+            }
+        });
+    }
+
+    // Drawn behind the panels on a layer covering the whole screen, which egui doesn't treat as
+    // wanting the pointer unless a handle is dragged
+    fn gizmo(&mut self, context: &egui::Context, scene: &mut Scene) {
+        self.gizmo_hovered = false;
+        let Some(id) = self.selected.filter(|id| scene.entries.contains_key(id)) else { return };
+
+        egui::Area::new(egui::Id::new("gizmo"))
+            .order(egui::Order::Background)
+            .fixed_pos(egui::Pos2::ZERO)
+            .show(context, |ui| {
+                ui.allocate_rect(context.screen_rect(), egui::Sense::hover());
+                self.gizmo_hovered = show_gizmo(ui, scene, id, self.gizmo_mode);
+            });
+    }
+
+    fn outliner(&mut self, context: &egui::Context, scene: &mut Scene) {
+        let mut duplicated = false;
+        let mut removed = false;
+
+        egui::Window::new("Outliner").show(context, |ui| {
+            ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                let ids: Vec<ObjectId> = scene.entries.keys().copied().collect();
+                for id in ids {
+                    ui.horizontal(|ui| {
+                        let mut visible = scene.is_visible(id).unwrap_or(true);
+                        if ui.checkbox(&mut visible, "").on_hover_text("Visible").changed() {
+                            scene.set_visible(id, visible);
+                        }
+
+                        if matches!(&self.renaming, Some((renamed, _)) if *renamed == id) {
+                            // Clicking elsewhere or pressing enter keeps the new name
+                            let (_, name) = self.renaming.as_mut().unwrap();
+                            let response = ui.text_edit_singleline(name);
+                            if response.lost_focus() {
+                                if !name.is_empty() {
+                                    scene.set_name(id, name);
+                                }
+                                self.renaming = None;
+                            } else if !response.has_focus() {
+                                response.request_focus();
+                            }
+                        } else {
+                            let label = ui.selectable_label(self.selection.contains(&id), object_label(scene, id));
+                            if label.clicked() {
+                                self.select(Some(id), ui.input(|input| input.modifiers.command));
+                            }
+                            if label.double_clicked() {
+                                self.renaming = Some((id, scene.name(id).unwrap_or_default().to_string()));
+                            }
+                        }
+                    });
+                }
+            });
+
+            ui.separator();
+            let selected = self.selected.filter(|id| scene.entries.contains_key(id));
+            let selection = self.selection.iter().filter(|id| scene.entries.contains_key(id)).count();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(selected.is_some(), Button::new("Rename")).clicked() {
+                    self.renaming = selected.map(|id| (id, scene.name(id).unwrap_or_default().to_string()));
+                }
+                if ui.add_enabled(selection > 0, Button::new("Duplicate")).on_hover_text("Ctrl+D, meshes become instances sharing their triangles").clicked() {
+                    duplicated = true;
+                }
+                // The scene's BVH and GPU buffers can't be empty, so the last object stays
+                let delete = ui.add_enabled(selection > 0 && scene.entries.len() > selection, Button::new("Delete"));
+                if delete.clicked() {
+                    removed = true;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Gizmo");
+                ui.selectable_value(&mut self.gizmo_mode, GizmoMode::Translate, "Move");
+                ui.selectable_value(&mut self.gizmo_mode, GizmoMode::Rotate, "Rotate");
+                ui.selectable_value(&mut self.gizmo_mode, GizmoMode::Scale, "Scale");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Copy offset");
+                let offset = &mut self.duplicate_offset;
+                for value in [&mut offset.0, &mut offset.1, &mut offset.2] {
+                    ui.add(egui::DragValue::new(value).speed(0.05));
+                }
+            });
+        });
+
+        if duplicated {
+            self.duplicate_selected(scene);
+        }
+        if removed {
+            let edits = self.selection.iter().filter_map(|&id| scene.take(id)).map(Edit::Removed).collect();
+            push_edits(scene, edits);
+            self.select(None, false);
+            self.renaming = None;
+        }
+    }
+
+    fn material_inspector(&mut self, context: &egui::Context, scene: &mut Scene) {
+        let Some(id) = self.selected else { return };
+        let Some(color) = scene.color(id) else {
+            self.select(None, false);
+            return;
+        };
+        let title = scene.name(id).map_or_else(|| format!("Object {}", id.0), str::to_string);
+
+        let mut open = true;
+        egui::Window::new("Material").open(&mut open).resizable(false).show(context, |ui| {
+            ui.label(title);
+            ui.image(SizedTexture::new(self.preview_texture, egui::vec2(PREVIEW_SIZE as f32, PREVIEW_SIZE as f32)));
+
+            let mut rgb = [color.0, color.1, color.2];
+            ui.horizontal(|ui| {
+                ui.label("Color");
+                if ui.color_edit_button_rgb(&mut rgb).changed() {
+                    scene.set_color(id, Vec3(rgb[0], rgb[1], rgb[2]));
+                }
+            });
+
+            // Materials are shared, so edits show on every object using this one until it gets a copy
+            let Some(material_id) = scene.material(id) else { return };
+            let users = scene.entries.keys().filter(|&&other| scene.material(other) == Some(material_id)).count();
+            if users > 1 {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Shared by {} objects", users));
+                    if ui.button("Make unique").clicked() {
+                        let copy = scene.add_material(scene.materials[material_id.0]);
+                        scene.set_material(id, copy);
+                    }
+                });
+            }
+            let Some(material_id) = scene.material(id) else { return };
+
+            let mut material = scene.materials[material_id.0];
+            ui.checkbox(&mut material.diffuse, "Diffuse");
+            ui.add(Slider::new(&mut material.roughness, 0.0..=1.0).text("Roughness"));
+            ui.add_enabled(material.diffuse, Slider::new(&mut material.metalness, 0.0..=1.0).text("Metalness"));
+            ui.add(Slider::new(&mut material.transparency, 0.0..=1.0).text("Transparency"));
+            let mut glass = material.ior > 0.0;
+            if ui.checkbox(&mut glass, "Glass").changed() {
+                material.ior = if glass { 1.5 } else { 0.0 };
+            }
+            ui.add_enabled(glass, Slider::new(&mut material.ior, 1.0..=2.5).text("IOR"));
+            ui.add_enabled(glass, Slider::new(&mut material.dispersion, 0.0..=0.02).text("Dispersion"));
+            ui.checkbox(&mut material.two_sided, "Two-sided");
+            ui.checkbox(&mut material.hair, "Hair");
+            ui.checkbox(&mut material.cull_backfaces, "Cull backfaces");
+
+            let mut has_film = material.thin_film.is_some();
+            if ui.checkbox(&mut has_film, "Thin film").changed() {
+                material.thin_film = has_film.then_some(ThinFilm { thickness: 400.0, ior: 1.33 });
+            }
+            if let Some(film) = &mut material.thin_film {
+                ui.add(Slider::new(&mut film.thickness, 50.0..=1500.0).text("Thickness (nm)"));
+                ui.add(Slider::new(&mut film.ior, 1.0..=2.5).text("Film IOR"));
+            }
+
+            let image_name = |index: usize| {
+                let path = &scene.image_paths[index];
+                Path::new(path).file_name().map_or(path.clone(), |name| name.to_string_lossy().into_owned())
+            };
+            ComboBox::from_label("Albedo texture")
+                .selected_text(material.albedo_texture.map_or("None".to_string(), image_name))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut material.albedo_texture, None, "None");
+                    for index in 0..scene.image_paths.len() {
+                        ui.selectable_value(&mut material.albedo_texture, Some(index), image_name(index));
+                    }
+                });
+            ui.add_enabled(material.albedo_texture.is_some(), Slider::new(&mut material.alpha_cutoff, 0.0..=1.0).text("Alpha cutoff"));
+
+            if material != scene.materials[material_id.0] {
+                scene.materials[material_id.0] = material;
+                scene.dirty = true;
+            }
+        });
+
+        if !open {
+            self.select(None, false);
+        }
+    }
+}
+
+// Records edits made together as one, unless there are none
+fn push_edits(scene: &mut Scene, mut edits: Vec<Edit>) {
+    match edits.len() {
+        0 => {},
+        1 => scene.history.push(edits.pop().unwrap()),
+        _ => scene.history.push(Edit::Group(edits)),
+    }
+}
+
+// Sampling options that trade accuracy for less noise, the photon and bidirectional passes, and
+// the BVH heat map, the exposure, the post effects and the fade after camera moves
+fn render_settings(context: &egui::Context, scene: &mut Scene) {
+    egui::Window::new("Render").default_open(false).show(context, |ui| {
+        let before = (scene.spectral, scene.max_radiance, scene.roughness_regularization, scene.outlier_rejection, scene.caustics, scene.photon_radius, scene.bidirectional, scene.interaction_mode, scene.bvh_heatmap, scene.exposure, scene.auto_exposure);
+        ui.checkbox(&mut scene.spectral, "Spectral");
+        ui.add(Slider::new(&mut scene.max_radiance, 0.0..=10.0).text("Max radiance"))
+            .on_hover_text("Samples brighter than this are dimmed, 0 keeps them all");
+        ui.add(Slider::new(&mut scene.roughness_regularization, 0.0..=1.0).text("Roughness regularization"))
+            .on_hover_text("Least roughness of reflections after a matte bounce");
+        ui.checkbox(&mut scene.outlier_rejection, "Reject outliers");
+        ui.checkbox(&mut scene.caustics, "Caustics");
+        ui.add(Slider::new(&mut scene.photon_radius, 0.005..=0.5).logarithmic(true).text("Photon radius"))
+            .on_hover_text("Size of the cells caustic photons are gathered over");
+        ui.checkbox(&mut scene.bidirectional, "Bidirectional")
+            .on_hover_text("Connect matte bounces to light paths from the sky, for interiors");
+        ui.checkbox(&mut scene.interaction_mode, "Fast preview while moving")
+            .on_hover_text("Trace at quarter resolution until the view stops changing");
+        ui.checkbox(&mut scene.bvh_heatmap, "BVH heat map")
+            .on_hover_text("Color pixels by how expensive their camera ray is to trace, red for the costliest");
+        ui.add(Slider::new(&mut scene.exposure, -8.0..=8.0).text("Exposure"))
+            .on_hover_text("Stops the view is brightened by, captures keep the radiance as traced");
+        ui.checkbox(&mut scene.auto_exposure, "Auto exposure")
+            .on_hover_text("Adapt the exposure to the brightness of the view over time");
+        if before != (scene.spectral, scene.max_radiance, scene.roughness_regularization, scene.outlier_rejection, scene.caustics, scene.photon_radius, scene.bidirectional, scene.interaction_mode, scene.bvh_heatmap, scene.exposure, scene.auto_exposure) {
+            scene.dirty = true;
+        }
+        // Traces the same image faster, so the samples are kept
+        ui.checkbox(&mut scene.raster_primary, "Rasterize camera rays")
+            .on_hover_text("Find what camera rays hit first by drawing the triangles, much faster on dense meshes");
+        ui.checkbox(&mut scene.stackless_traversal, "Stackless traversal")
+            .on_hover_text("Walk the BVH with skip links instead of a stack, can be faster on GPUs short on registers");
+        // Reflections lose what is out of view, so the samples start over
+        if ui.checkbox(&mut scene.frustum_culling, "Frustum culling")
+            .on_hover_text("Upload only the part of the BVH inside the view, faster to navigate huge scenes")
+            .changed() {
+            scene.moved = true;
+        }
+
+        // Drawn over the traced image, so changing them keeps the samples
+        ui.separator();
+        let post = &mut scene.post_process;
+        ui.checkbox(&mut post.enabled, "Post effects");
+        ui.add_enabled(post.enabled, Slider::new(&mut post.chromatic_aberration, 0.0..=10.0).text("Chromatic aberration"))
+            .on_hover_text("Pixels the red and blue channels are pulled apart by at the edges");
+        ui.add_enabled(post.enabled, Slider::new(&mut post.vignette, 0.0..=1.0).text("Vignette"));
+        ui.add_enabled(post.enabled, Slider::new(&mut post.grain, 0.0..=0.2).text("Film grain"));
+        ui.add(Slider::new(&mut scene.fade_duration, 0.0..=2.0).text("Fade on camera moves"))
+            .on_hover_text("Seconds the last converged frame is blended over the new samples after the camera moves");
+    });
+}
+
+// The selected object and its material as they were when the pointer went down
+struct PendingEdit {
+    id: ObjectId,
+    primitives: Vec<Object>,
+    transform: Transform,
+    material: Option<(usize, Material)>,
+}
+
+impl PendingEdit {
+    fn new(scene: &Scene, id: ObjectId) -> Option<Self> {
+        let primitives = scene.primitives(id)?.to_vec();
+        let transform = scene.entries[&id].transform;
+        let material = scene.material(id).map(|material| (material.0, scene.materials[material.0]));
+        Some(Self { id, primitives, transform, material })
+    }
+
+    // The edit leading from the recorded state to the current one, None if nothing changed
+    fn finish(self, scene: &Scene) -> Option<Edit> {
+        let mut edits = Vec::new();
+        if let Some(primitives) = scene.primitives(self.id).filter(|primitives| *primitives != self.primitives.as_slice()) {
+            let transforms = [self.transform, scene.entries[&self.id].transform];
+            edits.push(Edit::Primitives { id: self.id, before: self.primitives, after: primitives.to_vec(), transforms });
+        }
+        if let Some((index, before)) = self.material.filter(|&(index, before)| scene.materials[index] != before) {
+            edits.push(Edit::Material { index, before, after: scene.materials[index] });
+        }
+
+        match edits.len() {
+            0 | 1 => edits.pop(),
+            _ => Some(Edit::Group(edits)),
+        }
+    }
+}
+
+// Name of the object, or what it is made of when it has none
+fn object_label(scene: &Scene, id: ObjectId) -> String {
+    let primitives = &scene.objects[scene.entries[&id].primitives.clone()];
+    let kind = match (primitives.first(), primitives.len()) {
+        (None, _) => "Empty".to_string(),
+        (Some(Object::Triangle(_)), count) if count > 1 => format!("Mesh of {} triangles", count),
+        (Some(Object::Sphere(_)), _) => "Sphere".to_string(),
+        (Some(Object::Triangle(_)), _) => "Triangle".to_string(),
+        (Some(Object::Quad(_)), _) => "Quad".to_string(),
+        (Some(Object::Billboard(_)), _) => "Label".to_string(),
+        (Some(Object::Curve(_)), count) => format!("Hair of {} strands", count),
+        (Some(Object::Points(_)), _) => {
+            let points: usize = primitives.iter()
+                .map(|object| if let Object::Points(cluster) = object { cluster.points.len() } else { 0 })
+                .sum();
+            format!("Point cloud of {} points", points)
+        },
+        (Some(Object::Instance(instance)), _) => format!("Instance of {} triangles", scene.prototypes[instance.prototype].primitives.len()),
+    };
+
+    match scene.name(id) {
+        Some(name) => format!("{} ({})", name, kind),
+        None => format!("{} {}", kind, id.0),
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+/// Average luminance auto exposure brings the view to, a mid grey
+pub const EXPOSURE_KEY: f32 = 0.18;
+/// Bytes the exposure shader meters into: the summed workgroup means and the workgroup count
+pub const METERING_BUFFER_SIZE: u64 = 8;
+// Match LOG_OFFSET and FIXED_POINT in the exposure shader, which adds each workgroup's mean log2
+// luminance shifted by the offset into a whole number of 1/FIXED_POINT steps
+const METERING_LOG_OFFSET: f32 = 16.0;
+const METERING_FIXED_POINT: f32 = 1024.0;
+
+// Stops per second the adapted luminance closes in on the metered one at. Like eyes, the view
+// darkens quickly when it turns to something bright and takes longer to open up in the dark.
+const ADAPTATION_BRIGHTER: f32 = 3.0;
+const ADAPTATION_DARKER: f32 = 1.0;
+// Adapted within this many stops of the metered luminance counts as settled
+const SETTLED_STOPS: f32 = 0.05;
+// Stops the exposure stays within, so a black frame or a look into the sun doesn't run away
+const EXPOSURE_RANGE: f32 = 8.0;
+
+/// Average log2 luminance of a metered frame, None when no pixel was metered
+pub fn log_average_luminance(metering: &[u32]) -> Option<f32> {
+    match metering {
+        [_, 0] => None,
+        [sum, count] => Some(*sum as f32 / METERING_FIXED_POINT / *count as f32 - METERING_LOG_OFFSET),
+        _ => panic!("Metering holds a sum and a count"),
+    }
+}
+
+/// Follows the brightness of the view over time, so the exposure eases in after the camera moves
+/// from a dark interior to the bright sky instead of jumping or blowing out
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AutoExposure {
+    // Log2 luminances the view is adapted to and was last metered at, None before the first metering
+    adapted: Option<f32>,
+    metered: f32,
+}
+
+impl AutoExposure {
+    /// Moves the adapted luminance towards the metered log2 average over `elapsed`, taking the
+    /// first metering as it is, and returns the new exposure
+    pub fn adapt(&mut self, log_average: f32, elapsed: Duration) -> f32 {
+        self.metered = log_average;
+        let adapted = match self.adapted {
+            Some(adapted) => {
+                let rate = if log_average > adapted { ADAPTATION_BRIGHTER } else { ADAPTATION_DARKER };
+                let closed = 1.0 - (-rate * elapsed.as_secs_f32()).exp();
+                adapted + (log_average - adapted) * closed
+            },
+            None => log_average,
+        };
+        self.adapted = Some(adapted);
+        self.exposure()
+    }
+
+    /// Factor the radiance is scaled by to bring the adapted luminance to EXPOSURE_KEY, 1 before
+    /// the first metering
+    pub fn exposure(&self) -> f32 {
+        match self.adapted {
+            Some(adapted) => (EXPOSURE_KEY.log2() - adapted).clamp(-EXPOSURE_RANGE, EXPOSURE_RANGE).exp2(),
+            None => 1.0,
+        }
+    }
+
+    /// Whether the view has adapted to the last metering
+    pub fn settled(&self) -> bool {
+        self.adapted.is_some_and(|adapted| (adapted - self.metered).abs() < SETTLED_STOPS)
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::{ObjectId, Scene, SkySource};
+
+// How often the watched files' modification times are read
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What a watched file is to the scene, and so what has to be read again when it changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Asset {
+    /// The script the scene was built with
+    Script,
+    /// A mesh read from a file, by the object it was added as
+    Mesh(ObjectId),
+    /// One of the texture images, which share a texture array
+    Textures,
+    /// A sky, by its index in the scene's skies
+    Sky(usize),
+}
+
+struct WatchedFile {
+    path: String,
+    asset: Asset,
+    modified: Option<SystemTime>,
+}
+
+/// Polls the files a scene was built from, so they can be read again while the viewer stays open
+#[derive(Default)]
+pub struct FileWatcher {
+    files: Vec<WatchedFile>,
+    checked: Option<Instant>,
+}
+
+impl FileWatcher {
+    pub fn watch(&mut self, path: &str, asset: Asset) {
+        self.files.push(WatchedFile { path: path.to_string(), asset, modified: modified_time(path) });
+    }
+
+    /// Watches the meshes, texture images and sky files of the scene, in place of the ones of the
+    /// scene watched before
+    pub fn watch_scene(&mut self, scene: &Scene) {
+        self.files.retain(|file| file.asset == Asset::Script);
+        for (&id, source) in &scene.mesh_sources {
+            if scene.entries.contains_key(&id) {
+                self.watch(&source.path, Asset::Mesh(id));
+            }
+        }
+        for path in &scene.image_paths {
+            self.watch(path, Asset::Textures);
+        }
+        for (i, sky) in scene.skies.iter().enumerate() {
+            match sky {
+                SkySource::Faces(paths) => paths.iter().for_each(|path| self.watch(path, Asset::Sky(i))),
+                SkySource::File(path) => self.watch(path, Asset::Sky(i)),
+                SkySource::Solid(_) => {},
+            }
+        }
+    }
+
+    /// Assets with files modified since the last call, each listed once. The files are only
+    /// looked at every WATCH_INTERVAL, so this is cheap to call every frame.
+    pub fn changed(&mut self) -> Vec<Asset> {
+        if self.checked.is_some_and(|checked| checked.elapsed() < WATCH_INTERVAL) {
+            return Vec::new();
+        }
+        self.checked = Some(Instant::now());
+
+        let mut changed = Vec::new();
+        for file in &mut self.files {
+            // A file that can't be read, like one an editor is replacing, is looked at again next time
+            let modified = modified_time(&file.path);
+            if modified.is_some() && modified != file.modified {
+                file.modified = modified;
+                if !changed.contains(&file.asset) {
+                    changed.push(file.asset);
+                }
+            }
+        }
+        changed
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
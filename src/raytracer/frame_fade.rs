@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use super::{Camera, Projection, Vec3};
+
+/// Bytes of the fade shader's uniform: the history's camera and the current one, four vectors each
+pub const FADE_UNIFORM_SIZE: u64 = 128;
+/// Samples per pixel the main window needs before its frame is kept to fade from when the camera
+/// moves. Frames with fewer are noisier than they are worth blending in, so the one kept before
+/// stays instead.
+pub const FADE_HISTORY_SAMPLES: u32 = 16;
+/// Seconds the view fades from the last converged frame to the new samples by default
+pub const DEFAULT_FADE_DURATION: f32 = 0.3;
+
+/// Share of the kept frame shown `elapsed` after it was kept, falling from 1 to 0 over
+/// `duration` seconds. A duration of 0 shows the new samples right away.
+pub fn fade_weight(elapsed: Duration, duration: f32) -> f32 {
+    if duration <= 0.0 {
+        return 0.0;
+    }
+    (1.0 - elapsed.as_secs_f32() / duration).clamp(0.0, 1.0)
+}
+
+/// Where a direction from a camera crosses the image plane of the camera a frame was traced
+/// through, as the uv of that frame, None when the frame doesn't show it. Everything is taken to
+/// be far away, which is exact when the camera turns and close enough for small moves. Mirrors
+/// `reproject` in the fade shader.
+pub fn reproject(history: &Camera, direction: Vec3) -> Option<(f32, f32)> {
+    let to_plane = history.lower_left_corner - history.origin;
+    let normal = history.horizontal.cross(history.vertical);
+    let facing = direction.dot(normal);
+    // Behind the camera or parallel to its image plane
+    if facing * to_plane.dot(normal) <= 0.0 {
+        return None;
+    }
+    let on_plane = direction * (to_plane.dot(normal) / facing) - to_plane;
+    let uv = (
+        on_plane.dot(history.horizontal) / history.horizontal.dot(history.horizontal),
+        on_plane.dot(history.vertical) / history.vertical.dot(history.vertical),
+    );
+    ((0.0..1.0).contains(&uv.0) && (0.0..1.0).contains(&uv.1)).then_some(uv)
+}
+
+/// Whether frames of one camera can be reprojected to another. Only perspective image planes
+/// are, panoramas and parallel rays would need the depth of every pixel.
+pub fn can_reproject(history: &Camera, camera: &Camera) -> bool {
+    history.projection() == Projection::Perspective && camera.projection() == Projection::Perspective
+}
+
+/// The fade shader's uniform: the cameras the kept frame and the new samples were traced
+/// through, the kept frame's weight and the exposure the blend is shown at
+pub fn fade_uniform_data(history: &Camera, camera: &Camera, weight: f32, exposure: f32) -> [f32; FADE_UNIFORM_SIZE as usize / 4] {
+    let vectors = [
+        (history.origin, weight),
+        (history.lower_left_corner, exposure),
+        (history.horizontal, 0.0),
+        (history.vertical, 0.0),
+        (camera.origin, 0.0),
+        (camera.lower_left_corner, 0.0),
+        (camera.horizontal, 0.0),
+        (camera.vertical, 0.0),
+    ];
+    let mut data = [0.0; FADE_UNIFORM_SIZE as usize / 4];
+    for (i, (vector, padding)) in vectors.into_iter().enumerate() {
+        data[i * 4..i * 4 + 4].copy_from_slice(&[vector.0, vector.1, vector.2, padding]);
+    }
+    data
+}
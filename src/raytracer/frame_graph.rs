@@ -0,0 +1,142 @@
+/// What the passes of a frame read and write, declared so that the order of the passes can be checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameResource {
+    /// Objects, tree, materials and points, uploaded before the passes run
+    SceneBuffers,
+    /// Caustic photons gathered for each viewport
+    PhotonMap,
+    /// Accumulated radiance and color buffer of each viewport
+    ColorBuffer,
+    /// Color buffer of the editor's material preview
+    PreviewBuffer,
+    /// Image presented in each window
+    Surface,
+}
+
+/// A step of `State::render`. Adding one takes a variant here, its resources and a match arm in
+/// `State::encode_frame_pass`, then it goes into the `FrameGraph` where it belongs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramePass {
+    /// Traces photons and one more sample per pixel into every viewport
+    RayTrace,
+    /// Traces the material the editor shows a preview of
+    #[cfg(feature = "editor")]
+    MaterialPreview,
+    /// Draws each viewport's color buffer over its window
+    Blit,
+    /// Draws the loading progress bar over the main window
+    Progress,
+    /// Draws the frame time graph over the main window when it is shown
+    FrameTimes,
+    /// Draws the editor's panels over the main window
+    #[cfg(feature = "editor")]
+    Ui,
+}
+
+impl FramePass {
+    pub fn reads(self) -> &'static [FrameResource] {
+        use FrameResource::*;
+        match self {
+            FramePass::RayTrace => &[SceneBuffers, PhotonMap, ColorBuffer],
+            #[cfg(feature = "editor")]
+            FramePass::MaterialPreview => &[PreviewBuffer],
+            FramePass::Blit => &[ColorBuffer],
+            FramePass::Progress | FramePass::FrameTimes => &[Surface],
+            // The preview is shown next to the material's settings
+            #[cfg(feature = "editor")]
+            FramePass::Ui => &[PreviewBuffer, Surface],
+        }
+    }
+
+    pub fn writes(self) -> &'static [FrameResource] {
+        use FrameResource::*;
+        match self {
+            FramePass::RayTrace => &[PhotonMap, ColorBuffer],
+            #[cfg(feature = "editor")]
+            FramePass::MaterialPreview => &[PreviewBuffer],
+            FramePass::Blit | FramePass::Progress | FramePass::FrameTimes => &[Surface],
+            #[cfg(feature = "editor")]
+            FramePass::Ui => &[Surface],
+        }
+    }
+}
+
+/// Passes of a frame in the order they are encoded. Every pass comes after the passes writing
+/// what it reads, which the graph checks whenever passes are added.
+#[derive(Debug, Clone)]
+pub struct FrameGraph {
+    passes: Vec<FramePass>,
+}
+
+impl Default for FrameGraph {
+    fn default() -> Self {
+        Self {
+            passes: vec![
+                FramePass::RayTrace,
+                #[cfg(feature = "editor")]
+                FramePass::MaterialPreview,
+                FramePass::Blit,
+                FramePass::Progress,
+                FramePass::FrameTimes,
+                #[cfg(feature = "editor")]
+                FramePass::Ui,
+            ],
+        }
+    }
+}
+
+impl FrameGraph {
+    /// Passes in the given order, or the first pass reading something a later one writes
+    pub fn new(passes: Vec<FramePass>) -> Result<Self, FramePass> {
+        let graph = Self { passes };
+        graph.check().map(|_| graph)
+    }
+
+    pub fn passes(&self) -> &[FramePass] {
+        &self.passes
+    }
+
+    /// Puts the pass right after `after`, or at the end when `after` isn't in the graph
+    pub fn insert_after(&mut self, after: FramePass, pass: FramePass) -> Result<(), FramePass> {
+        let index = self.passes.iter().position(|&existing| existing == after).map_or(self.passes.len(), |i| i + 1);
+        self.insert_at(index, pass)
+    }
+
+    /// Puts the pass right before `before`, or at the end when `before` isn't in the graph
+    pub fn insert_before(&mut self, before: FramePass, pass: FramePass) -> Result<(), FramePass> {
+        let index = self.passes.iter().position(|&existing| existing == before).unwrap_or(self.passes.len());
+        self.insert_at(index, pass)
+    }
+
+    /// Takes the pass out of the frame, returning whether it was in it
+    pub fn remove(&mut self, pass: FramePass) -> bool {
+        let count = self.passes.len();
+        self.passes.retain(|&existing| existing != pass);
+        self.passes.len() != count
+    }
+
+    // Inserts the pass unless it breaks the order, in which case the graph stays as it was
+    fn insert_at(&mut self, index: usize, pass: FramePass) -> Result<(), FramePass> {
+        self.passes.insert(index, pass);
+        let checked = self.check();
+        if checked.is_err() {
+            self.passes.remove(index);
+        }
+        checked
+    }
+
+    // A pass reading a resource has to come after every pass writing it in the same frame, or it
+    // would see last frame's contents. Passes that both read and write it, like overlays drawn on
+    // the same image, can go in either order.
+    fn check(&self) -> Result<(), FramePass> {
+        let conflicts = |pass: FramePass, later: FramePass| later.writes().iter().any(|resource| {
+            pass.reads().contains(resource) && !(pass.writes().contains(resource) && later.reads().contains(resource))
+        });
+        for (i, &pass) in self.passes.iter().enumerate() {
+            if self.passes[i + 1..].iter().any(|&later| conflicts(pass, later)) {
+                return Err(pass);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Frames the graph shows, matches HISTORY in the frame time shader
+pub const FRAME_TIME_HISTORY: usize = 240;
+/// Bytes of the frame time shader's uniform: the CPU and GPU times, then the count and scale padded to a vector
+pub const FRAME_TIMES_UNIFORM_SIZE: u64 = (2 * FRAME_TIME_HISTORY as u64 + 4) * 4;
+// The lowest the top of the graph goes, a little over a frame at 30 frames per second so both
+// budget lines show
+const MIN_SCALE_MS: f32 = 36.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameTime {
+    /// Updating the scene and recording the frame, including tree rebuilds and buffer uploads
+    pub cpu_ms: f32,
+    /// From submitting the frame until the GPU finished it, None until the queue reports it.
+    /// Includes waiting for work submitted before it.
+    pub gpu_ms: Option<f32>,
+}
+
+/// The times of the last FRAME_TIME_HISTORY frames, drawn as a graph to show where stutters come from
+#[derive(Default)]
+pub struct FrameTimes {
+    frames: VecDeque<FrameTime>,
+    // Number of the oldest frame kept
+    first: u64,
+    // GPU times the queue reported by frame number, not filled in yet
+    reported: Arc<Mutex<Vec<(u64, f32)>>>,
+}
+
+impl FrameTimes {
+    /// Adds a frame, dropping the oldest past FRAME_TIME_HISTORY, and returns a callback for
+    /// `Queue::on_submitted_work_done` reporting its GPU time counted from now
+    pub fn push(&mut self, cpu: Duration) -> impl FnOnce() + Send + 'static {
+        if self.frames.len() == FRAME_TIME_HISTORY {
+            self.frames.pop_front();
+            self.first += 1;
+        }
+        self.frames.push_back(FrameTime { cpu_ms: milliseconds(cpu), gpu_ms: None });
+
+        let number = self.first + self.frames.len() as u64 - 1;
+        let reported = self.reported.clone();
+        let submitted = Instant::now();
+        move || reported.lock().unwrap().push((number, milliseconds(submitted.elapsed())))
+    }
+
+    /// Frames oldest first, with the GPU times reported so far
+    pub fn frames(&mut self) -> &VecDeque<FrameTime> {
+        for (number, gpu_ms) in self.reported.lock().unwrap().drain(..) {
+            if let Some(frame) = number.checked_sub(self.first).and_then(|i| self.frames.get_mut(i as usize)) {
+                frame.gpu_ms = Some(gpu_ms);
+            }
+        }
+        &self.frames
+    }
+
+    /// The frames laid out like the frame time shader's uniform, scaled so the slowest fits
+    pub fn uniform_data(&mut self) -> Vec<f32> {
+        let frames = self.frames();
+        let mut data = vec![0.0; FRAME_TIMES_UNIFORM_SIZE as usize / 4];
+        let (cpu, rest) = data.split_at_mut(FRAME_TIME_HISTORY);
+        let (gpu, rest) = rest.split_at_mut(FRAME_TIME_HISTORY);
+        for (i, frame) in frames.iter().enumerate() {
+            cpu[i] = frame.cpu_ms;
+            gpu[i] = frame.gpu_ms.unwrap_or(-1.0);
+        }
+
+        let slowest = frames.iter().map(|frame| frame.cpu_ms.max(frame.gpu_ms.unwrap_or(0.0))).fold(0.0, f32::max);
+        rest[0] = frames.len() as f32;
+        rest[1] = (slowest * 1.1).max(MIN_SCALE_MS);
+        data
+    }
+}
+
+fn milliseconds(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
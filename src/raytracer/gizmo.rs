@@ -0,0 +1,128 @@
+use std::f32::consts::TAU;
+
+use egui::{Color32, Pos2, Rect, Response, Sense, Shape, Stroke};
+
+use super::{Camera, ObjectId, Scene, Vec3};
+
+/// Which transform dragging the gizmo on the selected object edits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GizmoMode {
+    /// Arrows along each axis that move the object
+    #[default]
+    Translate,
+    /// Rings around each axis that turn the object
+    Rotate,
+    /// A handle in the middle that grows or shrinks the object, dragging up or right grows it
+    Scale,
+}
+
+// Length of the axes as a share of the distance to the camera, so the gizmo keeps its size on screen
+const GIZMO_SCALE: f32 = 0.15;
+// Radius in points of the handles that can be dragged
+const HANDLE_RADIUS: f32 = 7.0;
+// Change in size per point dragged
+const SCALE_SPEED: f32 = 0.005;
+const RING_SEGMENTS: usize = 48;
+const AXES: [(Vec3, Color32); 3] = [
+    (Vec3(1.0, 0.0, 0.0), Color32::from_rgb(230, 70, 70)),
+    (Vec3(0.0, 1.0, 0.0), Color32::from_rgb(80, 200, 80)),
+    (Vec3(0.0, 0.0, 1.0), Color32::from_rgb(70, 120, 230)),
+];
+
+/// Draws the gizmo over the object and applies drags on its handles. Returns whether the
+/// pointer is over a handle, so clicking one doesn't pick whatever is behind it.
+pub fn show_gizmo(ui: &mut egui::Ui, scene: &mut Scene, id: ObjectId, mode: GizmoMode) -> bool {
+    let screen = ui.ctx().screen_rect();
+    let Some(position) = scene.position(id) else { return false };
+    let Some(center) = project(&scene.camera, position, screen) else { return false };
+    let length = (position - scene.camera.origin).magnitude() * GIZMO_SCALE;
+    let painter = ui.painter().clone();
+    let mut hovered = false;
+
+    match mode {
+        GizmoMode::Translate => {
+            for (i, &(axis, color)) in AXES.iter().enumerate() {
+                let Some(tip) = project(&scene.camera, position + axis * length, screen) else { continue };
+                let handle = handle(ui, i, tip);
+                painter.line_segment([center, tip], Stroke::new(2.0, color));
+                painter.circle_filled(tip, HANDLE_RADIUS, highlight(color, &handle));
+
+                // Pointer movement along the arrow on screen moves the object as far along the axis
+                let on_screen = tip - center;
+                if handle.dragged() && on_screen.length_sq() > 1.0 {
+                    let amount = handle.drag_delta().dot(on_screen) / on_screen.length_sq() * length;
+                    scene.set_position(id, position + axis * amount);
+                }
+                hovered |= handle.hovered() || handle.dragged();
+            }
+        },
+        GizmoMode::Rotate => {
+            for (i, &(axis, color)) in AXES.iter().enumerate() {
+                // The ring lies in the plane of the other two axes
+                let (side, up) = (AXES[(i + 1) % 3].0, AXES[(i + 2) % 3].0);
+                let ring_point = |angle: f32| position + (side * angle.cos() + up * angle.sin()) * length;
+                let ring: Vec<Pos2> = (0..=RING_SEGMENTS)
+                    .filter_map(|k| project(&scene.camera, ring_point(k as f32 / RING_SEGMENTS as f32 * TAU), screen))
+                    .collect();
+                painter.add(Shape::line(ring, Stroke::new(2.0, color)));
+
+                let knob_angle = TAU / 8.0;
+                let Some(knob) = project(&scene.camera, ring_point(knob_angle), screen) else { continue };
+                let handle = handle(ui, i, knob);
+                painter.circle_filled(knob, HANDLE_RADIUS, highlight(color, &handle));
+
+                if let (true, Some(pointer)) = (handle.dragged(), handle.interact_pointer_pos()) {
+                    // Turn by the angle the pointer swept around the center, in whichever direction
+                    // the ring turns on screen for a positive angle around the axis
+                    let swept = screen_angle(pointer - handle.drag_delta() - center, pointer - center);
+                    let direction = project(&scene.camera, ring_point(knob_angle + 0.01), screen)
+                        .map_or(1.0, |ahead| screen_angle(knob - center, ahead - center).signum());
+                    scene.rotate(id, axis, swept * direction);
+                }
+                hovered |= handle.hovered() || handle.dragged();
+            }
+        },
+        GizmoMode::Scale => {
+            let handle = handle(ui, 0, center);
+            painter.rect_filled(Rect::from_center_size(center, egui::Vec2::splat(2.0 * HANDLE_RADIUS)), 2.0, highlight(Color32::LIGHT_GRAY, &handle));
+
+            if handle.dragged() {
+                let delta = handle.drag_delta();
+                scene.scale(id, (1.0 + (delta.x - delta.y) * SCALE_SPEED).max(0.5));
+            }
+            hovered |= handle.hovered() || handle.dragged();
+        },
+    }
+
+    hovered
+}
+
+// Where a point in the scene shows up on screen, None behind the camera
+fn project(camera: &Camera, point: Vec3, screen: Rect) -> Option<Pos2> {
+    let forward = camera.lower_left_corner + (camera.horizontal + camera.vertical) * 0.5 - camera.origin;
+    let direction = point - camera.origin;
+    let depth = direction.dot(forward) / forward.dot(forward);
+    if depth <= 1e-4 {
+        return None;
+    }
+
+    // Rows of the render go down the screen in the same order as the kernel walks `vertical`
+    let on_plane = camera.origin + direction / depth - camera.lower_left_corner;
+    let x = on_plane.dot(camera.horizontal) / camera.horizontal.dot(camera.horizontal);
+    let y = on_plane.dot(camera.vertical) / camera.vertical.dot(camera.vertical);
+    Some(screen.min + egui::vec2(x * screen.width(), y * screen.height()))
+}
+
+fn handle(ui: &mut egui::Ui, index: usize, at: Pos2) -> Response {
+    let rect = Rect::from_center_size(at, egui::Vec2::splat(2.0 * HANDLE_RADIUS));
+    ui.interact(rect, egui::Id::new(("gizmo handle", index)), Sense::drag())
+}
+
+fn highlight(color: Color32, handle: &Response) -> Color32 {
+    if handle.hovered() || handle.dragged() { Color32::WHITE } else { color }
+}
+
+// Signed angle from one screen direction to the other
+fn screen_angle(from: egui::Vec2, to: egui::Vec2) -> f32 {
+    (from.x * to.y - from.y * to.x).atan2(from.dot(to))
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+use std::mem::size_of;
+
+use super::{Scene, SCENE_DATA_SIZE, STATS_BUFFER_SIZE};
+
+/// Floats of one GeometricPrimitive before its layer mask: type, material and 16 floats of data
+pub const OBJECT_FLOATS: usize = 18;
+/// Floats of one Node: its corners, children, object count, skip link and split axis, padded
+pub const NODE_FLOATS: usize = 12;
+/// Floats of one Material
+pub const MATERIAL_FLOATS: usize = 12;
+/// Words of one entry of the point buffer: a point cloud's point and its packed color, or a
+/// triangle's corner normal
+pub const POINT_WORDS: usize = 4;
+
+/// Size in bytes of one GeometricPrimitive in the object buffer, the layer mask following its floats
+pub const OBJECT_STRIDE: u64 = (size_of::<[f32; OBJECT_FLOATS]>() + size_of::<u32>()) as u64;
+/// Size in bytes of one Node in the node buffer
+pub const NODE_STRIDE: u64 = size_of::<[f32; NODE_FLOATS]>() as u64;
+/// Size in bytes of one object index, stored as a float
+pub const OBJECT_INDEX_STRIDE: u64 = size_of::<f32>() as u64;
+/// Size in bytes of one Material in the material buffer
+pub const MATERIAL_STRIDE: u64 = size_of::<[f32; MATERIAL_FLOATS]>() as u64;
+/// Size in bytes of one entry of the point buffer
+pub const POINT_STRIDE: u64 = size_of::<[u32; POINT_WORDS]>() as u64;
+
+// Writes and copies work in whole words, and uniforms are laid out in vectors of four floats
+const _: () = assert!(OBJECT_STRIDE.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT));
+const _: () = assert!(NODE_STRIDE.is_multiple_of(16) && MATERIAL_STRIDE.is_multiple_of(16) && POINT_STRIDE.is_multiple_of(16));
+const _: () = assert!(SCENE_DATA_SIZE.is_multiple_of(16) && STATS_BUFFER_SIZE.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT));
+
+/// A storage buffer the scene is uploaded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneBuffer {
+    Objects,
+    Nodes,
+    ObjectIndices,
+    Materials,
+    Points,
+}
+
+impl SceneBuffer {
+    pub const ALL: [SceneBuffer; 5] = [SceneBuffer::Objects, SceneBuffer::Nodes, SceneBuffer::ObjectIndices, SceneBuffer::Materials, SceneBuffer::Points];
+
+    /// What the buffer holds, e.g. "objects"
+    pub fn name(self) -> &'static str {
+        match self {
+            SceneBuffer::Objects => "objects",
+            SceneBuffer::Nodes => "BVH nodes",
+            SceneBuffer::ObjectIndices => "object indices",
+            SceneBuffer::Materials => "materials",
+            SceneBuffer::Points => "points",
+        }
+    }
+
+    /// Size in bytes of one element
+    pub fn stride(self) -> u64 {
+        match self {
+            SceneBuffer::Objects => OBJECT_STRIDE,
+            SceneBuffer::Nodes => NODE_STRIDE,
+            SceneBuffer::ObjectIndices => OBJECT_INDEX_STRIDE,
+            SceneBuffer::Materials => MATERIAL_STRIDE,
+            SceneBuffer::Points => POINT_STRIDE,
+        }
+    }
+
+    /// Elements of the scene the buffer holds. Spatial splits can list an object in several
+    /// leaves, so there can be more object indices than objects.
+    pub fn count(self, scene: &Scene) -> usize {
+        match self {
+            SceneBuffer::Objects => scene.object_buffer_len(),
+            SceneBuffer::Nodes => scene.node_buffer_len(),
+            SceneBuffer::ObjectIndices => scene.object_index_buffer_len(),
+            SceneBuffer::Materials => scene.materials.len(),
+            SceneBuffer::Points => scene.point_buffer_len(),
+        }
+    }
+
+    /// Bytes the scene's elements take, at least one element as bindings can't be empty: a scene
+    /// without objects or point clouds binds one zeroed element no node refers to. Sizes too
+    /// large for 64 bits saturate, which no device can hold either.
+    pub fn size(self, scene: &Scene) -> u64 {
+        (self.count(scene).max(1) as u64).saturating_mul(self.stride())
+    }
+}
+
+/// A buffer of the scene larger than the device can bind to the kernel at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferLimitError {
+    pub buffer: SceneBuffer,
+    pub size: u64,
+    pub limit: u64,
+}
+
+impl fmt::Display for BufferLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The scene's {} take {} MiB, more than the {} MiB the device can bind in one buffer", self.buffer.name(), self.size >> 20, self.limit >> 20)
+    }
+}
+
+impl std::error::Error for BufferLimitError {}
+
+/// Checks that every buffer the scene is uploaded to fits the device's limits, the object buffer
+/// being the first to run out with large meshes at OBJECT_STRIDE bytes a triangle
+pub fn check_scene_limits(scene: &Scene, limits: &wgpu::Limits) -> Result<(), BufferLimitError> {
+    let limit = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+    match SceneBuffer::ALL.into_iter().map(|buffer| (buffer, buffer.size(scene))).find(|&(_, size)| size > limit) {
+        Some((buffer, size)) => Err(BufferLimitError { buffer, size, limit }),
+        None => Ok(()),
+    }
+}
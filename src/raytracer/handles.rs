@@ -0,0 +1,23 @@
+use std::ops::Range;
+
+use super::Transform;
+
+/// Stable handle to something added to the scene, valid for the lifetime of the scene
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId(pub u32);
+
+/// Layers new objects are placed on, just the first one
+pub const DEFAULT_LAYERS: u32 = 1;
+
+/// Bookkeeping for a handle: its optional name, the primitives it was built from and where it shows up
+#[derive(Debug, Clone)]
+pub struct ObjectEntry {
+    pub name: Option<String>,
+    pub primitives: Range<usize>,
+    /// Bit per layer the object is on, drawn when it shares a layer with the camera's render mask
+    pub layers: u32,
+    /// Hidden objects stay in the scene and keep their layers, but are never hit
+    pub visible: bool,
+    /// Moves, turns and scaling the object went through since it was added
+    pub transform: Transform,
+}
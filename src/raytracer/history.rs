@@ -0,0 +1,70 @@
+use super::{Material, Object, ObjectEntry, ObjectId, Transform};
+
+// Oldest edits are forgotten past this many, as they can hold copies of whole meshes
+const MAX_EDITS: usize = 100;
+
+/// An object taken out of the scene, with everything needed to put it back under the same handle
+#[derive(Debug, Clone)]
+pub struct RemovedObject {
+    pub id: ObjectId,
+    pub entry: ObjectEntry,
+    pub primitives: Vec<Object>,
+}
+
+/// One undoable change to the scene, holding the state on both sides of it
+#[derive(Debug, Clone)]
+pub enum Edit {
+    /// The object was added, undoing takes it out again
+    Added(RemovedObject),
+    /// The object was removed, undoing puts it back
+    Removed(RemovedObject),
+    /// The object's primitives were moved, turned, scaled, recolored or given another material,
+    /// with the object's transform before and after
+    Primitives { id: ObjectId, before: Vec<Object>, after: Vec<Object>, transforms: [Transform; 2] },
+    /// The material at this index in the scene's list was changed
+    Material { index: usize, before: Material, after: Material },
+    /// Several edits made as one, undone together
+    Group(Vec<Edit>),
+}
+
+/// Edits that can be undone, and the undone ones that can be redone until a new edit is made
+#[derive(Debug, Default)]
+pub struct History {
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+}
+
+impl History {
+    /// Records an edit that was just made to the scene
+    pub fn push(&mut self, edit: Edit) {
+        self.undo_stack.push(edit);
+        if self.undo_stack.len() > MAX_EDITS {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub(crate) fn pop_undo(&mut self) -> Option<Edit> {
+        self.undo_stack.pop()
+    }
+
+    pub(crate) fn pop_redo(&mut self) -> Option<Edit> {
+        self.redo_stack.pop()
+    }
+
+    pub(crate) fn push_undone(&mut self, edit: Edit) {
+        self.redo_stack.push(edit);
+    }
+
+    pub(crate) fn push_redone(&mut self, edit: Edit) {
+        self.undo_stack.push(edit);
+    }
+}
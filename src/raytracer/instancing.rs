@@ -0,0 +1,67 @@
+use super::{Node, Object, Transform, Vec3};
+
+/// Triangles of a mesh shared by its instances, in the mesh's own space, with a BVH of their own.
+/// They are uploaded after the scene's primitives, nodes and object indices, and only the
+/// instances pointing at them are in the scene's BVH.
+#[derive(Debug, Clone)]
+pub struct Prototype {
+    /// Triangles only, white so each instance's color shows as it is
+    pub primitives: Vec<Object>,
+    pub nodes: Vec<Node>,
+    pub object_indices: Vec<usize>,
+    /// Average centroid of the triangles, where an identity instance has its position
+    pub centroid: Vec3,
+}
+
+impl Prototype {
+    /// Box around the triangles, from the root of their BVH
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        self.nodes.first().map_or((Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0)), |root| (root.min_corner, root.max_corner))
+    }
+}
+
+/// A prototype's triangles placed in the scene. The kernel takes rays into the prototype's space
+/// through the inverse of the transform and walks the prototype's BVH, so any number of
+/// instances share one copy of the triangles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub prototype: usize, // Index into the scene's prototypes
+    /// From the prototype's space to the scene's
+    pub transform: Transform,
+    pub color: Vec3,
+    pub material: usize,
+}
+
+impl Instance {
+    /// Box around the placed triangles, made of the corners of the prototype's box
+    pub fn bounds(&self, prototype: &Prototype) -> (Vec3, Vec3) {
+        let (min, max) = prototype.bounds();
+        (0..8)
+            .map(|i| self.transform.apply(Vec3(
+                if i & 1 == 0 { min.0 } else { max.0 },
+                if i & 2 == 0 { min.1 } else { max.1 },
+                if i & 4 == 0 { min.2 } else { max.2 },
+            )))
+            .fold((Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY), Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)), |(min, max), corner| (min.min(corner), max.max(corner)))
+    }
+
+    /// Sphere around the placed triangles
+    pub fn bounding_sphere(&self, prototype: &Prototype) -> (Vec3, f32) {
+        let (min, max) = prototype.bounds();
+        (self.transform.apply((min + max) * 0.5), (max - min).magnitude() * 0.5 * self.transform.scale)
+    }
+
+    /// Average centroid of the placed triangles
+    pub fn position(&self, prototype: &Prototype) -> Vec3 {
+        self.transform.apply(prototype.centroid)
+    }
+
+    /// Columns of the matrix taking directions from the scene into the prototype's space, then
+    /// the offset added to points, as the kernel reads them
+    pub fn inverse_matrix(&self) -> [f32; 12] {
+        let inverse = self.transform.inverse();
+        let [x, y, z] = inverse.rotation.map(|column| column * inverse.scale);
+        let t = inverse.translation;
+        [x.0, x.1, x.2, y.0, y.1, y.2, z.0, z.1, z.2, t.0, t.1, t.2]
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+use image::DynamicImage;
+
+use super::{scene_images, Scene, SkyFaces, SkySource, Vec3};
+
+/// A scene with its BVH built and its active sky and texture images decoded, ready to upload
+pub struct LoadedScene {
+    pub scene: Scene,
+    pub sky: SkyFaces,
+    pub images: Vec<DynamicImage>,
+}
+
+enum LoadMessage {
+    Progress(&'static str, f32),
+    Done(Box<LoadedScene>),
+}
+
+/// Prepares a scene on a background thread so the window stays responsive while it loads:
+/// builds the BVH, or reads it from a cache, then decodes the sky and the texture images.
+pub struct SceneLoader {
+    receiver: Receiver<LoadMessage>,
+    /// What the loader is busy with
+    pub stage: &'static str,
+    /// Share of the loading that is done, from 0 to 1
+    pub fraction: f32,
+}
+
+impl SceneLoader {
+    pub fn spawn(mut scene: Scene, bvh_cache: Option<String>) -> Self {
+        let (sender, receiver) = channel();
+
+        thread::spawn(move || {
+            let progress = |stage, fraction| {
+                sender.send(LoadMessage::Progress(stage, fraction)).ok();
+            };
+
+            progress("Building BVH", 0.0);
+            match &bvh_cache {
+                Some(path) => scene.make_scene_cached(path),
+                None => scene.make_scene(),
+            }
+
+            progress("Loading sky", 0.5);
+            let sky = scene.skies[scene.active_sky].load().expect("Failed to load sky");
+
+            progress("Loading textures", 0.7);
+            let images = scene_images(&scene, |done| progress("Loading textures", 0.7 + 0.3 * done));
+
+            sender.send(LoadMessage::Done(Box::new(LoadedScene { scene, sky, images }))).ok();
+        });
+
+        Self { receiver, stage: "Starting", fraction: 0.0 }
+    }
+
+    /// Takes in the progress reported since the last call, returning the scene once it is ready
+    pub fn poll(&mut self) -> Option<LoadedScene> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(LoadMessage::Progress(stage, fraction)) => {
+                    self.stage = stage;
+                    self.fraction = fraction;
+                },
+                Ok(LoadMessage::Done(loaded)) => return Some(*loaded),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => panic!("Loading the scene failed"),
+            }
+        }
+    }
+}
+
+/// Stand-in rendered while the real scene loads: a plain sky, and a hidden sphere since the
+/// GPU buffers can't be empty
+pub fn placeholder_scene(width: f32, height: f32) -> Scene {
+    let mut scene = Scene::new(1, width, height);
+    scene.skies = vec![SkySource::Solid([40, 44, 52])];
+    let sphere = scene.add_sphere(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 0.1);
+    scene.set_visible(sphere, false);
+    scene.make_scene();
+    scene
+}
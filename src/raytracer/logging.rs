@@ -0,0 +1,46 @@
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+// Used when neither RUST_LOG nor `--log-level` is set, wgpu's info messages are too many to read
+const DEFAULT_FILTER: &str = "warn,rust_raytracing_wgpu=info";
+
+/// Keeps the Chrome trace being written until it is dropped, at the end of main
+pub struct LoggingGuard {
+    #[cfg(feature = "chrome-trace")]
+    _chrome: Option<tracing_chrome::FlushGuard>,
+}
+
+/// Prints log messages and the `log` records of wgpu and winit to stderr, filtered by `filter`, or
+/// RUST_LOG without it, in the env_logger syntax like `debug` or `warn,rust_raytracing_wgpu=trace`.
+/// With a `chrome_trace` path the spans are also written there as a Chrome trace, which needs the
+/// chrome-trace feature.
+pub fn init_logging(filter: Option<&str>, chrome_trace: Option<&str>) -> LoggingGuard {
+    let filter = match filter {
+        Some(filter) => EnvFilter::try_new(filter).unwrap_or_else(|e| panic!("Invalid log level {}: {}", filter, e)),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER)),
+    };
+    let registry = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_filter(filter));
+
+    #[cfg(feature = "chrome-trace")]
+    {
+        let (chrome, guard) = match chrome_trace {
+            Some(path) => {
+                let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new().file(path).include_args(true).build();
+                (Some(layer), Some(guard))
+            },
+            None => (None, None),
+        };
+        registry.with(chrome).init();
+        LoggingGuard { _chrome: guard }
+    }
+    #[cfg(not(feature = "chrome-trace"))]
+    {
+        registry.init();
+        if chrome_trace.is_some() {
+            tracing::warn!("Chrome traces need the chrome-trace feature, none is written");
+        }
+        LoggingGuard {}
+    }
+}
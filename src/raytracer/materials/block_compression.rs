@@ -0,0 +1,235 @@
+use image::{Rgba, RgbaImage};
+
+// BCn formats store each 4x4 texel block in a fixed number of bytes: 8 for BC1, 16 for BC3
+// and BC7. The GPU decodes them on sampling, so they stay compressed in VRAM.
+const BLOCK_SIZE: u32 = 4;
+
+/// Block compressed formats that textures can be uploaded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// RGB with 1-bit alpha, 4 bits per texel
+    Bc1,
+    /// BC1 color with a separately interpolated alpha, 8 bits per texel
+    Bc3,
+    /// Higher quality RGBA, 8 bits per texel. Only the GPU can decode it.
+    Bc7,
+}
+
+impl BlockFormat {
+    pub fn block_bytes(self) -> usize {
+        match self {
+            BlockFormat::Bc1 => 8,
+            BlockFormat::Bc3 | BlockFormat::Bc7 => 16,
+        }
+    }
+
+    /// sRGB texture format, matching how uncompressed images are uploaded
+    pub fn texture_format(self) -> wgpu::TextureFormat {
+        match self {
+            BlockFormat::Bc1 => wgpu::TextureFormat::Bc1RgbaUnormSrgb,
+            BlockFormat::Bc3 => wgpu::TextureFormat::Bc3RgbaUnormSrgb,
+            BlockFormat::Bc7 => wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+        }
+    }
+
+    /// Bytes taken by an image of the given size, partial blocks at the edges count as whole ones
+    pub fn level_bytes(self, width: u32, height: u32) -> usize {
+        blocks(width) as usize * blocks(height) as usize * self.block_bytes()
+    }
+}
+
+/// Blocks needed to cover the given number of texels
+pub fn blocks(texels: u32) -> u32 {
+    texels.max(1).div_ceil(BLOCK_SIZE)
+}
+
+/// Compresses the image to BC3 blocks row by row. Edge blocks of images that aren't a
+/// multiple of 4 in size repeat the last row and column.
+pub fn compress_bc3(image: &RgbaImage) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let mut data = Vec::with_capacity(BlockFormat::Bc3.level_bytes(width, height));
+
+    for block_y in 0..blocks(height) {
+        for block_x in 0..blocks(width) {
+            let mut texels = [[0; 4]; 16];
+            for (i, texel) in texels.iter_mut().enumerate() {
+                let x = (block_x * BLOCK_SIZE + i as u32 % BLOCK_SIZE).min(width - 1);
+                let y = (block_y * BLOCK_SIZE + i as u32 / BLOCK_SIZE).min(height - 1);
+                *texel = image.get_pixel(x, y).0;
+            }
+            data.extend(compress_alpha_block(&texels));
+            data.extend(compress_color_block(&texels));
+        }
+    }
+
+    data
+}
+
+/// Decodes BC1 or BC3 blocks back to an image, for adapters without BC support
+pub fn decompress(format: BlockFormat, data: &[u8], width: u32, height: u32) -> Option<RgbaImage> {
+    if format == BlockFormat::Bc7 || data.len() < format.level_bytes(width, height) {
+        return None;
+    }
+
+    let mut image = RgbaImage::new(width, height);
+    let block_bytes = format.block_bytes();
+    for (i, block) in data.chunks_exact(block_bytes).take((blocks(width) * blocks(height)) as usize).enumerate() {
+        let block_x = i as u32 % blocks(width);
+        let block_y = i as u32 / blocks(width);
+
+        let texels = match format {
+            BlockFormat::Bc1 => decompress_color_block(block, true),
+            _ => {
+                let mut texels = decompress_color_block(&block[8..], false);
+                for (texel, alpha) in texels.iter_mut().zip(decompress_alpha_block(&block[..8])) {
+                    texel[3] = alpha;
+                }
+                texels
+            },
+        };
+
+        for (j, texel) in texels.iter().enumerate() {
+            let x = block_x * BLOCK_SIZE + j as u32 % BLOCK_SIZE;
+            let y = block_y * BLOCK_SIZE + j as u32 / BLOCK_SIZE;
+            if x < width && y < height {
+                image.put_pixel(x, y, Rgba(*texel));
+            }
+        }
+    }
+
+    Some(image)
+}
+
+// Endpoints span the block's bounding box in RGB, pulled in slightly so the interpolated
+// colors land closer to the texels
+fn compress_color_block(texels: &[[u8; 4]; 16]) -> [u8; 8] {
+    let mut min = [255u8; 3];
+    let mut max = [0u8; 3];
+    for texel in texels {
+        for channel in 0..3 {
+            min[channel] = min[channel].min(texel[channel]);
+            max[channel] = max[channel].max(texel[channel]);
+        }
+    }
+    for channel in 0..3 {
+        let inset = (max[channel] - min[channel]) / 16;
+        min[channel] += inset;
+        max[channel] -= inset;
+    }
+
+    let mut color_0 = to_565(max);
+    let mut color_1 = to_565(min);
+    if color_0 < color_1 {
+        std::mem::swap(&mut color_0, &mut color_1);
+    }
+
+    let mut indices = 0u32;
+    if color_0 != color_1 {
+        let palette = color_palette(color_0, color_1, false);
+        for (i, texel) in texels.iter().enumerate() {
+            let index = nearest(&palette, |entry| {
+                (0..3).map(|channel| (entry[channel] as i32 - texel[channel] as i32).pow(2)).sum()
+            });
+            indices |= (index as u32) << (2 * i);
+        }
+    }
+
+    let mut block = [0; 8];
+    block[0..2].copy_from_slice(&color_0.to_le_bytes());
+    block[2..4].copy_from_slice(&color_1.to_le_bytes());
+    block[4..8].copy_from_slice(&indices.to_le_bytes());
+    block
+}
+
+// Endpoints are the smallest and largest alpha, with six levels evenly spaced between them
+fn compress_alpha_block(texels: &[[u8; 4]; 16]) -> [u8; 8] {
+    let alpha_0 = texels.iter().map(|texel| texel[3]).max().unwrap();
+    let alpha_1 = texels.iter().map(|texel| texel[3]).min().unwrap();
+
+    let mut indices = 0u64;
+    if alpha_0 != alpha_1 {
+        let palette = alpha_palette(alpha_0, alpha_1);
+        for (i, texel) in texels.iter().enumerate() {
+            let index = nearest(&palette, |&entry| (entry as i32 - texel[3] as i32).abs());
+            indices |= (index as u64) << (3 * i);
+        }
+    }
+
+    let mut block = [0; 8];
+    block[0] = alpha_0;
+    block[1] = alpha_1;
+    block[2..8].copy_from_slice(&indices.to_le_bytes()[..6]);
+    block
+}
+
+fn decompress_color_block(block: &[u8], allow_transparent: bool) -> [[u8; 4]; 16] {
+    let color_0 = u16::from_le_bytes([block[0], block[1]]);
+    let color_1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let palette = color_palette(color_0, color_1, allow_transparent);
+    std::array::from_fn(|i| palette[(indices >> (2 * i)) as usize & 3])
+}
+
+fn decompress_alpha_block(block: &[u8]) -> [u8; 16] {
+    let palette = alpha_palette(block[0], block[1]);
+    let mut bytes = [0; 8];
+    bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bytes);
+    std::array::from_fn(|i| palette[(indices >> (3 * i)) as usize & 7])
+}
+
+// BC1 blocks whose first color isn't the larger one use the last entry for transparent black,
+// BC3 color blocks always interpolate four colors
+fn color_palette(color_0: u16, color_1: u16, allow_transparent: bool) -> [[u8; 4]; 4] {
+    let a = from_565(color_0);
+    let b = from_565(color_1);
+    let mix = |weight_a: u32, weight_b: u32| {
+        let total = weight_a + weight_b;
+        let channel = |i: usize| ((weight_a * a[i] as u32 + weight_b * b[i] as u32) / total) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+
+    let opaque = |color: [u8; 3]| [color[0], color[1], color[2], 255];
+    if color_0 > color_1 || !allow_transparent {
+        [opaque(a), opaque(b), mix(2, 1), mix(1, 2)]
+    } else {
+        [opaque(a), opaque(b), mix(1, 1), [0; 4]]
+    }
+}
+
+fn alpha_palette(alpha_0: u8, alpha_1: u8) -> [u8; 8] {
+    let (a, b) = (alpha_0 as u32, alpha_1 as u32);
+    if alpha_0 > alpha_1 {
+        std::array::from_fn(|i| match i {
+            0 => alpha_0,
+            1 => alpha_1,
+            _ => (((8 - i as u32) * a + (i as u32 - 1) * b) / 7) as u8,
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => alpha_0,
+            1 => alpha_1,
+            6 => 0,
+            7 => 255,
+            _ => (((6 - i as u32) * a + (i as u32 - 1) * b) / 5) as u8,
+        })
+    }
+}
+
+fn nearest<T>(palette: &[T], distance: impl Fn(&T) -> i32) -> usize {
+    (0..palette.len()).min_by_key(|&i| distance(&palette[i])).unwrap()
+}
+
+fn to_565(color: [u8; 3]) -> u16 {
+    let r = (color[0] as u16 * 31 + 127) / 255;
+    let g = (color[1] as u16 * 63 + 127) / 255;
+    let b = (color[2] as u16 * 31 + 127) / 255;
+    (r << 11) | (g << 5) | b
+}
+
+fn from_565(color: u16) -> [u8; 3] {
+    let r = (color >> 11) & 31;
+    let g = (color >> 5) & 63;
+    let b = color & 31;
+    [((r * 255 + 15) / 31) as u8, ((g * 255 + 31) / 63) as u8, ((b * 255 + 15) / 31) as u8]
+}
@@ -1,14 +1,31 @@
+use std::io;
+
 use image::DynamicImage;
 
+use super::{blocks, generate_mipmaps, mip_level_count, CompressedCubeMap, SkyFaces, TextureFiltering};
+
 pub struct CubeMapMaterial {
-    texture: wgpu::Texture,
+    pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
 
 impl CubeMapMaterial {
-    // Initialize the CubeMapMaterial with device and image data
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, images: Vec<DynamicImage>) -> Self {
+    /// Uploads block compressed faces as they are when the device can sample them and they
+    /// are whole blocks in size, otherwise decodes them first
+    pub fn from_faces(device: &wgpu::Device, queue: &wgpu::Queue, faces: SkyFaces, filtering: TextureFiltering) -> io::Result<Self> {
+        match faces {
+            SkyFaces::Images(images) => Ok(Self::new(device, queue, images, filtering)),
+            SkyFaces::Compressed(cube) if device.features().contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+                && cube.width % 4 == 0 && cube.height % 4 == 0 => {
+                Ok(Self::new_compressed(device, queue, &cube, filtering))
+            },
+            SkyFaces::Compressed(cube) => Ok(Self::new(device, queue, cube.decompress()?, filtering)),
+        }
+    }
+
+    // Initialize the CubeMapMaterial with device and image data, the mip chain is drawn on the GPU
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, images: Vec<DynamicImage>, filtering: TextureFiltering) -> Self {
         assert_eq!(images.len(), 6, "There must be exactly 6 images for a cube map");
         
         // Assuming all images are of the same size
@@ -21,7 +38,7 @@ impl CubeMapMaterial {
                 height: img_height,
                 depth_or_array_layers: 6,
             },
-            mip_level_count: 1,
+            mip_level_count: mip_level_count(img_width, img_height),
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -45,11 +62,11 @@ impl CubeMapMaterial {
                     },
                     aspect: wgpu::TextureAspect::All,
                 },
-                &bytes.as_slice(),
+                bytes.as_slice(),
                 wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some((4 * img_width) as u32),
-                    rows_per_image: Some(img_height as u32),
+                    bytes_per_row: Some(4 * img_width),
+                    rows_per_image: Some(img_height),
                 },
                 wgpu::Extent3d {
                     width: img_width,
@@ -59,29 +76,81 @@ impl CubeMapMaterial {
             );
         }
 
+        generate_mipmaps(device, queue, &texture);
+
+        Self::from_texture(device, texture, filtering)
+    }
+
+    // Block compressed faces keep the format and mip chain of the file they came from
+    fn new_compressed(device: &wgpu::Device, queue: &wgpu::Queue, cube: &CompressedCubeMap, filtering: TextureFiltering) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: cube.width,
+                height: cube.height,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: cube.mip_levels(),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: cube.format.texture_format(),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some("CubeMapTexture"),
+            view_formats: &[],
+        });
+
+        for (i, levels) in cube.faces.iter().enumerate() {
+            for (level, data) in levels.iter().enumerate() {
+                // Copies cover whole blocks, even on levels smaller than one
+                let blocks_wide = blocks(cube.width >> level);
+                let blocks_high = blocks(cube.height >> level);
+
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level: level as u32,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: i as u32,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    data,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(blocks_wide * cube.format.block_bytes() as u32),
+                        rows_per_image: Some(blocks_high),
+                    },
+                    wgpu::Extent3d {
+                        width: 4 * blocks_wide,
+                        height: 4 * blocks_high,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        Self::from_texture(device, texture, filtering)
+    }
+
+    fn from_texture(device: &wgpu::Device, texture: wgpu::Texture, filtering: TextureFiltering) -> Self {
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
-            format: Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+            format: Some(texture.format()),
             dimension: Some(wgpu::TextureViewDimension::Cube),
             aspect: wgpu::TextureAspect::All,
             base_mip_level: 0,
-            mip_level_count: Some(1),
+            mip_level_count: None,
             base_array_layer: 0,
             array_layer_count: Some(6),
             label: Some("Texture View"),
         });
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear, // Adjust based on mipmapping usage
-            lod_min_clamp: 0.0, // Optional: Adjust based on usage
-            lod_max_clamp: std::f32::MAX, // Optional: Adjust based on usage
-            ..Default::default()
-        });
+        let sampler = filtering.create_sampler(device, wgpu::AddressMode::Repeat, wgpu::AddressMode::Repeat);
 
         Self { texture, view, sampler }
     }
+
+    pub fn set_filtering(&mut self, device: &wgpu::Device, filtering: TextureFiltering) {
+        self.sampler = filtering.create_sampler(device, wgpu::AddressMode::Repeat, wgpu::AddressMode::Repeat);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,85 @@
+use super::MATERIAL_FLOATS;
+
+/// Index into the scene's material list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub usize);
+
+impl MaterialId {
+    /// Single-sided material every primitive starts with
+    pub const DEFAULT: MaterialId = MaterialId(0);
+    /// Two-sided material used for thin geometry like squares
+    pub const TWO_SIDED: MaterialId = MaterialId(1);
+}
+
+// Bits of the flags word in the material buffer
+const CULL_BACKFACES: u32 = 1;
+const TWO_SIDED: u32 = 2;
+const DIFFUSE: u32 = 4;
+const HAIR: u32 = 8;
+
+/// Thin transparent layer on top of a surface, like soap or oil, whose reflections
+/// interfere and tint the surface with angle-dependent colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThinFilm {
+    /// Thickness of the layer in nanometers, a few hundred gives the strongest colors
+    pub thickness: f32,
+    /// Index of refraction of the layer, 1.33 for soapy water
+    pub ior: f32,
+}
+
+/// Surface settings shared by every primitive that references the material
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Material {
+    /// Skip hits on the back of triangles, which is safe and faster for closed meshes
+    pub cull_backfaces: bool,
+    /// Flip the normal towards the ray on back hits so thin geometry shades on both sides
+    pub two_sided: bool,
+    /// Scatter rays in random directions like a matte surface instead of mirroring them
+    pub diffuse: bool,
+    /// Blurs mirror reflections by jittering the reflected ray, 0 is a perfect mirror
+    pub roughness: f32,
+    /// Share of a diffuse material's bounces that reflect like a mirror instead of scattering
+    pub metalness: f32,
+    /// Chance a ray passes straight through the surface as if it missed, 0 is opaque. Unlike glass
+    /// the ray keeps going in the same direction, so stacked see-through layers blend in any order.
+    pub transparency: f32,
+    /// Refracts rays into the surface like glass with this index of refraction, 1.5 for window glass.
+    /// 0 keeps the surface opaque.
+    pub ior: f32,
+    /// Cauchy coefficient in square micrometers by which the index grows towards blue, splitting
+    /// white light into a rainbow in spectral mode. Around 0.004 for crown glass, 0.01 for flint.
+    pub dispersion: f32,
+    /// Scatter light around the fiber like hair: curves reflect into a cone around their
+    /// direction, blurred along it by the roughness
+    pub hair: bool,
+    pub thin_film: Option<ThinFilm>,
+    /// Index of one of the scene's images, as in `Texture::Image`, multiplied into the color of
+    /// triangles and quads. Quads span the whole image; triangles map their corners to (0, 0),
+    /// (1, 0) and (0, 1).
+    pub albedo_texture: Option<usize>,
+    /// Hits where the albedo texture's alpha is below this are ignored and rays pass through,
+    /// cutting foliage cards and fences out of plain quads. 0 keeps every hit.
+    pub alpha_cutoff: f32,
+}
+
+impl Material {
+    pub fn flatten(&self) -> [f32; MATERIAL_FLOATS] {
+        let mut flags = 0;
+        if self.cull_backfaces {
+            flags |= CULL_BACKFACES;
+        }
+        if self.two_sided {
+            flags |= TWO_SIDED;
+        }
+        if self.diffuse {
+            flags |= DIFFUSE;
+        }
+        if self.hair {
+            flags |= HAIR;
+        }
+        // A zero thickness tells the kernel there is no film
+        let film = self.thin_film.map_or([0.0, 1.0], |film| [film.thickness, film.ior]);
+        let albedo_texture = self.albedo_texture.map_or(-1.0, |index| index as f32);
+        [flags as f32, film[0], film[1], self.alpha_cutoff, albedo_texture, self.roughness, self.metalness, self.transparency, self.ior, self.dispersion, 0.0, 0.0]
+    }
+}
@@ -0,0 +1,136 @@
+/// How image textures are filtered when the kernel samples them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextureFiltering {
+    /// Closest texel of the closest mip level, blocky but cheap
+    Nearest,
+    /// Blends the four closest texels of the closest mip level
+    Bilinear,
+    /// Also blends between the two closest mip levels, hiding the seams between them
+    #[default]
+    Trilinear,
+}
+
+impl TextureFiltering {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(TextureFiltering::Nearest),
+            "bilinear" => Some(TextureFiltering::Bilinear),
+            "trilinear" => Some(TextureFiltering::Trilinear),
+            _ => None,
+        }
+    }
+
+    /// The next option, for cycling through them with a key
+    pub fn next(self) -> Self {
+        match self {
+            TextureFiltering::Nearest => TextureFiltering::Bilinear,
+            TextureFiltering::Bilinear => TextureFiltering::Trilinear,
+            TextureFiltering::Trilinear => TextureFiltering::Nearest,
+        }
+    }
+
+    /// Sampler using this filtering, with the given addressing along u and along v and w
+    pub fn create_sampler(self, device: &wgpu::Device, address_mode_u: wgpu::AddressMode, address_mode_vw: wgpu::AddressMode) -> wgpu::Sampler {
+        let (filter, mipmap_filter) = match self {
+            TextureFiltering::Nearest => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest),
+            TextureFiltering::Bilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest),
+            TextureFiltering::Trilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear),
+        };
+
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u,
+            address_mode_v: address_mode_vw,
+            address_mode_w: address_mode_vw,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter,
+            ..Default::default()
+        })
+    }
+}
+
+/// Mip levels in a full chain for a texture of the given size, down to a single texel
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    width.max(height).max(1).ilog2() + 1
+}
+
+/// Fills every mip level below the first of each layer by drawing it from the level above.
+/// The texture needs a renderable format and RENDER_ATTACHMENT usage.
+pub fn generate_mipmaps(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Mipmap Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../../../shaders/mipmap_shader.wgsl").into()),
+    });
+
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Mipmap Pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vert_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "frag_main",
+            targets: &[Some(texture.format().into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    });
+
+    // View of a single level of a single layer
+    let level_view = |layer: u32, level: u32| texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Mipmap Level View"),
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: level,
+        mip_level_count: Some(1),
+        base_array_layer: layer,
+        array_layer_count: Some(1),
+        ..Default::default()
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Mipmap Encoder"),
+    });
+
+    for layer in 0..texture.depth_or_array_layers() {
+        for level in 1..texture.mip_level_count() {
+            let source = level_view(layer, level - 1);
+            let target = level_view(layer, level);
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Mipmap Bind Group"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source),
+                    },
+                ],
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    queue.submit(Some(encoder.finish()));
+}
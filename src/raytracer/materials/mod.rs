@@ -1,3 +1,21 @@
+pub mod block_compression;
 pub mod cube_material;
+pub mod material;
+pub mod mipmaps;
+pub mod sky_source;
+pub mod texture;
+pub mod texture_array;
+pub mod texture_atlas;
+pub mod text_atlas;
 
-pub use cube_material::*;
\ No newline at end of file
+pub use block_compression::*;
+pub use cube_material::*;
+pub use material::*;
+pub use mipmaps::*;
+pub use sky_source::*;
+pub use texture::*;
+pub use texture_array::*;
+pub use texture_atlas::*;
+pub use text_atlas::*;
+
+use super::{Vec3, MATERIAL_FLOATS};
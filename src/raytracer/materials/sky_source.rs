@@ -0,0 +1,270 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use image::io::Reader as ImageReader;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use tracing::info_span;
+
+use super::{decompress, BlockFormat};
+
+/// Where the six faces of the sky cube map come from. Faces are returned in the order
+/// the cube texture expects: +X, -X, +Y, -Y, +Z, -Z.
+#[derive(Debug, Clone)]
+pub enum SkySource {
+    /// One image per face, already in cube texture order
+    Faces([String; 6]),
+    /// A single file: a KTX2 or DDS cube map container, or an image holding all six faces as a
+    /// horizontal or vertical cross (4:3 or 3:4) or a strip in face order (6:1 or 1:6)
+    File(String),
+    /// The same sRGB color on every face, like the placeholder shown while a scene loads
+    Solid([u8; 3]),
+}
+
+impl Default for SkySource {
+    fn default() -> Self {
+        SkySource::Faces([
+            "assets/gfx/sky_right.png".to_string(),
+            "assets/gfx/sky_left.png".to_string(),
+            "assets/gfx/sky_bottom.png".to_string(), // 3 is bottom
+            "assets/gfx/sky_top.png".to_string(),
+            "assets/gfx/sky_back.png".to_string(),
+            "assets/gfx/sky_front.png".to_string(),
+        ])
+    }
+}
+
+/// Faces of a loaded sky, either plain images or blocks to upload as they are
+pub enum SkyFaces {
+    Images(Vec<DynamicImage>),
+    Compressed(CompressedCubeMap),
+}
+
+/// Block compressed cube map read from a KTX2 or DDS file, with the mip chain it was saved with
+pub struct CompressedCubeMap {
+    pub format: BlockFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Blocks of each face's mip levels, indexed by face then level
+    pub faces: Vec<Vec<Vec<u8>>>,
+}
+
+impl CompressedCubeMap {
+    pub fn mip_levels(&self) -> u32 {
+        self.faces[0].len() as u32
+    }
+
+    /// Decodes the first mip level of each face, for adapters that can't sample BC textures
+    pub fn decompress(&self) -> io::Result<Vec<DynamicImage>> {
+        self.faces.iter().map(|levels| {
+            decompress(self.format, &levels[0], self.width, self.height)
+                .map(DynamicImage::ImageRgba8)
+                .ok_or_else(|| invalid("BC7 skies need an adapter with BC texture compression"))
+        }).collect()
+    }
+}
+
+impl SkySource {
+    pub fn load(&self) -> io::Result<SkyFaces> {
+        let _span = info_span!("load_sky", source = ?self).entered();
+        match self {
+            SkySource::Faces(paths) => Ok(SkyFaces::Images(paths.iter().map(|path| open_image(path)).collect::<io::Result<_>>()?)),
+            SkySource::File(path) => {
+                let extension = Path::new(path).extension()
+                    .map(|extension| extension.to_string_lossy().to_lowercase());
+                match extension.as_deref() {
+                    Some("ktx2") => load_ktx2(&fs::read(path)?),
+                    Some("dds") => load_dds(&fs::read(path)?),
+                    _ => Ok(SkyFaces::Images(split_faces(&open_image(path)?)?)),
+                }
+            },
+            SkySource::Solid([r, g, b]) => {
+                let face = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([*r, *g, *b, 255])));
+                Ok(SkyFaces::Images(vec![face; 6]))
+            },
+        }
+    }
+}
+
+fn open_image(path: &str) -> io::Result<DynamicImage> {
+    ImageReader::open(Path::new(path))?
+        .decode()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Cuts the faces out of a cross or strip layout, telling them apart by aspect ratio
+fn split_faces(image: &DynamicImage) -> io::Result<Vec<DynamicImage>> {
+    let (width, height) = image.dimensions();
+    // Cells of each face in the layout, in cube texture order
+    let (face, cells): (u32, [(u32, u32); 6]) = if width * 3 == height * 4 {
+        // Horizontal cross, +Y above and -Y below the +Z face
+        (width / 4, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)])
+    } else if width * 4 == height * 3 {
+        // Vertical cross, -Z hangs below -Y and is stored upside down
+        (width / 3, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)])
+    } else if width == height * 6 {
+        (height, [(0, 0), (1, 0), (2, 0), (3, 0), (4, 0), (5, 0)])
+    } else if height == width * 6 {
+        (width, [(0, 0), (0, 1), (0, 2), (0, 3), (0, 4), (0, 5)])
+    } else {
+        return Err(invalid("sky image is neither a 4:3 or 3:4 cross nor a 6:1 or 1:6 strip"));
+    };
+
+    let vertical_cross = width * 4 == height * 3;
+    Ok(cells.iter().enumerate().map(|(i, &(column, row))| {
+        let face_image = image.crop_imm(column * face, row * face, face, face);
+        if vertical_cross && i == 5 { face_image.rotate180() } else { face_image }
+    }).collect())
+}
+
+// RGBA8 cube maps keep the first mip level of each face, BC1, BC3 and BC7 ones keep every level
+fn load_ktx2(bytes: &[u8]) -> io::Result<SkyFaces> {
+    const IDENTIFIER: &[u8; 12] = b"\xABKTX 20\xBB\r\n\x1A\n";
+    const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+    const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+
+    if bytes.len() < 104 || &bytes[..12] != IDENTIFIER {
+        return Err(invalid("not a KTX2 file"));
+    }
+    let format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?;
+    let faces = read_u32(bytes, 36)?;
+    let level_count = read_u32(bytes, 40)?.max(1);
+    let supercompression = read_u32(bytes, 44)?;
+    if faces != 6 || supercompression != 0 {
+        return Err(invalid("KTX2 file is not a cube map without supercompression"));
+    }
+
+    // The level index follows the header, level 0 comes first. Faces are stored one after
+    // another within each level.
+    if format == VK_FORMAT_R8G8B8A8_UNORM || format == VK_FORMAT_R8G8B8A8_SRGB {
+        let offset = read_u64(bytes, 80)? as usize;
+        let face_bytes = 4 * width as usize * height as usize;
+        return Ok(SkyFaces::Images(faces_from_bytes(bytes, offset, face_bytes, width, height, false)?));
+    }
+    let format = match format {
+        131..=134 => BlockFormat::Bc1,
+        137 | 138 => BlockFormat::Bc3,
+        145 | 146 => BlockFormat::Bc7,
+        _ => return Err(invalid("only RGBA8, BC1, BC3 and BC7 KTX2 cube maps are supported")),
+    };
+
+    let mut cube = CompressedCubeMap { format, width, height, faces: vec![Vec::new(); 6] };
+    for level in 0..level_count {
+        let offset = read_u64(bytes, 80 + 24 * level as usize)? as usize;
+        let face_bytes = format.level_bytes(width >> level, height >> level);
+        for (face, levels) in cube.faces.iter_mut().enumerate() {
+            levels.push(read_blocks(bytes, offset + face * face_bytes, face_bytes)?);
+        }
+    }
+    Ok(SkyFaces::Compressed(cube))
+}
+
+// Uncompressed 32-bit RGBA or BGRA cube maps keep the first mip level of each face,
+// BC1, BC3 and BC7 ones keep every level
+fn load_dds(bytes: &[u8]) -> io::Result<SkyFaces> {
+    const CUBEMAP_ALL_FACES: u32 = 0xFE00;
+    const DXGI_FORMAT_R8G8B8A8_UNORM: u32 = 28;
+    const DXGI_FORMAT_R8G8B8A8_UNORM_SRGB: u32 = 29;
+
+    if bytes.len() < 128 || &bytes[..4] != b"DDS " {
+        return Err(invalid("not a DDS file"));
+    }
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let mip_levels = read_u32(bytes, 28)?.max(1);
+    let four_cc = &bytes[84..88];
+    let caps2 = read_u32(bytes, 112)?;
+    if caps2 & CUBEMAP_ALL_FACES != CUBEMAP_ALL_FACES {
+        return Err(invalid("DDS file is not a cube map with all six faces"));
+    }
+
+    // Block compressed faces are stored with their whole mip chain before the next face
+    let block_format = match four_cc {
+        b"DXT1" => Some(BlockFormat::Bc1),
+        b"DXT5" => Some(BlockFormat::Bc3),
+        b"DX10" => match read_u32(bytes, 128)? {
+            70..=72 => Some(BlockFormat::Bc1),
+            76..=78 => Some(BlockFormat::Bc3),
+            97..=99 => Some(BlockFormat::Bc7),
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(format) = block_format {
+        let mut offset = if four_cc == b"DX10" { 148 } else { 128 };
+        let mut cube = CompressedCubeMap { format, width, height, faces: vec![Vec::new(); 6] };
+        for levels in cube.faces.iter_mut() {
+            for level in 0..mip_levels {
+                let level_bytes = format.level_bytes(width >> level, height >> level);
+                levels.push(read_blocks(bytes, offset, level_bytes)?);
+                offset += level_bytes;
+            }
+        }
+        return Ok(SkyFaces::Compressed(cube));
+    }
+
+    let (offset, bgra) = if four_cc == b"DX10" {
+        let format = read_u32(bytes, 128)?;
+        if format != DXGI_FORMAT_R8G8B8A8_UNORM && format != DXGI_FORMAT_R8G8B8A8_UNORM_SRGB {
+            return Err(invalid("only RGBA8, BC1, BC3 and BC7 DDS cube maps are supported"));
+        }
+        (148, false)
+    } else {
+        let bit_count = read_u32(bytes, 88)?;
+        let red_mask = read_u32(bytes, 92)?;
+        if four_cc != [0; 4] || bit_count != 32 || (red_mask != 0xFF && red_mask != 0xFF0000) {
+            return Err(invalid("only 32-bit, DXT1 and DXT5 DDS cube maps are supported"));
+        }
+        (128, red_mask == 0xFF0000)
+    };
+
+    // Each face is stored with its whole mip chain before the next face
+    let face_bytes: usize = (0..mip_levels)
+        .map(|level| 4 * (width >> level).max(1) as usize * (height >> level).max(1) as usize)
+        .sum();
+    let faces = faces_from_bytes(bytes, offset, face_bytes, width, height, bgra)?;
+    Ok(SkyFaces::Images(faces))
+}
+
+fn read_blocks(bytes: &[u8], offset: usize, length: usize) -> io::Result<Vec<u8>> {
+    bytes.get(offset..offset + length)
+        .map(|blocks| blocks.to_vec())
+        .ok_or_else(|| invalid("cube map file is truncated"))
+}
+
+// Reads six faces spaced face_bytes apart, keeping the first width x height texels of each
+fn faces_from_bytes(bytes: &[u8], offset: usize, face_bytes: usize, width: u32, height: u32, bgra: bool) -> io::Result<Vec<DynamicImage>> {
+    let level_bytes = 4 * width as usize * height as usize;
+    (0..6).map(|face| {
+        let start = offset + face * face_bytes;
+        let mut texels = bytes.get(start..start + level_bytes)
+            .ok_or_else(|| invalid("cube map file is truncated"))?
+            .to_vec();
+        if bgra {
+            for texel in texels.chunks_exact_mut(4) {
+                texel.swap(0, 2);
+            }
+        }
+        let image = RgbaImage::from_raw(width, height, texels)
+            .ok_or_else(|| invalid("cube map face has the wrong size"))?;
+        Ok(DynamicImage::ImageRgba8(image))
+    }).collect()
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> io::Result<u32> {
+    bytes.get(offset..offset + 4)
+        .map(|slice| u32::from_le_bytes(slice.try_into().unwrap()))
+        .ok_or_else(|| invalid("cube map header is truncated"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> io::Result<u64> {
+    bytes.get(offset..offset + 8)
+        .map(|slice| u64::from_le_bytes(slice.try_into().unwrap()))
+        .ok_or_else(|| invalid("cube map header is truncated"))
+}
@@ -0,0 +1,23 @@
+use super::Vec3;
+
+/// How a surface's color varies across its UV coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Texture {
+    /// Just the object color
+    Solid,
+    /// One of the scene's images, by its index in `image_paths`, tinted by the object color
+    Image(usize),
+    /// Alternating squares of the object color and `color`, `scale` squares per unit of UV
+    Checker { color: Vec3, scale: f32 },
+}
+
+impl Texture {
+    // Kind, image index, secondary color and scale as laid out in the object buffer
+    pub fn flatten(&self) -> [f32; 6] {
+        match *self {
+            Texture::Solid => [0.0; 6],
+            Texture::Image(index) => [1.0, index as f32, 0.0, 0.0, 0.0, 0.0],
+            Texture::Checker { color, scale } => [2.0, 0.0, color.0, color.1, color.2, scale],
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use image::{imageops::FilterType, DynamicImage};
+
+use super::{blocks, compress_bc3, generate_mipmaps, mip_level_count, pack_textures, BlockFormat, TextureFiltering, TextureRegion};
+
+// Every layer has this size, large images are resampled to fill one and small ones packed together
+pub const LAYER_WIDTH: u32 = 1024;
+pub const LAYER_HEIGHT: u32 = 512;
+
+/// Byte size of one entry of the texture lookup table
+pub const TEXTURE_REGION_STRIDE: u64 = 32;
+
+/// Image textures for scene objects, packed into the layers of a single 2D array texture.
+/// Layers are BC3 compressed when the device supports it. Objects and materials refer to
+/// textures by index into the lookup table, which says where in the array each one is.
+pub struct TextureArrayMaterial {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub regions: Vec<TextureRegion>,
+    pub region_buffer: wgpu::Buffer,
+    pub image_count: usize,
+}
+
+impl TextureArrayMaterial {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, images: Vec<DynamicImage>, filtering: TextureFiltering) -> Self {
+        let image_count = images.len();
+        let compressed = device.features().contains(wgpu::Features::TEXTURE_COMPRESSION_BC);
+        let mip_levels = mip_level_count(LAYER_WIDTH, LAYER_HEIGHT);
+        // An empty array can't be bound, so scenes without images get a single white layer
+        let (layers, regions) = if images.is_empty() {
+            let white = image::RgbaImage::from_pixel(LAYER_WIDTH, LAYER_HEIGHT, image::Rgba([255; 4]));
+            (vec![white], vec![TextureRegion::whole_layer(0)])
+        } else {
+            pack_textures(&images)
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: LAYER_WIDTH,
+                height: LAYER_HEIGHT,
+                depth_or_array_layers: layers.len() as u32,
+            },
+            mip_level_count: mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: if compressed { BlockFormat::Bc3.texture_format() } else { wgpu::TextureFormat::Rgba8UnormSrgb },
+            usage: if compressed {
+                wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST
+            } else {
+                wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::RENDER_ATTACHMENT
+            },
+            label: Some("Texture Array"),
+            view_formats: &[],
+        });
+
+        for (i, image) in layers.into_iter().enumerate() {
+            let mut rgba = image;
+
+            // Compressed layers have each mip level filtered down from the one above it before
+            // encoding, uncompressed ones only upload the first level and draw the rest on the GPU
+            let upload_levels = if compressed { mip_levels } else { 1 };
+            for level in 0..upload_levels {
+                let width = (LAYER_WIDTH >> level).max(1);
+                let height = (LAYER_HEIGHT >> level).max(1);
+                if level > 0 {
+                    rgba = image::imageops::resize(&rgba, width, height, FilterType::Triangle);
+                }
+
+                // Compressed copies cover whole blocks, even on levels smaller than one
+                let (data, bytes_per_row, rows, extent) = if compressed {
+                    let (blocks_wide, blocks_high) = (blocks(width), blocks(height));
+                    let bytes_per_row = blocks_wide * BlockFormat::Bc3.block_bytes() as u32;
+                    (compress_bc3(&rgba), bytes_per_row, blocks_high, (4 * blocks_wide, 4 * blocks_high))
+                } else {
+                    (rgba.as_raw().clone(), 4 * width, height, (width, height))
+                };
+
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level: level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: i as u32, // One layer per image
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &data,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(bytes_per_row),
+                        rows_per_image: Some(rows),
+                    },
+                    wgpu::Extent3d {
+                        width: extent.0,
+                        height: extent.1,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        if !compressed {
+            generate_mipmaps(device, queue, &texture);
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Texture Array View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let sampler = filtering.create_sampler(device, wgpu::AddressMode::Repeat, wgpu::AddressMode::ClampToEdge);
+
+        let region_data: Vec<f32> = regions.iter().flat_map(|region| region.flatten()).collect();
+        let region_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Texture Region Buffer"),
+            size: TEXTURE_REGION_STRIDE * regions.len() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&region_buffer, 0, bytemuck::cast_slice(&region_data));
+
+        Self { texture, view, sampler, regions, region_buffer, image_count }
+    }
+
+    pub fn set_filtering(&mut self, device: &wgpu::Device, filtering: TextureFiltering) {
+        self.sampler = filtering.create_sampler(device, wgpu::AddressMode::Repeat, wgpu::AddressMode::ClampToEdge);
+    }
+}
@@ -0,0 +1,96 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView, RgbaImage};
+
+use super::{LAYER_HEIGHT, LAYER_WIDTH};
+
+// Images small enough to share a layer are packed side by side in rows ("shelves"), each
+// surrounded by a border repeating its edge texels. Sampling a few mip levels down still
+// stays within the border instead of bleeding into the neighbours.
+const GUTTER: u32 = 4;
+const MAX_PACKED_WIDTH: u32 = LAYER_WIDTH / 2 - 2 * GUTTER;
+const MAX_PACKED_HEIGHT: u32 = LAYER_HEIGHT / 2 - 2 * GUTTER;
+
+/// Where one texture ended up in the texture array, in the layout of the kernel's lookup table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureRegion {
+    pub layer: u32,
+    /// UV of the region's top left corner within the layer
+    pub offset: [f32; 2],
+    /// UV size of the region, 1 when the texture fills the whole layer
+    pub scale: [f32; 2],
+    /// Highest mip level that doesn't mix in texels from outside the region
+    pub max_lod: f32,
+}
+
+impl TextureRegion {
+    pub fn whole_layer(layer: u32) -> Self {
+        Self { layer, offset: [0.0; 2], scale: [1.0; 2], max_lod: f32::MAX }
+    }
+
+    pub fn flatten(&self) -> [f32; 8] {
+        [self.offset[0], self.offset[1], self.scale[0], self.scale[1], self.layer as f32, self.max_lod, 0.0, 0.0]
+    }
+}
+
+/// Whether `pack_textures` packs the image together with others instead of giving it a layer
+pub fn is_packed(image: &DynamicImage) -> bool {
+    let (width, height) = image.dimensions();
+    width <= MAX_PACKED_WIDTH && height <= MAX_PACKED_HEIGHT
+}
+
+/// Arranges the images into LAYER_WIDTH x LAYER_HEIGHT layers, returning the layers and where
+/// each image went. Large images are stretched over a layer of their own, small ones are
+/// packed together at their own size.
+pub fn pack_textures(images: &[DynamicImage]) -> (Vec<RgbaImage>, Vec<TextureRegion>) {
+    let mut layers = Vec::new();
+    let mut regions = vec![TextureRegion::whole_layer(0); images.len()];
+
+    let (small, large): (Vec<usize>, Vec<usize>) = (0..images.len()).partition(|&i| is_packed(&images[i]));
+
+    for i in large {
+        regions[i] = TextureRegion::whole_layer(layers.len() as u32);
+        layers.push(images[i].resize_exact(LAYER_WIDTH, LAYER_HEIGHT, FilterType::Triangle).to_rgba8());
+    }
+
+    // Tallest first keeps the shelves evenly filled
+    let mut small = small;
+    small.sort_by_key(|&i| std::cmp::Reverse(images[i].height()));
+
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    let mut layer: Option<RgbaImage> = None;
+    for i in small {
+        let image = images[i].to_rgba8();
+        let (width, height) = image.dimensions();
+        let (padded_width, padded_height) = (width + 2 * GUTTER, height + 2 * GUTTER);
+
+        if x + padded_width > LAYER_WIDTH {
+            (x, y, shelf_height) = (0, y + shelf_height, 0);
+        }
+        if layer.is_none() || y + padded_height > LAYER_HEIGHT {
+            layers.extend(layer.take());
+            layer = Some(RgbaImage::new(LAYER_WIDTH, LAYER_HEIGHT));
+            (x, y, shelf_height) = (0, 0, 0);
+        }
+
+        // Copy the image with its border, clamping to the nearest edge texel
+        let target = layer.as_mut().unwrap();
+        for dy in 0..padded_height {
+            for dx in 0..padded_width {
+                let source_x = dx.saturating_sub(GUTTER).min(width - 1);
+                let source_y = dy.saturating_sub(GUTTER).min(height - 1);
+                target.put_pixel(x + dx, y + dy, *image.get_pixel(source_x, source_y));
+            }
+        }
+
+        regions[i] = TextureRegion {
+            layer: layers.len() as u32,
+            offset: [(x + GUTTER) as f32 / LAYER_WIDTH as f32, (y + GUTTER) as f32 / LAYER_HEIGHT as f32],
+            scale: [width as f32 / LAYER_WIDTH as f32, height as f32 / LAYER_HEIGHT as f32],
+            max_lod: GUTTER.ilog2() as f32,
+        };
+        x += padded_width;
+        shelf_height = shelf_height.max(padded_height);
+    }
+    layers.extend(layer);
+
+    (layers, regions)
+}
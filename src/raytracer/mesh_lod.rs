@@ -0,0 +1,40 @@
+use super::{ObjectId, Triangle, Vec3};
+
+/// Distance from the camera in radii of a mesh's bounds up to which it is shown in full detail
+pub const LOD_DISTANCE: f32 = 8.0;
+
+/// A mesh shown with fewer triangles the further it is from the camera, so heavy scans stay
+/// interactive. Levels are swapped in whole like the frames of a mesh sequence, so they show
+/// where the mesh was when they were made.
+pub struct MeshLod {
+    /// The object the levels are shown on
+    pub id: ObjectId,
+    /// The full mesh, then simplified levels from `ObjMesh::levels_of_detail`
+    pub levels: Vec<Vec<Triangle>>,
+    /// Level the object holds now
+    pub shown: usize,
+    center: Vec3,
+    radius: f32,
+}
+
+impl MeshLod {
+    pub fn new(id: ObjectId, levels: Vec<Vec<Triangle>>) -> Self {
+        assert!(!levels.is_empty(), "Meshes with levels of detail need at least the full mesh");
+        let (min, max) = levels[0].iter().flat_map(|triangle| triangle.corners).fold(
+            (Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY), Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)),
+            |(min, max), corner| (min.min(corner), max.max(corner)),
+        );
+        let (center, radius) = if levels[0].is_empty() { (Vec3(0.0, 0.0, 0.0), 0.0) } else { ((min + max) * 0.5, (max - min).magnitude() * 0.5) };
+        Self { id, levels, shown: 0, center, radius }
+    }
+
+    /// Level to show seen from `eye`: the full mesh up to LOD_DISTANCE radii away, then one
+    /// level more every time the distance doubles
+    pub fn level_for(&self, eye: Vec3) -> usize {
+        let distance = (eye - self.center).magnitude() / (self.radius * LOD_DISTANCE).max(f32::MIN_POSITIVE);
+        if distance < 1.0 {
+            return 0;
+        }
+        (distance.log2() as usize + 1).min(self.levels.len() - 1)
+    }
+}
@@ -0,0 +1,51 @@
+use std::fs;
+use std::io;
+use std::time::Instant;
+
+use super::{ObjMesh, ObjectId, Triangle, Vec3};
+
+/// A mesh animated by swapping in the triangles of one file per frame, like the cached output
+/// of a simulation. Every frame is read up front so playback doesn't wait on the disk.
+pub struct MeshSequence {
+    /// The object the frames are shown on
+    pub id: ObjectId,
+    pub frames: Vec<Vec<Triangle>>,
+    pub fps: f32,
+    /// Frame the object holds now
+    pub shown: usize,
+    started: Option<Instant>, // Playback starts on the first update rather than when loaded
+}
+
+impl MeshSequence {
+    /// Reads every OBJ, STL and PLY file in the folder as one frame, in order of file name
+    pub fn load(folder: &str) -> io::Result<Vec<Vec<Triangle>>> {
+        let mut paths: Vec<String> = fs::read_dir(folder)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+                matches!(extension.as_deref(), Some("obj" | "stl" | "ply"))
+            })
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        if paths.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no mesh files in {}", folder)));
+        }
+        paths.sort();
+
+        paths.iter()
+            .map(|path| ObjMesh::load(Vec3(1.0, 1.0, 1.0), path).map(|mesh| mesh.triangles))
+            .collect()
+    }
+
+    pub fn new(id: ObjectId, frames: Vec<Vec<Triangle>>, fps: f32) -> Self {
+        assert!(fps > 0.0, "Mesh sequences need a positive frame rate");
+        Self { id, frames, fps, shown: 0, started: None }
+    }
+
+    /// Frame that should be shown by now, looping back to the first after the last
+    pub fn current_frame(&mut self) -> usize {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        (started.elapsed().as_secs_f32() * self.fps) as usize % self.frames.len()
+    }
+}
@@ -4,10 +4,89 @@ pub mod shapes;
 pub mod materials;
 pub mod renderer;
 pub mod node;
+pub mod handles;
+pub mod history;
+pub mod mesh_sequence;
+pub mod mesh_lod;
+pub mod camera_rig;
+pub mod bvh_cache;
+pub mod stats;
+pub mod loading;
+pub mod workgroup_tuning;
+pub mod adapters;
+pub mod cpu_renderer;
+pub mod frame_graph;
+pub mod ray_stats;
+pub mod file_watcher;
+pub mod console;
+pub mod render_worker;
+pub mod remote_view;
+pub mod benchmark;
+pub mod frame_times;
+pub mod logging;
+pub mod gpu_layout;
+pub mod exposure;
+pub mod post_process;
+pub mod frame_fade;
+pub mod bake;
+pub mod probes;
+pub mod raster;
+pub mod checkpoint;
+pub mod batch;
+pub mod transform;
+pub mod instancing;
+pub mod replay;
+#[cfg(feature = "denoise")]
+pub mod denoise;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+#[cfg(feature = "editor")]
+pub mod editor;
+#[cfg(feature = "editor")]
+pub mod gizmo;
 
 pub use camera::*;
 pub use scene::*;
 pub use shapes::*;
 pub use materials::*;
 pub use renderer::*;
-pub use node::*;
\ No newline at end of file
+pub use node::*;
+pub use handles::*;
+pub use history::*;
+pub use mesh_sequence::*;
+pub use mesh_lod::*;
+pub use camera_rig::*;
+pub use stats::*;
+pub use loading::*;
+pub use workgroup_tuning::*;
+pub use adapters::*;
+pub use cpu_renderer::*;
+pub use frame_graph::*;
+pub use ray_stats::*;
+pub use file_watcher::*;
+pub use console::*;
+pub use render_worker::*;
+pub use remote_view::*;
+pub use benchmark::*;
+pub use frame_times::*;
+pub use logging::*;
+pub use gpu_layout::*;
+pub use exposure::*;
+pub use post_process::*;
+pub use frame_fade::*;
+pub use bake::*;
+pub use probes::*;
+pub use raster::*;
+pub use checkpoint::*;
+pub use batch::*;
+pub use transform::*;
+pub use instancing::*;
+pub use replay::*;
+#[cfg(feature = "denoise")]
+pub use denoise::*;
+#[cfg(feature = "scripting")]
+pub use scripting::*;
+#[cfg(feature = "editor")]
+pub use editor::*;
+#[cfg(feature = "editor")]
+pub use gizmo::*;
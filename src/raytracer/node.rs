@@ -6,16 +6,58 @@ pub struct Node {
     pub left_child: i32,
     pub max_corner: Vec3,
     pub object_count: usize,
+    pub split_axis: usize, // The left child holds the objects lower along this axis
 }
 
-impl Node {
-    pub fn default() -> Self {
+impl Default for Node {
+    fn default() -> Self {
         Node {
             // Use very large/small numbers to indicate an "empty" bounding volume
             min_corner: Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY),
             max_corner: Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
             left_child: -1, // Using -1 to indicate "no child"
             object_count: 0,
+            split_axis: 0,
         }
     }
 }
+
+impl Node {
+    pub fn surface_area(&self) -> f32 {
+        surface_area(self.min_corner, self.max_corner)
+    }
+}
+
+/// An object's bounds while the BVH is built. Spatial splits clip these bounds,
+/// so an object straddling a split plane is referenced from both sides.
+#[derive(Debug, Clone, Copy)]
+pub struct BvhReference {
+    pub object: usize,
+    pub min_corner: Vec3,
+    pub max_corner: Vec3,
+}
+
+impl BvhReference {
+    pub fn centroid(&self) -> Vec3 {
+        (self.min_corner + self.max_corner) * 0.5
+    }
+}
+
+/// Box enclosing every reference, inverted (min above max) when there are none
+pub fn reference_bounds(references: &[BvhReference]) -> (Vec3, Vec3) {
+    let mut min_corner = Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max_corner = Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for reference in references {
+        min_corner = min_corner.min(reference.min_corner);
+        max_corner = max_corner.max(reference.max_corner);
+    }
+    (min_corner, max_corner)
+}
+
+pub fn surface_area(min_corner: Vec3, max_corner: Vec3) -> f32 {
+    let extent = max_corner - min_corner;
+    if extent.0 < 0.0 || extent.1 < 0.0 || extent.2 < 0.0 {
+        return 0.0; // Empty box
+    }
+    2.0 * (extent.0 * extent.1 + extent.1 * extent.2 + extent.2 * extent.0)
+}
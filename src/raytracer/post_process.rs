@@ -0,0 +1,43 @@
+/// Bytes of the screen shader's post-process uniform: the three intensities and the grain's seed,
+/// padded to two vectors
+pub const POST_PROCESS_UNIFORM_SIZE: u64 = 32;
+
+/// Stylization applied as the color buffer is drawn to the window, after the exposure, for nicer
+/// looking demos. Captures keep the radiance as traced, without any of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostProcess {
+    /// Draws the view with the effects below, each of which is off at 0
+    pub enabled: bool,
+    /// Pixels the red and blue channels are pulled apart by at the edges of the view, like a
+    /// cheap lens does
+    pub chromatic_aberration: f32,
+    /// How much the corners darken, 1 turning them black
+    pub vignette: f32,
+    /// Strength of the film grain, noise that changes every frame
+    pub grain: f32,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chromatic_aberration: 2.0,
+            vignette: 0.4,
+            grain: 0.04,
+        }
+    }
+}
+
+impl PostProcess {
+    /// The effects laid out like the screen shader's uniform, all zero while disabled. The seed
+    /// picks the grain's noise, so it should change every frame.
+    pub fn uniform_data(&self, seed: u32) -> [f32; POST_PROCESS_UNIFORM_SIZE as usize / 4] {
+        let intensities = if self.enabled {
+            [self.chromatic_aberration.max(0.0), self.vignette.clamp(0.0, 1.0), self.grain.max(0.0)]
+        } else {
+            [0.0; 3]
+        };
+        // The seed is read back as a u32 by the shader
+        [intensities[0], intensities[1], intensities[2], f32::from_bits(seed), 0.0, 0.0, 0.0, 0.0]
+    }
+}
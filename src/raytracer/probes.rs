@@ -0,0 +1,92 @@
+use std::f32::consts::PI;
+
+use super::Vec3;
+
+/// Texels of the probe atlas each probe takes, one per spherical harmonics coefficient with its
+/// red, green and blue. The first texel's alpha counts the probe's updates.
+pub const PROBE_TEXELS: u32 = 4;
+/// Probes the probe pass updates per workgroup, matches probe_main's workgroup size
+pub const PROBE_WORKGROUP_SIZE: u32 = 64;
+/// Most probes along each axis, so the atlas stays within the texture sizes every device has
+pub const MAX_PROBES_PER_AXIS: u32 = 64;
+
+// Constant and linear spherical harmonics basis functions, without the direction for the latter
+const SH_CONSTANT: f32 = 0.282095;
+const SH_LINEAR: f32 = 0.488603;
+
+/// Regular grid of irradiance probes over a box, one in the middle of each cell. Each probe keeps
+/// the light arriving from every direction as linear spherical harmonics, updated by a few paths
+/// per frame, and matte surfaces blend the probes around them instead of tracing further bounces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbeGrid {
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Probes along x, y and z, at least 1 each
+    pub counts: [u32; 3],
+}
+
+impl ProbeGrid {
+    /// Grid over the box with probes about `spacing` apart, further where an axis would need
+    /// more than MAX_PROBES_PER_AXIS
+    pub fn fit(min: Vec3, max: Vec3, spacing: f32) -> Self {
+        assert!(spacing > 0.0, "Probe spacing has to be positive");
+        let extent = max - min;
+        let count = |length: f32| ((length / spacing).ceil() as u32).clamp(1, MAX_PROBES_PER_AXIS);
+        Self { min, max, counts: [count(extent.0), count(extent.1), count(extent.2)] }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.counts.iter().product()
+    }
+
+    /// Distance between neighbouring probes along each axis
+    pub fn spacing(&self) -> Vec3 {
+        let extent = self.max - self.min;
+        Vec3(extent.0 / self.counts[0] as f32, extent.1 / self.counts[1] as f32, extent.2 / self.counts[2] as f32)
+    }
+
+    /// Where a probe sits, probes being numbered along x first, then y, then z
+    pub fn position(&self, index: u32) -> Vec3 {
+        let cell = [index % self.counts[0], index / self.counts[0] % self.counts[1], index / (self.counts[0] * self.counts[1])];
+        let spacing = self.spacing();
+        self.min + Vec3((cell[0] as f32 + 0.5) * spacing.0, (cell[1] as f32 + 0.5) * spacing.1, (cell[2] as f32 + 0.5) * spacing.2)
+    }
+
+    /// Width and height of the atlas: a probe's texels side by side along x, and a row per y and z
+    pub fn atlas_size(&self) -> (u32, u32) {
+        (self.counts[0] * PROBE_TEXELS, self.counts[1] * self.counts[2])
+    }
+
+    /// The scene uniform's probe fields: the first probe, the spacing and the counts, each
+    /// padded to four floats
+    pub fn uniform_data(&self) -> [f32; 12] {
+        let (first, spacing) = (self.position(0), self.spacing());
+        [
+            first.0, first.1, first.2, 0.0,
+            spacing.0, spacing.1, spacing.2, 0.0,
+            self.counts[0] as f32, self.counts[1] as f32, self.counts[2] as f32, 0.0,
+        ]
+    }
+}
+
+/// Linear spherical harmonics of the light arriving at a point, from samples of the radiance in
+/// directions spread evenly over the sphere. Mirrors the projection in probe_main.
+pub fn project_sh(samples: &[(Vec3, Vec3)]) -> [Vec3; 4] {
+    let mut coefficients = [Vec3(0.0, 0.0, 0.0); 4];
+    for &(direction, radiance) in samples {
+        coefficients[0] += radiance * SH_CONSTANT;
+        coefficients[1] += radiance * (SH_LINEAR * direction.0);
+        coefficients[2] += radiance * (SH_LINEAR * direction.1);
+        coefficients[3] += radiance * (SH_LINEAR * direction.2);
+    }
+    coefficients.map(|coefficient| coefficient * (4.0 * PI / samples.len().max(1) as f32))
+}
+
+/// Light a white matte surface facing `normal` reflects under the light of the coefficients, the
+/// irradiance divided by pi. Mirrors `probe_irradiance` in the kernel.
+pub fn sh_irradiance(coefficients: &[Vec3; 4], normal: Vec3) -> Vec3 {
+    // The cosine lobe keeps all of the constant band and two thirds of the linear one
+    let linear = coefficients[1] * normal.0 + coefficients[2] * normal.1 + coefficients[3] * normal.2;
+    let irradiance = coefficients[0] * SH_CONSTANT + linear * (SH_LINEAR * 2.0 / 3.0);
+    Vec3(irradiance.0.max(0.0), irradiance.1.max(0.0), irradiance.2.max(0.0))
+}
@@ -0,0 +1,42 @@
+use super::{Camera, Projection};
+
+/// Bytes of the raster shader's uniform: the clip matrix followed by the render mask, padded to
+/// a multiple of 16
+pub const RASTER_UNIFORM_SIZE: u64 = 80;
+/// Closest to the camera a triangle is drawn, in distances of the image plane. The raster depth
+/// is reversed, near over distance, so this costs no precision further away.
+pub const RASTER_NEAR: f32 = 1e-5;
+
+/// The raster shader's uniform for a camera: the matrix taking world positions to clip space,
+/// column by column, then the render mask. Pixel (x, y) of the raster target is the kernel's
+/// pixel (x, y), whose ray goes through uv ((x + 0.5) / width, (y + 0.5) / height) of the image
+/// plane, so the first row counts from the lower left corner. Only perspective cameras can be
+/// rasterized, the other projections get None.
+pub fn raster_uniform_data(camera: &Camera) -> Option<[f32; RASTER_UNIFORM_SIZE as usize / 4]> {
+    if camera.projection() != Projection::Perspective {
+        return None;
+    }
+    // w is the distance along the image plane's normal, 1 on the plane itself
+    let to_plane = camera.lower_left_corner - camera.origin;
+    let normal = camera.horizontal.cross(camera.vertical);
+    let w = normal * (1.0 / to_plane.dot(normal));
+    // uv times w, which stays linear in the position
+    let u = camera.horizontal * (1.0 / camera.horizontal.dot(camera.horizontal));
+    let v = camera.vertical * (1.0 / camera.vertical.dot(camera.vertical));
+    let uw = u - w * to_plane.dot(u);
+    let vw = v - w * to_plane.dot(v);
+
+    // Normalized device x runs with u and y against v, and z is the near distance over w
+    let rows = [uw * 2.0 - w, w - vw * 2.0];
+    let mut data = [0.0; RASTER_UNIFORM_SIZE as usize / 4];
+    for (i, row) in rows.into_iter().enumerate() {
+        data[i] = row.0;
+        data[4 + i] = row.1;
+        data[8 + i] = row.2;
+        data[12 + i] = -row.dot(camera.origin);
+    }
+    data[14] = RASTER_NEAR;
+    (data[3], data[7], data[11], data[15]) = (w.0, w.1, w.2, -w.dot(camera.origin));
+    data[16] = f32::from_bits(camera.render_mask); // Read back as a u32 by the shader
+    Some(data)
+}
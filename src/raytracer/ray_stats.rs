@@ -0,0 +1,41 @@
+/// Counters the kernel adds to, each a 64-bit count kept as a low and a high word. Matches
+/// STAT_COUNTERS in the kernel.
+pub const STAT_COUNTERS: usize = 5;
+
+/// Bytes of the stats buffer
+pub const STATS_BUFFER_SIZE: u64 = 8 * STAT_COUNTERS as u64;
+
+/// Work the kernel did per second, from the counters it adds to as it traces
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RayStats {
+    /// Rays from the camera, one per pixel and sample
+    pub primary_rays: f64,
+    /// Rays bounced off surfaces and photons sent from the sky
+    pub secondary_rays: f64,
+    /// Rays testing whether a light vertex is visible
+    pub shadow_rays: f64,
+    pub node_visits: f64,
+    pub triangle_tests: f64,
+}
+
+impl RayStats {
+    /// Rates of the counters read back from the stats buffer, counted over the given seconds
+    pub fn from_counters(words: &[u32], seconds: f64) -> Self {
+        let rate = |counter: usize| {
+            let count = words[2 * counter] as u64 | (words[2 * counter + 1] as u64) << 32;
+            count as f64 / seconds
+        };
+        Self {
+            primary_rays: rate(0),
+            secondary_rays: rate(1),
+            shadow_rays: rate(2),
+            node_visits: rate(3),
+            triangle_tests: rate(4),
+        }
+    }
+
+    /// Rays of every kind, in millions per second
+    pub fn mrays_per_second(&self) -> f64 {
+        (self.primary_rays + self.secondary_rays + self.shadow_rays) / 1e6
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+
+use image::codecs::jpeg::JpegEncoder;
+use winit::keyboard::KeyCode;
+
+use super::renderer::linear_to_srgb;
+
+// Quality frames are encoded at, out of 100
+const JPEG_QUALITY: u8 = 80;
+// Request bodies are single input lines, anything longer is cut off
+const MAX_BODY_SIZE: u64 = 256;
+// The request line and headers together, and how many headers, before a request is refused
+const MAX_HEADER_SIZE: u64 = 8192;
+const MAX_HEADERS: usize = 64;
+// Separates the JPEGs of the stream
+const BOUNDARY: &str = "frame";
+
+// Shows the stream and posts keys and mouse drags back as lines of `parse_remote_input`
+const VIEWER_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<title>Ray Tracer</title>
+<style>body { margin: 0; background: #000; } img { display: block; margin: auto; max-width: 100vw; max-height: 100vh; cursor: grab; }</style>
+</head>
+<body>
+<img id="view" src="/stream" draggable="false">
+<script>
+const send = line => fetch("/input", { method: "POST", body: line });
+let dragging = false;
+addEventListener("keydown", e => { if (!e.repeat) send("down " + e.code); e.preventDefault(); });
+addEventListener("keyup", e => send("up " + e.code));
+addEventListener("blur", () => send("release"));
+view.addEventListener("mousedown", e => { dragging = true; e.preventDefault(); });
+addEventListener("mouseup", () => dragging = false);
+addEventListener("mousemove", e => { if (dragging) send("look " + e.movementX + " " + e.movementY); });
+</script>
+</body>
+</html>
+"#;
+
+/// Keys and mouse movement sent back by a browser viewing the stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemoteInput {
+    KeyDown(KeyCode),
+    KeyUp(KeyCode),
+    /// Lets go of every key, sent when the page loses focus so none stay held
+    Release,
+    /// A drag across the view, in pixels
+    Look { dx: f32, dy: f32 },
+}
+
+/// Reads an input line the viewer page posts. Keys are named like the browser's `KeyboardEvent.code`,
+/// which matches winit's key codes. Keys the scene doesn't use read as None.
+pub fn parse_remote_input(line: &str) -> Option<RemoteInput> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["down", key] => key_code(key).map(RemoteInput::KeyDown),
+        ["up", key] => key_code(key).map(RemoteInput::KeyUp),
+        ["release"] => Some(RemoteInput::Release),
+        ["look", dx, dy] => {
+            let (dx, dy) = (dx.parse::<f32>().ok()?, dy.parse::<f32>().ok()?);
+            (dx.is_finite() && dy.is_finite()).then_some(RemoteInput::Look { dx, dy })
+        },
+        _ => None,
+    }
+}
+
+// The keys `Scene::update` moves the camera and the sky with
+fn key_code(name: &str) -> Option<KeyCode> {
+    match name {
+        "KeyW" => Some(KeyCode::KeyW),
+        "KeyA" => Some(KeyCode::KeyA),
+        "KeyS" => Some(KeyCode::KeyS),
+        "KeyD" => Some(KeyCode::KeyD),
+        "KeyQ" => Some(KeyCode::KeyQ),
+        "KeyE" => Some(KeyCode::KeyE),
+        "Space" => Some(KeyCode::Space),
+        "ShiftLeft" => Some(KeyCode::ShiftLeft),
+        "ArrowLeft" => Some(KeyCode::ArrowLeft),
+        "ArrowRight" => Some(KeyCode::ArrowRight),
+        "ArrowUp" => Some(KeyCode::ArrowUp),
+        "ArrowDown" => Some(KeyCode::ArrowDown),
+        "BracketLeft" => Some(KeyCode::BracketLeft),
+        "BracketRight" => Some(KeyCode::BracketRight),
+        "Minus" => Some(KeyCode::Minus),
+        "Equal" => Some(KeyCode::Equal),
+        _ => None,
+    }
+}
+
+/// Linear RGBA radiance, four floats per pixel row by row, as a JPEG like the PNG screenshots
+pub fn encode_jpeg(width: u32, height: u32, radiance: &[f32]) -> Vec<u8> {
+    let pixels: Vec<u8> = radiance.chunks_exact(4)
+        .flat_map(|rgba| [0, 1, 2].map(|c| (linear_to_srgb(rgba[c]) * 255.0 + 0.5) as u8))
+        .collect();
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+        .encode(&pixels, width, height, image::ColorType::Rgb8)
+        .expect("Failed to encode a frame");
+    jpeg
+}
+
+#[derive(Default)]
+struct Shared {
+    // The latest frame and how many were published before it, which stream threads wait on
+    frame: Mutex<(u64, Arc<Vec<u8>>)>,
+    new_frame: Condvar,
+    input: Mutex<Vec<RemoteInput>>,
+    viewers: Mutex<usize>,
+}
+
+/// Serves the rendered view to browsers over HTTP, so the renderer can run on a machine without a
+/// display. `/` is a page showing `/stream`, a motion JPEG of the published frames, and the page
+/// posts its keys and mouse drags to `/input`. Every connection is served on its own thread.
+pub struct RemoteView {
+    shared: Arc<Shared>,
+}
+
+impl RemoteView {
+    pub fn listen(listener: TcpListener) -> Self {
+        let shared = Arc::new(Shared::default());
+        let server = shared.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = server.clone();
+                // Errors are viewers closing the page, which there is nothing to do about
+                std::thread::spawn(move || serve_request(stream, &shared));
+            }
+        });
+        Self { shared }
+    }
+
+    /// Sends a JPEG to every open stream, and to streams opened later until the next one
+    pub fn publish(&self, jpeg: Vec<u8>) {
+        let mut frame = self.shared.frame.lock().unwrap();
+        *frame = (frame.0 + 1, Arc::new(jpeg));
+        self.shared.new_frame.notify_all();
+    }
+
+    /// Input posted since the last call, in order
+    pub fn take_input(&self) -> Vec<RemoteInput> {
+        std::mem::take(&mut self.shared.input.lock().unwrap())
+    }
+
+    /// Streams currently open, so frames don't have to be encoded for nobody
+    pub fn viewer_count(&self) -> usize {
+        *self.shared.viewers.lock().unwrap()
+    }
+}
+
+fn serve_request(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    // Read through `take` so a client that never ends a line can't grow it without bound
+    let mut head = reader.by_ref().take(MAX_HEADER_SIZE);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    let mut headers = 0;
+    loop {
+        let mut header = String::new();
+        head.read_line(&mut header)?;
+        // A line the limit cut off
+        if head.limit() == 0 && !header.ends_with('\n') {
+            return respond(&mut writer, "431 Request Header Fields Too Large", "text/plain", b"Headers too large");
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            return respond(&mut writer, "431 Request Header Fields Too Large", "text/plain", b"Too many headers");
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut words = request_line.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("GET"), Some("/")) => respond(&mut writer, "200 OK", "text/html; charset=utf-8", VIEWER_PAGE.as_bytes()),
+        (Some("GET"), Some("/stream")) => {
+            *shared.viewers.lock().unwrap() += 1;
+            let result = stream_frames(&mut writer, shared);
+            *shared.viewers.lock().unwrap() -= 1;
+            result
+        },
+        (Some("POST"), Some("/input")) => {
+            let mut body = String::new();
+            reader.take(content_length.min(MAX_BODY_SIZE)).read_to_string(&mut body)?;
+            shared.input.lock().unwrap().extend(body.lines().filter_map(parse_remote_input));
+            respond(&mut writer, "204 No Content", "text/plain", &[])
+        },
+        _ => respond(&mut writer, "404 Not Found", "text/plain", b"Not found"),
+    }
+}
+
+fn respond(writer: &mut impl Write, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(writer, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len())?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+// Writes each published frame as a part of a multipart response until the viewer goes away
+fn stream_frames(writer: &mut impl Write, shared: &Shared) -> io::Result<()> {
+    write!(writer, "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n", BOUNDARY)?;
+    let mut sent = 0;
+    loop {
+        let jpeg = {
+            let frame = shared.new_frame.wait_while(shared.frame.lock().unwrap(), |frame| frame.0 == sent).unwrap();
+            sent = frame.0;
+            frame.1.clone()
+        };
+        write!(writer, "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n", BOUNDARY, jpeg.len())?;
+        writer.write_all(&jpeg)?;
+        writer.write_all(b"\r\n")?;
+        writer.flush()?;
+    }
+}
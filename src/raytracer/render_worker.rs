@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+
+use image::{DynamicImage, RgbImage, RgbaImage};
+use tracing::{info, warn};
+
+use super::{band_frames, warn_unsupported, CpuRenderer, Scene, SceneBuffers, SCENE_DATA_SIZE};
+
+// Sent first by both ends, bumped whenever the messages below change
+const MAGIC: &[u8; 8] = b"RTWRK002";
+/// Rows of the image in one tile job
+pub const TILE_ROWS: u32 = 32;
+// Largest tile a worker accepts, in pixels, so a broken message can't make it allocate everything
+const MAX_TILE_PIXELS: u64 = 1 << 26;
+
+// Messages, all little-endian, with every byte buffer sent as a u32 length and its bytes:
+// coordinator: magic, the object, node, object index, material and point buffers, then six sky
+//   faces as u32 width, u32 height and an RGB8 buffer, then u32 texture count and each texture as
+//   u32 width, u32 height and an RGBA8 buffer
+// then per tile: u32 first row, u32 width, u32 rows, u32 frame count and each frame's scene parameters
+// worker, per tile: width * rows * 4 f32 of radiance
+// A tile of 0 rows ends the session.
+
+/// A run of rows of the image, with the scene parameters of every frame traced for it
+struct Tile {
+    index: usize,
+    first_row: u32,
+    rows: u32,
+    frames: Vec<Vec<u8>>,
+}
+
+/// Renders `samples` samples per pixel of a built scene on render workers, addressed as host:port,
+/// and returns the linear RGBA radiance laid out like `render_cpu`'s. The image is split into tiles
+/// of TILE_ROWS rows handed to whichever worker is free, and tiles of workers that disconnect are
+/// handed to the others. Tiles left when every worker is gone are traced here on the CPU.
+pub fn render_distributed(scene: &mut Scene, workers: &[String], width: u32, height: u32, samples: u32) -> Vec<f32> {
+    warn_unsupported(scene);
+    let buffers = SceneBuffers::new(scene);
+    let tiles: VecDeque<Tile> = (0..height.div_ceil(TILE_ROWS)).map(|i| {
+        let rows = i * TILE_ROWS..((i + 1) * TILE_ROWS).min(height);
+        Tile { index: i as usize, first_row: rows.start, rows: rows.len() as u32, frames: band_frames(scene, rows, height, 0..samples) }
+    }).collect();
+    let mut results: Vec<Option<Vec<f32>>> = (0..tiles.len()).map(|_| None).collect();
+
+    let queue = Mutex::new(tiles);
+    let finished = Mutex::new(&mut results);
+    std::thread::scope(|scope| {
+        for address in workers {
+            let (buffers, queue, finished) = (&buffers, &queue, &finished);
+            scope.spawn(move || {
+                if let Err(e) = drive_worker(address, buffers, width, queue, finished) {
+                    warn!("Render worker {} failed: {}", address, e);
+                }
+            });
+        }
+    });
+
+    let mut renderer: Option<CpuRenderer> = None;
+    for tile in queue.into_inner().unwrap() {
+        info!("Tracing tile {} here, no worker is left", tile.index);
+        let renderer = tile_renderer(&mut renderer, &buffers, width, tile.rows, tile.first_row);
+        for frame in &tile.frames {
+            renderer.render_frame(frame);
+        }
+        results[tile.index] = Some(std::mem::take(&mut renderer.accumulation));
+    }
+    results.into_iter().flat_map(|tile| tile.expect("A tile was never rendered")).collect()
+}
+
+// Sends one worker the scene and then tiles until there are none left. A tile the worker fails on
+// goes back in the queue.
+fn drive_worker(address: &str, buffers: &SceneBuffers, width: u32, queue: &Mutex<VecDeque<Tile>>, finished: &Mutex<&mut Vec<Option<Vec<f32>>>>) -> io::Result<()> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    writer.write_all(MAGIC)?;
+    write_scene(&mut writer, buffers)?;
+
+    loop {
+        let Some(tile) = queue.lock().unwrap().pop_front() else {
+            write_u32s(&mut writer, &[0, width, 0, 0])?;
+            return writer.flush();
+        };
+        match trace_remotely(&mut reader, &mut writer, &tile, width) {
+            Ok(radiance) => finished.lock().unwrap()[tile.index] = Some(radiance),
+            Err(e) => {
+                queue.lock().unwrap().push_back(tile);
+                return Err(e);
+            },
+        }
+    }
+}
+
+fn trace_remotely(reader: &mut impl Read, writer: &mut impl Write, tile: &Tile, width: u32) -> io::Result<Vec<f32>> {
+    write_u32s(writer, &[tile.first_row, width, tile.rows, tile.frames.len() as u32])?;
+    for frame in &tile.frames {
+        write_bytes(writer, frame)?;
+    }
+    writer.flush()?;
+
+    let mut radiance = vec![0; (width * tile.rows * 4 * 4) as usize];
+    reader.read_exact(&mut radiance)?;
+    Ok(radiance.chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect())
+}
+
+/// Waits for coordinators on the listener and traces the tiles they send on the CPU, one
+/// coordinator at a time. A session that breaks off is reported and the next one accepted.
+pub fn serve_worker(listener: TcpListener) -> io::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept()?;
+        info!("Rendering for {}", peer);
+        match serve_session(stream) {
+            Ok(tiles) => info!("Rendered {} tile(s) for {}", tiles, peer),
+            Err(e) => warn!("Session with {} failed: {}", peer, e),
+        }
+    }
+}
+
+// Reads the scene and traces tiles until the coordinator sends the empty one, returning how many
+fn serve_session(stream: TcpStream) -> io::Result<usize> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a render coordinator"));
+    }
+    let buffers = read_scene(&mut reader)?;
+
+    let (mut renderer, mut tiles) = (None, 0);
+    loop {
+        let (first_row, width, rows, frame_count) = (read_u32(&mut reader)?, read_u32(&mut reader)?, read_u32(&mut reader)?, read_u32(&mut reader)?);
+        if rows == 0 {
+            return Ok(tiles);
+        }
+        if width as u64 * rows as u64 > MAX_TILE_PIXELS {
+            return Err(invalid("tile is too large"));
+        }
+
+        let renderer = tile_renderer(&mut renderer, &buffers, width, rows, first_row);
+        for _ in 0..frame_count {
+            let frame = read_bytes(&mut reader)?;
+            if frame.len() as u64 != SCENE_DATA_SIZE {
+                return Err(invalid("scene parameters of the wrong size"));
+            }
+            renderer.render_frame(&frame);
+        }
+        for value in &renderer.accumulation {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()?;
+        tiles += 1;
+    }
+}
+
+// The renderer set to the tile, made on the first tile so the sky is only converted once
+fn tile_renderer<'a>(renderer: &'a mut Option<CpuRenderer>, buffers: &SceneBuffers, width: u32, rows: u32, first_row: u32) -> &'a mut CpuRenderer {
+    match renderer {
+        Some(renderer) => {
+            renderer.set_tile(width, rows, first_row);
+            renderer
+        },
+        None => renderer.insert(CpuRenderer::from_buffers(buffers, width, rows, first_row)),
+    }
+}
+
+fn write_scene(writer: &mut impl Write, buffers: &SceneBuffers) -> io::Result<()> {
+    for buffer in [&buffers.objects, &buffers.nodes, &buffers.object_indices, &buffers.materials, &buffers.points] {
+        write_bytes(writer, buffer)?;
+    }
+    for face in &buffers.sky {
+        let face = face.to_rgb8();
+        write_u32s(writer, &[face.width(), face.height()])?;
+        write_bytes(writer, face.as_raw())?;
+    }
+    write_u32s(writer, &[buffers.textures.len() as u32])?;
+    for texture in &buffers.textures {
+        let texture = texture.to_rgba8();
+        write_u32s(writer, &[texture.width(), texture.height()])?;
+        write_bytes(writer, texture.as_raw())?;
+    }
+    Ok(())
+}
+
+fn read_scene(reader: &mut impl Read) -> io::Result<SceneBuffers> {
+    let mut buffers = SceneBuffers {
+        objects: read_bytes(reader)?,
+        nodes: read_bytes(reader)?,
+        object_indices: read_bytes(reader)?,
+        materials: read_bytes(reader)?,
+        points: read_bytes(reader)?,
+        sky: Vec::with_capacity(6),
+        textures: Vec::new(),
+    };
+    for _ in 0..6 {
+        let (width, height) = (read_u32(reader)?, read_u32(reader)?);
+        let face = RgbImage::from_raw(width, height, read_bytes(reader)?).ok_or_else(|| invalid("sky face doesn't match its size"))?;
+        buffers.sky.push(DynamicImage::ImageRgb8(face));
+    }
+    for _ in 0..read_u32(reader)? {
+        let (width, height) = (read_u32(reader)?, read_u32(reader)?);
+        let texture = RgbaImage::from_raw(width, height, read_bytes(reader)?).ok_or_else(|| invalid("texture doesn't match its size"))?;
+        buffers.textures.push(DynamicImage::ImageRgba8(texture));
+    }
+    // The CPU renderer indexes the buffers as they are, so anything out of range is refused here
+    let size = |face: &DynamicImage| (face.width(), face.height());
+    if buffers.sky.iter().any(|face| size(face) != size(&buffers.sky[0]) || face.width() == 0 || face.height() == 0) {
+        return Err(invalid("sky faces are empty or differ in size"));
+    }
+    if buffers.textures.iter().any(|texture| texture.width() == 0 || texture.height() == 0) {
+        return Err(invalid("texture is empty"));
+    }
+    buffers.check().map_err(invalid)?;
+    Ok(buffers)
+}
+
+fn write_u32s(writer: &mut impl Write, values: &[u32]) -> io::Result<()> {
+    values.iter().try_for_each(|value| writer.write_all(&value.to_le_bytes()))
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+// Reads through `take` so a wrong length fails at the end of the stream instead of allocating it
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader)? as u64;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "message ended early"));
+    }
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -1,286 +1,2369 @@
 
-use image::DynamicImage;
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
+use tracing::{debug, debug_span, error, info, info_span, warn};
 use wgpu::{BufferBinding, BufferUsages, Sampler, TextureView};
 use winit::{
     dpi::PhysicalSize, 
-    window::Window
+    window::{Window, WindowId}
 };
 
+use std::collections::VecDeque;
+use std::io;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use image::io::Reader as ImageReader;
 
-use super::{CubeMapMaterial, Scene};
+use super::{check_scene_limits, describe_adapter, enumerate_adapters, find_adapter, load_workgroup_size, log_adapter_limits, render_label_atlas, save_workgroup_size, workgroup_cache_path, Camera, AutoExposure, Command, CubeMapMaterial, Edit, FrameGraph, FramePass, FrameTime, FrameTimes, DEFAULT_WORKGROUP_SIZE, WORKGROUP_SIZES, BufferLimitError, LoadedScene, ObjMesh, ObjectId, Scene, SceneBuffer, Setting, Shape, TextureArrayMaterial, TextureFiltering, Vec3, COMMAND_HELP, FRAME_TIMES_UNIFORM_SIZE, RayStats, METERING_BUFFER_SIZE, POST_PROCESS_UNIFORM_SIZE, SCENE_DATA_SIZE, STATS_BUFFER_SIZE, log_average_luminance, can_reproject, fade_uniform_data, fade_weight, FADE_HISTORY_SAMPLES, FADE_UNIFORM_SIZE, ProbeGrid, PROBE_WORKGROUP_SIZE, raster_uniform_data, RASTER_UNIFORM_SIZE};
+#[cfg(feature = "editor")]
+use super::{Editor, Material, Texture, PREVIEW_SIZE};
+
+/// Image formats the accumulated render can be captured in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// 8-bit sRGB PNG
+    Png8,
+    /// 16-bit sRGB PNG, avoiding banding in smooth gradients
+    Png16,
+    /// 32-bit float OpenEXR holding the unclamped linear radiance
+    Exr,
+}
+
+impl CaptureFormat {
+    /// OpenEXR for `.exr` paths, 8-bit PNG otherwise
+    pub fn from_path(path: &str) -> Self {
+        if path.to_lowercase().ends_with(".exr") { CaptureFormat::Exr } else { CaptureFormat::Png8 }
+    }
+}
 
 pub struct State<'a> {
     // Device/Context objects
+    instance: Option<wgpu::Instance>, // None when the host passed everything made with it
+    adapter: wgpu::Adapter,
+    device: Arc<wgpu::Device>,
+    device_lost: Arc<AtomicBool>, // Set by the driver's device lost callback, the device is recreated on the next frame
+    queue: Arc<wgpu::Queue>,
+    /// The main window, which the first viewport presents to
+    pub window: &'a Window,
+    viewports: Vec<Viewport<'a>>, // The main window's first, then added ones like a top-down debug view
+
+    // Assets shared by every viewport
+    sampler: wgpu::Sampler,
+    object_buffer: wgpu::Buffer,
+    node_buffer: wgpu::Buffer,
+    object_index_buffer: wgpu::Buffer,
+    material_buffer: wgpu::Buffer,
+    point_buffer: wgpu::Buffer,
+    skies: Vec<Option<CubeMapMaterial>>, // Loaded skies, indexed like scene.skies
+    active_sky: usize,
+    object_textures: TextureArrayMaterial,
+    texture_filtering: TextureFiltering, // Filtering the sky and object texture samplers were made with
+    uploaded_labels: usize, // Labels drawn into the text atlas layer of object_textures
+    last_reset: Instant, // When the accumulation last started over, to tell when the view settles
+    interacting: bool, // Tracing blocks of pixels since the view changed
+    staging_belt: wgpu::util::StagingBelt, // Mapped buffers the scene is uploaded through, reused once copied out of
+    oversized: bool, // The scene grew past the device's limits, the buffers hold the last one that fit
+    prepared: Option<bool>, // Set by update for the next recorded frame, to whether that frame idles
+    /// Samples per pixel after which a static scene stops being traced and the last frame is
+    /// only presented again, 0 to keep tracing
+    pub idle_samples: u32,
+
+    // Pipeline Objects
+    ray_tracing_bind_group_layout: wgpu::BindGroupLayout,
+    screen_bind_group_layout: wgpu::BindGroupLayout,
+    ray_tracing_pipeline: wgpu::ComputePipeline,
+    workgroup_size: (u32, u32), // Of the ray tracing kernel's main entry point
+    push_constants: bool, // The camera and frame index are pushed with each dispatch instead of written to the scene uniform
+    adapter_name: String,
+    workgroup_size_tuned: bool, // Benchmarked or read from the cache, otherwise done once a scene is loaded
+    photon_pipeline: wgpu::ComputePipeline,
+    screen_pipeline: wgpu::RenderPipeline,
+    progress_pipeline: wgpu::RenderPipeline,
+    progress_bind_group: wgpu::BindGroup,
+    progress_buffer: wgpu::Buffer,
+    loading_progress: Option<f32>, // Share of the background loading that is done, drawn as a bar while set
+    frame_time_pipeline: wgpu::RenderPipeline,
+    frame_time_bind_group: wgpu::BindGroup,
+    frame_time_buffer: wgpu::Buffer,
+    frame_times: FrameTimes,
+    update_time: Duration, // Spent in the last update, counted into the next recorded frame's CPU time
+    /// Draws the graph of the last frames' CPU and GPU times over the main window
+    pub show_frame_times: bool,
+    stats_buffer: wgpu::Buffer, // Counters the kernel adds to, shared by every dispatch
+    stats_readback: wgpu::Buffer,
+    post_process_buffer: wgpu::Buffer, // Shared by the windows' screen passes
+    presented: u32, // Frames drawn to the windows, seeding the film grain
+    stats_counted_since: Instant, // When the counters were last cleared
+    stats_pending: Option<(f64, Arc<AtomicBool>)>, // Seconds the copy being read back was counted over, and whether it is mapped
+    ray_stats: Option<RayStats>,
+    rays_traced: (f64, f64), // Millions of rays counted since the start, and the seconds they were counted over
+    exposure_pipeline: wgpu::ComputePipeline,
+    metering_buffer: wgpu::Buffer, // Brightness of the main window, metered each frame with auto exposure
+    metering_readback: wgpu::Buffer,
+    metering_pending: Option<Arc<AtomicBool>>, // Whether the metering being read back is mapped
+    metered_at: Instant, // When the last metering was read back, to adapt over the time since
+    exposure: AutoExposure,
+    fade_pipeline: wgpu::ComputePipeline,
+    fade_buffer: wgpu::Buffer,
+    fade_history: Option<FadeHistory>, // Main window's last converged frame, faded out after the camera moved
+    traced: (Camera, u32), // Camera the main window's accumulation was traced through, and its samples per pixel
+    probe_pipeline: wgpu::ComputePipeline,
+    probes: ProbeAtlas,
+    raster_pipeline: wgpu::RenderPipeline,
+    /// Passes `render` encodes each frame, in order
+    pub frame_graph: FrameGraph,
+
+    // Editor panels and the preview sphere of the material they edit
+    #[cfg(feature = "editor")]
+    editor: Editor,
+    #[cfg(feature = "editor")]
+    preview: MaterialPreview,
+
+    // Scene to render
+    pub scene: Scene,
+}
+
+// A window the scene is traced into with a camera and samples of its own. The device, the pipelines
+// and the scene's buffers are shared, only what depends on the view is kept per viewport.
+struct Viewport<'a> {
+    window: &'a Window,
     surface: wgpu::Surface<'a>,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
-    pub size: PhysicalSize<u32>,
-    pub window: &'a Window,
+    present_modes: Vec<wgpu::PresentMode>, // Supported by the surface, in PRESENT_MODES order
+    size: PhysicalSize<u32>,
+    camera: Option<Camera>, // Looked through instead of the scene's camera, None for the main window
+    color_buffer: wgpu::Texture,
+    color_buffer_view: TextureView,
+    scene_parameters: wgpu::Buffer,
+    uploaded_parameters: Vec<u8>, // Last written to scene_parameters, to skip writing it again unchanged
+    photon_buffer: wgpu::Buffer, // Emptied whenever this view's accumulation restarts
+    aov_buffer: wgpu::Buffer,
+    accumulation_buffer: wgpu::Buffer,
+    frame_index: u32,
+    ray_tracing_bind_group: wgpu::BindGroup,
+    screen_bind_group: wgpu::BindGroup,
+    raster: RasterTarget,
+    raster_buffer: wgpu::Buffer, // Camera the raster pass draws through
+    raster_bind_group: wgpu::BindGroup,
+}
+
+// Copy of the main window's accumulation, blended over the new samples for a while after the
+// camera moved away from where it was traced
+struct FadeHistory {
+    buffer: wgpu::Buffer,
+    camera: Camera,
+    kept_at: Instant,
+}
+
+// Light of the scene's probe grid that the kernel reads, and the texture the probe pass writes each
+// frame before it is copied over. Without a grid both are a single texel.
+struct ProbeAtlas {
+    grid: Option<ProbeGrid>, // Grid the atlas was made for
+    texture: wgpu::Texture,
+    view: TextureView,
+    target: wgpu::Texture,
+    target_bind_group: wgpu::BindGroup,
+}
+
+// Where the raster pass draws the triangles in front of a viewport's camera: the index of the one
+// at each pixel, which the kernel starts its camera rays from, and the depth buffer finding it
+struct RasterTarget {
+    _visibility: wgpu::Texture, // Keeps the textures behind the views alive
+    visibility_view: TextureView,
+    _depth: wgpu::Texture,
+    depth_view: TextureView,
+}
+
+/// Sets up a `State` with parts of the host application's wgpu setup, see `State::builder`.
+/// Whatever isn't passed is made by the renderer as `State::new` does.
+pub struct StateBuilder<'a> {
+    window: &'a Window,
+    scene: Scene,
+    adapter_choice: Option<String>,
+    instance: Option<wgpu::Instance>,
+    adapter: Option<wgpu::Adapter>,
+    device: Option<(Arc<wgpu::Device>, Arc<wgpu::Queue>)>,
+    surface: Option<wgpu::Surface<'a>>,
+}
+
+impl<'a> StateBuilder<'a> {
+    /// Picks the adapter by index, kind or name as `find_adapter` does, unless one is passed
+    pub fn adapter_choice(mut self, choice: &str) -> Self {
+        self.adapter_choice = Some(choice.to_string());
+        self
+    }
+
+    /// Instance the adapter and the surface were made with, needed to add viewports and to
+    /// replace the renderer's own device when it is lost
+    pub fn instance(mut self, instance: wgpu::Instance) -> Self {
+        self.instance = Some(instance);
+        self
+    }
+
+    /// Adapter to render on, the one the device was requested from when a device is passed
+    pub fn adapter(mut self, adapter: wgpu::Adapter) -> Self {
+        self.adapter = Some(adapter);
+        self
+    }
+
+    /// Device and queue to render with, which need the limits `required_limits` returns. The host
+    /// keeps handling their errors and their loss.
+    pub fn device(mut self, device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self {
+        self.device = Some((device, queue));
+        self
+    }
+
+    /// Surface of the window to present to, made with the same instance as the adapter
+    pub fn surface(mut self, surface: wgpu::Surface<'a>) -> Self {
+        self.surface = Some(surface);
+        self
+    }
+
+    /// Fails when the scene doesn't fit the device's buffer limits
+    pub async fn build(self) -> Result<State<'a>, BufferLimitError> {
+        assert!(self.device.is_none() || self.adapter.is_some(), "A device needs the adapter it was requested from");
+        State::from_builder(self).await
+    }
+}
+
+// Present modes from the lowest latency without tearing to the lowest latency overall: Mailbox
+// replaces queued frames, Fifo waits for vertical sync and Immediate presents right away
+const PRESENT_MODES: [wgpu::PresentMode; 3] = [wgpu::PresentMode::Mailbox, wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate];
+
+// Side of the pixel blocks sharing a sample in interaction mode, and how long the view has to stay
+// unchanged before the full resolution comes back
+const INTERACTION_BLOCK_SIZE: u32 = 2;
+const INTERACTION_SETTLE_TIME: Duration = Duration::from_millis(250);
+
+// Samples per pixel a static scene is traced to before the renderer idles
+const DEFAULT_IDLE_SAMPLES: u32 = 2048;
+
+// Photons are traced in 8x8 workgroups, this many along each side, matching PHOTONS_PER_FRAME in the kernel
+const PHOTON_WORKGROUPS: u32 = 16;
+// Cells of the caustic photon hash grid, 16 bytes each
+const PHOTON_GRID_CELLS: u64 = 1 << 18;
+
+// Pixels each workgroup of the exposure shader meters, its WORKGROUP_SIZE times PIXELS_PER_INVOCATION
+const METERING_PIXELS_PER_WORKGROUP: u32 = 256 * 16;
+
+// Bytes of each mapped buffer the scene is uploaded through, larger writes get a buffer of their own
+const STAGING_CHUNK_SIZE: u64 = 1 << 20;
+
+// Bytes of the kernel's FrameConstants, pushed with each dispatch where the device allows
+const FRAME_CONSTANTS_SIZE: u32 = 64;
+
+// Number of samples the material preview accumulates before it stops tracing
+#[cfg(feature = "editor")]
+const PREVIEW_SAMPLES: u32 = 256;
+
+// A single sphere carrying the edited material, traced into a color buffer of its own
+// that the editor shows next to the material's settings
+#[cfg(feature = "editor")]
+struct MaterialPreview {
+    scene: Scene,
+    sphere: ObjectId,
+    _color_buffer: wgpu::Texture, // Keeps the texture behind the view alive
+    color_buffer_view: TextureView,
+    scene_parameters: wgpu::Buffer,
+    object_buffer: wgpu::Buffer,
+    node_buffer: wgpu::Buffer,
+    object_index_buffer: wgpu::Buffer,
+    material_buffer: wgpu::Buffer,
+    point_buffer: wgpu::Buffer,
+    photon_buffer: wgpu::Buffer,
+    aov_buffer: wgpu::Buffer,
+    accumulation_buffer: wgpu::Buffer,
+    shown: Option<(Material, Vec3)>, // Material and color the accumulated samples are of
+    frame_index: u32,
+}
+
+impl<'a> State<'a> {
+
+    /// Opens the renderer on the adapter `adapter_choice` names, see `find_adapter`, or on the one
+    /// wgpu prefers for the window without a choice. Fails when the scene doesn't fit the device's
+    /// buffer limits.
+    pub async fn new(window: &'a Window, scene: Scene, adapter_choice: Option<&str>) -> Result<Self, BufferLimitError> {
+        let mut builder = Self::builder(window, scene);
+        if let Some(choice) = adapter_choice {
+            builder = builder.adapter_choice(choice);
+        }
+        builder.build().await
+    }
+
+    /// Starts a renderer for the window that can be handed the host application's instance,
+    /// adapter, device and surface instead of making its own
+    pub fn builder(window: &'a Window, scene: Scene) -> StateBuilder<'a> {
+        StateBuilder { window, scene, adapter_choice: None, instance: None, adapter: None, device: None, surface: None }
+    }
+
+    async fn from_builder(builder: StateBuilder<'a>) -> Result<Self, BufferLimitError> {
+        let StateBuilder { window, scene, adapter_choice, instance, adapter, device, surface } = builder;
+
+        // Physical pixels, so the render stays sharp on high-DPI displays
+        let size = window.inner_size();
+
+        // An instance is only needed to make what the host didn't pass
+        let instance = instance.or_else(|| (surface.is_none() || adapter.is_none()).then(|| {
+            let instance_descriptor = wgpu::InstanceDescriptor {
+                backends: wgpu::Backends::all(), ..Default::default()
+            };
+            wgpu::Instance::new(instance_descriptor)
+        }));
+        let surface = surface.unwrap_or_else(|| instance.as_ref().unwrap().create_surface(window)
+            .unwrap());
+
+        let adapter = match (adapter, adapter_choice) {
+            (Some(adapter), _) => adapter,
+            (None, Some(choice)) => find_adapter(enumerate_adapters(instance.as_ref().unwrap()), &choice)
+                .unwrap_or_else(|| panic!("No adapter matches \"{}\", --list-adapters shows them", choice)),
+            (None, None) => {
+                let adapter_descriptor = wgpu::RequestAdapterOptionsBase {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: Some(&surface),
+                    force_fallback_adapter: false,
+                };
+                instance.as_ref().unwrap().request_adapter(&adapter_descriptor).await.unwrap()
+            },
+        };
+        assert!(adapter.is_surface_supported(&surface), "The chosen adapter can't present to the window");
+        log_adapter_limits(&adapter);
+
+        // A device the host made stays the host's to replace when it is lost, as does one the
+        // renderer can't find the adapter for again without an instance
+        let (device, queue, device_lost) = match device {
+            Some((device, queue)) => (device, queue, Arc::new(AtomicBool::new(false))),
+            None => {
+                let (device, queue) = init_device_and_queue(&adapter).await;
+                let device_lost = match instance {
+                    Some(_) => watch_device_loss(&device),
+                    None => Arc::new(AtomicBool::new(false)),
+                };
+                (Arc::new(device), Arc::new(queue), device_lost)
+            },
+        };
+        assert!(device.limits().max_storage_buffers_per_shader_stage >= required_limits().max_storage_buffers_per_shader_stage,
+            "The device needs the limits required_limits() returns");
+        check_scene_limits(&scene, &device.limits())?;
+        let push_constants = supports_push_constants(&device);
+        let adapter_name = adapter.get_info().name;
+        let cached_workgroup_size = load_workgroup_size(&workgroup_cache_path(), &adapter_name);
+        let workgroup_size = cached_workgroup_size.unwrap_or(DEFAULT_WORKGROUP_SIZE);
+
+        let (config, present_modes) = init_surface_configuration(&adapter, &surface, &size);
+
+        // Create the assets the viewports share, only the active sky is loaded up front
+        let sampler = create_sampler(&device);
+        let object_buffer = create_scene_buffer(&device, &scene, SceneBuffer::Objects);
+        let node_buffer = create_scene_buffer(&device, &scene, SceneBuffer::Nodes);
+        let object_index_buffer = create_scene_buffer(&device, &scene, SceneBuffer::ObjectIndices);
+        let sky_material = create_sky(&device, &queue, &scene);
+        let object_textures = create_object_textures(&device, &queue, &scene);
+        let material_buffer = create_scene_buffer(&device, &scene, SceneBuffer::Materials);
+        let point_buffer = create_scene_buffer(&device, &scene, SceneBuffer::Points);
+        let stats_buffer = create_stats_buffer(&device);
+        let stats_readback = create_stats_readback(&device);
+        let (metering_buffer, metering_readback) = create_metering_buffers(&device);
+        let post_process_buffer = create_post_process_buffer(&device);
+        
+        // create bind group layouts
+        let (ray_tracing_bind_group_layout, 
+            screen_bind_group_layout) = make_bind_group_layouts(&device).await;
+        
+        // Create render pipeline
+        let (ray_tracing_pipeline,
+            photon_pipeline,
+            screen_pipeline) = make_pipeline(&device, &ray_tracing_bind_group_layout, &screen_bind_group_layout, workgroup_size, push_constants, config.format).await;
+        let (progress_pipeline,
+            progress_bind_group,
+            progress_buffer) = create_overlay_pipeline(&device, config.format, "Progress", include_str!("../../shaders/progress_shader.wgsl"), 16);
+        let (frame_time_pipeline,
+            frame_time_bind_group,
+            frame_time_buffer) = create_overlay_pipeline(&device, config.format, "Frame Time", include_str!("../../shaders/frame_time_shader.wgsl"), FRAME_TIMES_UNIFORM_SIZE);
+        let exposure_pipeline = create_exposure_pipeline(&device);
+        let fade_pipeline = create_fade_pipeline(&device);
+        let fade_buffer = create_fade_buffer(&device);
+        let probe_pipeline = create_probe_pipeline(&device, &ray_tracing_bind_group_layout, push_constants);
+        let probes = create_probe_atlas(&device, &probe_pipeline, scene.probe_grid);
+        let raster_pipeline = create_raster_pipeline(&device);
+        
+        let active_sky = scene.active_sky;
+        let mut skies: Vec<Option<CubeMapMaterial>> = scene.skies.iter().map(|_| None).collect();
+        skies[active_sky] = Some(sky_material);
+
+        #[cfg(feature = "editor")]
+        let preview = create_material_preview(&device).await;
+        #[cfg(feature = "editor")]
+        let editor = Editor::new(&device, window, config.format, &preview.color_buffer_view);
+
+        let mut state = Self {
+            // Device/Context objects
+            instance,
+            adapter,
+            device,
+            device_lost,
+            queue,
+            window,
+            viewports: Vec::new(),
+            // Assets
+            sampler,
+            object_buffer,
+            node_buffer,
+            object_index_buffer,
+            material_buffer,
+            point_buffer,
+            skies,
+            active_sky,
+            object_textures,
+            texture_filtering: scene.texture_filtering,
+            uploaded_labels: scene.labels.len(),
+            last_reset: Instant::now(),
+            interacting: false,
+            staging_belt: wgpu::util::StagingBelt::new(STAGING_CHUNK_SIZE),
+            oversized: false,
+            prepared: None,
+            idle_samples: DEFAULT_IDLE_SAMPLES,
+            // Pipeline Objects
+            ray_tracing_bind_group_layout,
+            screen_bind_group_layout,
+            ray_tracing_pipeline,
+            workgroup_size,
+            push_constants,
+            adapter_name,
+            workgroup_size_tuned: cached_workgroup_size.is_some(),
+            photon_pipeline,
+            screen_pipeline,
+            progress_pipeline,
+            progress_bind_group,
+            progress_buffer,
+            loading_progress: None,
+            frame_time_pipeline,
+            frame_time_bind_group,
+            frame_time_buffer,
+            frame_times: FrameTimes::default(),
+            update_time: Duration::ZERO,
+            show_frame_times: false,
+            stats_buffer,
+            stats_readback,
+            post_process_buffer,
+            presented: 0,
+            stats_counted_since: Instant::now(),
+            stats_pending: None,
+            ray_stats: None,
+            rays_traced: (0.0, 0.0),
+            exposure_pipeline,
+            metering_buffer,
+            metering_readback,
+            metering_pending: None,
+            metered_at: Instant::now(),
+            exposure: AutoExposure::default(),
+            fade_pipeline,
+            fade_buffer,
+            fade_history: None,
+            traced: (scene.camera, 0),
+            probe_pipeline,
+            probes,
+            raster_pipeline,
+            frame_graph: FrameGraph::default(),
+            // Editor
+            #[cfg(feature = "editor")]
+            editor,
+            #[cfg(feature = "editor")]
+            preview,
+            // Scene to render
+            scene,
+        };
+        let main_viewport = state.create_viewport(window, surface, config, present_modes, None);
+        state.viewports.push(main_viewport);
+        Ok(state)
+    }
+
+    // Configures the surface and makes the viewport's own buffers, along with the bind groups tying
+    // them to the shared ones. The camera, or the scene's for the main window, is fit to its size.
+    fn create_viewport(&mut self, window: &'a Window, surface: wgpu::Surface<'a>, config: wgpu::SurfaceConfiguration, present_modes: Vec<wgpu::PresentMode>, mut camera: Option<Camera>) -> Viewport<'a> {
+        surface.configure(&self.device, &config);
+        let size = PhysicalSize::new(config.width, config.height);
+        let aspect_ratio = size.width as f32 / size.height.max(1) as f32;
+        camera.as_mut().unwrap_or(&mut self.scene.camera).set_aspect_ratio(aspect_ratio);
+
+        let (color_buffer, color_buffer_view, aov_buffer, accumulation_buffer) = create_view_buffers(&self.device, &size);
+        let scene_parameters = pollster::block_on(create_scene_parameters(&self.device));
+        let photon_buffer = create_photon_buffer(&self.device, PHOTON_GRID_CELLS);
+        let raster = create_raster_target(&self.device, &size);
+        let raster_buffer = create_raster_buffer(&self.device);
+        let (ray_tracing_bind_group,
+            screen_bind_group) = self.make_viewport_bind_groups(&color_buffer_view, &scene_parameters, &photon_buffer, &aov_buffer, &accumulation_buffer, &raster.visibility_view);
+        let raster_bind_group = self.make_raster_bind_group(&raster_buffer);
+        Viewport {
+            window,
+            surface,
+            config,
+            present_modes,
+            size,
+            camera,
+            color_buffer,
+            color_buffer_view,
+            scene_parameters,
+            uploaded_parameters: Vec::new(),
+            photon_buffer,
+            aov_buffer,
+            accumulation_buffer,
+            frame_index: 0,
+            ray_tracing_bind_group,
+            screen_bind_group,
+            raster,
+            raster_buffer,
+            raster_bind_group,
+        }
+    }
+
+    /// Adds a window the scene is traced into through its own camera, e.g. a top-down view next to
+    /// the main one. Its frames are drawn along with the main window's.
+    pub fn add_viewport(&mut self, window: &'a Window, camera: Camera) {
+        let surface = self.instance.as_ref().expect("Adding a viewport needs the instance, see StateBuilder::instance")
+            .create_surface(window).unwrap();
+        assert!(self.adapter.is_surface_supported(&surface), "The adapter can't present to the new window");
+        let (mut config, present_modes) = init_surface_configuration(&self.adapter, &surface, &window.inner_size());
+        // The screen pipeline is made for the main window's format
+        let format = self.viewports[0].config.format;
+        assert!(surface.get_capabilities(&self.adapter).formats.contains(&format), "The new window can't show the main window's format");
+        config.format = format;
+        let viewport = self.create_viewport(window, surface, config, present_modes, Some(camera));
+        self.viewports.push(viewport);
+        self.prepared = None;
+    }
+
+    /// Stops drawing into an added window and hides it
+    pub fn remove_viewport(&mut self, window_id: WindowId) {
+        if let Some(index) = self.viewport_index(window_id).filter(|&index| index > 0) {
+            self.viewports.remove(index).window.set_visible(false);
+        }
+    }
+
+    /// Whether the window is one of the viewports added next to the main window
+    pub fn has_viewport(&self, window_id: WindowId) -> bool {
+        self.viewport_index(window_id).is_some_and(|index| index > 0)
+    }
+
+    /// Looks through a new camera in an added window, fit to the window's size
+    pub fn set_viewport_camera(&mut self, window_id: WindowId, mut camera: Camera) {
+        let Some(index) = self.viewport_index(window_id).filter(|&index| index > 0) else { return };
+        let viewport = &mut self.viewports[index];
+        camera.set_aspect_ratio(viewport.size.width as f32 / viewport.size.height as f32);
+        viewport.camera = Some(camera);
+        viewport.frame_index = 0;
+    }
+
+    fn viewport_index(&self, window_id: WindowId) -> Option<usize> {
+        self.viewports.iter().position(|viewport| viewport.window.id() == window_id)
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.resize_view(0, new_size);
+    }
+
+    /// Follows a new size of an added window, see `resize` for the main one
+    pub fn resize_viewport(&mut self, window_id: WindowId, new_size: PhysicalSize<u32>) {
+        if let Some(index) = self.viewport_index(window_id) {
+            self.resize_view(index, new_size);
+        }
+    }
+
+    fn resize_view(&mut self, index: usize, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            let viewport = &mut self.viewports[index];
+            viewport.size = new_size;
+            viewport.config.width = new_size.width;
+            viewport.config.height = new_size.height;
+            viewport.surface.configure(&self.device, &viewport.config);
+            viewport.camera.as_mut().unwrap_or(&mut self.scene.camera).set_aspect_ratio(new_size.width as f32 / new_size.height as f32);
+
+            // The color and AOV buffers are sized per pixel, so they have to follow the window
+            (viewport.color_buffer,
+                viewport.color_buffer_view,
+                viewport.aov_buffer,
+                viewport.accumulation_buffer) = create_view_buffers(&self.device, &new_size);
+            viewport.raster = create_raster_target(&self.device, &new_size);
+            if index == 0 {
+                // Frames of the old size can't be blended into the new one
+                self.fade_history = None;
+                self.traced.1 = 0;
+            }
+            self.reset_accumulation();
+
+            self.rebuild_bind_groups();
+        }
+    }
+
+    fn rebuild_bind_groups(&mut self) {
+        for i in 0..self.viewports.len() {
+            let viewport = &self.viewports[i];
+            let (ray_tracing_bind_group,
+                screen_bind_group) = self.make_viewport_bind_groups(&viewport.color_buffer_view, &viewport.scene_parameters, &viewport.photon_buffer, &viewport.aov_buffer, &viewport.accumulation_buffer, &viewport.raster.visibility_view);
+            let raster_bind_group = self.make_raster_bind_group(&viewport.raster_buffer);
+            self.viewports[i].ray_tracing_bind_group = ray_tracing_bind_group;
+            self.viewports[i].screen_bind_group = screen_bind_group;
+            self.viewports[i].raster_bind_group = raster_bind_group;
+        }
+    }
+
+    // Bind groups of one viewport's buffers along with the shared scene buffers, sky and textures
+    fn make_viewport_bind_groups(&self, color_buffer_view: &TextureView, scene_parameters: &wgpu::Buffer, photon_buffer: &wgpu::Buffer, aov_buffer: &wgpu::Buffer, accumulation_buffer: &wgpu::Buffer, visibility_view: &TextureView) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        pollster::block_on(make_bind_groups(&self.device, color_buffer_view, &self.sampler, &self.post_process_buffer, scene_parameters, &self.object_buffer, &self.node_buffer, &self.object_index_buffer, &self.material_buffer, &self.point_buffer, photon_buffer, &self.stats_buffer, &self.ray_tracing_bind_group_layout, &self.screen_bind_group_layout, self.skies[self.active_sky].as_ref().unwrap(), aov_buffer, accumulation_buffer, &self.object_textures, &self.probes.view, visibility_view))
+    }
+
+    // The raster pass reads the triangles and their materials from the shared scene buffers
+    fn make_raster_bind_group(&self, raster_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Raster Bind Group"),
+            layout: &self.raster_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.object_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.material_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: raster_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    // Grows the object, node, index and material buffers when objects were added since they were created,
+    // and reloads the texture array when new images or labels were registered. The buffers are left
+    // as they are when the scene has grown past the device's limits.
+    fn fit_scene_buffers(&mut self) -> Result<(), BufferLimitError> {
+        if self.object_textures.image_count != self.scene.image_paths.len() + !self.scene.labels.is_empty() as usize
+            || self.uploaded_labels != self.scene.labels.len() {
+            self.object_textures = create_object_textures(&self.device, &self.queue, &self.scene);
+            self.uploaded_labels = self.scene.labels.len();
+            self.rebuild_bind_groups();
+        }
+
+        let needs_growth = self.object_buffer.size() < SceneBuffer::Objects.size(&self.scene)
+            || self.node_buffer.size() < SceneBuffer::Nodes.size(&self.scene)
+            || self.object_index_buffer.size() < SceneBuffer::ObjectIndices.size(&self.scene)
+            || self.material_buffer.size() < SceneBuffer::Materials.size(&self.scene)
+            || self.point_buffer.size() < SceneBuffer::Points.size(&self.scene);
+        if !needs_growth {
+            return Ok(());
+        }
+
+        check_scene_limits(&self.scene, &self.device.limits())?;
+        self.object_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Objects);
+        self.node_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Nodes);
+        self.object_index_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::ObjectIndices);
+        self.material_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Materials);
+        self.point_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Points);
+        self.rebuild_bind_groups();
+        Ok(())
+    }
+
+    // Takes back the last edit when it grew the scene past the device's limits. When that wasn't
+    // it, the buffers keep the last scene that fit and the scene isn't uploaded until it fits again.
+    fn refuse_oversized_edit(&mut self, error: BufferLimitError) {
+        let undone = self.scene.undo() && {
+            self.scene.make_scene();
+            self.fit_scene_buffers().is_ok()
+        };
+        self.oversized = !undone;
+        let message = match undone {
+            true => format!("Edit undone, {}", error),
+            false => format!("The scene isn't updated until it fits again, {}", error),
+        };
+        error!("{}", message);
+        #[cfg(feature = "editor")]
+        self.editor.console.print(&message);
+    }
+
+    // Loads the scene's active sky the first time it is picked, then only the bind groups
+    // have to be rebuilt to point at it
+    fn switch_sky(&mut self) {
+        self.skies.resize_with(self.scene.skies.len(), || None);
+        let index = self.scene.active_sky;
+        if self.skies[index].is_none() {
+            let faces = self.scene.skies[index].load().expect("Failed to load sky");
+            self.skies[index] = Some(CubeMapMaterial::from_faces(&self.device, &self.queue, faces, self.texture_filtering).expect("Failed to load sky"));
+        }
+        self.active_sky = index;
+        self.rebuild_bind_groups();
+    }
+
+    /// Decodes the scene's texture images again, e.g. after their files changed. The old textures
+    /// stay up when one of them can't be read, like a file caught halfway through being saved.
+    pub fn reload_textures(&mut self) -> image::ImageResult<()> {
+        let images = read_scene_images(&self.scene, |_| {})?;
+        self.object_textures = TextureArrayMaterial::new(&self.device, &self.queue, images, self.texture_filtering);
+        self.uploaded_labels = self.scene.labels.len();
+        self.rebuild_bind_groups();
+        self.reset_accumulation();
+        Ok(())
+    }
+
+    /// Loads one of the scene's skies again, e.g. after its file changed. Skies that aren't shown
+    /// are only dropped, to be loaded when they are picked.
+    pub fn reload_sky(&mut self, index: usize) -> io::Result<()> {
+        if index != self.active_sky {
+            if let Some(sky) = self.skies.get_mut(index) {
+                *sky = None;
+            }
+            return Ok(());
+        }
+        let faces = self.scene.skies[index].load()?;
+        self.skies[index] = Some(CubeMapMaterial::from_faces(&self.device, &self.queue, faces, self.texture_filtering)?);
+        self.rebuild_bind_groups();
+        self.reset_accumulation();
+        Ok(())
+    }
+
+    // Replaces the texture samplers, the textures and their mips stay as they are
+    fn apply_texture_filtering(&mut self) {
+        self.texture_filtering = self.scene.texture_filtering;
+        for sky in self.skies.iter_mut().flatten() {
+            sky.set_filtering(&self.device, self.texture_filtering);
+        }
+        self.object_textures.set_filtering(&self.device, self.texture_filtering);
+        self.rebuild_bind_groups();
+    }
+
+    /// Updates the scene and draws a frame of it, see `update` and `record`
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError>{
+        self.update();
+        self.record()
+    }
+
+    /// The CPU half of a frame: applies the scene's changes, rebuilding or refitting its tree, and
+    /// uploads it in a submission of its own. Called right after `record`, it runs while the GPU
+    /// still traces the frame before, which keeps animated scenes from waiting on either side.
+    pub fn update(&mut self) {
+        let _span = debug_span!("update").entered();
+        let start_time = Instant::now();
+        if self.device_lost.load(Ordering::Relaxed) {
+            self.recover_device();
+        }
+        self.read_metering();
+        // A new scale factor changes the window's size in physical pixels, which not every
+        // platform follows with a Resized event
+        for i in 0..self.viewports.len() {
+            let window_size = self.viewports[i].window.inner_size();
+            if window_size != self.viewports[i].size {
+                self.resize_view(i, window_size);
+            }
+        }
+        
+        if self.scene.active_sky != self.active_sky {
+            self.switch_sky();
+            self.reset_accumulation();
+        }
+        if self.scene.texture_filtering != self.texture_filtering {
+            self.apply_texture_filtering();
+            self.reset_accumulation();
+        }
+        // A new probe grid starts its probes over in an atlas of its size
+        if self.scene.probe_grid != self.probes.grid {
+            self.probes = create_probe_atlas(&self.device, &self.probe_pipeline, self.scene.probe_grid);
+            self.rebuild_bind_groups();
+            self.reset_accumulation();
+        }
+        if self.scene.dirty {
+            // Edits can move or add objects, so the tree has to be rebuilt before uploading
+            self.scene.make_scene();
+            match self.fit_scene_buffers() {
+                Ok(()) => self.oversized = false,
+                Err(e) => self.refuse_oversized_edit(e),
+            }
+            self.reset_accumulation();
+        } else if self.scene.moved {
+            // Nothing was added or removed, so the tree keeps its shape and the buffers their size
+            self.scene.refit_bvh();
+            self.reset_accumulation();
+        }
+        // Blocks of pixels share a sample until the view has settled, then the accumulation
+        // starts over at full resolution
+        let interacting = self.scene.interaction_mode && self.last_reset.elapsed() < INTERACTION_SETTLE_TIME;
+        if self.interacting && !interacting {
+            for viewport in &mut self.viewports {
+                viewport.frame_index = 0;
+            }
+        }
+        self.interacting = interacting;
+        self.keep_fade_history();
+        self.scene.block_size = if interacting { INTERACTION_BLOCK_SIZE } else { 1 };
+        // A converged render is presented as it is, without tracing or uploading anything
+        let idle = self.is_idle();
+        if !idle {
+            self.prepare_scene();
+        }
+        self.prepared = Some(idle);
+        self.update_time = start_time.elapsed();
+    }
+
+    /// The GPU half of a frame: traces and draws every viewport and presents them. Scene changes
+    /// since the last `update` are uploaded first, and accumulation resets in between make it
+    /// update again.
+    pub fn record(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let _span = debug_span!("record").entered();
+        self.read_stats();
+        let idle = match self.prepared.take() {
+            Some(idle) => idle,
+            None => {
+                self.update();
+                self.prepared.take().unwrap()
+            },
+        };
+
+        let start_time = Instant::now();
+        // Errors of the main window are passed on, other windows are configured again and skip the frame
+        let mut drawables = Vec::with_capacity(self.viewports.len());
+        for (i, viewport) in self.viewports.iter().enumerate() {
+            match viewport.surface.get_current_texture() {
+                Ok(drawable) => drawables.push(Some(drawable)),
+                Err(e) if i == 0 => return Err(e),
+                Err(_) => {
+                    viewport.surface.configure(&self.device, &viewport.config);
+                    drawables.push(None);
+                },
+            }
+        }
+        let image_view_descriptor = wgpu::TextureViewDescriptor::default();
+        let image_views: Vec<Option<TextureView>> = drawables.iter()
+            .map(|drawable| drawable.as_ref().map(|drawable| drawable.texture.create_view(&image_view_descriptor)))
+            .collect();
+        
+        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder")
+        };
+        let mut command_encoder = self.device.create_command_encoder(&command_encoder_descriptor);
+        
+        for pass in self.frame_graph.passes().to_vec() {
+            self.encode_frame_pass(pass, &mut command_encoder, &image_views, idle);
+        }
+        let stats_copied = self.copy_stats(&mut command_encoder);
+        let metered = self.encode_metering(&mut command_encoder);
+        
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+        self.queue.on_submitted_work_done(self.frame_times.push(self.update_time + start_time.elapsed()));
+        if stats_copied {
+            let mapped = Arc::new(AtomicBool::new(false));
+            let flag = mapped.clone();
+            self.stats_readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                flag.store(result.is_ok(), Ordering::Relaxed);
+            });
+            let seconds = self.stats_counted_since.elapsed().as_secs_f64();
+            self.stats_counted_since = Instant::now();
+            self.stats_pending = Some((seconds, mapped));
+        }
+        if metered {
+            let mapped = Arc::new(AtomicBool::new(false));
+            let flag = mapped.clone();
+            self.metering_readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                flag.store(result.is_ok(), Ordering::Relaxed);
+            });
+            self.metering_pending = Some(mapped);
+        }
+        if !idle {
+            for viewport in &mut self.viewports {
+                viewport.frame_index += 1;
+            }
+            self.traced = (self.scene.camera, self.viewports[0].frame_index);
+        }
+        
+        for drawable in drawables.into_iter().flatten() {
+            drawable.present();
+        }
+        self.presented = self.presented.wrapping_add(1);
+        Ok(())
+    }
+
+    // Encodes one pass of the frame graph. The image views are of every viewport's window, None
+    // for those skipping the frame, and the main window's always comes first.
+    fn encode_frame_pass(&mut self, pass: FramePass, command_encoder: &mut wgpu::CommandEncoder, image_views: &[Option<TextureView>], idle: bool) {
+        let _span = debug_span!("frame_pass", ?pass).entered();
+        match pass {
+            // A converged render is presented as it is, without tracing anything
+            FramePass::RayTrace => if !idle {
+                self.encode_probe_pass(command_encoder);
+                for viewport in &self.viewports {
+                    if self.scene.raster_primary {
+                        self.encode_raster_pass(command_encoder, viewport);
+                    }
+                    self.encode_ray_trace_pass(command_encoder, viewport);
+                }
+                self.encode_fade_pass(command_encoder);
+            },
+            #[cfg(feature = "editor")]
+            FramePass::MaterialPreview => self.encode_preview_pass(command_encoder),
+            FramePass::Blit => {
+                let data = self.scene.post_process.uniform_data(self.presented);
+                self.queue.write_buffer(&self.post_process_buffer, 0, bytemuck::cast_slice(&data));
+                for (viewport, image_view) in self.viewports.iter().zip(image_views) {
+                    if let Some(image_view) = image_view {
+                        self.encode_screen_pass(command_encoder, viewport, image_view);
+                    }
+                }
+            },
+            // The progress bar, the frame time graph and the panels only go on the main window
+            FramePass::Progress => if let (Some(_), Some(main_view)) = (self.loading_progress, &image_views[0]) {
+                self.encode_progress_pass(command_encoder, main_view);
+            },
+            FramePass::FrameTimes => if let (true, Some(main_view)) = (self.show_frame_times, &image_views[0]) {
+                let data = self.frame_times.uniform_data();
+                self.queue.write_buffer(&self.frame_time_buffer, 0, bytemuck::cast_slice(&data));
+                encode_overlay_pass(command_encoder, main_view, &self.frame_time_pipeline, &self.frame_time_bind_group, "Frame Time Pass");
+            },
+            // Panels go on top of the render, their edits are uploaded with the next frame
+            #[cfg(feature = "editor")]
+            FramePass::Ui => if let Some(main_view) = &image_views[0] {
+                self.editor.draw(&self.device, &self.queue, command_encoder, main_view, self.window, &mut self.scene, self.ray_stats);
+            },
+        }
+    }
+
+    // Draws the viewport's color buffer over the whole of its window
+    fn encode_screen_pass(&self, command_encoder: &mut wgpu::CommandEncoder, viewport: &Viewport, image_view: &TextureView) {
+        let color_attachment = wgpu::RenderPassColorAttachment {
+            view: image_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.75,
+                    g: 0.5,
+                    b: 0.25,
+                    a: 1.0
+                }),
+                store: wgpu::StoreOp::Store,
+            },
+        };
+        
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(color_attachment)],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        };
+        // Begin the render pass and set up for drawing
+        let mut render_pass = command_encoder.begin_render_pass(&render_pass_descriptor);
+        render_pass.set_pipeline(&self.screen_pipeline); // Set the screen rendering pipeline
+        render_pass.set_bind_group(0, &viewport.screen_bind_group, &[]); // Set the bind group
+        render_pass.draw(0..6, 0..1);
+    }
+
+    // Replaces a lost device with a new one on the same adapter, or on the one wgpu prefers when
+    // that is gone too, then recreates every buffer, texture and pipeline from the scene. The
+    // scene itself is uploaded again by the next frame like any other.
+    fn recover_device(&mut self) {
+        // Only devices made with an instance of the renderer's own are watched for loss
+        let instance = self.instance.as_ref().unwrap();
+        let main_surface = &self.viewports[0].surface;
+        let adapter = enumerate_adapters(instance).into_iter()
+            .find(|adapter| adapter.get_info().name == self.adapter_name && adapter.is_surface_supported(main_surface))
+            .or_else(|| pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                compatible_surface: Some(main_surface),
+                ..Default::default()
+            })))
+            .expect("No adapter left to recover the lost device on");
+        warn!("Recreating the device on {}", describe_adapter(&adapter));
+
+        let (device, queue) = pollster::block_on(init_device_and_queue(&adapter));
+        self.device_lost = watch_device_loss(&device);
+        self.device = Arc::new(device);
+        self.queue = Arc::new(queue);
+        self.push_constants = supports_push_constants(&self.device);
+        if adapter.get_info().name != self.adapter_name {
+            self.adapter_name = adapter.get_info().name;
+            let cached_workgroup_size = load_workgroup_size(&workgroup_cache_path(), &self.adapter_name);
+            self.workgroup_size = cached_workgroup_size.unwrap_or(DEFAULT_WORKGROUP_SIZE);
+            self.workgroup_size_tuned = cached_workgroup_size.is_some();
+        }
+        self.adapter = adapter;
+
+        self.sampler = create_sampler(&self.device);
+        self.stats_buffer = create_stats_buffer(&self.device);
+        self.stats_readback = create_stats_readback(&self.device);
+        self.post_process_buffer = create_post_process_buffer(&self.device);
+        self.stats_counted_since = Instant::now();
+        self.stats_pending = None;
+        (self.metering_buffer, self.metering_readback) = create_metering_buffers(&self.device);
+        self.metering_pending = None;
+        self.exposure_pipeline = create_exposure_pipeline(&self.device);
+        self.fade_pipeline = create_fade_pipeline(&self.device);
+        self.fade_buffer = create_fade_buffer(&self.device);
+        self.fade_history = None;
+        self.traced.1 = 0;
+        self.object_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Objects);
+        self.node_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Nodes);
+        self.object_index_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::ObjectIndices);
+        self.material_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Materials);
+        self.point_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Points);
+        // Skies other than the active one are loaded again when they are picked
+        self.active_sky = self.scene.active_sky;
+        self.skies = self.scene.skies.iter().map(|_| None).collect();
+        self.skies[self.active_sky] = Some(create_sky(&self.device, &self.queue, &self.scene));
+        self.texture_filtering = self.scene.texture_filtering;
+        self.object_textures = create_object_textures(&self.device, &self.queue, &self.scene);
+        self.uploaded_labels = self.scene.labels.len();
+
+        let (ray_tracing_bind_group_layout,
+            screen_bind_group_layout) = pollster::block_on(make_bind_group_layouts(&self.device));
+        self.ray_tracing_bind_group_layout = ray_tracing_bind_group_layout;
+        self.screen_bind_group_layout = screen_bind_group_layout;
+        let (ray_tracing_pipeline,
+            photon_pipeline,
+            screen_pipeline) = pollster::block_on(make_pipeline(&self.device, &self.ray_tracing_bind_group_layout, &self.screen_bind_group_layout, self.workgroup_size, self.push_constants, self.viewports[0].config.format));
+        self.ray_tracing_pipeline = ray_tracing_pipeline;
+        self.photon_pipeline = photon_pipeline;
+        self.screen_pipeline = screen_pipeline;
+        // The probes start over on the new device
+        self.probe_pipeline = create_probe_pipeline(&self.device, &self.ray_tracing_bind_group_layout, self.push_constants);
+        self.probes = create_probe_atlas(&self.device, &self.probe_pipeline, self.scene.probe_grid);
+        self.raster_pipeline = create_raster_pipeline(&self.device);
+
+        // Every window keeps its surface, camera and format, which the screen pipeline was made for,
+        // and the present mode picked with V when the new adapter supports it
+        for viewport in std::mem::take(&mut self.viewports) {
+            let (mut config, present_modes) = init_surface_configuration(&self.adapter, &viewport.surface, &viewport.size);
+            config.format = viewport.config.format;
+            if present_modes.contains(&viewport.config.present_mode) {
+                config.present_mode = viewport.config.present_mode;
+            }
+            let recreated = self.create_viewport(viewport.window, viewport.surface, config, present_modes, viewport.camera);
+            self.viewports.push(recreated);
+        }
+        let format = self.viewports[0].config.format;
+        let (progress_pipeline,
+            progress_bind_group,
+            progress_buffer) = create_overlay_pipeline(&self.device, format, "Progress", include_str!("../../shaders/progress_shader.wgsl"), 16);
+        self.progress_pipeline = progress_pipeline;
+        self.progress_bind_group = progress_bind_group;
+        self.progress_buffer = progress_buffer;
+        let (frame_time_pipeline,
+            frame_time_bind_group,
+            frame_time_buffer) = create_overlay_pipeline(&self.device, format, "Frame Time", include_str!("../../shaders/frame_time_shader.wgsl"), FRAME_TIMES_UNIFORM_SIZE);
+        self.frame_time_pipeline = frame_time_pipeline;
+        self.frame_time_bind_group = frame_time_bind_group;
+        self.frame_time_buffer = frame_time_buffer;
+
+        #[cfg(feature = "editor")]
+        {
+            self.preview = pollster::block_on(create_material_preview(&self.device));
+            self.editor.recreate_renderer(&self.device, format, &self.preview.color_buffer_view);
+        }
+        self.reset_accumulation();
+    }
+
+    /// Presents the main window's frames with the given mode when its surface supports it,
+    /// returning whether it does
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> bool {
+        let main = &mut self.viewports[0];
+        if !main.present_modes.contains(&mode) {
+            return false;
+        }
+        main.config.present_mode = mode;
+        main.surface.configure(&self.device, &main.config);
+        true
+    }
+
+    /// Switches the main window to the next present mode its surface supports and returns it
+    pub fn cycle_present_mode(&mut self) -> wgpu::PresentMode {
+        let main = &self.viewports[0];
+        let current = main.present_modes.iter().position(|&mode| mode == main.config.present_mode);
+        if let Some(&mode) = current.and_then(|i| main.present_modes.get((i + 1) % main.present_modes.len())) {
+            self.set_present_mode(mode);
+        }
+        self.viewports[0].config.present_mode
+    }
+
+    /// Samples per pixel the main window accumulated since the render last restarted
+    pub fn sample_count(&self) -> u32 {
+        self.viewports[0].frame_index
+    }
+
+    /// Rays traced and work done per second over the last second or so, None until the kernel's
+    /// counters have been read back once
+    pub fn ray_stats(&self) -> Option<RayStats> {
+        self.ray_stats
+    }
+
+    /// Millions of rays traced since the renderer started, and the seconds they were counted over
+    pub fn ray_totals(&self) -> (f64, f64) {
+        self.rays_traced
+    }
+
+    // Copies the counters out and clears them about once a second, unless the last copy is still
+    // being read back. Returns whether a copy was encoded.
+    fn copy_stats(&mut self, command_encoder: &mut wgpu::CommandEncoder) -> bool {
+        if self.stats_pending.is_some() || self.stats_counted_since.elapsed() < Duration::from_secs(1) {
+            return false;
+        }
+        command_encoder.copy_buffer_to_buffer(&self.stats_buffer, 0, &self.stats_readback, 0, STATS_BUFFER_SIZE);
+        command_encoder.clear_buffer(&self.stats_buffer, 0, None);
+        true
+    }
+
+    // Takes the counters of the last copy once the GPU is done with it, without waiting for it
+    fn read_stats(&mut self) {
+        self.device.poll(wgpu::Maintain::Poll);
+        let Some((seconds, mapped)) = &self.stats_pending else { return };
+        if !mapped.load(Ordering::Relaxed) {
+            return;
+        }
+        let counters = self.stats_readback.slice(..).get_mapped_range();
+        let stats = RayStats::from_counters(bytemuck::cast_slice(&counters), *seconds);
+        self.rays_traced.0 += stats.mrays_per_second() * seconds;
+        self.rays_traced.1 += seconds;
+        self.ray_stats = Some(stats);
+        drop(counters);
+        self.stats_readback.unmap();
+        self.stats_pending = None;
+    }
+
+    // Copies the main window's accumulation aside when the camera moved away from where it was
+    // traced, once it has enough samples to be worth fading from. Otherwise the frame kept before
+    // stays, so moving on after a short stop still fades from the last converged view.
+    fn keep_fade_history(&mut self) {
+        let (camera, samples) = self.traced;
+        if self.scene.fade_duration <= 0.0 || camera == self.scene.camera || samples < FADE_HISTORY_SAMPLES {
+            return;
+        }
+        self.traced.1 = 0;
+        let main = &self.viewports[0];
+        let buffer = match self.fade_history.take() {
+            Some(history) if history.buffer.size() == main.accumulation_buffer.size() => history.buffer,
+            _ => self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Fade History Buffer"),
+                size: main.accumulation_buffer.size(),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        };
+        // Submitted on its own, ahead of the frame that traces over the accumulation
+        let mut command_encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Fade History Encoder")
+        });
+        command_encoder.copy_buffer_to_buffer(&main.accumulation_buffer, 0, &buffer, 0, buffer.size());
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+        self.fade_history = Some(FadeHistory { buffer, camera, kept_at: Instant::now() });
+    }
+
+    // Blends the kept frame, reprojected to the current camera, over the main window's new samples
+    // while the fade lasts
+    fn encode_fade_pass(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        let Some(history) = &self.fade_history else { return };
+        let weight = fade_weight(history.kept_at.elapsed(), self.scene.fade_duration);
+        // The heat map's colors aren't radiance to blend
+        if weight <= 0.0 || self.scene.bvh_heatmap || !can_reproject(&history.camera, &self.scene.camera) {
+            return;
+        }
+        let data = fade_uniform_data(&history.camera, &self.scene.camera, weight, self.scene.exposure_scale());
+        self.queue.write_buffer(&self.fade_buffer, 0, bytemuck::cast_slice(&data));
+
+        let main = &self.viewports[0];
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fade Bind Group"),
+            layout: &self.fade_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: history.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: main.accumulation_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&main.color_buffer_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.fade_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut fade_pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Fade Pass"),
+            timestamp_writes: None,
+        });
+        fade_pass.set_pipeline(&self.fade_pipeline);
+        fade_pass.set_bind_group(0, &bind_group, &[]);
+        fade_pass.dispatch_workgroups(main.size.width.div_ceil(8), main.size.height.div_ceil(8), 1);
+    }
+
+    // Meters the main window's accumulated radiance for auto exposure and copies it out, unless the
+    // last metering is still being read back. Returns whether it was encoded.
+    fn encode_metering(&self, command_encoder: &mut wgpu::CommandEncoder) -> bool {
+        if !self.scene.auto_exposure || self.metering_pending.is_some() {
+            return false;
+        }
+        let main = &self.viewports[0];
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Metering Bind Group"),
+            layout: &self.exposure_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: main.accumulation_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.metering_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        command_encoder.clear_buffer(&self.metering_buffer, 0, None);
+        {
+            let mut metering_pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Metering Pass"),
+                timestamp_writes: None,
+            });
+            metering_pass.set_pipeline(&self.exposure_pipeline);
+            metering_pass.set_bind_group(0, &bind_group, &[]);
+            let pixels = main.size.width * main.size.height;
+            metering_pass.dispatch_workgroups(pixels.div_ceil(METERING_PIXELS_PER_WORKGROUP).max(1), 1, 1);
+        }
+        command_encoder.copy_buffer_to_buffer(&self.metering_buffer, 0, &self.metering_readback, 0, METERING_BUFFER_SIZE);
+        true
+    }
+
+    // Adapts the exposure to the last metering once the GPU is done with it, without waiting for
+    // it. Turning auto exposure off forgets what the view was adapted to.
+    fn read_metering(&mut self) {
+        if !self.scene.auto_exposure {
+            self.exposure = AutoExposure::default();
+        }
+        let Some(mapped) = &self.metering_pending else { return };
+        if !mapped.load(Ordering::Relaxed) {
+            return;
+        }
+        let metering = self.metering_readback.slice(..).get_mapped_range();
+        let log_average = log_average_luminance(bytemuck::cast_slice(&metering));
+        drop(metering);
+        self.metering_readback.unmap();
+        self.metering_pending = None;
+
+        let elapsed = self.metered_at.elapsed();
+        self.metered_at = Instant::now();
+        if let (true, Some(log_average)) = (self.scene.auto_exposure, log_average) {
+            self.scene.adapted_exposure = self.exposure.adapt(log_average, elapsed);
+        }
+    }
+
+    /// Whether every viewport has converged past `idle_samples` and nothing changed since, in which
+    /// case frames only need drawing when the window asks for them
+    pub fn is_idle(&self) -> bool {
+        #[cfg(feature = "editor")]
+        if self.preview.shown.is_some() && self.preview.frame_index < PREVIEW_SAMPLES {
+            return false;
+        }
+        self.idle_samples > 0
+            && self.viewports.iter().all(|viewport| viewport.frame_index >= self.idle_samples)
+            && !self.interacting
+            && self.loading_progress.is_none()
+            && !self.scene.dirty
+            && !self.scene.moved
+            && self.scene.active_sky == self.active_sky
+            && self.scene.texture_filtering == self.texture_filtering
+            && (!self.scene.auto_exposure || self.exposure.settled())
+    }
+
+    /// Shows a progress bar over the frame while a scene loads in the background, None hides it
+    pub fn set_loading_progress(&mut self, fraction: Option<f32>) {
+        self.loading_progress = fraction;
+    }
+
+    /// Swaps in a scene prepared by a `SceneLoader`, uploading its sky and textures and
+    /// recreating the scene buffers to fit it. A scene too large for the device is turned away
+    /// and the current one kept.
+    pub fn replace_scene(&mut self, loaded: LoadedScene) -> Result<(), BufferLimitError> {
+        let LoadedScene { scene, sky, images } = loaded;
+        check_scene_limits(&scene, &self.device.limits())?;
+        self.scene = scene;
+        self.oversized = false;
+        // The window may have been resized or moved to another monitor while the scene loaded
+        let size = self.viewports[0].size;
+        self.scene.camera.set_aspect_ratio(size.width as f32 / size.height as f32);
+
+        self.texture_filtering = self.scene.texture_filtering;
+        self.active_sky = self.scene.active_sky;
+        self.skies = self.scene.skies.iter().map(|_| None).collect();
+        self.skies[self.active_sky] = Some(CubeMapMaterial::from_faces(&self.device, &self.queue, sky, self.texture_filtering).expect("Failed to load sky"));
+        self.object_textures = TextureArrayMaterial::new(&self.device, &self.queue, images, self.texture_filtering);
+        self.uploaded_labels = self.scene.labels.len();
+
+        self.object_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Objects);
+        self.node_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Nodes);
+        self.object_index_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::ObjectIndices);
+        self.material_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Materials);
+        self.point_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Points);
+        self.rebuild_bind_groups();
+        // The placeholder is too light to tell the workgroup sizes apart, the first real scene isn't
+        if !self.workgroup_size_tuned {
+            self.tune_workgroup_size();
+        }
+        self.reset_accumulation();
+
+        // Handles into the placeholder scene mean nothing in the new one
+        #[cfg(feature = "editor")]
+        {
+            self.editor.select(None, false);
+        }
+        Ok(())
+    }
+
+    // Times a few frames of the current scene with every workgroup size, keeps the fastest and
+    // caches it for the adapter so later runs skip the benchmark
+    fn tune_workgroup_size(&mut self) {
+        const FRAMES: u32 = 4;
+        self.prepare_scene();
+        let main = &self.viewports[0];
+        let mut fastest: Option<(Duration, (u32, u32), wgpu::ComputePipeline)> = None;
+        for size in WORKGROUP_SIZES {
+            let pipeline = create_ray_compute_pipeline(&self.device, &[&self.ray_tracing_bind_group_layout], "main", size, self.push_constants);
+            // The first dispatch also pays for compiling the pipeline, so it isn't timed
+            let mut elapsed = Duration::ZERO;
+            for frame in 0..=FRAMES {
+                let start = Instant::now();
+                let mut command_encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Workgroup Benchmark Encoder")
+                });
+                {
+                    let mut pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                        label: Some("Workgroup Benchmark Pass"),
+                        timestamp_writes: None,
+                    });
+                    pass.set_pipeline(&pipeline);
+                    pass.set_bind_group(0, &main.ray_tracing_bind_group, &[]);
+                    if self.push_constants {
+                        pass.set_push_constants(0, bytemuck::cast_slice(&frame_constants(&self.scene.camera, main.frame_index)));
+                    }
+                    pass.dispatch_workgroups(main.size.width.div_ceil(size.0), main.size.height.div_ceil(size.1), 1);
+                }
+                self.queue.submit(std::iter::once(command_encoder.finish()));
+                self.device.poll(wgpu::Maintain::Wait);
+                if frame > 0 {
+                    elapsed += start.elapsed();
+                }
+            }
+            debug!("Workgroup size {}x{}: {:?} per frame", size.0, size.1, elapsed / FRAMES);
+            if fastest.as_ref().is_none_or(|(best, _, _)| elapsed < *best) {
+                fastest = Some((elapsed, size, pipeline));
+            }
+        }
+
+        let (_, size, pipeline) = fastest.unwrap();
+        self.ray_tracing_pipeline = pipeline;
+        self.workgroup_size = size;
+        self.workgroup_size_tuned = true;
+        info!("Using workgroup size {}x{} on {}", size.0, size.1, self.adapter_name);
+        if let Err(e) = save_workgroup_size(&workgroup_cache_path(), &self.adapter_name, size) {
+            warn!("Failed to cache the workgroup size: {}", e);
+        }
+    }
+
+    fn encode_progress_pass(&self, command_encoder: &mut wgpu::CommandEncoder, view: &TextureView) {
+        let fraction = self.loading_progress.unwrap_or(0.0);
+        self.queue.write_buffer(&self.progress_buffer, 0, bytemuck::cast_slice(&[fraction, 0.0, 0.0, 0.0]));
+        encode_overlay_pass(command_encoder, view, &self.progress_pipeline, &self.progress_bind_group, "Progress Pass");
+    }
+
+    /// The CPU and GPU times of the last frames, oldest first
+    pub fn frame_times(&mut self) -> &VecDeque<FrameTime> {
+        self.frame_times.frames()
+    }
+
+    /// Passes a window event to the editor panels, returning true when they used it
+    #[cfg(feature = "editor")]
+    pub fn editor_event(&mut self, event: &winit::event::WindowEvent) -> bool {
+        self.editor.on_window_event(self.window, event)
+    }
+
+    /// Selects the object under the pixel for the material editor, or clears the selection over the
+    /// sky. With `extend` the object is added to the selection or taken out of it instead.
+    #[cfg(feature = "editor")]
+    pub fn select_at(&mut self, x: u32, y: u32, extend: bool) {
+        let picked = self.pick(x, y);
+        self.editor.select(picked, extend);
+    }
+
+    /// Carries out a console command through the same scene edits, settings and captures as the
+    /// panels and shortcuts, returning what to print. Scripts are loaded by the caller, which owns
+    /// the scene loader, and `wait` only holds back the commands queued after it.
+    pub fn run_command(&mut self, command: &Command) -> Result<String, String> {
+        match command {
+            Command::Spawn { shape, position, size } => {
+                let color = Vec3(0.8, 0.8, 0.8);
+                let id = match shape {
+                    Shape::Sphere => self.scene.add_sphere(*position, color, *size),
+                    Shape::Square => self.scene.add_square(*position, *size, *size, color, 0.0),
+                };
+                self.scene.dirty = true;
+                self.scene.history.push(Edit::Added(self.scene.snapshot(id).unwrap()));
+                Ok(format!("Added object {}", id.0))
+            },
+            Command::Set { setting, value } => {
+                let scene = &mut self.scene;
+                match setting {
+                    Setting::Bounces => {
+                        scene.max_diffuse_bounces = *value as usize;
+                        scene.max_specular_bounces = *value as usize;
+                    },
+                    Setting::DiffuseBounces => scene.max_diffuse_bounces = *value as usize,
+                    Setting::SpecularBounces => scene.max_specular_bounces = *value as usize,
+                    Setting::SkyIntensity => scene.sky_intensity = value.max(0.0),
+                    Setting::SkyYaw => scene.sky_yaw = value.rem_euclid(360.0),
+                    Setting::MaxRadiance => scene.max_radiance = value.max(0.0),
+                    Setting::IdleSamples => self.idle_samples = *value as u32,
+                    Setting::Spectral => scene.spectral = *value != 0.0,
+                    Setting::Caustics => scene.caustics = *value != 0.0,
+                    Setting::BvhHeatmap => scene.bvh_heatmap = *value != 0.0,
+                    Setting::Bidirectional => scene.bidirectional = *value != 0.0,
+                    Setting::Exposure => scene.exposure = *value,
+                    Setting::AutoExposure => scene.auto_exposure = *value != 0.0,
+                    Setting::PostEffects => scene.post_process.enabled = *value != 0.0,
+                    Setting::Fade => scene.fade_duration = value.max(0.0),
+                    Setting::Probes => scene.fit_probe_grid(value.max(0.0)),
+                    Setting::Hybrid => scene.raster_primary = *value != 0.0,
+                    Setting::FrustumCulling => scene.frustum_culling = *value != 0.0,
+                    Setting::Stackless => scene.stackless_traversal = *value != 0.0,
+                }
+                self.reset_accumulation();
+                Ok(format!("{:?} set to {}", setting, value))
+            },
+            Command::Load(path) => {
+                let extension = Path::new(path).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+                if !matches!(extension.as_deref(), Some("obj" | "stl" | "ply")) {
+                    return Err(format!("{} is not an OBJ, STL or PLY mesh", path));
+                }
+                let mesh = ObjMesh::load(Vec3(1.0, 1.0, 1.0), path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+                let id = self.scene.add_obj_mesh(mesh);
+                self.scene.dirty = true;
+                self.scene.history.push(Edit::Added(self.scene.snapshot(id).unwrap()));
+                Ok(format!("Added {} as object {}", path, id.0))
+            },
+            Command::Screenshot(path) => {
+                let path = path.clone().unwrap_or_else(|| {
+                    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+                    format!("screenshot-{}.png", seconds)
+                });
+                self.capture(&path, CaptureFormat::from_path(&path)).map_err(|e| format!("Failed to save {}: {}", path, e))?;
+                Ok(format!("Saved {} with {} samples", path, self.sample_count()))
+            },
+            Command::Wait(samples) => Ok(format!("{} of {} samples", self.sample_count(), samples)),
+            Command::Undo => self.scene.undo().then(|| "Undone".to_string()).ok_or("Nothing to undo".to_string()),
+            Command::Redo => self.scene.redo().then(|| "Redone".to_string()).ok_or("Nothing to redo".to_string()),
+            Command::Help => Ok(COMMAND_HELP.to_string()),
+        }
+    }
+
+    /// Prints a command's output, in the console as well when there is one
+    pub fn console_print(&mut self, message: &str) {
+        println!("{}", message);
+        #[cfg(feature = "editor")]
+        self.editor.console.print(message);
+    }
+
+    /// Commands typed into the console since the last call
+    #[cfg(feature = "editor")]
+    pub fn take_console_commands(&mut self) -> Vec<Command> {
+        self.editor.console.take_commands()
+    }
+
+    /// Remembers the selected objects for `paste`
+    #[cfg(feature = "editor")]
+    pub fn copy_selected(&mut self) {
+        self.editor.copy(&self.scene);
+    }
+
+    /// Adds another copy of the last copied objects
+    #[cfg(feature = "editor")]
+    pub fn paste(&mut self) {
+        self.editor.paste(&mut self.scene);
+    }
+
+    /// Copies the selected objects next to themselves, meshes as instances sharing their triangles
+    #[cfg(feature = "editor")]
+    pub fn duplicate_selected(&mut self) {
+        self.editor.duplicate_selected(&mut self.scene);
+    }
+
+    // Traces the material preview with one more sample, starting over when the edited material
+    // or the sky changed and stopping once it has converged
+    #[cfg(feature = "editor")]
+    fn encode_preview_pass(&mut self, command_encoder: &mut wgpu::CommandEncoder) {
+        let Some((material, color)) = self.editor.previewed_material(&self.scene) else { return };
+        let preview = &mut self.preview;
+
+        let sky_changed = preview.scene.active_sky != self.scene.active_sky
+            || preview.scene.sky_yaw != self.scene.sky_yaw
+            || preview.scene.sky_intensity != self.scene.sky_intensity;
+        if preview.shown != Some((material, color)) || sky_changed {
+            // Spheres take their image from the object's texture rather than the material
+            preview.scene.materials[0] = material;
+            preview.scene.set_color(preview.sphere, color);
+            preview.scene.set_texture(preview.sphere, material.albedo_texture.map_or(Texture::Solid, Texture::Image));
+            preview.scene.image_paths.clone_from(&self.scene.image_paths);
+            preview.scene.active_sky = self.scene.active_sky;
+            preview.scene.sky_yaw = self.scene.sky_yaw;
+            preview.scene.sky_intensity = self.scene.sky_intensity;
+            preview.shown = Some((material, color));
+            preview.frame_index = 0;
+        }
+        if preview.frame_index >= PREVIEW_SAMPLES {
+            return;
+        }
+
+        self.queue.write_buffer(&preview.scene_parameters, 0, &preview.scene.flatten_scene_data(preview.frame_index));
+        self.queue.write_buffer(&preview.object_buffer, 0, &preview.scene.flatten_object_data());
+        self.queue.write_buffer(&preview.node_buffer, 0, &preview.scene.flatten_node_data());
+        self.queue.write_buffer(&preview.object_index_buffer, 0, &preview.scene.flatten_object_index_data());
+        self.queue.write_buffer(&preview.material_buffer, 0, &preview.scene.flatten_material_data());
+        self.queue.write_buffer(&preview.point_buffer, 0, &preview.scene.flatten_point_data());
+
+        // The sky and object textures are shared with the main render and can be swapped out
+        // at any time, so the bind group is made fresh
+        let (ray_tracing_bind_group, _) = pollster::block_on(make_bind_groups(&self.device, &preview.color_buffer_view, &self.sampler, &self.post_process_buffer, &preview.scene_parameters, &preview.object_buffer, &preview.node_buffer, &preview.object_index_buffer, &preview.material_buffer, &preview.point_buffer, &preview.photon_buffer, &self.stats_buffer, &self.ray_tracing_bind_group_layout, &self.screen_bind_group_layout, self.skies[self.active_sky].as_ref().unwrap(), &preview.aov_buffer, &preview.accumulation_buffer, &self.object_textures, &self.probes.view, &self.viewports[0].raster.visibility_view));
+
+        let mut preview_pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Material Preview Pass"),
+            timestamp_writes: None,
+        });
+        preview_pass.set_pipeline(&self.ray_tracing_pipeline);
+        preview_pass.set_bind_group(0, &ray_tracing_bind_group, &[]);
+        if self.push_constants {
+            preview_pass.set_push_constants(0, bytemuck::cast_slice(&frame_constants(&preview.scene.camera, preview.frame_index)));
+        }
+        preview_pass.dispatch_workgroups(PREVIEW_SIZE.div_ceil(self.workgroup_size.0), PREVIEW_SIZE.div_ceil(self.workgroup_size.1), 1);
+        preview.frame_index += 1;
+    }
+
+    /// Discards the accumulated samples of every viewport, e.g. after the camera or scene changed
+    pub fn reset_accumulation(&mut self) {
+        for viewport in &mut self.viewports {
+            viewport.frame_index = 0;
+        }
+        self.last_reset = Instant::now();
+        self.prepared = None;
+    }
+
+    /// Saves the main window's accumulated radiance in the given format
+    pub fn capture(&self, path: &str, format: CaptureFormat) -> image::ImageResult<()> {
+        let main = &self.viewports[0];
+        let accumulation_bytes = read_buffer(&self.device, &self.queue, &main.accumulation_buffer);
+        let accumulation: &[f32] = bytemuck::cast_slice(&accumulation_bytes);
+        save_radiance(path, format, main.color_buffer.width(), main.color_buffer.height(), accumulation)
+    }
+
+    fn encode_ray_trace_pass(&self, command_encoder: &mut wgpu::CommandEncoder, viewport: &Viewport) {
+        if self.scene.caustics || self.scene.bvh_heatmap {
+            self.encode_photon_pass(command_encoder, viewport);
+        }
+        let ray_trace_pass_descriptor = wgpu::ComputePassDescriptor {
+            label: Some("Ray Pass Descriptor"),
+            timestamp_writes: None,
+        };
+        let mut ray_trace_pass = command_encoder.begin_compute_pass(&ray_trace_pass_descriptor);
+        ray_trace_pass.set_pipeline(&self.ray_tracing_pipeline);
+        ray_trace_pass.set_bind_group(0, &viewport.ray_tracing_bind_group, &[]);
+        self.push_frame_constants(&mut ray_trace_pass, viewport);
+        let block = self.scene.block_size;
+        let (width, height) = self.workgroup_size;
+        ray_trace_pass.dispatch_workgroups(viewport.size.width.div_ceil(width * block), viewport.size.height.div_ceil(height * block), 1);
+    }
+
+    // Draws the triangles in front of the viewport's camera into its visibility texture, for the
+    // kernel to start the camera rays from. Cameras that can't be rasterized leave it as it is,
+    // the kernel doesn't read it for them.
+    fn encode_raster_pass(&self, command_encoder: &mut wgpu::CommandEncoder, viewport: &Viewport) {
+        let camera = viewport.camera.as_ref().unwrap_or(&self.scene.camera);
+        let Some(data) = raster_uniform_data(camera) else { return };
+        self.queue.write_buffer(&viewport.raster_buffer, 0, bytemuck::cast_slice(&data));
+
+        let mut raster_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Raster Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &viewport.raster.visibility_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            // The depth is reversed, so it starts at 0 and nearer triangles are greater
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &viewport.raster.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        raster_pass.set_pipeline(&self.raster_pipeline);
+        raster_pass.set_bind_group(0, &viewport.raster_bind_group, &[]);
+        raster_pass.draw(0..self.scene.objects.len() as u32 * 3, 0..1);
+    }
+
+    // Adds this frame's caustic photons to the viewport's photon map, emptied whenever its accumulation
+    // restarts. The BVH heat map samples the costs of the view into it instead, emptied every frame.
+    fn encode_photon_pass(&self, command_encoder: &mut wgpu::CommandEncoder, viewport: &Viewport) {
+        if viewport.frame_index == 0 || self.scene.bvh_heatmap {
+            command_encoder.clear_buffer(&viewport.photon_buffer, 0, None);
+        }
+        let mut photon_pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Photon Pass"),
+            timestamp_writes: None,
+        });
+        photon_pass.set_pipeline(&self.photon_pipeline);
+        photon_pass.set_bind_group(0, &viewport.ray_tracing_bind_group, &[]);
+        self.push_frame_constants(&mut photon_pass, viewport);
+        photon_pass.dispatch_workgroups(PHOTON_WORKGROUPS, PHOTON_WORKGROUPS, 1);
+    }
+
+    // Traces a few paths from every probe of the grid and blends their light into the probe, then
+    // copies the atlas over the one the kernel reads so this frame's samples already see it
+    fn encode_probe_pass(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        let Some(grid) = self.probes.grid else { return };
+        let main = &self.viewports[0];
+        {
+            let mut probe_pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Probe Pass"),
+                timestamp_writes: None,
+            });
+            probe_pass.set_pipeline(&self.probe_pipeline);
+            probe_pass.set_bind_group(0, &main.ray_tracing_bind_group, &[]);
+            probe_pass.set_bind_group(1, &self.probes.target_bind_group, &[]);
+            self.push_frame_constants(&mut probe_pass, main);
+            probe_pass.dispatch_workgroups(grid.count().div_ceil(PROBE_WORKGROUP_SIZE), 1, 1);
+        }
+        command_encoder.copy_texture_to_texture(self.probes.target.as_image_copy(), self.probes.texture.as_image_copy(), self.probes.texture.size());
+    }
+
+    // Hands the viewport's camera and frame index to the dispatches that follow, when the device
+    // takes push constants. Otherwise the kernel reads them from the scene uniform.
+    fn push_frame_constants(&self, pass: &mut wgpu::ComputePass, viewport: &Viewport) {
+        if self.push_constants {
+            let camera = viewport.camera.as_ref().unwrap_or(&self.scene.camera);
+            pass.set_push_constants(0, bytemuck::cast_slice(&frame_constants(camera, viewport.frame_index)));
+        }
+    }
+
+    /// Traces the current view with auxiliary outputs enabled and saves them next to `path_prefix`:
+    /// `_albedo.exr`, `_normal.exr`, `_depth.exr` and `_object_id.exr` as float images. The object
+    /// id is 0 where the sky shows and the object index plus one elsewhere, exact up to 2^24 objects.
+    pub fn export_aovs(&mut self, path_prefix: &str) -> image::ImageResult<()> {
+        let aov_data = self.trace_aovs();
+
+        let (width, height) = (self.viewports[0].size.width, self.viewports[0].size.height);
+        let mut albedo = ImageBuffer::<Rgb<f32>, Vec<f32>>::new(width, height);
+        let mut normal = ImageBuffer::<Rgb<f32>, Vec<f32>>::new(width, height);
+        let mut depth = ImageBuffer::<Rgb<f32>, Vec<f32>>::new(width, height);
+        let mut object_id = ImageBuffer::<Rgb<f32>, Vec<f32>>::new(width, height);
+
+        // Each sample is albedo.xyz, object id, normal.xyz, depth
+        for (i, sample) in aov_data.chunks_exact(8).enumerate() {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            albedo.put_pixel(x, y, Rgb([sample[0], sample[1], sample[2]]));
+            object_id.put_pixel(x, y, Rgb([sample[3] + 1.0; 3]));
+            normal.put_pixel(x, y, Rgb([sample[4], sample[5], sample[6]]));
+            depth.put_pixel(x, y, Rgb([sample[7], sample[7], sample[7]]));
+        }
+
+        albedo.save(format!("{}_albedo.exr", path_prefix))?;
+        normal.save(format!("{}_normal.exr", path_prefix))?;
+        depth.save(format!("{}_depth.exr", path_prefix))?;
+        object_id.save(format!("{}_object_id.exr", path_prefix))?;
+
+        Ok(())
+    }
+
+    /// Object covering the given pixel of the current view, None where the sky shows
+    pub fn pick(&mut self, x: u32, y: u32) -> Option<ObjectId> {
+        let aov_data = self.trace_aovs();
+        let PhysicalSize { width, height } = self.viewports[0].size;
+        if x >= width || y >= height {
+            return None;
+        }
+
+        // The object id sample is the index of the primitive that was hit, -1 for the sky
+        let primitive = aov_data[((y * width + x) * 8 + 3) as usize];
+        if primitive < 0.0 {
+            return None;
+        }
+        self.scene.object_at_primitive(primitive as usize)
+    }
+
+    /// Traces the main window's view with AOVs enabled, leaving its color buffer up to date,
+    /// and returns the raw AOV samples (8 floats per pixel)
+    fn trace_aovs(&mut self) -> Vec<f32> {
+        let write_aovs = self.scene.write_aovs;
+        self.scene.write_aovs = true;
+        self.scene.block_size = 1; // Every pixel needs its own AOVs
+        self.prepare_scene();
+        self.scene.write_aovs = write_aovs;
+
+        let mut command_encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("AOV Encoder")
+        });
+        self.encode_ray_trace_pass(&mut command_encoder, &self.viewports[0]);
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+        self.viewports[0].frame_index += 1;
+
+        let aov_bytes = read_buffer(&self.device, &self.queue, &self.viewports[0].aov_buffer);
+        bytemuck::cast_slice(&aov_bytes).to_vec()
+    }
+
+    /// Runs the accumulated render through Open Image Denoise, guided by the albedo
+    /// and normal AOVs, before saving it as an 8-bit sRGB image
+    #[cfg(feature = "denoise")]
+    pub fn save_denoised(&mut self, path: &str) -> image::ImageResult<()> {
+        let aov_data = self.trace_aovs();
+        let main = &self.viewports[0];
+        let accumulation_bytes = read_buffer(&self.device, &self.queue, &main.accumulation_buffer);
+        let accumulation: &[f32] = bytemuck::cast_slice(&accumulation_bytes);
+
+        let (width, height) = (main.size.width, main.size.height);
+        let pixel_count = (width * height) as usize;
+        let mut color = Vec::with_capacity(pixel_count * 3);
+        let mut albedo = Vec::with_capacity(pixel_count * 3);
+        let mut normal = Vec::with_capacity(pixel_count * 3);
+
+        for (rgba, sample) in accumulation.chunks_exact(4).zip(aov_data.chunks_exact(8)) {
+            color.extend_from_slice(&rgba[0..3]);
+            albedo.extend_from_slice(&sample[0..3]);
+            normal.extend_from_slice(&sample[4..7]);
+        }
+
+        let denoised = super::denoise(width, height, &color, &albedo, &normal);
+
+        let mut output = ImageBuffer::<Rgb<u8>, Vec<u8>>::new(width, height);
+        for (pixel, rgb) in output.pixels_mut().zip(denoised.chunks_exact(3)) {
+            *pixel = Rgb([0, 1, 2].map(|c| (linear_to_srgb(rgb[c]) * 255.0 + 0.5) as u8));
+        }
+        output.save(path)
+    }
+
+    // Uploads what the next frame traces through the staging belt, in a submission of its own so
+    // the copies can start while the frame is still being recorded
+    fn prepare_scene(&mut self) {
+        let _span = debug_span!("upload").entered();
+        let mut command_encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Upload Encoder")
+        });
+        let (device, belt, encoder) = (&self.device, &mut self.staging_belt, &mut command_encoder);
+
+        for viewport in &mut self.viewports {
+            // Added viewports look through their own camera, showing the layers the main one does
+            if let Some(camera) = &mut viewport.camera {
+                camera.render_mask = self.scene.camera.render_mask;
+                std::mem::swap(camera, &mut self.scene.camera);
+            }
+            // Convert the f32 array to bytes. With push constants the frame index isn't read from
+            // the uniform, which then stays the same while samples accumulate.
+            let frame_index = if self.push_constants { 0 } else { viewport.frame_index };
+            let scene_data_bytes = self.scene.flatten_scene_data(frame_index);
+            if let Some(camera) = &mut viewport.camera {
+                std::mem::swap(camera, &mut self.scene.camera);
+            }
+
+            // Write to the buffer when it changed
+            if scene_data_bytes != viewport.uploaded_parameters {
+                stage_write(belt, device, encoder, &viewport.scene_parameters, &scene_data_bytes);
+                viewport.uploaded_parameters = scene_data_bytes;
+            }
+        }
+
+        // A scene past the limits doesn't fit the buffers, which keep showing the last one that did
+        if !self.oversized {
+            stage_write(belt, device, encoder, &self.object_buffer, &self.scene.flatten_object_data());
+
+            // Get node and object index data in bytes, culled to the camera's view when enabled. The tree is
+            // shared, so it can't be culled to one camera while other viewports look through theirs.
+            let (node_data_bytes, object_index_data_bytes) = if self.scene.frustum_culling && self.viewports.len() == 1 {
+                self.scene.flatten_culled_bvh_data()
+            } else {
+                (self.scene.flatten_node_data(), self.scene.flatten_object_index_data())
+            };
+            stage_write(belt, device, encoder, &self.node_buffer, &node_data_bytes);
+            stage_write(belt, device, encoder, &self.object_index_buffer, &object_index_data_bytes);
+
+            stage_write(belt, device, encoder, &self.material_buffer, &self.scene.flatten_material_data());
+
+            // Write the points of point clouds, which their clusters in the object buffer refer to
+            stage_write(belt, device, encoder, &self.point_buffer, &self.scene.flatten_point_data());
+        }
+
+        self.staging_belt.finish();
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+        // The staging buffers are mapped again once the GPU has copied out of them
+        self.staging_belt.recall();
+    }
+}
+
+// Copies the bytes to the start of the buffer through mapped staging buffers of the belt, a chunk
+// at a time so large meshes don't need a staging buffer as large as themselves
+fn stage_write(belt: &mut wgpu::util::StagingBelt, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, buffer: &wgpu::Buffer, bytes: &[u8]) {
+    // Chunks of whole floats, as copies have to be a multiple of four bytes
+    for (i, chunk) in bytes.chunks(STAGING_CHUNK_SIZE as usize).enumerate() {
+        let size = wgpu::BufferSize::new(chunk.len() as u64).unwrap();
+        belt.write_buffer(encoder, buffer, i as u64 * STAGING_CHUNK_SIZE, size, device).copy_from_slice(chunk);
+    }
+}
+
+/// Saves linear RGBA radiance, four floats per pixel row by row, in the given format
+pub fn save_radiance(path: &str, format: CaptureFormat, width: u32, height: u32, radiance: &[f32]) -> image::ImageResult<()> {
+    match format {
+        CaptureFormat::Png8 => {
+            let mut output = ImageBuffer::<Rgb<u8>, Vec<u8>>::new(width, height);
+            for (pixel, rgba) in output.pixels_mut().zip(radiance.chunks_exact(4)) {
+                *pixel = Rgb([0, 1, 2].map(|c| (linear_to_srgb(rgba[c]) * 255.0 + 0.5) as u8));
+            }
+            output.save(path)
+        },
+        CaptureFormat::Png16 => {
+            let mut output = ImageBuffer::<Rgb<u16>, Vec<u16>>::new(width, height);
+            for (pixel, rgba) in output.pixels_mut().zip(radiance.chunks_exact(4)) {
+                *pixel = Rgb([0, 1, 2].map(|c| (linear_to_srgb(rgba[c]) * 65535.0 + 0.5) as u16));
+            }
+            output.save_with_format(path, image::ImageFormat::Png)
+        },
+        CaptureFormat::Exr => {
+            let output = ImageBuffer::<Rgba<f32>, Vec<f32>>::from_raw(width, height, radiance.to_vec())
+                .expect("Radiance does not match the image size");
+            output.save_with_format(path, image::ImageFormat::OpenExr)
+        },
+    }
+}
+
+// ----------Embedded Rendering---------- //
+/// Ray traces a scene into render targets of a device the host application owns, e.g. a game
+/// engine or a GUI, without a window of its own. Samples accumulate over calls for as long as
+/// the camera and the scene stay the same.
+pub struct Renderer {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    size: PhysicalSize<u32>,
 
     // Assets
-    color_buffer: wgpu::Texture,
+    _color_buffer: wgpu::Texture, // Keeps the texture behind the view alive
     color_buffer_view: TextureView,
     sampler: wgpu::Sampler,
     scene_parameters: wgpu::Buffer,
     object_buffer: wgpu::Buffer,
     node_buffer: wgpu::Buffer,
     object_index_buffer: wgpu::Buffer,
-    sky_material: CubeMapMaterial,
+    material_buffer: wgpu::Buffer,
+    point_buffer: wgpu::Buffer,
+    photon_buffer: wgpu::Buffer,
+    stats_buffer: wgpu::Buffer, // Counters the kernel adds to, read by take_ray_stats
+    post_process_buffer: wgpu::Buffer,
+    sky: CubeMapMaterial,
+    active_sky: usize,
+    object_textures: TextureArrayMaterial,
+    texture_filtering: TextureFiltering, // Filtering the sky and object texture samplers were made with
+    uploaded_labels: usize, // Labels drawn into the text atlas layer of object_textures
+    aov_buffer: wgpu::Buffer,
+    accumulation_buffer: wgpu::Buffer,
+    frame_index: u32,
+    camera: Option<Camera>, // The accumulated samples were traced through
 
     // Pipeline Objects
+    ray_tracing_bind_group_layout: wgpu::BindGroupLayout,
+    screen_bind_group_layout: wgpu::BindGroupLayout,
     ray_tracing_pipeline: wgpu::ComputePipeline,
-    ray_tracing_bind_group: wgpu::BindGroup,
+    photon_pipeline: wgpu::ComputePipeline,
     screen_pipeline: wgpu::RenderPipeline,
+    ray_tracing_bind_group: wgpu::BindGroup,
     screen_bind_group: wgpu::BindGroup,
+    _probes: wgpu::Texture, // A single texel, the embedded renderer traces every bounce instead of using probes
+    probes_view: TextureView,
+    raster: RasterTarget, // A single pixel, the embedded renderer traces its camera rays
 
-    // Scene to render
+    /// Scene to render, edits to it are picked up by the next `render_to_texture`
     pub scene: Scene,
 }
 
-impl<'a> State<'a> {
-
-    pub async fn new(window: &'a Window, scene: Scene) -> Self {
-
-        let size = window.inner_size();
-
-        let instance_descriptor = wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(), ..Default::default()
-        };
-        let instance = wgpu::Instance::new(instance_descriptor);
-        let surface = instance.create_surface(window)
-            .unwrap();
-
-        let adapter_descriptor = wgpu::RequestAdapterOptionsBase {
-            power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        };
-        let adapter = instance.request_adapter(&adapter_descriptor)
-            .await.unwrap();
-
-        let (device, queue) = init_device_and_queue(&adapter).await;
-
-        let config = init_surface_configuration(&adapter, &surface, &size);
-        surface.configure(&device, &config);
-
-        // Create assets to be used
-        let (color_buffer, 
-            color_buffer_view, 
-            sampler, 
-            scene_parameters, 
-            object_buffer, 
-            node_buffer, 
+impl Renderer {
+    /// Prepares a built scene for rendering into `width` by `height` targets of the given format,
+    /// which should be an sRGB one. The device needs the limits `required_limits` returns, and the
+    /// scene has to fit its buffer limits.
+    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, scene: Scene, format: wgpu::TextureFormat, width: u32, height: u32) -> Result<Self, BufferLimitError> {
+        check_scene_limits(&scene, &device.limits())?;
+        let size = PhysicalSize::new(width, height);
+        let (color_buffer,
+            color_buffer_view,
+            sampler,
+            scene_parameters,
+            object_buffer,
+            node_buffer,
             object_index_buffer,
-            sky_material) = create_assets(&device, &size, &scene, &queue).await;
-        
-        // create bind group layouts
-        let (ray_tracing_bind_group_layout, 
-            screen_bind_group_layout) = make_bind_group_layouts(&device).await;
-        
-        // Create render pipeline
-        let (ray_tracing_pipeline, 
-            screen_pipeline) = make_pipeline(&device, &ray_tracing_bind_group_layout, &screen_bind_group_layout).await;
-        
-        // Create bind groups
-        let (ray_tracing_bind_group, 
-            screen_bind_group) = make_bind_groups(&device, &color_buffer_view, &sampler, &scene_parameters, &object_buffer, &node_buffer, &object_index_buffer, &ray_tracing_bind_group_layout, &screen_bind_group_layout, &sky_material).await;
+            sky,
+            aov_buffer,
+            accumulation_buffer) = pollster::block_on(create_assets(&device, &size, &scene, &queue));
+        let object_textures = create_object_textures(&device, &queue, &scene);
+        let material_buffer = create_scene_buffer(&device, &scene, SceneBuffer::Materials);
+        let point_buffer = create_scene_buffer(&device, &scene, SceneBuffer::Points);
+        let photon_buffer = create_photon_buffer(&device, PHOTON_GRID_CELLS);
+        let stats_buffer = create_stats_buffer(&device);
+        let post_process_buffer = create_post_process_buffer(&device);
+        let probes = create_probe_texture(&device, (1, 1), wgpu::TextureUsages::TEXTURE_BINDING);
+        let probes_view = probes.create_view(&wgpu::TextureViewDescriptor::default());
+        let raster = create_raster_target(&device, &PhysicalSize::new(1, 1));
 
-        Self {
-            // Device/Context objects
-            surface,
+        let (ray_tracing_bind_group_layout,
+            screen_bind_group_layout) = pollster::block_on(make_bind_group_layouts(&device));
+        let (ray_tracing_pipeline,
+            photon_pipeline,
+            screen_pipeline) = pollster::block_on(make_pipeline(&device, &ray_tracing_bind_group_layout, &screen_bind_group_layout, DEFAULT_WORKGROUP_SIZE, false, format));
+        let (ray_tracing_bind_group,
+            screen_bind_group) = pollster::block_on(make_bind_groups(&device, &color_buffer_view, &sampler, &post_process_buffer, &scene_parameters, &object_buffer, &node_buffer, &object_index_buffer, &material_buffer, &point_buffer, &photon_buffer, &stats_buffer, &ray_tracing_bind_group_layout, &screen_bind_group_layout, &sky, &aov_buffer, &accumulation_buffer, &object_textures, &probes_view, &raster.visibility_view));
+
+        Ok(Self {
             device,
             queue,
-            config,
             size,
-            window,
-            // Assets
-            color_buffer,
+            _color_buffer: color_buffer,
             color_buffer_view,
             sampler,
             scene_parameters,
             object_buffer,
             node_buffer,
             object_index_buffer,
-            sky_material,
-            // Pipeline Objects
+            material_buffer,
+            point_buffer,
+            photon_buffer,
+            stats_buffer,
+            post_process_buffer,
+            sky,
+            active_sky: scene.active_sky,
+            object_textures,
+            texture_filtering: scene.texture_filtering,
+            uploaded_labels: scene.labels.len(),
+            aov_buffer,
+            accumulation_buffer,
+            frame_index: 0,
+            camera: None,
+            ray_tracing_bind_group_layout,
+            screen_bind_group_layout,
             ray_tracing_pipeline,
-            ray_tracing_bind_group,
+            photon_pipeline,
             screen_pipeline,
+            ray_tracing_bind_group,
             screen_bind_group,
-            // Scene to render
+            _probes: probes,
+            probes_view,
+            raster,
             scene,
-        }
+        })
     }
 
-    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+    /// Renders into targets of a new size from the next call on, starting the accumulation over
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.size = PhysicalSize::new(width, height);
+            (self._color_buffer,
+                self.color_buffer_view,
+                self.aov_buffer,
+                self.accumulation_buffer) = create_view_buffers(&self.device, &self.size);
+            self.rebuild_bind_groups();
+            self.reset_accumulation();
         }
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError>{
-        
-        self.prepare_scene();
-        
-        let start_time = std::time::Instant::now();
-        let drawable = self.surface.get_current_texture()?;
-        let image_view_descriptor = wgpu::TextureViewDescriptor::default();
-        let image_view = drawable.texture.create_view(&image_view_descriptor);
-        
-        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder")
-        };
-        let mut command_encoder = self.device.create_command_encoder(&command_encoder_descriptor);
-        
-        let ray_trace_pass_descriptor = wgpu::ComputePassDescriptor {
-            label: Some("Ray Pass Descriptor"),
-            timestamp_writes: None,
-        };
-        let mut ray_trace_pass = command_encoder.begin_compute_pass(&ray_trace_pass_descriptor);
-        ray_trace_pass.set_pipeline(&self.ray_tracing_pipeline);
-        ray_trace_pass.set_bind_group(0, &self.ray_tracing_bind_group, &[]);
-        ray_trace_pass.dispatch_workgroups(self.size.width/8, self.size.height/8, 1);
-        drop(ray_trace_pass);
-        
-        let color_attachment = wgpu::RenderPassColorAttachment {
-            view: &image_view,
-            resolve_target: None,
-            ops: wgpu::Operations {
-                load: wgpu::LoadOp::Clear(wgpu::Color {
-                    r: 0.75,
-                    g: 0.5,
-                    b: 0.25,
-                    a: 1.0
-                }),
-                store: wgpu::StoreOp::Store,
-            },
-        };
-        
-        let render_pass_descriptor = wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
-            color_attachments: &[Some(color_attachment)],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        };
-        // Begin the render pass and set up for drawing
+    /// Traces one more sample per pixel through the camera and draws the accumulated image over the
+    /// target, which has to be of the size and format the renderer was made for. The commands are
+    /// submitted to the queue before this returns.
+    pub fn render_to_texture(&mut self, target: &wgpu::TextureView, camera: &Camera) {
+        let _span = debug_span!("render_to_texture").entered();
+        if self.camera != Some(*camera) {
+            self.camera = Some(*camera);
+            self.scene.camera = *camera;
+            self.reset_accumulation();
+        }
+        self.sync_scene();
+
+        let upload_span = debug_span!("upload").entered();
+        self.queue.write_buffer(&self.scene_parameters, 0, &self.scene.flatten_scene_data(self.frame_index));
+        self.queue.write_buffer(&self.object_buffer, 0, &self.scene.flatten_object_data());
+        self.queue.write_buffer(&self.node_buffer, 0, &self.scene.flatten_node_data());
+        self.queue.write_buffer(&self.object_index_buffer, 0, &self.scene.flatten_object_index_data());
+        self.queue.write_buffer(&self.material_buffer, 0, &self.scene.flatten_material_data());
+        self.queue.write_buffer(&self.point_buffer, 0, &self.scene.flatten_point_data());
+        self.queue.write_buffer(&self.post_process_buffer, 0, bytemuck::cast_slice(&self.scene.post_process.uniform_data(self.frame_index)));
+        drop(upload_span);
+
+        let mut command_encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Embedded Encoder")
+        });
+        if (self.scene.caustics && self.frame_index == 0) || self.scene.bvh_heatmap {
+            command_encoder.clear_buffer(&self.photon_buffer, 0, None);
+        }
+        {
+            let mut pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Embedded Ray Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_bind_group(0, &self.ray_tracing_bind_group, &[]);
+            if self.scene.caustics || self.scene.bvh_heatmap {
+                pass.set_pipeline(&self.photon_pipeline);
+                pass.dispatch_workgroups(PHOTON_WORKGROUPS, PHOTON_WORKGROUPS, 1);
+            }
+            pass.set_pipeline(&self.ray_tracing_pipeline);
+            pass.dispatch_workgroups(self.size.width.div_ceil(DEFAULT_WORKGROUP_SIZE.0), self.size.height.div_ceil(DEFAULT_WORKGROUP_SIZE.1), 1);
+        }
         {
-            let mut render_pass = command_encoder.begin_render_pass(&render_pass_descriptor);
-            render_pass.set_pipeline(&self.screen_pipeline); // Set the screen rendering pipeline
-            render_pass.set_bind_group(0, &self.screen_bind_group, &[]); // Set the bind group
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Embedded Screen Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(&self.screen_pipeline);
+            render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
             render_pass.draw(0..6, 0..1);
         }
-        
         self.queue.submit(std::iter::once(command_encoder.finish()));
-        
-        drawable.present();
-        
-        let object_count = self.scene.objects.len();
-        let duration = start_time.elapsed(); // Calculate how long the rendering took
-        println!("Rendered in {:?}, object count: {}", duration, object_count);
-        
-        Ok(())
+        self.frame_index += 1;
     }
 
-    fn prepare_scene(&self) {
-        // Convert the f32 array to bytes
-        let scene_data_bytes = self.scene.flatten_scene_data();
+    /// Samples per pixel accumulated since the render last restarted
+    pub fn sample_count(&self) -> u32 {
+        self.frame_index
+    }
 
-        // Write to the buffer
-        self.queue.write_buffer(
-            &self.scene_parameters, 
-            0,
-            &scene_data_bytes,
-        );
+    /// Reads back the accumulated linear RGBA radiance, laid out like `render_offline`'s
+    pub fn radiance(&self) -> Vec<f32> {
+        bytemuck::cast_slice(&read_buffer(&self.device, &self.queue, &self.accumulation_buffer)).to_vec()
+    }
 
-        // Get object data in bytes
-        let object_data_bytes = self.scene.flatten_object_data();
+    /// Discards the accumulated samples, which the renderer does by itself when the camera or the scene changes
+    pub fn reset_accumulation(&mut self) {
+        self.frame_index = 0;
+    }
 
-        // Write to the buffer
-        self.queue.write_buffer(
-            &self.object_buffer, // The wgpu::Buffer for object data
-            0, // Offset within the buffer
-            &object_data_bytes, // The byte slice containing the object data
-        );
-        
-        // Get node data in bytes
-        let node_data_bytes = self.scene.flatten_node_data();
-        // Write to the buffer
-        self.queue.write_buffer(
-            &self.node_buffer, // The wgpu::Buffer for node data
-            0, // Offset within the buffer
-            &node_data_bytes, // The byte slice containing the node data
-        );
-
-        // Get node data in bytes
-        let object_index_data_bytes = self.scene.flatten_object_index_data();
-        
-        // Write to the buffer
-        self.queue.write_buffer(
-            &self.object_index_buffer, // The wgpu::Buffer for object_index data
-            0, // Offset within the buffer
-            &object_index_data_bytes, // The byte slice containing the object_index data
-        );
+    /// Rates of the kernel's counters since the last call, or since the renderer was made, over
+    /// the given seconds. Waits for the frames submitted so far and clears the counters.
+    pub fn take_ray_stats(&mut self, seconds: f64) -> RayStats {
+        let counters = read_buffer(&self.device, &self.queue, &self.stats_buffer);
+        let mut command_encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Stats Clear Encoder")
+        });
+        command_encoder.clear_buffer(&self.stats_buffer, 0, None);
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+        RayStats::from_counters(bytemuck::cast_slice(&counters), seconds)
+    }
+
+    // Catches up with edits to the scene since the last call, like State::render does each frame
+    fn sync_scene(&mut self) {
+        let mut rebind = false;
+        if self.scene.active_sky != self.active_sky || self.scene.texture_filtering != self.texture_filtering {
+            self.active_sky = self.scene.active_sky;
+            self.texture_filtering = self.scene.texture_filtering;
+            self.sky = create_sky(&self.device, &self.queue, &self.scene);
+            self.object_textures.set_filtering(&self.device, self.texture_filtering);
+            rebind = true;
+        }
+        if self.scene.dirty {
+            self.scene.make_scene();
+            // The buffers are made again to fit objects that were added
+            self.object_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Objects);
+            self.node_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Nodes);
+            self.object_index_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::ObjectIndices);
+            self.material_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Materials);
+            self.point_buffer = create_scene_buffer(&self.device, &self.scene, SceneBuffer::Points);
+            if self.object_textures.image_count != self.scene.image_paths.len() + !self.scene.labels.is_empty() as usize
+                || self.uploaded_labels != self.scene.labels.len() {
+                self.object_textures = create_object_textures(&self.device, &self.queue, &self.scene);
+                self.uploaded_labels = self.scene.labels.len();
+            }
+            rebind = true;
+        } else if self.scene.moved {
+            self.scene.refit_bvh();
+            self.reset_accumulation();
+        }
+        if rebind {
+            self.rebuild_bind_groups();
+            self.reset_accumulation();
+        }
+    }
+
+    fn rebuild_bind_groups(&mut self) {
+        let (ray_tracing_bind_group,
+            screen_bind_group) = pollster::block_on(make_bind_groups(&self.device, &self.color_buffer_view, &self.sampler, &self.post_process_buffer, &self.scene_parameters, &self.object_buffer, &self.node_buffer, &self.object_index_buffer, &self.material_buffer, &self.point_buffer, &self.photon_buffer, &self.stats_buffer, &self.ray_tracing_bind_group_layout, &self.screen_bind_group_layout, &self.sky, &self.aov_buffer, &self.accumulation_buffer, &self.object_textures, &self.probes_view, &self.raster.visibility_view));
+        self.ray_tracing_bind_group = ray_tracing_bind_group;
+        self.screen_bind_group = screen_bind_group;
+    }
+}
+
+// ----------Offline Rendering---------- //
+/// Renders `samples` samples per pixel of a built scene without a window and returns the linear
+/// RGBA radiance. The image is split into one horizontal band per adapter, each traced on its own
+/// thread through a camera narrowed to its rows, so the bands line up without seams. Fails when the
+/// scene doesn't fit one of the adapters' buffer limits.
+pub fn render_offline(scene: &mut Scene, adapters: &[wgpu::Adapter], width: u32, height: u32, samples: u32) -> Result<Vec<f32>, BufferLimitError> {
+    resume_offline(scene, adapters, width, height, 0..samples, None)
+}
+
+/// Traces the samples `frames` of an offline render, going on from the radiance of the samples
+/// before `frames.start`, laid out like `render_offline`'s and None when it starts there. Caustic
+/// photons of the frames before aren't kept, the photon map starts over.
+pub fn resume_offline(scene: &mut Scene, adapters: &[wgpu::Adapter], width: u32, height: u32, frames: std::ops::Range<u32>, accumulation: Option<&[f32]>) -> Result<Vec<f32>, BufferLimitError> {
+    assert!(!adapters.is_empty(), "Offline rendering needs at least one adapter");
+    let band_count = adapters.len() as u32;
+
+    // Scene parameters of every frame of every band, made up front as the camera is per band
+    let bands: Vec<_> = (0..band_count).map(|band| {
+        let rows = band * height / band_count..(band + 1) * height / band_count;
+        let band_accumulation = accumulation.map(|accumulation| &accumulation[(rows.start * width * 4) as usize..(rows.end * width * 4) as usize]);
+        (rows.len() as u32, band_frames(scene, rows, height, frames.clone()), band_accumulation)
+    }).collect();
+
+    let scene: &Scene = scene;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = adapters.iter().zip(&bands).map(|(adapter, (rows, frames, accumulation))| {
+            scope.spawn(move || render_band(adapter, scene, frames, PhysicalSize::new(width, *rows), *accumulation))
+        }).collect();
+        let bands = handles.into_iter().map(|handle| handle.join().expect("Rendering a band failed"));
+        bands.collect::<Result<Vec<_>, _>>().map(|bands| bands.concat())
+    })
+}
+
+/// Scene parameters of the frames `frames` of the image rows `rows`, with the camera narrowed to them
+pub fn band_frames(scene: &mut Scene, rows: std::ops::Range<u32>, height: u32, frames: std::ops::Range<u32>) -> Vec<Vec<u8>> {
+    let (lower_left_corner, vertical) = (scene.camera.lower_left_corner, scene.camera.vertical);
+    scene.camera.lower_left_corner = lower_left_corner + vertical * (rows.start as f32 / height as f32);
+    scene.camera.vertical = vertical * (rows.len() as f32 / height as f32);
+    let frames = frames.map(|frame| scene.flatten_scene_data(frame)).collect();
+    scene.camera.lower_left_corner = lower_left_corner;
+    scene.camera.vertical = vertical;
+    frames
+}
+
+// Traces one band of an offline render on its adapter, one frame per sample, going on from the
+// band's accumulation when there is one
+fn render_band(adapter: &wgpu::Adapter, scene: &Scene, frames: &[Vec<u8>], size: PhysicalSize<u32>, accumulation: Option<&[f32]>) -> Result<Vec<f32>, BufferLimitError> {
+    let (device, queue) = pollster::block_on(init_device_and_queue(adapter));
+    check_scene_limits(scene, &device.limits())?;
+    let (_color_buffer,
+        color_buffer_view,
+        sampler,
+        scene_parameters,
+        object_buffer,
+        node_buffer,
+        object_index_buffer,
+        sky_material,
+        aov_buffer,
+        accumulation_buffer) = pollster::block_on(create_assets(&device, &size, scene, &queue));
+    let object_textures = create_object_textures(&device, &queue, scene);
+    let material_buffer = create_scene_buffer(&device, scene, SceneBuffer::Materials);
+    let point_buffer = create_scene_buffer(&device, scene, SceneBuffer::Points);
+    let photon_buffer = create_photon_buffer(&device, PHOTON_GRID_CELLS);
+    let stats_buffer = create_stats_buffer(&device);
+    // Offline renders trace every bounce, the kernel doesn't take a single texel for probes
+    let probes = create_probe_texture(&device, (1, 1), wgpu::TextureUsages::TEXTURE_BINDING);
+    let probes_view = probes.create_view(&wgpu::TextureViewDescriptor::default());
+    // Nor does it rasterize its camera rays
+    let raster = create_raster_target(&device, &PhysicalSize::new(1, 1));
+
+    let (ray_tracing_bind_group_layout, screen_bind_group_layout) = pollster::block_on(make_bind_group_layouts(&device));
+    let (ray_tracing_pipeline, photon_pipeline, _) = pollster::block_on(make_pipeline(&device, &ray_tracing_bind_group_layout, &screen_bind_group_layout, DEFAULT_WORKGROUP_SIZE, false, wgpu::TextureFormat::Bgra8UnormSrgb));
+    let (ray_tracing_bind_group, _) = pollster::block_on(make_bind_groups(&device, &color_buffer_view, &sampler, &create_post_process_buffer(&device), &scene_parameters, &object_buffer, &node_buffer, &object_index_buffer, &material_buffer, &point_buffer, &photon_buffer, &stats_buffer, &ray_tracing_bind_group_layout, &screen_bind_group_layout, &sky_material, &aov_buffer, &accumulation_buffer, &object_textures, &probes_view, &raster.visibility_view));
+
+    queue.write_buffer(&object_buffer, 0, &scene.flatten_object_data());
+    queue.write_buffer(&node_buffer, 0, &scene.flatten_node_data());
+    queue.write_buffer(&object_index_buffer, 0, &scene.flatten_object_index_data());
+    queue.write_buffer(&material_buffer, 0, &scene.flatten_material_data());
+    queue.write_buffer(&point_buffer, 0, &scene.flatten_point_data());
+    if let Some(accumulation) = accumulation {
+        queue.write_buffer(&accumulation_buffer, 0, bytemuck::cast_slice(accumulation));
+    }
+
+    let start_time = Instant::now();
+    for frame in frames {
+        queue.write_buffer(&scene_parameters, 0, frame);
+        let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Offline Encoder")
+        });
+        if scene.bvh_heatmap {
+            command_encoder.clear_buffer(&photon_buffer, 0, None);
+        }
+        {
+            let mut pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Offline Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_bind_group(0, &ray_tracing_bind_group, &[]);
+            if scene.caustics || scene.bvh_heatmap {
+                pass.set_pipeline(&photon_pipeline);
+                pass.dispatch_workgroups(PHOTON_WORKGROUPS, PHOTON_WORKGROUPS, 1);
+            }
+            pass.set_pipeline(&ray_tracing_pipeline);
+            pass.dispatch_workgroups(size.width.div_ceil(DEFAULT_WORKGROUP_SIZE.0), size.height.div_ceil(DEFAULT_WORKGROUP_SIZE.1), 1);
+        }
+        queue.submit(std::iter::once(command_encoder.finish()));
+        // Waiting on every frame keeps long renders from queueing up thousands of submissions
+        device.poll(wgpu::Maintain::Wait);
     }
+
+    let counters = read_buffer(&device, &queue, &stats_buffer);
+    let stats = RayStats::from_counters(bytemuck::cast_slice(&counters), start_time.elapsed().as_secs_f64());
+    info!("{} traced {:.1} Mrays/s", adapter.get_info().name, stats.mrays_per_second());
+
+    let accumulation_bytes = read_buffer(&device, &queue, &accumulation_buffer);
+    Ok(bytemuck::cast_slice(&accumulation_bytes).to_vec())
 }
 
 // ----------Initialization Functions---------- //
+// Flags the device as lost when the driver gives it up. wgpu's errors stay fatal as by default,
+// except those of a device already lost, which are expected until it is replaced.
+fn watch_device_loss(device: &wgpu::Device) -> Arc<AtomicBool> {
+    let lost = Arc::new(AtomicBool::new(false));
+    let flag = lost.clone();
+    device.set_device_lost_callback(move |reason, message| {
+        if let wgpu::DeviceLostReason::Unknown | wgpu::DeviceLostReason::Destroyed = reason {
+            error!("Device lost: {}", message);
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+    let flag = lost.clone();
+    device.on_uncaptured_error(Box::new(move |error| {
+        if flag.load(Ordering::Relaxed) {
+            error!("Error on the lost device: {}", error);
+        } else {
+            panic!("wgpu error: {}", error);
+        }
+    }));
+    lost
+}
+
+/// Limits a device has to have for the renderer, to request devices handed to `StateBuilder::device`
+/// or `Renderer::new` with
+pub fn required_limits() -> wgpu::Limits {
+    // The photon map and the ray counters are a ninth and tenth storage buffer, past the default limit
+    wgpu::Limits {
+        max_storage_buffers_per_shader_stage: 10,
+        ..Default::default()
+    }
+}
+
+/// Creates a device on the adapter with the features and limits the renderer uses, to make a
+/// `Renderer` with
+pub fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    pollster::block_on(init_device_and_queue(adapter))
+}
+
 async fn init_device_and_queue(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    // BC compressed textures are used when the adapter supports them, cutting texture memory, and
+    // push constants, sparing a write of the scene uniform every frame
+    let mut required_features = adapter.features() & wgpu::Features::TEXTURE_COMPRESSION_BC;
+    let mut required_limits = required_limits();
+    // Large meshes need all the buffer size the adapter has, the defaults stop at 128 MiB
+    required_limits.max_storage_buffer_binding_size = adapter.limits().max_storage_buffer_binding_size;
+    required_limits.max_buffer_size = adapter.limits().max_buffer_size;
+    if adapter.features().contains(wgpu::Features::PUSH_CONSTANTS) && adapter.limits().max_push_constant_size >= FRAME_CONSTANTS_SIZE {
+        required_features |= wgpu::Features::PUSH_CONSTANTS;
+        required_limits.max_push_constant_size = FRAME_CONSTANTS_SIZE;
+    }
     let device_descriptor = wgpu::DeviceDescriptor {
-        required_features: wgpu::Features::empty(),
-        required_limits: wgpu::Limits::default(),
+        required_features,
+        required_limits,
         label: Some("Device"),
     };
     adapter.request_device(&device_descriptor, None).await.unwrap()
 }
 
-fn init_surface_configuration(adapter: &wgpu::Adapter, surface: &wgpu::Surface, size: &PhysicalSize<u32>) -> wgpu::SurfaceConfiguration {
+// Whether the kernel can take the camera and frame index as push constants on the device
+fn supports_push_constants(device: &wgpu::Device) -> bool {
+    device.features().contains(wgpu::Features::PUSH_CONSTANTS) && device.limits().max_push_constant_size >= FRAME_CONSTANTS_SIZE
+}
+
+// Camera and frame index laid out like the kernel's FrameConstants
+fn frame_constants(camera: &Camera, frame_index: u32) -> [f32; 16] {
+    let [projection, projection_a, projection_b] = camera.projection().uniform_data();
+    [
+        camera.origin.0, camera.origin.1, camera.origin.2, frame_index as f32,
+        camera.lower_left_corner.0, camera.lower_left_corner.1, camera.lower_left_corner.2, projection,
+        camera.horizontal.0, camera.horizontal.1, camera.horizontal.2, projection_a,
+        camera.vertical.0, camera.vertical.1, camera.vertical.2, projection_b,
+    ]
+}
+
+// Returns the configuration along with the present modes V cycles through, in PRESENT_MODES order
+fn init_surface_configuration(adapter: &wgpu::Adapter, surface: &wgpu::Surface, size: &PhysicalSize<u32>) -> (wgpu::SurfaceConfiguration, Vec<wgpu::PresentMode>) {
     let surface_capabilities = surface.get_capabilities(adapter);
-    
-    let present_mode = if surface_capabilities.present_modes.contains(&wgpu::PresentMode::Mailbox) {
-        wgpu::PresentMode::Mailbox // Triple buffering if available
-    } else if surface_capabilities.present_modes.contains(&wgpu::PresentMode::Fifo) {
-        wgpu::PresentMode::Fifo // V-Sync (Double Buffering)
-    } else {
-        wgpu::PresentMode::Immediate // For the lowest latency, might introduce tearing
-    };
+
+    let present_modes: Vec<wgpu::PresentMode> = PRESENT_MODES.into_iter()
+        .filter(|mode| surface_capabilities.present_modes.contains(mode))
+        .collect();
+    // Every surface supports Fifo, but not every backend lists it
+    let present_mode = present_modes.first().copied().unwrap_or(wgpu::PresentMode::Fifo);
 
     let surface_format = surface_capabilities
         .formats
         .iter()
         .copied()
-        .filter(|f | f.is_srgb())
-        .next()
+        .find(|f | f.is_srgb())
         .unwrap_or(surface_capabilities.formats[0]);
 
-    wgpu::SurfaceConfiguration {
+    let config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: surface_format,
         width: size.width,
         height: size.height,
-        present_mode: present_mode,
+        present_mode,
         alpha_mode: surface_capabilities.alpha_modes[0],
         view_formats: vec![],
         desired_maximum_frame_latency: 2
-    }
+    };
+    (config, present_modes)
 }
 
 // ----------Asset Creation Functions---------- //
+#[allow(clippy::type_complexity)]
 async fn create_assets(
     device: &wgpu::Device,
     size: &winit::dpi::PhysicalSize<u32>,
     scene: &Scene,
     queue: &wgpu::Queue,
-) -> (wgpu::Texture, wgpu::TextureView, wgpu::Sampler, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, CubeMapMaterial) {
+) -> (wgpu::Texture, wgpu::TextureView, wgpu::Sampler, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, CubeMapMaterial, wgpu::Buffer, wgpu::Buffer) {
 
     let (color_buffer, color_buffer_view) = create_color_buffer(device, size);
 
+    let aov_buffer = create_aov_buffer(device, size);
+
+    let accumulation_buffer = create_accumulation_buffer(device, size);
+
+    let sampler = create_sampler(device);
+
+    let scene_parameters = create_scene_parameters(device).await;
+
+    let object_buffer = create_scene_buffer(device, scene, SceneBuffer::Objects);
+
+    let node_buffer = create_scene_buffer(device, scene, SceneBuffer::Nodes);
+
+    let object_index_buffer = create_scene_buffer(device, scene, SceneBuffer::ObjectIndices);
+
+    let sky_material = create_sky(device, queue, scene);
+    // Return the created resources
+    (color_buffer, color_buffer_view, sampler, scene_parameters, object_buffer, node_buffer, object_index_buffer, sky_material, aov_buffer, accumulation_buffer)
+} 
+
+fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
     let sampler_descriptor = wgpu::SamplerDescriptor {
         label: Some("Sampler Descriptor"),
         address_mode_u: wgpu::AddressMode::Repeat,
@@ -296,29 +2379,73 @@ async fn create_assets(
         border_color: None,
     };
 
-    let sampler = device.create_sampler(&sampler_descriptor);
+    device.create_sampler(&sampler_descriptor)
+}
+
+// Loads the scene's active sky
+fn create_sky(device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) -> CubeMapMaterial {
+    let faces = scene.skies[scene.active_sky].load().expect("Failed to load sky");
+    CubeMapMaterial::from_faces(device, queue, faces, scene.texture_filtering).expect("Failed to load sky")
+}
 
-    let scene_parameters = create_scene_parameters(device).await;
+// The color, AOV and accumulation buffers of a viewport, which are sized per pixel
+fn create_view_buffers(device: &wgpu::Device, size: &PhysicalSize<u32>) -> (wgpu::Texture, wgpu::TextureView, wgpu::Buffer, wgpu::Buffer) {
+    let (color_buffer, color_buffer_view) = create_color_buffer(device, size);
+    (color_buffer, color_buffer_view, create_aov_buffer(device, size), create_accumulation_buffer(device, size))
+}
+
+fn create_object_textures(device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) -> TextureArrayMaterial {
+    TextureArrayMaterial::new(device, queue, scene_images(scene, |_| {}), scene.texture_filtering)
+}
 
-    let object_buffer = create_object_buffer(device, scene).await;
+/// Decodes the scene's texture images in the order of `image_paths`, followed by the label atlas
+/// when there are labels, calling `progress` with the share decoded so far after each one
+pub fn scene_images(scene: &Scene, progress: impl FnMut(f32)) -> Vec<DynamicImage> {
+    read_scene_images(scene, progress).expect("Failed to load texture")
+}
 
-    let node_buffer = create_node_buffer(device, scene).await;
+/// Like `scene_images`, but returns the first image that couldn't be opened or decoded as an error
+pub fn read_scene_images(scene: &Scene, mut progress: impl FnMut(f32)) -> image::ImageResult<Vec<DynamicImage>> {
+    let _span = info_span!("load_textures", count = scene.image_paths.len()).entered();
+    let mut images = scene.image_paths.iter().enumerate().map(|(i, path)| {
+        let image = ImageReader::open(Path::new(path))?.decode()?;
+        progress((i + 1) as f32 / scene.image_paths.len() as f32);
+        Ok(image)
+    }).collect::<image::ImageResult<Vec<DynamicImage>>>()?;
+    // Labels share one atlas layer after the images
+    if !scene.labels.is_empty() {
+        images.push(DynamicImage::ImageRgba8(render_label_atlas(&scene.labels)));
+    }
+    Ok(images)
+}
 
-    let object_index_buffer = create_object_index_buffer(device, scene).await;
+#[cfg(feature = "editor")]
+async fn create_material_preview(device: &wgpu::Device) -> MaterialPreview {
+    let size = PhysicalSize::new(PREVIEW_SIZE, PREVIEW_SIZE);
+    let mut scene = Scene::new(8, size.width as f32, size.height as f32);
+    scene.camera = Camera::new(Vec3(0.0, 0.0, -3.5), Vec3(0.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), 40.0, 1.0);
+    let sphere = scene.add_sphere(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0), 1.0);
+    scene.make_scene();
 
-    let paths = vec![
-        "assets/gfx/sky_right.png",
-        "assets/gfx/sky_left.png",
-        "assets/gfx/sky_bottom.png", // 3 is bottom
-        "assets/gfx/sky_top.png",
-        "assets/gfx/sky_back.png",
-        "assets/gfx/sky_front.png",
-    ];
-    let images:Vec<DynamicImage> = load_cube_map_images(paths);
-    let sky_material: CubeMapMaterial = CubeMapMaterial::new(device, queue, images);
-    // Return the created resources
-    (color_buffer, color_buffer_view, sampler, scene_parameters, object_buffer, node_buffer, object_index_buffer, sky_material)
-} 
+    let (color_buffer, color_buffer_view) = create_color_buffer(device, &size);
+    MaterialPreview {
+        sphere,
+        _color_buffer: color_buffer,
+        color_buffer_view,
+        scene_parameters: create_scene_parameters(device).await,
+        object_buffer: create_scene_buffer(device, &scene, SceneBuffer::Objects),
+        node_buffer: create_scene_buffer(device, &scene, SceneBuffer::Nodes),
+        object_index_buffer: create_scene_buffer(device, &scene, SceneBuffer::ObjectIndices),
+        material_buffer: create_scene_buffer(device, &scene, SceneBuffer::Materials),
+        point_buffer: create_scene_buffer(device, &scene, SceneBuffer::Points),
+        photon_buffer: create_photon_buffer(device, 1), // The preview doesn't trace caustics
+        aov_buffer: create_aov_buffer(device, &size),
+        accumulation_buffer: create_accumulation_buffer(device, &size),
+        shown: None,
+        frame_index: 0,
+        scene,
+    }
+}
 
 fn create_color_buffer(device: &wgpu::Device, size: &PhysicalSize<u32>) -> (wgpu::Texture, wgpu::TextureView) {
     let color_buffer_description = wgpu::TextureDescriptor {
@@ -348,47 +2475,143 @@ fn create_color_buffer(device: &wgpu::Device, size: &PhysicalSize<u32>) -> (wgpu
         array_layer_count: None,
     };
 
-    let color_buffer_view = color_buffer.create_view(&color_buffer_view_description);
-    (color_buffer, color_buffer_view)
+    let color_buffer_view = color_buffer.create_view(&color_buffer_view_description);
+    (color_buffer, color_buffer_view)
+}
+
+fn create_aov_buffer(device: &wgpu::Device, size: &PhysicalSize<u32>) -> wgpu::Buffer {
+    // One albedo/object id/normal/depth sample (8 floats) per pixel
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("AOV Buffer"),
+        size: 32 * size.width as u64 * size.height as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_accumulation_buffer(device: &wgpu::Device, size: &PhysicalSize<u32>) -> wgpu::Buffer {
+    // Running average of the linear radiance, one vec4 per pixel
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Accumulation Buffer"),
+        size: 16 * size.width as u64 * size.height as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+async fn create_scene_parameters(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Scene Parameters Buffer"),
+        size: SCENE_DATA_SIZE,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Sized by gpu_layout to hold the scene, uploaded to with every change
+fn create_scene_buffer(device: &wgpu::Device, scene: &Scene, buffer: SceneBuffer) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(&format!("Scene Buffer ({})", buffer.name())),
+        size: buffer.size(scene),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_photon_buffer(device: &wgpu::Device, cells: u64) -> wgpu::Buffer {
+    // Summed power and count of the photons in each hash grid cell, cleared on the GPU
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Photon Buffer"),
+        size: 16 * cells,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_stats_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    // Cleared on the GPU whenever the counters are copied out
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Stats Buffer"),
+        size: STATS_BUFFER_SIZE,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_stats_readback(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Stats Readback Buffer"),
+        size: STATS_BUFFER_SIZE,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
 }
 
-async fn create_scene_parameters(device: &wgpu::Device) -> wgpu::Buffer {
+// Written before every screen pass, the effects are off until then
+fn create_post_process_buffer(device: &wgpu::Device) -> wgpu::Buffer {
     device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Scene Parameters Buffer"),
-        size: 80,
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        label: Some("Post-Process Buffer"),
+        size: POST_PROCESS_UNIFORM_SIZE,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         mapped_at_creation: false,
     })
 }
 
-async fn create_object_buffer(device: &wgpu::Device, scene: &Scene) -> wgpu::Buffer {
-    let object_buffer_descriptor = wgpu::BufferDescriptor {
-        label: Some("Object Buffer Descriptor"),
-        size: 84 * scene.objects.len() as u64,
-        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+fn create_fade_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Fade Buffer"),
+        size: FADE_UNIFORM_SIZE,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         mapped_at_creation: false,
-    };
-    device.create_buffer(&object_buffer_descriptor)
+    })
 }
 
-async fn create_node_buffer(device: &wgpu::Device, scene: &Scene) -> wgpu::Buffer {
-    let node_buffer_descriptor = wgpu::BufferDescriptor {
-        label: Some("Node Buffer Descriptor"),
-        size: 32 * (scene.nodes_used as u64),
-        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+fn create_raster_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Raster Buffer"),
+        size: RASTER_UNIFORM_SIZE,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         mapped_at_creation: false,
-    };
-    device.create_buffer(&node_buffer_descriptor)
+    })
 }
 
-async fn create_object_index_buffer(device: &wgpu::Device, scene: &Scene) -> wgpu::Buffer {
-    let object_index_buffer_descriptor = wgpu::BufferDescriptor {
-        label: Some("Object Buffer Descriptor"),
-        size: 4 * scene.objects.len() as u64,
-        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+fn create_raster_target(device: &wgpu::Device, size: &PhysicalSize<u32>) -> RasterTarget {
+    let create_texture = |label: &str, format: wgpu::TextureFormat, usage: wgpu::TextureUsages| device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d { width: size.width, height: size.height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    });
+    // New textures are zeroed, which the kernel reads as no triangle at any pixel
+    let visibility = create_texture("Visibility Texture", wgpu::TextureFormat::R32Uint, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING);
+    let depth = create_texture("Raster Depth Texture", wgpu::TextureFormat::Depth32Float, wgpu::TextureUsages::RENDER_ATTACHMENT);
+    RasterTarget {
+        visibility_view: visibility.create_view(&wgpu::TextureViewDescriptor::default()),
+        _visibility: visibility,
+        depth_view: depth.create_view(&wgpu::TextureViewDescriptor::default()),
+        _depth: depth,
+    }
+}
+
+// The buffer the exposure shader meters into, cleared before each metering, and the one it is read back through
+fn create_metering_buffers(device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+    let metering_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Metering Buffer"),
+        size: METERING_BUFFER_SIZE,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
         mapped_at_creation: false,
-    };
-    device.create_buffer(&object_index_buffer_descriptor)
+    });
+    let metering_readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Metering Readback Buffer"),
+        size: METERING_BUFFER_SIZE,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    (metering_buffer, metering_readback)
 }
 
 // ----------Pipeline and bind group Creation Functions---------- //
@@ -463,6 +2686,112 @@ async fn make_bind_group_layouts(device: &wgpu::Device) -> (wgpu::BindGroupLayou
                 ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                 count: None,
             },
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer { 
+                    ty: wgpu::BufferBindingType::Storage { read_only: false }, 
+                    has_dynamic_offset: false, 
+                    min_binding_size: None,
+                },
+                count: None, // Not an arrayed binding
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 8,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer { 
+                    ty: wgpu::BufferBindingType::Storage { read_only: false }, 
+                    has_dynamic_offset: false, 
+                    min_binding_size: None,
+                },
+                count: None, // Not an arrayed binding
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 9,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 10,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 11,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 12,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 13,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 14,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 15,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 16,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 17,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Uint,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
         ],
     };
     let ray_tracing_bind_group_layout: wgpu::BindGroupLayout = device.create_bind_group_layout(&ray_tracing_bind_group_layout_descriptor);
@@ -489,6 +2818,17 @@ async fn make_bind_group_layouts(device: &wgpu::Device) -> (wgpu::BindGroupLayou
                 },
                 count: None,
             },
+            // Post-process uniform
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
         ],
     };
     let screen_bind_group_layout = device.create_bind_group_layout(&screen_bind_group_layout_descriptor);
@@ -496,25 +2836,36 @@ async fn make_bind_group_layouts(device: &wgpu::Device) -> (wgpu::BindGroupLayou
     (ray_tracing_bind_group_layout, screen_bind_group_layout)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn make_bind_groups(
     device: &wgpu::Device,
     color_buffer_view: &wgpu::TextureView,
     sampler: &Sampler,
+    post_process_buffer: &wgpu::Buffer,
     scene_parameters: &wgpu::Buffer,
     object_buffer: &wgpu::Buffer,
     node_buffer: &wgpu::Buffer,
     object_index_buffer: &wgpu::Buffer,
+    material_buffer: &wgpu::Buffer,
+    point_buffer: &wgpu::Buffer,
+    photon_buffer: &wgpu::Buffer,
+    stats_buffer: &wgpu::Buffer,
     ray_tracing_bind_group_layout: &wgpu::BindGroupLayout,
     screen_bind_group_layout: &wgpu::BindGroupLayout,
-    sky_material: &CubeMapMaterial) -> (wgpu::BindGroup, wgpu::BindGroup) {
+    sky_material: &CubeMapMaterial,
+    aov_buffer: &wgpu::Buffer,
+    accumulation_buffer: &wgpu::Buffer,
+    object_textures: &TextureArrayMaterial,
+    probes: &wgpu::TextureView,
+    visibility: &wgpu::TextureView) -> (wgpu::BindGroup, wgpu::BindGroup) {
     // ----------Ray tracing bind groups---------- //
     let ray_tracing_bind_group_descriptor = wgpu::BindGroupDescriptor {
         label: Some("Ray bind Group Descriptor"),
-        layout: &ray_tracing_bind_group_layout,
+        layout: ray_tracing_bind_group_layout,
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::TextureView(&color_buffer_view),
+                resource: wgpu::BindingResource::TextureView(color_buffer_view),
             },
             wgpu::BindGroupEntry {
                 binding: 1,
@@ -556,6 +2907,78 @@ async fn make_bind_groups(
                 binding: 6,
                 resource: wgpu::BindingResource::Sampler(&sky_material.sampler),
             },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::Buffer(BufferBinding {
+                    buffer: aov_buffer,
+                    offset: 0,
+                    size: None, // Use the entire buffer
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::Buffer(BufferBinding {
+                    buffer: accumulation_buffer,
+                    offset: 0,
+                    size: None, // Use the entire buffer
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: wgpu::BindingResource::TextureView(&object_textures.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: wgpu::BindingResource::Sampler(&object_textures.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: wgpu::BindingResource::Buffer(BufferBinding {
+                    buffer: material_buffer,
+                    offset: 0,
+                    size: None, // Use the entire buffer
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: wgpu::BindingResource::Buffer(BufferBinding {
+                    buffer: &object_textures.region_buffer,
+                    offset: 0,
+                    size: None, // Use the entire buffer
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 13,
+                resource: wgpu::BindingResource::Buffer(BufferBinding {
+                    buffer: point_buffer,
+                    offset: 0,
+                    size: None, // Use the entire buffer
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 14,
+                resource: wgpu::BindingResource::Buffer(BufferBinding {
+                    buffer: photon_buffer,
+                    offset: 0,
+                    size: None, // Use the entire buffer
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 15,
+                resource: wgpu::BindingResource::Buffer(BufferBinding {
+                    buffer: stats_buffer,
+                    offset: 0,
+                    size: None, // Use the entire buffer
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 16,
+                resource: wgpu::BindingResource::TextureView(probes),
+            },
+            wgpu::BindGroupEntry {
+                binding: 17,
+                resource: wgpu::BindingResource::TextureView(visibility),
+            },
         ],
     };
     let ray_tracing_bind_group = device.create_bind_group(&ray_tracing_bind_group_descriptor);
@@ -563,17 +2986,22 @@ async fn make_bind_groups(
     // ----------Screen bind groups---------- //
     let screen_bind_group_descriptor = wgpu::BindGroupDescriptor {
         label: Some("Screen bind Group Descriptor"),
-        layout: &screen_bind_group_layout,
+        layout: screen_bind_group_layout,
         entries: &[
             // Sampler
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::Sampler(&sampler),
+                resource: wgpu::BindingResource::Sampler(sampler),
             },
             // Texture view
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: wgpu::BindingResource::TextureView(&color_buffer_view),
+                resource: wgpu::BindingResource::TextureView(color_buffer_view),
+            },
+            // Post-process uniform
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: post_process_buffer.as_entire_binding(),
             },
         ],
     };
@@ -586,24 +3014,49 @@ async fn make_pipeline(
     device: &wgpu::Device,
     ray_tracing_bind_group_layout: &wgpu::BindGroupLayout,
     screen_bind_group_layout: &wgpu::BindGroupLayout,
-    ) -> (wgpu::ComputePipeline, wgpu::RenderPipeline) {
-    // ----------Ray tracing pipeline---------- //
-    let ray_tracing_pipeline = create_ray_compute_pipeline(device, ray_tracing_bind_group_layout);
+    workgroup_size: (u32, u32),
+    push_constants: bool,
+    format: wgpu::TextureFormat,
+    ) -> (wgpu::ComputePipeline, wgpu::ComputePipeline, wgpu::RenderPipeline) {
+    // ----------Ray tracing pipelines---------- //
+    let ray_tracing_pipeline = create_ray_compute_pipeline(device, &[ray_tracing_bind_group_layout], "main", workgroup_size, push_constants);
+    let photon_pipeline = create_ray_compute_pipeline(device, &[ray_tracing_bind_group_layout], "photon_main", DEFAULT_WORKGROUP_SIZE, push_constants);
 
     // ----------Screen/render pipeline---------- //
-    let screen_pipeline = create_screen_pipeline(device, screen_bind_group_layout);
+    let screen_pipeline = create_screen_pipeline(device, screen_bind_group_layout, format);
 
     // Return the created resources
-    (ray_tracing_pipeline, screen_pipeline)
+    (ray_tracing_pipeline, photon_pipeline, screen_pipeline)
 }
 
-fn create_ray_compute_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::ComputePipeline {
-    let pipeline_layout = create_pipeline_layout(device, bind_group_layout);
+// With push_constants the kernel reads the camera and frame index from push constants, which every
+// dispatch with the pipeline then has to set, see frame_constants
+fn create_ray_compute_pipeline(device: &wgpu::Device, bind_group_layouts: &[&wgpu::BindGroupLayout], entry_point: &str, workgroup_size: (u32, u32), push_constants: bool) -> wgpu::ComputePipeline {
+    let push_constant_ranges: &[wgpu::PushConstantRange] = if push_constants {
+        &[wgpu::PushConstantRange { stages: wgpu::ShaderStages::COMPUTE, range: 0..FRAME_CONSTANTS_SIZE }]
+    } else {
+        &[]
+    };
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Pipeline Layout"),
+        bind_group_layouts,
+        push_constant_ranges,
+    });
 
-    // Create the shader module
+    // Create the shader module, with the main entry point's workgroup size swapped for the tuned one
+    let mut source = include_str!("../../shaders/raytracer_kernel.wgsl").replacen(
+        "@workgroup_size(8,8,1)\nfn main(",
+        &format!("@workgroup_size({},{},1)\nfn main(", workgroup_size.0, workgroup_size.1),
+        1,
+    );
+    if push_constants {
+        source = source
+            .replacen("var<private> frame: FrameConstants;", "var<push_constant> frame: FrameConstants;", 1)
+            .replace("    frame = frame_from_scene();\n", "");
+    }
     let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some("Tracing Shader Module"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/raytracer_kernel.wgsl").into()),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
     });
 
     // Define the compute pipeline descriptor with the shader module and entry point
@@ -611,14 +3064,16 @@ fn create_ray_compute_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::
         label: Some("Pipeline Descriptor"),
         layout: Some(&pipeline_layout),
         module: &shader_module,
-        entry_point: "main", // Entry point in the shader
+        entry_point, // Entry point in the shader
     };
 
     // Create the compute pipeline
     device.create_compute_pipeline(&pipeline_descriptor)
 }
 
-fn create_screen_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::RenderPipeline {
+// Draws the color buffer over a target of the given format, which should be an sRGB one as the
+// color buffer holds linear values
+fn create_screen_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
     let pipeline_layout = create_pipeline_layout(device, bind_group_layout);
 
     // Vertex shader module
@@ -646,7 +3101,7 @@ fn create_screen_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindG
             module: &fragment_shader_module,
             entry_point: "frag_main",
             targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                format,
                 blend: Some(wgpu::BlendState::REPLACE),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
@@ -667,6 +3122,196 @@ fn create_screen_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindG
     device.create_render_pipeline(&pipeline_descriptor)
 }
 
+// Pipeline drawing the loading bar over the frame, with the uniform holding how far along it is
+// A pipeline drawing a full screen triangle over the frame with the shader, which reads a uniform
+// of the given size at binding 0, and that uniform's buffer and bind group
+fn create_overlay_pipeline(device: &wgpu::Device, format: wgpu::TextureFormat, name: &str, source: &str, uniform_size: u64) -> (wgpu::RenderPipeline, wgpu::BindGroup, wgpu::Buffer) {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(&format!("{} Shader Module", name)),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{} Pipeline", name)),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vert_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "frag_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    });
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(&format!("{} Buffer", name)),
+        size: uniform_size,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(&format!("{} Bind Group", name)),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    });
+
+    (pipeline, bind_group, buffer)
+}
+
+// Reduces a viewport's accumulated radiance to its log-average luminance, with the bind group
+// layout taken from the shader
+fn create_exposure_pipeline(device: &wgpu::Device) -> wgpu::ComputePipeline {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Exposure Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/exposure_shader.wgsl").into()),
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Exposure Pipeline"),
+        layout: None,
+        module: &shader_module,
+        entry_point: "meter_main",
+    })
+}
+
+fn create_fade_pipeline(device: &wgpu::Device) -> wgpu::ComputePipeline {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Fade Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/fade_shader.wgsl").into()),
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Fade Pipeline"),
+        layout: None,
+        module: &shader_module,
+        entry_point: "fade_main",
+    })
+}
+
+// Draws triangles without vertex buffers, each vertex reads its corner from the object buffer
+fn create_raster_pipeline(device: &wgpu::Device) -> wgpu::RenderPipeline {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Raster Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/raster_shader.wgsl").into()),
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Raster Pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "raster_vertex",
+            buffers: &[],
+        },
+        // Both sides are drawn, the kernel culls backfaces of the materials that ask for it
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Greater,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "raster_fragment",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: wgpu::TextureFormat::R32Uint,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+// The kernel's probe_main, which writes the probe atlas bound second next to the ray tracing bind group
+fn create_probe_pipeline(device: &wgpu::Device, ray_tracing_bind_group_layout: &wgpu::BindGroupLayout, push_constants: bool) -> wgpu::ComputePipeline {
+    let target_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Probe Target Bind Group Layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: wgpu::TextureFormat::Rgba16Float,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        }],
+    });
+    create_ray_compute_pipeline(device, &[ray_tracing_bind_group_layout, &target_layout], "probe_main", DEFAULT_WORKGROUP_SIZE, push_constants)
+}
+
+// Texture of spherical harmonics coefficients, laid out as ProbeGrid::atlas_size describes
+fn create_probe_texture(device: &wgpu::Device, (width, height): (u32, u32), usage: wgpu::TextureUsages) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Probe Texture"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage,
+        view_formats: &[],
+    })
+}
+
+// New textures are zeroed, which the probe pass reads as probes without updates
+fn create_probe_atlas(device: &wgpu::Device, probe_pipeline: &wgpu::ComputePipeline, grid: Option<ProbeGrid>) -> ProbeAtlas {
+    let size = grid.map_or((1, 1), |grid| grid.atlas_size());
+    let texture = create_probe_texture(device, size, wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST);
+    let target = create_probe_texture(device, size, wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC);
+    let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+    let target_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Probe Target Bind Group"),
+        layout: &probe_pipeline.get_bind_group_layout(1),
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&target_view),
+        }],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    ProbeAtlas { grid, texture, view, target, target_bind_group }
+}
+
+// Draws an overlay pipeline's triangle over what the view already holds
+fn encode_overlay_pass(command_encoder: &mut wgpu::CommandEncoder, view: &TextureView, pipeline: &wgpu::RenderPipeline, bind_group: &wgpu::BindGroup, label: &str) {
+    let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
+
 fn create_pipeline_layout(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::PipelineLayout {
     device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Pipeline Layout"),
@@ -675,11 +3320,37 @@ fn create_pipeline_layout(device: &wgpu::Device, bind_group_layout: &wgpu::BindG
     })
 }
 
-fn load_cube_map_images(paths: Vec<&str>) -> Vec<DynamicImage> {
-    paths.into_iter().map(|path| {
-        ImageReader::open(Path::new(path))
-            .expect("Failed to open image")
-            .decode()
-            .expect("Failed to decode image")
-    }).collect()
-}
\ No newline at end of file
+// ----------Readback Functions---------- //
+fn read_buffer(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Staging Buffer"),
+        size: buffer.size(),
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder")
+    });
+    command_encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+    queue.submit(std::iter::once(command_encoder.finish()));
+
+    // Block until the copy is done and the staging buffer is mapped
+    let buffer_slice = staging_buffer.slice(..);
+    buffer_slice.map_async(wgpu::MapMode::Read, |result| result.expect("Failed to map readback buffer"));
+    device.poll(wgpu::Maintain::Wait);
+
+    let data = buffer_slice.get_mapped_range().to_vec();
+    staging_buffer.unmap();
+    data
+}
+
+// The color buffer holds linear values that the sRGB surface encodes on present
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
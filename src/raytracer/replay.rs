@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+
+use super::{Camera, ObjectId, Scene, Transform, Vec3};
+
+// Identifies replay files, bumped whenever the layout below changes
+const MAGIC: &[u8; 8] = b"RTRPL001";
+// The scale, the rotation column by column and the translation
+const TRANSFORM_FLOATS: usize = 13;
+
+// Layout, all little-endian:
+// magic, u32 objects in the scene when the recording started,
+// then per frame until the end of the file: CAMERA_FLOATS f32 of the camera, u32 transform count
+// and per transform a u32 object id and TRANSFORM_FLOATS f32
+/// Where the camera and the objects that moved were on one frame of a replay
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayFrame {
+    pub camera: Camera,
+    /// Objects whose transform changed since the frame before, with their new one
+    pub transforms: Vec<(ObjectId, Transform)>,
+}
+
+/// Writes where the camera and the objects are every frame to a replay file, for `Replay` to
+/// put them back there later
+pub struct ReplayRecorder {
+    writer: BufWriter<File>,
+    transforms: BTreeMap<ObjectId, Transform>, // As last written
+}
+
+impl ReplayRecorder {
+    /// Starts a replay of the loaded scene, its objects are where the scene's script put them
+    pub fn create(path: &str, scene: &Scene) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(scene.entries.len() as u32).to_le_bytes())?;
+        Ok(Self { writer, transforms: BTreeMap::new() })
+    }
+
+    /// Adds the scene as it is drawn this frame. Only transforms that changed since the last
+    /// frame are written, on the first one those of objects moved since they were added.
+    pub fn record(&mut self, scene: &Scene) -> io::Result<()> {
+        let transforms: Vec<(ObjectId, Transform)> = scene.entries.iter()
+            .map(|(id, entry)| (*id, entry.transform))
+            .filter(|(id, transform)| self.transforms.get(id).copied().unwrap_or_default() != *transform)
+            .collect();
+
+        write_f32s(&mut self.writer, &scene.camera.to_floats())?;
+        self.writer.write_all(&(transforms.len() as u32).to_le_bytes())?;
+        for (id, transform) in &transforms {
+            self.writer.write_all(&id.0.to_le_bytes())?;
+            let [x, y, z] = transform.rotation;
+            let t = transform.translation;
+            write_f32s(&mut self.writer, &[transform.scale, x.0, x.1, x.2, y.0, y.1, y.2, z.0, z.1, z.2, t.0, t.1, t.2])?;
+        }
+        self.transforms.extend(transforms);
+        // Every frame is flushed, so a replay of a crash has the frames up to it
+        self.writer.flush()
+    }
+}
+
+/// Frames recorded by `ReplayRecorder`, played back one per frame drawn in place of the keys,
+/// the camera path and the scene's script. The samples of a frame are seeded by its index, so a
+/// replay of the same scene draws the same frames every time.
+pub struct Replay {
+    /// Objects the scene had when the recording started
+    pub objects: u32,
+    pub frames: Vec<ReplayFrame>,
+    next: usize,
+}
+
+impl Replay {
+    /// Reads a replay. A recording cut off in the middle of a frame, e.g. by a crash, keeps the
+    /// frames before it.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let mut reader = bytes.as_slice();
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a replay"));
+        }
+        let objects = read_u32(&mut reader)?;
+
+        let mut frames = Vec::new();
+        while !reader.is_empty() {
+            match read_frame(&mut reader) {
+                Ok(frame) => frames.push(frame),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Self { objects, frames, next: 0 })
+    }
+
+    /// Whether the replay can have been recorded on the scene
+    pub fn check(&self, scene: &Scene) -> Result<(), String> {
+        if scene.entries.len() as u32 != self.objects {
+            return Err(format!("it was recorded on a scene of {} objects, not {}", self.objects, scene.entries.len()));
+        }
+        Ok(())
+    }
+
+    /// Puts the camera and the objects where the next frame has them, and whether that moved
+    /// any. None once every frame was played.
+    pub fn step(&mut self, scene: &mut Scene) -> Option<bool> {
+        let frame = self.frames.get(self.next)?;
+        self.next += 1;
+        let mut moved = scene.camera != frame.camera;
+        scene.camera = frame.camera;
+        for (id, transform) in &frame.transforms {
+            if scene.entries.get(id).is_some_and(|entry| entry.transform != *transform) {
+                scene.set_transform(*id, *transform);
+                moved = true;
+            }
+        }
+        Some(moved)
+    }
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<ReplayFrame> {
+    let camera = Camera::from_floats(read_f32s(reader)?)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown camera projection"))?;
+    let count = read_u32(reader)?;
+    let mut transforms = Vec::new();
+    for _ in 0..count {
+        let id = ObjectId(read_u32(reader)?);
+        let [scale, x0, x1, x2, y0, y1, y2, z0, z1, z2, t0, t1, t2] = read_f32s::<TRANSFORM_FLOATS>(reader)?;
+        let rotation = [Vec3(x0, x1, x2), Vec3(y0, y1, y2), Vec3(z0, z1, z2)];
+        transforms.push((id, Transform { scale, rotation, translation: Vec3(t0, t1, t2) }));
+    }
+    Ok(ReplayFrame { camera, transforms })
+}
+
+fn write_f32s(writer: &mut impl Write, values: &[f32]) -> io::Result<()> {
+    values.iter().try_for_each(|value| writer.write_all(&value.to_le_bytes()))
+}
+
+fn read_f32s<const N: usize>(reader: &mut impl Read) -> io::Result<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in &mut values {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        *value = f32::from_le_bytes(bytes);
+    }
+    Ok(values)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
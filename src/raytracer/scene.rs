@@ -1,13 +1,104 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::ops::Range;
+use std::path::Path;
 
 use rand::Rng;
+use rayon::prelude::*;
+use tracing::{debug_span, info_span};
 use winit::keyboard::KeyCode;
 
-use super::{Camera, Node, ObjMesh, Sphere, Square, Triangle, Vec3}; // Import the Rng trait to use random number generation methods
+use super::{build_point_clusters, read_point_cloud, label_aspect, label_uv_rect, reference_bounds, rotate_vector_around_axis, surface_area, Billboard, BvhReference, Camera, CameraRig, Curve, Edit, Heightmap, History, Instance, Material, MaterialId, MeshLod, MeshSequence, MeshSource, Node, NODE_FLOATS, OBJECT_FLOATS, POINT_STRIDE, ObjMesh, ObjectEntry, ObjectId, PointCluster, PostProcess, ProbeGrid, Prototype, Quad, RemovedObject, SkySource, Sphere, Square, Texture, TextureFiltering, Transform, Triangle, Vec3, DEFAULT_FADE_DURATION, DEFAULT_LAYERS, MAX_LABELS, MAX_LABEL_CHARS}; // Import the Rng trait to use random number generation methods
 
+// Cost of visiting one BVH node relative to intersecting one object, used by the SAH
+pub(crate) const TRAVERSAL_COST: f32 = 0.125;
+// Extra references spatial splits may add, as a share of the object count
+const SPATIAL_SPLIT_BUDGET: f32 = 0.3;
+// Spatial splits are tried when the object split's children overlap by more than this share of the root's area
+const SPATIAL_SPLIT_ALPHA: f32 = 1e-5;
+// Evenly spaced planes a spatial split chooses from
+const SPATIAL_SPLIT_BINS: usize = 8;
+// Deepest tree the kernel's traversal stack can walk
+const MAX_BVH_DEPTH: usize = 32;
+/// Version of the BVH builder, part of the BVH cache's key so trees an older builder made are
+/// rebuilt. Bump it whenever a change to the build gives another tree for the same scene.
+pub const BVH_BUILDER_VERSION: u32 = 1;
+// Removed objects' primitives are compacted away once they are more than a quarter of the list
+const COMPACTION_RATIO: usize = 4;
+// Subtrees with fewer references are built on the current thread, as spawning would cost more than it saves
+const PARALLEL_BUILD_THRESHOLD: usize = 4096;
+
+/// Openings the sky can shine through that the uniform buffer has room for
+pub const MAX_PORTALS: usize = 4;
+
+/// Size in bytes of the scene parameters uniform: camera and settings, then the portals at 48 bytes
+/// each and the probe grid's 48
+pub const SCENE_DATA_SIZE: u64 = 144 + 48 * MAX_PORTALS as u64 + 48;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Object {
     Sphere(Sphere),
     Triangle(Triangle),
+    Quad(Quad),
+    Billboard(Billboard),
+    Points(PointCluster),
+    Curve(Curve),
+    Instance(Instance),
+}
+
+// A node's references divided between its two children
+struct Split {
+    left: Vec<BvhReference>,
+    right: Vec<BvhReference>,
+    axis: usize, // The left child holds the references lower along this axis
+    cost: f32,
+}
+
+// Where a prototype's parts start in the buffers, see `prototype_offsets`
+#[derive(Clone, Copy)]
+struct PrototypeOffsets {
+    primitives: usize,
+    nodes: usize,
+    object_indices: usize,
+}
+
+// Part of the BVH built on its own, with its root node first
+struct Subtree {
+    nodes: Vec<Node>,
+    object_indices: Vec<usize>,
+}
+
+impl Subtree {
+    // Places two subtrees under a node, with their roots next to each other right after it
+    // so the kernel finds the right one at left_child + 1
+    fn join(mut node: Node, mut left: Subtree, mut right: Subtree) -> Subtree {
+        node.left_child = 1; // Points to its first child instead
+        node.object_count = 0; // And has no direct sphere count
+        left.relocate(3, 0);
+        right.relocate(2 + left.nodes.len(), left.object_indices.len());
+
+        let mut nodes = Vec::with_capacity(1 + left.nodes.len() + right.nodes.len());
+        nodes.extend([node, left.nodes[0], right.nodes[0]]);
+        nodes.extend_from_slice(&left.nodes[1..]);
+        nodes.extend_from_slice(&right.nodes[1..]);
+
+        let mut object_indices = left.object_indices;
+        object_indices.append(&mut right.object_indices);
+
+        Subtree { nodes, object_indices }
+    }
+
+    // Shifts child links for the nodes below the root moving to node_offset,
+    // and leaf ranges for the indices moving to index_offset
+    fn relocate(&mut self, node_offset: usize, index_offset: usize) {
+        for node in &mut self.nodes {
+            if node.object_count == 0 {
+                node.left_child += node_offset as i32 - 1;
+            } else {
+                node.left_child += index_offset as i32;
+            }
+        }
+    }
 }
 
 pub struct Scene {
@@ -16,12 +107,112 @@ pub struct Scene {
     pub nodes: Vec<Node>,
     pub nodes_used: usize,
     pub object_indices: Vec<usize>,
-    pub max_bounces: usize,
+    /// Mirror reflections a path may follow, kept high so reflections of reflections stay crisp
+    pub max_specular_bounces: usize,
+    /// Scatters off matte surfaces a path may follow, each adding noise and costing a full trace
+    pub max_diffuse_bounces: usize,
+    /// Largest leaf the BVH builder may keep when the SAH says splitting it would not pay off
+    pub max_leaf_size: usize,
+    /// Walk the BVH with skip links instead of a per-ray stack
+    pub stackless_traversal: bool,
+    pub write_aovs: bool,
+    /// Upload only the part of the BVH inside the camera's view each frame. Speeds up navigating
+    /// huge scenes, but objects outside the view also vanish from reflections.
+    pub frustum_culling: bool,
     pub keys_pressed: HashSet<KeyCode>,
+    pub entries: BTreeMap<ObjectId, ObjectEntry>,
+    /// Meshes shared by instances, see `make_instance`. They are never removed, so instances kept
+    /// in the history still find theirs.
+    pub prototypes: Vec<Prototype>,
+    // Primitives of removed objects, kept in place and hidden so removing doesn't move the others
+    // or rebuild the tree. Restores and compaction fill them again.
+    free_primitives: Vec<Range<usize>>,
+    pub image_paths: Vec<String>, // One entry of the texture lookup table per image
+    pub labels: Vec<String>, // One row of the text atlas per label, uploaded after the images
+    pub materials: Vec<Material>,
+    /// Cube maps that can be drawn behind the scene, each loaded the first time it is shown
+    pub skies: Vec<SkySource>,
+    /// Index into skies of the one drawn
+    pub active_sky: usize,
+    /// Turns the sky around the vertical axis, in degrees
+    pub sky_yaw: f32,
+    /// Multiplies the sky's color, brightening or dimming everything it lights
+    pub sky_intensity: f32,
+    /// Filtering of the sky and albedo textures, applied by the renderer when it changes
+    pub texture_filtering: TextureFiltering,
+    next_object_id: u32,
+    /// Set when objects were edited and the BVH and GPU buffers need to be rebuilt
+    pub dirty: bool,
+    /// Set when objects only moved, turned, changed size or were removed, so refitting the BVH's
+    /// bounds is enough
+    pub moved: bool,
+    /// Edits made through the editor, for undo and redo
+    pub history: History,
+    /// Objects whose triangles are swapped for the next file's as time passes
+    pub mesh_sequences: Vec<MeshSequence>,
+    /// Objects whose triangles are swapped for simpler ones the further they are from the camera
+    pub mesh_lods: Vec<MeshLod>,
+    /// Path the camera flies along instead of following the keys
+    pub camera_rig: Option<CameraRig>,
+    /// Viewpoints by name, added by scripts for batch jobs to render the scene from
+    pub cameras: BTreeMap<String, Camera>,
+    /// Files meshes were read from, to read them again when they change
+    pub mesh_sources: BTreeMap<ObjectId, MeshSource>,
+    /// Windows and other openings of an interior. Matte surfaces send part of their bounces
+    /// through them, so rooms lit only by the sky through a window converge much faster.
+    pub portals: Vec<Quad>,
+    /// Trace a few wavelengths per sample instead of red, green and blue, so glass with
+    /// dispersion splits light into colors. Needs more samples to converge.
+    pub spectral: bool,
+    /// Brightest a sample may be before it is scaled down, cutting fireflies at the cost of
+    /// dimming bright highlights. 0 turns the clamp off.
+    pub max_radiance: f32,
+    /// Least roughness of mirror reflections seen after a matte bounce. Blurs the rarely found
+    /// caustic-like paths that show up as fireflies.
+    pub roughness_regularization: f32,
+    /// Scale down samples far brighter than their pixel's average so far before adding them
+    pub outlier_rejection: bool,
+    /// Trace photons from the sky through mirrors and glass each frame and add the light they
+    /// leave on matte surfaces, so caustics show up long before camera paths find them
+    pub caustics: bool,
+    /// Size of the cells photons are gathered over. Smaller cells give sharper but noisier caustics.
+    pub photon_radius: f32,
+    /// Pair every camera path with a short light subpath from the sky and connect their matte
+    /// bounces, so rooms lit through small openings converge without portals
+    pub bidirectional: bool,
+    /// Trace one sample per 2x2 block of pixels while the camera moves or the scene is edited,
+    /// going back to full resolution once it settles
+    pub interaction_mode: bool,
+    /// Pixels along each side of the blocks sharing one sample, set by the renderer
+    pub block_size: u32,
+    /// Color each pixel by how its camera ray's cost of traversing the BVH ranks among the rest
+    /// of the view, from blue for the cheapest to red for the most expensive, instead of
+    /// shading it. Shows where a build strategy leaves the tree expensive, see `bvh_cost`.
+    pub bvh_heatmap: bool,
+    /// Stops the view is brightened by, darkened for negative values. Only what is shown
+    /// changes, captures keep the radiance as traced.
+    pub exposure: f32,
+    /// Meter the main window's brightness each frame and adapt the exposure to it over time, on
+    /// top of `exposure`, so moving between a dark interior and the bright sky doesn't blow out
+    pub auto_exposure: bool,
+    /// Factor auto exposure adapted the view to, set by the renderer
+    pub adapted_exposure: f32,
+    /// Chromatic aberration, vignette and film grain drawn over the view
+    pub post_process: PostProcess,
+    /// Seconds the main window fades from its last converged frame to the new samples after the
+    /// camera moves, instead of popping to a single noisy sample. 0 turns the fade off.
+    pub fade_duration: f32,
+    /// Irradiance probes the window's renderer updates each frame, lighting what matte surfaces
+    /// reflect instead of tracing their bounces. Offline and CPU renders trace every bounce.
+    pub probe_grid: Option<ProbeGrid>,
+    /// Rasterize the triangles in front of the window's camera and start its rays from them
+    /// instead of walking the BVH, much faster on dense meshes. Only perspective cameras are
+    /// rasterized, and the window's renderer is the only one that does.
+    pub raster_primary: bool,
 }
 
 impl Scene {
-    // Initialize an empty scene
+    // Initialize an empty scene, allowing max_bounces of both diffuse and specular bounces
     pub fn new(max_bounces: usize, width: f32, height: f32) -> Self {
         Self {
             objects: Vec::new(),
@@ -29,8 +220,54 @@ impl Scene {
             nodes: Vec::new(),
             nodes_used: 0,
             object_indices: Vec::new(),
-            max_bounces,
+            max_specular_bounces: max_bounces,
+            max_diffuse_bounces: max_bounces,
+            max_leaf_size: 4,
+            stackless_traversal: false,
+            write_aovs: false,
+            frustum_culling: false,
             keys_pressed: HashSet::new(),
+            entries: BTreeMap::new(),
+            prototypes: Vec::new(),
+            free_primitives: Vec::new(),
+            image_paths: Vec::new(),
+            labels: Vec::new(),
+            materials: vec![
+                Material::default(),
+                Material { two_sided: true, ..Default::default() },
+            ],
+            skies: vec![SkySource::default()],
+            active_sky: 0,
+            sky_yaw: 0.0,
+            sky_intensity: 1.0,
+            texture_filtering: TextureFiltering::default(),
+            next_object_id: 0,
+            dirty: false,
+            moved: false,
+            history: History::default(),
+            mesh_sequences: Vec::new(),
+            mesh_lods: Vec::new(),
+            camera_rig: None,
+            cameras: BTreeMap::new(),
+            mesh_sources: BTreeMap::new(),
+            portals: Vec::new(),
+            spectral: false,
+            max_radiance: 0.0,
+            roughness_regularization: 0.0,
+            outlier_rejection: false,
+            caustics: false,
+            photon_radius: 0.05,
+            bidirectional: false,
+            interaction_mode: false,
+            block_size: 1,
+            bvh_heatmap: false,
+            exposure: 0.0,
+            auto_exposure: false,
+            adapted_exposure: 1.0,
+            post_process: PostProcess::default(),
+            fade_duration: DEFAULT_FADE_DURATION,
+            probe_grid: None,
+            raster_primary: false,
         }
     }
 
@@ -58,8 +295,7 @@ impl Scene {
             if i % 2 == 0 {
                 // Add a sphere...
                 let sphere_radius = 0.1 + 1.9 * rng.gen::<f32>(); // Random sphere radius
-                let sphere = Sphere::new(center, color, sphere_radius);
-                self.objects.push(Object::Sphere(sphere));
+                self.add_sphere(center, color, sphere_radius);
             } else {
                 // Add a triangle...
                 let offsets = [
@@ -68,241 +304,1051 @@ impl Scene {
                 Vec3(-3.0 + 6.0 * rng.gen::<f32>(), -3.0 + 6.0 * rng.gen::<f32>(), -3.0 + 6.0 * rng.gen::<f32>())
                 ];
                 let triangle = Triangle::build_from_center_and_offsets(center, offsets, color);
+                let start = self.objects.len();
                 self.objects.push(Object::Triangle(triangle));
+                self.register(start);
             }
         }
     }
 
     /// Method to add a Sphere to the scene
-    pub fn add_sphere(&mut self, center: Vec3, color: Vec3, radius: f32) {
+    pub fn add_sphere(&mut self, center: Vec3, color: Vec3, radius: f32) -> ObjectId {
+        let start = self.objects.len();
         let sphere = Sphere::new(center, color, radius);
         self.objects.push(Object::Sphere(sphere));
+        self.register(start)
     }
 
     /// Method to add a Triangle to the scene
-    pub fn add_triangle(&mut self, corners: [Vec3; 3], color: Vec3) {
+    pub fn add_triangle(&mut self, corners: [Vec3; 3], color: Vec3) -> ObjectId {
+        let start = self.objects.len();
         let triangle = Triangle::build_from_corners(corners, color);
         self.objects.push(Object::Triangle(triangle));
+        self.register(start)
     }
 
     /// Method to add a mesh to the scene
-    pub fn add_square(&mut self, center: Vec3, height: f32, width: f32, color: Vec3, orientation: f32) {
-        for triangle in Square::new(center, height, width, color, orientation).triangles {
+    pub fn add_square(&mut self, center: Vec3, height: f32, width: f32, color: Vec3, orientation: f32) -> ObjectId {
+        let start = self.objects.len();
+        let mut quad = Square::new(center, height, width, color, orientation).quad;
+        quad.material = MaterialId::TWO_SIDED.0; // Squares are thin, so shade both sides
+        self.objects.push(Object::Quad(quad));
+        self.register(start)
+    }
+
+    /// Method to add an OBJ, STL or PLY mesh to the scene, named after the file it was loaded from
+    pub fn add_object_mesh(&mut self, path: &str) -> ObjectId {
+        self.add_obj_mesh(ObjMesh::load(Vec3(1.0, 1.0, 1.0), path).expect("Failed to load mesh"))
+    }
+
+    /// Adds an OBJ mesh as it was posed with `at`, `scaled` and `rotated`, named after its file
+    pub fn add_obj_mesh(&mut self, mesh: ObjMesh) -> ObjectId {
+        let start = self.objects.len();
+        let source = mesh.source().clone();
+        for triangle in mesh.triangles {
             self.objects.push(Object::Triangle(triangle));
         }
+        let id = self.register(start);
+        if !mesh.name.is_empty() {
+            self.set_name(id, &mesh.name);
+        }
+        self.mesh_sources.insert(id, source);
+        id
     }
 
-    /// Method to add a mesh to the scene
-    pub fn add_object_mesh(&mut self, path: &str) {
-        for triangle in ObjMesh::new(Vec3(1.0, 1.0, 1.0), path).triangles {
-            self.objects.push(Object::Triangle(triangle));
+    /// Adds a mesh like `add_obj_mesh` along with up to `levels` simplified copies of it, which
+    /// are swapped in the further it is from the camera, see `MeshLod`
+    pub fn add_obj_mesh_lods(&mut self, mesh: ObjMesh, levels: usize) -> ObjectId {
+        let lods = mesh.levels_of_detail(levels);
+        let id = self.add_obj_mesh(mesh);
+        self.mesh_lods.push(MeshLod::new(id, lods));
+        id
+    }
+
+    /// Reads the mesh's file again and swaps in its triangles, posed as before, simplifying its
+    /// levels of detail again if it has them. Returns false for objects that weren't read from
+    /// a file or were removed since.
+    pub fn reload_mesh(&mut self, id: ObjectId) -> io::Result<bool> {
+        let Some(source) = self.mesh_sources.get(&id).filter(|_| self.entries.contains_key(&id)) else { return Ok(false) };
+        let mesh = source.load()?;
+        if let Some(lod) = self.mesh_lods.iter_mut().find(|lod| lod.id == id) {
+            *lod = MeshLod::new(id, mesh.levels_of_detail(lod.levels.len() - 1));
         }
+        self.set_triangles(id, mesh.triangles);
+        Ok(true)
     }
 
-    pub fn make_scene(&mut self) {
-        // Initialize object indices for easy tracking
-        self.object_indices = (0..self.objects.len()).collect();
+    /// Adds a generated mesh, such as one from `shapes::procedural`, as one object using the given material
+    pub fn add_mesh(&mut self, triangles: Vec<Triangle>, material: MaterialId) -> ObjectId {
+        let start = self.objects.len();
+        self.objects.extend(triangles.into_iter().map(|mut triangle| {
+            triangle.material = material.0;
+            Object::Triangle(triangle)
+        }));
+        self.register(start)
+    }
 
-        // Now, build the BVH for the scene
-        self.build_bvh();
+    /// Adds a text label at `position`, `height` tall, that always faces the camera.
+    /// The object is named after its text so it can be found again.
+    pub fn add_label(&mut self, position: Vec3, text: &str, height: f32, color: Vec3) -> ObjectId {
+        assert!(self.labels.len() < MAX_LABELS, "The text atlas holds at most {} labels", MAX_LABELS);
+        let text: String = text.chars().take(MAX_LABEL_CHARS).collect();
+
+        let start = self.objects.len();
+        let billboard = Billboard::new(position, height * label_aspect(&text), height, color, self.labels.len());
+        self.objects.push(Object::Billboard(billboard));
+        self.labels.push(text.clone());
+        self.dirty = true;
+
+        let id = self.register(start);
+        self.set_name(id, &text);
+        id
     }
 
-    fn build_bvh(&mut self) {
-        // Initialize sphere indices for easy tracking
-        self.object_indices = (0..self.objects.len()).collect();
-        self.nodes = vec![Node::default(); 2 * self.objects.len() - 1]; // Placeholder for actual size
-        
-        let root_index = 0;
-        let node = &mut self.nodes[root_index];
-        node.left_child = 0; // Starting index for sphere indices
-        node.object_count = self.objects.len();
-        self.nodes_used = 1;
-        
-        self.update_bounds(root_index);
-        self.subdivide(root_index);
-    }
-
-    fn update_bounds(&mut self, node_index: usize) {
-        let node = &mut self.nodes[node_index];
-
-        // Reset bounds to extreme values
-        node.min_corner = Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY);
-        node.max_corner = Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
-
-        let start_index = node.left_child as usize;
-        let end_index = start_index + node.object_count as usize;
-
-        for &i in &self.object_indices[start_index..end_index] {
-            match &self.objects[i] {
-                Object::Sphere(sphere) => {
-                    let min = sphere.center - sphere.radius;
+    /// Adds terrain generated from a grayscale image, `scale` wide along x and z and `scale.1` high.
+    /// Each chunk of the grid becomes its own object, named after the file and the chunk's index.
+    pub fn add_heightmap(&mut self, path: &str, scale: Vec3) -> Vec<ObjectId> {
+        let stem = Path::new(path).file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "heightmap".to_string());
 
-                    let max = sphere.center + sphere.radius;
+        let mut ids = Vec::new();
+        for (i, chunk) in Heightmap::new(Vec3(1.0, 1.0, 1.0), path, scale).chunks.into_iter().enumerate() {
+            let start = self.objects.len();
+            self.objects.extend(chunk.into_iter().map(Object::Triangle));
+            let id = self.register(start);
+            self.set_name(id, &format!("{}_{}", stem, i));
+            ids.push(id);
+        }
+        ids
+    }
 
-                    node.min_corner.0 = node.min_corner.0.min(min.0);
-                    node.max_corner.0 = node.max_corner.0.max(max.0);
+    /// Adds hair or fur strands as one object using the given material, usually one with `hair` set
+    pub fn add_curves(&mut self, curves: Vec<Curve>, material: MaterialId) -> ObjectId {
+        let start = self.objects.len();
+        self.objects.extend(curves.into_iter().map(|mut curve| {
+            curve.material = material.0;
+            Object::Curve(curve)
+        }));
+        self.register(start)
+    }
 
-                    node.min_corner.1 = node.min_corner.1.min(min.1);
-                    node.max_corner.1 = node.max_corner.1.max(max.1);
+    /// Marks the parallelogram spanned by `edge_u` and `edge_v` from `corner` as an opening the sky
+    /// lights the scene through, like a window. It isn't drawn, so the window itself still needs
+    /// to be left open or filled with transparent glass. Fails past MAX_PORTALS portals.
+    pub fn add_portal(&mut self, corner: Vec3, edge_u: Vec3, edge_v: Vec3) -> Result<(), String> {
+        if self.portals.len() >= MAX_PORTALS {
+            return Err(format!("scenes hold at most {} portals", MAX_PORTALS));
+        }
+        self.portals.push(Quad::new(corner, edge_u, edge_v, Vec3(0.0, 0.0, 0.0)));
+        Ok(())
+    }
 
-                    node.min_corner.2 = node.min_corner.2.min(min.2);
-                    node.max_corner.2 = node.max_corner.2.max(max.2);
-                },
-                Object::Triangle(triangle) => {
-                    for corner in &triangle.corners {
-                        node.min_corner.0 = node.min_corner.0.min(corner.0);
-                        node.max_corner.0 = node.max_corner.0.max(corner.0);
+    /// Adds the points of an XYZ or PLY point cloud as spheres of the given radius, named after the file.
+    /// Neighbouring points are grouped so each group takes one slot in the object buffer.
+    pub fn add_point_cloud(&mut self, path: &str, radius: f32) -> ObjectId {
+        let (points, colors) = read_point_cloud(path).expect("Failed to load point cloud");
+        let start = self.objects.len();
+        self.objects.extend(build_point_clusters(points, colors, radius).into_iter().map(Object::Points));
+        let id = self.register(start);
+        if let Some(stem) = Path::new(path).file_stem() {
+            self.set_name(id, &stem.to_string_lossy());
+        }
+        id
+    }
 
-                        node.min_corner.1 = node.min_corner.1.min(corner.1);
-                        node.max_corner.1 = node.max_corner.1.max(corner.1);
+    /// Plays the OBJ, STL and PLY files in a folder back as one animated object, one file per frame
+    /// in order of file name at `fps` frames per second, looping. The object is named after the folder.
+    pub fn add_mesh_sequence(&mut self, folder: &str, fps: f32) -> ObjectId {
+        let frames = MeshSequence::load(folder).expect("Failed to load mesh sequence");
+        let start = self.objects.len();
+        self.objects.extend(frames[0].iter().cloned().map(Object::Triangle));
+        let id = self.register(start);
+        if let Some(name) = Path::new(folder).file_name() {
+            self.set_name(id, &name.to_string_lossy());
+        }
+        self.mesh_sequences.push(MeshSequence::new(id, frames, fps));
+        id
+    }
 
-                        node.min_corner.2 = node.min_corner.2.min(corner.2);
-                        node.max_corner.2 = node.max_corner.2.max(corner.2);
-                    }
-                },
+    // Swaps the triangles of sequences that reached another frame
+    fn advance_mesh_sequences(&mut self) {
+        // Sequences of removed objects stop playing
+        let entries = &self.entries;
+        self.mesh_sequences.retain(|sequence| entries.contains_key(&sequence.id));
+
+        for i in 0..self.mesh_sequences.len() {
+            let frame = self.mesh_sequences[i].current_frame();
+            if frame == self.mesh_sequences[i].shown {
+                continue;
+            }
+            self.mesh_sequences[i].shown = frame;
+            let id = self.mesh_sequences[i].id;
+            let triangles = self.mesh_sequences[i].frames[frame].clone();
+            self.set_triangles(id, triangles);
+        }
+    }
+
+    // Swaps in the level of detail of each mesh that has them for its distance from the camera
+    fn select_mesh_lods(&mut self) {
+        // Levels of removed objects are dropped
+        let entries = &self.entries;
+        self.mesh_lods.retain(|lod| entries.contains_key(&lod.id));
+
+        for i in 0..self.mesh_lods.len() {
+            let level = self.mesh_lods[i].level_for(self.camera.origin);
+            if level == self.mesh_lods[i].shown {
+                continue;
+            }
+            self.mesh_lods[i].shown = level;
+            let id = self.mesh_lods[i].id;
+            let triangles = self.mesh_lods[i].levels[level].clone();
+            self.set_triangles(id, triangles);
+        }
+    }
+
+    // Replaces the object's triangles, keeping the material and color it was given. As many
+    // triangles as before only need the BVH refit, others change the object's size and rebuild it.
+    fn set_triangles(&mut self, id: ObjectId, triangles: Vec<Triangle>) {
+        let Some(entry) = self.entries.get(&id) else { return };
+        let range = entry.primitives.clone();
+        // Instances swap the triangles they share with every other instance of the prototype
+        if let [Object::Instance(instance)] = &self.objects[range.clone()] {
+            let prototype = instance.prototype;
+            self.prototypes[prototype] = self.build_prototype(triangles.into_iter().map(Object::Triangle).collect());
+            self.dirty = true;
+        } else if range.len() == triangles.len() {
+            for (object, mut triangle) in self.objects[range].iter_mut().zip(triangles) {
+                if let Object::Triangle(shown) = object {
+                    triangle.material = shown.material;
+                    triangle.color = shown.color;
+                }
+                *object = Object::Triangle(triangle);
             }
-            
+            self.moved = true;
+        } else {
+            let mut removed = self.take(id).unwrap();
+            let (material, color) = match removed.primitives.first() {
+                Some(Object::Triangle(triangle)) => (triangle.material, triangle.color),
+                _ => (0, Vec3(1.0, 1.0, 1.0)),
+            };
+            removed.primitives = triangles.into_iter()
+                .map(|triangle| Object::Triangle(Triangle { material, color, ..triangle }))
+                .collect();
+            self.restore(removed);
         }
     }
 
-    fn subdivide(&mut self, node_index: usize) {
-        if self.nodes[node_index].object_count <= 2 {
-            return; // Base case: node is sufficiently small
+    // Hands out a handle for the primitives pushed since `start`
+    fn register(&mut self, start: usize) -> ObjectId {
+        let id = ObjectId(self.next_object_id);
+        self.next_object_id += 1;
+        self.entries.insert(id, ObjectEntry { name: None, primitives: start..self.objects.len(), layers: DEFAULT_LAYERS, visible: true, transform: Transform::default() });
+        id
+    }
+
+    /// Looks up the first object with the given name
+    /// Spreads irradiance probes about `spacing` apart over the bounds of the built scene, or
+    /// removes them for a spacing of 0
+    pub fn fit_probe_grid(&mut self, spacing: f32) {
+        self.probe_grid = self.bounds()
+            .filter(|_| spacing > 0.0)
+            .map(|(min, max)| ProbeGrid::fit(min, max, spacing));
+    }
+
+    pub fn find(&self, name: &str) -> Option<ObjectId> {
+        self.entries.iter()
+            .find(|(_, entry)| entry.name.as_deref() == Some(name))
+            .map(|(&id, _)| id)
+    }
+
+    pub fn name(&self, id: ObjectId) -> Option<&str> {
+        self.entries.get(&id)?.name.as_deref()
+    }
+
+    pub fn set_name(&mut self, id: ObjectId, name: &str) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.name = Some(name.to_string());
         }
+    }
 
-        let axis = self.longest_axis(node_index);
-        let (split, i) = self.median_split(node_index, axis);
+    pub fn layers(&self, id: ObjectId) -> Option<u32> {
+        Some(self.entries.get(&id)?.layers)
+    }
 
-        if split == 0 || split == self.nodes[node_index].object_count as usize {
-            return;
+    /// Puts the object on the layers whose bits are set, replacing the ones it was on
+    pub fn set_layers(&mut self, id: ObjectId, layers: u32) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.layers = layers;
+            self.dirty = true;
         }
+    }
 
-        let left_child_index = self.nodes_used;
-        self.nodes_used += 1;
-        let right_child_index = self.nodes_used;
-        self.nodes_used += 1;
-
-        self.nodes[left_child_index].left_child = self.nodes[node_index].left_child;
-        self.nodes[left_child_index].object_count = split;
-
-        self.nodes[right_child_index].left_child = i as i32;
-        self.nodes[right_child_index].object_count = self.nodes[node_index].object_count - split;
-        
-        self.nodes[node_index].left_child = left_child_index as i32; // Points to its first child instead
-        self.nodes[node_index].object_count = 0; // And has no direct sphere count
-        
-        // Recurse for each child
-        self.update_bounds(left_child_index);
-        self.update_bounds(right_child_index);
-        self.subdivide(left_child_index);
-        self.subdivide(right_child_index);
-    }
-
-    fn longest_axis(&self, node_index: usize) -> usize {
-        let node = &self.nodes[node_index];
-        let extent = node.max_corner - node.min_corner;
-    
-        if extent.0 > extent.1 && extent.0 > extent.2 {
-            0
-        } else if extent.1 > extent.2 {
-            1
-        } else {
-            2
+    pub fn is_visible(&self, id: ObjectId) -> Option<bool> {
+        Some(self.entries.get(&id)?.visible)
+    }
+
+    /// Hides the object from the render or shows it again, keeping it in the scene either way
+    pub fn set_visible(&mut self, id: ObjectId, visible: bool) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.visible = visible;
+            self.dirty = true;
         }
     }
 
-    fn median_split(&mut self, node_index: usize, axis: usize) -> (usize, usize) {
-        let node = &self.nodes[node_index];
-        let extent = node.max_corner - node.min_corner;
+    /// Takes the object out of the scene. Its handle stops working, other handles stay valid.
+    pub fn remove(&mut self, id: ObjectId) {
+        self.take(id);
+    }
+
+    /// Removes the object, returning it so it can be put back with `restore`. Its primitives are
+    /// hidden where they are, so only they change on the GPU and the tree is refit rather than
+    /// rebuilt, until enough have piled up to compact the object list.
+    pub fn take(&mut self, id: ObjectId) -> Option<RemovedObject> {
+        let entry = self.entries.remove(&id)?;
+        let range = entry.primitives.clone();
+        let primitives = self.objects[range.clone()].to_vec();
+        self.free_primitives.push(range);
+        self.moved = true;
+
+        if self.free_primitive_count() * COMPACTION_RATIO > self.objects.len() {
+            self.compact_objects();
+        }
+        Some(RemovedObject { id, entry, primitives })
+    }
 
-        
-        let split_pos = match axis {
-            0 => node.min_corner.0 + extent.0 / 2.0,
-            1 => node.min_corner.1 + extent.1 / 2.0,
-            _ => node.min_corner.2 + extent.2 / 2.0,
+    /// Puts a removed object back under its old handle, into a free slot of its size when there is
+    /// one, like the one it was taken from
+    pub fn restore(&mut self, removed: RemovedObject) {
+        let slot = self.free_primitives.iter().position(|slot| slot.len() == removed.primitives.len());
+        let primitives = match slot {
+            Some(i) => {
+                let slot = self.free_primitives.swap_remove(i);
+                for (object, primitive) in self.objects[slot.clone()].iter_mut().zip(removed.primitives) {
+                    *object = primitive;
+                }
+                // The tree still lists the slot, it only has to be refit to what is in it now
+                self.moved = true;
+                slot
+            },
+            None => {
+                let start = self.objects.len();
+                self.objects.extend(removed.primitives);
+                self.dirty = true;
+                start..self.objects.len()
+            },
         };
-    
-        let start = node.left_child as usize;
-        let end = start + node.object_count as usize;
-        let mut i = start;
-        let mut j = end - 1;
-        
-        while i <= j {
-            if self.object_position(&self.objects[self.object_indices[i]], axis) < split_pos {
-                i += 1;
-            } else {
-                self.object_indices.swap(i, j);
-                if j == 0 { break; } // Prevent underflow
-                j -= 1;
+        self.entries.insert(removed.id, ObjectEntry { primitives, ..removed.entry });
+    }
+
+    /// Primitives left behind by removed objects that no object uses
+    pub fn free_primitive_count(&self) -> usize {
+        self.free_primitives.iter().map(Range::len).sum()
+    }
+
+    /// Moves objects from the end of the object list into the slots removals left where they fit,
+    /// then drops the free space at the end. Only the moved objects are rewritten, and the tree keeps
+    /// its shape with its object indices pointing at their new places, unless a leaf ends up empty
+    /// and the tree has to be rebuilt.
+    pub fn compact_objects(&mut self) {
+        if self.free_primitives.is_empty() {
+            return;
+        }
+        let freed: Vec<Range<usize>> = self.free_primitives.clone();
+        let mut new_index: Vec<usize> = (0..self.objects.len()).collect();
+
+        // Last objects first, each into the lowest slot before it that is large enough
+        let mut by_start: Vec<ObjectId> = self.entries.keys().copied().collect();
+        by_start.sort_by_key(|id| std::cmp::Reverse(self.entries[id].primitives.start));
+        for id in by_start {
+            let range = self.entries[&id].primitives.clone();
+            self.free_primitives.sort_by_key(|slot| slot.start);
+            // Objects before every slot stay where they are
+            if self.free_primitives.first().is_some_and(|slot| slot.start > range.start) {
+                break;
+            }
+            let Some(i) = self.free_primitives.iter()
+                .position(|slot| slot.start < range.start && slot.len() >= range.len()) else { continue };
+            let target = self.free_primitives[i].start..self.free_primitives[i].start + range.len();
+            for (offset, old) in range.clone().enumerate() {
+                self.objects[target.start + offset] = self.objects[old].clone();
+                new_index[old] = target.start + offset;
+            }
+            self.free_primitives[i].start = target.end;
+            self.free_primitives.push(range);
+            self.entries.get_mut(&id).unwrap().primitives = target;
+        }
+
+        // Free space at the end is dropped, the rest stays hidden until something fits in it
+        self.free_primitives.retain(|slot| !slot.is_empty());
+        self.free_primitives.sort_by_key(|slot| slot.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for slot in self.free_primitives.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.end == slot.start => last.end = slot.end,
+                _ => merged.push(slot),
+            }
+        }
+        if merged.last().is_some_and(|last| last.end == self.objects.len()) {
+            self.objects.truncate(merged.pop().unwrap().start);
+        }
+        self.free_primitives = merged;
+
+        // A stale tree is rebuilt anyway, otherwise its leaves follow the moved objects, drop the
+        // slots that were filled or cut off and keep listing those still free, so restoring an
+        // object into one only needs a refit
+        if !self.dirty {
+            let was_free = slot_mask(&freed, new_index.len());
+            let is_free = slot_mask(&self.free_primitives, new_index.len());
+            let object_indices = remap_leaves(&mut self.nodes[..self.nodes_used], &self.object_indices, self.objects.len(), |index, mapped| {
+                if !was_free[index] {
+                    mapped.push(new_index[index]);
+                }
+                if is_free[index] {
+                    mapped.push(index);
+                }
+            });
+            match object_indices {
+                Some(object_indices) => self.object_indices = object_indices,
+                None => self.dirty = true,
             }
         }
-        
-        (i - start, i) // Return the count of spheres in the left partition
+        self.moved = true;
+    }
+
+    // Closes every gap removals left by moving the objects after it down, before a full rebuild
+    fn pack_objects(&mut self) {
+        self.compact_objects();
+        if self.free_primitives.is_empty() {
+            return;
+        }
+        let removed = slot_mask(&std::mem::take(&mut self.free_primitives), self.objects.len());
+        // One past the end too, where objects without primitives may start
+        let mut new_index = vec![0; self.objects.len() + 1];
+        let mut kept = 0;
+        for (index, new) in new_index.iter_mut().enumerate() {
+            *new = kept;
+            kept += (index < self.objects.len() && !removed[index]) as usize;
+        }
+        let mut index = 0;
+        self.objects.retain(|_| {
+            index += 1;
+            !removed[index - 1]
+        });
+        for entry in self.entries.values_mut() {
+            let start = new_index[entry.primitives.start];
+            entry.primitives = start..start + entry.primitives.len();
+        }
     }
 
-    fn object_position(&self, object: &Object, axis: usize) -> f32 {
-        match object {
-            Object::Sphere(sphere) => {
-                match axis {
-                    0 => sphere.center.0,
-                    1 => sphere.center.1,
-                    _ => sphere.center.2,
+    /// Copy of the object as it is now, to record in the history
+    pub fn snapshot(&self, id: ObjectId) -> Option<RemovedObject> {
+        let entry = self.entries.get(&id)?.clone();
+        let primitives = self.objects[entry.primitives.clone()].to_vec();
+        Some(RemovedObject { id, entry, primitives })
+    }
+
+    /// The primitives the object is built from
+    pub fn primitives(&self, id: ObjectId) -> Option<&[Object]> {
+        Some(&self.objects[self.entries.get(&id)?.primitives.clone()])
+    }
+
+    /// Overwrites the object's primitives and transform with ones from an earlier snapshot of the same object
+    pub fn replace_primitives(&mut self, id: ObjectId, primitives: &[Object], transform: Transform) {
+        let Some(entry) = self.entries.get_mut(&id) else { return };
+        if entry.primitives.len() == primitives.len() {
+            entry.transform = transform;
+            self.objects[entry.primitives.clone()].clone_from_slice(primitives);
+            self.dirty = true;
+        }
+    }
+
+    /// Reverts the last edit in the history, returning false when there was none
+    pub fn undo(&mut self) -> bool {
+        let Some(edit) = self.history.pop_undo() else { return false };
+        self.apply_edit(&edit, true);
+        self.history.push_undone(edit);
+        true
+    }
+
+    /// Makes the last undone edit again, returning false when there was none
+    pub fn redo(&mut self) -> bool {
+        let Some(edit) = self.history.pop_redo() else { return false };
+        self.apply_edit(&edit, false);
+        self.history.push_redone(edit);
+        true
+    }
+
+    // Brings the scene to the state after the edit, or before it when reverting
+    fn apply_edit(&mut self, edit: &Edit, revert: bool) {
+        match edit {
+            Edit::Added(object) | Edit::Removed(object) => {
+                // Undoing an addition removes, like redoing a removal does
+                if revert == matches!(edit, Edit::Added(_)) {
+                    self.take(object.id);
+                } else {
+                    self.restore(object.clone());
                 }
             },
-            Object::Triangle(triangle) => {
-                // For a triangle, use the centroid or an average position of its corners for sorting
-                match axis {
-                    0 => triangle.centroid.0,
-                    1 => triangle.centroid.1,
-                    _ => triangle.centroid.2,
+            Edit::Primitives { id, before, after, transforms: [transform_before, transform_after] } => {
+                if revert {
+                    self.replace_primitives(*id, before, *transform_before);
+                } else {
+                    self.replace_primitives(*id, after, *transform_after);
+                }
+            },
+            Edit::Material { index, before, after } => {
+                self.materials[*index] = if revert { *before } else { *after };
+                self.dirty = true;
+            },
+            Edit::Group(edits) => {
+                if revert {
+                    edits.iter().rev().for_each(|edit| self.apply_edit(edit, true));
+                } else {
+                    edits.iter().for_each(|edit| self.apply_edit(edit, false));
                 }
             },
         }
     }
 
-    pub fn flatten_scene_data(&self) -> Vec<u8> {
-        let scene_data_flat: [f32; 17] = [
+    /// Adds a copy of the object with the same name, layers and visibility, returning its handle.
+    /// Meshes are made instances first, see `make_instance`, so the copy shares their triangles
+    /// and only adds an instance; other objects are copied primitive by primitive. The copy keeps
+    /// the material, so material edits still reach both until one is made unique. Callers keeping
+    /// a history call `make_instance` first to record its edit.
+    pub fn duplicate(&mut self, id: ObjectId) -> Option<ObjectId> {
+        self.make_instance(id);
+        let snapshot = self.snapshot(id)?;
+        Some(self.add_snapshot(&snapshot))
+    }
+
+    /// Moves a mesh's triangles into a new prototype and puts an instance of it in their place,
+    /// so copies of the mesh share the triangles. Each instance keeps its own transform, color
+    /// and material, while swapping the triangles, like reloading the mesh's file does, reaches
+    /// them all. Returns the edit for the history, or None when the object is an instance
+    /// already, isn't all triangles of one color and material, or swaps its triangles by itself
+    /// as a mesh sequence or levels of detail.
+    pub fn make_instance(&mut self, id: ObjectId) -> Option<Edit> {
+        let before = self.snapshot(id)?;
+        let Some(Object::Triangle(first)) = before.primitives.first() else { return None };
+        let (color, material) = (first.color, first.material);
+        let uniform = before.primitives.iter()
+            .all(|object| matches!(object, Object::Triangle(triangle) if triangle.color == color && triangle.material == material));
+        let animated = self.mesh_sequences.iter().any(|sequence| sequence.id == id) || self.mesh_lods.iter().any(|lod| lod.id == id);
+        if !uniform || animated {
+            return None;
+        }
+
+        let prototype = self.add_prototype(before.primitives.clone());
+        let mut after = self.take(id)?;
+        after.primitives = vec![Object::Instance(Instance { prototype, transform: Transform::default(), color, material })];
+        self.restore(after.clone());
+        Some(Edit::Group(vec![Edit::Removed(before), Edit::Added(after)]))
+    }
+
+    // Builds a prototype from triangles, returning its index in prototypes
+    fn add_prototype(&mut self, primitives: Vec<Object>) -> usize {
+        self.prototypes.push(self.build_prototype(primitives));
+        self.prototypes.len() - 1
+    }
+
+    fn build_prototype(&self, mut primitives: Vec<Object>) -> Prototype {
+        let mut sum = Vec3(0.0, 0.0, 0.0);
+        for object in &mut primitives {
+            if let Object::Triangle(triangle) = object {
+                triangle.color = Vec3(1.0, 1.0, 1.0);
+                sum += triangle.centroid;
+            }
+        }
+        let centroid = sum / primitives.len().max(1) as f32;
+        let tree = BvhBuilder { objects: &primitives, prototypes: &[], max_leaf_size: self.max_leaf_size }.build();
+        Prototype { nodes: tree.nodes, object_indices: tree.object_indices, primitives, centroid }
+    }
+
+    /// Adds a new object built like the snapshot, returning its handle
+    pub fn add_snapshot(&mut self, snapshot: &RemovedObject) -> ObjectId {
+        let start = self.objects.len();
+        self.objects.extend_from_slice(&snapshot.primitives);
+
+        let copy = self.register(start);
+        let entry = self.entries.get_mut(&copy).unwrap();
+        entry.name.clone_from(&snapshot.entry.name);
+        entry.layers = snapshot.entry.layers;
+        entry.visible = snapshot.entry.visible;
+        self.dirty = true;
+        copy
+    }
+
+    /// Center of a sphere, or the average centroid of an object's triangles. None for objects
+    /// left without primitives, which have no position.
+    pub fn position(&self, id: ObjectId) -> Option<Vec3> {
+        let primitives = self.entries.get(&id)?.primitives.clone();
+        if primitives.is_empty() {
+            return None;
+        }
+        let count = primitives.len() as f32;
+        let mut sum = Vec3(0.0, 0.0, 0.0);
+        for object in &self.objects[primitives] {
+            sum += match object {
+                Object::Sphere(sphere) => sphere.center,
+                Object::Triangle(triangle) => triangle.centroid,
+                Object::Quad(quad) => quad.centroid,
+                Object::Billboard(billboard) => billboard.center,
+                Object::Points(cluster) => cluster.centroid,
+                Object::Curve(curve) => curve.centroid,
+                Object::Instance(instance) => instance.position(&self.prototypes[instance.prototype]),
+            };
+        }
+        Some(sum / count)
+    }
+
+    /// Moves every primitive of the object so its position ends up at `position`
+    pub fn set_position(&mut self, id: ObjectId, position: Vec3) {
+        let Some(current) = self.position(id) else { return };
+        let offset = position - current;
+        self.follow_transform(id, Transform::translation(offset));
+        let primitives = self.entries[&id].primitives.clone();
+        for object in &mut self.objects[primitives] {
+            match object {
+                Object::Sphere(sphere) => sphere.center += offset,
+                Object::Triangle(triangle) => triangle.translate(offset),
+                Object::Quad(quad) => quad.translate(offset),
+                Object::Billboard(billboard) => billboard.center += offset,
+                Object::Points(cluster) => {
+                    for point in &mut cluster.points {
+                        *point += offset;
+                    }
+                    cluster.centroid += offset;
+                },
+                Object::Curve(curve) => {
+                    for point in &mut curve.control_points {
+                        *point += offset;
+                    }
+                    curve.centroid += offset;
+                },
+                Object::Instance(instance) => instance.transform = instance.transform.then(Transform::translation(offset)),
+            }
+        }
+        self.moved = true;
+    }
+
+    /// Turns the object by `angle` radians around `axis` through its position
+    pub fn rotate(&mut self, id: ObjectId, axis: Vec3, angle: f32) {
+        let Some(pivot) = self.position(id) else { return };
+        let turn = |point: Vec3| pivot + rotate_vector_around_axis(point - pivot, axis, angle);
+        self.follow_transform(id, Transform::rotation_about(pivot, axis, angle));
+        let primitives = self.entries[&id].primitives.clone();
+        for object in &mut self.objects[primitives] {
+            match object {
+                Object::Sphere(sphere) => sphere.center = turn(sphere.center),
+                Object::Triangle(triangle) => {
+                    for corner in &mut triangle.corners {
+                        *corner = turn(*corner);
+                    }
+                    if let Some(normals) = &mut triangle.normals {
+                        *normals = normals.map(|normal| rotate_vector_around_axis(normal, axis, angle));
+                    }
+                    triangle.make_centroid();
+                },
+                Object::Quad(quad) => {
+                    quad.corner = turn(quad.corner);
+                    quad.edge_u = rotate_vector_around_axis(quad.edge_u, axis, angle);
+                    quad.edge_v = rotate_vector_around_axis(quad.edge_v, axis, angle);
+                    quad.centroid = turn(quad.centroid);
+                },
+                Object::Billboard(billboard) => billboard.center = turn(billboard.center),
+                Object::Points(cluster) => {
+                    for point in &mut cluster.points {
+                        *point = turn(*point);
+                    }
+                    cluster.make_centroid();
+                },
+                Object::Curve(curve) => {
+                    for point in &mut curve.control_points {
+                        *point = turn(*point);
+                    }
+                    curve.make_centroid();
+                },
+                Object::Instance(instance) => instance.transform = instance.transform.then(Transform::rotation_about(pivot, axis, angle)),
+            }
+        }
+        self.moved = true;
+    }
+
+    /// Grows or shrinks the object by `factor` around its position
+    pub fn scale(&mut self, id: ObjectId, factor: f32) {
+        let Some(pivot) = self.position(id) else { return };
+        let stretch = |point: Vec3| pivot + (point - pivot) * factor;
+        self.follow_transform(id, Transform::scaling_about(pivot, factor));
+        let primitives = self.entries[&id].primitives.clone();
+        for object in &mut self.objects[primitives] {
+            match object {
+                Object::Sphere(sphere) => {
+                    sphere.center = stretch(sphere.center);
+                    sphere.radius *= factor;
+                },
+                Object::Triangle(triangle) => {
+                    for corner in &mut triangle.corners {
+                        *corner = stretch(*corner);
+                    }
+                    triangle.make_centroid();
+                },
+                Object::Quad(quad) => {
+                    quad.corner = stretch(quad.corner);
+                    quad.edge_u = quad.edge_u * factor;
+                    quad.edge_v = quad.edge_v * factor;
+                    quad.centroid = stretch(quad.centroid);
+                },
+                Object::Billboard(billboard) => {
+                    billboard.center = stretch(billboard.center);
+                    billboard.width *= factor;
+                    billboard.height *= factor;
+                },
+                Object::Points(cluster) => {
+                    for point in &mut cluster.points {
+                        *point = stretch(*point);
+                    }
+                    cluster.radius *= factor;
+                    cluster.make_centroid();
+                },
+                Object::Curve(curve) => {
+                    for point in &mut curve.control_points {
+                        *point = stretch(*point);
+                    }
+                    curve.radius *= factor;
+                    curve.make_centroid();
+                },
+                Object::Instance(instance) => instance.transform = instance.transform.then(Transform::scaling_about(pivot, factor)),
+            }
+        }
+        self.moved = true;
+    }
+
+    /// Moves, turns and scales the object so it has gone through `transform` since it was added,
+    /// e.g. to put it back where a replay has it
+    pub fn set_transform(&mut self, id: ObjectId, transform: Transform) {
+        let Some(entry) = self.entries.get_mut(&id) else { return };
+        let change = entry.transform.inverse().then(transform);
+        entry.transform = transform;
+        let primitives = entry.primitives.clone();
+        for object in &mut self.objects[primitives] {
+            match object {
+                Object::Sphere(sphere) => {
+                    sphere.center = change.apply(sphere.center);
+                    sphere.radius *= change.scale;
+                },
+                Object::Triangle(triangle) => {
+                    for corner in &mut triangle.corners {
+                        *corner = change.apply(*corner);
+                    }
+                    if let Some(normals) = &mut triangle.normals {
+                        *normals = normals.map(|normal| change.turn(normal));
+                    }
+                    triangle.make_centroid();
+                },
+                Object::Quad(quad) => {
+                    quad.corner = change.apply(quad.corner);
+                    quad.edge_u = change.turn(quad.edge_u) * change.scale;
+                    quad.edge_v = change.turn(quad.edge_v) * change.scale;
+                    quad.centroid = change.apply(quad.centroid);
+                },
+                Object::Billboard(billboard) => {
+                    billboard.center = change.apply(billboard.center);
+                    billboard.width *= change.scale;
+                    billboard.height *= change.scale;
+                },
+                Object::Points(cluster) => {
+                    for point in &mut cluster.points {
+                        *point = change.apply(*point);
+                    }
+                    cluster.radius *= change.scale;
+                    cluster.make_centroid();
+                },
+                Object::Curve(curve) => {
+                    for point in &mut curve.control_points {
+                        *point = change.apply(*point);
+                    }
+                    curve.radius *= change.scale;
+                    curve.make_centroid();
+                },
+                Object::Instance(instance) => instance.transform = instance.transform.then(change),
+            }
+        }
+        self.moved = true;
+    }
+
+    // Adds an edit's move, turn or scaling to what the object went through
+    fn follow_transform(&mut self, id: ObjectId, change: Transform) {
+        let entry = self.entries.get_mut(&id).unwrap();
+        entry.transform = entry.transform.then(change);
+    }
+
+    /// Color of the object's first primitive
+    pub fn color(&self, id: ObjectId) -> Option<Vec3> {
+        let primitives = self.entries.get(&id)?.primitives.clone();
+        match self.objects.get(primitives.start)? {
+            Object::Sphere(sphere) => Some(sphere.color),
+            Object::Triangle(triangle) => Some(triangle.color),
+            Object::Quad(quad) => Some(quad.color),
+            Object::Billboard(billboard) => Some(billboard.color),
+            Object::Points(cluster) => Some(cluster.color),
+            Object::Curve(curve) => Some(curve.color),
+            Object::Instance(instance) => Some(instance.color),
+        }
+    }
+
+    pub fn set_color(&mut self, id: ObjectId, color: Vec3) {
+        let Some(entry) = self.entries.get(&id) else { return };
+        for object in &mut self.objects[entry.primitives.clone()] {
+            match object {
+                Object::Sphere(sphere) => sphere.color = color,
+                Object::Triangle(triangle) => triangle.color = color,
+                Object::Quad(quad) => quad.color = color,
+                Object::Billboard(billboard) => billboard.color = color,
+                Object::Points(cluster) => cluster.color = color,
+                Object::Curve(curve) => curve.color = color,
+                Object::Instance(instance) => instance.color = color,
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Registers an image to be uploaded with the scene, reusing the entry if it was loaded before
+    pub fn load_texture(&mut self, path: &str) -> Texture {
+        let index = match self.image_paths.iter().position(|p| p == path) {
+            Some(index) => index,
+            None => {
+                self.image_paths.push(path.to_string());
+                self.dirty = true;
+                self.image_paths.len() - 1
+            }
+        };
+        Texture::Image(index)
+    }
+
+    /// Sets the texture of the object's spheres; other primitives have no UVs yet and keep their color
+    pub fn set_texture(&mut self, id: ObjectId, texture: Texture) {
+        let Some(entry) = self.entries.get(&id) else { return };
+        for object in &mut self.objects[entry.primitives.clone()] {
+            if let Object::Sphere(sphere) = object {
+                sphere.texture = texture;
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Registers another sky to switch to, returning its index in skies
+    pub fn add_sky(&mut self, sky: SkySource) -> usize {
+        self.skies.push(sky);
+        self.skies.len() - 1
+    }
+
+    /// Switches to the sky after the active one, wrapping around to the first
+    pub fn next_sky(&mut self) {
+        self.active_sky = (self.active_sky + 1) % self.skies.len();
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        self.dirty = true;
+        MaterialId(self.materials.len() - 1)
+    }
+
+    /// Material of the object's first primitive
+    pub fn material(&self, id: ObjectId) -> Option<MaterialId> {
+        let primitives = self.entries.get(&id)?.primitives.clone();
+        match self.objects.get(primitives.start)? {
+            Object::Sphere(sphere) => Some(MaterialId(sphere.material)),
+            Object::Triangle(triangle) => Some(MaterialId(triangle.material)),
+            Object::Quad(quad) => Some(MaterialId(quad.material)),
+            Object::Billboard(billboard) => Some(MaterialId(billboard.material)),
+            Object::Points(cluster) => Some(MaterialId(cluster.material)),
+            Object::Curve(curve) => Some(MaterialId(curve.material)),
+            Object::Instance(instance) => Some(MaterialId(instance.material)),
+        }
+    }
+
+    /// Object built from the primitive at this index of `objects`, like the object id of a picked pixel
+    pub fn object_at_primitive(&self, index: usize) -> Option<ObjectId> {
+        self.entries.iter()
+            .find(|(_, entry)| entry.primitives.contains(&index))
+            .map(|(&id, _)| id)
+    }
+
+    pub fn set_material(&mut self, id: ObjectId, material: MaterialId) {
+        let Some(entry) = self.entries.get(&id) else { return };
+        for object in &mut self.objects[entry.primitives.clone()] {
+            match object {
+                Object::Sphere(sphere) => sphere.material = material.0,
+                Object::Triangle(triangle) => triangle.material = material.0,
+                Object::Quad(quad) => quad.material = material.0,
+                Object::Billboard(billboard) => billboard.material = material.0,
+                Object::Points(cluster) => cluster.material = material.0,
+                Object::Curve(curve) => curve.material = material.0,
+                Object::Instance(instance) => instance.material = material.0,
+            }
+        }
+        self.dirty = true;
+    }
+
+    pub fn make_scene(&mut self) {
+        let _span = info_span!("build_bvh", objects = self.objects.len()).entered();
+        // Build the BVH for the scene, which also lays out the object indices leaf by leaf
+        self.pack_objects();
+        self.build_bvh();
+        self.dirty = false;
+        self.moved = false;
+    }
+
+    /// Recomputes the node bounds after objects moved, keeping the tree's structure. Much faster
+    /// than a rebuild, but the tree gets slower to trace the further objects move from where it was built.
+    pub fn refit_bvh(&mut self) {
+        let _span = debug_span!("refit_bvh", nodes = self.nodes_used).entered();
+        // Children are always stored after their parent, so walking backwards visits them first
+        for i in (0..self.nodes_used).rev() {
+            let node = self.nodes[i];
+            let (min_corner, max_corner) = if node.object_count > 0 {
+                let start = node.left_child as usize;
+                let references: Vec<BvhReference> = self.object_indices[start..start + node.object_count].iter()
+                    .map(|&object| self.object_reference(object))
+                    .collect();
+                reference_bounds(&references)
+            } else if node.left_child >= 0 {
+                let left = self.nodes[node.left_child as usize];
+                let right = self.nodes[node.left_child as usize + 1];
+                (left.min_corner.min(right.min_corner), left.max_corner.max(right.max_corner))
+            } else {
+                continue; // Root of an empty scene
+            };
+            self.nodes[i].min_corner = min_corner;
+            self.nodes[i].max_corner = max_corner;
+        }
+        self.moved = false;
+    }
+
+    fn build_bvh(&mut self) {
+        let tree = BvhBuilder { objects: &self.objects, prototypes: &self.prototypes, max_leaf_size: self.max_leaf_size }.build();
+        self.nodes = tree.nodes;
+        self.object_indices = tree.object_indices;
+        self.nodes_used = self.nodes.len();
+    }
+
+    fn object_reference(&self, index: usize) -> BvhReference {
+        object_reference(&self.objects, &self.prototypes, index)
+    }
+
+    /// Factor the radiance is scaled by before it is shown
+    pub fn exposure_scale(&self) -> f32 {
+        let adapted = if self.auto_exposure { self.adapted_exposure } else { 1.0 };
+        self.exposure.exp2() * adapted
+    }
+
+    pub fn flatten_scene_data(&self, frame_index: u32) -> Vec<u8> {
+        let [projection, projection_a, projection_b] = self.camera.projection().uniform_data();
+        let mut scene_data_flat: Vec<f32> = vec![
             self.camera.origin.0,
             self.camera.origin.1,
             self.camera.origin.2,
-            0.0, // Padding for alignment
+            projection,
             self.camera.lower_left_corner.0,
             self.camera.lower_left_corner.1,
             self.camera.lower_left_corner.2,
-            0.0, // Padding for alignment
+            projection_a,
             self.camera.horizontal.0,
             self.camera.horizontal.1,
             self.camera.horizontal.2,
-            0.0, // Padding for alignment
+            projection_b,
             self.camera.vertical.0,
             self.camera.vertical.1,
             self.camera.vertical.2,
-            self.max_bounces as f32,
+            self.max_specular_bounces as f32,
             self.object_indices.len() as f32,
+            if self.write_aovs { 1.0 } else { 0.0 },
+            frame_index as f32,
+            if self.stackless_traversal { 1.0 } else { 0.0 },
+            f32::from_bits(self.camera.render_mask), // Read back as a u32 by the kernel
+            self.max_diffuse_bounces as f32,
+            self.sky_yaw.to_radians(),
+            self.sky_intensity,
+            self.portals.len() as f32,
+            if self.spectral { 1.0 } else { 0.0 },
+            self.max_radiance,
+            self.roughness_regularization,
+            if self.outlier_rejection { 1.0 } else { 0.0 },
+            if self.caustics { 1.0 } else { 0.0 },
+            self.photon_radius,
+            if self.bidirectional { 1.0 } else { 0.0 },
+            self.block_size as f32,
+            if self.bvh_heatmap { 1.0 } else { 0.0 },
+            self.exposure_scale(),
+            self.raster_primary_mode(),
         ];
+        // Unused portal slots stay zeroed
+        for i in 0..MAX_PORTALS {
+            let portal = self.portals.get(i).map_or([Vec3(0.0, 0.0, 0.0); 3], |portal| [portal.corner, portal.edge_u, portal.edge_v]);
+            for vector in portal {
+                scene_data_flat.extend_from_slice(&[vector.0, vector.1, vector.2, 0.0]);
+            }
+        }
+        // Without a grid the counts are 0, which the kernel reads as no probes
+        scene_data_flat.extend_from_slice(&self.probe_grid.map_or([0.0; 12], |grid| grid.uniform_data()));
 
         // Convert the f32 array to bytes and return
         bytemuck::cast_slice(&scene_data_flat).to_vec()
     }
 
+    // 0 without rasterized camera rays, 2 when every object they can see is an opaque triangle
+    // and the raster pass finds the nearest one, 1 when others could be in front of it
+    fn raster_primary_mode(&self) -> f32 {
+        if !self.raster_primary {
+            return 0.0;
+        }
+        let opaque_triangle = |object: &Object| match object {
+            Object::Triangle(triangle) => self.materials[triangle.material].transparency == 0.0,
+            _ => false,
+        };
+        let only_triangles = self.objects.iter().zip(self.primitive_layers())
+            .all(|(object, layers)| layers & self.camera.render_mask == 0 || opaque_triangle(object));
+        if only_triangles { 2.0 } else { 1.0 }
+    }
+
+    /// The scene's primitives followed by those of every prototype, which are only reached
+    /// through their instances and sit on every layer
     pub fn flatten_object_data(&self) -> Vec<u8> {
         let mut data = Vec::new();
+        let mut first_point = 0;
+        let offsets = self.prototype_offsets();
 
-        for object in &self.objects {
+        let prototype_primitives = self.prototypes.iter().flat_map(|prototype| &prototype.primitives).map(|object| (object, DEFAULT_LAYERS));
+        for (object, layers) in self.objects.iter().zip(self.primitive_layers()).chain(prototype_primitives) {
             match object {
                 Object::Sphere(sphere) => {
-                    let sphere_attributes: [f32; 17] = [
-                        0.0, sphere.center.0, sphere.center.1, sphere.center.2, sphere.radius, // Center + Radius
+                    let texture = sphere.texture.flatten();
+                    let sphere_attributes: [f32; OBJECT_FLOATS] = [
+                        0.0, sphere.material as f32, // Type + Material
+                        sphere.center.0, sphere.center.1, sphere.center.2, sphere.radius, // Center + Radius
                         sphere.color.0, sphere.color.1, sphere.color.2, // Color + Padding
-                        // Padding or default values for triangle attributes
-                        0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+                        texture[0], texture[1], texture[2], texture[3], texture[4], texture[5], // Texture
+                        // Padding
+                        0.0, 0.0, 0.0,
                     ];
                     data.extend_from_slice(bytemuck::cast_slice(&sphere_attributes));
                 },
                 Object::Triangle(triangle) => {
-                    let triangle_attributes: [f32; 17] = [
-                        // Padding or default values for triangle attributes
-                        1.0, 0.0, 0.0, 0.0, 0.0,
+                    // Corner colors are packed into whole numbers a float holds exactly, -1 when there are none
+                    let vertex_colors = triangle.vertex_colors
+                        .map_or([-1.0; 3], |colors| colors.map(|[r, g, b]| (r as u32 | (g as u32) << 8 | (b as u32) << 16) as f32));
+                    // Corner normals share the point buffer with the point clouds in object order, -1 for flat faces
+                    let normals = match triangle.normals {
+                        Some(_) => {
+                            first_point += 3;
+                            (first_point - 3) as f32
+                        },
+                        None => -1.0,
+                    };
+                    let triangle_attributes: [f32; OBJECT_FLOATS] = [
+                        1.0, triangle.material as f32, // Type + Material
+                        vertex_colors[0], vertex_colors[1], vertex_colors[2], normals, // Corner colors + First normal
                         triangle.color.0, triangle.color.1, triangle.color.2, // Color + Padding
                         triangle.corners[0].0, triangle.corners[0].1, triangle.corners[0].2, // corner_a
                         triangle.corners[1].0, triangle.corners[1].1, triangle.corners[1].2, // corner_b
@@ -310,45 +1356,272 @@ impl Scene {
                     ];
                     data.extend_from_slice(bytemuck::cast_slice(&triangle_attributes));
                 },
+                Object::Quad(quad) => {
+                    let quad_attributes: [f32; OBJECT_FLOATS] = [
+                        2.0, quad.material as f32, // Type + Material
+                        // Padding or default values for quad attributes
+                        0.0, 0.0, 0.0, 0.0,
+                        quad.color.0, quad.color.1, quad.color.2, // Color
+                        quad.corner.0, quad.corner.1, quad.corner.2, // Corner
+                        quad.edge_u.0, quad.edge_u.1, quad.edge_u.2, // Edge u
+                        quad.edge_v.0, quad.edge_v.1, quad.edge_v.2, // Edge v
+                    ];
+                    data.extend_from_slice(bytemuck::cast_slice(&quad_attributes));
+                },
+                Object::Billboard(billboard) => {
+                    // The text atlas is the texture after the scene's images
+                    let uv = label_uv_rect(billboard.label, &self.labels[billboard.label]);
+                    let billboard_attributes: [f32; OBJECT_FLOATS] = [
+                        3.0, billboard.material as f32, // Type + Material
+                        billboard.center.0, billboard.center.1, billboard.center.2, billboard.width * 0.5, // Center + Half width
+                        billboard.color.0, billboard.color.1, billboard.color.2, // Color
+                        billboard.height * 0.5, self.image_paths.len() as f32, // Half height + Atlas texture
+                        uv[0], uv[1], uv[2], uv[3], // UV rectangle of the label
+                        // Padding
+                        0.0, 0.0, 0.0,
+                    ];
+                    data.extend_from_slice(bytemuck::cast_slice(&billboard_attributes));
+                },
+                Object::Points(cluster) => {
+                    // The points follow those of earlier clusters in the point buffer
+                    let cluster_attributes: [f32; OBJECT_FLOATS] = [
+                        4.0, cluster.material as f32, // Type + Material
+                        first_point as f32, cluster.points.len() as f32, cluster.radius, 0.0, // First point + Count + Radius
+                        cluster.color.0, cluster.color.1, cluster.color.2, // Color
+                        // Padding
+                        0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+                    ];
+                    data.extend_from_slice(bytemuck::cast_slice(&cluster_attributes));
+                    first_point += cluster.points.len();
+                },
+                Object::Curve(curve) => {
+                    let [p0, p1, p2, p3] = curve.control_points;
+                    let curve_attributes: [f32; OBJECT_FLOATS] = [
+                        5.0, curve.material as f32, // Type + Material
+                        p0.0, p0.1, p0.2, curve.radius, // Root + Radius
+                        curve.color.0, curve.color.1, curve.color.2, // Color
+                        p1.0, p1.1, p1.2, // Control points
+                        p2.0, p2.1, p2.2,
+                        p3.0, p3.1, p3.2, // Tip
+                    ];
+                    data.extend_from_slice(bytemuck::cast_slice(&curve_attributes));
+                },
+                Object::Instance(instance) => {
+                    let m = instance.inverse_matrix();
+                    let root = offsets[instance.prototype].nodes;
+                    let instance_attributes: [f32; OBJECT_FLOATS] = [
+                        6.0, instance.material as f32, // Type + Material
+                        m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], // Scene to prototype matrix
+                        m[9], m[10], m[11], // Offset
+                        root as f32, // Root of the prototype's BVH
+                        instance.color.0, instance.color.1, instance.color.2, // Color
+                    ];
+                    data.extend_from_slice(bytemuck::cast_slice(&instance_attributes));
+                },
             }
+            data.extend_from_slice(&layers.to_le_bytes());
         }
 
         data
     }
 
-    pub fn flatten_node_data(&self) -> Vec<u8> {
+    /// Points of every point cloud and corner normals of every smoothly shaded triangle in object
+    /// order, 16 bytes each: a point's position followed by its sRGB color packed into a u32, or a
+    /// normal and a padding word. Never empty, as the GPU buffer can't be.
+    pub fn flatten_point_data(&self) -> Vec<u8> {
         let mut data = Vec::new();
-    
-        for i in 0..self.nodes_used {
-            let node = &self.nodes[i];
-            // Flatten each node's data into f32 values
-            let node_attributes: [f32; 8] = [
-                node.min_corner.0, node.min_corner.1, node.min_corner.2,
-                node.left_child as f32, // Cast to f32 for buffer compatibility
-                node.max_corner.0, node.max_corner.1, node.max_corner.2,
-                node.object_count as f32, // Cast to f32 for buffer compatibility
-            ];
-    
-            // Convert the f32 values to bytes and extend the data vector
-            data.extend_from_slice(bytemuck::cast_slice(&node_attributes));
-        }
-    
+        for object in self.objects.iter().chain(self.prototypes.iter().flat_map(|prototype| &prototype.primitives)) {
+            match object {
+                Object::Points(cluster) => for (point, color) in cluster.points.iter().zip(&cluster.colors) {
+                    let packed = u32::from_le_bytes([color[0], color[1], color[2], 255]);
+                    data.extend_from_slice(bytemuck::cast_slice(&[point.0.to_bits(), point.1.to_bits(), point.2.to_bits(), packed]));
+                },
+                Object::Triangle(Triangle { normals: Some(normals), .. }) => for normal in normals {
+                    data.extend_from_slice(bytemuck::cast_slice(&[normal.0.to_bits(), normal.1.to_bits(), normal.2.to_bits(), 0]));
+                },
+                _ => {},
+            }
+        }
+        if data.is_empty() {
+            data.resize(POINT_STRIDE as usize, 0);
+        }
         data
     }
 
-    pub fn flatten_object_index_data(&self) -> Vec<u8> {
+    /// Corners of the box around every object, from the root of the BVH, None before it is built
+    /// or when the scene is empty
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let root = self.nodes.first().filter(|_| self.nodes_used > 0)?;
+        let extent = root.max_corner - root.min_corner;
+        (extent.0.is_finite() && extent.1.is_finite() && extent.2.is_finite()).then_some((root.min_corner, root.max_corner))
+    }
+
+    /// Number of points in all point clouds
+    pub fn point_count(&self) -> usize {
+        self.objects.iter()
+            .map(|object| if let Object::Points(cluster) = object { cluster.points.len() } else { 0 })
+            .sum()
+    }
+
+    /// Entries of the point buffer: the points of all point clouds, then three corner normals for
+    /// every smoothly shaded triangle, prototypes' included
+    pub fn point_buffer_len(&self) -> usize {
+        self.objects.iter()
+            .chain(self.prototypes.iter().flat_map(|prototype| &prototype.primitives))
+            .map(|object| match object {
+                Object::Points(cluster) => cluster.points.len(),
+                Object::Triangle(Triangle { normals: Some(_), .. }) => 3,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    // Layers of the object each primitive belongs to, none for hidden objects
+    fn primitive_layers(&self) -> Vec<u32> {
+        let mut layers = vec![DEFAULT_LAYERS; self.objects.len()];
+        for entry in self.entries.values() {
+            let mask = if entry.visible { entry.layers } else { 0 };
+            layers[entry.primitives.clone()].fill(mask);
+        }
+        // Left behind by removed objects, which the kernel skips on no layer
+        for slot in &self.free_primitives {
+            layers[slot.clone()].fill(0);
+        }
+        layers
+    }
+
+    pub fn flatten_material_data(&self) -> Vec<u8> {
         let mut data = Vec::new();
-    
-        for &index in &self.object_indices {
-            // Cast each index to f32 and extend the data vector
-            data.extend_from_slice(bytemuck::cast_slice(&[index as f32]));
+
+        for material in &self.materials {
+            data.extend_from_slice(bytemuck::cast_slice(&material.flatten()));
         }
-    
+
+        data
+    }
+
+    /// Entries of the object buffer: the scene's primitives, then every prototype's
+    pub fn object_buffer_len(&self) -> usize {
+        self.objects.len() + self.prototypes.iter().map(|prototype| prototype.primitives.len()).sum::<usize>()
+    }
+
+    /// Entries of the node buffer: the scene's BVH, then every prototype's
+    pub fn node_buffer_len(&self) -> usize {
+        self.nodes.len() + self.prototypes.iter().map(|prototype| prototype.nodes.len()).sum::<usize>()
+    }
+
+    /// Entries of the object index buffer: the scene's BVH's, then every prototype's
+    pub fn object_index_buffer_len(&self) -> usize {
+        self.object_indices.len() + self.prototypes.iter().map(|prototype| prototype.object_indices.len()).sum::<usize>()
+    }
+
+    // Where each prototype's primitives, nodes and object indices start, after the scene's own
+    fn prototype_offsets(&self) -> Vec<PrototypeOffsets> {
+        let mut next = PrototypeOffsets { primitives: self.objects.len(), nodes: self.nodes_used, object_indices: self.object_indices.len() };
+        self.prototypes.iter().map(|prototype| {
+            let offsets = next;
+            next.primitives += prototype.primitives.len();
+            next.nodes += prototype.nodes.len();
+            next.object_indices += prototype.object_indices.len();
+            offsets
+        }).collect()
+    }
+
+    /// The scene's BVH followed by every prototype's, each prototype's root only reached from its
+    /// instances
+    pub fn flatten_node_data(&self) -> Vec<u8> {
+        let mut data = flatten_nodes(&self.nodes[..self.nodes_used]);
+        data.extend(self.flatten_prototype_nodes());
         data
     }
 
-    pub fn update(&mut self) {
+    pub fn flatten_object_index_data(&self) -> Vec<u8> {
+        let mut data = flatten_object_indices(&self.object_indices);
+        data.extend(self.flatten_prototype_object_indices());
+        data
+    }
+
+    fn flatten_prototype_nodes(&self) -> Vec<u8> {
+        self.prototypes.iter().zip(self.prototype_offsets())
+            .flat_map(|(prototype, offsets)| flatten_nodes_at(&prototype.nodes, offsets.nodes, offsets.object_indices))
+            .collect()
+    }
+
+    fn flatten_prototype_object_indices(&self) -> Vec<u8> {
+        let indices: Vec<usize> = self.prototypes.iter().zip(self.prototype_offsets())
+            .flat_map(|(prototype, offsets)| prototype.object_indices.iter().map(move |index| index + offsets.primitives))
+            .collect();
+        flatten_object_indices(&indices)
+    }
+
+    /// Node and object index data holding only the objects inside the camera's view.
+    /// Falls back to the whole BVH when nothing is in view.
+    pub fn flatten_culled_bvh_data(&self) -> (Vec<u8>, Vec<u8>) {
+        let visible: Vec<bool> = self.objects.par_iter()
+            .map(|object| {
+                let (center, radius) = bounding_sphere(object, &self.prototypes);
+                self.camera.sees_sphere(center, radius)
+            })
+            .collect();
+
+        match self.cull_subtree(0, &visible) {
+            Some(tree) => {
+                // Padded to the whole tree's size, so the prototypes stay where instances look for them
+                let mut nodes = flatten_nodes(&tree.nodes);
+                nodes.resize(self.nodes_used * NODE_FLOATS * 4, 0);
+                nodes.extend(self.flatten_prototype_nodes());
+                let mut object_indices = flatten_object_indices(&tree.object_indices);
+                object_indices.resize(self.object_indices.len() * 4, 0);
+                object_indices.extend(self.flatten_prototype_object_indices());
+                (nodes, object_indices)
+            },
+            None => (self.flatten_node_data(), self.flatten_object_index_data()),
+        }
+    }
+
+    // Copies the subtree below a node keeping only visible objects, with bounds refit to them.
+    // Nodes left with one non-empty child are replaced by that child, empty subtrees are dropped.
+    fn cull_subtree(&self, node_index: usize, visible: &[bool]) -> Option<Subtree> {
+        let node = self.nodes[node_index];
+
+        if node.object_count > 0 {
+            let start = node.left_child as usize;
+            let object_indices: Vec<usize> = self.object_indices[start..start + node.object_count].iter()
+                .copied()
+                .filter(|&i| visible[i])
+                .collect();
+            if object_indices.is_empty() {
+                return None;
+            }
+
+            let references: Vec<BvhReference> = object_indices.iter().map(|&i| self.object_reference(i)).collect();
+            let (min_corner, max_corner) = reference_bounds(&references);
+            let leaf = Node { min_corner, max_corner, left_child: 0, object_count: object_indices.len(), ..node };
+            return Some(Subtree { nodes: vec![leaf], object_indices });
+        }
+
+        let left_child = node.left_child as usize;
+        match (self.cull_subtree(left_child, visible), self.cull_subtree(left_child + 1, visible)) {
+            (Some(left), Some(right)) => {
+                let joined = Node {
+                    min_corner: left.nodes[0].min_corner.min(right.nodes[0].min_corner),
+                    max_corner: left.nodes[0].max_corner.max(right.nodes[0].max_corner),
+                    ..node
+                };
+                Some(Subtree::join(joined, left, right))
+            },
+            (Some(child), None) | (None, Some(child)) => Some(child),
+            (None, None) => None,
+        }
+    }
+
+    /// Moves the camera and adjusts the sky for the held keys, or flies the camera along its rig,
+    /// returning whether anything changed.
+    /// Also steps mesh sequences to their current frame and picks the levels of detail of meshes
+    /// that have them, which marks the scene moved or dirty.
+    pub fn update(&mut self) -> bool {
         let movement_speed = 0.01; // Adjust speed as necessary
+        let mut moved = false;
         for key in self.keys_pressed.iter() {
             match key {
                 KeyCode::KeyW => self.camera.move_forwards(movement_speed),
@@ -363,8 +1636,443 @@ impl Scene {
                 KeyCode::ArrowRight => self.camera.rotate_yaw(-1.0),
                 KeyCode::ArrowUp => self.camera.rotate_pitch(1.0),
                 KeyCode::ArrowDown => self.camera.rotate_pitch(-1.0),
-                _ => {},
+                KeyCode::BracketLeft => self.sky_yaw = (self.sky_yaw - 1.0).rem_euclid(360.0),
+                KeyCode::BracketRight => self.sky_yaw = (self.sky_yaw + 1.0).rem_euclid(360.0),
+                KeyCode::Minus => self.sky_intensity /= 1.02,
+                KeyCode::Equal => self.sky_intensity *= 1.02,
+                _ => continue,
+            }
+            moved = true;
+        }
+        if let Some(rig) = &mut self.camera_rig {
+            let (lookfrom, lookat) = rig.current_pose();
+            let before = self.camera;
+            self.camera.look_at(lookfrom, lookat);
+            // Holding still at the end of the path lets the image converge
+            moved |= self.camera != before;
+        }
+        self.advance_mesh_sequences();
+        self.select_mesh_lods();
+        moved
+    }
+}
+
+// Builds a BVH over a list of primitives, the scene's own or a prototype's
+struct BvhBuilder<'a> {
+    objects: &'a [Object],
+    prototypes: &'a [Prototype], // Bound the instances among the objects
+    max_leaf_size: usize,
+}
+
+impl BvhBuilder<'_> {
+    fn build(&self) -> Subtree {
+        let references: Vec<BvhReference> = (0..self.objects.len())
+            .into_par_iter()
+            .map(|i| object_reference(self.objects, self.prototypes, i))
+            .collect();
+
+        let (min_corner, max_corner) = reference_bounds(&references);
+        let root_area = surface_area(min_corner, max_corner);
+        let duplicate_budget = (references.len() as f32 * SPATIAL_SPLIT_BUDGET) as usize;
+
+        // Nodes are collected as the tree grows, so the buffer only holds the nodes actually used
+        self.build_subtree(references, 1, root_area, duplicate_budget)
+    }
+
+    // Builds the tree below a node with the node itself first. Each subtree owns its nodes and
+    // indices, so large ones are built on separate threads and stitched together afterwards.
+    fn build_subtree(&self, references: Vec<BvhReference>, depth: usize, root_area: f32, duplicate_budget: usize) -> Subtree {
+        let (min_corner, max_corner) = reference_bounds(&references);
+        let mut node = Node { min_corner, max_corner, ..Default::default() };
+
+        let split = if depth < MAX_BVH_DEPTH {
+            self.split(&node, &references, root_area, duplicate_budget)
+        } else {
+            None
+        };
+        let Some((Split { left, right, axis, .. }, duplicate_budget)) = split else {
+            // Leaf: its objects are the subtree's whole index list. Only the root of an empty
+            // scene has none, and keeps no children either so it isn't read as an inner node.
+            node.left_child = if references.is_empty() { -1 } else { 0 };
+            node.object_count = references.len();
+            return Subtree {
+                nodes: vec![node],
+                object_indices: references.iter().map(|reference| reference.object).collect(),
+            };
+        };
+
+        // Children share the remaining duplicates by size, which keeps the result independent of thread timing
+        let reference_count = left.len() + right.len();
+        let left_budget = duplicate_budget * left.len() / reference_count;
+        let right_budget = duplicate_budget - left_budget;
+
+        let (left, right) = if reference_count >= PARALLEL_BUILD_THRESHOLD {
+            rayon::join(
+                || self.build_subtree(left, depth + 1, root_area, left_budget),
+                || self.build_subtree(right, depth + 1, root_area, right_budget),
+            )
+        } else {
+            (
+                self.build_subtree(left, depth + 1, root_area, left_budget),
+                self.build_subtree(right, depth + 1, root_area, right_budget),
+            )
+        };
+
+        node.split_axis = axis;
+        Subtree::join(node, left, right)
+    }
+
+    // Picks the cheaper of an object split and a spatial split by the SAH, or None when the node should stay a leaf
+    // along with the duplicate budget left for the children
+    fn split(&self, node: &Node, references: &[BvhReference], root_area: f32, duplicate_budget: usize) -> Option<(Split, usize)> {
+        let object_count = references.len();
+        if object_count <= 1 {
+            return None; // Base case: a single object can't be split
+        }
+
+        let area = node.surface_area();
+        let axis = longest_axis(node);
+
+        let mut split = self.object_split(area, references);
+
+        // Clipping only pays off when the object split leaves its children overlapping,
+        // which is what long thin triangles do
+        let mut duplicates = 0;
+        if duplicate_budget > 0 && overlap_area(&split.left, &split.right) > SPATIAL_SPLIT_ALPHA * root_area {
+            if let Some(spatial) = self.spatial_split(node, references, axis) {
+                let extra = spatial.left.len() + spatial.right.len() - object_count;
+                if extra <= duplicate_budget && spatial.cost < split.cost {
+                    duplicates = extra;
+                    split = spatial;
+                }
+            }
+        }
+
+        // Small nodes stay leaves unless the SAH expects the split to be cheaper to trace
+        if object_count <= self.max_leaf_size && split.cost >= object_count as f32 {
+            return None;
+        }
+
+        Some((split, duplicate_budget - duplicates))
+    }
+
+    // Partitions references around the middle of their centroids' bounds. Using the centroids
+    // rather than the node keeps one huge object from pushing every other object to the same side.
+    fn object_split(&self, area: f32, references: &[BvhReference]) -> Split {
+        let mut min_centroid = Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max_centroid = Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for reference in references {
+            min_centroid = min_centroid.min(reference.centroid());
+            max_centroid = max_centroid.max(reference.centroid());
+        }
+
+        let extent = max_centroid - min_centroid;
+        let axis = if extent.0 > extent.1 && extent.0 > extent.2 {
+            0
+        } else if extent.1 > extent.2 {
+            1
+        } else {
+            2
+        };
+        let split_pos = (min_centroid.axis(axis) + max_centroid.axis(axis)) / 2.0;
+
+        let (mut left, mut right): (Vec<BvhReference>, Vec<BvhReference>) = references.iter()
+            .partition(|reference| reference.centroid().axis(axis) < split_pos);
+
+        // Centroids that all coincide, or lie so close that the middle rounds onto one end, leave a
+        // side empty. Halving them in centroid order still lets a crowded node shrink to leaf size.
+        if left.is_empty() || right.is_empty() {
+            left = references.to_vec();
+            left.sort_by(|a, b| a.centroid().axis(axis).total_cmp(&b.centroid().axis(axis)));
+            right = left.split_off(left.len() / 2);
+        }
+        let cost = split_cost(area, &left, &right);
+        Split { left, right, axis, cost }
+    }
+
+    // Cuts the node with the cheapest of a few evenly spaced planes,
+    // clipping references that straddle it into both children
+    fn spatial_split(&self, node: &Node, references: &[BvhReference], axis: usize) -> Option<Split> {
+        let min = node.min_corner.axis(axis);
+        let extent = node.max_corner.axis(axis) - min;
+        if extent <= 0.0 {
+            return None;
+        }
+
+        let area = node.surface_area();
+        let mut best: Option<Split> = None;
+        for bin in 1..SPATIAL_SPLIT_BINS {
+            let plane = min + extent * bin as f32 / SPATIAL_SPLIT_BINS as f32;
+            let (left, right) = self.split_references(references, axis, plane);
+
+            // Splits that keep every reference on one side would never terminate
+            if left.is_empty() || right.is_empty() || left.len() == references.len() || right.len() == references.len() {
+                continue;
+            }
+
+            let cost = split_cost(area, &left, &right);
+            if best.as_ref().is_none_or(|best| cost < best.cost) {
+                best = Some(Split { left, right, axis, cost });
+            }
+        }
+
+        best
+    }
+
+    fn split_references(&self, references: &[BvhReference], axis: usize, plane: f32) -> (Vec<BvhReference>, Vec<BvhReference>) {
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+
+        for reference in references {
+            if reference.max_corner.axis(axis) <= plane {
+                left.push(*reference);
+            } else if reference.min_corner.axis(axis) >= plane {
+                right.push(*reference);
+            } else {
+                let (left_part, right_part) = self.clip_reference(reference, axis, plane);
+                left.push(left_part);
+                right.push(right_part);
+            }
+        }
+
+        (left, right)
+    }
+
+    // Splits a reference's bounds at the plane. Triangles and quads are clipped exactly,
+    // so each half is only as large as the part of the polygon on that side.
+    fn clip_reference(&self, reference: &BvhReference, axis: usize, plane: f32) -> (BvhReference, BvhReference) {
+        let mut left = BvhReference {
+            max_corner: reference.max_corner.with_axis(axis, plane),
+            ..*reference
+        };
+        let mut right = BvhReference {
+            min_corner: reference.min_corner.with_axis(axis, plane),
+            ..*reference
+        };
+
+        let polygon = match &self.objects[reference.object] {
+            Object::Sphere(_) | Object::Billboard(_) | Object::Points(_) | Object::Curve(_) | Object::Instance(_) => None,
+            Object::Triangle(triangle) => Some(triangle.corners.to_vec()),
+            Object::Quad(quad) => Some(quad.corners().to_vec()),
+        };
+
+        if let Some(corners) = polygon {
+            let mut left_bounds = (Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY), Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY));
+            let mut right_bounds = left_bounds;
+
+            for i in 0..corners.len() {
+                let a = corners[i];
+                let b = corners[(i + 1) % corners.len()];
+                let (a_pos, b_pos) = (a.axis(axis), b.axis(axis));
+
+                if a_pos <= plane {
+                    left_bounds = (left_bounds.0.min(a), left_bounds.1.max(a));
+                }
+                if a_pos >= plane {
+                    right_bounds = (right_bounds.0.min(a), right_bounds.1.max(a));
+                }
+
+                // The edge crosses the plane, both halves end at the crossing point
+                if (a_pos < plane && b_pos > plane) || (a_pos > plane && b_pos < plane) {
+                    let crossing = a + (b - a) * ((plane - a_pos) / (b_pos - a_pos));
+                    left_bounds = (left_bounds.0.min(crossing), left_bounds.1.max(crossing));
+                    right_bounds = (right_bounds.0.min(crossing), right_bounds.1.max(crossing));
+                }
+            }
+
+            // Stay inside the reference, which earlier splits may already have clipped
+            left.min_corner = left.min_corner.max(left_bounds.0);
+            left.max_corner = left.max_corner.min(left_bounds.1);
+            right.min_corner = right.min_corner.max(right_bounds.0);
+            right.max_corner = right.max_corner.min(right_bounds.1);
+        }
+
+        (left, right)
+    }
+}
+
+fn object_reference(objects: &[Object], prototypes: &[Prototype], index: usize) -> BvhReference {
+    let (min_corner, max_corner) = match &objects[index] {
+        Object::Sphere(sphere) => (sphere.center - sphere.radius, sphere.center + sphere.radius),
+        Object::Triangle(triangle) => {
+            let corners = &triangle.corners;
+            (corners[0].min(corners[1]).min(corners[2]), corners[0].max(corners[1]).max(corners[2]))
+        },
+        Object::Quad(quad) => {
+            let corners = quad.corners();
+            (
+                corners[0].min(corners[1]).min(corners[2]).min(corners[3]),
+                corners[0].max(corners[1]).max(corners[2]).max(corners[3]),
+            )
+        },
+        // Bounds every orientation, as the kernel turns it towards the camera
+        Object::Billboard(billboard) => (billboard.center - billboard.radius(), billboard.center + billboard.radius()),
+        Object::Points(cluster) => cluster.bounds(),
+        Object::Curve(curve) => curve.bounds(),
+        Object::Instance(instance) => instance.bounds(&prototypes[instance.prototype]),
+    };
+    BvhReference { object: index, min_corner, max_corner }
+}
+
+// Surface area heuristic cost of tracing through a split node, in units of object intersections
+fn split_cost(area: f32, left: &[BvhReference], right: &[BvhReference]) -> f32 {
+    if area <= 0.0 {
+        return 0.0; // Degenerate bounds, always split
+    }
+
+    let (left_min, left_max) = reference_bounds(left);
+    let (right_min, right_max) = reference_bounds(right);
+    TRAVERSAL_COST
+        + (surface_area(left_min, left_max) * left.len() as f32 + surface_area(right_min, right_max) * right.len() as f32) / area
+}
+
+fn overlap_area(left: &[BvhReference], right: &[BvhReference]) -> f32 {
+    let (left_min, left_max) = reference_bounds(left);
+    let (right_min, right_max) = reference_bounds(right);
+    surface_area(left_min.max(right_min), left_max.min(right_max))
+}
+
+fn longest_axis(node: &Node) -> usize {
+    let extent = node.max_corner - node.min_corner;
+
+    if extent.0 > extent.1 && extent.0 > extent.2 {
+        0
+    } else if extent.1 > extent.2 {
+        1
+    } else {
+        2
+    }
+}
+
+// Marks the indices inside any of the slots
+fn slot_mask(slots: &[Range<usize>], len: usize) -> Vec<bool> {
+    let mut mask = vec![false; len];
+    for slot in slots {
+        mask[slot.clone()].fill(true);
+    }
+    mask
+}
+
+// Rewrites the object indices leaf by leaf, each index becoming the ones the mapping pushes. A leaf
+// left empty would read as an inner node, so it keeps those of its old indices still below len,
+// slots that now hold an object listed in another leaf too. None when there are none of those
+// either, so the tree has to be rebuilt.
+fn remap_leaves(nodes: &mut [Node], object_indices: &[usize], len: usize, map: impl Fn(usize, &mut Vec<usize>)) -> Option<Vec<usize>> {
+    let mut leaves: Vec<usize> = (0..nodes.len()).filter(|&i| nodes[i].object_count > 0).collect();
+    leaves.sort_by_key(|&i| nodes[i].left_child);
+    let mut remapped = Vec::with_capacity(object_indices.len());
+    for leaf in leaves {
+        let start = remapped.len();
+        let contents = nodes[leaf].left_child as usize..nodes[leaf].left_child as usize + nodes[leaf].object_count;
+        for &index in &object_indices[contents.clone()] {
+            map(index, &mut remapped);
+        }
+        if remapped.len() == start {
+            remapped.extend(object_indices[contents].iter().filter(|&&index| index < len));
+            if remapped.len() == start {
+                return None;
             }
         }
+        nodes[leaf].left_child = start as i32;
+        nodes[leaf].object_count = remapped.len() - start;
+    }
+    Some(remapped)
+}
+
+fn flatten_nodes(nodes: &[Node]) -> Vec<u8> {
+    flatten_nodes_at(nodes, 0, 0)
+}
+
+// Nodes moved to start at node_offset, with the object indices of their leaves moved to start at
+// index_offset
+fn flatten_nodes_at(nodes: &[Node], node_offset: usize, index_offset: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for (node, skip_link) in nodes.iter().zip(skip_links(nodes)) {
+        let left_child = match node.left_child {
+            -1 => -1, // Root of an empty tree
+            left_child if node.object_count > 0 => left_child + index_offset as i32,
+            left_child => left_child + node_offset as i32,
+        };
+        let skip_link = if skip_link < 0 { skip_link } else { skip_link + node_offset as i32 };
+        // Flatten each node's data into f32 values
+        let node_attributes: [f32; NODE_FLOATS] = [
+            node.min_corner.0, node.min_corner.1, node.min_corner.2,
+            left_child as f32, // Cast to f32 for buffer compatibility
+            node.max_corner.0, node.max_corner.1, node.max_corner.2,
+            node.object_count as f32, // Cast to f32 for buffer compatibility
+            skip_link as f32,
+            node.split_axis as f32,
+            0.0, 0.0, // Padding for alignment
+        ];
+
+        // Convert the f32 values to bytes and extend the data vector
+        data.extend_from_slice(bytemuck::cast_slice(&node_attributes));
+    }
+
+    data
+}
+
+// Node the stackless traversal moves to after a node is missed or its objects were tested:
+// the right sibling for left children, otherwise the parent's skip link, and -1 past the end
+fn skip_links(nodes: &[Node]) -> Vec<i32> {
+    let mut skip_links = vec![-1; nodes.len()];
+    let mut pending: Vec<usize> = (0..nodes.len().min(1)).collect(); // Start at the root, if there is one
+
+    while let Some(i) = pending.pop() {
+        let node = &nodes[i];
+        if node.object_count > 0 {
+            continue; // Leaves have no children to link
+        }
+        let left_child = node.left_child as usize;
+        skip_links[left_child] = left_child as i32 + 1;
+        skip_links[left_child + 1] = skip_links[i];
+        pending.extend([left_child, left_child + 1]);
+    }
+
+    skip_links
+}
+
+fn flatten_object_indices(object_indices: &[usize]) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for &index in object_indices {
+        // Cast each index to f32 and extend the data vector
+        data.extend_from_slice(bytemuck::cast_slice(&[index as f32]));
     }
-}
\ No newline at end of file
+
+    data
+}
+
+// Sphere enclosing an object, used to test it against the camera's view
+fn bounding_sphere(object: &Object, prototypes: &[Prototype]) -> (Vec3, f32) {
+    match object {
+        Object::Sphere(sphere) => (sphere.center, sphere.radius),
+        Object::Triangle(triangle) => {
+            let radius = triangle.corners.iter()
+                .map(|&corner| (corner - triangle.centroid).magnitude())
+                .fold(0.0, f32::max);
+            (triangle.centroid, radius)
+        },
+        Object::Quad(quad) => {
+            let radius = quad.corners().iter()
+                .map(|&corner| (corner - quad.centroid).magnitude())
+                .fold(0.0, f32::max);
+            (quad.centroid, radius)
+        },
+        Object::Billboard(billboard) => (billboard.center, billboard.radius()),
+        Object::Points(cluster) => {
+            let radius = cluster.points.iter()
+                .map(|&point| (point - cluster.centroid).magnitude())
+                .fold(0.0, f32::max);
+            (cluster.centroid, radius + cluster.radius)
+        },
+        Object::Curve(curve) => {
+            let radius = curve.control_points.iter()
+                .map(|&point| (point - curve.centroid).magnitude())
+                .fold(0.0, f32::max);
+            (curve.centroid, radius + curve.radius)
+        },
+        Object::Instance(instance) => instance.bounding_sphere(&prototypes[instance.prototype]),
+    }
+}
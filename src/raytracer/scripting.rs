@@ -0,0 +1,304 @@
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+
+use super::{fur_ball, CameraRig, CameraShake, DisplacementMap, Material, ObjMesh, ObjectId, Projection, Scene, TextureFiltering, Vec3};
+
+// Shared view of the scene handed to scripts while they run
+#[derive(Clone)]
+struct ScriptScene(Rc<RefCell<Scene>>);
+
+/// A Rhai script that builds the scene when loaded and can animate it every frame.
+///
+/// The scene is reached through `scene()`, both at the top level, which runs once,
+/// and inside an optional `fn on_update(dt)` that is called each frame:
+///
+/// ```rhai
+/// let ball = scene().add_sphere(vec3(0.0, 0.0, -1.0), vec3(1.0, 0.0, 0.0), 0.5);
+/// scene().set_name(ball, "ball");
+///
+/// fn on_update(dt) {
+///     let ball = scene().find("ball");
+///     scene().set_position(ball, scene().position(ball) + vec3(0.0, dt * 0.1, 0.0));
+/// }
+/// ```
+pub struct SceneScript {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    shared: Rc<RefCell<Scene>>,
+    has_update: bool,
+}
+
+impl SceneScript {
+    pub fn load(path: &str) -> Result<Self, Box<EvalAltResult>> {
+        // Stand-in that gets swapped with the real scene whenever the script runs
+        let shared = Rc::new(RefCell::new(Scene::new(1, 1.0, 1.0)));
+        let engine = make_engine(ScriptScene(shared.clone()));
+        let ast = engine.compile_file(PathBuf::from(path))?;
+        let has_update = ast.iter_functions().any(|f| f.name == "on_update" && f.params.len() == 1);
+
+        Ok(Self { engine, ast, scope: Scope::new(), shared, has_update })
+    }
+
+    /// Runs the script's top level, which is where scenes are built
+    pub fn run_setup(&mut self, scene: &mut Scene) -> Result<(), Box<EvalAltResult>> {
+        self.with_scene(scene, |engine, ast, scope| engine.run_ast_with_scope(scope, ast))
+    }
+
+    /// Calls the script's `on_update(dt)` if it defines one
+    pub fn on_update(&mut self, scene: &mut Scene, dt: f32) -> Result<(), Box<EvalAltResult>> {
+        if !self.has_update {
+            return Ok(());
+        }
+        self.with_scene(scene, |engine, ast, scope| {
+            // Only the function runs, the top level already built the scene in run_setup
+            let options = CallFnOptions::new().eval_ast(false).rewind_scope(false);
+            engine.call_fn_with_options::<Dynamic>(options, scope, ast, "on_update", (dt,)).map(|_| ())
+        })
+    }
+
+    // Lends the scene to the script for the duration of `f`, taking it back afterwards
+    fn with_scene<T>(&mut self, scene: &mut Scene, f: impl FnOnce(&Engine, &AST, &mut Scope<'static>) -> T) -> T {
+        std::mem::swap(&mut *self.shared.borrow_mut(), scene);
+        let result = f(&self.engine, &self.ast, &mut self.scope);
+        std::mem::swap(&mut *self.shared.borrow_mut(), scene);
+        result
+    }
+}
+
+fn make_engine(shared: ScriptScene) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.register_type_with_name::<Vec3>("Vec3")
+        .register_fn("vec3", |x: f32, y: f32, z: f32| Vec3(x, y, z))
+        .register_get("x", |v: &mut Vec3| v.0)
+        .register_get("y", |v: &mut Vec3| v.1)
+        .register_get("z", |v: &mut Vec3| v.2)
+        .register_fn("+", |a: Vec3, b: Vec3| a + b)
+        .register_fn("-", |a: Vec3, b: Vec3| a - b)
+        .register_fn("*", |a: Vec3, s: f32| a * s);
+
+    engine.register_type_with_name::<ObjectId>("ObjectId");
+
+    engine.register_type_with_name::<ScriptScene>("Scene")
+        .register_fn("scene", move || shared.clone())
+        .register_fn("add_sphere", |s: &mut ScriptScene, center: Vec3, color: Vec3, radius: f32| {
+            s.0.borrow_mut().add_sphere(center, color, radius)
+        })
+        .register_fn("add_triangle", |s: &mut ScriptScene, a: Vec3, b: Vec3, c: Vec3, color: Vec3| {
+            s.0.borrow_mut().add_triangle([a, b, c], color)
+        })
+        .register_fn("add_square", |s: &mut ScriptScene, center: Vec3, height: f32, width: f32, color: Vec3, orientation: f32| {
+            s.0.borrow_mut().add_square(center, height, width, color, orientation)
+        })
+        .register_fn("add_object_mesh", |s: &mut ScriptScene, path: &str| {
+            s.0.borrow_mut().add_object_mesh(path)
+        })
+        .register_fn("add_object_mesh", |s: &mut ScriptScene, path: &str, position: Vec3, rotation: Vec3, scale: f32| {
+            // Rotation is given in degrees around x, y and z like `rotate`
+            let mesh = ObjMesh::load(Vec3(1.0, 1.0, 1.0), path).expect("Failed to load mesh")
+                .scaled(scale)
+                .rotated(Vec3(rotation.0.to_radians(), rotation.1.to_radians(), rotation.2.to_radians()))
+                .at(position);
+            s.0.borrow_mut().add_obj_mesh(mesh)
+        })
+        .register_fn("add_displaced_mesh", |s: &mut ScriptScene, path: &str, map: &str, height: f32, subdivisions: rhai::INT| {
+            let mesh = ObjMesh::load(Vec3(1.0, 1.0, 1.0), path).expect("Failed to load mesh")
+                .displaced(&DisplacementMap::new(map), height, subdivisions.max(0) as u32);
+            s.0.borrow_mut().add_obj_mesh(mesh)
+        })
+        .register_fn("add_decimated_mesh", |s: &mut ScriptScene, path: &str, triangles: rhai::INT| {
+            let mesh = ObjMesh::load(Vec3(1.0, 1.0, 1.0), path).expect("Failed to load mesh")
+                .decimated(triangles.max(0) as usize);
+            s.0.borrow_mut().add_obj_mesh(mesh)
+        })
+        .register_fn("add_mesh_lods", |s: &mut ScriptScene, path: &str, levels: rhai::INT| {
+            let mesh = ObjMesh::load(Vec3(1.0, 1.0, 1.0), path).expect("Failed to load mesh");
+            s.0.borrow_mut().add_obj_mesh_lods(mesh, levels.max(0) as usize)
+        })
+        .register_fn("add_point_cloud", |s: &mut ScriptScene, path: &str, radius: f32| {
+            s.0.borrow_mut().add_point_cloud(path, radius)
+        })
+        .register_fn("add_fur_ball", |s: &mut ScriptScene, center: Vec3, radius: f32, strands: rhai::INT, length: f32, color: Vec3| {
+            // Strands get a hair material of their own, a hundredth of their length thick
+            let mut scene = s.0.borrow_mut();
+            let material = scene.add_material(Material { hair: true, roughness: 0.3, ..Default::default() });
+            scene.add_curves(fur_ball(center, radius, strands.max(0) as usize, length, length * 0.01, color), material)
+        })
+        .register_fn("add_mesh_sequence", |s: &mut ScriptScene, folder: &str, fps: f32| {
+            s.0.borrow_mut().add_mesh_sequence(folder, fps)
+        })
+        .register_fn("add_portal", |s: &mut ScriptScene, corner: Vec3, edge_u: Vec3, edge_v: Vec3| -> Result<(), Box<EvalAltResult>> {
+            Ok(s.0.borrow_mut().add_portal(corner, edge_u, edge_v)?)
+        })
+        .register_fn("add_label", |s: &mut ScriptScene, position: Vec3, text: &str, height: f32, color: Vec3| {
+            s.0.borrow_mut().add_label(position, text, height, color)
+        })
+        .register_fn("find", |s: &mut ScriptScene, name: &str| {
+            s.0.borrow().find(name).map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_fn("set_name", |s: &mut ScriptScene, id: ObjectId, name: &str| {
+            s.0.borrow_mut().set_name(id, name)
+        })
+        .register_fn("position", |s: &mut ScriptScene, id: ObjectId| {
+            s.0.borrow().position(id).map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_fn("set_position", |s: &mut ScriptScene, id: ObjectId, position: Vec3| {
+            s.0.borrow_mut().set_position(id, position)
+        })
+        .register_fn("rotate", |s: &mut ScriptScene, id: ObjectId, axis: Vec3, degrees: f32| {
+            s.0.borrow_mut().rotate(id, axis, degrees.to_radians())
+        })
+        .register_fn("scale", |s: &mut ScriptScene, id: ObjectId, factor: f32| {
+            s.0.borrow_mut().scale(id, factor)
+        })
+        .register_fn("color", |s: &mut ScriptScene, id: ObjectId| {
+            s.0.borrow().color(id).map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_fn("set_color", |s: &mut ScriptScene, id: ObjectId, color: Vec3| {
+            s.0.borrow_mut().set_color(id, color)
+        })
+        .register_fn("set_layers", |s: &mut ScriptScene, id: ObjectId, layers: rhai::INT| {
+            s.0.borrow_mut().set_layers(id, layers as u32)
+        })
+        .register_fn("set_visible", |s: &mut ScriptScene, id: ObjectId, visible: bool| {
+            s.0.borrow_mut().set_visible(id, visible)
+        })
+        .register_fn("remove", |s: &mut ScriptScene, id: ObjectId| {
+            s.0.borrow_mut().remove(id)
+        })
+        .register_fn("duplicate", |s: &mut ScriptScene, id: ObjectId| {
+            s.0.borrow_mut().duplicate(id).map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_fn("set_render_mask", |s: &mut ScriptScene, mask: rhai::INT| {
+            let mut scene = s.0.borrow_mut();
+            scene.camera.render_mask = mask as u32;
+            scene.dirty = true;
+        })
+        .register_fn("set_sky_yaw", |s: &mut ScriptScene, degrees: f32| {
+            let mut scene = s.0.borrow_mut();
+            scene.sky_yaw = degrees;
+            scene.dirty = true;
+        })
+        .register_fn("set_sky_intensity", |s: &mut ScriptScene, intensity: f32| {
+            let mut scene = s.0.borrow_mut();
+            scene.sky_intensity = intensity.max(0.0);
+            scene.dirty = true;
+        })
+        .register_fn("set_spectral", |s: &mut ScriptScene, spectral: bool| {
+            let mut scene = s.0.borrow_mut();
+            scene.spectral = spectral;
+            scene.dirty = true;
+        })
+        .register_fn("set_max_radiance", |s: &mut ScriptScene, radiance: f32| {
+            let mut scene = s.0.borrow_mut();
+            scene.max_radiance = radiance.max(0.0);
+            scene.dirty = true;
+        })
+        .register_fn("set_roughness_regularization", |s: &mut ScriptScene, roughness: f32| {
+            let mut scene = s.0.borrow_mut();
+            scene.roughness_regularization = roughness.clamp(0.0, 1.0);
+            scene.dirty = true;
+        })
+        .register_fn("set_outlier_rejection", |s: &mut ScriptScene, enabled: bool| {
+            let mut scene = s.0.borrow_mut();
+            scene.outlier_rejection = enabled;
+            scene.dirty = true;
+        })
+        .register_fn("set_caustics", |s: &mut ScriptScene, enabled: bool, photon_radius: f32| {
+            let mut scene = s.0.borrow_mut();
+            scene.caustics = enabled;
+            scene.photon_radius = photon_radius.max(1e-3);
+            scene.dirty = true;
+        })
+        .register_fn("set_bidirectional", |s: &mut ScriptScene, enabled: bool| {
+            let mut scene = s.0.borrow_mut();
+            scene.bidirectional = enabled;
+            scene.dirty = true;
+        })
+        .register_fn("next_sky", |s: &mut ScriptScene| {
+            s.0.borrow_mut().next_sky();
+        })
+        .register_fn("set_texture_filtering", |s: &mut ScriptScene, name: &str| -> Result<(), Box<EvalAltResult>> {
+            let filtering = TextureFiltering::from_name(name).ok_or_else(|| format!("unknown texture filtering '{}'", name))?;
+            s.0.borrow_mut().texture_filtering = filtering;
+            Ok(())
+        })
+        .register_fn("set_projection", |s: &mut ScriptScene, name: &str| -> Result<(), Box<EvalAltResult>> {
+            let projection = Projection::from_name(name).ok_or_else(|| format!("unknown projection '{}'", name))?;
+            let mut scene = s.0.borrow_mut();
+            scene.camera.set_projection(projection);
+            scene.dirty = true;
+            Ok(())
+        })
+        .register_fn("set_fisheye", |s: &mut ScriptScene, fov: f32| {
+            let mut scene = s.0.borrow_mut();
+            scene.camera.set_projection(Projection::Fisheye { fov: fov.clamp(1.0, 360.0) });
+            scene.dirty = true;
+        })
+        .register_fn("set_stereo", |s: &mut ScriptScene, eye_separation: f32, convergence: f32| {
+            let mut scene = s.0.borrow_mut();
+            scene.camera.set_projection(Projection::Stereo { eye_separation: eye_separation.max(0.0), convergence: convergence.max(1e-3) });
+            scene.dirty = true;
+        })
+        .register_fn("set_orthographic", |s: &mut ScriptScene, height: f32| {
+            let mut scene = s.0.borrow_mut();
+            scene.camera.set_projection(Projection::Orthographic { height: height.max(1e-3) });
+            scene.dirty = true;
+        })
+        .register_fn("set_camera_path", |s: &mut ScriptScene, points: rhai::Array, speed: f32, looping: bool| -> Result<(), Box<EvalAltResult>> {
+            let points = points.into_iter()
+                .map(|point| point.try_cast::<Vec3>().ok_or_else(|| "camera paths are made of vec3 points".to_string()))
+                .collect::<Result<Vec<Vec3>, String>>()?;
+            if points.len() < 2 {
+                return Err("camera paths need at least two points".into());
+            }
+            let mut scene = s.0.borrow_mut();
+            let shake = scene.camera_rig.as_ref().and_then(|rig| rig.shake);
+            let mut rig = CameraRig::new(points, speed.max(1e-3), looping);
+            rig.shake = shake;
+            scene.camera_rig = Some(rig);
+            Ok(())
+        })
+        .register_fn("set_camera_shake", |s: &mut ScriptScene, amplitude: f32, frequency: f32| -> Result<(), Box<EvalAltResult>> {
+            let mut scene = s.0.borrow_mut();
+            let rig = scene.camera_rig.as_mut().ok_or("camera shake needs a camera path, set one first")?;
+            rig.shake = (amplitude > 0.0).then_some(CameraShake { amplitude, frequency: frequency.max(0.0) });
+            Ok(())
+        })
+        // Named cameras keep the field of view and projection of the scene's camera
+        .register_fn("add_camera", |s: &mut ScriptScene, name: &str, from: Vec3, at: Vec3| {
+            let mut scene = s.0.borrow_mut();
+            let mut camera = scene.camera;
+            camera.look_at(from, at);
+            scene.cameras.insert(name.to_string(), camera);
+        })
+        .register_fn("clear_camera_path", |s: &mut ScriptScene| {
+            s.0.borrow_mut().camera_rig = None;
+        })
+        .register_fn("max_bounces", |s: &mut ScriptScene| {
+            let scene = s.0.borrow();
+            scene.max_specular_bounces.max(scene.max_diffuse_bounces) as rhai::INT
+        })
+        .register_fn("set_max_bounces", |s: &mut ScriptScene, bounces: rhai::INT| {
+            let mut scene = s.0.borrow_mut();
+            scene.max_specular_bounces = bounces.max(1) as usize;
+            scene.max_diffuse_bounces = bounces.max(1) as usize;
+            scene.dirty = true;
+        })
+        .register_fn("set_max_specular_bounces", |s: &mut ScriptScene, bounces: rhai::INT| {
+            let mut scene = s.0.borrow_mut();
+            scene.max_specular_bounces = bounces.max(0) as usize;
+            scene.dirty = true;
+        })
+        .register_fn("set_max_diffuse_bounces", |s: &mut ScriptScene, bounces: rhai::INT| {
+            let mut scene = s.0.borrow_mut();
+            scene.max_diffuse_bounces = bounces.max(0) as usize;
+            scene.dirty = true;
+        });
+
+    engine
+}
@@ -0,0 +1,30 @@
+use super::Vec3;
+
+/// Rectangle that turns to face the camera in the kernel, showing one label from the text atlas
+#[derive(Debug, Clone, PartialEq)]
+pub struct Billboard {
+    pub center: Vec3,
+    pub width: f32,
+    pub height: f32,
+    pub color: Vec3,
+    pub label: usize, // Row of the label in the text atlas
+    pub material: usize,
+}
+
+impl Billboard {
+    pub fn new(center: Vec3, width: f32, height: f32, color: Vec3, label: usize) -> Self {
+        Self {
+            center,
+            width,
+            height,
+            color,
+            label,
+            material: 0,
+        }
+    }
+
+    // Half the diagonal, the furthest any corner gets from the center whichever way it faces
+    pub fn radius(&self) -> f32 {
+        0.5 * (self.width * self.width + self.height * self.height).sqrt()
+    }
+}
@@ -0,0 +1,65 @@
+use rand::Rng;
+
+use super::Vec3;
+
+/// Cubic Bezier segment swept by a circle, for hair and fur strands too thin to tessellate.
+/// The kernel intersects it as a chain of short capsules along the curve.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    pub control_points: [Vec3; 4],
+    pub radius: f32,
+    pub color: Vec3,
+    pub centroid: Vec3,
+    pub material: usize,
+}
+
+impl Curve {
+    pub fn new(control_points: [Vec3; 4], radius: f32, color: Vec3) -> Self {
+        let mut curve = Self { control_points, radius, color, centroid: Vec3(0.0, 0.0, 0.0), material: 0 };
+        curve.make_centroid();
+        curve
+    }
+
+    pub fn make_centroid(&mut self) {
+        self.centroid = self.point(0.5);
+    }
+
+    /// Point on the curve at `t` from 0 at the root to 1 at the tip
+    pub fn point(&self, t: f32) -> Vec3 {
+        let [p0, p1, p2, p3] = self.control_points;
+        let s = 1.0 - t;
+        p0 * (s * s * s) + p1 * (3.0 * s * s * t) + p2 * (3.0 * s * t * t) + p3 * (t * t * t)
+    }
+
+    /// Box around the control points grown by the radius, which holds the whole curve
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let [p0, p1, p2, p3] = self.control_points;
+        (p0.min(p1).min(p2).min(p3) - self.radius, p0.max(p1).max(p2).max(p3) + self.radius)
+    }
+}
+
+/// Strands growing out of a sphere's surface, `length` long and bending down under gravity,
+/// for fur test scenes
+pub fn fur_ball(center: Vec3, radius: f32, strands: usize, length: f32, strand_radius: f32, color: Vec3) -> Vec<Curve> {
+    let mut rng = rand::thread_rng();
+    (0..strands).map(|_| {
+        // Uniform direction on the sphere
+        let y: f32 = rng.gen_range(-1.0..1.0);
+        let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+        let ring = (1.0 - y * y).sqrt();
+        let normal = Vec3(ring * angle.cos(), y, ring * angle.sin());
+
+        let root = center + normal * radius;
+        let droop = Vec3(0.0, -length * 0.4, 0.0);
+        Curve::new(
+            [
+                root,
+                root + normal * (length * 0.4),
+                root + normal * (length * 0.7) + droop * 0.5,
+                root + normal * length + droop,
+            ],
+            strand_radius,
+            color,
+        )
+    }).collect()
+}
@@ -0,0 +1,283 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use super::{Triangle, Vec3};
+
+// Weight of the planes holding open borders in place, per squared length of the border edge
+const BORDER_WEIGHT: f64 = 1000.0;
+
+/// Sum of squared distances to a set of planes, the quadric error metric of Garland and Heckbert.
+/// The symmetric 4x4 matrix is stored as its upper triangle.
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    // Squared distance to the plane `normal . p + d = 0`, times `weight`
+    fn plane(normal: [f64; 3], d: f64, weight: f64) -> Self {
+        let [a, b, c] = normal;
+        Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d].map(|value| value * weight))
+    }
+
+    fn add(self, other: Quadric) -> Quadric {
+        let mut sum = self;
+        for (value, other) in sum.0.iter_mut().zip(other.0) {
+            *value += other;
+        }
+        sum
+    }
+
+    fn error(&self, [x, y, z]: [f64; 3]) -> f64 {
+        let q = &self.0;
+        q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9]
+    }
+
+    // Point with the least error, None when the planes don't pin one down, like those of a flat patch
+    fn minimum(&self) -> Option<[f64; 3]> {
+        let q = &self.0;
+        let [a, b, c, d, e, f] = [q[0], q[1], q[2], q[4], q[5], q[7]];
+        let det = a * (d * f - e * e) - b * (b * f - e * c) + c * (b * e - d * c);
+        let scale = (a + d + f).powi(3);
+        if det.abs() <= scale * 1e-9 || scale == 0.0 {
+            return None;
+        }
+        // Cramer's rule for A p = -b
+        let [u, v, w] = [-q[3], -q[6], -q[8]];
+        Some([
+            (u * (d * f - e * e) - b * (v * f - e * w) + c * (v * e - d * w)) / det,
+            (a * (v * f - e * w) - u * (b * f - e * c) + c * (b * w - v * c)) / det,
+            (a * (d * w - v * e) - b * (b * w - v * c) + u * (b * e - d * c)) / det,
+        ])
+    }
+}
+
+// Collapsing vertex `b` into `a` at `position`, valid while neither changed since it was queued
+struct Collapse {
+    cost: f64,
+    a: usize,
+    b: usize,
+    versions: [u32; 2],
+    position: [f64; 3],
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost.total_cmp(&other.cost) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // Reversed, so the heap pops the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+/// Simplifies the surface by collapsing the edges whose removal moves it least until at most
+/// `target` triangles are left, measured with quadric error metrics. Corners are joined by
+/// position like in `recompute_normals`, and open borders are held in place. Collapses that
+/// would flip a face or pinch the surface are skipped, so a mesh can end above `target`.
+/// Triangles keep their color and material, corner normals are dropped.
+pub fn decimate_triangles(triangles: &[Triangle], target: usize) -> Vec<Triangle> {
+    if triangles.len() <= target {
+        return triangles.to_vec();
+    }
+
+    // Join the corners into vertices shared by the faces meeting there
+    let key = |corner: Vec3| [corner.0.to_bits(), corner.1.to_bits(), corner.2.to_bits()];
+    let mut vertex_of: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut positions: Vec<[f64; 3]> = Vec::new();
+    let mut colors: Vec<Option<[u8; 3]>> = Vec::new();
+    let mut faces: Vec<[usize; 3]> = Vec::with_capacity(triangles.len());
+    for triangle in triangles {
+        let mut face = [0; 3];
+        for (k, corner) in triangle.corners.into_iter().enumerate() {
+            face[k] = *vertex_of.entry(key(corner)).or_insert_with(|| {
+                positions.push([corner.0 as f64, corner.1 as f64, corner.2 as f64]);
+                colors.push(triangle.vertex_colors.map(|corner_colors| corner_colors[k]));
+                positions.len() - 1
+            });
+        }
+        faces.push(face);
+    }
+
+    let mut faces_of: Vec<Vec<usize>> = vec![Vec::new(); positions.len()];
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (f, face) in faces.iter().enumerate() {
+        let normal = face_normal(&positions, *face);
+        let twice_area = length(normal);
+        for k in 0..3 {
+            faces_of[face[k]].push(f);
+            let (a, b) = (face[k], face[(k + 1) % 3]);
+            edge_faces.entry((a.min(b), a.max(b))).or_default().push(f);
+        }
+        // Planes are weighted by area, so many small faces count as much as one large one, and
+        // degenerate faces have no plane to keep the surface near
+        if twice_area > 0.0 {
+            let normal = scale(normal, 1.0 / twice_area);
+            let plane = Quadric::plane(normal, -dot(normal, positions[face[0]]), twice_area * 0.5);
+            for vertex in *face {
+                quadrics[vertex] = quadrics[vertex].add(plane);
+            }
+        }
+    }
+
+    // Edges of a single face lie on an open border, which a plane through the edge at right angles
+    // to the face keeps from shrinking
+    for (&(a, b), edge_faces) in &edge_faces {
+        if let [f] = edge_faces[..] {
+            let normal = face_normal(&positions, faces[f]);
+            let edge = sub(positions[b], positions[a]);
+            let across = cross(edge, normal);
+            let across_length = length(across);
+            if across_length > 0.0 {
+                let across = scale(across, 1.0 / across_length);
+                let plane = Quadric::plane(across, -dot(across, positions[a]), BORDER_WEIGHT * dot(edge, edge));
+                quadrics[a] = quadrics[a].add(plane);
+                quadrics[b] = quadrics[b].add(plane);
+            }
+        }
+    }
+
+    let mut versions = vec![0u32; positions.len()];
+    let mut removed = vec![false; positions.len()];
+    let mut alive = vec![true; faces.len()];
+    let mut live = faces.len();
+
+    let candidate = |positions: &[[f64; 3]], quadrics: &[Quadric], versions: &[u32], a: usize, b: usize| {
+        let quadric = quadrics[a].add(quadrics[b]);
+        let midpoint = scale(add(positions[a], positions[b]), 0.5);
+        let span = length(sub(positions[a], positions[b]));
+        // The optimum can land far off for nearly flat patches, where the ends or middle do as well
+        let position = quadric.minimum()
+            .filter(|&optimum| length(sub(optimum, midpoint)) <= span)
+            .unwrap_or_else(|| {
+                [positions[a], positions[b], midpoint].into_iter()
+                    .min_by(|&p, &q| quadric.error(p).total_cmp(&quadric.error(q)))
+                    .unwrap()
+            });
+        Collapse { cost: quadric.error(position).max(0.0), a, b, versions: [versions[a], versions[b]], position }
+    };
+
+    let mut heap: BinaryHeap<Collapse> = edge_faces.keys()
+        .map(|&(a, b)| candidate(&positions, &quadrics, &versions, a, b))
+        .collect();
+
+    while live > target {
+        let Some(Collapse { a, b, versions: queued, position, .. }) = heap.pop() else { break };
+        if removed[a] || removed[b] || queued != [versions[a], versions[b]] {
+            continue;
+        }
+
+        // Vertices next to both ends other than across their shared faces would pinch the surface
+        let shared_faces = faces_of[a].iter().filter(|&&f| alive[f] && faces[f].contains(&b)).count();
+        let neighbours_of = |vertex: usize| -> HashSet<usize> {
+            faces_of[vertex].iter()
+                .filter(|&&f| alive[f])
+                .flat_map(|&f| faces[f])
+                .filter(|&other| other != vertex)
+                .collect()
+        };
+        if neighbours_of(a).intersection(&neighbours_of(b)).count() > shared_faces {
+            continue;
+        }
+        if folds(&positions, &faces, &alive, &faces_of[a], a, b, position) || folds(&positions, &faces, &alive, &faces_of[b], b, a, position) {
+            continue;
+        }
+
+        positions[a] = position;
+        quadrics[a] = quadrics[a].add(quadrics[b]);
+        removed[b] = true;
+        for f in std::mem::take(&mut faces_of[b]) {
+            if !alive[f] {
+                continue;
+            }
+            if faces[f].contains(&a) {
+                alive[f] = false;
+                live -= 1;
+            } else {
+                for vertex in &mut faces[f] {
+                    if *vertex == b {
+                        *vertex = a;
+                    }
+                }
+                faces_of[a].push(f);
+            }
+        }
+        faces_of[a].retain(|&f| alive[f]);
+        versions[a] += 1;
+
+        let neighbours: HashSet<usize> = faces_of[a].iter().flat_map(|&f| faces[f]).filter(|&vertex| vertex != a).collect();
+        for neighbour in neighbours {
+            heap.push(candidate(&positions, &quadrics, &versions, a, neighbour));
+        }
+    }
+
+    faces.iter().zip(triangles).zip(&alive)
+        .filter(|(_, &alive)| alive)
+        .map(|((face, source), _)| {
+            let corners = face.map(|vertex| {
+                let [x, y, z] = positions[vertex];
+                Vec3(x as f32, y as f32, z as f32)
+            });
+            let mut triangle = Triangle::build_from_corners(corners, source.color);
+            triangle.material = source.material;
+            if source.vertex_colors.is_some() {
+                triangle.vertex_colors = Some(face.map(|vertex| colors[vertex].unwrap_or([255; 3])));
+            }
+            triangle
+        })
+        .collect()
+}
+
+// Whether moving `vertex` to `position` turns any of its faces not shared with `other` over
+fn folds(positions: &[[f64; 3]], faces: &[[usize; 3]], alive: &[bool], faces_of: &[usize], vertex: usize, other: usize, position: [f64; 3]) -> bool {
+    faces_of.iter()
+        .filter(|&&f| alive[f] && !faces[f].contains(&other))
+        .any(|&f| {
+            let before = face_normal(positions, faces[f]);
+            let moved = faces[f].map(|corner| if corner == vertex { position } else { positions[corner] });
+            let after = cross(sub(moved[1], moved[0]), sub(moved[2], moved[0]));
+            dot(before, after) <= 0.0
+        })
+}
+
+// Cross product of two edges, twice the area long
+fn face_normal(positions: &[[f64; 3]], [a, b, c]: [usize; 3]) -> [f64; 3] {
+    cross(sub(positions[b], positions[a]), sub(positions[c], positions[a]))
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f64; 3], factor: f64) -> [f64; 3] {
+    a.map(|value| value * factor)
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use image::{ImageBuffer, Luma};
+
+use super::{Triangle, Vec3};
+
+/// Grayscale image pushing a surface out along its normals, white by the full height.
+/// Meshes don't carry texture coordinates here, so the image is projected along x, y and z
+/// over the mesh's bounds and the three are blended by the normal, which hides the seams.
+#[derive(Clone)]
+pub struct DisplacementMap {
+    image: ImageBuffer<Luma<u16>, Vec<u16>>,
+}
+
+impl DisplacementMap {
+    pub fn new(path: &str) -> Self {
+        let image = image::open(path)
+            .expect("Should have been able to read the displacement map")
+            .into_luma16();
+        Self { image }
+    }
+
+    // Bilinear lookup from 0 to 1, repeating the image outside of 0..1
+    fn sample(&self, u: f32, v: f32) -> f32 {
+        let (width, height) = self.image.dimensions();
+        let x = u.rem_euclid(1.0) * width as f32 - 0.5;
+        let y = v.rem_euclid(1.0) * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |dx: f32, dy: f32| {
+            let px = ((x0 + dx) as i64).rem_euclid(width as i64) as u32;
+            let py = ((y0 + dy) as i64).rem_euclid(height as i64) as u32;
+            self.image.get_pixel(px, py).0[0] as f32 / u16::MAX as f32
+        };
+        let top = texel(0.0, 0.0) * (1.0 - fx) + texel(1.0, 0.0) * fx;
+        let bottom = texel(0.0, 1.0) * (1.0 - fx) + texel(1.0, 1.0) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    // Blend of the three planar projections, with `size` the length the image spans
+    fn height(&self, position: Vec3, normal: Vec3, origin: Vec3, size: f32) -> f32 {
+        let local = (position - origin) / size;
+        let weights = Vec3(normal.0.abs(), normal.1.abs(), normal.2.abs());
+        let total = (weights.0 + weights.1 + weights.2).max(1e-6);
+        (self.sample(local.2, local.1) * weights.0
+            + self.sample(local.0, local.2) * weights.1
+            + self.sample(local.0, local.1) * weights.2) / total
+    }
+}
+
+/// Splits every triangle into `4^subdivisions` smaller ones and moves their corners out along the
+/// smoothed surface normal by the map times `height`. Corners shared between triangles get the
+/// same normal, so the surface stays closed where the mesh was.
+pub fn displace_triangles(triangles: &[Triangle], map: &DisplacementMap, height: f32, subdivisions: u32) -> Vec<Triangle> {
+    assert!(subdivisions <= 6, "Displacement is limited to 6 subdivisions, 4096 triangles for each one");
+    if triangles.is_empty() {
+        return Vec::new();
+    }
+
+    // Area-weighted face normals summed at every distinct corner position
+    let key = |corner: Vec3| [corner.0.to_bits(), corner.1.to_bits(), corner.2.to_bits()];
+    let mut corner_normals: HashMap<[u32; 3], Vec3> = HashMap::new();
+    for triangle in triangles {
+        let [a, b, c] = triangle.corners;
+        let face = (b - a).cross(c - a);
+        for corner in triangle.corners {
+            *corner_normals.entry(key(corner)).or_insert(Vec3(0.0, 0.0, 0.0)) += face;
+        }
+    }
+
+    let (min, max) = triangles.iter().flat_map(|triangle| triangle.corners).fold(
+        (Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY), Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)),
+        |(min, max), corner| (min.min(corner), max.max(corner)),
+    );
+    let extent = max - min;
+    let size = extent.0.max(extent.1).max(extent.2).max(1e-6);
+
+    let steps = 1usize << subdivisions;
+    let mut displaced = Vec::with_capacity(triangles.len() * steps * steps);
+    for triangle in triangles {
+        let [a, b, c] = triangle.corners;
+        let normals = triangle.corners.map(|corner| corner_normals[&key(corner)]);
+        let colors = triangle.vertex_colors.map(|colors| colors.map(|color| Vec3(color[0] as f32, color[1] as f32, color[2] as f32)));
+
+        // Corner of the small triangle grid at step i along a to b and j along a to c
+        let point = |i: usize, j: usize| {
+            let (u, v) = (i as f32 / steps as f32, j as f32 / steps as f32);
+            let w = 1.0 - u - v;
+            let position = a * w + b * u + c * v;
+            let normal = (normals[0] * w + normals[1] * u + normals[2] * v).normalize();
+            let color = colors.map(|colors| {
+                let color = colors[0] * w + colors[1] * u + colors[2] * v;
+                [color.0, color.1, color.2].map(|channel| channel.round() as u8)
+            });
+            (position + normal * (map.height(position, normal, min, size) * height), color)
+        };
+        let small_triangle = |corners: [(Vec3, Option<[u8; 3]>); 3]| {
+            let mut small = Triangle::build_from_corners(corners.map(|(position, _)| position), triangle.color);
+            small.material = triangle.material;
+            if triangle.vertex_colors.is_some() {
+                small.vertex_colors = Some(corners.map(|(_, color)| color.unwrap()));
+            }
+            small
+        };
+
+        for j in 0..steps {
+            for i in 0..steps - j {
+                displaced.push(small_triangle([point(i, j), point(i + 1, j), point(i, j + 1)]));
+                if i + j + 1 < steps {
+                    displaced.push(small_triangle([point(i + 1, j), point(i + 1, j + 1), point(i, j + 1)]));
+                }
+            }
+        }
+    }
+    displaced
+}
@@ -0,0 +1,94 @@
+use super::{Triangle, Vec2, Vec3};
+
+// Grid cells per side of one chunk, each chunk becomes its own object in the scene
+const CHUNK_CELLS: usize = 32;
+
+// Struct to represent a terrain grid generated from a grayscale image
+pub struct Heightmap {
+    // Grid vertices in row-major order, with smoothed normals and UVs spanning the image
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    columns: usize,
+    rows: usize,
+
+    pub chunks: Vec<Vec<Triangle>>,
+    color: Vec3,
+}
+
+impl Heightmap {
+    /// Loads an image and builds a grid centered on the origin, `scale` wide along x and z,
+    /// with white pixels `scale.1` high
+    pub fn new(color: Vec3, path: &str, scale: Vec3) -> Self {
+        let image = image::open(path)
+            .expect("Should have been able to read the heightmap")
+            .into_luma16();
+        let (columns, rows) = (image.width() as usize, image.height() as usize);
+        assert!(columns >= 2 && rows >= 2, "Heightmap needs at least 2x2 pixels");
+
+        let mut heightmap = Heightmap {
+            vertices: Vec::with_capacity(columns * rows),
+            normals: Vec::with_capacity(columns * rows),
+            uvs: Vec::with_capacity(columns * rows),
+            columns,
+            rows,
+            chunks: Vec::new(),
+            color,
+        };
+
+        for (x, z, pixel) in image.enumerate_pixels() {
+            let u = x as f32 / (columns - 1) as f32;
+            let v = z as f32 / (rows - 1) as f32;
+            let height = pixel.0[0] as f32 / u16::MAX as f32;
+            heightmap.vertices.push(Vec3((u - 0.5) * scale.0, height * scale.1, (v - 0.5) * scale.2));
+            heightmap.uvs.push(Vec2(u, v));
+        }
+
+        heightmap.calculate_normals();
+        heightmap.build_chunks();
+
+        heightmap
+    }
+
+    fn vertex(&self, x: usize, z: usize) -> Vec3 {
+        self.vertices[z * self.columns + x]
+    }
+
+    // Central differences between neighbouring vertices, clamped at the edges
+    fn calculate_normals(&mut self) {
+        for z in 0..self.rows {
+            for x in 0..self.columns {
+                let along_x = self.vertex((x + 1).min(self.columns - 1), z) - self.vertex(x.saturating_sub(1), z);
+                let along_z = self.vertex(x, (z + 1).min(self.rows - 1)) - self.vertex(x, z.saturating_sub(1));
+                self.normals.push(along_z.cross(along_x).normalize());
+            }
+        }
+    }
+
+    // Two triangles per grid cell, grouped into square chunks so each can be edited on its own
+    fn build_chunks(&mut self) {
+        let cells_x = self.columns - 1;
+        let cells_z = self.rows - 1;
+
+        for chunk_z in (0..cells_z).step_by(CHUNK_CELLS) {
+            for chunk_x in (0..cells_x).step_by(CHUNK_CELLS) {
+                let mut triangles = Vec::new();
+
+                for z in chunk_z..(chunk_z + CHUNK_CELLS).min(cells_z) {
+                    for x in chunk_x..(chunk_x + CHUNK_CELLS).min(cells_x) {
+                        let top_left = self.vertex(x, z);
+                        let top_right = self.vertex(x + 1, z);
+                        let bottom_left = self.vertex(x, z + 1);
+                        let bottom_right = self.vertex(x + 1, z + 1);
+
+                        // Wound so the geometric normal points up
+                        triangles.push(Triangle::build_from_corners([top_left, bottom_left, top_right], self.color));
+                        triangles.push(Triangle::build_from_corners([top_right, bottom_left, bottom_right], self.color));
+                    }
+                }
+
+                self.chunks.push(triangles);
+            }
+        }
+    }
+}
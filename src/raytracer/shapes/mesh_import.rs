@@ -0,0 +1,278 @@
+use std::io;
+
+use super::{CornerColors, Vec3};
+
+// Binary STL: an 80 byte header, a triangle count, then 50 bytes per triangle
+const STL_HEADER: usize = 84;
+const STL_TRIANGLE: usize = 50;
+
+/// Reads the triangles of a binary or ASCII STL file as their three corners each
+pub fn read_stl(bytes: &[u8]) -> io::Result<Vec<[Vec3; 3]>> {
+    // Binary files may also start with "solid", so the size is the more reliable tell
+    let binary_count = bytes.get(80..STL_HEADER).map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize);
+    match binary_count {
+        Some(count) if bytes.len() == STL_HEADER + count * STL_TRIANGLE => Ok(read_binary_stl(bytes, count)),
+        _ if bytes.starts_with(b"solid") => read_ascii_stl(bytes),
+        _ => Err(invalid("file is neither a binary nor an ASCII STL")),
+    }
+}
+
+fn read_binary_stl(bytes: &[u8], count: usize) -> Vec<[Vec3; 3]> {
+    let float = |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    (0..count).map(|i| {
+        // Each record starts with a facet normal, recomputed from the winding instead
+        let corners = STL_HEADER + i * STL_TRIANGLE + 12;
+        [0, 1, 2].map(|c| {
+            let offset = corners + c * 12;
+            Vec3(float(offset), float(offset + 4), float(offset + 8))
+        })
+    }).collect()
+}
+
+fn read_ascii_stl(bytes: &[u8]) -> io::Result<Vec<[Vec3; 3]>> {
+    let text = std::str::from_utf8(bytes).map_err(|_| invalid("ASCII STL is not valid UTF-8"))?;
+    let mut triangles = Vec::new();
+    let mut corners = Vec::with_capacity(3);
+    let mut words = text.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "vertex" => {
+                let mut coordinate = || -> io::Result<f32> {
+                    words.next()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or_else(|| invalid("STL vertex needs three numbers"))
+                };
+                corners.push(Vec3(coordinate()?, coordinate()?, coordinate()?));
+            },
+            // Facets with more than three vertices are split into a fan
+            "endloop" => {
+                for i in 1..corners.len().saturating_sub(1) {
+                    triangles.push([corners[0], corners[i], corners[i + 1]]);
+                }
+                corners.clear();
+            },
+            _ => {},
+        }
+    }
+    Ok(triangles)
+}
+
+/// Reads the faces of an ASCII or binary PLY file as triangles, splitting polygons into fans,
+/// along with the colors of their corners when the vertices have them
+pub fn read_ply(bytes: &[u8]) -> io::Result<Vec<([Vec3; 3], Option<CornerColors>)>> {
+    let ply = parse_ply(bytes)?;
+    let corner = |index: usize| ply.vertices.get(index).copied().ok_or_else(|| invalid("PLY face points past the vertices"));
+    let mut triangles = Vec::new();
+    for polygon in &ply.faces {
+        for k in 1..polygon.len().saturating_sub(1) {
+            let indices = [polygon[0], polygon[k], polygon[k + 1]];
+            let colors = ply.colors.as_ref().map(|colors| indices.map(|index| colors[index]));
+            triangles.push(([corner(indices[0])?, corner(indices[1])?, corner(indices[2])?], colors));
+        }
+    }
+    Ok(triangles)
+}
+
+/// Vertices and faces of a PLY file
+pub struct PlyData {
+    pub vertices: Vec<Vec3>,
+    /// sRGB color of each vertex, when the file has red, green and blue properties
+    pub colors: Option<Vec<[u8; 3]>>,
+    /// Vertex indices of each polygon
+    pub faces: Vec<Vec<usize>>,
+}
+
+/// Parses an ASCII or binary PLY file. Only vertex positions and colors and face indices are
+/// kept, other elements and properties are skipped.
+pub fn parse_ply(bytes: &[u8]) -> io::Result<PlyData> {
+    let header_end = find(bytes, b"end_header")
+        .ok_or_else(|| invalid("PLY header has no end_header"))?;
+    let body_start = bytes[header_end..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |i| header_end + i + 1);
+    let header = std::str::from_utf8(&bytes[..header_end]).map_err(|_| invalid("PLY header is not valid UTF-8"))?;
+
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err(invalid("file is not a PLY"));
+    }
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", name, ..] => format = Some(match *name {
+                "ascii" => PlyFormat::Ascii,
+                "binary_little_endian" => PlyFormat::LittleEndian,
+                "binary_big_endian" => PlyFormat::BigEndian,
+                _ => return Err(invalid("unknown PLY format")),
+            }),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid("PLY element count is not a number"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let property = PlyProperty { name: name.to_string(), list_count: Some(PlyScalar::parse(count_type)?), scalar: PlyScalar::parse(item_type)? };
+                elements.last_mut().ok_or_else(|| invalid("PLY property outside an element"))?.properties.push(property);
+            },
+            ["property", scalar, name] => {
+                let property = PlyProperty { name: name.to_string(), list_count: None, scalar: PlyScalar::parse(scalar)? };
+                elements.last_mut().ok_or_else(|| invalid("PLY property outside an element"))?.properties.push(property);
+            },
+            _ => {},
+        }
+    }
+
+    let mut reader = PlyReader {
+        format: format.ok_or_else(|| invalid("PLY header has no format"))?,
+        bytes: &bytes[body_start..],
+        offset: 0,
+    };
+    let mut ply = PlyData { vertices: Vec::new(), colors: None, faces: Vec::new() };
+    for element in &elements {
+        let position = |name: &str| element.properties.iter().position(|property| property.name == name);
+        let axes = [position("x"), position("y"), position("z")];
+        let channels = [position("red"), position("green"), position("blue")];
+        let has_color = element.name == "vertex" && channels.iter().all(Option::is_some);
+        if has_color {
+            ply.colors = Some(Vec::with_capacity(element.count));
+        }
+        let indices = element.properties.iter().position(|property| property.name == "vertex_indices" || property.name == "vertex_index");
+
+        for _ in 0..element.count {
+            let mut values = Vec::with_capacity(element.properties.len());
+            for property in &element.properties {
+                values.push(reader.read_property(property)?);
+            }
+            if element.name == "vertex" {
+                let coordinate = |axis: Option<usize>| axis.map_or(0.0, |i| values[i][0] as f32);
+                ply.vertices.push(Vec3(coordinate(axes[0]), coordinate(axes[1]), coordinate(axes[2])));
+                if let Some(colors) = &mut ply.colors {
+                    // Float channels run from 0 to 1, integer ones from 0 to 255
+                    colors.push(channels.map(|channel| {
+                        let i = channel.unwrap();
+                        let value = values[i][0];
+                        match element.properties[i].scalar {
+                            PlyScalar::F32 | PlyScalar::F64 => (value * 255.0).round().clamp(0.0, 255.0) as u8,
+                            _ => value.clamp(0.0, 255.0) as u8,
+                        }
+                    }));
+                }
+            } else if let (true, Some(i)) = (element.name == "face", indices) {
+                ply.faces.push(values[i].iter().map(|&index| index as usize).collect());
+            }
+        }
+    }
+    Ok(ply)
+}
+
+#[derive(Clone, Copy)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => PlyScalar::I8,
+            "uchar" | "uint8" => PlyScalar::U8,
+            "short" | "int16" => PlyScalar::I16,
+            "ushort" | "uint16" => PlyScalar::U16,
+            "int" | "int32" => PlyScalar::I32,
+            "uint" | "uint32" => PlyScalar::U32,
+            "float" | "float32" => PlyScalar::F32,
+            "double" | "float64" => PlyScalar::F64,
+            _ => return Err(invalid("unknown PLY property type")),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyScalar::I8 | PlyScalar::U8 => 1,
+            PlyScalar::I16 | PlyScalar::U16 => 2,
+            PlyScalar::I32 | PlyScalar::U32 | PlyScalar::F32 => 4,
+            PlyScalar::F64 => 8,
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    list_count: Option<PlyScalar>, // Type of the length in front of a list property
+    scalar: PlyScalar,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+// Walks the body of the file one value at a time, as text or as binary of either byte order
+struct PlyReader<'a> {
+    format: PlyFormat,
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl PlyReader<'_> {
+    // All of a property's values, one for a scalar or the items of a list
+    fn read_property(&mut self, property: &PlyProperty) -> io::Result<Vec<f64>> {
+        let count = match property.list_count {
+            Some(count_type) => self.read_value(count_type)? as usize,
+            None => 1,
+        };
+        (0..count).map(|_| self.read_value(property.scalar)).collect()
+    }
+
+    fn read_value(&mut self, scalar: PlyScalar) -> io::Result<f64> {
+        if let PlyFormat::Ascii = self.format {
+            let rest = &self.bytes[self.offset..];
+            let start = rest.iter().position(|b| !b.is_ascii_whitespace()).ok_or_else(|| invalid("PLY body is truncated"))?;
+            let length = rest[start..].iter().position(|b| b.is_ascii_whitespace()).unwrap_or(rest.len() - start);
+            self.offset += start + length;
+            return std::str::from_utf8(&rest[start..start + length]).ok()
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| invalid("PLY value is not a number"));
+        }
+
+        let size = scalar.size();
+        let mut raw = [0u8; 8];
+        raw[..size].copy_from_slice(self.bytes.get(self.offset..self.offset + size).ok_or_else(|| invalid("PLY body is truncated"))?);
+        self.offset += size;
+        if let PlyFormat::BigEndian = self.format {
+            raw[..size].reverse();
+        }
+        Ok(match scalar {
+            PlyScalar::I8 => raw[0] as i8 as f64,
+            PlyScalar::U8 => raw[0] as f64,
+            PlyScalar::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+            PlyScalar::U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+            PlyScalar::I32 => i32::from_le_bytes(raw[..4].try_into().unwrap()) as f64,
+            PlyScalar::U32 => u32::from_le_bytes(raw[..4].try_into().unwrap()) as f64,
+            PlyScalar::F32 => f32::from_le_bytes(raw[..4].try_into().unwrap()) as f64,
+            PlyScalar::F64 => f64::from_le_bytes(raw),
+        })
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -1,11 +1,30 @@
 pub mod sphere;
 pub mod triangle;
 pub mod square;
+pub mod quad;
+pub mod billboard;
 pub mod obj_mesh;
+pub mod mesh_import;
+pub mod heightmap;
+pub mod displacement;
+pub mod decimate;
+pub mod point_cloud;
+pub mod curve;
+pub mod procedural;
 pub mod utils;
 
 pub use sphere::*;
 pub use triangle::*;
 pub use square::*;
+pub use quad::*;
+pub use billboard::*;
 pub use obj_mesh::*;
-pub use utils::*;
\ No newline at end of file
+pub use mesh_import::*;
+pub use heightmap::*;
+pub use displacement::*;
+pub use decimate::*;
+pub use point_cloud::*;
+pub use curve::*;
+pub use utils::*;
+
+use super::Texture;
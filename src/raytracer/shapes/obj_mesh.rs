@@ -1,114 +1,377 @@
+use std::collections::HashMap;
 use std::fs;
-use super::{Vec3, Vec2, Triangle};
+use std::io;
+use std::path::Path;
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+use tracing::info_span;
+use super::{decimate_triangles, displace_triangles, read_ply, read_stl, rotate_vector_around_axis, DisplacementMap, Vec3, Vec2, Triangle};
 
-// Struct to represent an OBJ mesh
+/// Largest angle in radians between neighbouring faces that `recompute_normals` smooths over when
+/// a file comes without normals, sharper edges stay creased
+pub const DEFAULT_SMOOTH_ANGLE: f32 = std::f32::consts::FRAC_PI_3;
+// Levels of detail stop before getting down to fewer triangles than this
+const MIN_LOD_TRIANGLES: usize = 64;
+
+// Struct to represent an OBJ mesh, STL and PLY files are read into it as well
+#[derive(Clone)]
 pub struct ObjMesh {
     // Vertices, texture coordinates, and normals
     v: Vec<Vec3>,
     vt: Vec<Vec2>,
     vn: Vec<Vec3>,
+    // Vertex colors from the "v x y z r g b" extension, used when every vertex has one
+    vc: Vec<[u8; 3]>,
 
     pub triangles: Vec<Triangle>,
     color: Vec3,
+    /// Name of the file the mesh was read from, without its extension
+    pub name: String,
+    // Where the model's origin was moved to, the pivot for scaling and rotating
+    position: Vec3,
+    source: MeshSource,
 }
 
-impl ObjMesh {
-    pub fn new(color: Vec3, path: &str) -> Self {
+/// The file a mesh was read from and how it was posed since, enough to read it again when the
+/// file changes
+#[derive(Clone)]
+pub struct MeshSource {
+    pub path: String,
+    color: Vec3,
+    steps: Vec<MeshStep>,
+}
 
+#[derive(Clone)]
+enum MeshStep {
+    At(Vec3),
+    Scaled(f32),
+    Rotated(Vec3),
+    Displaced(Arc<DisplacementMap>, f32, u32),
+    Normals(f32),
+    Decimated(usize),
+}
 
-        let contents = fs::read_to_string(path)
-            .expect("Should have been able to read the file");
+impl MeshSource {
+    /// Reads the file again and poses it the same way
+    pub fn load(&self) -> io::Result<ObjMesh> {
+        let mut mesh = ObjMesh::load(self.color, &self.path)?;
+        for step in &self.steps {
+            mesh = match step {
+                MeshStep::At(position) => mesh.at(*position),
+                MeshStep::Scaled(factor) => mesh.scaled(*factor),
+                MeshStep::Rotated(angles) => mesh.rotated(*angles),
+                MeshStep::Displaced(map, height, subdivisions) => mesh.displaced(map, *height, *subdivisions),
+                MeshStep::Normals(smooth_angle) => mesh.recompute_normals(*smooth_angle),
+                MeshStep::Decimated(target) => mesh.decimated(*target),
+            };
+        }
+        Ok(mesh)
+    }
+}
 
-        let mut mesh = ObjMesh {
+impl ObjMesh {
+    /// Reads an OBJ file. Malformed lines fail with their line number, directives that don't
+    /// change the triangles, like groups and materials, are skipped. Faces shade with the file's
+    /// normals, and a file without any gets them from `recompute_normals`.
+    pub fn new(color: Vec3, path: &str) -> io::Result<Self> {
+        let _span = info_span!("load_mesh", path).entered();
+        let contents = fs::read_to_string(path)?;
+        ObjMesh::parse(color, path, &contents)
+    }
+
+    /// Reads OBJ text, naming the mesh after `path` as if it had been read from there
+    pub fn parse(color: Vec3, path: &str, contents: &str) -> io::Result<Self> {
+        let mut mesh = ObjMesh::new_empty(color, path);
+        mesh.process_file_contents(contents)?;
+        if mesh.vn.is_empty() {
+            smooth_normals(&mut mesh.triangles, DEFAULT_SMOOTH_ANGLE);
+        }
+        Ok(mesh)
+    }
+
+    fn new_empty(color: Vec3, path: &str) -> Self {
+        ObjMesh {
             v: Vec::new(),
             vt: Vec::new(),
             vn: Vec::new(),
+            vc: Vec::new(),
             triangles: Vec::new(),
             color,
+            name: Path::new(path).file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned()),
+            position: Vec3(0.0, 0.0, 0.0),
+            source: MeshSource { path: path.to_string(), color, steps: Vec::new() },
+        }
+    }
+
+    /// Where the mesh was read from and how it was posed
+    pub fn source(&self) -> &MeshSource {
+        &self.source
+    }
+
+    /// Reads an OBJ, STL or PLY file, picking the format by its extension. STL and PLY files carry no
+    /// normals, so theirs come from `recompute_normals`.
+    pub fn load(color: Vec3, path: &str) -> io::Result<Self> {
+        let _span = info_span!("load_mesh", path).entered();
+        let extension = Path::new(path).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+        let corners = match extension.as_deref() {
+            Some("stl") => read_stl(&fs::read(path)?)?.into_iter().map(|corners| (corners, None)).collect(),
+            Some("ply") => read_ply(&fs::read(path)?)?,
+            _ => return ObjMesh::parse(color, path, &fs::read_to_string(path)?),
         };
 
-        mesh.process_file_contents(&contents);
+        let mut mesh = ObjMesh::new_empty(color, path);
+        mesh.triangles = corners.into_iter()
+            .map(|(corners, vertex_colors)| Triangle { vertex_colors, ..Triangle::build_from_corners(corners, color) })
+            .collect();
+        smooth_normals(&mut mesh.triangles, DEFAULT_SMOOTH_ANGLE);
+        Ok(mesh)
+    }
 
-        mesh
+    /// Moves the model so its origin is at `position`. The mesh can be cloned and
+    /// posed again to add several copies while reading the file once.
+    pub fn at(mut self, position: Vec3) -> Self {
+        let offset = position - self.position;
+        for triangle in &mut self.triangles {
+            triangle.translate(offset);
+        }
+        self.position = position;
+        self.source.steps.push(MeshStep::At(position));
+        self
     }
 
+    /// Grows or shrinks the model by `factor` around its origin
+    pub fn scaled(mut self, factor: f32) -> Self {
+        let pivot = self.position;
+        self.transform_corners(|corner| pivot + (corner - pivot) * factor);
+        self.source.steps.push(MeshStep::Scaled(factor));
+        self
+    }
 
-    fn process_file_contents(&mut self, contents: &str) {
-        let lines = contents.lines();
+    /// Turns the model around its origin by the angles in radians around the x, y and z axes, in that order
+    pub fn rotated(mut self, angles: Vec3) -> Self {
+        let pivot = self.position;
+        let turn = |vector: Vec3| {
+            let vector = rotate_vector_around_axis(vector, Vec3(1.0, 0.0, 0.0), angles.0);
+            let vector = rotate_vector_around_axis(vector, Vec3(0.0, 1.0, 0.0), angles.1);
+            rotate_vector_around_axis(vector, Vec3(0.0, 0.0, 1.0), angles.2)
+        };
+        self.transform_corners(|corner| pivot + turn(corner - pivot));
+        for normals in self.triangles.iter_mut().filter_map(|triangle| triangle.normals.as_mut()) {
+            *normals = normals.map(turn);
+        }
+        self.source.steps.push(MeshStep::Rotated(angles));
+        self
+    }
 
-        for line in lines {
-            if line.starts_with("v ") {
-                self.read_vertex_data(line);
-            } else if line.starts_with("vt") {
-                self.read_texcoord_data(line);
-            } else if line.starts_with("vn") {
-                self.read_normal_data(line);
-            } else if line.starts_with("f") {
-                self.read_face_data(line);
+    /// Subdivides the surface `subdivisions` times and pushes it out along its normals by the
+    /// displacement map times `height`, so bumps show in silhouettes and shadows. Each level
+    /// quadruples the triangle count. The new surface gets its normals from `recompute_normals`.
+    pub fn displaced(mut self, map: &DisplacementMap, height: f32, subdivisions: u32) -> Self {
+        self.triangles = displace_triangles(&self.triangles, map, height, subdivisions);
+        smooth_normals(&mut self.triangles, DEFAULT_SMOOTH_ANGLE);
+        self.source.steps.push(MeshStep::Displaced(Arc::new(map.clone()), height, subdivisions));
+        self
+    }
+
+    /// Simplifies the surface down to about `target` triangles with `decimate_triangles`, so heavy
+    /// scans can be previewed interactively. The simplified surface gets its normals from
+    /// `recompute_normals`.
+    pub fn decimated(mut self, target: usize) -> Self {
+        self.triangles = decimate_triangles(&self.triangles, target);
+        smooth_normals(&mut self.triangles, DEFAULT_SMOOTH_ANGLE);
+        self.source.steps.push(MeshStep::Decimated(target));
+        self
+    }
+
+    /// The mesh's triangles followed by up to `count` levels of detail for `Scene::add_obj_mesh_lods`,
+    /// each simplified to half the triangles of the one before. Levels stop early once they get
+    /// small or the surface can't be simplified much further.
+    pub fn levels_of_detail(&self, count: usize) -> Vec<Vec<Triangle>> {
+        let _span = info_span!("levels_of_detail", mesh = self.name.as_str(), count).entered();
+        let mut levels = vec![self.triangles.clone()];
+        while levels.len() <= count {
+            let previous = levels.last().unwrap();
+            let target = previous.len() / 2;
+            if target < MIN_LOD_TRIANGLES {
+                break;
+            }
+            let mut level = decimate_triangles(previous, target);
+            if level.len() > previous.len() * 3 / 4 {
+                break;
             }
+            smooth_normals(&mut level, DEFAULT_SMOOTH_ANGLE);
+            levels.push(level);
         }
+        levels
     }
 
-    fn read_vertex_data(&mut self, line: &str) {
-        let components: Vec<&str> = line.split_whitespace().collect();
-        // ["v", "x", "y", "z"]
-        let new_vertex = Vec3(
-            components[1].parse().unwrap(),
-            components[2].parse().unwrap(),
-            components[3].parse().unwrap(),
-        );
-
-        self.v.push(new_vertex);
-    }
-
-    fn read_texcoord_data(&mut self, line: &str) {
-        let components: Vec<&str> = line.split_whitespace().collect();
-        // ["vt", "u", "v"]
-        let new_texcoord = Vec2(
-            components[1].parse().unwrap(),
-            components[2].parse().unwrap(),
-        );
-
-        self.vt.push(new_texcoord);
-    }
-
-    fn read_normal_data(&mut self, line: &str) {
-        let components: Vec<&str> = line.split_whitespace().collect();
-        // ["vn", "nx", "ny", "nz"]
-        let new_normal = Vec3(
-            components[1].parse().unwrap(),
-            components[2].parse().unwrap(),
-            components[3].parse().unwrap(),
-        );
-
-        self.vn.push(new_normal);
-    }
-
-    pub fn read_face_data(&mut self, line: &str) {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        // Skip the "f" prefix and then process the vertices
-        let vertex_descriptions = &parts[1..];
-    
-        // For each face, convert it into triangles
-        // Assuming the face is a quad or a polygon that needs to be triangulated as a fan
-        let first_vertex_description = vertex_descriptions[0];
-        for i in 1..vertex_descriptions.len() - 1 {
-            let mut tri = Triangle::new(); // Assuming Triangle::default() or some initializer exists
-            tri.corners[0] = self.read_corner(first_vertex_description);
-            tri.corners[1] = self.read_corner(vertex_descriptions[i]);
-            tri.corners[2] = self.read_corner(vertex_descriptions[i + 1]);
+    /// Replaces the corner normals with ones averaged from the faces meeting at each corner,
+    /// weighted by the angle each face spans there. Only faces within `smooth_angle` radians of
+    /// the corner's own face are averaged, so edges sharper than that stay creased, and an angle
+    /// of 0 shades every face flat.
+    pub fn recompute_normals(mut self, smooth_angle: f32) -> Self {
+        smooth_normals(&mut self.triangles, smooth_angle);
+        self.source.steps.push(MeshStep::Normals(smooth_angle));
+        self
+    }
+
+    fn transform_corners(&mut self, transform: impl Fn(Vec3) -> Vec3) {
+        for triangle in &mut self.triangles {
+            for corner in &mut triangle.corners {
+                *corner = transform(*corner);
+            }
+            triangle.make_centroid();
+        }
+    }
+
+
+    fn process_file_contents(&mut self, contents: &str) -> io::Result<()> {
+        for (number, line) in contents.lines().enumerate() {
+            // Anything after a # is a comment
+            let line = line.split('#').next().unwrap_or_default();
+            let mut components = line.split_whitespace();
+            let result = match components.next() {
+                Some("v") => self.read_vertex_data(components),
+                Some("vt") => self.read_texcoord_data(components),
+                Some("vn") => self.read_normal_data(components),
+                Some("f") => self.read_face_data(components),
+                // Objects, groups, materials, smoothing, lines and points don't add triangles
+                _ => Ok(()),
+            };
+            result.map_err(|message| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, message)))?;
+        }
+        Ok(())
+    }
+
+    fn read_vertex_data(&mut self, components: SplitWhitespace) -> Result<(), String> {
+        // x y z, an optional w, or x y z r g b with colors from 0 to 1
+        let values = read_numbers(components, 3, "vertex")?;
+        self.v.push(Vec3(values[0], values[1], values[2]));
+        if values.len() >= 6 {
+            let channel = |i: usize| (values[i] * 255.0).round().clamp(0.0, 255.0) as u8;
+            self.vc.push([channel(3), channel(4), channel(5)]);
+        }
+        Ok(())
+    }
+
+    fn read_texcoord_data(&mut self, components: SplitWhitespace) -> Result<(), String> {
+        // u, with v and w optional
+        let values = read_numbers(components, 1, "texture coordinate")?;
+        self.vt.push(Vec2(values[0], values.get(1).copied().unwrap_or(0.0)));
+        Ok(())
+    }
+
+    fn read_normal_data(&mut self, components: SplitWhitespace) -> Result<(), String> {
+        // nx ny nz
+        let values = read_numbers(components, 3, "normal")?;
+        self.vn.push(Vec3(values[0], values[1], values[2]));
+        Ok(())
+    }
+
+    fn read_face_data(&mut self, components: SplitWhitespace) -> Result<(), String> {
+        let indices = components.map(|description| self.read_corner(description)).collect::<Result<Vec<Corner>, String>>()?;
+        if indices.len() < 3 {
+            return Err(format!("face needs at least 3 corners, found {}", indices.len()));
+        }
+
+        // Polygons are triangulated as a fan around their first corner
+        for i in 1..indices.len() - 1 {
+            let [a, b, c] = [indices[0], indices[i], indices[i + 1]];
+            let corners = [a.0, b.0, c.0];
+            let mut tri = Triangle::new();
+            tri.corners = corners.map(|index| self.v[index]);
+            if self.vc.len() == self.v.len() {
+                tri.vertex_colors = Some(corners.map(|index| self.vc[index]));
+            }
+            // Faces with a normal at every corner shade smoothly, others flat
+            if let (Some(a), Some(b), Some(c)) = (a.2, b.2, c.2) {
+                tri.normals = Some([a, b, c].map(|index| self.vn[index].normalize()));
+            }
+            if let (Some(a), Some(b), Some(c)) = (a.1, b.1, c.1) {
+                tri.uvs = Some([a, b, c].map(|index| self.vt[index]));
+            }
             tri.color = self.color;
             tri.make_centroid();
             self.triangles.push(tri);
         }
+        Ok(())
+    }
+
+    // Indices into the vertices, texture coordinates and normals of a face corner written as v,
+    // v/vt, v//vn or v/vt/vn
+    fn read_corner(&self, vertex_description: &str) -> Result<Corner, String> {
+        let mut parts = vertex_description.split('/');
+        let vertex = resolve_index(parts.next().unwrap_or_default(), self.v.len(), "vertex")?;
+        let texcoord = parts.next().filter(|part| !part.is_empty())
+            .map(|texcoord| resolve_index(texcoord, self.vt.len(), "texture coordinate"))
+            .transpose()?;
+        let normal = parts.next().filter(|part| !part.is_empty())
+            .map(|normal| resolve_index(normal, self.vn.len(), "normal"))
+            .transpose()?;
+        if parts.next().is_some() {
+            return Err(format!("corner {:?} has more than three indices", vertex_description));
+        }
+        Ok((vertex, texcoord, normal))
+    }
+}
+
+// Indices of a face corner's vertex, texture coordinate and normal
+type Corner = (usize, Option<usize>, Option<usize>);
+
+// Corner normals averaged from the faces within `smooth_angle` of each other, see `recompute_normals`
+fn smooth_normals(triangles: &mut [Triangle], smooth_angle: f32) {
+    // Corners are matched by position, as STL files and many OBJ exports repeat shared vertices
+    let key = |corner: Vec3| [corner.0.to_bits(), corner.1.to_bits(), corner.2.to_bits()];
+    let face_normals: Vec<Vec3> = triangles.iter()
+        .map(|triangle| (triangle.corners[1] - triangle.corners[0]).cross(triangle.corners[2] - triangle.corners[0]).normalize())
+        .collect();
+    // Faces meeting at every distinct corner position, with the angle each spans there
+    let mut faces_at: HashMap<[u32; 3], Vec<(usize, f32)>> = HashMap::new();
+    for (i, triangle) in triangles.iter().enumerate() {
+        for k in 0..3 {
+            let corner = triangle.corners[k];
+            let to_next = (triangle.corners[(k + 1) % 3] - corner).normalize();
+            let to_previous = (triangle.corners[(k + 2) % 3] - corner).normalize();
+            let angle = to_next.dot(to_previous).clamp(-1.0, 1.0).acos();
+            faces_at.entry(key(corner)).or_default().push((i, angle));
+        }
+    }
+
+    let min_cosine = smooth_angle.cos();
+    for (i, triangle) in triangles.iter_mut().enumerate() {
+        let face = face_normals[i];
+        // Degenerate faces have no normal to shade with or lend their neighbours
+        if face.magnitude() == 0.0 {
+            triangle.normals = None;
+            continue;
+        }
+        triangle.normals = Some(triangle.corners.map(|corner| {
+            faces_at[&key(corner)].iter()
+                .filter(|&&(j, _)| face_normals[j].dot(face) >= min_cosine)
+                .fold(Vec3(0.0, 0.0, 0.0), |sum, &(j, angle)| sum + face_normals[j] * angle)
+                .normalize()
+        }));
     }
+}
 
-    fn read_corner(&mut self, vertex_description: &str) -> Vec3 {
-        let v_vt_vn: Vec<&str> = vertex_description.split('/').collect();
-        let v = self.v[v_vt_vn[0].parse::<usize>().unwrap() - 1];
-        // let vt = self.vt[v_vt_vn[1].parse::<usize>().unwrap() - 1];
-        // let vn =self.vn[v_vt_vn[2].parse::<usize>().unwrap() - 1];
+// The finite numbers after a directive, at least `needed` of them
+fn read_numbers(components: SplitWhitespace, needed: usize, kind: &str) -> Result<Vec<f32>, String> {
+    let values = components
+        .map(|component| component.parse::<f32>().ok().filter(|value| value.is_finite())
+            .ok_or_else(|| format!("{} component {:?} is not a finite number", kind, component)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    if values.len() < needed {
+        return Err(format!("{} needs {} numbers, found {}", kind, needed, values.len()));
+    }
+    Ok(values)
+}
 
-        return v;
+// Position in a list of `count` entries of a 1-based index, or a negative one counting back from the last
+fn resolve_index(text: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index: i64 = text.parse().map_err(|_| format!("{} index {:?} is not a whole number", kind, text))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{} index {} is outside the {} read so far", kind, index, count));
     }
-}
\ No newline at end of file
+    Ok(resolved as usize)
+}
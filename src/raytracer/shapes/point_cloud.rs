@@ -0,0 +1,109 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::{parse_ply, Vec3};
+
+/// Points sharing one slot in the object buffer. The kernel tests each of them as a sphere, and
+/// they travel to the GPU through the point buffer at 16 bytes each.
+pub const POINTS_PER_CLUSTER: usize = 16;
+
+/// A handful of neighbouring points of a point cloud, drawn as spheres of the same radius
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointCluster {
+    pub points: Vec<Vec3>,
+    /// sRGB color of each point
+    pub colors: Vec<[u8; 3]>,
+    pub radius: f32,
+    /// Tint multiplied with every point's color
+    pub color: Vec3,
+    pub centroid: Vec3,
+    pub material: usize,
+}
+
+impl PointCluster {
+    pub fn new(points: Vec<Vec3>, colors: Vec<[u8; 3]>, radius: f32) -> Self {
+        let mut cluster = Self { points, colors, radius, color: Vec3(1.0, 1.0, 1.0), centroid: Vec3(0.0, 0.0, 0.0), material: 0 };
+        cluster.make_centroid();
+        cluster
+    }
+
+    pub fn make_centroid(&mut self) {
+        let sum = self.points.iter().fold(Vec3(0.0, 0.0, 0.0), |sum, &point| sum + point);
+        self.centroid = sum / self.points.len().max(1) as f32;
+    }
+
+    /// Box around every point's sphere
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let (min, max) = self.points.iter().fold(
+            (Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY), Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)),
+            |(min, max), &point| (min.min(point), max.max(point)),
+        );
+        (min - self.radius, max + self.radius)
+    }
+}
+
+/// Reads the points of an XYZ text file, or the vertices of a PLY file, with their colors.
+/// XYZ lines hold a position optionally followed by a 0 to 255 color. Points without one are white.
+pub fn read_point_cloud(path: &str) -> io::Result<(Vec<Vec3>, Vec<[u8; 3]>)> {
+    let extension = Path::new(path).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+    if extension.as_deref() == Some("ply") {
+        let ply = parse_ply(&fs::read(path)?)?;
+        let colors = ply.colors.unwrap_or_else(|| vec![[255; 3]; ply.vertices.len()]);
+        return Ok((ply.vertices, colors));
+    }
+
+    let mut points = Vec::new();
+    let mut colors = Vec::new();
+    for line in fs::read_to_string(path)?.lines() {
+        let values: Vec<f32> = line.split([' ', '\t', ',', ';'])
+            .filter(|word| !word.is_empty())
+            .map_while(|word| word.parse().ok())
+            .collect();
+        // Headers and comments don't start with a number
+        if values.len() < 3 {
+            continue;
+        }
+        points.push(Vec3(values[0], values[1], values[2]));
+        colors.push(match values.get(3..6) {
+            Some(&[r, g, b]) => [r, g, b].map(|channel| channel.clamp(0.0, 255.0) as u8),
+            _ => [255; 3],
+        });
+    }
+    Ok((points, colors))
+}
+
+/// Groups points into clusters of neighbours by sorting them along a Morton curve,
+/// so each cluster's bounds stay tight for the BVH
+pub fn build_point_clusters(points: Vec<Vec3>, colors: Vec<[u8; 3]>, radius: f32) -> Vec<PointCluster> {
+    let (min, max) = points.iter().fold(
+        (Vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY), Vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)),
+        |(min, max), &point| (min.min(point), max.max(point)),
+    );
+    let extent = (max - min).max(Vec3(1e-6, 1e-6, 1e-6));
+
+    let mut order: Vec<(u32, usize)> = points.iter().enumerate().map(|(i, &point)| {
+        let cell = |axis: usize| (((point.axis(axis) - min.axis(axis)) / extent.axis(axis)) * 1023.0) as u32;
+        (morton_code(cell(0), cell(1), cell(2)), i)
+    }).collect();
+    order.sort_unstable();
+
+    order.chunks(POINTS_PER_CLUSTER).map(|chunk| {
+        PointCluster::new(
+            chunk.iter().map(|&(_, i)| points[i]).collect(),
+            chunk.iter().map(|&(_, i)| colors[i]).collect(),
+            radius,
+        )
+    }).collect()
+}
+
+// Interleaves the bits of three 10 bit cell coordinates
+fn morton_code(x: u32, y: u32, z: u32) -> u32 {
+    let spread = |mut v: u32| {
+        v = (v | (v << 16)) & 0x030000FF;
+        v = (v | (v << 8)) & 0x0300F00F;
+        v = (v | (v << 4)) & 0x030C30C3;
+        (v | (v << 2)) & 0x09249249
+    };
+    spread(x) | (spread(y) << 1) | (spread(z) << 2)
+}
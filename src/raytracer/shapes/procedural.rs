@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use super::{Triangle, Vec3};
+
+// Tessellated meshes built from parameters, handy for test scenes and material previews.
+// Every generator winds its triangles so the geometric normal points outwards. Counts too low
+// to close the shape are raised to the fewest that do.
+
+// Height of the teapot's control points from the base to the top of the lid's knob
+const TEAPOT_HEIGHT: f32 = 3.15;
+
+/// Sphere made of `rings` bands from pole to pole, each split into `segments` around the y axis
+pub fn uv_sphere(center: Vec3, radius: f32, segments: usize, rings: usize, color: Vec3) -> Vec<Triangle> {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let profile: Vec<(f32, f32)> = (0..=rings)
+        .map(|i| {
+            let theta = PI * i as f32 / rings as f32;
+            (radius * theta.sin(), radius * theta.cos())
+        })
+        .collect();
+    revolve(center, &profile, segments, color)
+}
+
+/// Sphere made by splitting each face of an icosahedron `subdivisions` times, giving evenly sized triangles
+pub fn icosphere(center: Vec3, radius: f32, subdivisions: usize, color: Vec3) -> Vec<Triangle> {
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    let mut vertices: Vec<Vec3> = [
+        Vec3(-1.0, t, 0.0), Vec3(1.0, t, 0.0), Vec3(-1.0, -t, 0.0), Vec3(1.0, -t, 0.0),
+        Vec3(0.0, -1.0, t), Vec3(0.0, 1.0, t), Vec3(0.0, -1.0, -t), Vec3(0.0, 1.0, -t),
+        Vec3(t, 0.0, -1.0), Vec3(t, 0.0, 1.0), Vec3(-t, 0.0, -1.0), Vec3(-t, 0.0, 1.0),
+    ].iter().map(|vertex| vertex.normalize()).collect();
+    let mut faces: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edges shared by two faces reuse the same midpoint
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize, vertices: &mut Vec<Vec3>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                vertices.push(((vertices[a] + vertices[b]) * 0.5).normalize());
+                vertices.len() - 1
+            })
+        };
+
+        let mut subdivided = Vec::with_capacity(faces.len() * 4);
+        for [a, b, c] in faces {
+            let ab = midpoint(a, b, &mut vertices);
+            let bc = midpoint(b, c, &mut vertices);
+            let ca = midpoint(c, a, &mut vertices);
+            subdivided.extend([[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]);
+        }
+        faces = subdivided;
+    }
+
+    faces.iter()
+        .map(|&[a, b, c]| {
+            let corners = [vertices[a], vertices[b], vertices[c]];
+            // Face away from the center whichever way the source table winds
+            let outward = (corners[1] - corners[0]).cross(corners[2] - corners[0]).dot(corners[0]) > 0.0;
+            let corners = if outward { corners } else { [corners[0], corners[2], corners[1]] };
+            Triangle::build_from_corners(corners.map(|corner| center + corner * radius), color)
+        })
+        .collect()
+}
+
+/// Ring around the y axis, `major_radius` from the center to the middle of a tube `minor_radius` thick
+pub fn torus(center: Vec3, major_radius: f32, minor_radius: f32, segments: usize, sides: usize, color: Vec3) -> Vec<Triangle> {
+    let (segments, sides) = (segments.max(3), sides.max(3));
+    let profile: Vec<(f32, f32)> = (0..=sides)
+        .map(|i| {
+            let angle = 2.0 * PI * (i % sides) as f32 / sides as f32;
+            (major_radius + minor_radius * angle.cos(), -minor_radius * angle.sin())
+        })
+        .collect();
+    revolve(center, &profile, segments, color)
+}
+
+/// Tube following a (p, q) torus knot, winding p times around the y axis and q times through the hole
+#[allow(clippy::too_many_arguments)]
+pub fn torus_knot(center: Vec3, radius: f32, tube_radius: f32, p: u32, q: u32, segments: usize, sides: usize, color: Vec3) -> Vec<Triangle> {
+    let (segments, sides) = (segments.max(3), sides.max(3));
+    let curve = |t: f32| {
+        let r = radius * (2.0 + (q as f32 * t).cos()) / 3.0;
+        Vec3(r * (p as f32 * t).cos(), -radius * (q as f32 * t).sin() / 3.0, r * (p as f32 * t).sin())
+    };
+
+    grid(segments, sides, color, |segment, side| {
+        let t = 2.0 * PI * (segment % segments) as f32 / segments as f32;
+        let angle = 2.0 * PI * (side % sides) as f32 / sides as f32;
+
+        // Frame around the curve, with the normal pointing away from the knot's center
+        let step = 1e-3;
+        let tangent = (curve(t + step) - curve(t - step)).normalize();
+        let binormal = tangent.cross(curve(t)).normalize();
+        let normal = binormal.cross(tangent);
+
+        center + curve(t) + (normal * angle.cos() - binormal * angle.sin()) * tube_radius
+    })
+}
+
+/// Cylinder along the y axis with hemispherical caps, `height` between the centers of the caps
+pub fn capsule(center: Vec3, radius: f32, height: f32, segments: usize, rings: usize, color: Vec3) -> Vec<Triangle> {
+    let segments = segments.max(3);
+    let cap_rings = (rings / 2).max(1);
+    let mut profile = Vec::with_capacity(2 * cap_rings + 2);
+    for i in 0..=cap_rings {
+        let theta = PI / 2.0 * i as f32 / cap_rings as f32;
+        profile.push((radius * theta.sin(), height / 2.0 + radius * theta.cos()));
+    }
+    for i in 0..=cap_rings {
+        let theta = PI / 2.0 + PI / 2.0 * i as f32 / cap_rings as f32;
+        profile.push((radius * theta.sin(), -height / 2.0 + radius * theta.cos()));
+    }
+    revolve(center, &profile, segments, color)
+}
+
+/// Newell's Utah teapot standing upright, `height` from its base to the top of the lid's knob,
+/// with the body's axis through `center` halfway up and the spout along +x. Each of its 32
+/// Bezier patches is split into a `resolution` x `resolution` grid.
+pub fn teapot(center: Vec3, height: f32, resolution: usize, color: Vec3) -> Vec<Triangle> {
+    let resolution = resolution.max(1);
+    let scale = height / TEAPOT_HEIGHT;
+    TEAPOT_PATCHES.iter()
+        .flat_map(|patch| {
+            // Turned from z up to y up
+            let control = patch.map(|i| {
+                let [x, y, z] = TEAPOT_POINTS[i as usize];
+                Vec3(x, z - TEAPOT_HEIGHT / 2.0, -y) * scale
+            });
+            grid(resolution, resolution, color, move |column, row| {
+                center + bezier_patch(&control, column as f32 / resolution as f32, row as f32 / resolution as f32)
+            })
+        })
+        .collect()
+}
+
+// Point of a bicubic Bezier patch, `u` along each row of control points and `v` across the rows
+fn bezier_patch(control: &[Vec3; 16], u: f32, v: f32) -> Vec3 {
+    let rows = [0, 1, 2, 3].map(|row| bezier_curve([0, 1, 2, 3].map(|column| control[row * 4 + column]), u));
+    bezier_curve(rows, v)
+}
+
+// De Casteljau's construction, which keeps a run of equal control points exactly in place, so
+// patches closing to a point like the lid's knob leave no slivers there
+fn bezier_curve(points: [Vec3; 4], t: f32) -> Vec3 {
+    let lerp = |a: Vec3, b: Vec3| a + (b - a) * t;
+    let [a, b, c] = [lerp(points[0], points[1]), lerp(points[1], points[2]), lerp(points[2], points[3])];
+    lerp(lerp(a, b), lerp(b, c))
+}
+
+// Spins a (distance from the y axis, height) profile around the y axis. The profile runs
+// from top to bottom on the outside of the shape.
+fn revolve(center: Vec3, profile: &[(f32, f32)], segments: usize, color: Vec3) -> Vec<Triangle> {
+    grid(segments, profile.len() - 1, color, |segment, row| {
+        let angle = 2.0 * PI * (segment % segments) as f32 / segments as f32;
+        let (distance, height) = profile[row];
+        center + Vec3(distance * angle.cos(), height, distance * angle.sin())
+    })
+}
+
+// Two triangles per cell of a columns x rows grid of points. Cells that collapse to a line,
+// like the ones touching a sphere's poles, only produce their non-degenerate triangle.
+fn grid(columns: usize, rows: usize, color: Vec3, point: impl Fn(usize, usize) -> Vec3) -> Vec<Triangle> {
+    let mut triangles = Vec::with_capacity(2 * columns * rows);
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let a = point(column, row);
+            let b = point(column + 1, row);
+            let c = point(column, row + 1);
+            let d = point(column + 1, row + 1);
+
+            for corners in [[a, b, c], [b, d, c]] {
+                // Relative to the edge lengths, since sin(PI) leaves the poles a hair apart
+                let (edge_1, edge_2) = (corners[1] - corners[0], corners[2] - corners[0]);
+                if edge_1.cross(edge_2).magnitude() > 1e-6 * edge_1.magnitude() * edge_2.magnitude() {
+                    triangles.push(Triangle::build_from_corners(corners, color));
+                }
+            }
+        }
+    }
+
+    triangles
+}
+
+// Newell's Utah teapot, public domain: 32 bicubic Bezier patches over 306 control points, z up.
+// Each patch lists its control points row by row. Points 204, 205, 215 and 222, a hair off the
+// lid's tip, are in the original data but no patch uses them.
+const TEAPOT_PATCHES: [[u16; 16]; 32] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [3, 16, 17, 18, 7, 19, 20, 21, 11, 22, 23, 24, 15, 25, 26, 27],
+    [18, 28, 29, 30, 21, 31, 32, 33, 24, 34, 35, 36, 27, 37, 38, 39],
+    [30, 40, 41, 0, 33, 42, 43, 4, 36, 44, 45, 8, 39, 46, 47, 12],
+    [12, 13, 14, 15, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59],
+    [15, 25, 26, 27, 51, 60, 61, 62, 55, 63, 64, 65, 59, 66, 67, 68],
+    [27, 37, 38, 39, 62, 69, 70, 71, 65, 72, 73, 74, 68, 75, 76, 77],
+    [39, 46, 47, 12, 71, 78, 79, 48, 74, 80, 81, 52, 77, 82, 83, 56],
+    [56, 57, 58, 59, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95],
+    [59, 66, 67, 68, 87, 96, 97, 98, 91, 99, 100, 101, 95, 102, 103, 104],
+    [68, 75, 76, 77, 98, 105, 106, 107, 101, 108, 109, 110, 104, 111, 112, 113],
+    [77, 82, 83, 56, 107, 114, 115, 84, 110, 116, 117, 88, 113, 118, 119, 92],
+    [120, 121, 122, 123, 124, 125, 126, 127, 128, 129, 130, 131, 132, 133, 134, 135],
+    [123, 136, 137, 120, 127, 138, 139, 124, 131, 140, 141, 128, 135, 142, 143, 132],
+    [132, 133, 134, 135, 144, 145, 146, 147, 148, 149, 150, 151, 68, 152, 153, 154],
+    [135, 142, 143, 132, 147, 155, 156, 144, 151, 157, 158, 148, 154, 159, 160, 68],
+    [161, 162, 163, 164, 165, 166, 167, 168, 169, 170, 171, 172, 173, 174, 175, 176],
+    [164, 177, 178, 161, 168, 179, 180, 165, 172, 181, 182, 169, 176, 183, 184, 173],
+    [173, 174, 175, 176, 185, 186, 187, 188, 189, 190, 191, 192, 193, 194, 195, 196],
+    [176, 183, 184, 173, 188, 197, 198, 185, 192, 199, 200, 189, 196, 201, 202, 193],
+    [203, 203, 203, 203, 206, 207, 208, 209, 210, 210, 210, 210, 211, 212, 213, 214],
+    [203, 203, 203, 203, 209, 216, 217, 218, 210, 210, 210, 210, 214, 219, 220, 221],
+    [203, 203, 203, 203, 218, 223, 224, 225, 210, 210, 210, 210, 221, 226, 227, 228],
+    [203, 203, 203, 203, 225, 229, 230, 206, 210, 210, 210, 210, 228, 231, 232, 211],
+    [211, 212, 213, 214, 233, 234, 235, 236, 237, 238, 239, 240, 241, 242, 243, 244],
+    [214, 219, 220, 221, 236, 245, 246, 247, 240, 248, 249, 250, 244, 251, 252, 253],
+    [221, 226, 227, 228, 247, 254, 255, 256, 250, 257, 258, 259, 253, 260, 261, 262],
+    [228, 231, 232, 211, 256, 263, 264, 233, 259, 265, 266, 237, 262, 267, 268, 241],
+    [269, 269, 269, 269, 278, 279, 280, 281, 274, 275, 276, 277, 270, 271, 272, 273],
+    [269, 269, 269, 269, 281, 288, 289, 290, 277, 285, 286, 287, 273, 282, 283, 284],
+    [269, 269, 269, 269, 290, 297, 298, 299, 287, 294, 295, 296, 284, 291, 292, 293],
+    [269, 269, 269, 269, 299, 304, 305, 278, 296, 302, 303, 274, 293, 300, 301, 270],
+];
+
+const TEAPOT_POINTS: [[f32; 3]; 306] = [
+    [1.4, 0.0, 2.4], [1.4, -0.784, 2.4], [0.784, -1.4, 2.4], [0.0, -1.4, 2.4],
+    [1.3375, 0.0, 2.53125], [1.3375, -0.749, 2.53125], [0.749, -1.3375, 2.53125], [0.0, -1.3375, 2.53125],
+    [1.4375, 0.0, 2.53125], [1.4375, -0.805, 2.53125], [0.805, -1.4375, 2.53125], [0.0, -1.4375, 2.53125],
+    [1.5, 0.0, 2.4], [1.5, -0.84, 2.4], [0.84, -1.5, 2.4], [0.0, -1.5, 2.4],
+    [-0.784, -1.4, 2.4], [-1.4, -0.784, 2.4], [-1.4, 0.0, 2.4], [-0.749, -1.3375, 2.53125],
+    [-1.3375, -0.749, 2.53125], [-1.3375, 0.0, 2.53125], [-0.805, -1.4375, 2.53125], [-1.4375, -0.805, 2.53125],
+    [-1.4375, 0.0, 2.53125], [-0.84, -1.5, 2.4], [-1.5, -0.84, 2.4], [-1.5, 0.0, 2.4],
+    [-1.4, 0.784, 2.4], [-0.784, 1.4, 2.4], [0.0, 1.4, 2.4], [-1.3375, 0.749, 2.53125],
+    [-0.749, 1.3375, 2.53125], [0.0, 1.3375, 2.53125], [-1.4375, 0.805, 2.53125], [-0.805, 1.4375, 2.53125],
+    [0.0, 1.4375, 2.53125], [-1.5, 0.84, 2.4], [-0.84, 1.5, 2.4], [0.0, 1.5, 2.4],
+    [0.784, 1.4, 2.4], [1.4, 0.784, 2.4], [0.749, 1.3375, 2.53125], [1.3375, 0.749, 2.53125],
+    [0.805, 1.4375, 2.53125], [1.4375, 0.805, 2.53125], [0.84, 1.5, 2.4], [1.5, 0.84, 2.4],
+    [1.75, 0.0, 1.875], [1.75, -0.98, 1.875], [0.98, -1.75, 1.875], [0.0, -1.75, 1.875],
+    [2.0, 0.0, 1.35], [2.0, -1.12, 1.35], [1.12, -2.0, 1.35], [0.0, -2.0, 1.35],
+    [2.0, 0.0, 0.9], [2.0, -1.12, 0.9], [1.12, -2.0, 0.9], [0.0, -2.0, 0.9],
+    [-0.98, -1.75, 1.875], [-1.75, -0.98, 1.875], [-1.75, 0.0, 1.875], [-1.12, -2.0, 1.35],
+    [-2.0, -1.12, 1.35], [-2.0, 0.0, 1.35], [-1.12, -2.0, 0.9], [-2.0, -1.12, 0.9],
+    [-2.0, 0.0, 0.9], [-1.75, 0.98, 1.875], [-0.98, 1.75, 1.875], [0.0, 1.75, 1.875],
+    [-2.0, 1.12, 1.35], [-1.12, 2.0, 1.35], [0.0, 2.0, 1.35], [-2.0, 1.12, 0.9],
+    [-1.12, 2.0, 0.9], [0.0, 2.0, 0.9], [0.98, 1.75, 1.875], [1.75, 0.98, 1.875],
+    [1.12, 2.0, 1.35], [2.0, 1.12, 1.35], [1.12, 2.0, 0.9], [2.0, 1.12, 0.9],
+    [2.0, 0.0, 0.45], [2.0, -1.12, 0.45], [1.12, -2.0, 0.45], [0.0, -2.0, 0.45],
+    [1.5, 0.0, 0.225], [1.5, -0.84, 0.225], [0.84, -1.5, 0.225], [0.0, -1.5, 0.225],
+    [1.5, 0.0, 0.15], [1.5, -0.84, 0.15], [0.84, -1.5, 0.15], [0.0, -1.5, 0.15],
+    [-1.12, -2.0, 0.45], [-2.0, -1.12, 0.45], [-2.0, 0.0, 0.45], [-0.84, -1.5, 0.225],
+    [-1.5, -0.84, 0.225], [-1.5, 0.0, 0.225], [-0.84, -1.5, 0.15], [-1.5, -0.84, 0.15],
+    [-1.5, 0.0, 0.15], [-2.0, 1.12, 0.45], [-1.12, 2.0, 0.45], [0.0, 2.0, 0.45],
+    [-1.5, 0.84, 0.225], [-0.84, 1.5, 0.225], [0.0, 1.5, 0.225], [-1.5, 0.84, 0.15],
+    [-0.84, 1.5, 0.15], [0.0, 1.5, 0.15], [1.12, 2.0, 0.45], [2.0, 1.12, 0.45],
+    [0.84, 1.5, 0.225], [1.5, 0.84, 0.225], [0.84, 1.5, 0.15], [1.5, 0.84, 0.15],
+    [-1.6, 0.0, 2.025], [-1.6, -0.3, 2.025], [-1.5, -0.3, 2.25], [-1.5, 0.0, 2.25],
+    [-2.3, 0.0, 2.025], [-2.3, -0.3, 2.025], [-2.5, -0.3, 2.25], [-2.5, 0.0, 2.25],
+    [-2.7, 0.0, 2.025], [-2.7, -0.3, 2.025], [-3.0, -0.3, 2.25], [-3.0, 0.0, 2.25],
+    [-2.7, 0.0, 1.8], [-2.7, -0.3, 1.8], [-3.0, -0.3, 1.8], [-3.0, 0.0, 1.8],
+    [-1.5, 0.3, 2.25], [-1.6, 0.3, 2.025], [-2.5, 0.3, 2.25], [-2.3, 0.3, 2.025],
+    [-3.0, 0.3, 2.25], [-2.7, 0.3, 2.025], [-3.0, 0.3, 1.8], [-2.7, 0.3, 1.8],
+    [-2.7, 0.0, 1.575], [-2.7, -0.3, 1.575], [-3.0, -0.3, 1.35], [-3.0, 0.0, 1.35],
+    [-2.5, 0.0, 1.125], [-2.5, -0.3, 1.125], [-2.65, -0.3, 0.9375], [-2.65, 0.0, 0.9375],
+    [-2.0, -0.3, 0.9], [-1.9, -0.3, 0.6], [-1.9, 0.0, 0.6], [-3.0, 0.3, 1.35],
+    [-2.7, 0.3, 1.575], [-2.65, 0.3, 0.9375], [-2.5, 0.3, 1.125], [-1.9, 0.3, 0.6],
+    [-2.0, 0.3, 0.9], [1.7, 0.0, 1.425], [1.7, -0.66, 1.425], [1.7, -0.66, 0.6],
+    [1.7, 0.0, 0.6], [2.6, 0.0, 1.425], [2.6, -0.66, 1.425], [3.1, -0.66, 0.825],
+    [3.1, 0.0, 0.825], [2.3, 0.0, 2.1], [2.3, -0.25, 2.1], [2.4, -0.25, 2.025],
+    [2.4, 0.0, 2.025], [2.7, 0.0, 2.4], [2.7, -0.25, 2.4], [3.3, -0.25, 2.4],
+    [3.3, 0.0, 2.4], [1.7, 0.66, 0.6], [1.7, 0.66, 1.425], [3.1, 0.66, 0.825],
+    [2.6, 0.66, 1.425], [2.4, 0.25, 2.025], [2.3, 0.25, 2.1], [3.3, 0.25, 2.4],
+    [2.7, 0.25, 2.4], [2.8, 0.0, 2.475], [2.8, -0.25, 2.475], [3.525, -0.25, 2.49375],
+    [3.525, 0.0, 2.49375], [2.9, 0.0, 2.475], [2.9, -0.15, 2.475], [3.45, -0.15, 2.5125],
+    [3.45, 0.0, 2.5125], [2.8, 0.0, 2.4], [2.8, -0.15, 2.4], [3.2, -0.15, 2.4],
+    [3.2, 0.0, 2.4], [3.525, 0.25, 2.49375], [2.8, 0.25, 2.475], [3.45, 0.15, 2.5125],
+    [2.9, 0.15, 2.475], [3.2, 0.15, 2.4], [2.8, 0.15, 2.4], [0.0, 0.0, 3.15],
+    [0.0, -0.002, 3.15], [0.002, 0.0, 3.15], [0.8, 0.0, 3.15], [0.8, -0.45, 3.15],
+    [0.45, -0.8, 3.15], [0.0, -0.8, 3.15], [0.0, 0.0, 2.85], [0.2, 0.0, 2.7],
+    [0.2, -0.112, 2.7], [0.112, -0.2, 2.7], [0.0, -0.2, 2.7], [-0.002, 0.0, 3.15],
+    [-0.45, -0.8, 3.15], [-0.8, -0.45, 3.15], [-0.8, 0.0, 3.15], [-0.112, -0.2, 2.7],
+    [-0.2, -0.112, 2.7], [-0.2, 0.0, 2.7], [0.0, 0.002, 3.15], [-0.8, 0.45, 3.15],
+    [-0.45, 0.8, 3.15], [0.0, 0.8, 3.15], [-0.2, 0.112, 2.7], [-0.112, 0.2, 2.7],
+    [0.0, 0.2, 2.7], [0.45, 0.8, 3.15], [0.8, 0.45, 3.15], [0.112, 0.2, 2.7],
+    [0.2, 0.112, 2.7], [0.4, 0.0, 2.55], [0.4, -0.224, 2.55], [0.224, -0.4, 2.55],
+    [0.0, -0.4, 2.55], [1.3, 0.0, 2.55], [1.3, -0.728, 2.55], [0.728, -1.3, 2.55],
+    [0.0, -1.3, 2.55], [1.3, 0.0, 2.4], [1.3, -0.728, 2.4], [0.728, -1.3, 2.4],
+    [0.0, -1.3, 2.4], [-0.224, -0.4, 2.55], [-0.4, -0.224, 2.55], [-0.4, 0.0, 2.55],
+    [-0.728, -1.3, 2.55], [-1.3, -0.728, 2.55], [-1.3, 0.0, 2.55], [-0.728, -1.3, 2.4],
+    [-1.3, -0.728, 2.4], [-1.3, 0.0, 2.4], [-0.4, 0.224, 2.55], [-0.224, 0.4, 2.55],
+    [0.0, 0.4, 2.55], [-1.3, 0.728, 2.55], [-0.728, 1.3, 2.55], [0.0, 1.3, 2.55],
+    [-1.3, 0.728, 2.4], [-0.728, 1.3, 2.4], [0.0, 1.3, 2.4], [0.224, 0.4, 2.55],
+    [0.4, 0.224, 2.55], [0.728, 1.3, 2.55], [1.3, 0.728, 2.55], [0.728, 1.3, 2.4],
+    [1.3, 0.728, 2.4], [0.0, 0.0, 0.0], [1.5, 0.0, 0.15], [1.5, 0.84, 0.15],
+    [0.84, 1.5, 0.15], [0.0, 1.5, 0.15], [1.5, 0.0, 0.075], [1.5, 0.84, 0.075],
+    [0.84, 1.5, 0.075], [0.0, 1.5, 0.075], [1.425, 0.0, 0.0], [1.425, 0.798, 0.0],
+    [0.798, 1.425, 0.0], [0.0, 1.425, 0.0], [-0.84, 1.5, 0.15], [-1.5, 0.84, 0.15],
+    [-1.5, 0.0, 0.15], [-0.84, 1.5, 0.075], [-1.5, 0.84, 0.075], [-1.5, 0.0, 0.075],
+    [-0.798, 1.425, 0.0], [-1.425, 0.798, 0.0], [-1.425, 0.0, 0.0], [-1.5, -0.84, 0.15],
+    [-0.84, -1.5, 0.15], [0.0, -1.5, 0.15], [-1.5, -0.84, 0.075], [-0.84, -1.5, 0.075],
+    [0.0, -1.5, 0.075], [-1.425, -0.798, 0.0], [-0.798, -1.425, 0.0], [0.0, -1.425, 0.0],
+    [0.84, -1.5, 0.15], [1.5, -0.84, 0.15], [0.84, -1.5, 0.075], [1.5, -0.84, 0.075],
+    [0.798, -1.425, 0.0], [1.425, -0.798, 0.0],
+];
@@ -0,0 +1,41 @@
+use super::Vec3;
+
+/// Parallelogram spanned by two edges from one corner, intersected directly on the GPU
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quad {
+    pub corner: Vec3,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+    pub color: Vec3,
+    pub centroid: Vec3,
+    pub material: usize,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3, color: Vec3) -> Self {
+        Self {
+            corner,
+            edge_u,
+            edge_v,
+            color,
+            centroid: corner + (edge_u + edge_v) * 0.5,
+            material: 0,
+        }
+    }
+
+    // Corners in order around the edge, so consecutive corners share a side
+    pub fn corners(&self) -> [Vec3; 4] {
+        [
+            self.corner,
+            self.corner + self.edge_u,
+            self.corner + self.edge_u + self.edge_v,
+            self.corner + self.edge_v,
+        ]
+    }
+
+    // Moves the quad (and the centroid) by the given offset
+    pub fn translate(&mut self, offset: Vec3) {
+        self.corner += offset;
+        self.centroid += offset;
+    }
+}
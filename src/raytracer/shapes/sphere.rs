@@ -1,15 +1,18 @@
-use super::Vec3;
+use super::{Texture, Vec3};
 
 // Sphere struct that implements the Shape trait
+#[derive(Debug, Clone, PartialEq)]
 pub struct Sphere {
     pub center: Vec3,
     pub color: Vec3,
     pub radius: f32,
+    pub texture: Texture, // Mapped with latitude/longitude UVs
+    pub material: usize,
 }
 
 impl Sphere {
     // Sphere constructor
     pub fn new(center: Vec3, color: Vec3, radius: f32) -> Self {
-        Self { center, color, radius }
+        Self { center, color, radius, texture: Texture::Solid, material: 0 }
     }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use winit::dpi::PhysicalSize;
-use super::{Triangle, Vec3};
+use super::{Quad, Vec3};
 
 #[derive(Debug, Clone)]
 pub struct Square {
@@ -7,7 +7,7 @@ pub struct Square {
     pub size: PhysicalSize<f32>,
     pub color: Vec3,
     pub orientation: f32, // Orientation in radians around the Y-axis
-    pub triangles: Vec<Triangle>,
+    pub quad: Quad,
 }
 
 impl Square {
@@ -17,15 +17,15 @@ impl Square {
             size: PhysicalSize::new(width, height), 
             color,
             orientation, // Set orientation
-            triangles: Vec::new(),
+            quad: Quad::new(center, Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), color),
         };
 
-        square.calculate_triangles();
+        square.calculate_quad();
 
         square
     }
 
-    fn calculate_triangles(&mut self) {
+    fn calculate_quad(&mut self) {
         let width = self.size.width;
         let height = self.size.height;
 
@@ -48,11 +48,7 @@ impl Square {
             *corner = Vec3(rotated_x, corner.1, rotated_z) + self.center;
         }
 
-        // Create two triangles from these corners and store them
-        let triangle1 = Triangle::build_from_corners([corners[0], corners[2], corners[1]], self.color);
-        let triangle2 = Triangle::build_from_corners([corners[2], corners[3], corners[1]], self.color);
-
-        self.triangles.push(triangle1);
-        self.triangles.push(triangle2);
+        // Span a single quad from the bottom left corner, facing the same way the triangle pair used to
+        self.quad = Quad::new(corners[2], corners[3] - corners[2], corners[0] - corners[2], self.color);
     }
 }
@@ -1,10 +1,23 @@
-use super::Vec3;
+use super::{Vec2, Vec3};
 
-#[derive(Debug, Clone)]
+/// sRGB colors of a triangle's three corners
+pub type CornerColors = [[u8; 3]; 3];
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Triangle {
     pub corners: [Vec3; 3],
     pub color: Vec3,
+    /// sRGB color of each corner, as scanned meshes carry it. It is blended across the face
+    /// and multiplied with `color`.
+    pub vertex_colors: Option<CornerColors>,
+    /// Shading normal of each corner, blended across the face so meshes shade smoothly. Without
+    /// them the face is shaded with its own normal.
+    pub normals: Option<[Vec3; 3]>,
+    /// Texture coordinate of each corner, read from OBJ files that have them. Only baking uses
+    /// them, rendering doesn't.
+    pub uvs: Option<[Vec2; 3]>,
     pub centroid: Vec3,
+    pub material: usize,
 }
 
 impl Triangle {
@@ -15,7 +28,11 @@ impl Triangle {
         Self {
             corners,
             color,
-            centroid
+            vertex_colors: None,
+            normals: None,
+            uvs: None,
+            centroid,
+            material: 0,
         }
     }
     // Constructor to create a triangle directly from its corners and color
@@ -29,7 +46,11 @@ impl Triangle {
         Self {
             corners,
             color,
+            vertex_colors: None,
+            normals: None,
+            uvs: None,
             centroid,
+            material: 0,
         }
     }
 
@@ -52,8 +73,20 @@ impl Triangle {
         Self {
             corners,
             color,
+            vertex_colors: None,
+            normals: None,
+            uvs: None,
             centroid,
+            material: 0,
+        }
+    }
+
+    // Moves all corners (and the centroid) by the given offset
+    pub fn translate(&mut self, offset: Vec3) {
+        for corner in &mut self.corners {
+            *corner += offset;
         }
+        self.centroid += offset;
     }
 
     pub fn make_centroid(&mut self) {
@@ -63,4 +96,10 @@ impl Triangle {
             (self.corners[0].2 + self.corners[1].2 + self.corners[2].2) / 3.0
         );
     }
-}
\ No newline at end of file
+}
+
+impl Default for Triangle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,6 +1,7 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vec3(pub f32, pub f32, pub f32);
 
+#[allow(clippy::should_implement_trait)]
 impl Vec3 {
     // Add two vectors
     pub fn add(self, other: Vec3) -> Vec3 {
@@ -60,11 +61,40 @@ impl Vec3 {
             self
         }
     }
+
+    // Component along an axis, 0 for x, 1 for y and 2 for z
+    pub fn axis(self, axis: usize) -> f32 {
+        match axis {
+            0 => self.0,
+            1 => self.1,
+            _ => self.2,
+        }
+    }
+
+    // Copy of the vector with the component along an axis replaced
+    pub fn with_axis(self, axis: usize, value: f32) -> Vec3 {
+        match axis {
+            0 => Vec3(value, self.1, self.2),
+            1 => Vec3(self.0, value, self.2),
+            _ => Vec3(self.0, self.1, value),
+        }
+    }
+
+    // Component-wise minimum of two vectors
+    pub fn min(self, other: Vec3) -> Vec3 {
+        Vec3(self.0.min(other.0), self.1.min(other.1), self.2.min(other.2))
+    }
+
+    // Component-wise maximum of two vectors
+    pub fn max(self, other: Vec3) -> Vec3 {
+        Vec3(self.0.max(other.0), self.1.max(other.1), self.2.max(other.2))
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vec2(pub f32, pub f32);
 
+#[allow(clippy::should_implement_trait)]
 impl Vec2 {
     // Add two vectors
     pub fn add(self, other: Vec2) -> Vec2 {
@@ -0,0 +1,202 @@
+use std::fmt;
+
+use super::{surface_area, Node, Object, Scene, LAYER_HEIGHT, LAYER_WIDTH, SceneBuffer, TEXTURE_REGION_STRIDE, TRAVERSAL_COST};
+
+/// Size of the scene and quality of its BVH, for judging how an imported asset will perform
+#[derive(Debug, Clone, Default)]
+pub struct SceneStats {
+    pub spheres: usize,
+    pub triangles: usize,
+    pub quads: usize,
+    pub billboards: usize,
+    /// Points of point clouds, grouped into clusters that take one object slot each
+    pub points: usize,
+    pub point_clusters: usize,
+    pub curves: usize,
+    /// Placed copies of shared meshes, whose triangles are counted once per mesh
+    pub instances: usize,
+
+    pub nodes: usize,
+    pub leaves: usize,
+    /// Levels below the root of the deepest leaf
+    pub bvh_depth: usize,
+    pub largest_leaf: usize,
+    /// Objects listed in leaves, more than the object count when spatial splits duplicated some
+    pub object_references: usize,
+    pub bvh_cost: BvhCost,
+
+    /// Estimated sizes in bytes of the GPU buffers built from the scene. The color, AOV and
+    /// accumulation buffers follow the window size and are left out.
+    pub object_buffer_bytes: u64,
+    pub node_buffer_bytes: u64,
+    pub object_index_buffer_bytes: u64,
+    pub material_buffer_bytes: u64,
+    pub point_buffer_bytes: u64,
+    pub texture_array_bytes: u64,
+}
+
+/// Expected cost of tracing rays through a BVH and how much its siblings overlap, to compare
+/// build strategies by number rather than by frame time
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BvhCost {
+    /// Expected cost of tracing a ray through the tree under the surface area heuristic,
+    /// in units of object intersections
+    pub sah_cost: f32,
+    /// The part of `sah_cost` spent visiting inner nodes
+    pub traversal_cost: f32,
+    /// The part of `sah_cost` spent intersecting the objects of leaves
+    pub intersection_cost: f32,
+    /// Area where sibling nodes overlap summed over the tree, relative to the root's area.
+    /// Roughly how many extra nodes a ray visits because both children contain it.
+    pub sibling_overlap: f32,
+    /// Inner nodes whose children overlap at all
+    pub overlapping_nodes: usize,
+    /// Largest overlap of two siblings relative to their parent's area, 1 for children that
+    /// are as large as their parent
+    pub worst_overlap: f32,
+}
+
+impl SceneStats {
+    pub fn total_gpu_bytes(&self) -> u64 {
+        self.object_buffer_bytes
+            + self.node_buffer_bytes
+            + self.object_index_buffer_bytes
+            + self.material_buffer_bytes
+            + self.point_buffer_bytes
+            + self.texture_array_bytes
+    }
+}
+
+impl Scene {
+    /// Counts the scene's primitives and measures its BVH, which should have been built already
+    pub fn stats(&self) -> SceneStats {
+        let mut stats = SceneStats::default();
+
+        for object in &self.objects {
+            match object {
+                Object::Sphere(_) => stats.spheres += 1,
+                Object::Triangle(_) => stats.triangles += 1,
+                Object::Quad(_) => stats.quads += 1,
+                Object::Billboard(_) => stats.billboards += 1,
+                Object::Points(cluster) => {
+                    stats.point_clusters += 1;
+                    stats.points += cluster.points.len();
+                },
+                Object::Curve(_) => stats.curves += 1,
+                Object::Instance(_) => stats.instances += 1,
+            }
+        }
+        stats.triangles += self.prototypes.iter().map(|prototype| prototype.primitives.len()).sum::<usize>();
+
+        let nodes = &self.nodes[..self.nodes_used];
+        stats.nodes = nodes.len();
+        stats.object_references = self.object_indices.len();
+        if !nodes.is_empty() {
+            // Stack of (node index, depth), children sit next to each other at left_child
+            let mut stack = vec![(0, 0)];
+            while let Some((index, depth)) = stack.pop() {
+                let node: &Node = &nodes[index];
+                stats.bvh_depth = stats.bvh_depth.max(depth);
+                // The root of an empty scene is a leaf without objects or children
+                if node.object_count > 0 || node.left_child < 0 {
+                    stats.leaves += 1;
+                    stats.largest_leaf = stats.largest_leaf.max(node.object_count);
+                } else {
+                    stack.push((node.left_child as usize, depth + 1));
+                    stack.push((node.left_child as usize + 1, depth + 1));
+                }
+            }
+        }
+        stats.bvh_cost = self.bvh_cost();
+
+        stats.object_buffer_bytes = SceneBuffer::Objects.size(self);
+        stats.node_buffer_bytes = SceneBuffer::Nodes.size(self);
+        stats.object_index_buffer_bytes = SceneBuffer::ObjectIndices.size(self);
+        stats.material_buffer_bytes = SceneBuffer::Materials.size(self);
+        stats.point_buffer_bytes = SceneBuffer::Points.size(self);
+        // At most one RGBA8 layer per image plus the label atlas, fewer when small images share one,
+        // and a placeholder layer when there are none. The mip chain adds another third.
+        // Adapters with BC compression store a quarter of this.
+        let layers = (self.image_paths.len() + !self.labels.is_empty() as usize).max(1);
+        // The lookup table has an entry per texture
+        stats.texture_array_bytes = 4 * LAYER_WIDTH as u64 * LAYER_HEIGHT as u64 * layers as u64 * 4 / 3
+            + TEXTURE_REGION_STRIDE * layers as u64;
+
+        stats
+    }
+
+    /// Measures the BVH as built, zero before it is
+    pub fn bvh_cost(&self) -> BvhCost {
+        let mut cost = BvhCost::default();
+        let nodes = &self.nodes[..self.nodes_used];
+        let Some(root) = nodes.first() else { return cost };
+        let root_area = root.surface_area();
+        // Every node is reached from its parent, children sit next to each other at left_child
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node: &Node = &nodes[index];
+            let relative_area = if root_area > 0.0 { node.surface_area() / root_area } else { 1.0 };
+            if node.object_count > 0 || node.left_child < 0 {
+                cost.intersection_cost += relative_area * node.object_count as f32;
+                continue;
+            }
+
+            let left = &nodes[node.left_child as usize];
+            let right = &nodes[node.left_child as usize + 1];
+            cost.traversal_cost += relative_area * TRAVERSAL_COST;
+            let overlap = overlap_area(left, right);
+            if overlap > 0.0 {
+                cost.overlapping_nodes += 1;
+                if root_area > 0.0 {
+                    cost.sibling_overlap += overlap / root_area;
+                }
+                if node.surface_area() > 0.0 {
+                    cost.worst_overlap = cost.worst_overlap.max(overlap / node.surface_area());
+                }
+            }
+            stack.push(node.left_child as usize);
+            stack.push(node.left_child as usize + 1);
+        }
+        cost.sah_cost = cost.traversal_cost + cost.intersection_cost;
+        cost
+    }
+}
+
+impl fmt::Display for SceneStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Objects: {} spheres, {} triangles, {} quads, {} billboards, {} points in {} clusters, {} curves, {} instances",
+            self.spheres, self.triangles, self.quads, self.billboards, self.points, self.point_clusters, self.curves, self.instances)?;
+        writeln!(f, "BVH: {} nodes, {} leaves, depth {}, largest leaf {}, {} object references",
+            self.nodes, self.leaves, self.bvh_depth, self.largest_leaf, self.object_references)?;
+        writeln!(f, "BVH quality: {}", self.bvh_cost)?;
+        writeln!(f, "GPU memory: objects {}, nodes {}, object indices {}, materials {}, points {}, textures {}",
+            format_bytes(self.object_buffer_bytes),
+            format_bytes(self.node_buffer_bytes),
+            format_bytes(self.object_index_buffer_bytes),
+            format_bytes(self.material_buffer_bytes),
+            format_bytes(self.point_buffer_bytes),
+            format_bytes(self.texture_array_bytes))?;
+        write!(f, "GPU memory total: {}", format_bytes(self.total_gpu_bytes()))
+    }
+}
+
+impl fmt::Display for BvhCost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SAH cost {:.2} ({:.2} traversal, {:.2} intersection), sibling overlap {:.2} over {} nodes, worst {:.0}%",
+            self.sah_cost, self.traversal_cost, self.intersection_cost, self.sibling_overlap, self.overlapping_nodes, self.worst_overlap * 100.0)
+    }
+}
+
+fn overlap_area(a: &Node, b: &Node) -> f32 {
+    surface_area(a.min_corner.max(b.min_corner), a.max_corner.min(b.max_corner))
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    if bytes >= 1 << 20 {
+        format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+    } else if bytes >= 1 << 10 {
+        format!("{:.1} KiB", bytes as f64 / (1 << 10) as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
@@ -0,0 +1,72 @@
+use super::{rotate_vector_around_axis, Vec3};
+
+/// A move, turn and uniform scaling of points, taking `point` to
+/// `rotation * point * scale + translation`. Objects keep the one they went through since they
+/// were added, which is all their edits change about them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub scale: f32,
+    /// Columns of the rotation matrix, the directions the x, y and z axes are turned to
+    pub rotation: [Vec3; 3],
+    pub translation: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            rotation: [Vec3(1.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), Vec3(0.0, 0.0, 1.0)],
+            translation: Vec3(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn translation(offset: Vec3) -> Self {
+        Self { translation: offset, ..Default::default() }
+    }
+
+    /// Turns points by `angle` radians around `axis` through `pivot`
+    pub fn rotation_about(pivot: Vec3, axis: Vec3, angle: f32) -> Self {
+        let rotation = Transform::default().rotation.map(|column| rotate_vector_around_axis(column, axis, angle));
+        Self::about(pivot, Self { rotation, ..Default::default() })
+    }
+
+    /// Grows or shrinks points by `factor` around `pivot`
+    pub fn scaling_about(pivot: Vec3, factor: f32) -> Self {
+        Self::about(pivot, Self { scale: factor, ..Default::default() })
+    }
+
+    /// Moves and turns a direction, which has no position, e.g. a normal
+    pub fn turn(&self, direction: Vec3) -> Vec3 {
+        let [x, y, z] = self.rotation;
+        x * direction.0 + y * direction.1 + z * direction.2
+    }
+
+    pub fn apply(&self, point: Vec3) -> Vec3 {
+        self.turn(point) * self.scale + self.translation
+    }
+
+    /// This transform followed by `next`
+    pub fn then(&self, next: Transform) -> Self {
+        Self {
+            scale: self.scale * next.scale,
+            rotation: self.rotation.map(|column| next.turn(column)),
+            translation: next.apply(self.translation),
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        // The rotation's inverse is its transpose, whose columns are the rows of this one
+        let [x, y, z] = self.rotation;
+        let rotation = [Vec3(x.0, y.0, z.0), Vec3(x.1, y.1, z.1), Vec3(x.2, y.2, z.2)];
+        let scale = 1.0 / self.scale;
+        let inverse = Self { scale, rotation, translation: Vec3(0.0, 0.0, 0.0) };
+        Self { translation: inverse.apply(self.translation) * -1.0, ..inverse }
+    }
+
+    // The transform around the origin moved to happen around `pivot` instead
+    fn about(pivot: Vec3, transform: Transform) -> Self {
+        Self::translation(pivot * -1.0).then(transform).then(Self::translation(pivot))
+    }
+}
@@ -0,0 +1,42 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Workgroup sizes of the ray tracing kernel tried against the adapter, 8x8 is not the fastest everywhere
+pub const WORKGROUP_SIZES: [(u32, u32); 4] = [(4, 4), (8, 8), (16, 8), (16, 16)];
+
+/// Size the kernel is written with, used until the adapter has been benchmarked
+pub const DEFAULT_WORKGROUP_SIZE: (u32, u32) = (8, 8);
+
+/// File the fastest size of every adapter benchmarked so far is kept in
+pub fn workgroup_cache_path() -> PathBuf {
+    std::env::temp_dir().join("rust_raytracing_wgpu_workgroups.txt")
+}
+
+// One adapter per line: width, height, then the adapter's name, which may contain spaces
+fn parse_line(line: &str) -> Option<((u32, u32), &str)> {
+    let mut words = line.splitn(3, ' ');
+    let width = words.next()?.parse().ok()?;
+    let height = words.next()?.parse().ok()?;
+    Some(((width, height), words.next()?))
+}
+
+/// Fastest workgroup size found for the adapter on an earlier run
+pub fn load_workgroup_size(path: &Path, adapter: &str) -> Option<(u32, u32)> {
+    let text = fs::read_to_string(path).ok()?;
+    text.lines()
+        .filter_map(parse_line)
+        .find(|&(_, name)| name == adapter)
+        .map(|(size, _)| size)
+}
+
+/// Records the adapter's fastest workgroup size, keeping the other adapters' entries
+pub fn save_workgroup_size(path: &Path, adapter: &str, size: (u32, u32)) -> io::Result<()> {
+    let text = fs::read_to_string(path).unwrap_or_default();
+    let mut lines: Vec<String> = text.lines()
+        .filter(|line| parse_line(line).is_some_and(|(_, name)| name != adapter))
+        .map(str::to_string)
+        .collect();
+    lines.push(format!("{} {} {}", size.0, size.1, adapter));
+    fs::write(path, lines.join("\n") + "\n")
+}
@@ -0,0 +1,50 @@
+// Ambient occlusion is baked into the texture a mesh's texture coordinates lay out, darkest where
+// other surfaces close by block the most rays.
+
+use rust_raytracing_wgpu::raytracer::{bake_ambient_occlusion, AoBake, ObjMesh, Scene, SkySource, Vec2, Vec3};
+
+// A 4 by 4 floor facing up, its texture coordinates spanning the whole texture with +x to the
+// right and -z to the top
+const FLOOR: &str = "
+v -2 0 2
+v 2 0 2
+v 2 0 -2
+v -2 0 -2
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+f 1/1 2/2 3/3 4/4
+";
+
+#[test]
+fn texture_coordinates_are_kept_on_the_triangles() {
+    let mesh = ObjMesh::parse(Vec3(1.0, 1.0, 1.0), "floor.obj", FLOOR).unwrap();
+    assert_eq!(mesh.triangles[0].uvs, Some([Vec2(0.0, 0.0), Vec2(1.0, 0.0), Vec2(1.0, 1.0)]));
+    assert_eq!(mesh.triangles[1].uvs, Some([Vec2(0.0, 0.0), Vec2(1.0, 1.0), Vec2(0.0, 1.0)]));
+
+    let without = ObjMesh::parse(Vec3(1.0, 1.0, 1.0), "floor.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3").unwrap();
+    assert_eq!(without.triangles[0].uvs, None);
+}
+
+#[test]
+fn floor_darkens_under_a_ball() {
+    let mut scene = Scene::new(2, 1.0, 1.0);
+    scene.active_sky = scene.add_sky(SkySource::Solid([255, 255, 255]));
+    let floor = scene.add_obj_mesh(ObjMesh::parse(Vec3(1.0, 1.0, 1.0), "floor.obj", FLOOR).unwrap());
+    // Resting on the floor a quarter of the way in from its right edge
+    scene.add_sphere(Vec3(1.0, 0.5, 0.0), Vec3(1.0, 1.0, 1.0), 0.5);
+    scene.make_scene();
+
+    let bake = AoBake { size: 16, samples: 128, distance: 2.0 };
+    let texture = bake_ambient_occlusion(&scene, floor, &bake).unwrap();
+    assert_eq!(texture.dimensions(), (16, 16));
+
+    let under_ball = texture.get_pixel(12, 8)[0];
+    let open_floor = texture.get_pixel(1, 1)[0];
+    assert!(under_ball < 128, "{}", under_ball);
+    assert!(open_floor > 200, "{}", open_floor);
+    // Nothing but the floor is baked, so a ball without texture coordinates can't be
+    let ball = scene.entries.keys().copied().find(|&id| id != floor).unwrap();
+    assert!(bake_ambient_occlusion(&scene, ball, &bake).is_err());
+}
@@ -0,0 +1,57 @@
+// Auto exposure adapts to the metered brightness over time, the way eyes do, instead of jumping
+// to it every frame.
+
+use std::time::Duration;
+
+use rust_raytracing_wgpu::raytracer::{log_average_luminance, AutoExposure, Scene, EXPOSURE_KEY};
+
+#[test]
+fn metering_decodes_to_the_mean_log_luminance() {
+    // Two workgroups with means of 2^-3 and 2^1, shifted by 16 stops in steps of 1/1024
+    let metering = [(13 + 17) * 1024, 2];
+    assert_eq!(log_average_luminance(&metering), Some(-1.0));
+    assert_eq!(log_average_luminance(&[0, 0]), None);
+}
+
+#[test]
+fn first_metering_is_taken_as_it_is() {
+    let mut exposure = AutoExposure::default();
+    assert_eq!(exposure.exposure(), 1.0);
+    assert!(!exposure.settled());
+
+    let scale = exposure.adapt(EXPOSURE_KEY.log2() + 2.0, Duration::from_secs(10));
+    assert!((scale - 0.25).abs() < 1e-5, "{}", scale);
+    assert!(exposure.settled());
+}
+
+#[test]
+fn exposure_eases_towards_the_metering() {
+    let mut exposure = AutoExposure::default();
+    exposure.adapt(0.0, Duration::ZERO);
+
+    // Turning towards the bright sky darkens the view faster than turning back opens it up
+    let mut brighter = exposure;
+    let darkening = brighter.adapt(4.0, Duration::from_millis(100));
+    let mut darker = exposure;
+    let opening = darker.adapt(-4.0, Duration::from_millis(100));
+    let start = exposure.exposure();
+    assert!(darkening < start && opening > start);
+    assert!(start / darkening > opening / start);
+    assert!(!brighter.settled() && !darker.settled());
+
+    for _ in 0..100 {
+        brighter.adapt(4.0, Duration::from_millis(100));
+    }
+    assert!(brighter.settled());
+    assert!((brighter.exposure() - EXPOSURE_KEY / 16.0).abs() < 1e-3);
+}
+
+#[test]
+fn exposure_scale_combines_stops_and_adaptation() {
+    let mut scene = Scene::new(4, 32.0, 24.0);
+    scene.exposure = 1.0;
+    scene.adapted_exposure = 0.5;
+    assert_eq!(scene.exposure_scale(), 2.0);
+    scene.auto_exposure = true;
+    assert_eq!(scene.exposure_scale(), 1.0);
+}
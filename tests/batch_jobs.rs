@@ -0,0 +1,46 @@
+// Batch job lists read one render per line, and each job is seen through the scene's camera of
+// that name, fitted to the job's size.
+
+use rust_raytracing_wgpu::raytracer::{job_camera, parse_batch_jobs, BatchJob, Scene, Vec3};
+
+const JOBS: &str = "
+# Overnight renders
+scenes/room.rhai window 1920x1080 1024 renders/room-window.exr
+
+scenes/room.rhai - 640x480 64 renders/room.png
+";
+
+#[test]
+fn job_lists_are_read_line_by_line() {
+    let jobs = parse_batch_jobs(JOBS).unwrap();
+    assert_eq!(jobs, vec![
+        BatchJob { scene: "scenes/room.rhai".to_string(), camera: Some("window".to_string()), width: 1920, height: 1080, samples: 1024, output: "renders/room-window.exr".to_string() },
+        BatchJob { scene: "scenes/room.rhai".to_string(), camera: None, width: 640, height: 480, samples: 64, output: "renders/room.png".to_string() },
+    ]);
+
+    // The error points at the line that is wrong
+    let error = parse_batch_jobs("a.rhai - 64x64 8 a.png\nb.rhai - 64x 8 b.png").unwrap_err();
+    assert!(error.starts_with("line 2:"), "{}", error);
+    assert!(parse_batch_jobs("a.rhai - 64x64 0 a.png").is_err());
+    assert!(parse_batch_jobs("a.rhai - 64x64 8").is_err());
+}
+
+#[test]
+fn jobs_render_through_the_named_camera_at_their_size() {
+    let mut scene = Scene::new(4, 1.0, 1.0);
+    let mut window = scene.camera;
+    window.look_at(Vec3(2.0, 1.0, -2.0), Vec3(0.0, 0.0, 0.0));
+    scene.cameras.insert("window".to_string(), window);
+
+    let mut job = parse_batch_jobs("room.rhai window 200x100 8 room.png").unwrap().remove(0);
+    let camera = job_camera(&scene, &job).unwrap();
+    assert_eq!(camera.origin, Vec3(2.0, 1.0, -2.0));
+    let (width, height) = (camera.horizontal.dot(camera.horizontal).sqrt(), camera.vertical.dot(camera.vertical).sqrt());
+    assert!((width / height - 2.0).abs() < 1e-5, "{}", width / height);
+
+    job.camera = None;
+    assert_eq!(job_camera(&scene, &job).unwrap().origin, scene.camera.origin);
+    job.camera = Some("door".to_string());
+    let error = job_camera(&scene, &job).unwrap_err();
+    assert!(error.contains("window"), "{}", error);
+}
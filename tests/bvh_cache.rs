@@ -0,0 +1,69 @@
+// BVH caches are only used for the scene they were built from, and a cache whose tree points
+// outside itself or the scene is rebuilt instead of being traced.
+
+use rust_raytracing_wgpu::raytracer::{Scene, Vec3};
+
+// Where the root's left child is written: after the magic, hash, counts and its min corner
+const ROOT_LEFT_CHILD: usize = 36;
+
+fn scene() -> Scene {
+    let mut scene = Scene::new(2, 1.0, 1.0);
+    for i in 0..12 {
+        scene.add_sphere(Vec3(i as f32, 0.0, -3.0), Vec3(0.5, 0.5, 0.5), 0.4);
+    }
+    scene
+}
+
+fn cache_path(name: &str) -> String {
+    std::env::temp_dir().join(format!("bvh-cache-{}-{}.bin", name, std::process::id())).to_str().unwrap().to_string()
+}
+
+#[test]
+fn caches_load_only_for_the_same_geometry() {
+    let path = cache_path("geometry");
+    let mut built = scene();
+    built.make_scene_cached(&path);
+
+    let mut cached = scene();
+    assert!(cached.load_bvh(&path).unwrap());
+    assert_eq!(cached.nodes_used, built.nodes_used);
+    assert_eq!(cached.object_indices, built.object_indices);
+
+    let mut moved = scene();
+    moved.add_sphere(Vec3(0.0, 5.0, 0.0), Vec3(1.0, 1.0, 1.0), 1.0);
+    assert!(!moved.load_bvh(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn caches_pointing_out_of_range_are_rebuilt() {
+    let path = cache_path("ranges");
+    scene().make_scene_cached(&path);
+    let bytes = std::fs::read(&path).unwrap();
+    let corrupt = |change: &dyn Fn(&mut Vec<u8>)| {
+        let mut bytes = bytes.clone();
+        change(&mut bytes);
+        std::fs::write(&path, bytes).unwrap();
+    };
+
+    corrupt(&|bytes| bytes[ROOT_LEFT_CHILD..ROOT_LEFT_CHILD + 4].copy_from_slice(&1000i32.to_le_bytes()));
+    assert!(scene().load_bvh(&path).is_err());
+    // The last object index
+    corrupt(&|bytes| {
+        let end = bytes.len();
+        bytes[end - 4..].copy_from_slice(&12u32.to_le_bytes());
+    });
+    assert!(scene().load_bvh(&path).is_err());
+    // Counts larger than the file fail on reading, without allocating for them
+    corrupt(&|bytes| bytes[16..20].copy_from_slice(&u32::MAX.to_le_bytes()));
+    assert!(scene().load_bvh(&path).is_err());
+
+    let mut rebuilt = scene();
+    rebuilt.make_scene_cached(&path);
+    let mut built = scene();
+    built.make_scene();
+    assert_eq!(rebuilt.object_indices, built.object_indices);
+    // And the rebuilt tree replaced the broken cache
+    assert!(scene().load_bvh(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
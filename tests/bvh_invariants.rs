@@ -0,0 +1,181 @@
+// Builds trees over random scenes, including ones full of coincident and nearly coincident
+// centroids, and checks the structure the kernel and the CPU renderer rely on when walking them.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_raytracing_wgpu::raytracer::{Object, Scene, Vec3};
+
+const SEEDS: u64 = 8;
+const LEAF_SIZES: [usize; 4] = [1, 2, 4, 8];
+// Spatial splits may reference an object from more than one leaf, up to this share of the objects
+const DUPLICATE_SHARE: f32 = 0.3;
+
+fn random_point(rng: &mut StdRng, extent: f32) -> Vec3 {
+    Vec3(rng.gen_range(-extent..extent), rng.gen_range(-extent..extent), rng.gen_range(-extent..extent))
+}
+
+// Spheres of every size, triangles from tiny to long and thin, and a few floor squares
+fn random_scene(rng: &mut StdRng) -> Scene {
+    let mut scene = Scene::new(4, 1.0, 1.0);
+    for _ in 0..rng.gen_range(1..300) {
+        let center = random_point(rng, 10.0);
+        match rng.gen_range(0..10) {
+            0..=4 => { scene.add_sphere(center, Vec3(1.0, 1.0, 1.0), rng.gen_range(0.01..3.0)); },
+            5..=8 => {
+                let size = if rng.gen_bool(0.3) { 15.0 } else { 0.5 };
+                let corners = [center, center + random_point(rng, size), center + random_point(rng, size)];
+                scene.add_triangle(corners, Vec3(1.0, 1.0, 1.0));
+            },
+            _ => { scene.add_square(center, rng.gen_range(0.1..8.0), rng.gen_range(0.1..8.0), Vec3(1.0, 1.0, 1.0), rng.gen_range(0.0..360.0)); },
+        }
+    }
+    scene
+}
+
+// Objects stacked on a handful of centers, some identical, some a rounding error apart
+fn crowded_scene(rng: &mut StdRng) -> Scene {
+    let mut scene = Scene::new(4, 1.0, 1.0);
+    let centers: Vec<Vec3> = (0..rng.gen_range(1..4)).map(|_| random_point(rng, 5.0)).collect();
+    for i in 0..rng.gen_range(2..200) {
+        let center = centers[i % centers.len()];
+        match i % 3 {
+            0 => { scene.add_sphere(center, Vec3(1.0, 1.0, 1.0), 0.5); },
+            1 => { scene.add_sphere(center + f32::EPSILON * i as f32, Vec3(1.0, 1.0, 1.0), rng.gen_range(0.1..2.0)); },
+            _ => { scene.add_triangle([center - 0.5, center + Vec3(0.5, -0.5, 0.0), center + Vec3(0.0, 0.5, 0.0)], Vec3(1.0, 1.0, 1.0)); },
+        }
+    }
+    scene
+}
+
+fn contains(outer: (Vec3, Vec3), inner: (Vec3, Vec3)) -> bool {
+    (0..3).all(|axis| outer.0.axis(axis) <= inner.0.axis(axis) && inner.1.axis(axis) <= outer.1.axis(axis))
+}
+
+fn object_bounds(object: &Object) -> Option<(Vec3, Vec3)> {
+    match object {
+        Object::Sphere(sphere) => Some((sphere.center - sphere.radius, sphere.center + sphere.radius)),
+        Object::Triangle(triangle) => {
+            let corners = &triangle.corners;
+            Some((corners[0].min(corners[1]).min(corners[2]), corners[0].max(corners[1]).max(corners[2])))
+        },
+        _ => None,
+    }
+}
+
+// Walks the tree from the root, checking every node is reached once, children follow their
+// parent and sit inside its bounds, and leaves list each object at most once and at most
+// max_leaf_size references. Refit trees are also checked to bound every object they list.
+fn assert_invariants(scene: &Scene, refit: bool) {
+    let nodes = &scene.nodes[..scene.nodes_used];
+    let mut visits = vec![0; nodes.len()];
+    let mut references = vec![0; scene.objects.len()];
+    let mut stack = vec![0];
+    while let Some(i) = stack.pop() {
+        visits[i] += 1;
+        let node = nodes[i];
+        let bounds = (node.min_corner, node.max_corner);
+        if node.object_count > 0 {
+            assert!(node.object_count <= scene.max_leaf_size, "Leaf {} holds {} references", i, node.object_count);
+            let start = node.left_child as usize;
+            assert!(start + node.object_count <= scene.object_indices.len(), "Leaf {} runs past the index list", i);
+            let mut listed: Vec<usize> = scene.object_indices[start..start + node.object_count].to_vec();
+            listed.sort();
+            listed.dedup();
+            assert_eq!(listed.len(), node.object_count, "Leaf {} lists an object twice", i);
+            for index in listed {
+                assert!(index < scene.objects.len(), "Leaf {} lists object {} of {}", i, index, scene.objects.len());
+                references[index] += 1;
+                if let (true, Some(object)) = (refit, object_bounds(&scene.objects[index])) {
+                    assert!(contains(bounds, object), "Refit leaf {} doesn't bound object {}", i, index);
+                }
+            }
+        } else if node.left_child >= 0 {
+            let left = node.left_child as usize;
+            assert!(left > i && left + 1 < nodes.len(), "Node {} has children {} and {}", i, left, left + 1);
+            for child in [left, left + 1] {
+                assert!(contains(bounds, (nodes[child].min_corner, nodes[child].max_corner)), "Node {} doesn't bound child {}", i, child);
+                stack.push(child);
+            }
+        }
+    }
+
+    assert!(visits.iter().all(|&count| count == 1), "Some nodes are unreachable or shared");
+    assert!(references.iter().all(|&count| count >= 1), "Some objects are in no leaf");
+    let duplicates = references.iter().sum::<usize>() - scene.objects.len();
+    assert!(duplicates as f32 <= scene.objects.len() as f32 * DUPLICATE_SHARE, "{} objects are referenced twice", duplicates);
+}
+
+fn check_scenes(make: fn(&mut StdRng) -> Scene) {
+    for seed in 0..SEEDS {
+        for leaf_size in LEAF_SIZES {
+            let mut scene = make(&mut StdRng::seed_from_u64(seed));
+            scene.max_leaf_size = leaf_size;
+            scene.make_scene();
+            assert_invariants(&scene, false);
+            scene.refit_bvh();
+            assert_invariants(&scene, true);
+        }
+    }
+}
+
+#[test]
+fn random_scenes_keep_bvh_invariants() {
+    check_scenes(random_scene);
+}
+
+#[test]
+fn coincident_centroids_still_split_to_leaf_size() {
+    check_scenes(crowded_scene);
+}
+
+#[test]
+fn single_object_is_one_leaf() {
+    let mut scene = Scene::new(4, 1.0, 1.0);
+    scene.add_sphere(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0), 1.0);
+    scene.make_scene();
+    assert_eq!(scene.nodes_used, 1);
+    assert_invariants(&scene, false);
+}
+
+#[test]
+fn empty_scene_has_a_childless_root() {
+    let mut scene = Scene::new(4, 1.0, 1.0);
+    scene.make_scene();
+    assert_eq!(scene.nodes_used, 1);
+    assert!(scene.nodes[0].left_child < 0 && scene.nodes[0].object_count == 0);
+    scene.refit_bvh();
+    assert_invariants(&scene, true);
+}
+
+#[test]
+fn bvh_cost_adds_up() {
+    for seed in 0..SEEDS {
+        let mut scene = random_scene(&mut StdRng::seed_from_u64(seed));
+        scene.make_scene();
+        let cost = scene.bvh_cost();
+        assert!((cost.sah_cost - cost.traversal_cost - cost.intersection_cost).abs() < 1e-4);
+        assert!(cost.intersection_cost > 0.0);
+        assert!(cost.overlapping_nodes < scene.nodes_used && cost.worst_overlap <= 1.0 + 1e-4);
+        assert_eq!(scene.stats().bvh_cost, cost);
+    }
+}
+
+#[test]
+fn bvh_cost_measures_overlap() {
+    let mut apart = Scene::new(4, 1.0, 1.0);
+    let mut stacked = Scene::new(4, 1.0, 1.0);
+    for i in 0..16 {
+        apart.add_sphere(Vec3(10.0 * i as f32, 0.0, 0.0), Vec3(1.0, 1.0, 1.0), 1.0);
+        stacked.add_sphere(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0), 1.0);
+    }
+    for scene in [&mut apart, &mut stacked] {
+        scene.max_leaf_size = 1;
+        scene.make_scene();
+    }
+    assert_eq!((apart.bvh_cost().overlapping_nodes, apart.bvh_cost().sibling_overlap), (0, 0.0));
+    assert!(stacked.bvh_cost().sah_cost > apart.bvh_cost().sah_cost);
+
+    let mut empty = Scene::new(4, 1.0, 1.0);
+    empty.make_scene();
+    assert_eq!(empty.bvh_cost().sah_cost, 0.0);
+}
@@ -0,0 +1,77 @@
+// The panoramic, stereo and orthographic projections, rendered on the CPU, which maps pixels to rays the way
+// the kernel does.
+
+use rust_raytracing_wgpu::raytracer::{render_cpu, Material, Projection, Scene, SkySource, Vec3};
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 16;
+
+// A matte red ball at `center` under a blue sky, seen from (0, 0, -3) looking towards +z
+fn ball_scene(center: Vec3, radius: f32, projection: Projection) -> Scene {
+    let mut scene = Scene::new(2, WIDTH as f32, HEIGHT as f32);
+    scene.active_sky = scene.add_sky(SkySource::Solid([150, 180, 230]));
+    let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+    let ball = scene.add_sphere(center, Vec3(0.9, 0.1, 0.1), radius);
+    scene.set_material(ball, matte);
+    scene.camera.set_projection(projection);
+    scene.make_scene();
+    scene
+}
+
+fn pixel(radiance: &[f32], x: u32, y: u32) -> [f32; 3] {
+    let i = ((y * WIDTH + x) * 4) as usize;
+    [radiance[i], radiance[i + 1], radiance[i + 2]]
+}
+
+// Anything but the sky, which is the same in every direction
+fn is_ball(color: [f32; 3]) -> bool {
+    let out_of_view = ball_scene(Vec3(0.0, 0.0, -10.0), 0.1, Projection::Perspective);
+    let sky = pixel(&render_cpu(&out_of_view, WIDTH, HEIGHT, 1), WIDTH / 2, HEIGHT / 2);
+    color.iter().zip(sky).any(|(channel, sky)| (channel - sky).abs() > 0.01)
+}
+
+#[test]
+fn equirectangular_sees_behind_the_camera() {
+    let behind = Vec3(0.0, 0.0, -6.0);
+    let perspective = render_cpu(&ball_scene(behind, 1.0, Projection::Perspective), WIDTH, HEIGHT, 1);
+    let panorama = render_cpu(&ball_scene(behind, 1.0, Projection::Equirectangular), WIDTH, HEIGHT, 1);
+
+    // The edges of the panorama's middle row look straight back, its middle straight ahead
+    assert!(!is_ball(pixel(&perspective, 0, HEIGHT / 2)));
+    assert!(is_ball(pixel(&panorama, 0, HEIGHT / 2)));
+    assert!(is_ball(pixel(&panorama, WIDTH - 1, HEIGHT / 2)));
+    assert!(!is_ball(pixel(&panorama, WIDTH / 2, HEIGHT / 2)));
+}
+
+#[test]
+fn fisheye_is_black_outside_its_circle() {
+    let scene = ball_scene(Vec3(0.0, 0.0, 0.0), 0.5, Projection::Fisheye { fov: 180.0 });
+    let radiance = render_cpu(&scene, WIDTH, HEIGHT, 1);
+    assert_eq!(pixel(&radiance, 0, 0), [0.0; 3]);
+    assert!(is_ball(pixel(&radiance, WIDTH / 2, HEIGHT / 2)));
+    // A 180 degree fisheye sees to the sides, where the corners' neighbours inside the circle look
+    assert!(!is_ball(pixel(&radiance, WIDTH / 4, HEIGHT / 2)) && pixel(&radiance, WIDTH / 4, HEIGHT / 2) != [0.0; 3]);
+}
+
+#[test]
+fn stereo_eyes_converge_on_the_same_point() {
+    // A ball at the convergence distance shows in the middle of both halves, however far apart
+    // the eyes are
+    let projection = Projection::Stereo { eye_separation: 1.0, convergence: 3.0 };
+    let radiance = render_cpu(&ball_scene(Vec3(0.0, 0.0, 0.0), 0.5, projection), WIDTH, HEIGHT, 1);
+    assert!(is_ball(pixel(&radiance, WIDTH / 4, HEIGHT / 2)));
+    assert!(is_ball(pixel(&radiance, WIDTH * 3 / 4, HEIGHT / 2)));
+    assert!(!is_ball(pixel(&radiance, WIDTH / 2, HEIGHT / 2)));
+}
+
+#[test]
+fn orthographic_keeps_the_size_with_distance() {
+    let rows_covered = |distance: f32| {
+        let scene = ball_scene(Vec3(0.0, 0.0, distance), 1.0, Projection::Orthographic { height: 4.0 });
+        let radiance = render_cpu(&scene, WIDTH, HEIGHT, 1);
+        (0..HEIGHT).filter(|&y| is_ball(pixel(&radiance, WIDTH / 2, y))).count()
+    };
+    // Two units of the four the view is tall
+    assert_eq!(rows_covered(0.0), 8);
+    assert_eq!(rows_covered(20.0), 8);
+}
@@ -0,0 +1,74 @@
+// Camera rigs fly a Catmull-Rom path through their points at a steady speed, optionally with a
+// smooth shake, and land on the same poses every time.
+
+use rust_raytracing_wgpu::raytracer::{perlin, CameraRig, CameraShake, Vec3};
+
+fn distance(a: Vec3, b: Vec3) -> f32 {
+    (a - b).magnitude()
+}
+
+fn bend() -> Vec<Vec3> {
+    vec![Vec3(0.0, 1.0, 0.0), Vec3(4.0, 1.0, 0.0), Vec3(4.0, 1.0, -4.0), Vec3(8.0, 2.0, -4.0)]
+}
+
+#[test]
+fn path_starts_and_ends_on_its_points() {
+    let rig = CameraRig::new(bend(), 2.0, false);
+    assert!(distance(rig.pose_at(0.0).0, Vec3(0.0, 1.0, 0.0)) < 1e-4);
+    assert!(distance(rig.pose_at(rig.duration()).0, Vec3(8.0, 2.0, -4.0)) < 1e-3);
+    // Without looping the camera stays at the end, still facing the way it came
+    let (end, ahead) = rig.pose_at(rig.duration() + 10.0);
+    assert!(distance(end, Vec3(8.0, 2.0, -4.0)) < 1e-3);
+    assert!((ahead - end).0 > 0.0);
+}
+
+#[test]
+fn camera_moves_at_a_steady_speed() {
+    let rig = CameraRig::new(bend(), 2.0, false);
+    assert!(rig.length() > 12.0);
+
+    let steps = 50;
+    let interval = rig.duration() / steps as f32;
+    for i in 0..steps {
+        let from = rig.pose_at(i as f32 * interval).0;
+        let to = rig.pose_at((i + 1) as f32 * interval).0;
+        // Chords are a little shorter than the arcs they cut across in the bends
+        let moved = distance(from, to);
+        assert!(moved <= interval * 2.0 + 1e-3 && moved > interval * 2.0 * 0.95, "{} at step {}", moved, i);
+    }
+}
+
+#[test]
+fn camera_faces_along_the_path() {
+    let rig = CameraRig::new(vec![Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -10.0)], 1.0, true);
+    let (position, lookat) = rig.pose_at(3.0);
+    assert!(distance(position, Vec3(0.0, 0.0, -3.0)) < 1e-3);
+    assert!(distance(lookat - position, Vec3(0.0, 0.0, -1.0)) < 1e-4);
+
+    // Looping flies the path again from its start
+    assert!(distance(rig.pose_at(13.0).0, position) < 1e-3);
+}
+
+#[test]
+fn shake_is_smooth_bounded_and_repeatable() {
+    let mut rig = CameraRig::new(vec![Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -100.0)], 1.0, false);
+    rig.shake = Some(CameraShake { amplitude: 2.0, frequency: 3.0 });
+
+    let mut previous = rig.pose_at(0.0).1 - rig.pose_at(0.0).0;
+    let mut shaken = false;
+    for i in 1..400 {
+        let (position, lookat) = rig.pose_at(i as f32 * 0.01);
+        let direction = lookat - position;
+        let angle = direction.dot(Vec3(0.0, 0.0, -1.0)).clamp(-1.0, 1.0).acos().to_degrees();
+        assert!(angle <= 2.0 * 2f32.sqrt() + 1e-3, "{} degrees off the path", angle);
+        assert!(distance(direction, previous) < 0.01, "the view jumped at {}", i);
+        shaken |= angle > 0.5;
+        previous = direction;
+    }
+    assert!(shaken);
+    assert_eq!(rig.pose_at(1.234), rig.clone().pose_at(1.234));
+
+    for x in [-3.0, 0.0, 1.0, 17.0] {
+        assert_eq!(perlin(x), 0.0);
+    }
+}
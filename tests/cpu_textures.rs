@@ -0,0 +1,59 @@
+// The CPU renderer samples image textures and the label atlas like the kernel, so textured balls
+// take the image's color and labels show up in front of the sky.
+
+use image::{Rgba, RgbaImage};
+use rust_raytracing_wgpu::raytracer::{render_cpu, Material, ObjectId, Scene, SkySource, Vec3};
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 16;
+
+// A matte white ball at the origin under a blue sky, seen from (0, 0, -3) looking towards +z
+fn ball_scene() -> (Scene, ObjectId) {
+    let mut scene = Scene::new(2, WIDTH as f32, HEIGHT as f32);
+    scene.active_sky = scene.add_sky(SkySource::Solid([150, 180, 230]));
+    let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+    let ball = scene.add_sphere(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0), 1.0);
+    scene.set_material(ball, matte);
+    (scene, ball)
+}
+
+fn pixel(radiance: &[f32], x: u32, y: u32) -> [f32; 3] {
+    let i = ((y * WIDTH + x) * 4) as usize;
+    [radiance[i], radiance[i + 1], radiance[i + 2]]
+}
+
+#[test]
+fn image_textures_color_the_ball() {
+    let path = std::env::temp_dir().join(format!("cpu-texture-{}.png", std::process::id()));
+    RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])).save(&path).unwrap();
+
+    let (mut plain, _) = ball_scene();
+    plain.make_scene();
+    let (mut textured, ball) = ball_scene();
+    let texture = textured.load_texture(path.to_str().unwrap());
+    textured.set_texture(ball, texture);
+    textured.make_scene();
+
+    // Bluish from the sky it reflects until the texture turns it red
+    let [r, g, _] = pixel(&render_cpu(&plain, WIDTH, HEIGHT, 1), WIDTH / 2, HEIGHT / 2);
+    assert!(r < g, "{} {}", r, g);
+    let [r, g, _] = pixel(&render_cpu(&textured, WIDTH, HEIGHT, 1), WIDTH / 2, HEIGHT / 2);
+    assert!(r > g, "{} {}", r, g);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn labels_show_their_text() {
+    let mut scene = Scene::new(2, WIDTH as f32, HEIGHT as f32);
+    scene.active_sky = scene.add_sky(SkySource::Solid([150, 180, 230]));
+    scene.add_label(Vec3(0.0, 0.0, 0.0), "###", 1.0, Vec3(1.0, 0.0, 0.0));
+    scene.make_scene();
+
+    // The sky is bluer than it is red, the label's glyphs the other way round
+    let radiance = render_cpu(&scene, WIDTH, HEIGHT, 1);
+    let red = (0..WIDTH * HEIGHT).filter(|i| {
+        let [r, _, b] = pixel(&radiance, i % WIDTH, i / WIDTH);
+        r > b
+    }).count();
+    assert!(red > 0 && red < (WIDTH * HEIGHT) as usize / 2, "{} red pixels", red);
+}
@@ -0,0 +1,73 @@
+// Renders a small scene with the kernel and with the CPU renderer from the same seeds and checks
+// the images agree, which catches the two drifting apart in traversal or shading.
+// Skipped on machines without an adapter the renderer can run on.
+
+use rust_raytracing_wgpu::raytracer::{enumerate_adapters, is_adapter_supported, render_cpu, render_offline, Material, Scene, SkySource, Vec3};
+
+const WIDTH: u32 = 48;
+const HEIGHT: u32 = 32;
+const SAMPLES: u32 = 32;
+
+// Paths follow the same random numbers, so most pixels match to rounding. A path can still take
+// another branch where the GPU's math rounds differently, moving its pixel by 1 / SAMPLES, and
+// primary rays grazing a silhouette can land on the other side of it.
+const PIXEL_TOLERANCE: f32 = 0.1;
+const MAX_DIFFERING_PIXELS: f32 = 0.02;
+const MEAN_TOLERANCE: f32 = 0.01;
+
+// Matte, mirror, glass, filmed and see-through surfaces on spheres, a triangle and a floor quad
+fn test_scene() -> Scene {
+    let mut scene = Scene::new(8, WIDTH as f32, HEIGHT as f32);
+    scene.active_sky = scene.add_sky(SkySource::Solid([150, 180, 230]));
+
+    let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+    let mirror = scene.add_material(Material { roughness: 0.1, ..Default::default() });
+    let glass = scene.add_material(Material { ior: 1.5, ..Default::default() });
+    let metal = scene.add_material(Material { diffuse: true, metalness: 0.5, ..Default::default() });
+    let veil = scene.add_material(Material { diffuse: true, transparency: 0.5, two_sided: true, ..Default::default() });
+
+    let floor = scene.add_square(Vec3(0.0, -0.6, 0.0), 6.0, 6.0, Vec3(0.8, 0.8, 0.8), 0.0);
+    scene.set_material(floor, matte);
+    let ball = scene.add_sphere(Vec3(0.0, -0.1, 0.0), Vec3(0.9, 0.2, 0.2), 0.5);
+    scene.set_material(ball, matte);
+    let chrome = scene.add_sphere(Vec3(1.1, -0.2, 0.4), Vec3(0.9, 0.9, 0.9), 0.4);
+    scene.set_material(chrome, mirror);
+    let marble = scene.add_sphere(Vec3(-1.1, -0.2, 0.2), Vec3(1.0, 1.0, 1.0), 0.4);
+    scene.set_material(marble, glass);
+    let brass = scene.add_sphere(Vec3(0.5, -0.45, -1.0), Vec3(0.8, 0.6, 0.2), 0.15);
+    scene.set_material(brass, metal);
+    let sail = scene.add_triangle([Vec3(-0.6, -0.6, -0.8), Vec3(0.0, 0.6, -0.8), Vec3(-1.4, 0.4, -0.6)], Vec3(0.2, 0.7, 0.3));
+    scene.set_material(sail, veil);
+
+    scene.make_scene();
+    scene
+}
+
+#[test]
+fn gpu_and_cpu_renders_match() {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let Some(adapter) = enumerate_adapters(&instance).into_iter().find(is_adapter_supported) else {
+        eprintln!("No supported adapter, skipping the GPU/CPU comparison");
+        return;
+    };
+
+    for stackless in [false, true] {
+        let mut scene = test_scene();
+        scene.stackless_traversal = stackless;
+        let cpu = render_cpu(&scene, WIDTH, HEIGHT, SAMPLES);
+        let gpu = render_offline(&mut scene, std::slice::from_ref(&adapter), WIDTH, HEIGHT, SAMPLES).unwrap();
+        assert_eq!(cpu.len(), gpu.len());
+
+        let differences: Vec<f32> = cpu.chunks_exact(4).zip(gpu.chunks_exact(4))
+            .map(|(a, b)| (0..3).map(|c| (a[c] - b[c]).abs()).fold(0.0, f32::max))
+            .collect();
+        let pixels = differences.len() as f32;
+        let differing = differences.iter().filter(|&&difference| difference > PIXEL_TOLERANCE).count() as f32;
+        let mean = differences.iter().sum::<f32>() / pixels;
+        assert!(
+            differing / pixels <= MAX_DIFFERING_PIXELS,
+            "{} of {} pixels differ by more than {} (stackless: {})", differing, pixels, PIXEL_TOLERANCE, stackless,
+        );
+        assert!(mean <= MEAN_TOLERANCE, "Mean difference {} is above {} (stackless: {})", mean, MEAN_TOLERANCE, stackless);
+    }
+}
@@ -0,0 +1,25 @@
+// The denoiser is guided by the albedo and normal of what each pixel's camera ray hits first,
+// traced on the CPU for renders of every backend.
+
+use rust_raytracing_wgpu::raytracer::{CpuRenderer, Scene, SkySource, Vec3};
+
+#[test]
+fn guides_hold_the_first_hit_of_each_pixel() {
+    let mut scene = Scene::new(4, 9.0, 9.0);
+    scene.active_sky = scene.add_sky(SkySource::Solid([255, 255, 255]));
+    scene.add_sphere(Vec3(0.0, 0.0, 0.0), Vec3(0.8, 0.4, 0.2), 1.0);
+    scene.make_scene();
+
+    let (albedo, normal) = CpuRenderer::new(&scene, 9, 9).denoise_guides(&scene.flatten_scene_data(0));
+    assert_eq!((albedo.len(), normal.len()), (9 * 9 * 3, 9 * 9 * 3));
+
+    // The middle pixel looks straight at the ball, whose normal there faces the camera
+    let middle = (4 * 9 + 4) * 3;
+    assert_eq!(&albedo[middle..middle + 3], &[0.8, 0.4, 0.2]);
+    let facing = Vec3(normal[middle], normal[middle + 1], normal[middle + 2]).dot(Vec3(0.0, 0.0, -1.0));
+    assert!(facing > 0.99, "{}", facing);
+
+    // The corners miss it and see the sky
+    assert_eq!(&normal[0..3], &[0.0, 0.0, 0.0]);
+    assert!(albedo[0] > 0.5, "{}", albedo[0]);
+}
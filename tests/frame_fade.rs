@@ -0,0 +1,44 @@
+// After the camera moves, the last converged frame is reprojected to the new camera and faded out
+// over the new samples, instead of the view popping to a single noisy sample.
+
+use std::time::Duration;
+
+use rust_raytracing_wgpu::raytracer::{can_reproject, fade_weight, reproject, Camera, Projection, Vec3};
+
+fn camera() -> Camera {
+    Camera::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0), Vec3(0.0, 1.0, 0.0), 90.0, 1.0)
+}
+
+#[test]
+fn kept_frame_fades_out_over_the_duration() {
+    assert_eq!(fade_weight(Duration::ZERO, 0.5), 1.0);
+    assert_eq!(fade_weight(Duration::from_millis(250), 0.5), 0.5);
+    assert_eq!(fade_weight(Duration::from_secs(1), 0.5), 0.0);
+    // A duration of 0 turns the fade off
+    assert_eq!(fade_weight(Duration::ZERO, 0.0), 0.0);
+}
+
+#[test]
+fn turning_shifts_the_kept_frame_across_the_view() {
+    let history = camera();
+    let mut turned = camera();
+    turned.rotate_yaw(-10.0);
+
+    // The middle of the view after turning right was right of the middle before
+    let middle = turned.lower_left_corner + (turned.horizontal + turned.vertical) * 0.5 - turned.origin;
+    let (u, v) = reproject(&history, middle).unwrap();
+    assert!((u - (0.5 + 10f32.to_radians().tan() * 0.5)).abs() < 1e-4, "{}", u);
+    assert!((v - 0.5).abs() < 1e-4);
+
+    // Straight back and far off to the side aren't in the kept frame
+    assert_eq!(reproject(&history, Vec3(0.0, 0.0, 1.0)), None);
+    assert_eq!(reproject(&history, Vec3(1.0, 0.0, -0.1)), None);
+}
+
+#[test]
+fn only_perspective_frames_are_reprojected() {
+    let mut fisheye = camera();
+    fisheye.set_projection(Projection::Fisheye { fov: 180.0 });
+    assert!(can_reproject(&camera(), &camera()));
+    assert!(!can_reproject(&camera(), &fisheye));
+}
@@ -0,0 +1,47 @@
+// The buffers the scene is uploaded to are sized from gpu_layout's strides, so what the scene
+// flattens has to fit them exactly, and even an empty scene needs buffers that can be bound.
+
+use rust_raytracing_wgpu::raytracer::{check_scene_limits, BufferLimitError, Material, Scene, SceneBuffer, Vec3};
+
+#[test]
+fn flattened_data_matches_the_buffer_sizes() {
+    let mut scene = Scene::new(4, 32.0, 24.0);
+    let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+    for i in 0..10 {
+        let sphere = scene.add_sphere(Vec3(i as f32, 0.0, 0.0), Vec3(0.5, 0.5, 0.5), 0.4);
+        scene.set_material(sphere, matte);
+    }
+    scene.add_triangle([Vec3(0.0, 1.0, 0.0), Vec3(1.0, 1.0, 0.0), Vec3(0.0, 2.0, 0.0)], Vec3(0.8, 0.2, 0.2));
+    scene.make_scene();
+
+    assert_eq!(scene.flatten_object_data().len() as u64, SceneBuffer::Objects.size(&scene));
+    assert_eq!(scene.flatten_node_data().len() as u64, SceneBuffer::Nodes.size(&scene));
+    assert_eq!(scene.flatten_object_index_data().len() as u64, SceneBuffer::ObjectIndices.size(&scene));
+    assert_eq!(scene.flatten_material_data().len() as u64, SceneBuffer::Materials.size(&scene));
+    assert_eq!(scene.flatten_point_data().len() as u64, SceneBuffer::Points.size(&scene));
+}
+
+#[test]
+fn empty_scene_buffers_are_not_empty() {
+    let mut scene = Scene::new(4, 32.0, 24.0);
+    scene.make_scene();
+    for buffer in SceneBuffer::ALL {
+        assert!(buffer.size(&scene) >= buffer.stride(), "{} buffer is empty", buffer.name());
+    }
+    assert_eq!(check_scene_limits(&scene, &wgpu::Limits::downlevel_defaults()), Ok(()));
+}
+
+#[test]
+fn oversized_buffers_are_reported() {
+    let mut scene = Scene::new(4, 32.0, 24.0);
+    for i in 0..100 {
+        scene.add_sphere(Vec3(i as f32, 0.0, 0.0), Vec3(0.5, 0.5, 0.5), 0.4);
+    }
+    scene.make_scene();
+
+    let limit = SceneBuffer::Objects.stride() * 50;
+    let limits = wgpu::Limits { max_storage_buffer_binding_size: limit as u32, ..Default::default() };
+    let error = check_scene_limits(&scene, &limits).unwrap_err();
+    assert_eq!(error, BufferLimitError { buffer: SceneBuffer::Objects, size: SceneBuffer::Objects.stride() * 100, limit });
+    assert!(error.to_string().contains("objects"));
+}
@@ -0,0 +1,75 @@
+// Irradiance probes keep the light arriving from every direction as linear spherical harmonics on a
+// grid over the scene, which matte surfaces look their bounce light up in.
+
+use rust_raytracing_wgpu::raytracer::{project_sh, sh_irradiance, ProbeGrid, Scene, Vec3, MAX_PROBES_PER_AXIS, SCENE_DATA_SIZE};
+
+// Directions spread evenly over the sphere along a Fibonacci spiral
+fn sphere_directions(count: usize) -> Vec<Vec3> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+    (0..count).map(|i| {
+        let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+        let radius = (1.0 - y * y).sqrt();
+        let angle = golden_angle * i as f32;
+        Vec3(radius * angle.cos(), y, radius * angle.sin())
+    }).collect()
+}
+
+#[test]
+fn probes_sit_in_the_middle_of_their_cells() {
+    let grid = ProbeGrid::fit(Vec3(0.0, 0.0, 0.0), Vec3(4.0, 2.0, 1.0), 1.0);
+    assert_eq!(grid.counts, [4, 2, 1]);
+    assert_eq!(grid.count(), 8);
+    assert_eq!(grid.position(0), Vec3(0.5, 0.5, 0.5));
+    assert_eq!(grid.position(5), Vec3(1.5, 1.5, 0.5));
+    assert_eq!(grid.atlas_size(), (16, 2));
+
+    let long = ProbeGrid::fit(Vec3(0.0, 0.0, 0.0), Vec3(1000.0, 0.0, 1.0), 1.0);
+    assert_eq!(long.counts, [MAX_PROBES_PER_AXIS, 1, 1]);
+}
+
+#[test]
+fn harmonics_light_surfaces_facing_the_light() {
+    let directions = sphere_directions(4096);
+    let white = Vec3(1.0, 1.0, 1.0);
+
+    // Light from every direction reaches every surface alike
+    let everywhere: Vec<(Vec3, Vec3)> = directions.iter().map(|&direction| (direction, white)).collect();
+    let coefficients = project_sh(&everywhere);
+    for normal in [Vec3(0.0, 1.0, 0.0), Vec3(1.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0)] {
+        assert!((sh_irradiance(&coefficients, normal).0 - 1.0).abs() < 0.01);
+    }
+
+    // Light from above only: floors get all of it, walls half and ceilings none
+    let above: Vec<(Vec3, Vec3)> = directions.iter()
+        .map(|&direction| (direction, if direction.1 > 0.0 { white } else { Vec3(0.0, 0.0, 0.0) }))
+        .collect();
+    let coefficients = project_sh(&above);
+    assert!((sh_irradiance(&coefficients, Vec3(0.0, 1.0, 0.0)).0 - 1.0).abs() < 0.01);
+    assert!((sh_irradiance(&coefficients, Vec3(1.0, 0.0, 0.0)).0 - 0.5).abs() < 0.01);
+    assert!(sh_irradiance(&coefficients, Vec3(0.0, -1.0, 0.0)).0 < 0.01);
+}
+
+#[test]
+fn grid_is_fit_over_the_built_scene() {
+    let mut scene = Scene::new(2, 1.0, 1.0);
+    scene.add_sphere(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0), 1.0);
+    scene.add_sphere(Vec3(4.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0), 1.0);
+    scene.make_scene();
+
+    scene.fit_probe_grid(1.0);
+    let grid = scene.probe_grid.unwrap();
+    assert_eq!((grid.min, grid.max), (Vec3(-1.0, -1.0, -1.0), Vec3(5.0, 1.0, 1.0)));
+    assert_eq!(grid.counts, [6, 2, 2]);
+
+    // The uniform ends with the counts, which the kernel reads as no grid when they are 0
+    let data = scene.flatten_scene_data(0);
+    assert_eq!(data.len() as u64, SCENE_DATA_SIZE);
+    let floats: &[f32] = bytemuck::cast_slice(&data);
+    assert_eq!(&floats[floats.len() - 4..floats.len() - 1], &[6.0, 2.0, 2.0]);
+
+    scene.fit_probe_grid(0.0);
+    assert_eq!(scene.probe_grid, None);
+    let data = scene.flatten_scene_data(0);
+    let floats: &[f32] = bytemuck::cast_slice(&data);
+    assert_eq!(&floats[floats.len() - 4..floats.len() - 1], &[0.0, 0.0, 0.0]);
+}
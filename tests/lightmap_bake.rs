@@ -0,0 +1,67 @@
+// Lightmaps hold the light arriving at each point of a mesh, laid out by its texture coordinates or
+// by an atlas generated for it, with charts dilated so seams don't bleed in black.
+
+use rust_raytracing_wgpu::raytracer::{atlas_obj, bake_lightmap, dilate, generate_atlas, LightmapBake, ObjMesh, Scene, SkySource, Vec3};
+
+// A 4 by 4 floor facing up, without texture coordinates
+const FLOOR: &str = "
+v -2 0 2
+v 2 0 2
+v 2 0 -2
+v -2 0 -2
+f 1 2 3 4
+";
+
+#[test]
+fn generated_atlas_gives_each_triangle_its_own_cell() {
+    let mut triangles = ObjMesh::parse(Vec3(1.0, 1.0, 1.0), "floor.obj", FLOOR).unwrap().triangles;
+    triangles.extend(triangles.clone());
+    generate_atlas(&mut triangles, 64, 2);
+
+    // Four triangles take a 2 by 2 grid, each inside its half of the texture on both axes
+    for (i, triangle) in triangles.iter().enumerate() {
+        let cell = ((i % 2) as f32 * 0.5, (i / 2) as f32 * 0.5);
+        for uv in triangle.uvs.unwrap() {
+            assert!(uv.0 >= cell.0 && uv.0 <= cell.0 + 0.5, "{:?}", uv);
+            assert!(uv.1 >= cell.1 && uv.1 <= cell.1 + 0.5, "{:?}", uv);
+        }
+    }
+    let obj = atlas_obj(&triangles);
+    assert_eq!(obj.lines().filter(|line| line.starts_with("vt ")).count(), 12);
+    assert!(obj.lines().any(|line| line == "f 1/1/1 2/2/2 3/3/3"));
+}
+
+#[test]
+fn dilation_grows_covered_texels() {
+    // One red texel in the middle of a 5 by 5 texture
+    let mut radiance = vec![0.0; 5 * 5 * 4];
+    radiance[12 * 4..12 * 4 + 4].copy_from_slice(&[1.0, 0.0, 0.0, 1.0]);
+    dilate(&mut radiance, 5, 1);
+
+    let covered: Vec<usize> = (0..25).filter(|&i| radiance[i * 4 + 3] > 0.0).collect();
+    assert_eq!(covered, vec![6, 7, 8, 11, 12, 13, 16, 17, 18]);
+    assert_eq!(&radiance[6 * 4..6 * 4 + 4], &[1.0, 0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn floor_without_texture_coordinates_is_lit_by_the_sky() {
+    let mut scene = Scene::new(2, 1.0, 1.0);
+    scene.active_sky = scene.add_sky(SkySource::Solid([255, 255, 255]));
+    let floor = scene.add_obj_mesh(ObjMesh::parse(Vec3(1.0, 1.0, 1.0), "floor.obj", FLOOR).unwrap());
+    scene.make_scene();
+
+    let mut passes = Vec::new();
+    let bake = LightmapBake { size: 16, samples: 32, dilation: 2 };
+    let lightmap = bake_lightmap(&scene, floor, &bake, |done| passes.push(done)).unwrap();
+    assert_eq!(passes, vec![0.5, 1.0]);
+    assert_eq!(lightmap.radiance.len(), 16 * 16 * 4);
+    assert_eq!(lightmap.atlas.as_ref().map(Vec::len), Some(2));
+
+    // Nothing is in the way, so every covered texel sees the whole white sky. The two triangles
+    // fill half of their cells in the first row of a 2 by 2 grid, and dilation grows them.
+    let covered: Vec<&[f32]> = lightmap.radiance.chunks_exact(4).filter(|texel| texel[3] > 0.0).collect();
+    assert!(covered.len() > 16 * 16 / 4, "{}", covered.len());
+    for texel in covered {
+        assert!((texel[0] - 1.0).abs() < 0.05, "{:?}", texel);
+    }
+}
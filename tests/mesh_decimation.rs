@@ -0,0 +1,103 @@
+// Simplified meshes stand in for scans too heavy to navigate, so they have to stay close to the
+// surface they replace, closed where it was closed and with open borders where they were.
+
+use std::collections::HashMap;
+
+use rust_raytracing_wgpu::raytracer::shapes::procedural::icosphere;
+use rust_raytracing_wgpu::raytracer::{decimate_triangles, MeshLod, ObjMesh, ObjectId, Scene, Triangle, Vec3};
+
+const WHITE: Vec3 = Vec3(1.0, 1.0, 1.0);
+
+// Number of triangles using every edge, with corners matched by position
+fn edge_uses(triangles: &[Triangle]) -> HashMap<[[u32; 3]; 2], usize> {
+    let key = |corner: Vec3| [corner.0.to_bits(), corner.1.to_bits(), corner.2.to_bits()];
+    let mut uses = HashMap::new();
+    for triangle in triangles {
+        for k in 0..3 {
+            let (a, b) = (key(triangle.corners[k]), key(triangle.corners[(k + 1) % 3]));
+            *uses.entry([a.min(b), a.max(b)]).or_insert(0) += 1;
+        }
+    }
+    uses
+}
+
+// Squares of `cells` by `cells` quads covering 0..1 on x and y
+fn grid(cells: usize) -> Vec<Triangle> {
+    let point = |i: usize, j: usize| Vec3(i as f32 / cells as f32, j as f32 / cells as f32, 0.0);
+    (0..cells).flat_map(|j| (0..cells).flat_map(move |i| [
+        Triangle::build_from_corners([point(i, j), point(i + 1, j), point(i + 1, j + 1)], WHITE),
+        Triangle::build_from_corners([point(i, j), point(i + 1, j + 1), point(i, j + 1)], WHITE),
+    ])).collect()
+}
+
+#[test]
+fn spheres_stay_round_and_closed() {
+    let sphere = icosphere(Vec3(0.0, 0.0, 0.0), 1.0, 4, WHITE);
+    let decimated = decimate_triangles(&sphere, 500);
+    assert!(decimated.len() <= 500 && decimated.len() > 400, "{} triangles left", decimated.len());
+    for corner in decimated.iter().flat_map(|triangle| triangle.corners) {
+        assert!((corner.magnitude() - 1.0).abs() < 0.05, "{:?} left the sphere", corner);
+    }
+    assert!(edge_uses(&decimated).values().all(|&uses| uses == 2));
+    // Every face still points out of the sphere
+    assert!(decimated.iter().all(|triangle| {
+        let [a, b, c] = triangle.corners;
+        (b - a).cross(c - a).dot(triangle.centroid) > 0.0
+    }));
+}
+
+#[test]
+fn flat_patches_keep_their_borders() {
+    let decimated = decimate_triangles(&grid(16), 40);
+    assert!(decimated.len() <= 40);
+    let area: f32 = decimated.iter().map(|triangle| {
+        let [a, b, c] = triangle.corners;
+        (b - a).cross(c - a).magnitude() * 0.5
+    }).sum();
+    assert!((area - 1.0).abs() < 1e-3, "area {}", area);
+    assert!(decimated.iter().flat_map(|triangle| triangle.corners).all(|corner| corner.2 == 0.0));
+    for corner in decimated.iter().flat_map(|triangle| triangle.corners) {
+        assert!((0.0..=1.0).contains(&corner.0) && (0.0..=1.0).contains(&corner.1), "{:?} left the square", corner);
+    }
+}
+
+#[test]
+fn small_meshes_are_left_alone() {
+    let triangles = grid(2);
+    assert_eq!(decimate_triangles(&triangles, 100), triangles);
+}
+
+#[test]
+fn levels_of_detail_follow_the_camera() {
+    // An OBJ of the sphere, so the mesh comes with its file's name and normals
+    let sphere = icosphere(Vec3(0.0, 0.0, 0.0), 1.0, 4, WHITE);
+    let mut contents = String::new();
+    for (i, triangle) in sphere.iter().enumerate() {
+        for corner in triangle.corners {
+            contents += &format!("v {} {} {}\n", corner.0, corner.1, corner.2);
+        }
+        contents += &format!("f {} {} {}\n", 3 * i + 1, 3 * i + 2, 3 * i + 3);
+    }
+    let mesh = ObjMesh::parse(WHITE, "sphere.obj", &contents).unwrap();
+
+    let levels = mesh.levels_of_detail(3);
+    assert_eq!(levels.len(), 4);
+    for pair in levels.windows(2) {
+        assert!(pair[1].len() <= pair[0].len() / 2 && pair[1].iter().all(|triangle| triangle.normals.is_some()));
+    }
+    let lod = MeshLod::new(ObjectId(0), levels.clone());
+    assert_eq!(lod.level_for(Vec3(0.0, 0.0, -3.0)), 0);
+    assert_eq!(lod.level_for(Vec3(0.0, 0.0, -20.0)), 1);
+    assert_eq!(lod.level_for(Vec3(0.0, 0.0, -1000.0)), 3);
+
+    let mut scene = Scene::new(4, 32.0, 24.0);
+    let id = scene.add_obj_mesh_lods(mesh, 3);
+    scene.make_scene();
+    scene.camera.origin = Vec3(0.0, 0.0, -1000.0);
+    scene.update();
+    assert_eq!(scene.objects.len(), levels[3].len());
+    assert_eq!(scene.name(id), Some("sphere"));
+    scene.camera.origin = Vec3(0.0, 0.0, -3.0);
+    scene.update();
+    assert_eq!(scene.objects.len(), sphere.len());
+}
@@ -0,0 +1,151 @@
+// OBJ files from other tools vary in which optional parts they write, and broken ones have to fail
+// with a line number instead of panicking halfway through a scene.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_raytracing_wgpu::raytracer::{ObjMesh, Vec3};
+
+const WHITE: Vec3 = Vec3(1.0, 1.0, 1.0);
+const FUZZ_CASES: u64 = 2000;
+
+// A cube written with every corner form, comments, groups and materials in between
+const CUBE: &str = "# exported cube
+mtllib cube.mtl
+o Cube
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0
+vn 0 0 -1
+vn 0 0 1
+g sides
+usemtl grey
+s off
+f 1 2 3 4
+f 5/1 6/2 7/3 8/4
+f 1//1 2//1 6//1 5//1
+f 4/1/2 3/2/2 7/3/2 8/4/2 # top
+f -8 -4 -1 -5
+l 1 2
+f 2/1/1\t3/2/1\t7/3/1  6/4/1
+";
+
+fn parse(contents: &str) -> std::io::Result<ObjMesh> {
+    ObjMesh::parse(WHITE, "test.obj", contents)
+}
+
+fn error(contents: &str) -> String {
+    match parse(contents) {
+        Ok(_) => panic!("{:?} should not parse", contents),
+        Err(error) => error.to_string(),
+    }
+}
+
+#[test]
+fn reads_every_corner_form() {
+    let mesh = parse(CUBE).unwrap();
+    assert_eq!(mesh.name, "test");
+    assert_eq!(mesh.triangles.len(), 12);
+
+    // The negative face is the left side, counting back from the last vertex
+    let left = &mesh.triangles[8];
+    assert!(left.corners.iter().all(|corner| corner.0 == -1.0));
+}
+
+#[test]
+fn windows_line_endings_and_empty_files() {
+    assert_eq!(parse(&CUBE.replace('\n', "\r\n")).unwrap().triangles.len(), 12);
+    assert!(parse("").unwrap().triangles.is_empty());
+    assert!(parse("# nothing here\n\n   \nvp 0.5\n").unwrap().triangles.is_empty());
+}
+
+#[test]
+fn vertex_colors_follow_the_vertices() {
+    let mesh = parse("v 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 0 1 0 0 0 1\nf 1 2 3\n").unwrap();
+    assert_eq!(mesh.triangles[0].vertex_colors, Some([[255, 0, 0], [0, 255, 0], [0, 0, 255]]));
+}
+
+#[test]
+fn malformed_lines_name_their_line() {
+    let vertices = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+    let cases = [
+        ("f 1 2 4\n", "vertex index 4"),
+        ("f 0 1 2\n", "vertex index 0"),
+        ("f -4 1 2\n", "vertex index -4"),
+        ("f 1 2\n", "at least 3 corners"),
+        ("f 1/1 2/1 3/1\n", "texture coordinate index 1"),
+        ("f 1//2 2//2 3//2\n", "normal index 2"),
+        ("f 1/// 2 3\n", "more than three indices"),
+        ("f one 2 3\n", "not a whole number"),
+        ("f 99999999999999999999 2 3\n", "not a whole number"),
+        ("v 1 2\n", "vertex needs 3 numbers"),
+        ("v 1 nan 2\n", "not a finite number"),
+        ("vn 0 inf 0\n", "not a finite number"),
+        ("vt\n", "texture coordinate needs 1 numbers"),
+    ];
+    for (line, message) in cases {
+        let error = error(&format!("{}{}", vertices, line));
+        assert!(error.starts_with("line 4: ") && error.contains(message), "{:?} gave {:?}", line, error);
+    }
+}
+
+// Random edits of a valid file and random text never panic, and whatever parses is usable
+#[test]
+fn fuzzed_files_never_panic() {
+    let alphabet: Vec<char> = "0123456789 -+./#\nvtnfe".chars().collect();
+    for seed in 0..FUZZ_CASES {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut contents: Vec<char> = if seed % 4 == 0 {
+            (0..rng.gen_range(0..200)).map(|_| alphabet[rng.gen_range(0..alphabet.len())]).collect()
+        } else {
+            CUBE.chars().collect()
+        };
+        for _ in 0..rng.gen_range(1..8) {
+            let at = rng.gen_range(0..=contents.len());
+            match rng.gen_range(0..3) {
+                0 if at < contents.len() => { contents.remove(at); },
+                1 => contents.insert(at, alphabet[rng.gen_range(0..alphabet.len())]),
+                _ => contents.insert(at, char::from_u32(rng.gen_range(0..0x3000)).unwrap_or('?')),
+            }
+        }
+
+        let contents: String = contents.into_iter().collect();
+        if let Ok(mesh) = parse(&contents) {
+            for triangle in &mesh.triangles {
+                assert!(triangle.corners.iter().all(|corner| corner.0.is_finite() && corner.1.is_finite() && corner.2.is_finite()));
+            }
+        }
+    }
+}
+
+#[test]
+fn corner_normals_come_from_the_file() {
+    let mesh = parse(CUBE).unwrap();
+    assert_eq!(mesh.triangles[4].normals, Some([Vec3(0.0, 0.0, -1.0); 3]));
+    assert_eq!(mesh.triangles[7].normals, Some([Vec3(0.0, 0.0, 1.0); 3]));
+    // Files with normals are taken as they are, faces without them stay flat
+    assert_eq!(mesh.triangles[0].normals, None);
+}
+
+// Two squares folded 30 degrees along the edge they share
+const FOLD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 0 0.57735\nv 2 1 0.57735\nf 1 2 3 4\nf 2 5 6 3\n";
+
+#[test]
+fn missing_normals_are_smoothed_below_the_angle() {
+    let mesh = parse(FOLD).unwrap();
+    let normals = mesh.triangles[0].normals.unwrap();
+    // The first corner is only on the flat square, the second is on the fold
+    assert_eq!(normals[0], Vec3(0.0, 0.0, 1.0));
+    assert!(normals[1].0 < -0.1 && normals[1].0 > -0.4 && normals[1].2 > 0.9, "{:?}", normals[1]);
+
+    let creased = parse(FOLD).unwrap().recompute_normals(0.1);
+    assert!(creased.triangles[0].normals.unwrap().iter().all(|normal| *normal == Vec3(0.0, 0.0, 1.0)));
+}
@@ -0,0 +1,108 @@
+// Removing objects hides their primitives in place and compacts the object list once enough have
+// piled up. The tree has to keep finding every object that is left without being rebuilt.
+
+use rust_raytracing_wgpu::raytracer::{render_cpu, Material, ObjectId, Scene, Vec3};
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 24;
+const SAMPLES: u32 = 4;
+
+// A grid of matte spheres and a few triangles in front of the camera, with its tree built
+fn test_scene() -> (Scene, Vec<ObjectId>) {
+    let mut scene = Scene::new(4, WIDTH as f32, HEIGHT as f32);
+    let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+    let mut ids = Vec::new();
+    for i in 0..24 {
+        let (x, y) = ((i % 6) as f32 - 2.5, (i / 6) as f32 - 1.5);
+        let id = if i % 5 == 0 {
+            scene.add_triangle([Vec3(x - 0.3, y - 0.3, 0.0), Vec3(x + 0.3, y - 0.3, 0.0), Vec3(x, y + 0.3, 0.0)], Vec3(0.3, 0.8, 0.3))
+        } else {
+            scene.add_sphere(Vec3(x, y, 0.0), Vec3(0.2 + 0.03 * i as f32, 0.5, 0.7), 0.35)
+        };
+        scene.set_material(id, matte);
+        ids.push(id);
+    }
+    scene.make_scene();
+    (scene, ids)
+}
+
+// Every primitive of a remaining object is in some leaf, and no leaf points past the object list
+fn assert_tree_covers(scene: &Scene, ids: &[ObjectId]) {
+    let mut listed = vec![false; scene.objects.len()];
+    for node in &scene.nodes[..scene.nodes_used] {
+        if node.object_count > 0 {
+            let start = node.left_child as usize;
+            for &index in &scene.object_indices[start..start + node.object_count] {
+                assert!(index < scene.objects.len(), "Leaf lists primitive {} of {}", index, scene.objects.len());
+                listed[index] = true;
+            }
+        }
+    }
+    for &id in ids {
+        let primitives = scene.entries[&id].primitives.clone();
+        assert!(primitives.clone().all(|index| listed[index]), "{:?} is missing from the tree", id);
+    }
+}
+
+#[test]
+fn removing_and_restoring_keeps_the_tree() {
+    let (mut scene, ids) = test_scene();
+    let count = scene.objects.len();
+
+    let removed = scene.take(ids[3]).unwrap();
+    assert!(!scene.dirty && scene.moved, "A removal should only need a refit");
+    assert_eq!(scene.objects.len(), count);
+    assert_eq!(scene.free_primitive_count(), 1);
+
+    scene.restore(removed);
+    assert!(!scene.dirty, "Restoring into the freed slot should only need a refit");
+    assert_eq!(scene.objects.len(), count);
+    assert_eq!(scene.free_primitive_count(), 0);
+    assert_tree_covers(&scene, &ids);
+}
+
+#[test]
+fn compaction_moves_objects_into_freed_slots() {
+    let (mut scene, ids) = test_scene();
+    let before: Vec<_> = ids.iter().map(|&id| scene.primitives(id).unwrap().to_vec()).collect();
+
+    // More than a quarter of the primitives, all from the front so the last ones fill their slots
+    let removed: Vec<ObjectId> = ids[..7].to_vec();
+    for &id in &removed {
+        scene.remove(id);
+    }
+    let kept: Vec<ObjectId> = ids.iter().copied().filter(|id| !removed.contains(id)).collect();
+    assert!(!scene.dirty, "Compaction should keep the tree");
+    assert!(scene.objects.len() < ids.len(), "The object list should have shrunk");
+    assert_eq!(scene.objects.len() - scene.free_primitive_count(), kept.len());
+
+    for (id, primitives) in ids.iter().zip(&before) {
+        if kept.contains(id) {
+            assert_eq!(scene.primitives(*id).unwrap(), primitives.as_slice(), "{:?} changed while compacting", id);
+        }
+    }
+    assert_tree_covers(&scene, &kept);
+
+    // Handles outlive the compaction, and one left without primitives has no position
+    assert!(kept.iter().all(|&id| scene.position(id).is_some()));
+    let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+    let empty = scene.add_mesh(Vec::new(), matte);
+    assert_eq!(scene.position(empty), None);
+}
+
+#[test]
+fn compacted_scene_renders_the_same() {
+    let (mut scene, ids) = test_scene();
+    scene.remove(ids[1]);
+    scene.remove(ids[4]);
+    scene.refit_bvh();
+    let hidden = render_cpu(&scene, WIDTH, HEIGHT, SAMPLES);
+
+    scene.compact_objects();
+    assert!(!scene.dirty);
+    scene.refit_bvh();
+    let compacted = render_cpu(&scene, WIDTH, HEIGHT, SAMPLES);
+
+    let largest = hidden.iter().zip(&compacted).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+    assert!(largest < 1e-4, "Compacting changed a pixel by {}", largest);
+}
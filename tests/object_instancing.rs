@@ -0,0 +1,95 @@
+// Duplicating a mesh turns it into an instance of a shared prototype and adds another instance,
+// which renders like a copy of the triangles and can be undone back into them.
+
+use rust_raytracing_wgpu::raytracer::shapes::procedural::teapot;
+use rust_raytracing_wgpu::raytracer::{render_cpu, Material, Object, ObjectId, Scene, SceneBuffers, SkySource, Vec3};
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 16;
+
+// A matte teapot left of the origin under a blue sky, seen from (0, 0, -3)
+fn mesh_scene() -> (Scene, ObjectId) {
+    let mut scene = Scene::new(4, WIDTH as f32, HEIGHT as f32);
+    scene.active_sky = scene.add_sky(SkySource::Solid([150, 180, 230]));
+    let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+    let pot = scene.add_mesh(teapot(Vec3(-1.5, 0.0, 0.0), 1.6, 3, Vec3(0.9, 0.4, 0.2)), matte);
+    (scene, pot)
+}
+
+fn instance_of(scene: &Scene, id: ObjectId) -> usize {
+    match scene.primitives(id).unwrap() {
+        [Object::Instance(instance)] => instance.prototype,
+        primitives => panic!("Object {} is {} primitives, not an instance", id.0, primitives.len()),
+    }
+}
+
+#[test]
+fn duplicated_meshes_share_their_triangles() {
+    let (mut scene, pot) = mesh_scene();
+    let triangles = scene.primitives(pot).unwrap().len();
+    let position = scene.position(pot).unwrap();
+    let copy = scene.duplicate(pot).unwrap();
+
+    assert_eq!(scene.prototypes.len(), 1);
+    assert_eq!(scene.prototypes[0].primitives.len(), triangles);
+    assert_eq!(instance_of(&scene, pot), 0);
+    assert_eq!(instance_of(&scene, copy), 0);
+    assert!((scene.position(copy).unwrap() - position).magnitude() < 1e-5);
+
+    // Moving the copy moves its instance alone, and duplicating again adds no prototype
+    scene.set_position(copy, position + Vec3(2.0, 0.0, 0.0));
+    assert!((scene.position(pot).unwrap() - position).magnitude() < 1e-5);
+    assert!((scene.position(copy).unwrap() - position - Vec3(2.0, 0.0, 0.0)).magnitude() < 1e-5);
+    scene.duplicate(copy).unwrap();
+    assert_eq!(scene.prototypes.len(), 1);
+    assert_eq!(scene.stats().instances, 3);
+    assert_eq!(scene.stats().triangles, triangles);
+
+    scene.make_scene();
+    assert_eq!(SceneBuffers::new(&scene).check(), Ok(()));
+}
+
+#[test]
+fn instances_render_like_copied_triangles() {
+    let (mut copied, _) = mesh_scene();
+    let matte = copied.add_material(Material { diffuse: true, ..Default::default() });
+    let pot = copied.add_mesh(teapot(Vec3(1.5, 0.0, 0.0), 1.6, 3, Vec3(0.9, 0.4, 0.2)), matte);
+    copied.rotate(pot, Vec3(0.0, 1.0, 0.0), 0.7);
+    copied.scale(pot, 0.8);
+    copied.make_scene();
+
+    let (mut instanced, pot) = mesh_scene();
+    let copy = instanced.duplicate(pot).unwrap();
+    instanced.set_position(copy, instanced.position(pot).unwrap() + Vec3(3.0, 0.0, 0.0));
+    instanced.rotate(copy, Vec3(0.0, 1.0, 0.0), 0.7);
+    instanced.scale(copy, 0.8);
+    instanced.make_scene();
+
+    let (mut alone, _) = mesh_scene();
+    alone.make_scene();
+
+    let differing = |a: &[f32], b: &[f32]| a.chunks_exact(4).zip(b.chunks_exact(4))
+        .filter(|(a, b)| a.iter().zip(b.iter()).any(|(a, b)| (a - b).abs() > 1e-3))
+        .count();
+    let [copied, instanced, alone] = [copied, instanced, alone].map(|scene| render_cpu(&scene, WIDTH, HEIGHT, 2));
+    // The copy covers part of the view, the same part either way
+    assert!(differing(&alone, &instanced) > 8, "{} pixels show the copy", differing(&alone, &instanced));
+    assert!(differing(&copied, &instanced) <= 2, "{} pixels differ", differing(&copied, &instanced));
+}
+
+#[test]
+fn undoing_the_instance_brings_the_triangles_back() {
+    let (mut scene, pot) = mesh_scene();
+    let before = scene.primitives(pot).unwrap().to_vec();
+    let edit = scene.make_instance(pot).unwrap();
+    scene.history.push(edit);
+    instance_of(&scene, pot);
+    assert!(scene.make_instance(pot).is_none());
+
+    assert!(scene.undo());
+    let after = scene.primitives(pot).unwrap();
+    assert_eq!(after.len(), before.len());
+    assert!(after.iter().all(|object| matches!(object, Object::Triangle(_))));
+    assert!(scene.redo());
+    instance_of(&scene, pot);
+}
@@ -0,0 +1,46 @@
+// Portals only change which directions matte bounces try, not the light those find, so a room lit
+// through a gap in its ceiling converges to the same image with a portal over the gap or without.
+
+use rust_raytracing_wgpu::raytracer::{render_cpu, Material, Scene, SkySource, Vec3, MAX_PORTALS};
+
+const WIDTH: u32 = 24;
+const HEIGHT: u32 = 16;
+const SAMPLES: u32 = 256;
+
+fn room(portal: bool) -> Scene {
+    let mut scene = Scene::new(8, WIDTH as f32, HEIGHT as f32);
+    scene.active_sky = scene.add_sky(SkySource::Solid([150, 180, 230]));
+    let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+    let floor = scene.add_square(Vec3(0.0, -0.6, 0.0), 6.0, 6.0, Vec3(0.8, 0.8, 0.8), 0.0);
+    scene.set_material(floor, matte);
+    // The ceiling leaves a strip open along the middle
+    for x in [-1.75, 1.75] {
+        let ceiling = scene.add_square(Vec3(x, 0.6, 0.0), 6.0, 3.0, Vec3(0.8, 0.8, 0.8), 0.0);
+        scene.set_material(ceiling, matte);
+    }
+    if portal {
+        scene.add_portal(Vec3(-0.25, 0.6, -3.0), Vec3(0.5, 0.0, 0.0), Vec3(0.0, 0.0, 6.0)).unwrap();
+    }
+    scene.make_scene();
+    scene
+}
+
+fn mean(radiance: &[f32]) -> f32 {
+    radiance.chunks_exact(4).map(|pixel| pixel[0] + pixel[1] + pixel[2]).sum::<f32>() / (radiance.len() / 4 * 3) as f32
+}
+
+#[test]
+fn portals_leave_the_brightness_as_it_was() {
+    let without = mean(&render_cpu(&room(false), WIDTH, HEIGHT, SAMPLES));
+    let with = mean(&render_cpu(&room(true), WIDTH, HEIGHT, SAMPLES));
+    assert!((with - without).abs() < 0.01 * without, "{} with the portal, {} without", with, without);
+}
+
+#[test]
+fn scenes_refuse_portals_past_the_limit() {
+    let mut scene = room(false);
+    for _ in 0..MAX_PORTALS {
+        scene.add_portal(Vec3(0.0, 1.0, 0.0), Vec3(1.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0)).unwrap();
+    }
+    assert!(scene.add_portal(Vec3(0.0, 1.0, 0.0), Vec3(1.0, 0.0, 0.0), Vec3(0.0, 0.0, 1.0)).is_err());
+}
@@ -0,0 +1,19 @@
+// The post effects reach the screen shader through a uniform that zeroes them while disabled, so
+// the view is drawn as traced unless they are asked for.
+
+use rust_raytracing_wgpu::raytracer::{PostProcess, POST_PROCESS_UNIFORM_SIZE};
+
+#[test]
+fn disabled_effects_are_zeroed() {
+    let post = PostProcess::default();
+    let data = post.uniform_data(7);
+    assert_eq!(data.len() * 4, POST_PROCESS_UNIFORM_SIZE as usize);
+    assert_eq!(&data[..3], &[0.0; 3]);
+    assert_eq!(data[3].to_bits(), 7);
+}
+
+#[test]
+fn enabled_effects_are_kept_in_range() {
+    let post = PostProcess { enabled: true, chromatic_aberration: 3.0, vignette: 1.5, grain: -0.1 };
+    assert_eq!(&post.uniform_data(0)[..3], &[3.0, 1.0, 0.0]);
+}
@@ -0,0 +1,57 @@
+// Generated meshes face outwards and keep their shape whatever resolution they are asked for,
+// counts too low to close a shape included.
+
+use rust_raytracing_wgpu::raytracer::shapes::procedural::{capsule, teapot, torus, torus_knot, uv_sphere};
+use rust_raytracing_wgpu::raytracer::{Triangle, Vec3};
+
+const WHITE: Vec3 = Vec3(1.0, 1.0, 1.0);
+
+// Volume enclosed by the triangles around `center`, positive when they face outwards
+fn signed_volume(triangles: &[Triangle], center: Vec3) -> f32 {
+    triangles.iter()
+        .map(|triangle| {
+            let [a, b, c] = triangle.corners.map(|corner| corner - center);
+            a.dot(b.cross(c)) / 6.0
+        })
+        .sum()
+}
+
+#[test]
+fn teapots_stand_upright_at_the_asked_height() {
+    let center = Vec3(1.0, 2.0, 3.0);
+    let resolution = 6;
+    let pot = teapot(center, 2.0, resolution, WHITE);
+    // The lid's knob and the base close to a point, leaving one triangle per cell next to it
+    assert_eq!(pot.len(), 32 * 2 * resolution * resolution - 8 * resolution);
+
+    let corners: Vec<Vec3> = pot.iter().flat_map(|triangle| triangle.corners).collect();
+    let lowest = corners.iter().map(|corner| corner.1).fold(f32::INFINITY, f32::min);
+    let highest = corners.iter().map(|corner| corner.1).fold(f32::NEG_INFINITY, f32::max);
+    assert!((lowest - 1.0).abs() < 1e-4 && (highest - 3.0).abs() < 1e-4, "{} to {}", lowest, highest);
+    // The spout reaches further from the body's axis than the handle
+    let (left, right) = corners.iter().fold((0.0_f32, 0.0_f32), |(left, right), corner| (left.min(corner.0 - 1.0), right.max(corner.0 - 1.0)));
+    assert!(right > -left && -left > 1.0, "{} to {}", left, right);
+
+    // Open at the spout and between the lid and the rim, but facing outwards everywhere
+    assert!(signed_volume(&pot, center) > 0.0);
+    assert_eq!(teapot(center, 2.0, 0, WHITE), teapot(center, 2.0, 1, WHITE));
+}
+
+#[test]
+fn too_few_segments_still_close_the_shape() {
+    let origin = Vec3(0.0, 0.0, 0.0);
+    let shapes = [
+        uv_sphere(origin, 1.0, 0, 1, WHITE),
+        torus(origin, 2.0, 0.5, 0, 1, WHITE),
+        capsule(origin, 0.5, 1.0, 1, 0, WHITE),
+    ];
+    for triangles in &shapes {
+        assert!(!triangles.is_empty());
+        assert!(signed_volume(triangles, origin) > 0.0);
+    }
+
+    // Raised to three segments and two rings, an octahedron
+    assert_eq!(uv_sphere(origin, 1.0, 0, 0, WHITE), uv_sphere(origin, 1.0, 3, 2, WHITE));
+    assert_eq!(torus(origin, 2.0, 0.5, 1, 2, WHITE), torus(origin, 2.0, 0.5, 3, 3, WHITE));
+    assert_eq!(torus_knot(origin, 2.0, 0.3, 2, 3, 2, 0, WHITE), torus_knot(origin, 2.0, 0.3, 2, 3, 3, 3, WHITE));
+}
@@ -0,0 +1,65 @@
+// The raster pass draws triangles through the camera's clip matrix onto the kernel's pixels, and
+// the kernel skips walking the BVH for camera rays when nothing but opaque triangles can be hit.
+
+use rust_raytracing_wgpu::raytracer::{raster_uniform_data, Camera, ObjMesh, Projection, Scene, Vec3, RASTER_NEAR};
+
+// A 4 by 4 floor facing up
+const FLOOR: &str = "
+v -2 0 2
+v 2 0 2
+v 2 0 -2
+v -2 0 -2
+f 1 2 3 4
+";
+
+// Clip coordinates of a point, the matrix going column by column
+fn clip(data: &[f32], point: Vec3) -> [f32; 4] {
+    let mut clip = [0.0; 4];
+    for (row, value) in clip.iter_mut().enumerate() {
+        *value = data[row] * point.0 + data[4 + row] * point.1 + data[8 + row] * point.2 + data[12 + row];
+    }
+    clip
+}
+
+#[test]
+fn points_land_on_the_pixel_their_camera_ray_goes_through() {
+    let mut camera = Camera::new(Vec3(1.0, 2.0, -3.0), Vec3(0.0, 0.5, 0.0), Vec3(0.0, 1.0, 0.0), 60.0, 1.5);
+    camera.render_mask = 0b101;
+    let data = raster_uniform_data(&camera).unwrap();
+    assert_eq!(data[16].to_bits(), 0b101);
+
+    // Three image plane distances along the ray through uv (0.25, 0.75), whose pixel is left of
+    // the middle and, counting rows down from the top of the target, below it
+    let on_plane = camera.lower_left_corner + camera.horizontal * 0.25 + camera.vertical * 0.75;
+    let point = camera.origin + (on_plane - camera.origin) * 3.0;
+    let [x, y, z, w] = clip(&data, point);
+    assert!((w - 3.0).abs() < 1e-4, "{}", w);
+    assert!((x / w + 0.5).abs() < 1e-4, "{}", x / w);
+    assert!((y / w + 0.5).abs() < 1e-4, "{}", y / w);
+    assert!((z / w - RASTER_NEAR / 3.0).abs() < 1e-9, "{}", z / w);
+
+    // The depth is reversed, nearer points are greater
+    let [_, _, z_far, w_far] = clip(&data, camera.origin + (on_plane - camera.origin) * 6.0);
+    assert!(z_far / w_far < z / w);
+
+    camera.set_projection(Projection::Orthographic { height: 4.0 });
+    assert_eq!(raster_uniform_data(&camera), None);
+}
+
+#[test]
+fn tree_is_skipped_only_when_everything_visible_is_an_opaque_triangle() {
+    let mut scene = Scene::new(2, 1.0, 1.0);
+    scene.add_obj_mesh(ObjMesh::parse(Vec3(1.0, 1.0, 1.0), "floor.obj", FLOOR).unwrap());
+    scene.make_scene();
+    // The mode sits right before the portals
+    let mode = |scene: &Scene| bytemuck::cast_slice::<u8, f32>(&scene.flatten_scene_data(0))[35];
+    assert_eq!(mode(&scene), 0.0);
+
+    scene.raster_primary = true;
+    assert_eq!(mode(&scene), 2.0);
+
+    // A sphere can be in front of the rasterized triangles, so camera rays walk the tree up to them
+    scene.add_sphere(Vec3(0.0, 1.0, 0.0), Vec3(1.0, 1.0, 1.0), 0.5);
+    scene.make_scene();
+    assert_eq!(mode(&scene), 1.0);
+}
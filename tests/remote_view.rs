@@ -0,0 +1,90 @@
+// The remote view is plain HTTP a browser talks to, so these requests are what the viewer page
+// sends: the page itself, the frame stream and the input posts, and requests too large to read.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use rust_raytracing_wgpu::raytracer::{encode_jpeg, parse_remote_input, RemoteInput, RemoteView};
+use winit::keyboard::KeyCode;
+
+fn start_view() -> (RemoteView, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    (RemoteView::listen(listener), address)
+}
+
+fn request(address: &str, request: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn input_lines_name_browser_keys() {
+    assert_eq!(parse_remote_input("down KeyW"), Some(RemoteInput::KeyDown(KeyCode::KeyW)));
+    assert_eq!(parse_remote_input("up ArrowLeft"), Some(RemoteInput::KeyUp(KeyCode::ArrowLeft)));
+    assert_eq!(parse_remote_input("look -3 2.5"), Some(RemoteInput::Look { dx: -3.0, dy: 2.5 }));
+    assert_eq!(parse_remote_input("release"), Some(RemoteInput::Release));
+    for line in ["down F13", "down", "look 1", "look NaN 0", "jump"] {
+        assert_eq!(parse_remote_input(line), None, "{:?} should not parse", line);
+    }
+}
+
+#[test]
+fn page_and_input_are_served() {
+    let (view, address) = start_view();
+    let page = request(&address, "GET / HTTP/1.1\r\nHost: test\r\n\r\n");
+    assert!(page.starts_with("HTTP/1.1 200 OK") && page.contains("src=\"/stream\""));
+    assert!(request(&address, "GET /missing HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+
+    let body = "down KeyW\nlook 4 0\ndown NotAKey\n";
+    let response = request(&address, &format!("POST /input HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body));
+    assert!(response.starts_with("HTTP/1.1 204"));
+    assert_eq!(view.take_input(), vec![RemoteInput::KeyDown(KeyCode::KeyW), RemoteInput::Look { dx: 4.0, dy: 0.0 }]);
+    assert!(view.take_input().is_empty());
+}
+
+#[test]
+fn oversized_requests_are_refused() {
+    let (_view, address) = start_view();
+    // A line that never ends, cut off at the header limit
+    let endless = format!("GET /{}", "a".repeat(8192 - 5));
+    assert!(request(&address, &endless).starts_with("HTTP/1.1 431"));
+    let many = format!("GET / HTTP/1.1\r\n{}", "X: y\r\n".repeat(65));
+    assert!(request(&address, &many).starts_with("HTTP/1.1 431"));
+    let enough = format!("GET / HTTP/1.1\r\n{}\r\n", "X: y\r\n".repeat(64));
+    assert!(request(&address, &enough).starts_with("HTTP/1.1 200 OK"));
+}
+
+#[test]
+fn streams_get_the_published_frames() {
+    let (view, address) = start_view();
+    let mut stream = TcpStream::connect(&address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    stream.write_all(b"GET /stream HTTP/1.1\r\n\r\n").unwrap();
+
+    // The stream counts as a viewer once its request is read
+    while view.viewer_count() == 0 {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let jpeg = encode_jpeg(2, 2, &[0.0, 0.5, 1.0, 1.0].repeat(4));
+    view.publish(jpeg.clone());
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    let mut length = None;
+    while !(length.is_some() && line == "\r\n") {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        if let Some(value) = line.strip_prefix("Content-Length: ") {
+            length = Some(value.trim().parse::<usize>().unwrap());
+        }
+    }
+    let mut frame = vec![0; length.unwrap()];
+    reader.read_exact(&mut frame).unwrap();
+    assert_eq!(frame, jpeg);
+    assert!(frame.starts_with(&[0xff, 0xd8]), "Frames should be JPEGs");
+}
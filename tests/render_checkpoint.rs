@@ -0,0 +1,46 @@
+// Checkpoints keep an offline render's samples so far, and a render resumed from them traces the
+// same image as one that was never stopped.
+
+use rust_raytracing_wgpu::raytracer::{render_cpu, render_fingerprint, resume_cpu, Checkpoint, Scene, Vec3};
+
+fn scene() -> Scene {
+    let mut scene = Scene::new(4, 8.0, 8.0);
+    scene.add_sphere(Vec3(0.0, 0.0, 0.0), Vec3(0.8, 0.4, 0.2), 1.0);
+    scene.add_sphere(Vec3(0.0, -101.0, 0.0), Vec3(0.5, 0.5, 0.5), 100.0);
+    scene.make_scene();
+    scene
+}
+
+#[test]
+fn resumed_render_matches_an_uninterrupted_one() {
+    let scene = scene();
+    let first = resume_cpu(&scene, 8, 8, 0..3, None);
+    let resumed = resume_cpu(&scene, 8, 8, 3..6, Some(&first));
+    assert_eq!(resumed, render_cpu(&scene, 8, 8, 6));
+}
+
+#[test]
+fn checkpoints_are_read_back_as_written() {
+    let mut scene = scene();
+    let path = std::env::temp_dir().join(format!("checkpoint-{}.ckpt", std::process::id()));
+    let path = path.to_str().unwrap();
+    let fingerprint = render_fingerprint(&scene);
+    let checkpoint = Checkpoint { scene: fingerprint, width: 2, height: 1, samples: 7, accumulation: vec![0.5, 1.0, 2.0, 1.0, 0.0, 0.25, 4.0, 1.0] };
+    checkpoint.save(path).unwrap();
+    let loaded = Checkpoint::load(path).unwrap();
+    assert_eq!(loaded, checkpoint);
+    assert!(loaded.check(fingerprint, 2, 1).is_ok());
+    assert!(loaded.check(fingerprint, 1, 2).is_err());
+
+    // Moving the camera makes it a different render
+    scene.camera.origin = Vec3(0.0, 0.0, -5.0);
+    assert!(loaded.check(render_fingerprint(&scene), 2, 1).is_err());
+
+    // Files cut short or of something else aren't read as checkpoints
+    let bytes = std::fs::read(path).unwrap();
+    std::fs::write(path, &bytes[..bytes.len() - 4]).unwrap();
+    assert!(Checkpoint::load(path).is_err());
+    std::fs::write(path, b"not a checkpoint at all").unwrap();
+    assert!(Checkpoint::load(path).is_err());
+    std::fs::remove_file(path).unwrap();
+}
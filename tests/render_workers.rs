@@ -0,0 +1,98 @@
+// Distributed renders split the image into tiles traced by workers over TCP. The tiles draw the
+// random numbers of the whole image, so merging them gives the image traced in one piece.
+
+use std::net::TcpListener;
+
+use rust_raytracing_wgpu::raytracer::{render_cpu, render_distributed, serve_worker, Material, Scene, SceneBuffers, SkySource, Vec3, NODE_FLOATS};
+
+const WIDTH: u32 = 40;
+// Not a multiple of TILE_ROWS, so the last tile is a short one
+const HEIGHT: u32 = 70;
+const SAMPLES: u32 = 3;
+
+fn test_scene() -> Scene {
+    let mut scene = Scene::new(4, WIDTH as f32, HEIGHT as f32);
+    scene.active_sky = scene.add_sky(SkySource::Solid([150, 180, 230]));
+    let matte = scene.add_material(Material { diffuse: true, ..Default::default() });
+    for i in 0..6 {
+        let id = scene.add_sphere(Vec3(i as f32 - 2.5, 0.0, -2.0), Vec3(0.2 * i as f32, 0.5, 0.8), 0.45);
+        scene.set_material(id, matte);
+    }
+    scene.add_square(Vec3(0.0, -0.5, -2.0), 10.0, 10.0, Vec3(0.8, 0.8, 0.8), 0.0);
+    // Workers are sent the label atlas with the other textures
+    scene.add_label(Vec3(0.0, 0.6, -2.0), "tiles", 0.4, Vec3(1.0, 0.2, 0.2));
+    scene.make_scene();
+    scene
+}
+
+fn start_worker() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || serve_worker(listener));
+    address
+}
+
+// Camera rays of a tile are built from its narrowed camera, which can round differently from the
+// whole image's, so a rare path may go another way
+fn assert_matches(expected: &[f32], actual: &[f32]) {
+    assert_eq!(expected.len(), actual.len());
+    let mean = expected.iter().zip(actual).map(|(a, b)| (a - b).abs()).sum::<f32>() / expected.len() as f32;
+    assert!(mean < 1e-5, "Tiles differ from the whole image by {} on average", mean);
+}
+
+#[test]
+fn workers_render_the_image_traced_whole() {
+    let mut scene = test_scene();
+    let expected = render_cpu(&scene, WIDTH, HEIGHT, SAMPLES);
+    let workers = [start_worker(), start_worker()];
+    let radiance = render_distributed(&mut scene, &workers, WIDTH, HEIGHT, SAMPLES);
+    assert_matches(&expected, &radiance);
+}
+
+#[test]
+fn unreachable_workers_leave_their_tiles_to_the_others() {
+    let mut scene = test_scene();
+    let expected = render_cpu(&scene, WIDTH, HEIGHT, SAMPLES);
+
+    // Nothing listens on a port that was just freed
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let radiance = render_distributed(&mut scene, &[closed.clone(), start_worker()], WIDTH, HEIGHT, SAMPLES);
+    assert_matches(&expected, &radiance);
+
+    // With no worker at all the tiles are traced locally
+    let radiance = render_distributed(&mut scene, &[closed], WIDTH, HEIGHT, SAMPLES);
+    assert_matches(&expected, &radiance);
+}
+
+// Sets one float of a flattened buffer
+fn set_float(buffer: &mut [u8], index: usize, value: f32) {
+    buffer[index * 4..index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[test]
+fn workers_refuse_scenes_that_index_past_their_buffers() {
+    let buffers = SceneBuffers::new(&test_scene());
+    assert_eq!(buffers.check(), Ok(()));
+    let broken = |change: &dyn Fn(&mut SceneBuffers)| {
+        let mut broken = buffers.clone();
+        change(&mut broken);
+        broken.check()
+    };
+
+    let nodes = buffers.nodes.len() / 4 / NODE_FLOATS;
+    // The root's children, then a leaf's objects
+    assert!(broken(&|b| set_float(&mut b.nodes, 3, nodes as f32)).is_err());
+    assert!(broken(&|b| set_float(&mut b.nodes, 3, 0.0)).is_err());
+    let leaf = (0..nodes).find(|&i| f32::from_le_bytes(buffers.nodes[(i * NODE_FLOATS + 7) * 4..][..4].try_into().unwrap()) > 0.0).unwrap();
+    assert!(broken(&|b| set_float(&mut b.nodes, leaf * NODE_FLOATS + 7, 1000.0)).is_err());
+    assert!(broken(&|b| set_float(&mut b.nodes, leaf * NODE_FLOATS + 8, nodes as f32)).is_err());
+    // Links that stay in range but lead back up the tree, which would trace forever
+    assert!(broken(&|b| set_float(&mut b.nodes, leaf * NODE_FLOATS + 8, 0.0)).is_err());
+    assert!(broken(&|b| set_float(&mut b.nodes, 8, 0.0)).is_err());
+    // Past the six balls, the ground and the label
+    assert!(broken(&|b| set_float(&mut b.object_indices, 0, 8.0)).is_err());
+    assert!(broken(&|b| b.nodes.clear()).is_err());
+    assert!(broken(&|b| b.materials.clear()).is_err());
+    // The label's atlas
+    assert!(broken(&|b| b.textures.clear()).is_err());
+}
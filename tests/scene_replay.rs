@@ -0,0 +1,81 @@
+// Replays put the camera and the objects back where they were on each recorded frame, objects by
+// the transform their edits added up to.
+
+use rust_raytracing_wgpu::raytracer::{Camera, ObjectId, Replay, ReplayRecorder, Scene, Transform, Vec3, CAMERA_FLOATS};
+
+// A ball and the square under it
+fn scene() -> (Scene, ObjectId, ObjectId) {
+    let mut scene = Scene::new(4, 8.0, 8.0);
+    let ball = scene.add_sphere(Vec3(0.0, 0.0, 0.0), Vec3(0.8, 0.4, 0.2), 1.0);
+    let square = scene.add_square(Vec3(0.0, -1.0, 0.0), 4.0, 4.0, Vec3(0.5, 0.5, 0.5), 0.0);
+    (scene, ball, square)
+}
+
+fn close(a: Vec3, b: Vec3) -> bool {
+    let d = a - b;
+    d.dot(d) < 1e-8
+}
+
+#[test]
+fn edits_add_up_to_the_transform_set_again_elsewhere() {
+    let (mut edited, _, square) = scene();
+    edited.rotate(square, Vec3(0.0, 1.0, 0.0), 0.7);
+    edited.scale(square, 2.0);
+    edited.set_position(square, Vec3(1.0, 2.0, 3.0));
+    let transform = edited.entries[&square].transform;
+
+    let (mut replayed, ..) = scene();
+    let before = replayed.position(square).unwrap();
+    assert!(close(transform.apply(before), Vec3(1.0, 2.0, 3.0)));
+    replayed.set_transform(square, transform);
+    assert!(close(replayed.position(square).unwrap(), Vec3(1.0, 2.0, 3.0)));
+    assert_eq!(replayed.entries[&square].transform, transform);
+    assert!(replayed.moved);
+
+    // Going back to no transform puts it where it was added
+    replayed.set_transform(square, Transform::default());
+    assert!(close(replayed.position(square).unwrap(), before));
+    assert!(close(transform.then(transform.inverse()).apply(Vec3(4.0, -2.0, 1.0)), Vec3(4.0, -2.0, 1.0)));
+
+    let mut camera = Camera::new(Vec3(1.0, 2.0, -3.0), Vec3(0.0, 0.5, 0.0), Vec3(0.0, 1.0, 0.0), 60.0, 1.5);
+    camera.render_mask = 0b110;
+    let floats: [f32; CAMERA_FLOATS] = camera.to_floats();
+    assert_eq!(Camera::from_floats(floats), Some(camera));
+}
+
+#[test]
+fn replays_play_back_the_recorded_frames() {
+    let path = std::env::temp_dir().join(format!("replay-{}.bin", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let (mut scene, ball, _) = scene();
+    let mut recorder = ReplayRecorder::create(path, &scene).unwrap();
+    recorder.record(&scene).unwrap();
+    scene.camera.move_forwards(0.5);
+    scene.set_position(ball, Vec3(0.0, 1.0, 0.0));
+    recorder.record(&scene).unwrap();
+    recorder.record(&scene).unwrap();
+    drop(recorder);
+
+    let mut replay = Replay::load(path).unwrap();
+    assert_eq!(replay.frames.len(), 3);
+    // Only the frame the ball moved on has its transform
+    assert_eq!(replay.frames.iter().map(|frame| frame.transforms.len()).collect::<Vec<_>>(), vec![0, 1, 0]);
+
+    let (mut replayed, ..) = self::scene();
+    assert!(replay.check(&replayed).is_ok());
+    assert_eq!(replay.step(&mut replayed), Some(false));
+    assert_eq!(replay.step(&mut replayed), Some(true));
+    assert_eq!(replayed.camera, scene.camera);
+    assert!(close(replayed.position(ball).unwrap(), Vec3(0.0, 1.0, 0.0)));
+    assert_eq!(replay.step(&mut replayed), Some(false));
+    assert_eq!(replay.step(&mut replayed), None);
+
+    // A recording cut off partway through a frame keeps the frames before it
+    let bytes = std::fs::read(path).unwrap();
+    std::fs::write(path, &bytes[..bytes.len() - 10]).unwrap();
+    assert_eq!(Replay::load(path).unwrap().frames.len(), 2);
+    std::fs::write(path, b"not a replay at all").unwrap();
+    assert!(Replay::load(path).is_err());
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,45 @@
+// Scene scripts build the scene once from their top level, and on_update only runs the function
+// each frame. Scene calls that fail end the script with their error.
+#![cfg(feature = "scripting")]
+
+use rust_raytracing_wgpu::raytracer::{Scene, SceneScript, Vec3};
+
+const SCRIPT: &str = "
+let ball = scene().add_sphere(vec3(0.0, 0.0, -1.0), vec3(1.0, 0.0, 0.0), 0.5);
+scene().set_name(ball, \"ball\");
+
+fn on_update(dt) {
+    let ball = scene().find(\"ball\");
+    scene().set_position(ball, scene().position(ball) + vec3(0.0, dt, 0.0));
+}
+";
+
+#[test]
+fn updates_move_the_scene_without_building_it_again() {
+    let path = std::env::temp_dir().join(format!("scene-{}.rhai", std::process::id()));
+    std::fs::write(&path, SCRIPT).unwrap();
+    let mut script = SceneScript::load(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut scene = Scene::new(4, 1.0, 1.0);
+    script.run_setup(&mut scene).unwrap();
+    assert_eq!(scene.entries.len(), 1);
+    for _ in 0..3 {
+        script.on_update(&mut scene, 0.5).unwrap();
+    }
+    assert_eq!(scene.entries.len(), 1);
+    let ball = *scene.entries.keys().next().unwrap();
+    let position = scene.position(ball).unwrap();
+    assert!((position - Vec3(0.0, 1.5, -1.0)).dot(position - Vec3(0.0, 1.5, -1.0)) < 1e-8, "{:?}", position);
+}
+
+#[test]
+fn scripts_get_an_error_past_the_portal_limit() {
+    let path = std::env::temp_dir().join(format!("portals-{}.rhai", std::process::id()));
+    std::fs::write(&path, "for i in 0..5 { scene().add_portal(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0)); }").unwrap();
+    let mut script = SceneScript::load(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let error = script.run_setup(&mut Scene::new(4, 1.0, 1.0)).unwrap_err();
+    assert!(error.to_string().contains("at most 4 portals"), "{}", error);
+}